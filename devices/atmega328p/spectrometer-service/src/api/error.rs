@@ -0,0 +1,156 @@
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+use crate::api::models::ErrorResponse;
+use crate::error::SpectrometerError;
+
+/// Error returned by API handlers, rendered as `ErrorResponse` JSON
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// 400 - the request itself is invalid
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    /// 404 - the resource or feature does not exist
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    /// 409 - the request conflicts with the current service state
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, message)
+    }
+
+    /// 422 - the request is well-formed but names an unknown value
+    pub fn unprocessable(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, message)
+    }
+
+    /// 429 - the client sends requests faster than accepted
+    pub fn too_many_requests(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::TOO_MANY_REQUESTS, message)
+    }
+
+    /// 500 - the service failed
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    #[cfg(test)]
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    #[cfg(test)]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<SpectrometerError> for ApiError {
+    fn from(error: SpectrometerError) -> Self {
+        let status = match &error {
+            SpectrometerError::Validation(_)
+            | SpectrometerError::Config(_)
+            | SpectrometerError::Protocol(_) => StatusCode::BAD_REQUEST,
+            SpectrometerError::NotRegistered | SpectrometerError::InvalidTransition { .. } => {
+                StatusCode::CONFLICT
+            }
+            SpectrometerError::SerialPort(_)
+            | SpectrometerError::DataSource(_)
+            | SpectrometerError::ChannelSend
+            | SpectrometerError::HttpClient(_)
+            | SpectrometerError::MonitoringStatus(_) => StatusCode::SERVICE_UNAVAILABLE,
+            SpectrometerError::Io(_) | SpectrometerError::Storage(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            tracing::error!("{}", self.message);
+        }
+        (
+            self.status,
+            Json(ErrorResponse {
+                error: self.message,
+            }),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ProtocolError;
+
+    #[test]
+    fn test_status_from_spectrometer_error() {
+        let cases = [
+            (
+                SpectrometerError::Validation("bad".into()),
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                SpectrometerError::Protocol(ProtocolError::InvalidGain(3)),
+                StatusCode::BAD_REQUEST,
+            ),
+            (SpectrometerError::NotRegistered, StatusCode::CONFLICT),
+            (
+                SpectrometerError::InvalidTransition {
+                    from: "idle".into(),
+                    to: "paused".into(),
+                },
+                StatusCode::CONFLICT,
+            ),
+            (
+                SpectrometerError::DataSource("gone".into()),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                SpectrometerError::ChannelSend,
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+        ];
+        for (error, status) in cases {
+            let message = error.to_string();
+            let api_error = ApiError::from(error);
+            assert_eq!(api_error.status(), status);
+            assert_eq!(api_error.message(), message);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_into_response_body() {
+        let response = ApiError::conflict("Deposition already running").into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"error": "Deposition already running"})
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{Request, State};
+use axum::http::{HeaderValue, Method, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::api::ApiError;
+use crate::service::calibration::SharedConfig;
+
+/// Window `control.max_requests_per_sec` is counted over
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Rate limit and serialization of the mutating control endpoints of one
+/// device, so aggressive client retries cannot flip the chamber back and
+/// forth or interleave commands
+pub struct ControlGuard {
+    /// Read on each request for `control.max_requests_per_sec` (reloadable)
+    config: SharedConfig,
+    /// When the control requests of the last `RATE_WINDOW` were accepted
+    accepted: Mutex<VecDeque<Instant>>,
+    /// Held while a control request is handled
+    in_flight: tokio::sync::Mutex<()>,
+}
+
+impl ControlGuard {
+    pub fn new(config: SharedConfig) -> Self {
+        Self {
+            config,
+            accepted: Mutex::new(VecDeque::new()),
+            in_flight: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Accept a request at `now` if fewer than `limit` were accepted in the
+    /// window before it; otherwise the time until one leaves the window.
+    /// A `limit` of 0 accepts every request.
+    fn admit(&self, limit: u32, now: Instant) -> Result<(), Duration> {
+        if limit == 0 {
+            return Ok(());
+        }
+        let mut accepted = self.accepted.lock().unwrap();
+        while accepted
+            .front()
+            .is_some_and(|&at| now.duration_since(at) >= RATE_WINDOW)
+        {
+            accepted.pop_front();
+        }
+        if accepted.len() >= limit as usize {
+            let oldest = accepted[0];
+            return Err(RATE_WINDOW - now.duration_since(oldest));
+        }
+        accepted.push_back(now);
+        Ok(())
+    }
+}
+
+/// Middleware of the control routes: reads pass, changes beyond the rate
+/// limit get 429 with `Retry-After`, and a change while another is still
+/// being handled gets 409
+pub async fn guard_control(
+    State(guard): State<Arc<ControlGuard>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+
+    let limit = guard
+        .config
+        .read()
+        .await
+        .config
+        .control
+        .max_requests_per_sec;
+    if let Err(retry_after) = guard.admit(limit, Instant::now()) {
+        tracing::warn!(
+            "Rejected {} {}: over {limit} control requests/s",
+            request.method(),
+            request.uri().path()
+        );
+        let mut response =
+            ApiError::too_many_requests(format!("More than {limit} control requests per second"))
+                .into_response();
+        let seconds = retry_after.as_secs_f64().ceil().max(1.0);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(seconds as u64));
+        return response;
+    }
+
+    let Ok(_handling) = guard.in_flight.try_lock() else {
+        tracing::warn!(
+            "Rejected {} {}: another control request is in progress",
+            request.method(),
+            request.uri().path()
+        );
+        return ApiError::conflict("Another control request is in progress").into_response();
+    };
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::middleware;
+    use axum::routing::post;
+    use tower::util::ServiceExt;
+
+    use super::*;
+    use crate::service::calibration::create_shared_config;
+
+    fn guard() -> (Arc<ControlGuard>, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = create_shared_config(dir.path().join("cfg.toml"));
+        (Arc::new(ControlGuard::new(config)), dir)
+    }
+
+    #[test]
+    fn test_admit_within_window() {
+        let (guard, _dir) = guard();
+        let t0 = Instant::now();
+        for i in 0..3 {
+            assert!(guard.admit(3, t0 + Duration::from_millis(i * 100)).is_ok());
+        }
+        let retry_after = guard.admit(3, t0 + Duration::from_millis(400)).unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(600));
+        // The first request left the window
+        assert!(guard.admit(3, t0 + Duration::from_millis(1000)).is_ok());
+        // No limit
+        assert!((0..100).all(|_| guard.admit(0, t0).is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_over_limit_and_concurrent() {
+        let (guard, _dir) = guard();
+        guard
+            .config
+            .write()
+            .await
+            .config
+            .control
+            .max_requests_per_sec = 2;
+        let app = Router::new()
+            .route("/start", post(|| async {}).get(|| async {}))
+            .route_layer(middleware::from_fn_with_state(guard.clone(), guard_control));
+        let send = |method: Method| {
+            app.clone().oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri("/start")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        // Another request is being handled
+        let handling = guard.in_flight.lock().await;
+        assert_eq!(
+            send(Method::POST).await.unwrap().status(),
+            StatusCode::CONFLICT
+        );
+        drop(handling);
+
+        assert_eq!(send(Method::POST).await.unwrap().status(), StatusCode::OK);
+        let response = send(Method::POST).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        // Reads are not limited
+        assert_eq!(send(Method::GET).await.unwrap().status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,182 @@
+use axum::Json;
+use axum::extract::{Path, Query, State};
+
+use crate::api::ApiError;
+use crate::api::models::*;
+use crate::service::state::AppState;
+use crate::storage::{MeasurementFilter, SharedArchive};
+
+/// GET /archive/runs - Summaries of archived deposition runs
+pub async fn get_runs(
+    State(state): State<AppState>,
+) -> Result<Json<ArchiveRunsResponse>, ApiError> {
+    let archive = require_archive(&state)?;
+    let runs = archive.blocking(|archive| archive.runs()).await?;
+
+    Ok(Json(ArchiveRunsResponse { runs }))
+}
+
+/// GET /archive/runs/{run_id}/layers - Layer boundaries of an archived run
+pub async fn get_run_layers(
+    State(state): State<AppState>,
+    Path(run_id): Path<String>,
+) -> Result<Json<ArchiveLayersResponse>, ApiError> {
+    let archive = require_archive(&state)?;
+    let id = run_id.clone();
+    let layers = archive.blocking(move |archive| archive.layers(&id)).await?;
+
+    if layers.is_empty() {
+        return Err(ApiError::not_found(format!(
+            "Run '{run_id}' not found in archive"
+        )));
+    }
+
+    Ok(Json(ArchiveLayersResponse { run_id, layers }))
+}
+
+/// GET /archive/measurements?from=&to=&run_id=&limit= - Archived measurements
+/// matching all given filters, oldest first
+pub async fn get_measurements(
+    State(state): State<AppState>,
+    Query(query): Query<ArchiveMeasurementsQuery>,
+) -> Result<Json<ArchiveMeasurementsResponse>, ApiError> {
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return Err(ApiError::bad_request("'from' must not be later than 'to'"));
+    }
+
+    let archive = require_archive(&state)?;
+    let filter = MeasurementFilter {
+        from: query.from,
+        to: query.to,
+        run_id: query.run_id,
+        limit: query.limit,
+    };
+    let measurements = archive
+        .blocking(move |archive| archive.query(&filter))
+        .await?;
+
+    Ok(Json(ArchiveMeasurementsResponse {
+        count: measurements.len(),
+        measurements,
+    }))
+}
+
+fn require_archive(state: &AppState) -> Result<&SharedArchive, ApiError> {
+    state.archive.as_ref().ok_or_else(|| {
+        ApiError::not_found("Measurement archive not enabled (start with --archive <path>)")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::http::StatusCode;
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::protocol::{MeasurementCycle, ProcessedMeasurement, SeriesData};
+    use crate::storage::{MeasurementArchive, RunTags};
+
+    fn test_state(with_archive: bool) -> (AppState, tempfile::TempDir) {
+        let (mut state, dir) = AppState::for_test();
+        state.archive = with_archive.then(|| {
+            Arc::new(MeasurementArchive::open(&dir.path().join("archive.db"), false).unwrap())
+        });
+        (state, dir)
+    }
+
+    fn archive_run(state: &AppState, run_id: &str, count: i64) {
+        let archive = state.archive.as_ref().unwrap();
+        let t0 = Utc::now();
+        for i in 0..count {
+            let timestamp = t0 + Duration::seconds(i);
+            let cycle = MeasurementCycle::with_timestamp(
+                timestamp,
+                SeriesData::new(vec![100]),
+                SeriesData::new(vec![1000]),
+                SeriesData::new(vec![550]),
+            );
+            let measurement = ProcessedMeasurement::new(timestamp, 100.0, 1000.0, 550.0, 50.0);
+            let tags = RunTags {
+                run_id: Some(run_id.to_string()),
+                material: "H".to_string(),
+                layer: 1,
+            };
+            archive.append(&measurement, &tags, &cycle).unwrap();
+        }
+    }
+
+    fn query(run_id: Option<&str>) -> Query<ArchiveMeasurementsQuery> {
+        Query(ArchiveMeasurementsQuery {
+            from: None,
+            to: None,
+            run_id: run_id.map(str::to_string),
+            limit: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_archive_disabled_is_not_found() {
+        let (state, _dir) = test_state(false);
+
+        let error = get_runs(State(state.clone())).await.unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+
+        let error = get_measurements(State(state), query(None))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_runs_and_measurements() {
+        let (state, _dir) = test_state(true);
+        archive_run(&state, "run-a", 2);
+        archive_run(&state, "run-b", 3);
+
+        let runs = get_runs(State(state.clone())).await.unwrap();
+        assert_eq!(runs.runs.len(), 2);
+
+        let layers = get_run_layers(State(state.clone()), Path("run-a".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(layers.layers[0].measurements, 2);
+        let error = get_run_layers(State(state.clone()), Path("run-x".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::NOT_FOUND);
+
+        let response = get_measurements(State(state), query(Some("run-b")))
+            .await
+            .unwrap();
+        assert_eq!(response.count, 3);
+        assert!(
+            response
+                .measurements
+                .iter()
+                .all(|m| m.run_id.as_deref() == Some("run-b"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_measurements_rejects_inverted_range() {
+        let (state, _dir) = test_state(true);
+        let now = Utc::now();
+
+        let error = get_measurements(
+            State(state),
+            Query(ArchiveMeasurementsQuery {
+                from: Some(now),
+                to: Some(now - Duration::seconds(1)),
+                run_id: None,
+                limit: None,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+}
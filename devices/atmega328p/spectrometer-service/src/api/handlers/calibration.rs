@@ -1,9 +1,12 @@
 use axum::Json;
 use axum::extract::State;
-use axum::http::StatusCode;
 use serde::Deserialize;
 
+use crate::api::ApiError;
+use crate::error::SpectrometerError;
+use crate::protocol::{AdcFrequency, Gain, MeasurementCount};
 use crate::service::calibration::SeriesMapping;
+use crate::service::events::ServiceEvent;
 use crate::service::state::AppState;
 
 pub async fn get_settings(State(state): State<AppState>) -> Json<serde_json::Value> {
@@ -14,11 +17,8 @@ pub async fn get_settings(State(state): State<AppState>) -> Json<serde_json::Val
         "gain": s.gain,
         "fadc": s.fadc,
         "count": s.count,
-        "series_mapping": {
-            "dark": s.series_mapping.dark,
-            "full": s.series_mapping.full,
-            "sample": s.series_mapping.sample,
-        },
+        "series_count": s.series_count,
+        "series_mapping": s.series_mapping,
         "last_updated": cfg.config.last_updated.to_rfc3339(),
     }))
 }
@@ -37,12 +37,37 @@ pub struct SeriesMappingRequest {
     pub dark: u8,
     pub full: u8,
     pub sample: u8,
+    #[serde(default)]
+    pub reference: Option<u8>,
 }
 
 pub async fn update_settings(
     State(state): State<AppState>,
     Json(req): Json<UpdateSettingsRequest>,
-) -> (StatusCode, Json<serde_json::Value>) {
+) -> Result<Json<serde_json::Value>, ApiError> {
+    Gain::try_from(req.gain).map_err(SpectrometerError::from)?;
+    AdcFrequency::try_from(req.fadc).map_err(SpectrometerError::from)?;
+    MeasurementCount::new(req.count).map_err(SpectrometerError::from)?;
+
+    let mapping = req.series_mapping.as_ref().map(|m| SeriesMapping {
+        dark: m.dark,
+        full: m.full,
+        sample: m.sample,
+        reference: m.reference,
+    });
+    if let Some(mapping) = &mapping {
+        let (series_count, sample_only) = {
+            let cfg = state.config.read().await;
+            (
+                cfg.config.device_settings.series_count,
+                cfg.config.processing.references.stored(),
+            )
+        };
+        mapping
+            .validate(series_count, sample_only)
+            .map_err(ApiError::bad_request)?;
+    }
+
     // Send commands to device
     for cmd in [
         format!("GAIN={}", req.gain),
@@ -54,46 +79,34 @@ pub async fn update_settings(
         }
     }
 
+    if let Some(source) = &mut state.device.write().await.data_source
+        && source.adc.is_some()
+    {
+        source.adc = Some((req.gain, req.fadc, req.count));
+    }
+
     // Save to config file
     let mut cfg = state.config.write().await;
     cfg.update_settings(req.gain, req.fadc, req.count);
 
-    if let Some(m) = &req.series_mapping {
-        cfg.config.device_settings.series_mapping = SeriesMapping {
-            dark: m.dark,
-            full: m.full,
-            sample: m.sample,
-        };
+    if let Some(mapping) = mapping {
+        cfg.config.device_settings.series_mapping = mapping;
     }
 
-    if let Err(e) = cfg.save() {
-        tracing::error!("Failed to save config: {e}");
-        return (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({"error": e})),
-        );
-    }
+    cfg.save()
+        .map_err(|e| ApiError::internal(format!("Failed to save config: {e}")))?;
 
-    let mapping = &cfg.config.device_settings.series_mapping;
-    let _ = state.broadcast_tx.send(serde_json::json!({
-        "type": "settings_updated",
+    state.events.publish(ServiceEvent::SettingsUpdated {
+        gain: req.gain,
+        fadc: req.fadc,
+        count: req.count,
+        series_mapping: cfg.config.device_settings.series_mapping.clone(),
+    });
+
+    Ok(Json(serde_json::json!({
+        "status": "applied",
         "gain": req.gain,
         "fadc": req.fadc,
         "count": req.count,
-        "series_mapping": {
-            "dark": mapping.dark,
-            "full": mapping.full,
-            "sample": mapping.sample,
-        },
-    }));
-
-    (
-        StatusCode::OK,
-        Json(serde_json::json!({
-            "status": "applied",
-            "gain": req.gain,
-            "fadc": req.fadc,
-            "count": req.count,
-        })),
-    )
+    })))
 }
@@ -0,0 +1,20 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::api::ApiError;
+use crate::api::models::*;
+use crate::service::reload::reload_config;
+use crate::service::state::AppState;
+
+/// POST /config/reload - Re-read the config file and apply runtime-safe changes
+pub async fn reload(State(state): State<AppState>) -> Result<Json<ConfigReloadResponse>, ApiError> {
+    let report = reload_config(&state).await.map_err(|e| {
+        tracing::warn!("Config reload failed: {e}");
+        ApiError::from(e)
+    })?;
+
+    Ok(Json(ConfigReloadResponse {
+        applied: report.applied,
+        deferred: report.deferred,
+    }))
+}
@@ -0,0 +1,79 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::api::ApiError;
+use crate::api::models::*;
+use crate::data_source::SourceSettings;
+use crate::service::state::AppState;
+
+/// POST /datasource/switch - Stop the data source and start the given one in
+/// its place, acquiring with the saved device settings
+pub async fn switch_source(
+    State(state): State<AppState>,
+    Json(settings): Json<SourceSettings>,
+) -> Result<Json<SourceSwitchResponse>, ApiError> {
+    let config = {
+        let cfg = state.config.read().await;
+        settings.to_data_source_config(&cfg.config.device_settings)
+    };
+
+    let source = state.switch_source(config).await.map_err(|e| {
+        tracing::warn!("Data source switch failed: {e}");
+        ApiError::bad_request(e)
+    })?;
+
+    Ok(Json(SourceSwitchResponse {
+        status: "switched".to_string(),
+        source,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::data_source::DataSourceConfig;
+    use crate::service::supervisor::SourceCommand;
+
+    /// App state whose supervisor accepts playback sources only
+    fn test_state() -> (AppState, tempfile::TempDir) {
+        let (mut state, dir) = AppState::for_test();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            while let Some(cmd) = cmd_rx.recv().await {
+                if let SourceCommand::Switch { config, reply } = cmd {
+                    let result = match config {
+                        DataSourceConfig::Playback { .. } => Ok("playback".to_string()),
+                        _ => Err("Failed to open serial port".to_string()),
+                    };
+                    let _ = reply.send(result);
+                }
+            }
+        });
+        state.device_cmd_tx = cmd_tx;
+        (state, dir)
+    }
+
+    #[tokio::test]
+    async fn test_switch_source() {
+        let (state, _dir) = test_state();
+        let settings = serde_json::from_str(r#"{"type": "playback", "file": "run.log"}"#).unwrap();
+        let response = switch_source(State(state), Json(settings)).await.unwrap();
+        assert_eq!(response.status, "switched");
+        assert_eq!(response.source, "playback");
+    }
+
+    #[tokio::test]
+    async fn test_switch_source_failure() {
+        let (state, _dir) = test_state();
+        let settings =
+            serde_json::from_str(r#"{"type": "serial", "port": "/dev/ttyUSB0"}"#).unwrap();
+        let error = switch_source(State(state), Json(settings))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert!(error.message().contains("serial port"));
+    }
+}
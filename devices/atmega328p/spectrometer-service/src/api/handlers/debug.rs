@@ -0,0 +1,43 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::api::models::*;
+use crate::service::state::AppState;
+
+/// GET /debug/device - Recent firmware debug dumps, oldest first
+pub async fn get_device_debug(State(state): State<AppState>) -> Json<DeviceDebugResponse> {
+    let device = state.device.read().await;
+
+    Json(DeviceDebugResponse {
+        blocks: device.debug_blocks.iter().cloned().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::protocol::DebugBlock;
+
+    #[tokio::test]
+    async fn test_get_device_debug_empty() {
+        let (state, _dir) = AppState::for_test();
+        let response = get_device_debug(State(state)).await;
+        assert!(response.blocks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_device_debug_returns_blocks() {
+        let (state, _dir) = AppState::for_test();
+        state.device.write().await.record_debug_block(DebugBlock {
+            timestamp: Utc::now(),
+            lines: vec!["reg0=0x1F".to_string()],
+            truncated: false,
+        });
+
+        let response = get_device_debug(State(state)).await;
+        assert_eq!(response.blocks.len(), 1);
+        assert_eq!(response.blocks[0].lines, vec!["reg0=0x1F"]);
+    }
+}
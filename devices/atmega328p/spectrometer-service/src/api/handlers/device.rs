@@ -1,23 +1,145 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use axum::Json;
 use axum::extract::State;
+use chrono::Utc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
 
 use crate::api::models::*;
+use crate::api::{API_VERSION, ApiError};
+use crate::monitoring::DeviceCapabilities;
+use crate::monitoring::announce::{DEVICE_NAME, DEVICE_TYPE};
+use crate::service::events::ServiceEvent;
+use crate::service::registration::{RegistrationLoss, clear_registration};
 use crate::service::state::AppState;
 
-/// GET /device/info - Return device capabilities
-pub async fn get_device_info() -> Json<DeviceInfoResponse> {
+/// Reply collection time of `POST /device/command` without `timeout_ms`
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Longest `timeout_ms` accepted by `POST /device/command`
+const MAX_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// GET /device/info - Return device capabilities and firmware version
+pub async fn get_device_info(State(state): State<AppState>) -> Json<DeviceInfoResponse> {
+    let (firmware_version, confirmed_settings, registered_at) = {
+        let device = state.device.read().await;
+        (
+            device.firmware_version.clone(),
+            device.confirmed_settings,
+            device.registered_at,
+        )
+    };
+
     Json(DeviceInfoResponse {
-        device_type: "spectrometer".to_string(),
-        name: "ATmega328P Monochromatic Spectrometer".to_string(),
-        capabilities: DeviceCapabilities {
-            has_spectrometer: true,
-            has_vacuum_chamber: true,
-            spectrometer_type: "two-component".to_string(),
-            is_monochromatic: true,
-        },
+        device_type: DEVICE_TYPE.to_string(),
+        name: DEVICE_NAME.to_string(),
+        capabilities: DeviceCapabilities::default(),
+        firmware_version,
+        confirmed_settings,
+        api_version: API_VERSION.to_string(),
+        registered_at,
+        registration_age_secs: registered_at
+            .map(|at| (Utc::now() - at).num_milliseconds() as f64 / 1000.0),
+    })
+}
+
+/// GET /devices - Devices served by this process, with their state and API
+/// path
+pub async fn list_devices(
+    State(devices): State<Arc<[(String, AppState)]>>,
+) -> Json<Vec<DeviceListing>> {
+    let mut listing = Vec::with_capacity(devices.len());
+    for (id, state) in devices.iter() {
+        let device = state.device.read().await;
+        let health = device.health.evaluate(Utc::now());
+        listing.push(DeviceListing {
+            id: id.clone(),
+            path: format!("/devices/{id}"),
+            device_type: DEVICE_TYPE.to_string(),
+            name: DEVICE_NAME.to_string(),
+            capabilities: DeviceCapabilities::default(),
+            chamber_state: device.chamber.state(),
+            registered: device.is_registered(),
+            spectrometer_id: device.spectrometer_id.clone(),
+            healthy: health.healthy,
+            ready: health.ready,
+        });
+    }
+    Json(listing)
+}
+
+/// GET /device/settings - Return the effective acquisition settings and
+/// data source
+pub async fn get_device_settings(State(state): State<AppState>) -> Json<DeviceSettingsResponse> {
+    let saved = {
+        let cfg = state.config.read().await;
+        let settings = &cfg.config.device_settings;
+        (settings.gain, settings.fadc, settings.count)
+    };
+    let device = state.device.read().await;
+    let source = device.data_source.as_ref();
+    let (gain, fadc, count) = source.and_then(|source| source.adc).unwrap_or(saved);
+    let confirmed = device
+        .confirmed_settings
+        .filter(|confirmed| confirmed.matches(gain, fadc, count));
+
+    Json(DeviceSettingsResponse {
+        gain,
+        fadc,
+        count,
+        baud_rate: source.and_then(|source| source.baud_rate),
+        source_type: source.map(|source| source.kind.to_string()),
+        source_name: source.map(|source| source.name.clone()),
+        confirmed: confirmed.is_some(),
+        confirmed_at: confirmed.map(|confirmed| confirmed.confirmed_at),
     })
 }
 
+/// POST /device/command - Send a raw command to the device and return the
+/// lines it wrote back until the timeout
+pub async fn send_command(
+    State(state): State<AppState>,
+    Json(req): Json<DeviceCommandRequest>,
+) -> Result<Json<DeviceCommandResponse>, ApiError> {
+    let command = req.command.trim().to_string();
+    if command.is_empty() || command.contains(['\r', '\n']) {
+        return Err(ApiError::bad_request(
+            "Command must be a single non-empty line",
+        ));
+    }
+    let timeout = req
+        .timeout_ms
+        .map_or(DEFAULT_COMMAND_TIMEOUT, Duration::from_millis);
+    if timeout > MAX_COMMAND_TIMEOUT {
+        return Err(ApiError::bad_request(format!(
+            "timeout_ms must be at most {}",
+            MAX_COMMAND_TIMEOUT.as_millis()
+        )));
+    }
+
+    // Subscribed before sending, so an immediate reply is not missed
+    let mut events = state.events.subscribe();
+    state.send_raw_command(&command).await.map_err(|e| {
+        tracing::warn!("Raw command '{command}' failed: {e}");
+        ApiError::conflict(e)
+    })?;
+
+    let deadline = Instant::now() + timeout;
+    let mut lines = Vec::new();
+    while let Ok(event) = tokio::time::timeout_at(deadline, events.recv()).await {
+        match event {
+            // Device lines only, not the echo of commands sent
+            Ok(ServiceEvent::Log { line }) if !line.starts_with("> ") => lines.push(line),
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    Ok(Json(DeviceCommandResponse { command, lines }))
+}
+
 /// POST /register - Receive assigned IDs from monitoring system
 pub async fn register(
     State(state): State<AppState>,
@@ -26,6 +148,7 @@ pub async fn register(
     let mut state = state.device.write().await;
 
     state.monitoring_api_url = Some(request.monitoring_api_url.clone());
+    state.registered_at = Some(Utc::now());
     state.spectrometer_id = request.spectrometer_id.clone();
     state.vacuum_chamber_id = request.vacuum_chamber_id.clone();
 
@@ -44,41 +167,154 @@ pub async fn register(
     })
 }
 
+/// POST /unregister - Clear the monitoring registration and stop pushing
+pub async fn unregister(State(state): State<AppState>) -> Json<UnregisterResponse> {
+    let mut device = state.device.write().await;
+    let cleared = clear_registration(&mut device, &state.events, RegistrationLoss::Unregistered);
+
+    Json(UnregisterResponse {
+        status: if cleared {
+            "unregistered"
+        } else {
+            "not_registered"
+        }
+        .to_string(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
-
-    use tokio::sync::{broadcast, mpsc};
+    use axum::http::StatusCode;
+    use tokio::sync::mpsc;
 
     use super::*;
-    use crate::service::calibration::create_shared_config;
-    use crate::service::state::create_shared_state;
-
-    fn test_state() -> (AppState, tempfile::TempDir) {
-        let dir = tempfile::tempdir().unwrap();
-        let (tx, _) = broadcast::channel(16);
-        let (cmd_tx, _) = mpsc::channel(16);
-        let state = AppState {
-            device: create_shared_state(),
-            config: create_shared_config(dir.path().join("cfg.toml")),
-            broadcast_tx: tx,
-            device_cmd_tx: cmd_tx,
-        };
-        (state, dir)
-    }
+    use crate::data_source::ActiveSource;
+    use crate::protocol::{ConfirmedSettings, FirmwareVersion};
+    use crate::service::supervisor::SourceCommand;
 
     #[tokio::test]
     async fn test_get_device_info() {
-        let response = get_device_info().await;
+        let (state, _dir) = AppState::for_test();
+        let response = get_device_info(State(state.clone())).await;
 
         assert_eq!(response.device_type, "spectrometer");
         assert!(response.capabilities.has_spectrometer);
         assert!(response.capabilities.has_vacuum_chamber);
         assert!(response.capabilities.is_monochromatic);
+        assert!(response.firmware_version.is_none());
+        assert_eq!(response.api_version, "v1");
+
+        state.device.write().await.firmware_version = FirmwareVersion::parse("1.3.0");
+        let response = get_device_info(State(state)).await;
+        assert_eq!(response.firmware_version.as_ref().unwrap().minor, 3);
+    }
+
+    #[tokio::test]
+    async fn test_get_device_settings() {
+        let (state, _dir) = AppState::for_test();
+        let response = get_device_settings(State(state.clone())).await;
+        let saved = state.config.read().await.config.device_settings.clone();
+        assert_eq!(response.gain, saved.gain);
+        assert_eq!(response.source_type, None);
+        assert!(!response.confirmed);
+
+        {
+            let mut device = state.device.write().await;
+            device.data_source = Some(ActiveSource {
+                kind: "serial",
+                name: "/dev/ttyUSB0".to_string(),
+                baud_rate: Some(38400),
+                adc: Some((4, 2.5, 10)),
+            });
+            device.confirmed_settings = Some(ConfirmedSettings {
+                gain: 4,
+                fadc: 2.5,
+                count: 10,
+                confirmed_at: chrono::Utc::now(),
+            });
+        }
+        let response = get_device_settings(State(state.clone())).await;
+        assert_eq!((response.gain, response.count), (4, 10));
+        assert_eq!(response.baud_rate, Some(38400));
+        assert_eq!(response.source_type.as_deref(), Some("serial"));
+        assert!(response.confirmed);
+
+        // Changed since startup: no longer what the device confirmed
+        state.device.write().await.data_source.as_mut().unwrap().adc = Some((8, 2.5, 10));
+        let response = get_device_settings(State(state)).await;
+        assert_eq!(response.gain, 8);
+        assert!(!response.confirmed);
+        assert!(response.confirmed_at.is_none());
+    }
+
+    /// App state whose data source answers `VERSION` and refuses other
+    /// commands
+    fn command_state() -> (AppState, tempfile::TempDir) {
+        let (mut state, dir) = AppState::for_test();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(16);
+        let events = state.events.clone();
+        tokio::spawn(async move {
+            while let Some(cmd) = cmd_rx.recv().await {
+                if let SourceCommand::Passthrough { command, reply } = cmd {
+                    if command != "VERSION" {
+                        let _ = reply.send(Err("Cannot send commands in playback mode".into()));
+                        continue;
+                    }
+                    let _ = reply.send(Ok(()));
+                    for line in ["> VERSION", "VERSION=1.2.0"] {
+                        events.publish(ServiceEvent::Log {
+                            line: line.to_string(),
+                        });
+                    }
+                    events.publish(ServiceEvent::DepositionStarted { at: Utc::now() });
+                }
+            }
+        });
+        state.device_cmd_tx = cmd_tx;
+        (state, dir)
+    }
+
+    fn command(command: &str, timeout_ms: Option<u64>) -> Json<DeviceCommandRequest> {
+        Json(DeviceCommandRequest {
+            command: command.to_string(),
+            timeout_ms,
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_send_command() {
+        let (state, _dir) = command_state();
+        let response = send_command(State(state), command(" VERSION\n", None))
+            .await
+            .unwrap();
+        assert_eq!(response.command, "VERSION");
+        assert_eq!(response.lines, vec!["VERSION=1.2.0"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_send_command_rejected() {
+        let (state, _dir) = command_state();
+        for request in [
+            command("", None),
+            command("GAIN=4\nCOUNT=5", None),
+            command("VERSION", Some(60_000)),
+        ] {
+            let error = send_command(State(state.clone()), request)
+                .await
+                .unwrap_err();
+            assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        }
+
+        // The source refusing the command is a conflict, not a bad request
+        let error = send_command(State(state), command("GAIN=4", None))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::CONFLICT);
     }
 
     #[tokio::test]
     async fn test_register() {
-        let (state, _dir) = test_state();
+        let (state, _dir) = AppState::for_test();
 
         let request = RegisterRequest {
             monitoring_api_url: "http://localhost:8200".to_string(),
@@ -94,5 +330,38 @@ mod tests {
         // Verify state was updated
         let s = state.device.read().await;
         assert!(s.is_registered());
+        assert!(s.registered_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unregister() {
+        let (state, _dir) = AppState::for_test();
+        let mut events = state.events.subscribe();
+        let request = RegisterRequest {
+            monitoring_api_url: "http://localhost:8200".to_string(),
+            spectrometer_id: Some("spec-123".to_string()),
+            vacuum_chamber_id: None,
+        };
+        let _ = register(State(state.clone()), Json(request)).await;
+
+        let info = get_device_info(State(state.clone())).await;
+        assert!(info.registered_at.is_some());
+        assert!(info.registration_age_secs.unwrap() >= 0.0);
+
+        let response = unregister(State(state.clone())).await;
+        assert_eq!(response.status, "unregistered");
+        assert!(!state.device.read().await.is_registered());
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::RegistrationLost {
+                reason: RegistrationLoss::Unregistered,
+                ..
+            }
+        ));
+
+        let info = get_device_info(State(state.clone())).await;
+        assert!(info.registration_age_secs.is_none());
+        let response = unregister(State(state)).await;
+        assert_eq!(response.status, "not_registered");
     }
 }
@@ -0,0 +1,132 @@
+use axum::Json;
+use axum::extract::State;
+
+use crate::api::models::*;
+use crate::service::state::AppState;
+
+/// GET /diagnostics/protocol - Unknown/error line counts and recent offending lines
+pub async fn get_protocol_diagnostics(
+    State(state): State<AppState>,
+) -> Json<ProtocolDiagnosticsResponse> {
+    let device = state.device.read().await;
+
+    Json(ProtocolDiagnosticsResponse {
+        sources: device.protocol.sources().clone(),
+        recent: device.protocol.recent().cloned().collect(),
+    })
+}
+
+/// GET /diagnostics/events - Recent firmware error, cycle-missing and
+/// ADC-ready lines
+pub async fn get_device_events(State(state): State<AppState>) -> Json<DeviceEventsResponse> {
+    let device = state.device.read().await;
+
+    Json(DeviceEventsResponse {
+        events: device.device_events.events().cloned().collect(),
+    })
+}
+
+/// GET /diagnostics/monitoring - Push totals per monitoring endpoint and the
+/// most recent push attempts
+pub async fn get_monitoring_diagnostics(
+    State(state): State<AppState>,
+) -> Json<MonitoringDiagnosticsResponse> {
+    let device = state.device.read().await;
+
+    Json(MonitoringDiagnosticsResponse {
+        registered: device.is_registered(),
+        monitoring_api_url: device.monitoring_api_url.clone(),
+        last_push_succeeded: device.health.last_push.as_ref().map(|push| push.success),
+        endpoints: device.push_log.endpoints().clone(),
+        recent: device.push_log.recent().cloned().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::protocol::{DeviceEvent, DeviceEventKind, ProtocolIssue, ProtocolIssueKind};
+    use crate::service::diagnostics::PushAttempt;
+
+    #[tokio::test]
+    async fn test_protocol_diagnostics_empty() {
+        let (state, _dir) = AppState::for_test();
+        let response = get_protocol_diagnostics(State(state)).await;
+        assert!(response.sources.is_empty());
+        assert!(response.recent.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_protocol_diagnostics_reports_issues() {
+        let (state, _dir) = AppState::for_test();
+        state.device.write().await.protocol.record(ProtocolIssue {
+            timestamp: Utc::now(),
+            source: "/dev/ttyUSB0".to_string(),
+            kind: ProtocolIssueKind::Error,
+            line: "ERROR Invalid GAIN value".to_string(),
+        });
+
+        let response = get_protocol_diagnostics(State(state)).await;
+        assert_eq!(response.sources["/dev/ttyUSB0"].errors, 1);
+        assert_eq!(response.recent.len(), 1);
+        assert_eq!(response.recent[0].line, "ERROR Invalid GAIN value");
+    }
+
+    #[tokio::test]
+    async fn test_monitoring_diagnostics() {
+        let (state, _dir) = AppState::for_test();
+        let response = get_monitoring_diagnostics(State(state.clone())).await;
+        assert!(!response.registered);
+        assert_eq!(response.last_push_succeeded, None);
+        assert!(response.recent.is_empty());
+
+        {
+            let mut device = state.device.write().await;
+            device.push_log.record(PushAttempt {
+                at: Utc::now(),
+                endpoint: "http://monitor:8200".to_string(),
+                timestamp: Utc::now(),
+                latency_ms: 250.0,
+                status: Some(503),
+                error: Some("Monitoring API returned 503".to_string()),
+            });
+            device
+                .health
+                .record_push(Err("Monitoring API returned 503".to_string()));
+        }
+
+        let response = get_monitoring_diagnostics(State(state)).await;
+        assert_eq!(response.last_push_succeeded, Some(false));
+        assert_eq!(response.endpoints["http://monitor:8200"].failed, 1);
+        assert_eq!(response.recent[0].status, Some(503));
+    }
+
+    #[tokio::test]
+    async fn test_device_events() {
+        let (state, _dir) = AppState::for_test();
+        assert!(
+            get_device_events(State(state.clone()))
+                .await
+                .events
+                .is_empty()
+        );
+
+        state
+            .device
+            .write()
+            .await
+            .device_events
+            .record(DeviceEvent {
+                timestamp: Utc::now(),
+                source: "/dev/ttyUSB0".to_string(),
+                kind: DeviceEventKind::AdcReady,
+                line: "ADC ready".to_string(),
+            });
+
+        let response = get_device_events(State(state)).await;
+        assert_eq!(response.events.len(), 1);
+        assert_eq!(response.events[0].kind, DeviceEventKind::AdcReady);
+    }
+}
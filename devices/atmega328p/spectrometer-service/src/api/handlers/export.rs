@@ -0,0 +1,309 @@
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use futures_util::{StreamExt, TryStreamExt, stream};
+
+use crate::api::ApiError;
+use crate::api::models::*;
+use crate::data_sink::csv::{CSV_HEADER, csv_row};
+use crate::error::SpectrometerError;
+use crate::protocol::ProcessedMeasurement;
+use crate::service::state::AppState;
+use crate::storage::archive::MAX_QUERY_LIMIT;
+use crate::storage::{PageCursor, SharedArchive};
+
+/// Timestamp format used in the download file name
+const FILENAME_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Archive rows read per query while streaming an export
+const EXPORT_PAGE_SIZE: usize = 5_000;
+
+/// Measurements with `from <= timestamp <= to` from the archive when enabled
+/// (the oldest `MAX_QUERY_LIMIT`), otherwise from the in-memory history, and
+/// whether the range held more than were returned
+pub(crate) async fn measurements_in_range(
+    state: &AppState,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<(Vec<ProcessedMeasurement>, bool), ApiError> {
+    Ok(match &state.archive {
+        Some(archive) => archived_in_range(archive, from, to, MAX_QUERY_LIMIT).await?,
+        None => (state.history.read().await.range(from, to), false),
+    })
+}
+
+/// The oldest `limit` archived measurements with `from <= timestamp <= to`,
+/// and whether the range held more
+async fn archived_in_range(
+    archive: &SharedArchive,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    limit: usize,
+) -> Result<(Vec<ProcessedMeasurement>, bool), SpectrometerError> {
+    // One row past the limit tells whether the range was cut short
+    let (mut archived, _) = archive
+        .blocking(move |archive| archive.page(from, to, None, limit + 1))
+        .await?;
+    let truncated = archived.len() > limit;
+    if truncated {
+        tracing::warn!("Range query truncated to {limit} measurements");
+        archived.truncate(limit);
+    }
+
+    Ok((
+        archived.into_iter().map(|m| m.measurement).collect(),
+        truncated,
+    ))
+}
+
+/// CSV rows of `measurements`, one per line
+fn csv_rows<'a>(measurements: impl IntoIterator<Item = &'a ProcessedMeasurement>) -> String {
+    let mut rows = String::new();
+    for measurement in measurements {
+        rows.push_str(&csv_row(measurement));
+        rows.push('\n');
+    }
+    rows
+}
+
+/// CSV of the archived measurements in `from..=to`, read `page_size` rows
+/// at a time while the response is sent. A failed read ends the response
+/// early, so the client sees an incomplete transfer.
+fn archive_csv(
+    archive: SharedArchive,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    page_size: usize,
+) -> Body {
+    // `None` once the last page was read
+    let start: Option<Option<PageCursor>> = Some(None);
+    let pages = stream::try_unfold(start, move |after| {
+        let archive = archive.clone();
+        async move {
+            let Some(after) = after else {
+                return Ok(None);
+            };
+            let (page, cursor) = archive
+                .blocking(move |archive| archive.page(from, to, after, page_size))
+                .await?;
+            let rows = csv_rows(page.iter().map(|archived| &archived.measurement));
+            let next = (page.len() == page_size).then_some(cursor);
+            Ok::<_, SpectrometerError>(Some((rows, next)))
+        }
+    });
+
+    let header = stream::once(async { Ok(format!("{CSV_HEADER}\n")) });
+    Body::from_stream(
+        header
+            .chain(pages)
+            .inspect_err(|e| tracing::error!("CSV export aborted: {e}")),
+    )
+}
+
+/// GET /export/csv?from=&to= - Measurements in a time range as a CSV download.
+/// Streams the whole range from the archive when enabled, otherwise reads
+/// the in-memory history.
+pub async fn export_csv(
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    if query.from > query.to {
+        return Err(ApiError::bad_request("'from' must not be later than 'to'"));
+    }
+
+    let body = match &state.archive {
+        Some(archive) => archive_csv(archive.clone(), query.from, query.to, EXPORT_PAGE_SIZE),
+        None => {
+            let measurements = state.history.read().await.range(query.from, query.to);
+            Body::from(format!("{CSV_HEADER}\n{}", csv_rows(&measurements)))
+        }
+    };
+
+    let filename = format!(
+        "measurements_{}_{}.csv",
+        query.from.format(FILENAME_TIME_FORMAT),
+        query.to.format(FILENAME_TIME_FORMAT)
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::body::to_bytes;
+    use axum::http::StatusCode;
+    use chrono::Duration;
+
+    use super::*;
+    use crate::protocol::{MeasurementCycle, SeriesData};
+    use crate::storage::{MeasurementArchive, RunTags};
+
+    fn measurement_at(timestamp: DateTime<Utc>) -> ProcessedMeasurement {
+        ProcessedMeasurement::new(timestamp, 100.0, 1000.0, 550.0, 50.0)
+    }
+
+    async fn body_lines(response: Response) -> Vec<String> {
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_export_from_history() {
+        let (state, _dir) = AppState::for_test();
+        let t0 = Utc::now();
+        {
+            let mut history = state.history.write().await;
+            for i in 0..5 {
+                history.push(measurement_at(t0 + Duration::seconds(i)));
+            }
+        }
+
+        let response = export_csv(
+            State(state),
+            Query(ExportQuery {
+                from: t0 + Duration::seconds(1),
+                to: t0 + Duration::seconds(2),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/csv; charset=utf-8"
+        );
+        assert!(
+            response.headers()[header::CONTENT_DISPOSITION]
+                .to_str()
+                .unwrap()
+                .starts_with("attachment; filename=\"measurements_")
+        );
+        let lines = body_lines(response).await;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].ends_with(",50,true,"));
+    }
+
+    #[tokio::test]
+    async fn test_export_prefers_archive() {
+        let (mut state, dir) = AppState::for_test();
+        let archive = MeasurementArchive::open(&dir.path().join("archive.db"), false).unwrap();
+        let t0 = Utc::now();
+        let cycle = MeasurementCycle::with_timestamp(
+            t0,
+            SeriesData::new(vec![100]),
+            SeriesData::new(vec![1000]),
+            SeriesData::new(vec![550]),
+        );
+        archive
+            .append(&measurement_at(t0), &RunTags::default(), &cycle)
+            .unwrap();
+        state.archive = Some(Arc::new(archive));
+
+        let response = export_csv(
+            State(state),
+            Query(ExportQuery {
+                from: t0 - Duration::seconds(1),
+                to: t0 + Duration::seconds(1),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(body_lines(response).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_archive_export_streams_every_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = MeasurementArchive::open(&dir.path().join("archive.db"), false).unwrap();
+        let t0 = Utc::now();
+        for i in 0..5 {
+            let timestamp = t0 + Duration::seconds(i);
+            let cycle = MeasurementCycle::with_timestamp(
+                timestamp,
+                SeriesData::new(vec![100]),
+                SeriesData::new(vec![1000]),
+                SeriesData::new(vec![550]),
+            );
+            archive
+                .append(&measurement_at(timestamp), &RunTags::default(), &cycle)
+                .unwrap();
+        }
+
+        let body = archive_csv(Arc::new(archive), t0, t0 + Duration::seconds(3), 2);
+        let bytes = to_bytes(body, usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], CSV_HEADER);
+    }
+
+    #[tokio::test]
+    async fn test_archived_range_flags_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = MeasurementArchive::open(&dir.path().join("archive.db"), false).unwrap();
+        let t0 = Utc::now();
+        for i in 0..3 {
+            let timestamp = t0 + Duration::seconds(i);
+            let cycle = MeasurementCycle::with_timestamp(
+                timestamp,
+                SeriesData::new(vec![100]),
+                SeriesData::new(vec![1000]),
+                SeriesData::new(vec![550]),
+            );
+            archive
+                .append(&measurement_at(timestamp), &RunTags::default(), &cycle)
+                .unwrap();
+        }
+        let archive = Arc::new(archive);
+        let to = t0 + Duration::seconds(2);
+
+        let (measurements, truncated) = archived_in_range(&archive, t0, to, 2).await.unwrap();
+        assert!(truncated);
+        assert_eq!(measurements.len(), 2);
+        assert_eq!(
+            measurements[0].timestamp.timestamp_micros(),
+            t0.timestamp_micros()
+        );
+
+        let (measurements, truncated) = archived_in_range(&archive, t0, to, 3).await.unwrap();
+        assert!(!truncated);
+        assert_eq!(measurements.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_export_rejects_inverted_range() {
+        let (state, _dir) = AppState::for_test();
+        let now = Utc::now();
+
+        let error = export_csv(
+            State(state),
+            Query(ExportQuery {
+                from: now,
+                to: now - Duration::seconds(1),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,73 @@
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use chrono::Utc;
+
+use crate::service::health::HealthReport;
+use crate::service::state::AppState;
+
+/// GET /healthz - 200 while the data source is active and data is fresh, else 503
+pub async fn healthz(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let report = state.device.read().await.health.evaluate(Utc::now());
+    (status_code(report.healthy), Json(report))
+}
+
+/// GET /readyz - Like /healthz, additionally requiring the last monitoring push to have succeeded
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let report = state.device.read().await.health.evaluate(Utc::now());
+    (status_code(report.ready), Json(report))
+}
+
+fn status_code(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_healthz_unavailable_without_source() {
+        let (state, _dir) = AppState::for_test();
+        let (status, report) = healthz(State(state)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!report.healthy);
+    }
+
+    #[tokio::test]
+    async fn test_healthz_ok_with_fresh_cycle() {
+        let (state, _dir) = AppState::for_test();
+        state.device.write().await.health.record_cycle(Utc::now());
+
+        let (status, report) = healthz(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(report.last_cycle_at.is_some());
+
+        let (status, _) = readyz(State(state)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_readyz_unavailable_after_failed_push() {
+        let (state, _dir) = AppState::for_test();
+        {
+            let mut device = state.device.write().await;
+            device.health.record_cycle(Utc::now());
+            device.health.record_push(Err("timeout".to_string()));
+        }
+
+        let (status, _) = healthz(State(state.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, report) = readyz(State(state)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            report.last_push.as_ref().unwrap().error.as_deref(),
+            Some("timeout")
+        );
+    }
+}
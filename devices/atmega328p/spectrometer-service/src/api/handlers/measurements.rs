@@ -0,0 +1,244 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use chrono::{Duration, Utc};
+
+use super::export::measurements_in_range;
+use crate::api::ApiError;
+use crate::api::models::*;
+use crate::service::history::ReadingStats;
+use crate::service::state::AppState;
+use crate::storage::downsample;
+
+/// Points returned when the query gives none
+const DEFAULT_POINTS: usize = 1000;
+
+/// Accepted range of the `points` parameter
+const POINTS_RANGE: std::ops::RangeInclusive<usize> = 3..=10_000;
+
+/// Statistics window when the query gives none
+const DEFAULT_WINDOW: &str = "60s";
+
+/// GET /measurements/downsampled?from=&to=&points=&method= - Valid
+/// measurements in a time range, reduced to at most `points` for charting.
+/// Reads from the archive when enabled, otherwise from the in-memory history.
+pub async fn get_downsampled(
+    State(state): State<AppState>,
+    Query(query): Query<DownsampledQuery>,
+) -> Result<Json<DownsampledResponse>, ApiError> {
+    if query.from > query.to {
+        return Err(ApiError::bad_request("'from' must not be later than 'to'"));
+    }
+    let points = query.points.unwrap_or(DEFAULT_POINTS);
+    if !POINTS_RANGE.contains(&points) {
+        return Err(ApiError::bad_request(format!(
+            "'points' must be between {} and {}, got {points}",
+            POINTS_RANGE.start(),
+            POINTS_RANGE.end()
+        )));
+    }
+
+    let (mut measurements, truncated) = measurements_in_range(&state, query.from, query.to).await?;
+    measurements.retain(|m| m.is_valid);
+    let source_count = measurements.len();
+    let measurements = downsample(measurements, points, query.method);
+
+    Ok(Json(DownsampledResponse {
+        method: query.method,
+        source_count,
+        count: measurements.len(),
+        truncated,
+        measurements,
+    }))
+}
+
+/// GET /measurements/stats?window= - Mean, spread, range, trend and invalid
+/// share of the calibrated readings over the last `window`, from the
+/// in-memory history
+pub async fn get_stats(
+    State(state): State<AppState>,
+    Query(query): Query<MeasurementStatsQuery>,
+) -> Result<Json<MeasurementStatsResponse>, ApiError> {
+    let window = parse_window(query.window.as_deref().unwrap_or(DEFAULT_WINDOW))
+        .map_err(ApiError::bad_request)?;
+
+    let to = Utc::now();
+    let from = to - window;
+    let measurements = state.history.read().await.range(from, to);
+
+    Ok(Json(MeasurementStatsResponse {
+        window_secs: window.as_seconds_f64(),
+        from,
+        to,
+        stats: ReadingStats::of(&measurements),
+    }))
+}
+
+/// Parse a window length: a positive number followed by `ms`, `s`, `m` or
+/// `h`, or by nothing for seconds
+fn parse_window(window: &str) -> Result<Duration, String> {
+    let window = window.trim();
+    let split = window
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(window.len());
+    let (value, unit) = window.split_at(split);
+    let seconds_per_unit = match unit {
+        "ms" => 1e-3,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => {
+            return Err(format!(
+                "invalid window unit '{unit}', expected ms, s, m or h"
+            ));
+        }
+    };
+    let seconds = value
+        .parse::<f64>()
+        .ok()
+        .map(|value| value * seconds_per_unit)
+        .filter(|seconds| *seconds > 0.0 && seconds.is_finite())
+        .ok_or_else(|| format!("invalid window '{window}', expected e.g. 60s"))?;
+
+    std::time::Duration::try_from_secs_f64(seconds)
+        .ok()
+        .and_then(|duration| Duration::from_std(duration).ok())
+        .ok_or_else(|| format!("window '{window}' is too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::protocol::ProcessedMeasurement;
+    use crate::service::history::create_shared_history;
+    use crate::storage::DownsampleMethod;
+
+    fn test_state() -> (AppState, tempfile::TempDir) {
+        let (mut state, dir) = AppState::for_test();
+        state.history = create_shared_history(1000);
+        (state, dir)
+    }
+
+    #[tokio::test]
+    async fn test_downsampled_from_history() {
+        let (state, _dir) = test_state();
+        let t0 = Utc::now();
+        {
+            let mut history = state.history.write().await;
+            for i in 0..500 {
+                let mut measurement = ProcessedMeasurement::new(
+                    t0 + Duration::seconds(i),
+                    100.0,
+                    1000.0,
+                    550.0,
+                    (i % 50) as f64,
+                );
+                measurement.is_valid = i % 100 != 0;
+                history.push(measurement);
+            }
+        }
+
+        let response = get_downsampled(
+            State(state),
+            Query(DownsampledQuery {
+                from: t0,
+                to: t0 + Duration::seconds(499),
+                points: Some(40),
+                method: DownsampleMethod::MinMax,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.source_count, 495);
+        assert_eq!(response.count, 40);
+        assert!(response.measurements.iter().all(|m| m.is_valid));
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("60s"), Ok(Duration::seconds(60)));
+        assert_eq!(parse_window("90"), Ok(Duration::seconds(90)));
+        assert_eq!(parse_window("500ms"), Ok(Duration::milliseconds(500)));
+        assert_eq!(parse_window("1.5m"), Ok(Duration::seconds(90)));
+        assert_eq!(parse_window("2h"), Ok(Duration::hours(2)));
+        for window in ["", "s", "0s", "-5s", "10d", "abc"] {
+            assert!(parse_window(window).is_err(), "{window}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_over_window() {
+        let (state, _dir) = test_state();
+        let now = Utc::now();
+        {
+            let mut history = state.history.write().await;
+            // Outside the window
+            history.push(ProcessedMeasurement::new(
+                now - Duration::seconds(120),
+                100.0,
+                1000.0,
+                550.0,
+                90.0,
+            ));
+            for i in 0..10 {
+                history.push(ProcessedMeasurement::new(
+                    now - Duration::seconds(30 - i),
+                    100.0,
+                    1000.0,
+                    550.0,
+                    40.0 + i as f64,
+                ));
+            }
+        }
+
+        let response = get_stats(
+            State(state.clone()),
+            Query(MeasurementStatsQuery {
+                window: Some("60s".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.window_secs, 60.0);
+        assert_eq!(response.stats.count, 10);
+        assert_eq!(response.stats.min, Some(40.0));
+        assert_eq!(response.stats.max, Some(49.0));
+        assert!((response.stats.slope.unwrap() - 1.0).abs() < 1e-9);
+
+        let error = get_stats(
+            State(state),
+            Query(MeasurementStatsQuery {
+                window: Some("soon".to_string()),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_downsampled_rejects_bad_query() {
+        let (state, _dir) = test_state();
+        let now = Utc::now();
+        for (from, to, points) in [
+            (now, now - Duration::seconds(1), None),
+            (now, now, Some(2)),
+            (now, now, Some(1_000_000)),
+        ] {
+            let error = get_downsampled(
+                State(state.clone()),
+                Query(DownsampledQuery {
+                    from,
+                    to,
+                    points,
+                    method: DownsampleMethod::Lttb,
+                }),
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
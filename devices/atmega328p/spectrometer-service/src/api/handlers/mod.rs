@@ -1,4 +1,16 @@
+pub mod archive;
 pub mod calibration;
+pub mod config;
+pub mod datasource;
+pub mod debug;
 pub mod device;
+pub mod diagnostics;
+pub mod export;
+pub mod health;
+pub mod measurements;
+pub mod metrics;
+pub mod monitoring;
+pub mod processing;
+pub mod selftest;
 pub mod spectrometer;
 pub mod vacuum_chamber;
@@ -0,0 +1,255 @@
+use std::time::Duration;
+
+use axum::Json;
+use axum::extract::{Query, State};
+
+use super::export::measurements_in_range;
+use crate::api::ApiError;
+use crate::api::models::*;
+use crate::error::SpectrometerError;
+use crate::monitoring::{MonitoringClient, ReadingDetails};
+use crate::protocol::ProcessedMeasurement;
+use crate::service::state::AppState;
+
+/// Delay between backfill pushes (caps backfill at 20 readings/s)
+const BACKFILL_PUSH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// POST /monitoring/backfill?from=&to= - Re-push stored measurements for a
+/// time range, from the archive when enabled, otherwise from the in-memory
+/// history
+pub async fn backfill(
+    State(state): State<AppState>,
+    Query(query): Query<BackfillQuery>,
+) -> Result<Json<BackfillResponse>, ApiError> {
+    if query.from > query.to {
+        return Err(ApiError::bad_request("'from' must not be later than 'to'"));
+    }
+
+    let (api_url, spectrometer_id) = {
+        let device = state.device.read().await;
+        (
+            device.monitoring_api_url.clone(),
+            device.spectrometer_id.clone(),
+        )
+    };
+
+    let (Some(api_url), Some(spectrometer_id)) = (api_url, spectrometer_id) else {
+        return Err(SpectrometerError::NotRegistered.into());
+    };
+
+    let (measurements, truncated) = measurements_in_range(&state, query.from, query.to).await?;
+    let count = measurements.len();
+
+    tracing::info!(
+        "Backfilling {} measurements from {} to {}",
+        count,
+        query.from,
+        query.to
+    );
+
+    let settings = state.config.read().await.config.monitoring.clone();
+    let client = MonitoringClient::build(&settings.tls, &settings.http)
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .with_auth(settings.auth)
+        .with_compression(settings.compression);
+    tokio::spawn(run_backfill(client, api_url, spectrometer_id, measurements));
+
+    Ok(Json(BackfillResponse {
+        status: "started".to_string(),
+        measurements: count,
+        truncated,
+        from: query.from,
+        to: query.to,
+    }))
+}
+
+/// Push measurements one by one, paced by `BACKFILL_PUSH_INTERVAL`, each
+/// with the wavelength it was taken at
+async fn run_backfill(
+    client: MonitoringClient,
+    api_url: String,
+    spectrometer_id: String,
+    measurements: Vec<ProcessedMeasurement>,
+) {
+    let mut failed = 0usize;
+
+    for measurement in &measurements {
+        let result = client
+            .post_backfill_data(
+                &api_url,
+                &spectrometer_id,
+                &[measurement.calibrated_reading],
+                measurement.wavelength.as_ref().map(std::slice::from_ref),
+                ReadingDetails {
+                    statistics: measurement.statistics.as_ref().map(std::slice::from_ref),
+                    series: None,
+                    thickness: measurement.thickness.as_ref().map(std::slice::from_ref),
+                },
+                measurement.timestamp,
+            )
+            .await;
+
+        if let Err(e) = result {
+            failed += 1;
+            tracing::warn!("Backfill push failed for {}: {e}", measurement.timestamp);
+        }
+
+        tokio::time::sleep(BACKFILL_PUSH_INTERVAL).await;
+    }
+
+    tracing::info!(
+        "Backfill finished: {} pushed, {} failed",
+        measurements.len() - failed,
+        failed
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::Router;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use chrono::{DateTime, Duration as ChronoDuration, Utc};
+
+    use super::*;
+    use crate::protocol::{MeasurementCycle, SeriesData};
+    use crate::storage::{MeasurementArchive, RunTags};
+
+    type Received = Arc<Mutex<Vec<serde_json::Value>>>;
+
+    /// Local monitoring API recording the body of every spectral data push
+    async fn mock_monitoring() -> (String, Received) {
+        let received: Received = Arc::default();
+        let recorded = received.clone();
+        let app = Router::new().route(
+            "/spectrometers/{id}/data",
+            post(move |Json(body): Json<serde_json::Value>| async move {
+                recorded.lock().unwrap().push(body);
+                StatusCode::OK
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (api_url, received)
+    }
+
+    /// The pushes received once `count` arrived, or after two seconds
+    async fn pushes(received: &Received, count: usize) -> Vec<serde_json::Value> {
+        for _ in 0..100 {
+            if received.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        received.lock().unwrap().clone()
+    }
+
+    async fn register(state: &AppState, api_url: String) {
+        let mut device = state.device.write().await;
+        device.monitoring_api_url = Some(api_url);
+        device.spectrometer_id = Some("spec-1".to_string());
+        device.control_wavelength = 550.0;
+    }
+
+    /// Measurement taken at 632.8 nm
+    fn measurement_at(timestamp: DateTime<Utc>) -> ProcessedMeasurement {
+        ProcessedMeasurement {
+            wavelength: Some(632.8),
+            ..ProcessedMeasurement::new(timestamp, 100.0, 1000.0, 550.0, 50.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_requires_registration() {
+        let (state, _dir) = AppState::for_test();
+        let now = Utc::now();
+        let query = BackfillQuery {
+            from: now - ChronoDuration::minutes(5),
+            to: now,
+        };
+
+        let err = backfill(State(state), Query(query)).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_rejects_inverted_range() {
+        let (state, _dir) = AppState::for_test();
+        let now = Utc::now();
+        let query = BackfillQuery {
+            from: now,
+            to: now - ChronoDuration::minutes(5),
+        };
+
+        let err = backfill(State(state), Query(query)).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_pushes_measurements_in_range() {
+        let (state, _dir) = AppState::for_test();
+        let (api_url, received) = mock_monitoring().await;
+        register(&state, api_url).await;
+
+        let now = Utc::now();
+        {
+            let mut history = state.history.write().await;
+            for i in 0..5 {
+                history.push(measurement_at(now - ChronoDuration::seconds(i)));
+            }
+        }
+
+        let query = BackfillQuery {
+            from: now - ChronoDuration::seconds(2),
+            to: now,
+        };
+        let response = backfill(State(state), Query(query)).await.unwrap();
+        assert_eq!(response.status, "started");
+        assert_eq!(response.measurements, 3);
+
+        let pushes = pushes(&received, 3).await;
+        assert_eq!(pushes.len(), 3);
+        for push in pushes {
+            assert_eq!(push["backfill"], true);
+            // Taken at, not the control wavelength of today
+            assert_eq!(push["wavelengths"], serde_json::json!([632.8]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_reads_archive() {
+        let (mut state, dir) = AppState::for_test();
+        let (api_url, received) = mock_monitoring().await;
+        register(&state, api_url).await;
+
+        let archive = MeasurementArchive::open(&dir.path().join("archive.db"), false).unwrap();
+        let now = Utc::now();
+        for i in 0..2 {
+            let timestamp = now - ChronoDuration::seconds(i);
+            let cycle = MeasurementCycle::with_timestamp(
+                timestamp,
+                SeriesData::new(vec![100]),
+                SeriesData::new(vec![1000]),
+                SeriesData::new(vec![550]),
+            );
+            archive
+                .append(&measurement_at(timestamp), &RunTags::default(), &cycle)
+                .unwrap();
+        }
+        state.archive = Some(Arc::new(archive));
+
+        let query = BackfillQuery {
+            from: now - ChronoDuration::minutes(1),
+            to: now,
+        };
+        let response = backfill(State(state), Query(query)).await.unwrap();
+        assert_eq!(response.measurements, 2);
+
+        let pushes = pushes(&received, 2).await;
+        assert_eq!(pushes.len(), 2);
+        assert_eq!(pushes[0]["wavelengths"], serde_json::json!([632.8]));
+    }
+}
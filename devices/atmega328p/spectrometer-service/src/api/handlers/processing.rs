@@ -0,0 +1,378 @@
+use axum::Json;
+use axum::extract::State;
+use chrono::Utc;
+
+use crate::api::ApiError;
+use crate::api::models::*;
+use crate::processing::calibration::mean;
+use crate::processing::outlier::stats::SeriesTotals;
+use crate::protocol::ProcessedMeasurement;
+use crate::service::events::ServiceEvent;
+use crate::service::state::{AppState, DeviceState};
+
+/// Measurements a reference capture averages by default
+const DEFAULT_CAPTURE_CYCLES: usize = 10;
+
+/// GET /processing/dark - Rolling dark estimate and compensation settings
+pub async fn get_dark_estimate(State(state): State<AppState>) -> Json<DarkEstimateResponse> {
+    let settings = {
+        let cfg = state.config.read().await;
+        cfg.config.processing.dark_compensation
+    };
+    let rolling = state.device.read().await.dark_estimate;
+
+    Json(DarkEstimateResponse {
+        enabled: settings.enabled,
+        smoothing: settings.smoothing,
+        blend: settings.blend,
+        estimate: rolling.estimate(),
+        cycles: rolling.cycles(),
+    })
+}
+
+/// GET /processing/thickness - Estimated thickness of the open layer
+pub async fn get_thickness(State(state): State<AppState>) -> Json<ThicknessResponse> {
+    let (settings, control) = {
+        let cfg = state.config.read().await;
+        (cfg.config.processing.thickness, cfg.config.control.clone())
+    };
+    let device = state.device.read().await;
+    let refractive_index = control
+        .material(&device.current_material)
+        .and_then(|material| material.refractive_index);
+
+    Json(ThicknessResponse {
+        enabled: settings.enabled,
+        min_swing: settings.min_swing,
+        control_wavelength: device.control_wavelength,
+        estimate: device
+            .thickness
+            .estimate(device.control_wavelength, refractive_index),
+    })
+}
+
+/// GET /processing/stats - Outlier exclusion counts and rates
+pub async fn get_outlier_stats(State(state): State<AppState>) -> Json<OutlierStatsResponse> {
+    let method = state.outlier_excluder.read().unwrap().name().to_string();
+    let stats = state.device.read().await.outlier_stats.clone();
+
+    Json(OutlierStatsResponse {
+        method,
+        cycles: stats.cycles(),
+        cycles_with_exclusions: stats.cycles_with_exclusions(),
+        dark: series_response(stats.dark()),
+        full: series_response(stats.full()),
+        sample: series_response(stats.sample()),
+        recent_cycles: stats.recent_cycles(),
+        recent_rate: stats.recent_rate(),
+    })
+}
+
+/// POST /processing/pause - Stop counting measurements towards the run and
+/// pushing them to the sinks, whatever the chamber state, e.g. during
+/// chamber maintenance. Cycles are still processed, archived and shown.
+pub async fn pause(State(state): State<AppState>) -> Json<ProcessingPauseResponse> {
+    set_paused(&state, true).await
+}
+
+/// POST /processing/resume - Undo `POST /processing/pause`
+pub async fn resume(State(state): State<AppState>) -> Json<ProcessingPauseResponse> {
+    set_paused(&state, false).await
+}
+
+async fn set_paused(state: &AppState, paused: bool) -> Json<ProcessingPauseResponse> {
+    let mut device = state.device.write().await;
+    if device.processing_paused.is_some() != paused {
+        let at = Utc::now();
+        device.processing_paused = paused.then_some(at);
+        state.metrics.set_processing_paused(paused);
+        if paused {
+            tracing::info!("Processing paused");
+            state.events.publish(ServiceEvent::ProcessingPaused { at });
+        } else {
+            tracing::info!("Processing resumed");
+            state.events.publish(ServiceEvent::ProcessingResumed { at });
+        }
+    }
+
+    Json(ProcessingPauseResponse {
+        paused,
+        paused_since: device.processing_paused,
+    })
+}
+
+/// GET /processing/references - Stored dark and full references
+pub async fn get_references(State(state): State<AppState>) -> Json<ReferencesResponse> {
+    let (settings, gain) = {
+        let cfg = state.config.read().await;
+        (
+            cfg.config.processing.references.clone(),
+            cfg.config.device_settings.gain,
+        )
+    };
+    let device = state.device.read().await;
+    let gain = current_gain(&device, gain);
+
+    Json(ReferencesResponse {
+        mode: settings.mode,
+        file: settings.file,
+        entries: device.references.entries.clone(),
+        current: device.references.lookup(gain, device.control_wavelength),
+    })
+}
+
+/// POST /processing/references/capture - Average the most recent
+/// measurements into a reference for the current gain and save it to the
+/// reference file
+pub async fn capture_reference(
+    State(state): State<AppState>,
+    Json(request): Json<ReferenceCaptureRequest>,
+) -> Result<Json<ReferenceCaptureResponse>, ApiError> {
+    let (settings, gain) = {
+        let cfg = state.config.read().await;
+        (
+            cfg.config.processing.references.clone(),
+            cfg.config.device_settings.gain,
+        )
+    };
+    let Some(file) = &settings.file else {
+        return Err(ApiError::bad_request(
+            "No reference file configured ([processing.references] file)",
+        ));
+    };
+    if request.target == ReferenceTarget::Both && settings.stored() {
+        return Err(ApiError::bad_request(
+            "Cycles carry no dark and full levels of their own in stored mode; \
+             capture \"dark\" and \"full\" separately",
+        ));
+    }
+    let cycles = request.cycles.unwrap_or(DEFAULT_CAPTURE_CYCLES);
+    if cycles == 0 {
+        return Err(ApiError::bad_request("'cycles' must be at least 1"));
+    }
+
+    let measurements = state.history.read().await.latest(cycles);
+    if measurements.is_empty() {
+        return Err(ApiError::conflict("No measurements to capture from yet"));
+    }
+    let level =
+        |f: fn(&ProcessedMeasurement) -> f64| mean(&measurements.iter().map(f).collect::<Vec<_>>());
+    let (dark, full) = match request.target {
+        ReferenceTarget::Both => (Some(level(|m| m.dark_mean)), Some(level(|m| m.full_mean))),
+        ReferenceTarget::Dark => (Some(level(|m| m.sample_mean)), None),
+        ReferenceTarget::Full => (None, Some(level(|m| m.sample_mean))),
+    };
+
+    let mut device = state.device.write().await;
+    let gain = current_gain(&device, gain);
+    let mut references = device.references.clone();
+    let entry = references
+        .record(gain, request.wavelength, dark, full, Utc::now())
+        .clone();
+    references
+        .save(file)
+        .map_err(|e| ApiError::internal(format!("Failed to save references: {e}")))?;
+    device.references = references;
+    tracing::info!(
+        "Captured {:?} reference at gain {} from {} measurements",
+        request.target,
+        gain,
+        measurements.len()
+    );
+
+    Ok(Json(ReferenceCaptureResponse {
+        cycles: measurements.len(),
+        entry,
+    }))
+}
+
+/// Gain the data source runs with, else the configured one
+fn current_gain(device: &DeviceState, configured: u8) -> u8 {
+    device
+        .data_source
+        .as_ref()
+        .and_then(|source| source.adc)
+        .map_or(configured, |(gain, _, _)| gain)
+}
+
+fn series_response(totals: SeriesTotals) -> SeriesExclusionResponse {
+    SeriesExclusionResponse {
+        values: totals.values,
+        excluded: totals.excluded,
+        rate: totals.rate(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::processing::references::{ReferenceTable, StoredReference};
+    use crate::protocol::{Material, OutlierExclusion, SeriesExclusion};
+
+    #[tokio::test]
+    async fn test_dark_estimate_before_first_cycle() {
+        let (state, _dir) = AppState::for_test();
+        let response = get_dark_estimate(State(state)).await;
+        assert!(!response.enabled);
+        assert_eq!(response.estimate, None);
+        assert_eq!(response.cycles, 0);
+    }
+
+    #[tokio::test]
+    async fn test_dark_estimate_reports_state() {
+        let (state, _dir) = AppState::for_test();
+        {
+            let mut cfg = state.config.write().await;
+            cfg.config.processing.dark_compensation.enabled = true;
+        }
+        state.device.write().await.dark_estimate.update(1234.0, 0.1);
+
+        let response = get_dark_estimate(State(state)).await;
+        assert!(response.enabled);
+        assert_eq!(response.estimate, Some(1234.0));
+        assert_eq!(response.cycles, 1);
+    }
+
+    #[tokio::test]
+    async fn test_thickness_uses_material_index() {
+        let (state, _dir) = AppState::for_test();
+        let response = get_thickness(State(state.clone())).await;
+        assert!(!response.enabled);
+        assert!(response.estimate.is_none());
+
+        state.config.write().await.config.control.materials = vec![Material {
+            refractive_index: Some(2.0),
+            ..Material::symbol("H")
+        }];
+        {
+            let mut device = state.device.write().await;
+            let start = Utc::now();
+            // Falls from 80 % to 40 % and back: one turning point
+            for reading in [80.0, 60.0, 40.0, 60.0] {
+                device.thickness.record(1, start, reading, 1.0);
+            }
+        }
+        let estimate = get_thickness(State(state)).await.estimate.unwrap();
+        assert_eq!(estimate.turning_points, 1);
+        // A quarter wave at 550 nm
+        assert_eq!(estimate.optical_nm, 137.5);
+        assert_eq!(estimate.physical_nm, Some(68.75));
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        use crate::service::metrics::PROCESSING_PAUSED;
+
+        let (state, _dir) = AppState::for_test();
+        let mut events = state.events.subscribe();
+
+        let response = pause(State(state.clone())).await;
+        assert!(response.paused);
+        let since = response.paused_since.unwrap();
+        // Pausing again keeps the original time and publishes nothing
+        let response = pause(State(state.clone())).await;
+        assert_eq!(response.paused_since, Some(since));
+        assert_eq!(
+            state.metrics.registry().get(PROCESSING_PAUSED, &[]),
+            Some(1.0)
+        );
+
+        let response = resume(State(state.clone())).await;
+        assert!(!response.paused);
+        assert_eq!(response.paused_since, None);
+        assert_eq!(state.device.read().await.processing_paused, None);
+        assert_eq!(
+            state.metrics.registry().get(PROCESSING_PAUSED, &[]),
+            Some(0.0)
+        );
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::ProcessingPaused { .. }
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::ProcessingResumed { .. }
+        ));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_outlier_stats() {
+        let (state, _dir) = AppState::for_test();
+        let response = get_outlier_stats(State(state.clone())).await;
+        assert_eq!(response.method, "Grubbs");
+        assert_eq!(response.cycles, 0);
+        assert_eq!(response.recent_rate, None);
+
+        let series = |excluded| SeriesExclusion { total: 5, excluded };
+        state
+            .device
+            .write()
+            .await
+            .outlier_stats
+            .record(&OutlierExclusion {
+                dark: series(1),
+                full: series(0),
+                sample: series(0),
+            });
+
+        let response = get_outlier_stats(State(state)).await;
+        assert_eq!(response.cycles, 1);
+        assert_eq!(response.cycles_with_exclusions, 1);
+        assert_eq!(response.dark.excluded, 1);
+        assert_eq!(response.dark.rate, Some(0.2));
+        assert_eq!(response.full.rate, Some(0.0));
+        assert_eq!(response.recent_cycles, 1);
+    }
+
+    #[tokio::test]
+    async fn test_capture_reference() {
+        let (state, dir) = AppState::for_test();
+        let request = || ReferenceCaptureRequest {
+            target: ReferenceTarget::Both,
+            cycles: Some(2),
+            wavelength: None,
+        };
+        let error = capture_reference(State(state.clone()), Json(request()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+
+        let file = dir.path().join("references.toml");
+        state.config.write().await.config.processing.references.file = Some(file.clone());
+        let error = capture_reference(State(state.clone()), Json(request()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+
+        {
+            let mut history = state.history.write().await;
+            for (dark, full) in [(1e6, 100.0), (14e6, 300.0), (12e6, 200.0)] {
+                history.push(ProcessedMeasurement::new(Utc::now(), dark, full, 7e6, 50.0));
+            }
+        }
+        let response = capture_reference(State(state.clone()), Json(request()))
+            .await
+            .unwrap();
+        assert_eq!(response.cycles, 2);
+        assert_eq!(response.entry.dark, Some(13e6));
+        assert_eq!(response.entry.full, Some(250.0));
+
+        let saved = ReferenceTable::load(&file).unwrap();
+        assert_eq!(saved, state.device.read().await.references);
+        let gain = state.config.read().await.config.device_settings.gain;
+        let response = get_references(State(state)).await;
+        assert_eq!(response.entries.len(), 1);
+        assert_eq!(response.entries[0].gain, Some(gain));
+        assert_eq!(
+            response.current,
+            Some(StoredReference {
+                dark: 13e6,
+                full: 250.0
+            })
+        );
+    }
+}
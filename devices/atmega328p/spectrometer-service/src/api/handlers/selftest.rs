@@ -0,0 +1,32 @@
+use std::time::Instant;
+
+use axum::Json;
+use axum::extract::State;
+use chrono::Utc;
+
+use crate::api::models::*;
+use crate::service::selftest::{CheckStatus, SelfTest};
+use crate::service::state::AppState;
+
+/// POST /selftest - Run the operator self-test and report each check
+pub async fn run_selftest(State(state): State<AppState>) -> Json<SelfTestResponse> {
+    let started_at = Utc::now();
+    let start = Instant::now();
+
+    tracing::info!("Running self-test");
+    let checks = SelfTest::default().run(&state).await;
+
+    let passed = checks.iter().all(|c| c.status != CheckStatus::Fail);
+    if passed {
+        tracing::info!("Self-test passed");
+    } else {
+        tracing::warn!("Self-test failed: {:?}", checks);
+    }
+
+    Json(SelfTestResponse {
+        passed,
+        started_at,
+        duration_ms: start.elapsed().as_millis() as u64,
+        checks,
+    })
+}
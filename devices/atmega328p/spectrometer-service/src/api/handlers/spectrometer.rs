@@ -1,7 +1,10 @@
 use axum::Json;
 use axum::extract::State;
+use chrono::Utc;
 
+use crate::api::ApiError;
 use crate::api::models::*;
+use crate::service::events::ServiceEvent;
 use crate::service::state::AppState;
 
 /// GET /control_wavelength - Get current control wavelength
@@ -19,56 +22,86 @@ pub async fn get_control_wavelength(
 pub async fn set_control_wavelength(
     State(state): State<AppState>,
     Json(request): Json<ControlWavelengthRequest>,
-) -> Json<ControlWavelengthResponse> {
+) -> Result<Json<ControlWavelengthResponse>, ApiError> {
+    state
+        .config
+        .read()
+        .await
+        .config
+        .control
+        .check_wavelength(request.wavelength)
+        .map_err(ApiError::bad_request)?;
+
     let mut device = state.device.write().await;
 
-    device.control_wavelength = request.wavelength;
+    let previous = std::mem::replace(&mut device.control_wavelength, request.wavelength);
 
     tracing::info!("Control wavelength set to {} nm", request.wavelength);
 
-    Json(ControlWavelengthResponse {
+    if previous != request.wavelength {
+        state
+            .events
+            .publish(ServiceEvent::ControlWavelengthChanged {
+                at: Utc::now(),
+                previous,
+                wavelength: request.wavelength,
+                material: None,
+            });
+    }
+
+    Ok(Json(ControlWavelengthResponse {
         control_wavelength: device.control_wavelength,
-    })
+    }))
 }
 
 #[cfg(test)]
 mod tests {
-
-    use tokio::sync::{broadcast, mpsc};
+    use axum::http::StatusCode;
 
     use super::*;
-    use crate::service::calibration::create_shared_config;
-    use crate::service::state::create_shared_state;
-
-    fn test_state() -> (AppState, tempfile::TempDir) {
-        let dir = tempfile::tempdir().unwrap();
-        let (tx, _) = broadcast::channel(16);
-        let (cmd_tx, _) = mpsc::channel(16);
-        let state = AppState {
-            device: create_shared_state(),
-            config: create_shared_config(dir.path().join("cfg.toml")),
-            broadcast_tx: tx,
-            device_cmd_tx: cmd_tx,
-        };
-        (state, dir)
-    }
 
     #[tokio::test]
     async fn test_get_control_wavelength() {
-        let (state, _dir) = test_state();
+        let (state, _dir) = AppState::for_test();
         let response = get_control_wavelength(State(state)).await;
         assert_eq!(response.control_wavelength, 550.0);
     }
 
     #[tokio::test]
     async fn test_set_control_wavelength() {
-        let (state, _dir) = test_state();
+        let (state, _dir) = AppState::for_test();
+        let mut events = state.events.subscribe();
 
         let request = ControlWavelengthRequest { wavelength: 600.0 };
-        let response = set_control_wavelength(State(state.clone()), Json(request)).await;
+        let response = set_control_wavelength(State(state.clone()), Json(request))
+            .await
+            .unwrap();
         assert_eq!(response.control_wavelength, 600.0);
 
         let device = state.device.read().await;
         assert_eq!(device.control_wavelength, 600.0);
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            ServiceEvent::ControlWavelengthChanged {
+                previous: 550.0,
+                wavelength: 600.0,
+                material: None,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_control_wavelength_out_of_range() {
+        let (state, _dir) = AppState::for_test();
+
+        for wavelength in [-600.0, 5000.0] {
+            let request = ControlWavelengthRequest { wavelength };
+            let error = set_control_wavelength(State(state.clone()), Json(request))
+                .await
+                .unwrap_err();
+            assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(state.device.read().await.control_wavelength, 550.0);
     }
 }
@@ -1,151 +1,864 @@
 use axum::Json;
-use axum::extract::State;
+use axum::extract::{Query, State};
+use chrono::Utc;
 
+use crate::api::ApiError;
 use crate::api::models::*;
+use crate::protocol::ShutterState;
+use crate::service::chamber::{ChamberState, ShutterSource};
+use crate::service::events::ServiceEvent;
 use crate::service::state::AppState;
 
 /// GET /vacuum_chamber/material - Get current material
 pub async fn get_material(State(state): State<AppState>) -> Json<MaterialResponse> {
-    let device = state.device.read().await;
+    let material = state.device.read().await.current_material.clone();
+    let info = state
+        .config
+        .read()
+        .await
+        .config
+        .control
+        .material(&material)
+        .cloned();
 
     Json(MaterialResponse {
-        material: device.current_material.clone(),
+        material,
+        info,
+        control_wavelength: None,
     })
 }
 
-/// POST /vacuum_chamber/material - Set material
-pub async fn set_material(State(state): State<AppState>, body: String) -> Json<MaterialResponse> {
-    let mut device = state.device.write().await;
+/// GET /vacuum_chamber/materials - Material catalogue from `control.materials`
+pub async fn get_materials(State(state): State<AppState>) -> Json<MaterialsResponse> {
+    let current = state.device.read().await.current_material.clone();
+    let materials = state.config.read().await.config.control.materials.clone();
+
+    Json(MaterialsResponse { current, materials })
+}
 
+/// POST /vacuum_chamber/material?keep_wavelength= - Set material, switching
+/// to its control wavelength preset unless `keep_wavelength` is set
+pub async fn set_material(
+    State(state): State<AppState>,
+    Query(query): Query<SetMaterialQuery>,
+    body: String,
+) -> Result<Json<MaterialResponse>, ApiError> {
     let material = body.trim().trim_matches('"').to_string();
-    device.current_material = material.clone();
+    let info = {
+        let cfg = state.config.read().await;
+        cfg.config
+            .control
+            .check_material(&material)
+            .map_err(ApiError::unprocessable)?;
+        cfg.config.control.material(&material).cloned()
+    };
+
+    let mut device = state.device.write().await;
+    let previous = std::mem::replace(&mut device.current_material, material.clone());
+
+    // A material change during deposition starts the next layer
+    if device.chamber.is_depositing() && previous != material {
+        device.layers.next_layer(&material, Utc::now());
+        tracing::info!("Layer {} started ({})", device.layer(), material);
+    }
 
     tracing::info!("Material set to {}", material);
 
-    Json(MaterialResponse { material })
+    state.events.publish(ServiceEvent::MaterialChanged {
+        previous,
+        material: material.clone(),
+        at: Utc::now(),
+    });
+
+    let preset = info
+        .as_ref()
+        .and_then(|info| info.control_wavelength)
+        .filter(|_| !query.keep_wavelength);
+    if let Some(wavelength) = preset {
+        let previous = std::mem::replace(&mut device.control_wavelength, wavelength);
+        if previous != wavelength {
+            tracing::info!("Control wavelength set to {wavelength} nm for {material}");
+            state
+                .events
+                .publish(ServiceEvent::ControlWavelengthChanged {
+                    at: Utc::now(),
+                    previous,
+                    wavelength,
+                    material: Some(material.clone()),
+                });
+        }
+    }
+
+    Ok(Json(MaterialResponse {
+        material,
+        info,
+        control_wavelength: preset,
+    }))
 }
 
-/// POST /vacuum_chamber/start - Start deposition
-pub async fn start_deposition(State(state): State<AppState>) -> Json<DepositionResponse> {
-    let mut device = state.device.write().await;
+/// POST /vacuum_chamber/prepare - Enter preconditioning before deposition
+pub async fn prepare_deposition(
+    State(state): State<AppState>,
+) -> Result<Json<DepositionResponse>, ApiError> {
+    transition(&state, ChamberState::Preconditioning).await?;
+
+    tracing::info!("Chamber preconditioning");
 
-    device.is_depositing = true;
-    device.is_running = true;
+    Ok(Json(DepositionResponse {
+        status: ChamberState::Preconditioning.to_string(),
+    }))
+}
+
+/// POST /vacuum_chamber/start - Start deposition
+pub async fn start_deposition(
+    State(state): State<AppState>,
+) -> Result<Json<DepositionResponse>, ApiError> {
+    let previous = transition(&state, ChamberState::Depositing).await?;
+
+    // Resuming a paused run keeps the layer; anything else starts a new run
+    if !matches!(previous, ChamberState::Paused | ChamberState::Depositing) {
+        let mut device = state.device.write().await;
+        let material = device.current_material.clone();
+        device.layers.start_run(&material, Utc::now());
+        device.run_id = Some(Utc::now().format("%Y%m%dT%H%M%SZ").to_string());
+    }
 
     tracing::info!("Deposition started");
+    state
+        .events
+        .publish(ServiceEvent::DepositionStarted { at: Utc::now() });
 
-    Json(DepositionResponse {
+    Ok(Json(DepositionResponse {
         status: "running".to_string(),
-    })
+    }))
+}
+
+/// POST /vacuum_chamber/pause - Pause deposition, e.g. while the shutter is
+/// closed; `/vacuum_chamber/resume` continues the same run and layer
+pub async fn pause_deposition(
+    State(state): State<AppState>,
+) -> Result<Json<DepositionResponse>, ApiError> {
+    if transition(&state, ChamberState::Paused).await? != ChamberState::Paused {
+        tracing::info!("Deposition paused");
+        state
+            .events
+            .publish(ServiceEvent::DepositionPaused { at: Utc::now() });
+    }
+
+    Ok(Json(DepositionResponse {
+        status: ChamberState::Paused.to_string(),
+    }))
+}
+
+/// POST /vacuum_chamber/resume - Continue a paused deposition. Unlike
+/// `/vacuum_chamber/start` it never begins a new run.
+pub async fn resume_deposition(
+    State(state): State<AppState>,
+) -> Result<Json<DepositionResponse>, ApiError> {
+    let mut device = state.device.write().await;
+    match device.chamber.state() {
+        ChamberState::Paused => {
+            device.chamber.transition_to(ChamberState::Depositing)?;
+            tracing::info!("Deposition resumed");
+            state
+                .events
+                .publish(ServiceEvent::DepositionResumed { at: Utc::now() });
+        }
+        // Already running, nothing to resume
+        ChamberState::Depositing => {}
+        current => {
+            return Err(ApiError::conflict(format!(
+                "Cannot resume deposition: chamber is {current}, not paused"
+            )));
+        }
+    }
+
+    Ok(Json(DepositionResponse {
+        status: "running".to_string(),
+    }))
+}
+
+/// POST /vacuum_chamber/fault - Abort the current run into `error`, e.g.
+/// when the chamber controller detects a fault. `/vacuum_chamber/stop`
+/// leaves the error.
+pub async fn report_fault(
+    State(state): State<AppState>,
+    Json(request): Json<ChamberFaultRequest>,
+) -> Result<Json<DepositionResponse>, ApiError> {
+    let reason = request.reason.trim().to_string();
+    if reason.is_empty() {
+        return Err(ApiError::bad_request("'reason' must not be empty"));
+    }
+
+    state
+        .device
+        .write()
+        .await
+        .chamber
+        .fail(&reason)
+        .map_err(|e| {
+            tracing::warn!("{e}");
+            ApiError::from(e)
+        })?;
+
+    tracing::error!("Deposition aborted: {reason}");
+    state.events.publish(ServiceEvent::ChamberFault {
+        at: Utc::now(),
+        reason,
+    });
+
+    Ok(Json(DepositionResponse {
+        status: ChamberState::Error.to_string(),
+    }))
 }
 
 /// POST /vacuum_chamber/stop - Stop deposition
-pub async fn stop_deposition(State(state): State<AppState>) -> Json<DepositionResponse> {
+pub async fn stop_deposition(
+    State(state): State<AppState>,
+) -> Result<Json<DepositionResponse>, ApiError> {
     let mut device = state.device.write().await;
+    if device.chamber.state() == ChamberState::Idle {
+        return Err(ApiError::conflict("No deposition run to stop"));
+    }
 
-    device.is_depositing = false;
-    device.is_running = false;
+    device.stop_run(Utc::now()).map_err(|e| {
+        tracing::warn!("{e}");
+        ApiError::from(e)
+    })?;
+    drop(device);
 
     tracing::info!("Deposition stopped");
+    state
+        .events
+        .publish(ServiceEvent::DepositionStopped { at: Utc::now() });
 
-    Json(DepositionResponse {
+    Ok(Json(DepositionResponse {
         status: "stopped".to_string(),
-    })
+    }))
 }
 
 /// GET /vacuum_chamber/status - Get chamber status
 pub async fn get_status(State(state): State<AppState>) -> Json<VacuumChamberStatusResponse> {
     let device = state.device.read().await;
+    let chamber = &device.chamber;
 
     Json(VacuumChamberStatusResponse {
-        status: if device.is_depositing {
+        status: if chamber.is_depositing() {
             "running".to_string()
         } else {
             "stopped".to_string()
         },
-        is_depositing: device.is_depositing,
+        is_depositing: chamber.is_depositing(),
+        state: chamber.state(),
+        state_since: chamber.state_since(),
+        state_entered_at: chamber.entered_at().clone(),
+        transitions: chamber.transitions().cloned().collect(),
+        layer: device.layer(),
+        run_id: device.run_id.clone(),
+        processing_paused: device.processing_paused.is_some(),
+        fault: chamber.fault().map(str::to_string),
+    })
+}
+
+/// GET /vacuum_chamber/shutter - Shutter position and the validity of the
+/// measurements taken in each position
+pub async fn get_shutter(State(state): State<AppState>) -> Json<ShutterResponse> {
+    shutter_response(&state).await
+}
+
+/// POST /vacuum_chamber/shutter - Open or close the shutter with the
+/// firmware's `SHUTTER=OPEN` / `SHUTTER=CLOSED` command
+pub async fn set_shutter(
+    State(state): State<AppState>,
+    Json(request): Json<ShutterRequest>,
+) -> Result<Json<ShutterResponse>, ApiError> {
+    let command = match request.state {
+        ShutterState::Open => "SHUTTER=OPEN",
+        ShutterState::Closed => "SHUTTER=CLOSED",
+    };
+    state.send_raw_command(command).await.map_err(|e| {
+        tracing::warn!("Shutter command '{command}' failed: {e}");
+        ApiError::conflict(e)
+    })?;
+
+    let at = Utc::now();
+    let moved = state
+        .device
+        .write()
+        .await
+        .shutter
+        .set(request.state, ShutterSource::Command, at);
+    if moved {
+        tracing::info!("Shutter {command}");
+        state.events.publish(ServiceEvent::ShutterChanged {
+            at,
+            state: request.state,
+            source: ShutterSource::Command,
+        });
+    }
+
+    Ok(shutter_response(&state).await)
+}
+
+async fn shutter_response(state: &AppState) -> Json<ShutterResponse> {
+    let device = state.device.read().await;
+    let shutter = &device.shutter;
+    let (open, closed) = shutter.counts();
+
+    Json(ShutterResponse {
+        state: shutter.state(),
+        since: shutter.since(),
+        source: shutter.source(),
+        open,
+        closed,
+    })
+}
+
+/// GET /vacuum_chamber/history?since= - Chamber state transitions, oldest
+/// first (the last 100)
+pub async fn get_history(
+    State(state): State<AppState>,
+    Query(query): Query<ChamberHistoryQuery>,
+) -> Json<ChamberHistoryResponse> {
+    let device = state.device.read().await;
+    let chamber = &device.chamber;
+
+    Json(ChamberHistoryResponse {
+        state: chamber.state(),
+        state_since: chamber.state_since(),
+        transitions: chamber.transitions_since(query.since),
+    })
+}
+
+/// GET /vacuum_chamber/layers - Layer boundaries of the current or last run
+pub async fn get_layers(State(state): State<AppState>) -> Json<LayersResponse> {
+    let device = state.device.read().await;
+
+    Json(LayersResponse {
+        run_id: device.run_id.clone(),
+        layers: device.layers.records().to_vec(),
+    })
+}
+
+/// Apply a chamber transition, mapping rejected transitions to 409.
+/// Returns the state the chamber left.
+async fn transition(state: &AppState, to: ChamberState) -> Result<ChamberState, ApiError> {
+    let mut device = state.device.write().await;
+    let from = device.chamber.state();
+
+    device.chamber.transition_to(to).map(|_| from).map_err(|e| {
+        tracing::warn!("{e}");
+        ApiError::from(e)
     })
 }
 
 #[cfg(test)]
 mod tests {
-
-    use tokio::sync::{broadcast, mpsc};
+    use axum::http::StatusCode;
+    use tokio::sync::mpsc;
 
     use super::*;
-    use crate::service::calibration::create_shared_config;
-    use crate::service::state::create_shared_state;
-
-    fn test_state() -> (AppState, tempfile::TempDir) {
-        let dir = tempfile::tempdir().unwrap();
-        let (tx, _) = broadcast::channel(16);
-        let (cmd_tx, _) = mpsc::channel(16);
-        let state = AppState {
-            device: create_shared_state(),
-            config: create_shared_config(dir.path().join("cfg.toml")),
-            broadcast_tx: tx,
-            device_cmd_tx: cmd_tx,
-        };
-        (state, dir)
-    }
+    use crate::protocol::Material;
+    use crate::service::supervisor::SourceCommand;
 
     #[tokio::test]
     async fn test_get_material() {
-        let (state, _dir) = test_state();
+        let (state, _dir) = AppState::for_test();
         let response = get_material(State(state)).await;
         assert_eq!(response.material, "H");
     }
 
+    #[tokio::test]
+    async fn test_get_materials() {
+        let (state, _dir) = AppState::for_test();
+        state.config.write().await.config.control.materials = vec![
+            Material {
+                name: Some("Ta2O5".to_string()),
+                refractive_index: Some(2.1),
+                ..Material::symbol("H")
+            },
+            Material::symbol("L"),
+        ];
+
+        let response = get_materials(State(state.clone())).await;
+        assert_eq!(response.current, "H");
+        assert_eq!(response.materials.len(), 2);
+
+        let response = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "H".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.info.as_ref().unwrap().refractive_index, Some(2.1));
+        let response = set_material(
+            State(state),
+            Query(SetMaterialQuery::default()),
+            "L".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.info, Some(Material::symbol("L")));
+    }
+
+    #[tokio::test]
+    async fn test_material_wavelength_preset() {
+        let (state, _dir) = AppState::for_test();
+        state.config.write().await.config.control.materials = vec![
+            Material {
+                control_wavelength: Some(632.8),
+                ..Material::symbol("H")
+            },
+            Material {
+                control_wavelength: Some(450.0),
+                ..Material::symbol("L")
+            },
+            Material::symbol("M"),
+        ];
+        let mut events = state.events.subscribe();
+
+        let response = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "L".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.control_wavelength, Some(450.0));
+        assert_eq!(state.device.read().await.control_wavelength, 450.0);
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            ServiceEvent::MaterialChanged { .. }
+        ));
+        match events.try_recv().unwrap() {
+            ServiceEvent::ControlWavelengthChanged {
+                previous,
+                wavelength,
+                material,
+                ..
+            } => {
+                assert_eq!((previous, wavelength), (550.0, 450.0));
+                assert_eq!(material.as_deref(), Some("L"));
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+
+        // Overridden: the wavelength stays
+        let response = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery {
+                keep_wavelength: true,
+            }),
+            "H".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.control_wavelength, None);
+        assert_eq!(state.device.read().await.control_wavelength, 450.0);
+
+        // No preset: the wavelength stays
+        let _ = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "M".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state.device.read().await.control_wavelength, 450.0);
+    }
+
     #[tokio::test]
     async fn test_set_material() {
-        let (state, _dir) = test_state();
-        let response = set_material(State(state.clone()), "L".to_string()).await;
+        let (state, _dir) = AppState::for_test();
+        let response = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "L".to_string(),
+        )
+        .await
+        .unwrap();
         assert_eq!(response.material, "L");
 
         let device = state.device.read().await;
         assert_eq!(device.current_material, "L");
     }
 
+    #[tokio::test]
+    async fn test_set_material_publishes_event() {
+        let (state, _dir) = AppState::for_test();
+        let mut events = state.events.subscribe();
+
+        let _ = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "L".to_string(),
+        )
+        .await
+        .unwrap();
+
+        match events.recv().await.unwrap() {
+            ServiceEvent::MaterialChanged {
+                previous, material, ..
+            } => {
+                assert_eq!(previous, "H");
+                assert_eq!(material, "L");
+            }
+            other => panic!("Unexpected event: {other:?}"),
+        }
+    }
+
     #[tokio::test]
     async fn test_set_material_json_string() {
-        let (state, _dir) = test_state();
-        let response = set_material(State(state.clone()), "\"H\"".to_string()).await;
+        let (state, _dir) = AppState::for_test();
+        let response = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "\"H\"".to_string(),
+        )
+        .await
+        .unwrap();
         assert_eq!(response.material, "H");
     }
 
+    #[tokio::test]
+    async fn test_set_material_unknown() {
+        let (state, _dir) = AppState::for_test();
+        let error = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "SiO2".to_string(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(state.device.read().await.current_material, "H");
+    }
+
     #[tokio::test]
     async fn test_start_stop_deposition() {
-        let (state, _dir) = test_state();
+        let (state, _dir) = AppState::for_test();
 
-        let response = start_deposition(State(state.clone())).await;
+        let response = start_deposition(State(state.clone())).await.unwrap();
         assert_eq!(response.status, "running");
         {
             let s = state.device.read().await;
-            assert!(s.is_depositing);
-            assert!(s.is_running);
+            assert_eq!(s.chamber.state(), ChamberState::Depositing);
+            assert!(s.should_process_data());
+            assert!(s.run_id.is_some());
         }
 
-        let response = stop_deposition(State(state.clone())).await;
+        let response = stop_deposition(State(state.clone())).await.unwrap();
         assert_eq!(response.status, "stopped");
         {
             let s = state.device.read().await;
-            assert!(!s.is_depositing);
-            assert!(!s.is_running);
+            assert_eq!(s.chamber.state(), ChamberState::Stopped);
+            assert!(!s.should_process_data());
+            assert!(s.run_id.is_none());
         }
     }
 
+    #[tokio::test]
+    async fn test_start_stop_publish_events() {
+        let (state, _dir) = AppState::for_test();
+        let mut events = state.events.subscribe();
+
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+        let _ = stop_deposition(State(state.clone())).await.unwrap();
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::DepositionStarted { .. }
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::DepositionStopped { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stop_when_idle_conflicts() {
+        let (state, _dir) = AppState::for_test();
+
+        let err = stop_deposition(State(state.clone())).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+
+        let s = state.device.read().await;
+        assert_eq!(s.chamber.state(), ChamberState::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_then_start() {
+        let (state, _dir) = AppState::for_test();
+
+        let response = prepare_deposition(State(state.clone())).await.unwrap();
+        assert_eq!(response.status, "preconditioning");
+
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+        let s = state.device.read().await;
+        assert_eq!(s.chamber.state(), ChamberState::Depositing);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_while_depositing_conflicts() {
+        let (state, _dir) = AppState::for_test();
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+
+        let err = prepare_deposition(State(state)).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+    }
+
     #[tokio::test]
     async fn test_get_status() {
-        let (state, _dir) = test_state();
+        let (state, _dir) = AppState::for_test();
 
         let response = get_status(State(state.clone())).await;
         assert_eq!(response.status, "stopped");
+        assert_eq!(response.state, ChamberState::Idle);
         assert!(!response.is_depositing);
 
-        let _ = start_deposition(State(state.clone())).await;
+        let _ = start_deposition(State(state.clone())).await.unwrap();
 
         let response = get_status(State(state)).await;
         assert_eq!(response.status, "running");
+        assert_eq!(response.state, ChamberState::Depositing);
         assert!(response.is_depositing);
+        assert_eq!(response.transitions.len(), 1);
+        assert!(
+            response
+                .state_entered_at
+                .contains_key(&ChamberState::Depositing)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pause_resume_and_fault() {
+        let (state, _dir) = AppState::for_test();
+        let mut events = state.events.subscribe();
+
+        // Nothing to pause before a run
+        let err = pause_deposition(State(state.clone())).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+        let run_id = state.device.read().await.run_id.clone();
+        let response = pause_deposition(State(state.clone())).await.unwrap();
+        assert_eq!(response.status, "paused");
+        assert!(!state.device.read().await.should_process_data());
+        let response = resume_deposition(State(state.clone())).await.unwrap();
+        assert_eq!(response.status, "running");
+        assert_eq!(state.device.read().await.run_id, run_id);
+        // Resuming a running deposition changes nothing
+        let _ = resume_deposition(State(state.clone())).await.unwrap();
+
+        let err = report_fault(
+            State(state.clone()),
+            Json(ChamberFaultRequest {
+                reason: " ".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        let response = report_fault(
+            State(state.clone()),
+            Json(ChamberFaultRequest {
+                reason: "pressure interlock".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status, "error");
+
+        let status = get_status(State(state.clone())).await;
+        assert_eq!(status.state, ChamberState::Error);
+        assert_eq!(status.fault.as_deref(), Some("pressure interlock"));
+        assert!(!status.is_depositing);
+        // A faulted run cannot resume, only stop
+        let err = start_deposition(State(state.clone())).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+        let _ = stop_deposition(State(state.clone())).await.unwrap();
+
+        let history = get_history(State(state), Query(ChamberHistoryQuery { since: None })).await;
+        let states: Vec<ChamberState> = history.transitions.iter().map(|t| t.to).collect();
+        assert_eq!(
+            states,
+            [
+                ChamberState::Depositing,
+                ChamberState::Paused,
+                ChamberState::Depositing,
+                ChamberState::Error,
+                ChamberState::Stopped
+            ]
+        );
+        assert_eq!(
+            history.transitions[3].reason.as_deref(),
+            Some("pressure interlock")
+        );
+
+        let kinds: Vec<&str> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|event| match event {
+                ServiceEvent::DepositionStarted { .. } => "started",
+                ServiceEvent::DepositionPaused { .. } => "paused",
+                ServiceEvent::DepositionResumed { .. } => "resumed",
+                ServiceEvent::ChamberFault { .. } => "fault",
+                ServiceEvent::DepositionStopped { .. } => "stopped",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(kinds, ["started", "paused", "resumed", "fault", "stopped"]);
+    }
+
+    #[tokio::test]
+    async fn test_set_shutter() {
+        let (mut state, _dir) = AppState::for_test();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(16);
+        tokio::spawn(async move {
+            while let Some(cmd) = cmd_rx.recv().await {
+                if let SourceCommand::Passthrough { command, reply } = cmd {
+                    let _ = reply.send(if command.starts_with("SHUTTER=") {
+                        Ok(())
+                    } else {
+                        Err("unexpected command".to_string())
+                    });
+                }
+            }
+        });
+        state.device_cmd_tx = cmd_tx;
+        let mut events = state.events.subscribe();
+
+        let response = set_shutter(
+            State(state.clone()),
+            Json(ShutterRequest {
+                state: ShutterState::Closed,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.state, Some(ShutterState::Closed));
+        assert_eq!(response.source, Some(ShutterSource::Command));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            ServiceEvent::ShutterChanged {
+                state: ShutterState::Closed,
+                ..
+            }
+        ));
+
+        state.device.write().await.shutter.record(false);
+        let response = get_shutter(State(state)).await;
+        assert_eq!(response.closed.invalid, 1);
+        assert_eq!(response.open.measurements, 0);
+    }
+
+    #[tokio::test]
+    async fn test_set_shutter_without_device() {
+        // No supervisor behind the channel: the command cannot be delivered
+        let (state, _dir) = AppState::for_test();
+        let error = set_shutter(
+            State(state.clone()),
+            Json(ShutterRequest {
+                state: ShutterState::Open,
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+        assert_eq!(state.device.read().await.shutter.state(), None);
+    }
+
+    #[tokio::test]
+    async fn test_resume_requires_pause() {
+        let (state, _dir) = AppState::for_test();
+
+        let err = resume_deposition(State(state.clone())).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            state.device.read().await.chamber.state(),
+            ChamberState::Idle
+        );
+
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+        let _ = stop_deposition(State(state.clone())).await.unwrap();
+        let err = resume_deposition(State(state.clone())).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn test_layer_numbering() {
+        let (state, _dir) = AppState::for_test();
+
+        // Material changes outside a run do not count as layers
+        let _ = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "L".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state.device.read().await.layer(), 0);
+
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+        assert_eq!(state.device.read().await.layer(), 1);
+
+        let _ = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "H".to_string(),
+        )
+        .await
+        .unwrap();
+        let _ = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "H".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state.device.read().await.layer(), 2);
+
+        state
+            .device
+            .write()
+            .await
+            .chamber
+            .transition_to(ChamberState::Paused)
+            .unwrap();
+        let _ = resume_deposition(State(state.clone())).await.unwrap();
+        assert_eq!(get_status(State(state.clone())).await.layer, 2);
+
+        let _ = stop_deposition(State(state.clone())).await.unwrap();
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+        assert_eq!(state.device.read().await.layer(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_material_change_closes_layer() {
+        let (state, _dir) = AppState::for_test();
+
+        let _ = start_deposition(State(state.clone())).await.unwrap();
+        let _ = set_material(
+            State(state.clone()),
+            Query(SetMaterialQuery::default()),
+            "L".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let response = get_layers(State(state.clone())).await;
+        assert!(response.run_id.is_some());
+        assert_eq!(response.layers.len(), 2);
+        assert_eq!(response.layers[0].material, "H");
+        assert_eq!(
+            response.layers[0].ended_at,
+            Some(response.layers[1].started_at)
+        );
+        assert_eq!(response.layers[1].material, "L");
+        assert!(response.layers[1].ended_at.is_none());
+
+        let _ = stop_deposition(State(state.clone())).await.unwrap();
+        let response = get_layers(State(state)).await;
+        assert!(response.run_id.is_none());
+        assert!(response.layers[1].ended_at.is_some());
     }
 }
@@ -1,7 +1,14 @@
+pub mod error;
+pub mod guard;
 pub mod handlers;
 pub mod models;
 pub mod routes;
+pub mod trace;
 pub mod web_ui;
 pub mod websocket;
 
-pub use routes::create_router;
+pub use error::ApiError;
+pub use routes::{create_multi_device_router, create_router};
+
+/// Current API version, the path prefix of every endpoint
+pub const API_VERSION: &str = "v1";
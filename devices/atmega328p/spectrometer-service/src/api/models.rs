@@ -1,5 +1,22 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::monitoring::DeviceCapabilities;
+use crate::processing::references::{ReferenceEntry, ReferenceMode, StoredReference};
+use crate::protocol::{
+    ConfirmedSettings, DebugBlock, DeviceEvent, FirmwareVersion, Material, ProcessedMeasurement,
+    ProtocolIssue, ShutterState, ThicknessEstimate,
+};
+use crate::service::chamber::{ChamberState, ChamberTransition, ShutterSource, ValidityCounts};
+use crate::service::diagnostics::{EndpointDelivery, ProtocolCounts, PushAttempt};
+use crate::service::history::ReadingStats;
+use crate::service::layers::LayerRecord;
+use crate::service::selftest::SelfTestCheck;
+use crate::storage::{ArchivedLayer, ArchivedMeasurement, DownsampleMethod, RunSummary};
+
 // ============= Device Endpoints =============
 
 #[derive(Debug, Serialize)]
@@ -8,14 +25,68 @@ pub struct DeviceInfoResponse {
     pub device_type: String,
     pub name: String,
     pub capabilities: DeviceCapabilities,
+    /// Reported by the firmware at startup; absent in playback and for
+    /// firmware without the `VERSION` command
+    pub firmware_version: Option<FirmwareVersion>,
+    /// ADC settings the firmware confirmed at startup; absent in playback
+    pub confirmed_settings: Option<ConfirmedSettings>,
+    /// API version served under `/{api_version}/`
+    pub api_version: String,
+    /// When OptiMonitor last registered; absent while unregistered
+    pub registered_at: Option<DateTime<Utc>>,
+    pub registration_age_secs: Option<f64>,
 }
 
+/// One device on `GET /devices`, with the type and capabilities OptiMonitor
+/// is told about when the device announces itself
 #[derive(Debug, Serialize)]
-pub struct DeviceCapabilities {
-    pub has_spectrometer: bool,
-    pub has_vacuum_chamber: bool,
-    pub spectrometer_type: String,
-    pub is_monochromatic: bool,
+pub struct DeviceListing {
+    /// Device name from the config
+    pub id: String,
+    /// Base path of the device's API, `/devices/{id}`
+    pub path: String,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    pub name: String,
+    pub capabilities: DeviceCapabilities,
+    pub chamber_state: ChamberState,
+    pub registered: bool,
+    pub spectrometer_id: Option<String>,
+    /// As on `/devices/{id}/healthz` and `/devices/{id}/readyz`
+    pub healthy: bool,
+    pub ready: bool,
+}
+
+/// Acquisition settings and data source the device currently runs with
+#[derive(Debug, Serialize)]
+pub struct DeviceSettingsResponse {
+    pub gain: u8,
+    pub fadc: f32,
+    pub count: u8,
+    /// Serial sources only
+    pub baud_rate: Option<u32>,
+    /// `serial`, `playback` or `socket`
+    pub source_type: Option<String>,
+    pub source_name: Option<String>,
+    /// Whether the device confirmed these GAIN, FADC and COUNT values
+    pub confirmed: bool,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceCommandRequest {
+    /// Single line sent as is, e.g. `VERSION`
+    pub command: String,
+    /// How long reply lines are collected, default 500 ms
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceCommandResponse {
+    pub command: String,
+    /// Lines the device wrote within the timeout, including any
+    /// measurement output
+    pub lines: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +104,12 @@ pub struct RegisterResponse {
     pub monitoring_api_url: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct UnregisterResponse {
+    /// `unregistered`, or `not_registered` when there was nothing to clear
+    pub status: String,
+}
+
 // ============= Spectrometer Endpoints =============
 
 #[derive(Debug, Deserialize)]
@@ -50,12 +127,87 @@ pub struct ControlWavelengthResponse {
 #[derive(Debug, Serialize)]
 pub struct MaterialResponse {
     pub material: String,
+    /// Catalogue entry of the material
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<Material>,
+    /// Control wavelength the material's preset switched to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control_wavelength: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SetMaterialQuery {
+    /// Leave the control wavelength as it is instead of applying the
+    /// material's preset
+    #[serde(default)]
+    pub keep_wavelength: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaterialsResponse {
+    /// Material in the chamber
+    pub current: String,
+    pub materials: Vec<Material>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct VacuumChamberStatusResponse {
     pub status: String,
     pub is_depositing: bool,
+    pub state: ChamberState,
+    pub state_since: DateTime<Utc>,
+    /// When each state was most recently entered
+    pub state_entered_at: BTreeMap<ChamberState, DateTime<Utc>>,
+    pub transitions: Vec<ChamberTransition>,
+    /// Current deposition layer number (0 before the first run)
+    pub layer: u32,
+    /// Current deposition run, as tagged in the measurement archive
+    pub run_id: Option<String>,
+    /// Whether processing is paused, so measurements are not pushed even
+    /// while depositing
+    pub processing_paused: bool,
+    /// Reason given for the fault while the chamber is in `error`
+    pub fault: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShutterRequest {
+    pub state: ShutterState,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShutterResponse {
+    /// Last commanded or reported position, None until either happens
+    pub state: Option<ShutterState>,
+    pub since: Option<DateTime<Utc>>,
+    pub source: Option<ShutterSource>,
+    /// Measurements taken while open and while closed
+    pub open: ValidityCounts,
+    pub closed: ValidityCounts,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChamberFaultRequest {
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChamberHistoryQuery {
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChamberHistoryResponse {
+    pub state: ChamberState,
+    pub state_since: DateTime<Utc>,
+    pub transitions: Vec<ChamberTransition>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LayersResponse {
+    pub run_id: Option<String>,
+    /// Layers of the current run, or of the last run once stopped
+    pub layers: Vec<LayerRecord>,
 }
 
 #[derive(Debug, Serialize)]
@@ -63,9 +215,269 @@ pub struct DepositionResponse {
     pub status: String,
 }
 
+// ============= Monitoring Endpoints =============
+
+#[derive(Debug, Deserialize)]
+pub struct BackfillQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackfillResponse {
+    pub status: String,
+    pub measurements: usize,
+    /// The archive held more measurements in the range than were pushed;
+    /// backfill the rest with a later `from`
+    pub truncated: bool,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+// ============= Debug Endpoints =============
+
+#[derive(Debug, Serialize)]
+pub struct DeviceDebugResponse {
+    pub blocks: Vec<DebugBlock>,
+}
+
+// ============= Diagnostics Endpoints =============
+
+#[derive(Debug, Serialize)]
+pub struct ProtocolDiagnosticsResponse {
+    /// Counts by data source (port name or log file)
+    pub sources: BTreeMap<String, ProtocolCounts>,
+    /// Most recent offending lines, oldest first
+    pub recent: Vec<ProtocolIssue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceEventsResponse {
+    /// Most recent firmware status lines, oldest first
+    pub events: Vec<DeviceEvent>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MonitoringDiagnosticsResponse {
+    pub registered: bool,
+    pub monitoring_api_url: Option<String>,
+    /// Whether the most recent push to the registered API (or its
+    /// failover endpoints) succeeded; unset before the first push
+    pub last_push_succeeded: Option<bool>,
+    /// Push totals by endpoint URL
+    pub endpoints: BTreeMap<String, EndpointDelivery>,
+    /// Most recent push attempts, oldest first
+    pub recent: Vec<PushAttempt>,
+}
+
+// ============= Processing Endpoints =============
+
+#[derive(Debug, Serialize)]
+pub struct ProcessingPauseResponse {
+    pub paused: bool,
+    /// When processing was paused
+    pub paused_since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DarkEstimateResponse {
+    /// Whether calibration uses the rolling estimate
+    pub enabled: bool,
+    pub smoothing: f64,
+    pub blend: f64,
+    /// Rolling dark mean, `None` before the first in-range cycle
+    pub estimate: Option<f64>,
+    /// Cycles folded in since the last reset
+    pub cycles: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThicknessResponse {
+    /// Whether `processing.thickness` estimation runs
+    pub enabled: bool,
+    pub min_swing: f64,
+    /// Wavelength the estimate is in quarter waves of, in nm
+    pub control_wavelength: f64,
+    /// Estimate for the open (or last) layer, `None` before its first
+    /// valid measurement
+    pub estimate: Option<ThicknessEstimate>,
+}
+
+/// Outlier exclusion totals for one series
+#[derive(Debug, Serialize)]
+pub struct SeriesExclusionResponse {
+    /// Raw values checked
+    pub values: u64,
+    /// Values excluded as outliers
+    pub excluded: u64,
+    /// Fraction of values excluded, `None` before the first cycle
+    pub rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutlierStatsResponse {
+    /// Active outlier exclusion method
+    pub method: String,
+    /// Cycles that went through outlier exclusion since startup
+    pub cycles: u64,
+    /// Cycles in which at least one value was excluded
+    pub cycles_with_exclusions: u64,
+    pub dark: SeriesExclusionResponse,
+    pub full: SeriesExclusionResponse,
+    pub sample: SeriesExclusionResponse,
+    /// Cycles the recent rate is taken over
+    pub recent_cycles: usize,
+    /// Fraction of all values excluded over the recent cycles
+    pub recent_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReferencesResponse {
+    pub mode: ReferenceMode,
+    pub file: Option<PathBuf>,
+    pub entries: Vec<ReferenceEntry>,
+    /// Levels calibration uses at the current gain and control wavelength
+    pub current: Option<StoredReference>,
+}
+
+/// Which levels a capture measures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceTarget {
+    /// Dark and full levels of cycles with all three series (default)
+    #[default]
+    Both,
+    /// Sample level with the beam blocked
+    Dark,
+    /// Sample level with the holder empty
+    Full,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReferenceCaptureRequest {
+    #[serde(default)]
+    pub target: ReferenceTarget,
+    /// Most recent measurements averaged, default 10
+    pub cycles: Option<usize>,
+    /// Wavelength the entry applies to; none for any wavelength
+    pub wavelength: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReferenceCaptureResponse {
+    /// Measurements averaged
+    pub cycles: usize,
+    /// Entry as saved to the reference file
+    pub entry: ReferenceEntry,
+}
+
+// ============= Config Endpoints =============
+
+#[derive(Debug, Serialize)]
+pub struct ConfigReloadResponse {
+    /// Settings applied immediately
+    pub applied: Vec<String>,
+    /// Settings that need a data source restart and were not applied
+    pub deferred: Vec<String>,
+}
+
+// ============= Data Source Endpoints =============
+
+#[derive(Debug, Serialize)]
+pub struct SourceSwitchResponse {
+    pub status: String,
+    /// Name of the data source now running
+    pub source: String,
+}
+
+// ============= Self-Test Endpoints =============
+
+#[derive(Debug, Serialize)]
+pub struct SelfTestResponse {
+    /// True when no check failed (skipped checks do not fail the test)
+    pub passed: bool,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub checks: Vec<SelfTestCheck>,
+}
+
+// ============= Archive Endpoints =============
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveMeasurementsQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub run_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveMeasurementsResponse {
+    pub count: usize,
+    pub measurements: Vec<ArchivedMeasurement>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveRunsResponse {
+    pub runs: Vec<RunSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveLayersResponse {
+    pub run_id: String,
+    pub layers: Vec<ArchivedLayer>,
+}
+
+// ============= Export Endpoints =============
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+// ============= Measurement Endpoints =============
+
+#[derive(Debug, Deserialize)]
+pub struct DownsampledQuery {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Most points to return (default 1000)
+    pub points: Option<usize>,
+    #[serde(default)]
+    pub method: DownsampleMethod,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownsampledResponse {
+    pub method: DownsampleMethod,
+    /// Valid measurements in the range before downsampling
+    pub source_count: usize,
+    pub count: usize,
+    /// The archive held more measurements in the range than were read;
+    /// the newest are missing
+    pub truncated: bool,
+    pub measurements: Vec<ProcessedMeasurement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MeasurementStatsQuery {
+    /// Window length such as `60s`, `500ms`, `5m` or `1h`; a bare number is
+    /// seconds (default 60s)
+    pub window: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MeasurementStatsResponse {
+    pub window_secs: f64,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    #[serde(flatten)]
+    pub stats: ReadingStats,
+}
+
 // ============= Error Response =============
 
-#[allow(dead_code)]
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub error: String,
@@ -1,75 +1,184 @@
+use std::sync::Arc;
+
 use axum::Router;
+use axum::http::HeaderValue;
+use axum::middleware;
+use axum::response::Response;
 use axum::routing::{get, post};
 
-use super::handlers::{calibration, device, spectrometer, vacuum_chamber};
-use super::{web_ui, websocket};
+use super::guard::{self, ControlGuard};
+use super::handlers::{
+    archive, calibration, config, datasource, debug, device, diagnostics, export, health,
+    measurements, metrics, monitoring, processing, selftest, spectrometer, vacuum_chamber,
+};
+use super::{API_VERSION, trace, web_ui, websocket};
 use crate::service::state::AppState;
 
-/// Create the API router with all endpoints
+/// Create the router: the web UI, the API under `/v1` and the same API at
+/// its unversioned paths as deprecated aliases, every request traced
 pub fn create_router(state: AppState) -> Router {
+    let guard = Arc::new(ControlGuard::new(state.config.clone()));
     Router::new()
-        // Web UI
         .route("/", get(web_ui::index))
+        .nest(&format!("/{API_VERSION}"), api_routes(&guard))
+        .merge(api_routes(&guard).layer(middleware::map_response(mark_deprecated)))
+        .layer(middleware::from_fn_with_state(
+            state.metrics.clone(),
+            trace::trace_request,
+        ))
+        .with_state(state)
+}
+
+/// Flag responses of the unversioned aliases so clients can tell they
+/// should move to `/v1`
+async fn mark_deprecated(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert("deprecation", HeaderValue::from_static("true"));
+    response
+}
+
+/// All API endpoints, relative to the version prefix. Both mounts share
+/// the device's `guard`.
+fn api_routes(guard: &Arc<ControlGuard>) -> Router<AppState> {
+    Router::new()
         // WebSocket
         .route("/ws", get(websocket::ws_handler))
         // Device settings API
+        .route("/config/reload", post(config::reload))
+        .route("/datasource/switch", post(datasource::switch_source))
+        // Device info and registration
+        .route("/device/info", get(device::get_device_info))
+        .route("/device/settings", get(device::get_device_settings))
+        .route("/register", post(device::register))
+        .route("/unregister", post(device::unregister))
+        // Processing state
+        .route("/processing/dark", get(processing::get_dark_estimate))
+        .route("/processing/stats", get(processing::get_outlier_stats))
+        .route("/processing/thickness", get(processing::get_thickness))
+        .route("/processing/references", get(processing::get_references))
+        .route(
+            "/processing/references/capture",
+            post(processing::capture_reference),
+        )
+        // Firmware diagnostics
+        .route("/debug/device", get(debug::get_device_debug))
+        .route(
+            "/diagnostics/protocol",
+            get(diagnostics::get_protocol_diagnostics),
+        )
+        .route("/diagnostics/events", get(diagnostics::get_device_events))
+        .route(
+            "/diagnostics/monitoring",
+            get(diagnostics::get_monitoring_diagnostics),
+        )
+        // Health checks
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        // Operator self-test
+        .route("/selftest", post(selftest::run_selftest))
+        // Prometheus metrics
+        .route("/metrics", get(metrics::get_metrics))
+        // Measurement archive
+        .route("/archive/runs", get(archive::get_runs))
+        .route(
+            "/archive/runs/{run_id}/layers",
+            get(archive::get_run_layers),
+        )
+        .route("/archive/measurements", get(archive::get_measurements))
+        // Charting
+        .route(
+            "/measurements/downsampled",
+            get(measurements::get_downsampled),
+        )
+        .route("/measurements/stats", get(measurements::get_stats))
+        // Report export
+        .route("/export/csv", get(export::export_csv))
+        // Monitoring recovery
+        .route("/monitoring/backfill", post(monitoring::backfill))
+        .route(
+            "/vacuum_chamber/materials",
+            get(vacuum_chamber::get_materials),
+        )
+        .route("/vacuum_chamber/status", get(vacuum_chamber::get_status))
+        .route("/vacuum_chamber/history", get(vacuum_chamber::get_history))
+        .route("/vacuum_chamber/layers", get(vacuum_chamber::get_layers))
+        .merge(control_routes().route_layer(middleware::from_fn_with_state(
+            guard.clone(),
+            guard::guard_control,
+        )))
+}
+
+/// Endpoints that change the device, the chamber or the run; their POSTs
+/// are rate limited and handled one at a time
+fn control_routes() -> Router<AppState> {
+    Router::new()
         .route(
             "/api/settings",
             get(calibration::get_settings).post(calibration::update_settings),
         )
-        // Device info and registration
-        .route("/device/info", get(device::get_device_info))
-        .route("/register", post(device::register))
-        // Spectrometer control
+        .route("/device/command", post(device::send_command))
+        .route("/processing/pause", post(processing::pause))
+        .route("/processing/resume", post(processing::resume))
         .route(
             "/control_wavelength",
             get(spectrometer::get_control_wavelength).post(spectrometer::set_control_wavelength),
         )
-        // Vacuum chamber control
         .route(
             "/vacuum_chamber/material",
             get(vacuum_chamber::get_material).post(vacuum_chamber::set_material),
         )
+        .route(
+            "/vacuum_chamber/prepare",
+            post(vacuum_chamber::prepare_deposition),
+        )
         .route(
             "/vacuum_chamber/start",
             post(vacuum_chamber::start_deposition),
         )
+        .route(
+            "/vacuum_chamber/pause",
+            post(vacuum_chamber::pause_deposition),
+        )
+        .route(
+            "/vacuum_chamber/resume",
+            post(vacuum_chamber::resume_deposition),
+        )
+        .route("/vacuum_chamber/fault", post(vacuum_chamber::report_fault))
         .route(
             "/vacuum_chamber/stop",
             post(vacuum_chamber::stop_deposition),
         )
-        .route("/vacuum_chamber/status", get(vacuum_chamber::get_status))
-        .with_state(state)
+        .route(
+            "/vacuum_chamber/shutter",
+            get(vacuum_chamber::get_shutter).post(vacuum_chamber::set_shutter),
+        )
+}
+
+/// Create the router for several devices: each device's API under
+/// `/devices/{name}`, and the devices with their state on `GET /devices`
+pub fn create_multi_device_router(devices: Vec<(String, AppState)>) -> Router {
+    let router = Router::new()
+        .route("/devices", get(device::list_devices))
+        .with_state(Arc::from(devices.clone()));
+
+    devices.into_iter().fold(router, |router, (name, state)| {
+        router.nest(&format!("/devices/{name}"), create_router(state))
+    })
 }
 
 #[cfg(test)]
 mod tests {
-
     use axum::body::Body;
     use axum::http::{Request, StatusCode};
-    use tokio::sync::{broadcast, mpsc};
     use tower::util::ServiceExt;
 
     use super::*;
-    use crate::service::calibration::create_shared_config;
-    use crate::service::state::create_shared_state;
-
-    fn test_app_state() -> (AppState, tempfile::TempDir) {
-        let dir = tempfile::tempdir().unwrap();
-        let (tx, _) = broadcast::channel(16);
-        let (cmd_tx, _) = mpsc::channel(16);
-        let state = AppState {
-            device: create_shared_state(),
-            config: create_shared_config(dir.path().join("cfg.toml")),
-            broadcast_tx: tx,
-            device_cmd_tx: cmd_tx,
-        };
-        (state, dir)
-    }
+    use crate::service::metrics::HTTP_REQUESTS_TOTAL;
 
     #[tokio::test]
     async fn test_device_info_route() {
-        let app = create_router(test_app_state().0);
+        let app = create_router(AppState::for_test().0);
         let response = app
             .oneshot(
                 Request::builder()
@@ -82,9 +191,29 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_versioned_and_legacy_routes() {
+        let app = create_router(AppState::for_test().0);
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+
+        let response = get("/v1/device/info").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("deprecation").is_none());
+
+        let response = get("/device/info").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["deprecation"], "true");
+
+        let response = get("/v1/").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
     #[tokio::test]
     async fn test_settings_get() {
-        let app = create_router(test_app_state().0);
+        let app = create_router(AppState::for_test().0);
         let response = app
             .oneshot(
                 Request::builder()
@@ -97,13 +226,114 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_metrics_route() {
+        let app = create_router(AppState::for_test().0);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/metrics")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            response.headers()[axum::http::header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/plain")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_requests_recorded_by_route() {
+        let state = AppState::for_test().0;
+        let metrics = state.metrics.clone();
+        let app = create_router(state);
+        for uri in ["/v1/archive/runs/a/layers", "/device/info"] {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let registry = metrics.registry();
+        assert_eq!(
+            registry.get(
+                HTTP_REQUESTS_TOTAL,
+                &[
+                    ("method", "GET"),
+                    ("path", "/v1/archive/runs/{run_id}/layers"),
+                    ("status", "404")
+                ]
+            ),
+            Some(1.0)
+        );
+        assert_eq!(
+            registry.get(
+                HTTP_REQUESTS_TOTAL,
+                &[
+                    ("method", "GET"),
+                    ("path", "/device/info"),
+                    ("status", "200")
+                ]
+            ),
+            Some(1.0)
+        );
+    }
+
     #[tokio::test]
     async fn test_web_ui_route() {
-        let app = create_router(test_app_state().0);
+        let app = create_router(AppState::for_test().0);
         let response = app
             .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
             .await
             .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_multi_device_routes() {
+        let (ch1, _dir1) = AppState::for_test();
+        let (ch2, _dir2) = AppState::for_test();
+        ch2.device.write().await.control_wavelength = 632.8;
+        let app =
+            create_multi_device_router(vec![("ch1".to_string(), ch1), ("ch2".to_string(), ch2)]);
+
+        let get = |uri: &str| {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        };
+        let body = |response: axum::response::Response| async {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+
+        let response = get("/devices").await.unwrap();
+        let listing = body(response).await;
+        assert_eq!(listing.as_array().unwrap().len(), 2);
+        assert_eq!(listing[1]["id"], "ch2");
+        assert_eq!(listing[1]["path"], "/devices/ch2");
+        assert_eq!(listing[1]["type"], "spectrometer");
+        assert_eq!(listing[1]["chamber_state"], "idle");
+        assert_eq!(listing[1]["registered"], false);
+        assert_eq!(listing[1]["capabilities"]["is_monochromatic"], true);
+
+        let response = get("/devices/ch2/v1/control_wavelength").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = get("/devices/ch2/control_wavelength").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await["control_wavelength"], 632.8);
+
+        let response = get("/devices/ch1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = get("/device/info").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = get("/devices/ch3/device/info").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,104 @@
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::Instrument;
+
+use crate::service::metrics::SharedMetrics;
+
+/// Route label of requests that matched no route, so unknown paths do not
+/// each get their own series
+const UNMATCHED: &str = "unmatched";
+
+/// Middleware of every route: handles the request in a `request` span with
+/// its method, route and, once answered, status and latency, logs the
+/// outcome and records it in the HTTP request metrics
+pub async fn trace_request(
+    State(metrics): State<SharedMetrics>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED, MatchedPath::as_str)
+        .to_string();
+    let span = tracing::info_span!(
+        "request",
+        %method,
+        path,
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+    );
+
+    let started = Instant::now();
+    let response = next.run(request).instrument(span.clone()).await;
+    let latency = started.elapsed();
+    let status = response.status();
+
+    span.record("status", status.as_u16());
+    span.record("latency_ms", latency.as_secs_f64() * 1000.0);
+    span.in_scope(|| {
+        if status.is_server_error() {
+            tracing::warn!("Request failed");
+        } else {
+            tracing::info!("Request handled");
+        }
+    });
+    metrics.record_request(method.as_str(), &path, status.as_u16(), latency);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::middleware;
+    use axum::routing::get;
+    use tower::util::ServiceExt;
+
+    use super::*;
+    use crate::service::metrics::{
+        HTTP_REQUEST_DURATION, HTTP_REQUESTS_TOTAL, create_shared_metrics,
+    };
+
+    #[tokio::test]
+    async fn test_records_route_and_status() {
+        let metrics = create_shared_metrics();
+        let app = Router::new()
+            .route("/runs/{id}", get(|| async { StatusCode::NOT_FOUND }))
+            .layer(middleware::from_fn_with_state(
+                metrics.clone(),
+                trace_request,
+            ));
+        for uri in ["/runs/a", "/runs/b", "/missing"] {
+            app.clone()
+                .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let registry = metrics.registry();
+        assert_eq!(
+            registry.get(
+                HTTP_REQUESTS_TOTAL,
+                &[("method", "GET"), ("path", "/runs/{id}"), ("status", "404")]
+            ),
+            Some(2.0)
+        );
+        assert_eq!(
+            registry.get(
+                HTTP_REQUESTS_TOTAL,
+                &[("method", "GET"), ("path", UNMATCHED), ("status", "404")]
+            ),
+            Some(1.0)
+        );
+        let text = registry.render();
+        assert!(text.contains(&format!(
+            "{HTTP_REQUEST_DURATION}_count{{method=\"GET\",path=\"/runs/{{id}}\"}} 2\n"
+        )));
+    }
+}
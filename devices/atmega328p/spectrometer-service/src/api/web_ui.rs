@@ -142,18 +142,20 @@ canvas { width: 100%; height: 100%; display: block; }
 
 <script>
 const MAX = 300;
+// Served under /devices/{name} when several devices run in one process
+const BASE = location.pathname.replace(/\/$/, '');
 const D = { t: [], dark: [], full: [], sample: [], clip: [] };
 let ws, cycles = 0;
 
 function connect() {
   const p = location.protocol === 'https:' ? 'wss:' : 'ws:';
-  ws = new WebSocket(`${p}//${location.host}/ws`);
+  ws = new WebSocket(`${p}//${location.host}${BASE}/v1/ws`);
   ws.onopen = () => { el('ws-badge').className='badge badge-ok'; el('ws-badge').textContent='Connected'; };
   ws.onclose = () => { el('ws-badge').className='badge badge-warn'; el('ws-badge').textContent='Reconnecting...'; setTimeout(connect,2000); };
   ws.onmessage = (e) => {
     const m = JSON.parse(e.data);
     if (m.type==='init') onInit(m);
-    else if (m.type==='cycle') onCycle(m);
+    else if (m.type==='measurement') onMeasurement(m);
     else if (m.type==='settings_updated') onSettingsUpdated(m);
     else if (m.type==='log') onLog(m);
   };
@@ -162,18 +164,35 @@ function connect() {
 function el(id) { return document.getElementById(id); }
 function fmt(n) { return Math.abs(n)>1e5 ? n.toExponential(2) : n.toFixed(2); }
 
+// Reference detector series; not editable here, kept when saving
+let mappedReference = null;
+
+function setSeriesOptions(count) {
+  for (const id of ['map-dark', 'map-full', 'map-sample']) {
+    const sel = el(id);
+    const value = sel.value;
+    sel.innerHTML = '';
+    for (let n = 1; n <= count; n++) sel.add(new Option(`SERIES ${n}`, n));
+    sel.value = value;
+  }
+}
+
+function showMapping(m) {
+  el('map-dark').value = m.dark;
+  el('map-full').value = m.full;
+  el('map-sample').value = m.sample;
+  mappedReference = m.reference ?? null;
+}
+
 function onInit(m) {
   el('sel-gain').value = m.device_settings.gain;
   el('sel-fadc').value = m.device_settings.fadc;
   el('sel-count').value = m.device_settings.count;
-  if (m.series_mapping) {
-    el('map-dark').value = m.series_mapping.dark;
-    el('map-full').value = m.series_mapping.full;
-    el('map-sample').value = m.series_mapping.sample;
-  }
+  if (m.device_settings.series_count) setSeriesOptions(m.device_settings.series_count);
+  if (m.series_mapping) showMapping(m.series_mapping);
 }
 
-function onCycle(m) {
+function onMeasurement(m) {
   cycles++;
   el('cycle-counter').textContent = `Cycles: ${cycles}`;
   el('v-t').textContent = fmt(m.calibrated_reading) + '%';
@@ -185,11 +204,11 @@ function onCycle(m) {
   D.dark.push(m.dark_mean);
   D.full.push(m.full_mean);
   D.sample.push(m.sample_mean);
-  D.clip.push(m.is_clipped);
+  D.clip.push(m.clipped);
   for (const k of Object.keys(D)) { if (D[k].length>MAX) D[k].shift(); }
 
   const cb = el('clip-badge');
-  if (m.is_clipped) { cb.style.display=''; cb.className='badge badge-err'; }
+  if (m.clipped) { cb.style.display=''; cb.className='badge badge-err'; }
   else { cb.style.display='none'; }
 
   draw();
@@ -199,11 +218,7 @@ function onSettingsUpdated(m) {
   el('sel-gain').value = m.gain;
   el('sel-fadc').value = m.fadc;
   el('sel-count').value = m.count;
-  if (m.series_mapping) {
-    el('map-dark').value = m.series_mapping.dark;
-    el('map-full').value = m.series_mapping.full;
-    el('map-sample').value = m.series_mapping.sample;
-  }
+  if (m.series_mapping) showMapping(m.series_mapping);
 }
 
 const MAX_LOG = 500;
@@ -255,9 +270,10 @@ async function saveSettings() {
       dark: parseInt(el('map-dark').value),
       full: parseInt(el('map-full').value),
       sample: parseInt(el('map-sample').value),
+      reference: mappedReference,
     },
   };
-  await fetch('/api/settings', { method:'POST', headers:{'Content-Type':'application/json'}, body:JSON.stringify(body) });
+  await fetch(`${BASE}/v1/api/settings`, { method:'POST', headers:{'Content-Type':'application/json'}, body:JSON.stringify(body) });
 }
 
 function draw() {
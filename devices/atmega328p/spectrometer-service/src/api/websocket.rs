@@ -20,12 +20,9 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
                 "gain": s.gain,
                 "fadc": s.fadc,
                 "count": s.count,
+                "series_count": s.series_count,
             },
-            "series_mapping": {
-                "dark": s.series_mapping.dark,
-                "full": s.series_mapping.full,
-                "sample": s.series_mapping.sample,
-            }
+            "series_mapping": s.series_mapping,
         })
     };
 
@@ -37,20 +34,22 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
         return;
     }
 
-    // Subscribe to broadcast channel
-    let mut rx = state.broadcast_tx.subscribe();
+    let mut events = state.events.subscribe();
 
     loop {
         tokio::select! {
-            msg = rx.recv() => {
-                match msg {
-                    Ok(data) => {
-                        if socket.send(Message::Text(data.to_string().into())).await.is_err() {
+            event = events.recv() => {
+                match event {
+                    Ok(event) => {
+                        let Ok(text) = serde_json::to_string(&event) else {
+                            continue;
+                        };
+                        if socket.send(Message::Text(text.into())).await.is_err() {
                             break;
                         }
                     }
                     Err(broadcast::error::RecvError::Lagged(n)) => {
-                        tracing::warn!("WebSocket client lagged by {} messages", n);
+                        tracing::warn!("WebSocket client lagged by {} events", n);
                     }
                     Err(broadcast::error::RecvError::Closed) => break,
                 }
@@ -1,9 +1,28 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 
+use chrono::{DateTime, Utc};
 use clap::{Args, Parser, Subcommand};
 
+use crate::data_sink::influx::InfluxSettings;
+use crate::data_sink::monitoring::MonitoringSinkSettings;
+use crate::data_sink::pushgateway::PushgatewaySettings;
+use crate::data_sink::run_log::RunLogFormat;
+use crate::data_sink::{DataSinkConfig, SinkArg};
 use crate::data_source::DataSourceConfig;
+use crate::data_source::playback::{TimeRange, parse_log_timestamp};
+use crate::data_source::serial::SerialLineSettings;
+use crate::error::SpectrometerError;
+use crate::logging::{LogFormat, LogRotation, LogSettings};
+use crate::monitoring::OverflowPolicy;
 use crate::processing::outlier::OutlierMethod;
+use crate::processing::outlier::esd::{DEFAULT_ESD_ALPHA, DEFAULT_ESD_MAX_OUTLIERS};
+use crate::processing::outlier::sigma_clip::{DEFAULT_SIGMA_K, DEFAULT_SIGMA_MAX_ITERATIONS};
+use crate::processing::smoothing::SmootherKind;
+use crate::processing::timing::TimestampMode;
+use crate::protocol::{AdcFrequency, Gain, MeasurementCount, OutOfRangePolicy, ProtocolKind};
+use crate::service::reprocess::ReprocessFormat;
+use crate::service::supervisor::RestartPolicy;
 
 #[derive(Parser, Debug)]
 #[command(name = "spectrometer-service")]
@@ -14,26 +33,226 @@ pub struct Cli {
     #[arg(short, long, default_value = "8100")]
     pub listen: u16,
 
-    /// HTTP server host
-    #[arg(long, default_value = "0.0.0.0")]
-    pub host: String,
+    /// HTTP server address; repeat or comma-separate for several. `::`
+    /// accepts IPv6 and IPv4 (dual-stack). Default: 0.0.0.0, or none with
+    /// --unix-socket.
+    #[arg(long, value_delimiter = ',')]
+    pub host: Vec<IpAddr>,
+
+    /// Also serve the HTTP API on this Unix domain socket, e.g. for a local
+    /// reverse proxy
+    #[arg(long)]
+    pub unix_socket: Option<PathBuf>,
 
     /// List available serial ports and exit
     #[arg(long)]
     pub list_ports: bool,
 
-    /// Outlier exclusion method
-    #[arg(long, value_enum, default_value = "grubbs")]
-    pub outlier_method: OutlierMethodArg,
+    /// Outlier exclusion method. Overrides the config file [processing] section.
+    #[arg(long, value_enum)]
+    pub outlier_method: Option<OutlierMethodArg>,
+
+    /// Alpha value for Grubbs test (significance level, default 0.05)
+    #[arg(long)]
+    pub grubbs_alpha: Option<f64>,
+
+    /// Clipping threshold for sigma-clip, in standard deviations (default 3)
+    #[arg(long)]
+    pub sigma_k: Option<f64>,
+
+    /// Maximum number of sigma-clip passes (default 5)
+    #[arg(long)]
+    pub sigma_max_iterations: Option<u32>,
+
+    /// Significance level for the generalized ESD test (default 0.05)
+    #[arg(long)]
+    pub esd_alpha: Option<f64>,
+
+    /// Maximum number of outliers the generalized ESD test removes from a
+    /// series (default 3)
+    #[arg(long)]
+    pub esd_max_outliers: Option<usize>,
+
+    /// Smoother for the calibrated reading. Overrides `processing.smoother`
+    /// in the config file (default: savitzky-golay).
+    #[arg(long, value_enum)]
+    pub smoother: Option<SmootherKind>,
+
+    /// Firmware dialect of the serial output or log file
+    #[arg(long, value_enum, default_value = "atmega")]
+    pub protocol: ProtocolKind,
 
-    /// Alpha value for Grubbs test (significance level)
-    #[arg(long, default_value = "0.05")]
-    pub grubbs_alpha: f64,
+    /// Handling of raw values above the 24-bit ADC range
+    #[arg(long, value_enum, default_value = "reject-value")]
+    pub out_of_range: OutOfRangePolicy,
+
+    /// Report lines that start like a known message but do not fit its
+    /// format as malformed instead of unknown, e.g. to check logs from new
+    /// firmware builds
+    #[arg(long)]
+    pub strict_protocol: bool,
+
+    /// Seconds a partial cycle may wait for END_CYCLE before it is discarded
+    /// (0 disables the timeout)
+    #[arg(long, default_value = "30")]
+    pub cycle_timeout_secs: u64,
+
+    /// Whether a data source that ended is started again
+    #[arg(long, value_enum, default_value = "on-failure")]
+    pub restart_policy: RestartPolicy,
+
+    /// Longest wait between restarts of a data source, in seconds; the wait
+    /// starts at 1 s and doubles while restarts deliver no data
+    #[arg(long, default_value = "60")]
+    pub restart_max_backoff_secs: u64,
+
+    /// Exit once every data source ended and is not restarted, e.g. playback
+    /// without --loop-playback; the exit status is 1 if a source failed
+    #[arg(long)]
+    pub exit_on_complete: bool,
+
+    /// Seconds without a new cycle after which /healthz and /readyz report 503
+    #[arg(long, default_value = "10")]
+    pub stale_after_secs: u64,
+
+    /// Instant within the acquisition window used as the measurement timestamp
+    #[arg(long, value_enum, default_value = "cycle-midpoint")]
+    pub timestamp_mode: TimestampMode,
 
     /// Path to calibration config file
     #[arg(long, default_value = "calibration.toml")]
     pub calibration_config: std::path::PathBuf,
 
+    /// Number of processed measurements kept in memory for backfill
+    #[arg(long, default_value = "36000")]
+    pub history_size: usize,
+
+    /// Number of failed monitoring pushes buffered for replay
+    #[arg(long, default_value = "10000")]
+    pub push_buffer_size: usize,
+
+    /// Number of measurements waiting for the monitoring sender task
+    #[arg(long, default_value = "256")]
+    pub push_queue_size: usize,
+
+    /// What a full monitoring push queue does with the next measurement
+    #[arg(long, value_enum, default_value = "drop-oldest")]
+    pub push_queue_overflow: OverflowPolicy,
+
+    /// Append monitoring pushes that can no longer be retried (dropped from
+    /// the full push buffer, or pending at shutdown) to this JSONL file, for
+    /// the `redeliver` command
+    #[arg(long)]
+    pub dead_letter: Option<PathBuf>,
+
+    /// Announce this service to OptiMonitor at this URL (e.g.
+    /// http://optimonitor:8200) on startup, and again while not registered
+    #[arg(long)]
+    pub monitoring_url: Option<String>,
+
+    /// Host OptiMonitor calls back on, e.g. the public address of a NAT
+    /// (default: --host, or localhost when listening on all interfaces)
+    #[arg(long)]
+    pub advertise_host: Option<String>,
+
+    /// Port OptiMonitor calls back on (default: --listen)
+    #[arg(long)]
+    pub advertise_port: Option<u16>,
+
+    /// Advertise each device on the local network over mDNS as
+    /// `_optimonitor-device._tcp`, for OptiMonitor's auto-discovery
+    #[arg(long)]
+    pub mdns: bool,
+
+    /// mDNS instance name, with `-<device>` appended when running several
+    /// devices; give each service on the network its own
+    #[arg(long, default_value = "spectrometer")]
+    pub mdns_name: String,
+
+    /// Measurement outputs: `monitoring`, `csv:<path>`, `runs:<dir>`, `stdout`,
+    /// `influx` or `pushgateway`. Repeat or comma-separate for several.
+    #[arg(long = "sink", value_delimiter = ',', default_value = "monitoring")]
+    pub sinks: Vec<SinkArg>,
+
+    /// File format of the `runs` sink
+    #[arg(long, value_enum, default_value = "csv")]
+    pub run_log_format: RunLogFormat,
+
+    /// InfluxDB base URL for the influx sink (e.g. http://localhost:8086)
+    #[arg(long)]
+    pub influx_url: Option<String>,
+
+    /// InfluxDB organization
+    #[arg(long)]
+    pub influx_org: Option<String>,
+
+    /// InfluxDB bucket
+    #[arg(long)]
+    pub influx_bucket: Option<String>,
+
+    /// InfluxDB API token
+    #[arg(long, env = "INFLUX_TOKEN", hide_env_values = true)]
+    pub influx_token: Option<String>,
+
+    /// Prometheus Pushgateway base URL for the pushgateway sink (e.g.
+    /// http://pushgateway:9091)
+    #[arg(long)]
+    pub pushgateway_url: Option<String>,
+
+    /// Job label of the pushed metrics group
+    #[arg(long, default_value = "spectrometer")]
+    pub pushgateway_job: String,
+
+    /// Instance label of the pushed metrics group
+    #[arg(long, default_value = "spectrometer")]
+    pub pushgateway_instance: String,
+
+    /// Shortest time in seconds between two pushes to the Pushgateway
+    #[arg(long, default_value = "5")]
+    pub pushgateway_interval: u64,
+
+    /// SQLite database archiving every processed measurement
+    #[arg(long)]
+    pub archive: Option<PathBuf>,
+
+    /// Also archive the raw ADC series of each cycle
+    #[arg(long, requires = "archive")]
+    pub archive_raw: bool,
+
+    /// Delete archived measurements older than this many days
+    #[arg(long, requires = "archive")]
+    pub archive_retention_days: Option<u32>,
+
+    /// Detach from the terminal and run in the background (Unix only);
+    /// requires --log-file, as the daemon has no stderr
+    #[arg(long, requires = "log_file")]
+    pub daemon: bool,
+
+    /// Write the daemon's PID to this file, removed again on shutdown
+    #[arg(long, requires = "daemon")]
+    pub pid_file: Option<PathBuf>,
+
+    /// Format of the service's log lines
+    #[arg(long, value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// Write the service's log to this file instead of stderr (not to be
+    /// confused with `serial --log-file`, which records the raw serial output)
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// When the --log-file is rotated
+    #[arg(long, value_enum, default_value = "daily", requires = "log_file")]
+    pub log_rotation: LogRotation,
+
+    /// Size in MiB at which `--log-rotation size` starts a new log file
+    #[arg(long, default_value = "100", requires = "log_file")]
+    pub log_max_size_mb: u64,
+
+    /// Number of rotated log files kept (0 keeps all)
+    #[arg(long, default_value = "7", requires = "log_file")]
+    pub log_max_files: usize,
+
     #[command(subcommand)]
     pub mode: Option<Mode>,
 }
@@ -45,6 +264,22 @@ pub enum Mode {
 
     /// Playback from log file
     Playback(PlaybackArgs),
+
+    /// Receive the line protocol from a local bridge process over a Unix
+    /// domain socket (a named pipe on Windows)
+    Socket(SocketArgs),
+
+    /// Check a log file offline and exit with status 1 if it would not
+    /// play back cleanly
+    ValidateLog(ValidateLogArgs),
+
+    /// Run a log file through the processing pipeline without delay and
+    /// write every measurement, e.g. to compare settings on a recorded run
+    Reprocess(ReprocessArgs),
+
+    /// Post the measurements in a dead-letter file to the monitoring API as
+    /// backfill, keeping those that still fail in the file
+    Redeliver(RedeliverArgs),
 }
 
 #[derive(Args, Debug, Clone)]
@@ -58,25 +293,62 @@ pub struct SerialArgs {
     pub baud: u32,
 
     /// ADC gain setting (1, 2, 4, 8, 16, 32, 64, 128). Overrides saved config.
-    #[arg(long)]
-    pub gain: Option<u8>,
+    #[arg(long, value_parser = parse_gain_arg)]
+    pub gain: Option<Gain>,
 
     /// ADC sample rate in Hz. Overrides saved config.
-    #[arg(long)]
-    pub fadc: Option<f32>,
+    #[arg(long, value_parser = parse_fadc_arg)]
+    pub fadc: Option<AdcFrequency>,
 
     /// Dump raw serial output to file for later playback
     #[arg(long)]
     pub log_file: Option<std::path::PathBuf>,
 
     /// Number of measurements per series (1-12). Overrides saved config.
+    #[arg(long, value_parser = parse_count_arg)]
+    pub count: Option<MeasurementCount>,
+
+    /// Start even if the firmware reports an unsupported major version
     #[arg(long)]
-    pub count: Option<u8>,
+    pub allow_firmware_mismatch: bool,
+
+    #[command(flatten)]
+    pub line: SerialLineSettings,
+}
+
+fn parse_gain_arg(value: &str) -> Result<Gain, String> {
+    let gain: u8 = value
+        .parse()
+        .map_err(|_| format!("invalid gain '{value}'"))?;
+    Gain::try_from(gain).map_err(|e| e.to_string())
+}
+
+fn parse_fadc_arg(value: &str) -> Result<AdcFrequency, String> {
+    let fadc: f32 = value
+        .parse()
+        .map_err(|_| format!("invalid FADC '{value}'"))?;
+    AdcFrequency::try_from(fadc).map_err(|e| e.to_string())
+}
+
+fn parse_count_arg(value: &str) -> Result<MeasurementCount, String> {
+    let count: u8 = value
+        .parse()
+        .map_err(|_| format!("invalid count '{value}'"))?;
+    MeasurementCount::new(count).map_err(|e| e.to_string())
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct SocketArgs {
+    /// Socket path to listen on (e.g. /run/spectrometer.sock), or pipe name
+    /// on Windows (e.g. \\.\pipe\spectrometer)
+    #[arg(short, long)]
+    pub path: PathBuf,
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct PlaybackArgs {
-    /// Path to log file (supports both timestamped and raw serial log formats)
+    /// Path to log file (supports both timestamped and raw serial log
+    /// formats), or a directory or glob pattern of rotated log files
     #[arg(short, long)]
     pub file: PathBuf,
 
@@ -91,6 +363,65 @@ pub struct PlaybackArgs {
     /// Cycle interval in ms for raw logs without timestamps (default: 100)
     #[arg(long, default_value = "100")]
     pub cycle_interval: u64,
+
+    /// Skip timestamped log lines before this time (RFC 3339, UTC without
+    /// offset)
+    #[arg(long, value_parser = parse_time_arg)]
+    pub from: Option<DateTime<Utc>>,
+
+    /// Stop at timestamped log lines after this time
+    #[arg(long, value_parser = parse_time_arg)]
+    pub to: Option<DateTime<Utc>>,
+
+    /// Keep reading at the end of the file as another process appends to
+    /// it, like `tail -f`, reopening it when it is rotated
+    #[arg(long, conflicts_with_all = ["loop_playback", "from", "to"])]
+    pub follow: bool,
+}
+
+fn parse_time_arg(value: &str) -> Result<DateTime<Utc>, String> {
+    parse_log_timestamp(value).ok_or_else(|| format!("invalid timestamp '{value}'"))
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ValidateLogArgs {
+    /// Path to log file (timestamped or raw serial log)
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReprocessArgs {
+    /// Path to log file (timestamped or raw serial log), or a directory or
+    /// glob pattern of rotated log files
+    pub file: PathBuf,
+
+    /// Output file (default: stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "jsonl")]
+    pub format: ReprocessFormat,
+
+    /// Cycle interval in ms for raw logs without timestamps (default: 100)
+    #[arg(long, default_value = "100")]
+    pub cycle_interval: u64,
+
+    /// Control wavelength in nm the log was recorded at, for looking up
+    /// stored references (default: 550)
+    #[arg(long, default_value = "550")]
+    pub wavelength: f64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RedeliverArgs {
+    /// Dead-letter file written with --dead-letter
+    pub file: PathBuf,
+
+    /// Monitoring API URL to post to instead of the one each measurement
+    /// was meant for
+    #[arg(long)]
+    pub api_url: Option<String>,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug, Default)]
@@ -100,9 +431,50 @@ pub enum OutlierMethodArg {
     /// Grubbs' test (default)
     #[default]
     Grubbs,
+    /// Iterated sigma clipping
+    SigmaClip,
+    /// Generalized ESD test for several outliers
+    Esd,
 }
 
 impl Cli {
+    /// TCP addresses the HTTP server listens on: the `--host` addresses at
+    /// `--listen`, all interfaces when neither they nor a Unix socket are
+    /// given
+    pub fn bind_addresses(&self) -> Vec<SocketAddr> {
+        let hosts = match (self.host.as_slice(), &self.unix_socket) {
+            ([], None) => vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)],
+            (hosts, _) => hosts.to_vec(),
+        };
+        hosts
+            .into_iter()
+            .map(|host| SocketAddr::new(host, self.listen))
+            .collect()
+    }
+
+    /// Host and port announced to OptiMonitor for call-backs
+    pub fn advertised_address(&self) -> (String, u16) {
+        let host = self.advertise_host.clone().unwrap_or_else(|| {
+            match self.bind_addresses().first().map(SocketAddr::ip) {
+                Some(IpAddr::V6(ip)) if !ip.is_unspecified() => format!("[{ip}]"),
+                Some(ip) if !ip.is_unspecified() => ip.to_string(),
+                _ => "localhost".to_string(),
+            }
+        });
+        (host, self.advertise_port.unwrap_or(self.listen))
+    }
+
+    /// Logging options for `logging::init`
+    pub fn to_log_settings(&self) -> LogSettings {
+        LogSettings {
+            format: self.log_format,
+            file: self.log_file.clone(),
+            rotation: self.log_rotation,
+            max_size: self.log_max_size_mb.saturating_mul(1024 * 1024),
+            max_files: self.log_max_files,
+        }
+    }
+
     /// Convert CLI args to DataSourceConfig.
     /// For serial mode, CLI args override saved config; saved config overrides hardcoded defaults.
     pub fn to_data_source_config(
@@ -113,28 +485,149 @@ impl Cli {
             Some(Mode::Serial(args)) => Some(DataSourceConfig::Serial {
                 port: args.device.clone(),
                 baud_rate: args.baud,
-                gain: args.gain.unwrap_or(saved.gain),
-                fadc: args.fadc.unwrap_or(saved.fadc),
-                count: args.count.unwrap_or(saved.count),
+                gain: args.gain.map_or(saved.gain, |gain| gain.as_u8()),
+                fadc: args.fadc.map_or(saved.fadc, |fadc| fadc.as_f32()),
+                count: args.count.map_or(saved.count, |count| count.as_u8()),
                 log_file: args.log_file.clone(),
+                allow_firmware_mismatch: args.allow_firmware_mismatch,
+                line: args.line,
             }),
             Some(Mode::Playback(args)) => Some(DataSourceConfig::Playback {
                 log_file: args.file.clone(),
                 speed_multiplier: args.speed,
                 loop_playback: args.loop_playback,
                 cycle_interval_ms: args.cycle_interval,
+                time_range: TimeRange {
+                    from: args.from,
+                    to: args.to,
+                },
+                follow: args.follow,
+            }),
+            Some(Mode::Socket(args)) => Some(DataSourceConfig::Socket {
+                path: args.path.clone(),
             }),
-            None => None,
+            Some(Mode::ValidateLog(_) | Mode::Reprocess(_) | Mode::Redeliver(_)) | None => None,
+        }
+    }
+
+    /// Partial cycle timeout, `None` when disabled
+    pub fn cycle_timeout(&self) -> Option<chrono::Duration> {
+        (self.cycle_timeout_secs > 0)
+            .then(|| chrono::Duration::seconds(self.cycle_timeout_secs as i64))
+    }
+
+    /// Convert CLI args to data sink configurations
+    pub fn to_sink_configs(&self) -> Result<Vec<DataSinkConfig>, SpectrometerError> {
+        self.sinks
+            .iter()
+            .map(|sink| {
+                Ok(match sink {
+                    SinkArg::Monitoring => DataSinkConfig::Monitoring(MonitoringSinkSettings {
+                        push_buffer_size: self.push_buffer_size,
+                        push_queue_size: self.push_queue_size,
+                        push_queue_overflow: self.push_queue_overflow,
+                        dead_letter: self.dead_letter.clone(),
+                        // From the calibration config, when the device starts
+                        endpoints: Vec::new(),
+                    }),
+                    SinkArg::Csv(path) => DataSinkConfig::Csv { path: path.clone() },
+                    SinkArg::Runs(dir) => DataSinkConfig::RunLog {
+                        dir: dir.clone(),
+                        format: self.run_log_format,
+                    },
+                    SinkArg::Stdout => DataSinkConfig::StdoutJson,
+                    SinkArg::Influx => DataSinkConfig::Influx(self.to_influx_settings()?),
+                    SinkArg::Pushgateway => {
+                        DataSinkConfig::Pushgateway(self.to_pushgateway_settings()?)
+                    }
+                })
+            })
+            .collect()
+    }
+
+    fn to_influx_settings(&self) -> Result<InfluxSettings, SpectrometerError> {
+        let require = |value: &Option<String>, flag: &str| {
+            value
+                .clone()
+                .ok_or_else(|| SpectrometerError::Config(format!("influx sink requires --{flag}")))
+        };
+
+        Ok(InfluxSettings {
+            url: require(&self.influx_url, "influx-url")?,
+            org: require(&self.influx_org, "influx-org")?,
+            bucket: require(&self.influx_bucket, "influx-bucket")?,
+            token: self.influx_token.clone(),
+        })
+    }
+
+    fn to_pushgateway_settings(&self) -> Result<PushgatewaySettings, SpectrometerError> {
+        let url = self.pushgateway_url.clone().ok_or_else(|| {
+            SpectrometerError::Config("pushgateway sink requires --pushgateway-url".to_string())
+        })?;
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(SpectrometerError::Config(format!(
+                "invalid --pushgateway-url {url:?}"
+            )));
         }
+        Ok(PushgatewaySettings {
+            url,
+            job: self.pushgateway_job.clone(),
+            instance: self.pushgateway_instance.clone(),
+            interval: std::time::Duration::from_secs(self.pushgateway_interval),
+        })
     }
 
-    /// Convert CLI args to OutlierMethod
-    pub fn to_outlier_method(&self) -> OutlierMethod {
-        match self.outlier_method {
-            OutlierMethodArg::None => OutlierMethod::None,
-            OutlierMethodArg::Grubbs => OutlierMethod::Grubbs {
-                alpha: self.grubbs_alpha,
-            },
+    /// Convert CLI args to OutlierMethod. CLI flags take precedence over the
+    /// method saved in the config file, which takes precedence over the default.
+    pub fn to_outlier_method(&self, saved: Option<&OutlierMethod>) -> OutlierMethod {
+        // Method-specific flags select their method when none is given
+        let method = self.outlier_method.clone().or_else(|| {
+            if self.grubbs_alpha.is_some() {
+                Some(OutlierMethodArg::Grubbs)
+            } else if self.sigma_k.is_some() || self.sigma_max_iterations.is_some() {
+                Some(OutlierMethodArg::SigmaClip)
+            } else if self.esd_alpha.is_some() || self.esd_max_outliers.is_some() {
+                Some(OutlierMethodArg::Esd)
+            } else {
+                None
+            }
+        });
+
+        match method {
+            None => saved.cloned().unwrap_or_default(),
+            Some(OutlierMethodArg::None) => OutlierMethod::None,
+            Some(OutlierMethodArg::Grubbs) => {
+                let saved_alpha = match saved {
+                    Some(OutlierMethod::Grubbs { alpha }) => *alpha,
+                    _ => 0.05,
+                };
+                OutlierMethod::Grubbs {
+                    alpha: self.grubbs_alpha.unwrap_or(saved_alpha),
+                }
+            }
+            Some(OutlierMethodArg::SigmaClip) => {
+                let (saved_k, saved_iterations) = match saved {
+                    Some(OutlierMethod::SigmaClip { k, max_iterations }) => (*k, *max_iterations),
+                    _ => (DEFAULT_SIGMA_K, DEFAULT_SIGMA_MAX_ITERATIONS),
+                };
+                OutlierMethod::SigmaClip {
+                    k: self.sigma_k.unwrap_or(saved_k),
+                    max_iterations: self.sigma_max_iterations.unwrap_or(saved_iterations),
+                }
+            }
+            Some(OutlierMethodArg::Esd) => {
+                let (saved_alpha, saved_max_outliers) = match saved {
+                    Some(OutlierMethod::Esd {
+                        alpha,
+                        max_outliers,
+                    }) => (*alpha, *max_outliers),
+                    _ => (DEFAULT_ESD_ALPHA, DEFAULT_ESD_MAX_OUTLIERS),
+                };
+                OutlierMethod::Esd {
+                    alpha: self.esd_alpha.unwrap_or(saved_alpha),
+                    max_outliers: self.esd_max_outliers.unwrap_or(saved_max_outliers),
+                }
+            }
         }
     }
 }
@@ -142,6 +635,7 @@ impl Cli {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data_source::serial::{FlowControl, Parity};
 
     #[test]
     fn test_cli_parse_serial() {
@@ -160,9 +654,47 @@ mod tests {
         if let Some(Mode::Serial(args)) = cli.mode {
             assert_eq!(args.device, "COM3");
             assert_eq!(args.baud, 38400);
+            assert_eq!(args.line, SerialLineSettings::default());
         }
     }
 
+    #[test]
+    fn test_cli_parse_serial_line_settings() {
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "serial",
+            "--device",
+            "/dev/ttyUSB0",
+            "--parity",
+            "odd",
+            "--stop-bits",
+            "2",
+            "--flow-control",
+            "hardware",
+            "--dtr",
+            "false",
+        ]);
+        let Some(Mode::Serial(args)) = cli.mode else {
+            panic!("Expected serial mode");
+        };
+        assert_eq!(args.line.parity, Parity::Odd);
+        assert_eq!(args.line.stop_bits, 2);
+        assert_eq!(args.line.flow_control, FlowControl::Hardware);
+        assert_eq!(args.line.dtr, Some(false));
+
+        assert!(
+            Cli::try_parse_from([
+                "spectrometer-service",
+                "serial",
+                "--device",
+                "/dev/ttyUSB0",
+                "--data-bits",
+                "9",
+            ])
+            .is_err()
+        );
+    }
+
     #[test]
     fn test_cli_parse_playback() {
         let cli = Cli::parse_from([
@@ -184,6 +716,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_parse_socket() {
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "socket",
+            "--path",
+            "/run/spectrometer.sock",
+        ]);
+
+        let config =
+            cli.to_data_source_config(&crate::service::calibration::DeviceSettings::default());
+        let Some(DataSourceConfig::Socket { path }) = config else {
+            panic!("expected a socket source, got {config:?}");
+        };
+        assert_eq!(path, PathBuf::from("/run/spectrometer.sock"));
+    }
+
     #[test]
     fn test_cli_parse_list_ports() {
         let cli = Cli::parse_from(["spectrometer-service", "--list-ports"]);
@@ -191,10 +740,71 @@ mod tests {
         assert!(cli.list_ports);
     }
 
+    #[test]
+    fn test_advertised_address() {
+        let cli = Cli::parse_from(["spectrometer-service", "--listen", "8200"]);
+        assert!(cli.monitoring_url.is_none());
+        assert_eq!(cli.advertised_address(), ("localhost".to_string(), 8200));
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--monitoring-url",
+            "http://optimonitor:8200",
+            "--advertise-host",
+            "lab-gw.example.com",
+            "--advertise-port",
+            "18100",
+        ]);
+        assert_eq!(
+            cli.monitoring_url.as_deref(),
+            Some("http://optimonitor:8200")
+        );
+        assert_eq!(
+            cli.advertised_address(),
+            ("lab-gw.example.com".to_string(), 18100)
+        );
+
+        let cli = Cli::parse_from(["spectrometer-service", "--host", "fd00::5"]);
+        assert_eq!(cli.advertised_address(), ("[fd00::5]".to_string(), 8100));
+    }
+
+    #[test]
+    fn test_bind_addresses() {
+        let cli = Cli::parse_from(["spectrometer-service"]);
+        assert_eq!(
+            cli.bind_addresses(),
+            ["0.0.0.0:8100".parse::<SocketAddr>().unwrap()]
+        );
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--listen",
+            "8200",
+            "--host",
+            "127.0.0.1,::",
+        ]);
+        assert_eq!(
+            cli.bind_addresses(),
+            [
+                "127.0.0.1:8200".parse::<SocketAddr>().unwrap(),
+                "[::]:8200".parse().unwrap()
+            ]
+        );
+
+        // Socket only
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--unix-socket",
+            "/run/spectrometer/api.sock",
+        ]);
+        assert!(cli.bind_addresses().is_empty());
+        assert!(Cli::try_parse_from(["spectrometer-service", "--host", "localhost"]).is_err());
+    }
+
     #[test]
     fn test_to_outlier_method() {
         let cli = Cli::parse_from(["spectrometer-service", "--outlier-method", "none"]);
-        assert!(matches!(cli.to_outlier_method(), OutlierMethod::None));
+        assert!(matches!(cli.to_outlier_method(None), OutlierMethod::None));
 
         let cli = Cli::parse_from([
             "spectrometer-service",
@@ -203,13 +813,269 @@ mod tests {
             "--grubbs-alpha",
             "0.01",
         ]);
-        if let OutlierMethod::Grubbs { alpha } = cli.to_outlier_method() {
+        if let OutlierMethod::Grubbs { alpha } = cli.to_outlier_method(None) {
             assert!((alpha - 0.01).abs() < 0.001);
         } else {
             panic!("Expected Grubbs method");
         }
     }
 
+    #[test]
+    fn test_to_sink_configs() {
+        let cli = Cli::parse_from(["spectrometer-service"]);
+        assert_eq!(
+            cli.to_sink_configs().unwrap(),
+            vec![DataSinkConfig::Monitoring(MonitoringSinkSettings {
+                push_buffer_size: 10000,
+                push_queue_size: 256,
+                push_queue_overflow: OverflowPolicy::DropOldest,
+                dead_letter: None,
+                endpoints: Vec::new(),
+            })]
+        );
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--sink",
+            "monitoring,stdout",
+            "--sink",
+            "csv:run.csv",
+            "--sink",
+            "runs:logs",
+            "--run-log-format",
+            "jsonl",
+        ]);
+        assert_eq!(
+            cli.to_sink_configs().unwrap(),
+            vec![
+                DataSinkConfig::Monitoring(MonitoringSinkSettings {
+                    push_buffer_size: 10000,
+                    push_queue_size: 256,
+                    push_queue_overflow: OverflowPolicy::DropOldest,
+                    dead_letter: None,
+                    endpoints: Vec::new(),
+                }),
+                DataSinkConfig::StdoutJson,
+                DataSinkConfig::Csv {
+                    path: PathBuf::from("run.csv")
+                },
+                DataSinkConfig::RunLog {
+                    dir: PathBuf::from("logs"),
+                    format: RunLogFormat::Jsonl,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_influx_sink_requires_settings() {
+        let cli = Cli::parse_from(["spectrometer-service", "--sink", "influx"]);
+        let err = cli.to_sink_configs().unwrap_err();
+        assert!(err.to_string().contains("--influx-url"));
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--sink",
+            "influx",
+            "--influx-url",
+            "http://localhost:8086",
+            "--influx-org",
+            "lab",
+            "--influx-bucket",
+            "coating",
+        ]);
+        assert!(matches!(
+            &cli.to_sink_configs().unwrap()[0],
+            DataSinkConfig::Influx(settings) if settings.bucket == "coating"
+        ));
+    }
+
+    #[test]
+    fn test_pushgateway_sink_settings() {
+        let cli = Cli::parse_from(["spectrometer-service", "--sink", "pushgateway"]);
+        let err = cli.to_sink_configs().unwrap_err();
+        assert!(err.to_string().contains("--pushgateway-url"));
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--sink",
+            "pushgateway",
+            "--pushgateway-url",
+            "http://pushgateway:9091",
+            "--pushgateway-instance",
+            "chamber-2",
+        ]);
+        assert_eq!(
+            cli.to_sink_configs().unwrap(),
+            [DataSinkConfig::Pushgateway(PushgatewaySettings {
+                url: "http://pushgateway:9091".to_string(),
+                job: "spectrometer".to_string(),
+                instance: "chamber-2".to_string(),
+                interval: std::time::Duration::from_secs(5),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_outlier_method_precedence() {
+        let saved = OutlierMethod::Grubbs { alpha: 0.01 };
+
+        let cli = Cli::parse_from(["spectrometer-service"]);
+        assert_eq!(cli.to_outlier_method(Some(&saved)), saved);
+        assert_eq!(cli.to_outlier_method(None), OutlierMethod::default());
+
+        let cli = Cli::parse_from(["spectrometer-service", "--outlier-method", "none"]);
+        assert_eq!(cli.to_outlier_method(Some(&saved)), OutlierMethod::None);
+
+        let cli = Cli::parse_from(["spectrometer-service", "--grubbs-alpha", "0.1"]);
+        assert_eq!(
+            cli.to_outlier_method(Some(&OutlierMethod::None)),
+            OutlierMethod::Grubbs { alpha: 0.1 }
+        );
+    }
+
+    #[test]
+    fn test_sigma_clip_outlier_method() {
+        let cli = Cli::parse_from(["spectrometer-service", "--outlier-method", "sigma-clip"]);
+        assert_eq!(
+            cli.to_outlier_method(None),
+            OutlierMethod::SigmaClip {
+                k: 3.0,
+                max_iterations: 5
+            }
+        );
+
+        // A lone --sigma-k selects sigma-clip and keeps the saved iterations
+        let saved = OutlierMethod::SigmaClip {
+            k: 3.0,
+            max_iterations: 2,
+        };
+        let cli = Cli::parse_from(["spectrometer-service", "--sigma-k", "2.5"]);
+        assert_eq!(
+            cli.to_outlier_method(Some(&saved)),
+            OutlierMethod::SigmaClip {
+                k: 2.5,
+                max_iterations: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_esd_outlier_method() {
+        let cli = Cli::parse_from(["spectrometer-service", "--outlier-method", "esd"]);
+        assert_eq!(
+            cli.to_outlier_method(None),
+            OutlierMethod::Esd {
+                alpha: 0.05,
+                max_outliers: 3
+            }
+        );
+
+        // A lone --esd-max-outliers selects ESD and keeps the saved alpha
+        let saved = OutlierMethod::Esd {
+            alpha: 0.01,
+            max_outliers: 3,
+        };
+        let cli = Cli::parse_from(["spectrometer-service", "--esd-max-outliers", "4"]);
+        assert_eq!(
+            cli.to_outlier_method(Some(&saved)),
+            OutlierMethod::Esd {
+                alpha: 0.01,
+                max_outliers: 4
+            }
+        );
+    }
+
+    #[test]
+    fn test_archive_options_require_archive() {
+        let result = Cli::try_parse_from(["spectrometer-service", "--archive-raw"]);
+        assert!(result.is_err());
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--archive",
+            "run.db",
+            "--archive-retention-days",
+            "30",
+        ]);
+        assert_eq!(cli.archive, Some(PathBuf::from("run.db")));
+        assert_eq!(cli.archive_retention_days, Some(30));
+        assert!(!cli.archive_raw);
+    }
+
+    #[test]
+    fn test_log_options() {
+        let settings = Cli::parse_from(["spectrometer-service"]).to_log_settings();
+        assert_eq!(settings.format, LogFormat::Text);
+        assert!(settings.file.is_none());
+
+        let result = Cli::try_parse_from(["spectrometer-service", "--log-rotation", "size"]);
+        assert!(result.is_err());
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--log-format",
+            "json",
+            "--log-file",
+            "/var/log/spectrometer/service.log",
+            "--log-rotation",
+            "size",
+            "--log-max-size-mb",
+            "50",
+            "serial",
+            "--device",
+            "/dev/ttyUSB0",
+            "--log-file",
+            "serial.log",
+        ]);
+        let settings = cli.to_log_settings();
+        assert_eq!(settings.format, LogFormat::Json);
+        assert_eq!(
+            settings.file,
+            Some(PathBuf::from("/var/log/spectrometer/service.log"))
+        );
+        assert_eq!(settings.rotation, LogRotation::Size);
+        assert_eq!(settings.max_size, 50 * 1024 * 1024);
+        assert_eq!(settings.max_files, 7);
+        // The serial mode's raw log is a separate option
+        let Some(Mode::Serial(args)) = cli.mode else {
+            panic!("Expected serial mode");
+        };
+        assert_eq!(args.log_file, Some(PathBuf::from("serial.log")));
+    }
+
+    #[test]
+    fn test_daemon_options() {
+        let cli = Cli::parse_from(["spectrometer-service"]);
+        assert!(!cli.daemon);
+
+        // A daemon has nowhere to log without a file
+        let result = Cli::try_parse_from(["spectrometer-service", "--daemon"]);
+        assert!(result.is_err());
+        let result = Cli::try_parse_from(["spectrometer-service", "--pid-file", "service.pid"]);
+        assert!(result.is_err());
+
+        let cli = Cli::parse_from([
+            "spectrometer-service",
+            "--daemon",
+            "--pid-file",
+            "/run/spectrometer.pid",
+            "--log-file",
+            "service.log",
+        ]);
+        assert!(cli.daemon);
+        assert_eq!(cli.pid_file, Some(PathBuf::from("/run/spectrometer.pid")));
+    }
+
+    #[test]
+    fn test_timestamp_mode() {
+        let cli = Cli::parse_from(["spectrometer-service"]);
+        assert_eq!(cli.timestamp_mode, TimestampMode::CycleMidpoint);
+
+        let cli = Cli::parse_from(["spectrometer-service", "--timestamp-mode", "arrival"]);
+        assert_eq!(cli.timestamp_mode, TimestampMode::Arrival);
+    }
+
     #[test]
     fn test_to_data_source_config_with_cli_overrides() {
         use crate::service::calibration::DeviceSettings;
@@ -253,6 +1119,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_rejects_invalid_device_settings() {
+        let parse = |flag: &str, value: &str| {
+            Cli::try_parse_from([
+                "spectrometer-service",
+                "serial",
+                "--device",
+                "COM3",
+                flag,
+                value,
+            ])
+            .map_err(|e| e.to_string())
+        };
+
+        let error = parse("--gain", "3").unwrap_err();
+        assert!(error.contains("Invalid GAIN value: 3"), "{error}");
+        let error = parse("--fadc", "300").unwrap_err();
+        assert!(error.contains("Invalid FADC value: 300"), "{error}");
+        let error = parse("--count", "20").unwrap_err();
+        assert!(error.contains("Invalid COUNT value: 20"), "{error}");
+        assert!(parse("--count", "many").is_err());
+
+        // Near-miss frequencies snap to the supported value
+        let Some(Mode::Serial(args)) = parse("--fadc", "62.49").unwrap().mode else {
+            panic!("Expected serial mode");
+        };
+        assert_eq!(args.fadc, Some(AdcFrequency::Hz62_5));
+    }
+
     #[test]
     fn test_to_data_source_config_falls_back_to_saved() {
         use crate::service::calibration::DeviceSettings;
@@ -0,0 +1,78 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::SpectrometerError;
+
+/// Detach from the terminal and, with `pid_file`, write the daemon's PID
+/// there. The working directory is kept, so relative paths stay valid.
+///
+/// Must run before any thread is started: only the calling thread survives
+/// the fork.
+#[cfg(unix)]
+pub fn daemonize(pid_file: Option<&Path>) -> Result<(), SpectrometerError> {
+    let mut daemon = daemonize::Daemonize::new().working_directory(std::env::current_dir()?);
+    if let Some(pid_file) = pid_file {
+        daemon = daemon.pid_file(pid_file);
+    }
+    daemon
+        .start()
+        .map_err(|e| SpectrometerError::Config(format!("cannot daemonize: {e}")))
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_pid_file: Option<&Path>) -> Result<(), SpectrometerError> {
+    Err(SpectrometerError::Config(
+        "--daemon is only supported on Unix".to_string(),
+    ))
+}
+
+/// Delete the PID file written by `daemonize` on a clean exit
+pub fn remove_pid_file(pid_file: &Path) {
+    if let Err(e) = std::fs::remove_file(pid_file) {
+        tracing::warn!("Failed to remove PID file {}: {e}", pid_file.display());
+    }
+}
+
+/// Tell systemd (`Type=notify`) the service is up. Does nothing when not
+/// started by systemd.
+pub fn notify_ready() {
+    #[cfg(unix)]
+    notify(sd_notify::NotifyState::Ready);
+}
+
+/// Tell systemd the service is shutting down
+pub fn notify_stopping() {
+    #[cfg(unix)]
+    notify(sd_notify::NotifyState::Stopping);
+}
+
+#[cfg(unix)]
+fn notify(state: sd_notify::NotifyState) {
+    if let Err(e) = sd_notify::notify(false, &[state]) {
+        tracing::warn!("Failed to notify systemd: {e}");
+    }
+}
+
+/// Interval at which systemd expects a keep-alive, halved for margin;
+/// `None` unless the unit sets `WatchdogSec=`
+pub fn systemd_watchdog_interval() -> Option<Duration> {
+    #[cfg(unix)]
+    {
+        let mut usec = 0;
+        if sd_notify::watchdog_enabled(false, &mut usec) {
+            return Some(Duration::from_micros(usec / 2));
+        }
+    }
+    None
+}
+
+/// Send `WATCHDOG=1` every `interval`, so systemd restarts the service if
+/// its runtime hangs
+pub async fn run_systemd_watchdog(interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        #[cfg(unix)]
+        notify(sd_notify::NotifyState::Watchdog);
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::DataSink;
+use crate::error::SpectrometerError;
+use crate::protocol::ProcessedMeasurement;
+
+pub const CSV_HEADER: &str =
+    "timestamp,dark_mean,full_mean,sample_mean,calibrated_reading,is_valid,validation_error";
+
+/// Appends measurements to a CSV file, writing the header for new files
+pub struct CsvSink {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl CsvSink {
+    pub fn create(path: &Path) -> Result<Self, SpectrometerError> {
+        let is_new = !path.exists() || path.metadata()?.len() == 0;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+
+        if is_new {
+            writeln!(writer, "{CSV_HEADER}")?;
+            writer.flush()?;
+        }
+
+        tracing::info!("Writing measurements to {}", path.display());
+
+        Ok(Self {
+            writer: Mutex::new(writer),
+        })
+    }
+}
+
+/// Quote a CSV field if it contains separators or quotes
+fn escape_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Format a measurement as one CSV line matching `CSV_HEADER`, without newline
+pub fn csv_row(measurement: &ProcessedMeasurement) -> String {
+    format!(
+        "{},{},{},{},{},{},{}",
+        measurement.timestamp.to_rfc3339(),
+        measurement.dark_mean,
+        measurement.full_mean,
+        measurement.sample_mean,
+        measurement.calibrated_reading,
+        measurement.is_valid,
+        escape_field(measurement.validation_error.as_deref().unwrap_or("")),
+    )
+}
+
+#[async_trait]
+impl DataSink for CsvSink {
+    async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError> {
+        let mut writer = self.writer.lock().unwrap();
+
+        writeln!(writer, "{}", csv_row(measurement))?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "csv"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_csv_sink_writes_header_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.csv");
+        let measurement = ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, 50.0);
+
+        CsvSink::create(&path)
+            .unwrap()
+            .write(&measurement)
+            .await
+            .unwrap();
+        CsvSink::create(&path)
+            .unwrap()
+            .write(&measurement.clone().with_error("bad, really".to_string()))
+            .await
+            .unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].ends_with(",50,true,"));
+        assert!(lines[2].ends_with(",false,\"bad, really\""));
+    }
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+
+use super::DataSink;
+use crate::error::SpectrometerError;
+use crate::protocol::ProcessedMeasurement;
+use crate::service::state::SharedState;
+
+/// InfluxDB measurement name for processed readings
+const MEASUREMENT_NAME: &str = "spectrometer";
+
+/// InfluxDB v2 connection settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct InfluxSettings {
+    /// Server base URL, e.g. `http://localhost:8086`
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    pub token: Option<String>,
+}
+
+/// Writes measurements to InfluxDB using the v2 line protocol API
+pub struct InfluxSink {
+    state: SharedState,
+    client: Client,
+    settings: InfluxSettings,
+}
+
+impl InfluxSink {
+    pub fn new(state: SharedState, settings: InfluxSettings) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            state,
+            client,
+            settings,
+        }
+    }
+}
+
+/// Escape a tag value (commas, spaces and equals signs are significant)
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Escape a string field value
+fn escape_string_field(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Format a measurement as a single line-protocol point with nanosecond timestamp
+fn to_line(
+    measurement: &ProcessedMeasurement,
+    material: &str,
+    spectrometer_id: Option<&str>,
+) -> String {
+    let mut line = String::from(MEASUREMENT_NAME);
+    if !material.is_empty() {
+        line.push_str(&format!(",material={}", escape_tag(material)));
+    }
+    if let Some(id) = spectrometer_id {
+        line.push_str(&format!(",spectrometer_id={}", escape_tag(id)));
+    }
+    line.push_str(&format!(",valid={}", measurement.is_valid));
+
+    line.push_str(&format!(
+        " calibrated_reading={},dark_mean={},full_mean={},sample_mean={}",
+        measurement.calibrated_reading,
+        measurement.dark_mean,
+        measurement.full_mean,
+        measurement.sample_mean,
+    ));
+    if let Some(error) = &measurement.validation_error {
+        line.push_str(&format!(
+            ",validation_error=\"{}\"",
+            escape_string_field(error)
+        ));
+    }
+
+    let nanos = measurement.timestamp.timestamp_nanos_opt().unwrap_or(0);
+    line.push_str(&format!(" {nanos}"));
+    line
+}
+
+#[async_trait]
+impl DataSink for InfluxSink {
+    async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError> {
+        let (material, spectrometer_id) = {
+            let state = self.state.read().await;
+            (
+                state.current_material.clone(),
+                state.spectrometer_id.clone(),
+            )
+        };
+        let body = to_line(measurement, &material, spectrometer_id.as_deref());
+
+        let url = format!("{}/api/v2/write", self.settings.url.trim_end_matches('/'));
+        let mut request = self
+            .client
+            .post(&url)
+            .query(&[
+                ("org", self.settings.org.as_str()),
+                ("bucket", self.settings.bucket.as_str()),
+                ("precision", "ns"),
+            ])
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body);
+        if let Some(token) = &self.settings.token {
+            request = request.header("Authorization", format!("Token {token}"));
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(SpectrometerError::DataSource(format!(
+                "InfluxDB returned {status}: {body}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "influx"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::service::state::create_shared_state;
+
+    fn measurement() -> ProcessedMeasurement {
+        ProcessedMeasurement::new(
+            Utc.timestamp_opt(1_700_000_000, 5).unwrap(),
+            100.0,
+            1000.0,
+            550.5,
+            50.0,
+        )
+    }
+
+    #[test]
+    fn test_line_protocol_format() {
+        let line = to_line(&measurement(), "H", Some("spec-1"));
+        assert_eq!(
+            line,
+            "spectrometer,material=H,spectrometer_id=spec-1,valid=true \
+             calibrated_reading=50,dark_mean=100,full_mean=1000,sample_mean=550.5 \
+             1700000000000000005"
+        );
+    }
+
+    #[test]
+    fn test_line_protocol_escaping() {
+        let invalid = measurement().with_error("say \"no\"".to_string());
+        let line = to_line(&invalid, "Ti O2,x=1", None);
+
+        assert!(line.starts_with("spectrometer,material=Ti\\ O2\\,x\\=1,valid=false "));
+        assert!(line.contains(",validation_error=\"say \\\"no\\\"\" "));
+    }
+
+    #[tokio::test]
+    async fn test_write_posts_with_token() {
+        use axum::extract::{Query, State};
+        use axum::http::HeaderMap;
+        use axum::{Router, routing::post};
+
+        type Received = Arc<Mutex<Vec<(HashMap<String, String>, String, String)>>>;
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                "/api/v2/write",
+                post(
+                    |State(received): State<Received>,
+                     Query(query): Query<HashMap<String, String>>,
+                     headers: HeaderMap,
+                     body: String| async move {
+                        let auth = headers["authorization"].to_str().unwrap().to_string();
+                        received.lock().unwrap().push((query, auth, body));
+                        axum::http::StatusCode::NO_CONTENT
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let sink = InfluxSink::new(
+            create_shared_state(),
+            InfluxSettings {
+                url: format!("http://{addr}/"),
+                org: "lab".to_string(),
+                bucket: "coating".to_string(),
+                token: Some("secret".to_string()),
+            },
+        );
+        sink.write(&measurement()).await.unwrap();
+
+        let received = received.lock().unwrap();
+        let (query, auth, body) = &received[0];
+        assert_eq!(query["org"], "lab");
+        assert_eq!(query["bucket"], "coating");
+        assert_eq!(query["precision"], "ns");
+        assert_eq!(auth, "Token secret");
+        assert!(body.starts_with("spectrometer,material=H,valid=true "));
+    }
+}
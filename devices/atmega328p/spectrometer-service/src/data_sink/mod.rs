@@ -0,0 +1,141 @@
+pub mod csv;
+pub mod influx;
+pub mod monitoring;
+pub mod pushgateway;
+pub mod run_log;
+pub mod stdout;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+
+use crate::error::SpectrometerError;
+use crate::protocol::ProcessedMeasurement;
+use crate::service::calibration::SharedConfig;
+use crate::service::events::EventBus;
+use crate::service::metrics::SharedMetrics;
+use crate::service::state::SharedState;
+
+/// Trait for outputs receiving processed measurements (monitoring API, files, ...)
+#[async_trait]
+pub trait DataSink: Send + Sync {
+    /// Deliver a processed measurement
+    async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError>;
+
+    /// Get the name of this data sink for logging
+    fn name(&self) -> &str;
+}
+
+/// Configuration for creating data sinks
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataSinkConfig {
+    /// Push to the registered OptiMonitor API from a sender task, buffering
+    /// failed pushes
+    Monitoring(monitoring::MonitoringSinkSettings),
+    /// Append measurements to a local CSV file
+    Csv { path: PathBuf },
+    /// Write each deposition run to a new file in `dir`
+    RunLog {
+        dir: PathBuf,
+        format: run_log::RunLogFormat,
+    },
+    /// Print one JSON object per measurement to stdout
+    StdoutJson,
+    /// Write line-protocol points to InfluxDB v2
+    Influx(influx::InfluxSettings),
+    /// Push the latest gauges to a Prometheus Pushgateway
+    Pushgateway(pushgateway::PushgatewaySettings),
+}
+
+impl DataSinkConfig {
+    /// Create a data sink from this configuration
+    pub fn create_sink(
+        &self,
+        state: &SharedState,
+        config: &SharedConfig,
+        events: &EventBus,
+        metrics: &SharedMetrics,
+    ) -> Result<Box<dyn DataSink>, SpectrometerError> {
+        Ok(match self {
+            DataSinkConfig::Monitoring(settings) => Box::new(monitoring::MonitoringSink::create(
+                state.clone(),
+                config.clone(),
+                events.clone(),
+                metrics.clone(),
+                settings,
+            )?),
+            DataSinkConfig::Csv { path } => Box::new(csv::CsvSink::create(path)?),
+            DataSinkConfig::RunLog { dir, format } => Box::new(run_log::RunLogSink::create(
+                dir,
+                *format,
+                state.clone(),
+                events,
+            )?),
+            DataSinkConfig::StdoutJson => Box::new(stdout::StdoutJsonSink::new()),
+            DataSinkConfig::Influx(settings) => {
+                Box::new(influx::InfluxSink::new(state.clone(), settings.clone()))
+            }
+            DataSinkConfig::Pushgateway(settings) => Box::new(pushgateway::PushgatewaySink::new(
+                state.clone(),
+                settings.clone(),
+            )),
+        })
+    }
+}
+
+/// Sink selection as given on the command line:
+/// `monitoring`, `csv:<path>`, `runs:<dir>`, `stdout`, `influx` or
+/// `pushgateway`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkArg {
+    Monitoring,
+    Csv(PathBuf),
+    Runs(PathBuf),
+    Stdout,
+    Influx,
+    Pushgateway,
+}
+
+impl FromStr for SinkArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("csv", path)) if !path.is_empty() => Ok(SinkArg::Csv(PathBuf::from(path))),
+            Some(("runs", dir)) if !dir.is_empty() => Ok(SinkArg::Runs(PathBuf::from(dir))),
+            None if s == "monitoring" => Ok(SinkArg::Monitoring),
+            None if s == "stdout" => Ok(SinkArg::Stdout),
+            None if s == "influx" => Ok(SinkArg::Influx),
+            None if s == "pushgateway" => Ok(SinkArg::Pushgateway),
+            _ => Err(format!(
+                "invalid sink '{s}', expected 'monitoring', 'csv:<path>', 'runs:<dir>', 'stdout', \
+                 'influx' or 'pushgateway'"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink_arg() {
+        assert_eq!("monitoring".parse::<SinkArg>(), Ok(SinkArg::Monitoring));
+        assert_eq!("stdout".parse::<SinkArg>(), Ok(SinkArg::Stdout));
+        assert_eq!(
+            "csv:/tmp/run.csv".parse::<SinkArg>(),
+            Ok(SinkArg::Csv(PathBuf::from("/tmp/run.csv")))
+        );
+        assert!("csv:".parse::<SinkArg>().is_err());
+        assert_eq!(
+            "runs:logs".parse::<SinkArg>(),
+            Ok(SinkArg::Runs(PathBuf::from("logs")))
+        );
+        assert!("runs:".parse::<SinkArg>().is_err());
+        assert_eq!("influx".parse::<SinkArg>(), Ok(SinkArg::Influx));
+        assert_eq!("pushgateway".parse::<SinkArg>(), Ok(SinkArg::Pushgateway));
+        assert!("kafka".parse::<SinkArg>().is_err());
+    }
+}
@@ -0,0 +1,824 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::StatusCode;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use super::DataSink;
+use crate::error::SpectrometerError;
+use crate::monitoring::{
+    DeadLetter, DeadLetterFile, EndpointMode, MonitoringClient, MonitoringEndpoint, MonitoringHttp,
+    MonitoringTls, OverflowPolicy, PendingPush, PushBuffer, PushQueue, ReadingDetails,
+};
+use crate::protocol::ProcessedMeasurement;
+use crate::service::calibration::{MonitoringSettings, SharedConfig};
+use crate::service::diagnostics::PushAttempt;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::metrics::SharedMetrics;
+use crate::service::state::SharedState;
+
+/// Queueing and delivery settings of the monitoring sink
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitoringSinkSettings {
+    pub push_buffer_size: usize,
+    pub push_queue_size: usize,
+    pub push_queue_overflow: OverflowPolicy,
+    /// JSONL file for pushes that can no longer be retried
+    pub dead_letter: Option<PathBuf>,
+    /// Mirror and failover endpoints, from `monitoring.endpoints` at startup
+    pub endpoints: Vec<MonitoringEndpoint>,
+}
+
+/// Pushes measurements to the registered OptiMonitor API, falling back to
+/// the failover endpoints, and copies them to each mirror endpoint.
+///
+/// Writes only queue the measurement; a sender task per destination
+/// delivers its queue, so a slow monitoring API does not hold up the
+/// processing loop, and a mirror that is down does not hold up the others.
+/// The configured overflow policy applies to the registered API's queue
+/// only; mirror queues always drop their oldest push, so `block` never
+/// waits on a mirror.
+pub struct MonitoringSink {
+    state: SharedState,
+    /// Read on each write for `push_series` (reloadable)
+    config: SharedConfig,
+    /// Queue of the registered API first, then one per mirror
+    queues: Vec<Arc<PushQueue>>,
+    senders: Vec<JoinHandle<()>>,
+}
+
+impl MonitoringSink {
+    /// Failed deliveries are published on `events` as `PushFailed` and
+    /// counted per endpoint in `metrics`; pushes given up on are appended
+    /// to the dead-letter file
+    pub fn create(
+        state: SharedState,
+        config: SharedConfig,
+        events: EventBus,
+        metrics: SharedMetrics,
+        settings: &MonitoringSinkSettings,
+    ) -> Result<Self, SpectrometerError> {
+        let dead_letter = settings
+            .dead_letter
+            .as_deref()
+            .map(DeadLetterFile::open)
+            .transpose()?
+            .map(Arc::new);
+        let (mirrors, failover): (Vec<_>, Vec<_>) = settings
+            .endpoints
+            .iter()
+            .cloned()
+            .partition(|endpoint| endpoint.mode == EndpointMode::Mirror);
+        let destinations = std::iter::once(Destination::Registered { failover })
+            .chain(mirrors.into_iter().map(Destination::Mirror));
+
+        let mut queues = Vec::new();
+        let mut senders = Vec::new();
+        for destination in destinations {
+            let overflow = match destination {
+                Destination::Registered { .. } => settings.push_queue_overflow,
+                Destination::Mirror(_) => OverflowPolicy::DropOldest,
+            };
+            let queue = Arc::new(PushQueue::new(settings.push_queue_size, overflow));
+            let sender = MonitoringSender::new(
+                state.clone(),
+                config.clone(),
+                events.clone(),
+                metrics.clone(),
+                settings.push_buffer_size,
+                dead_letter.clone(),
+                destination,
+            );
+            senders.push(tokio::spawn(sender.run(queue.clone())));
+            queues.push(queue);
+        }
+        Ok(Self {
+            state,
+            config,
+            queues,
+            senders,
+        })
+    }
+}
+
+impl Drop for MonitoringSink {
+    fn drop(&mut self) {
+        for sender in &self.senders {
+            sender.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl DataSink for MonitoringSink {
+    async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError> {
+        let control_wavelength = {
+            let state = self.state.read().await;
+            // Nothing to deliver to until registered
+            if !state.is_registered() {
+                return Ok(());
+            }
+            state.control_wavelength
+        };
+
+        let mut measurement = measurement.clone();
+        if !self.config.read().await.config.monitoring.push_series {
+            measurement.series = None;
+        }
+        let push = PendingPush {
+            measurement,
+            wavelength: control_wavelength,
+        };
+        for queue in &self.queues {
+            queue.enqueue(push.clone()).await;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "monitoring"
+    }
+}
+
+/// Where a sender delivers to
+enum Destination {
+    /// The registered monitoring API, then these failover endpoints in order
+    Registered { failover: Vec<MonitoringEndpoint> },
+    /// A mirror endpoint receiving a copy of every push
+    Mirror(MonitoringEndpoint),
+}
+
+/// Delivers queued measurements in order.
+///
+/// Failed pushes are buffered and replayed first on later pushes, so the
+/// monitoring side receives measurements in order. Pushes pushed out of the
+/// full buffer, or still in it when the sender stops, go to the dead-letter
+/// file.
+struct MonitoringSender {
+    state: SharedState,
+    /// Read on each push for the replay batch size, credentials and TLS
+    /// settings (reloadable)
+    config: SharedConfig,
+    events: EventBus,
+    metrics: SharedMetrics,
+    destination: Destination,
+    /// Client built for the TLS and HTTP settings it was created with
+    client: Mutex<Option<(MonitoringTls, MonitoringHttp, MonitoringClient)>>,
+    /// Pushes that failed while the monitoring API was unreachable
+    push_buffer: Mutex<PushBuffer>,
+    dead_letter: Option<Arc<DeadLetterFile>>,
+    /// Monitoring API URL and spectrometer ID buffered pushes are meant for
+    target: Mutex<Option<(String, String)>>,
+    /// Error of the last failed push, recorded with dead letters
+    last_error: Mutex<String>,
+}
+
+impl MonitoringSender {
+    fn new(
+        state: SharedState,
+        config: SharedConfig,
+        events: EventBus,
+        metrics: SharedMetrics,
+        push_buffer_size: usize,
+        dead_letter: Option<Arc<DeadLetterFile>>,
+        destination: Destination,
+    ) -> Self {
+        Self {
+            state,
+            config,
+            events,
+            metrics,
+            destination,
+            client: Mutex::new(None),
+            push_buffer: Mutex::new(PushBuffer::new(push_buffer_size)),
+            dead_letter,
+            target: Mutex::new(None),
+            last_error: Mutex::new(String::new()),
+        }
+    }
+
+    async fn run(self, queue: Arc<PushQueue>) {
+        loop {
+            let push = queue.next().await;
+            if let Err(e) = self.deliver(push).await {
+                tracing::error!("Failed to write to monitoring sink: {e}");
+            }
+        }
+    }
+
+    /// Client with the configured credentials, rebuilt when the TLS
+    /// settings changed
+    fn client(&self, settings: &MonitoringSettings) -> Result<MonitoringClient, SpectrometerError> {
+        let mut cached = self.client.lock().unwrap();
+        if cached
+            .as_ref()
+            .is_none_or(|(tls, http, _)| (tls, http) != (&settings.tls, &settings.http))
+        {
+            *cached = Some((
+                settings.tls.clone(),
+                settings.http.clone(),
+                MonitoringClient::build(&settings.tls, &settings.http)?,
+            ));
+        }
+        let (_, _, client) = cached.as_ref().unwrap();
+        Ok(client
+            .with_auth(settings.auth.clone())
+            .with_compression(settings.compression))
+    }
+
+    async fn send_push(
+        client: &MonitoringClient,
+        api_url: &str,
+        spectrometer_id: &str,
+        push: &PendingPush,
+    ) -> Result<StatusCode, SpectrometerError> {
+        client
+            .post_spectral_data(
+                api_url,
+                spectrometer_id,
+                &[push.measurement.calibrated_reading],
+                Some(&[push.wavelength]),
+                ReadingDetails {
+                    statistics: push
+                        .measurement
+                        .statistics
+                        .as_ref()
+                        .map(std::slice::from_ref),
+                    series: push.measurement.series.as_deref().map(std::slice::from_ref),
+                    thickness: push
+                        .measurement
+                        .thickness
+                        .as_ref()
+                        .map(std::slice::from_ref),
+                },
+                push.measurement.timestamp,
+            )
+            .instrument(tracing::info_span!("cycle", id = push.measurement.cycle_id))
+            .await
+    }
+
+    /// Monitoring API URLs and spectrometer IDs to try in order, or `None`
+    /// while not registered
+    async fn targets(&self) -> Option<Vec<(String, String)>> {
+        let (api_url, spectrometer_id) = {
+            let state = self.state.read().await;
+            (
+                state.monitoring_api_url.clone(),
+                state.spectrometer_id.clone(),
+            )
+        };
+        let (api_url, spectrometer_id) = (api_url?, spectrometer_id?);
+        let target = |endpoint: &MonitoringEndpoint| {
+            (
+                endpoint.url.clone(),
+                endpoint
+                    .spectrometer_id
+                    .clone()
+                    .unwrap_or_else(|| spectrometer_id.clone()),
+            )
+        };
+        Some(match &self.destination {
+            Destination::Registered { failover } => {
+                std::iter::once((api_url.clone(), spectrometer_id.clone()))
+                    .chain(failover.iter().map(target))
+                    .collect()
+            }
+            Destination::Mirror(endpoint) => vec![target(endpoint)],
+        })
+    }
+
+    /// Send `push` to the first target that takes it. Returns the error of
+    /// the last one tried when none did.
+    async fn send_any(
+        &self,
+        client: &MonitoringClient,
+        targets: &[(String, String)],
+        push: &PendingPush,
+    ) -> Result<(), SpectrometerError> {
+        let mut result = Ok(());
+        for (position, (api_url, spectrometer_id)) in targets.iter().enumerate() {
+            let started = Instant::now();
+            let sent = Self::send_push(client, api_url, spectrometer_id, push).await;
+            let latency = started.elapsed();
+            self.metrics.record_push(api_url, sent.is_ok());
+            self.state.write().await.push_log.record(PushAttempt {
+                at: Utc::now(),
+                endpoint: api_url.clone(),
+                timestamp: push.measurement.timestamp,
+                latency_ms: latency.as_secs_f64() * 1000.0,
+                status: match &sent {
+                    Ok(status) | Err(SpectrometerError::MonitoringStatus(status)) => {
+                        Some(status.as_u16())
+                    }
+                    Err(_) => None,
+                },
+                error: sent.as_ref().err().map(ToString::to_string),
+            });
+            result = sent.map(|_| ());
+            match &result {
+                Ok(()) => break,
+                Err(e) if position + 1 < targets.len() => {
+                    tracing::warn!("Push to {api_url} failed, trying the next endpoint: {e}");
+                }
+                Err(_) => {}
+            }
+        }
+        result
+    }
+
+    /// Replay buffered pushes oldest first, at most `replay_batch_size` per
+    /// write so a long backlog does not stall live processing.
+    /// Returns whether the buffer is now empty, or the first push error.
+    async fn replay_buffered(
+        &self,
+        client: &MonitoringClient,
+        targets: &[(String, String)],
+    ) -> Result<bool, SpectrometerError> {
+        let batch_size = self.config.read().await.config.monitoring.replay_batch_size;
+        let mut replayed = 0;
+        let mut result = Ok(());
+
+        while replayed < batch_size {
+            let Some(push) = self.push_buffer.lock().unwrap().front().cloned() else {
+                break;
+            };
+
+            if let Err(e) = self.send_any(client, targets, &push).await {
+                result = Err(e);
+                break;
+            }
+
+            self.push_buffer.lock().unwrap().pop_front();
+            replayed += 1;
+        }
+
+        let buffer = self.push_buffer.lock().unwrap();
+        if replayed > 0 {
+            tracing::info!(
+                "Replayed {} buffered measurements ({} remaining)",
+                replayed,
+                buffer.len()
+            );
+        }
+        result.map(|_| buffer.is_empty())
+    }
+
+    fn buffer_push(&self, push: PendingPush) {
+        let dropped = {
+            let mut buffer = self.push_buffer.lock().unwrap();
+            let dropped = buffer.enqueue(push);
+            if dropped.is_some() {
+                tracing::warn!(
+                    "Push buffer full, dropped oldest measurement ({} dropped so far)",
+                    buffer.dropped()
+                );
+            }
+            dropped
+        };
+        if let Some(push) = dropped {
+            self.write_dead_letter(push, "push buffer full");
+        }
+    }
+
+    /// Append a push given up on to the dead-letter file, if configured
+    fn write_dead_letter(&self, push: PendingPush, cause: &str) {
+        let (Some(file), Some((api_url, spectrometer_id))) =
+            (&self.dead_letter, self.target.lock().unwrap().clone())
+        else {
+            return;
+        };
+        let reason = format!("{cause}; last error: {}", self.last_error.lock().unwrap());
+        let letter = DeadLetter::new(push, &api_url, &spectrometer_id, reason);
+        if let Err(e) = file.append(&letter) {
+            tracing::error!(
+                "Failed to write dead letter to {}: {e}",
+                file.path().display()
+            );
+        }
+    }
+
+    /// Push a measurement, replaying buffered pushes first. A push that
+    /// fails is buffered; one made after the registration was lost is
+    /// dropped.
+    async fn deliver(&self, push: PendingPush) -> Result<(), SpectrometerError> {
+        let Some(targets) = self.targets().await else {
+            return Ok(());
+        };
+        let settings = self.config.read().await.config.monitoring.clone();
+        *self.target.lock().unwrap() = Some(targets[0].clone());
+
+        // Keep order: while older pushes are pending, queue behind them
+        let result = match self.client(&settings) {
+            Ok(client) => match self.replay_buffered(&client, &targets).await {
+                Ok(true) => self.send_any(&client, &targets, &push).await,
+                Ok(false) => {
+                    self.buffer_push(push);
+                    return Ok(());
+                }
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = &result {
+            *self.last_error.lock().unwrap() = e.to_string();
+            let sink = match &self.destination {
+                Destination::Registered { .. } => "monitoring".to_string(),
+                Destination::Mirror(endpoint) => format!("monitoring mirror {}", endpoint.url),
+            };
+            self.events.publish(ServiceEvent::PushFailed {
+                at: Utc::now(),
+                sink,
+                timestamp: push.measurement.timestamp,
+                error: e.to_string(),
+            });
+            self.buffer_push(push);
+        }
+        // Readiness and push alerts follow the registered API only
+        if let Destination::Registered { .. } = self.destination {
+            self.state
+                .write()
+                .await
+                .health
+                .record_push(result.as_ref().map(|_| ()).map_err(|e| e.to_string()));
+        }
+        result
+    }
+}
+
+impl Drop for MonitoringSender {
+    fn drop(&mut self) {
+        let pending: Vec<PendingPush> = {
+            let mut buffer = self.push_buffer.lock().unwrap();
+            std::iter::from_fn(|| buffer.pop_front()).collect()
+        };
+        if self.dead_letter.is_some() && !pending.is_empty() {
+            tracing::warn!(
+                "Writing {} undelivered measurements to the dead-letter file",
+                pending.len()
+            );
+        }
+        for push in pending {
+            self.write_dead_letter(push, "service stopped before delivery");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::monitoring::dead_letter::read_dead_letters;
+    use crate::protocol::FilteredSeries;
+    use crate::service::calibration::create_shared_config;
+    use crate::service::metrics::{MONITORING_PUSHES_TOTAL, create_shared_metrics};
+    use crate::service::state::create_shared_state;
+
+    fn measurement(reading: f64) -> ProcessedMeasurement {
+        ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, reading)
+    }
+
+    fn push(reading: f64) -> PendingPush {
+        PendingPush {
+            measurement: measurement(reading),
+            wavelength: 550.0,
+        }
+    }
+
+    fn registered_state(api_url: &str) -> SharedState {
+        let state = create_shared_state();
+        {
+            let mut s = state.try_write().unwrap();
+            s.monitoring_api_url = Some(api_url.to_string());
+            s.spectrometer_id = Some("spec-1".to_string());
+        }
+        state
+    }
+
+    fn registered_sender(api_url: &str) -> (MonitoringSender, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = create_shared_config(dir.path().join("cfg.toml"));
+        (
+            MonitoringSender::new(
+                registered_state(api_url),
+                config,
+                EventBus::default(),
+                create_shared_metrics(),
+                16,
+                None,
+                Destination::Registered {
+                    failover: Vec::new(),
+                },
+            ),
+            dir,
+        )
+    }
+
+    fn endpoint(url: &str, mode: EndpointMode) -> MonitoringEndpoint {
+        MonitoringEndpoint {
+            url: url.to_string(),
+            mode,
+            spectrometer_id: None,
+        }
+    }
+
+    fn registered_sink(
+        api_url: &str,
+        endpoints: Vec<MonitoringEndpoint>,
+    ) -> (MonitoringSink, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = create_shared_config(dir.path().join("cfg.toml"));
+        let sink = MonitoringSink::create(
+            registered_state(api_url),
+            config,
+            EventBus::default(),
+            create_shared_metrics(),
+            &MonitoringSinkSettings {
+                push_buffer_size: 16,
+                push_queue_size: 16,
+                push_queue_overflow: OverflowPolicy::DropOldest,
+                dead_letter: None,
+                endpoints,
+            },
+        )
+        .unwrap();
+        (sink, dir)
+    }
+
+    /// Minimal monitoring API that records the payloads it receives, each
+    /// after `delay`
+    async fn spawn_monitoring_api(delay: Duration) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        use axum::{Json, Router, extract::State, routing::post};
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                "/spectrometers/{id}/data",
+                post(
+                    move |State(received): State<Arc<Mutex<Vec<serde_json::Value>>>>,
+                          Json(body): Json<serde_json::Value>| async move {
+                        tokio::time::sleep(delay).await;
+                        received.lock().unwrap().push(body);
+                    },
+                ),
+            )
+            .with_state(received.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{addr}"), received)
+    }
+
+    fn readings(received: &Mutex<Vec<serde_json::Value>>) -> Vec<f64> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|body| body["calibrated_readings"][0].as_f64().unwrap())
+            .collect()
+    }
+
+    async fn wait_for(received: &Mutex<Vec<serde_json::Value>>, count: usize) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while received.lock().unwrap().len() < count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("pushes not delivered");
+    }
+
+    #[tokio::test]
+    async fn test_failed_push_is_buffered() {
+        let (sender, _dir) = registered_sender("http://127.0.0.1:1");
+        let mut events = sender.events.subscribe();
+
+        assert!(sender.deliver(push(1.0)).await.is_err());
+        // Replaying the first push fails, so the second is queued behind it
+        assert!(sender.deliver(push(2.0)).await.is_err());
+
+        assert_eq!(sender.push_buffer.lock().unwrap().len(), 2);
+        for _ in 0..2 {
+            assert!(matches!(
+                events.try_recv().unwrap(),
+                ServiceEvent::PushFailed { sink, .. } if sink == "monitoring"
+            ));
+        }
+        let last_push = sender.state.read().await.health.last_push.clone().unwrap();
+        assert!(!last_push.success);
+    }
+
+    #[tokio::test]
+    async fn test_given_up_pushes_dead_lettered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead.jsonl");
+        let sender = MonitoringSender::new(
+            registered_state("http://127.0.0.1:1"),
+            create_shared_config(dir.path().join("cfg.toml")),
+            EventBus::default(),
+            create_shared_metrics(),
+            2,
+            Some(Arc::new(DeadLetterFile::open(&path).unwrap())),
+            Destination::Registered {
+                failover: Vec::new(),
+            },
+        );
+
+        for reading in [1.0, 2.0, 3.0] {
+            assert!(sender.deliver(push(reading)).await.is_err());
+        }
+        let letters = read_dead_letters(&path).unwrap();
+        assert_eq!(letters.len(), 1);
+        assert_eq!(letters[0].measurement.calibrated_reading, 1.0);
+        assert_eq!(letters[0].api_url, "http://127.0.0.1:1");
+        assert_eq!(letters[0].spectrometer_id, "spec-1");
+        assert!(
+            letters[0]
+                .reason
+                .starts_with("push buffer full; last error: ")
+        );
+
+        // The rest when the sender stops
+        drop(sender);
+        let readings: Vec<f64> = read_dead_letters(&path)
+            .unwrap()
+            .iter()
+            .map(|letter| letter.measurement.calibrated_reading)
+            .collect();
+        assert_eq!(readings, [1.0, 2.0, 3.0]);
+    }
+
+    #[tokio::test]
+    async fn test_buffered_pushes_replayed_in_order() {
+        let (api_url, received) = spawn_monitoring_api(Duration::ZERO).await;
+        let (sender, _dir) = registered_sender(&api_url);
+
+        for reading in [1.0, 2.0] {
+            sender.buffer_push(push(reading));
+        }
+
+        sender.deliver(push(3.0)).await.unwrap();
+
+        assert_eq!(readings(&received), vec![1.0, 2.0, 3.0]);
+        assert!(sender.push_buffer.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_push_after_unregister_dropped() {
+        let (sender, _dir) = registered_sender("http://127.0.0.1:1");
+        sender.state.write().await.monitoring_api_url = None;
+
+        sender.deliver(push(1.0)).await.unwrap();
+        assert!(sender.push_buffer.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failover_when_registered_api_down() {
+        let (standby_url, received) = spawn_monitoring_api(Duration::ZERO).await;
+        let dir = tempfile::tempdir().unwrap();
+        let sender = MonitoringSender::new(
+            registered_state("http://127.0.0.1:1"),
+            create_shared_config(dir.path().join("cfg.toml")),
+            EventBus::default(),
+            create_shared_metrics(),
+            16,
+            None,
+            Destination::Registered {
+                failover: vec![endpoint(&standby_url, EndpointMode::Failover)],
+            },
+        );
+
+        sender.deliver(push(1.0)).await.unwrap();
+        assert_eq!(readings(&received), vec![1.0]);
+        assert!(sender.push_buffer.lock().unwrap().is_empty());
+        let registry = sender.metrics.registry();
+        let pushes = |endpoint: &str, result: &str| {
+            registry.get(
+                MONITORING_PUSHES_TOTAL,
+                &[("endpoint", endpoint), ("result", result)],
+            )
+        };
+        assert_eq!(pushes("http://127.0.0.1:1", "failed"), Some(1.0));
+        assert_eq!(pushes(&standby_url, "delivered"), Some(1.0));
+        let state = sender.state.read().await;
+        let attempts: Vec<_> = state.push_log.recent().collect();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].status, None);
+        assert!(attempts[0].error.is_some());
+        assert_eq!(attempts[1].endpoint, standby_url);
+        assert_eq!(attempts[1].status, Some(200));
+        drop(state);
+        assert!(
+            sender
+                .state
+                .read()
+                .await
+                .health
+                .last_push
+                .as_ref()
+                .unwrap()
+                .success
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mirror_gets_copy_independently() {
+        let (api_url, received) = spawn_monitoring_api(Duration::ZERO).await;
+        let (staging_url, mirrored) = spawn_monitoring_api(Duration::ZERO).await;
+        let (sink, _dir) = registered_sink(
+            &api_url,
+            vec![
+                endpoint(&staging_url, EndpointMode::Mirror),
+                endpoint("http://127.0.0.1:1", EndpointMode::Mirror),
+            ],
+        );
+
+        for reading in [1.0, 2.0] {
+            sink.write(&measurement(reading)).await.unwrap();
+        }
+        wait_for(&received, 2).await;
+        wait_for(&mirrored, 2).await;
+        assert_eq!(readings(&mirrored), vec![1.0, 2.0]);
+        // The unreachable mirror does not count against readiness
+        assert_eq!(sink.state.read().await.health.push_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_queue_does_not_wait_for_mirror() {
+        let (api_url, received) = spawn_monitoring_api(Duration::ZERO).await;
+        // Accepts connections but never answers, so the mirror's queue fills
+        let unreachable = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mirror_url = format!("http://{}", unreachable.local_addr().unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let sink = MonitoringSink::create(
+            registered_state(&api_url),
+            create_shared_config(dir.path().join("cfg.toml")),
+            EventBus::default(),
+            create_shared_metrics(),
+            &MonitoringSinkSettings {
+                push_buffer_size: 16,
+                push_queue_size: 1,
+                push_queue_overflow: OverflowPolicy::Block,
+                dead_letter: None,
+                endpoints: vec![endpoint(&mirror_url, EndpointMode::Mirror)],
+            },
+        )
+        .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            for reading in [1.0, 2.0, 3.0, 4.0, 5.0] {
+                sink.write(&measurement(reading)).await.unwrap();
+            }
+        })
+        .await
+        .expect("write waited for the unreachable mirror");
+
+        wait_for(&received, 5).await;
+        assert_eq!(readings(&received), vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[tokio::test]
+    async fn test_write_does_not_wait_for_api() {
+        let (api_url, received) = spawn_monitoring_api(Duration::from_millis(200)).await;
+        let (sink, _dir) = registered_sink(&api_url, Vec::new());
+
+        tokio::time::timeout(Duration::from_millis(100), async {
+            for reading in [1.0, 2.0, 3.0] {
+                sink.write(&measurement(reading)).await.unwrap();
+            }
+        })
+        .await
+        .expect("write waited for the monitoring API");
+
+        wait_for(&received, 3).await;
+        assert_eq!(readings(&received), vec![1.0, 2.0, 3.0]);
+    }
+
+    #[tokio::test]
+    async fn test_series_pushed_only_when_enabled() {
+        let (api_url, received) = spawn_monitoring_api(Duration::ZERO).await;
+        let (sink, _dir) = registered_sink(&api_url, Vec::new());
+        let mut with_series = measurement(1.0);
+        with_series.series = Some(Box::new(FilteredSeries {
+            dark: vec![100.0],
+            full: vec![1000.0],
+            sample: vec![550.0],
+        }));
+
+        sink.write(&with_series).await.unwrap();
+        sink.config.write().await.config.monitoring.push_series = true;
+        sink.write(&with_series).await.unwrap();
+
+        wait_for(&received, 2).await;
+        let received = received.lock().unwrap();
+        assert!(received[0].get("series").is_none());
+        assert_eq!(received[1]["series"][0]["sample"][0], 550.0);
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Url};
+
+use super::DataSink;
+use crate::error::SpectrometerError;
+use crate::protocol::ProcessedMeasurement;
+use crate::service::metrics::{MetricKind, MetricsRegistry, READING, READING_RATE};
+use crate::service::state::SharedState;
+
+pub const MEASUREMENT_VALID: &str = "spectrometer_measurement_valid";
+pub const LAST_MEASUREMENT: &str = "spectrometer_last_measurement_timestamp_seconds";
+
+/// Prometheus Pushgateway connection settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct PushgatewaySettings {
+    /// Server base URL, e.g. `http://pushgateway:9091`
+    pub url: String,
+    pub job: String,
+    pub instance: String,
+    /// Shortest time between two pushes; measurements in between only
+    /// update the values of the next one
+    pub interval: Duration,
+}
+
+/// Latest values pushed as gauges
+#[derive(Debug, Default)]
+struct Latest {
+    pushed_at: Option<Instant>,
+    /// Previous valid reading of the layer, for the rate
+    previous: Option<(u32, DateTime<Utc>, f64)>,
+    rate: Option<f64>,
+}
+
+/// Pushes the calibrated reading, its rate and validity as gauges to a
+/// Prometheus Pushgateway, replacing the job/instance group each time, for
+/// sites that do not scrape `/metrics`
+pub struct PushgatewaySink {
+    state: SharedState,
+    client: Client,
+    settings: PushgatewaySettings,
+    latest: Mutex<Latest>,
+}
+
+impl PushgatewaySink {
+    pub fn new(state: SharedState, settings: PushgatewaySettings) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .expect("Failed to create HTTP client");
+
+        Self {
+            state,
+            client,
+            settings,
+            latest: Mutex::new(Latest::default()),
+        }
+    }
+
+    /// Group URL: `<url>/metrics/job/<job>/instance/<instance>`
+    fn group_url(&self) -> Result<Url, SpectrometerError> {
+        let invalid = || {
+            SpectrometerError::Config(format!("invalid Pushgateway URL {:?}", self.settings.url))
+        };
+        let mut url = Url::parse(&self.settings.url).map_err(|_| invalid())?;
+        url.path_segments_mut()
+            .map_err(|_| invalid())?
+            .pop_if_empty()
+            .extend([
+                "metrics",
+                "job",
+                &self.settings.job,
+                "instance",
+                &self.settings.instance,
+            ]);
+        Ok(url)
+    }
+}
+
+/// Gauges of one measurement in the Prometheus text format
+fn render(
+    measurement: &ProcessedMeasurement,
+    material: &str,
+    layer: u32,
+    rate: Option<f64>,
+) -> String {
+    let registry = MetricsRegistry::default();
+    registry.describe(
+        READING,
+        MetricKind::Gauge,
+        "Latest calibrated reading (%) for the current material and layer",
+    );
+    registry.describe(
+        READING_RATE,
+        MetricKind::Gauge,
+        "Change of the calibrated reading in %/s within the current layer",
+    );
+    registry.describe(
+        MEASUREMENT_VALID,
+        MetricKind::Gauge,
+        "1 if the latest measurement passed validation, else 0",
+    );
+    registry.describe(
+        LAST_MEASUREMENT,
+        MetricKind::Gauge,
+        "Time of the latest measurement in seconds since the Unix epoch",
+    );
+
+    let layer = layer.to_string();
+    let labels = [("material", material), ("layer", layer.as_str())];
+    if measurement.is_valid {
+        registry.set(READING, &labels, measurement.calibrated_reading);
+    }
+    if let Some(rate) = rate {
+        registry.set(READING_RATE, &labels, rate);
+    }
+    registry.set(
+        MEASUREMENT_VALID,
+        &[],
+        if measurement.is_valid { 1.0 } else { 0.0 },
+    );
+    registry.set(
+        LAST_MEASUREMENT,
+        &[],
+        measurement.timestamp.timestamp_millis() as f64 / 1000.0,
+    );
+    registry.render()
+}
+
+#[async_trait]
+impl DataSink for PushgatewaySink {
+    async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError> {
+        let (material, layer) = {
+            let state = self.state.read().await;
+            (state.current_material.clone(), state.layer())
+        };
+
+        let rate = {
+            let mut latest = self.latest.lock().unwrap();
+            if measurement.is_valid {
+                let reading = measurement.calibrated_reading;
+                latest.rate = match latest.previous {
+                    Some((previous_layer, at, previous)) if previous_layer == layer => {
+                        let dt = (measurement.timestamp - at).as_seconds_f64();
+                        if dt > 0.0 {
+                            Some((reading - previous) / dt)
+                        } else {
+                            latest.rate
+                        }
+                    }
+                    // A new layer starts without a rate
+                    _ => None,
+                };
+                latest.previous = Some((layer, measurement.timestamp, reading));
+            }
+            let now = Instant::now();
+            if latest
+                .pushed_at
+                .is_some_and(|at| now.duration_since(at) < self.settings.interval)
+            {
+                return Ok(());
+            }
+            latest.pushed_at = Some(now);
+            latest.rate
+        };
+
+        let response = self
+            .client
+            .put(self.group_url()?)
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(render(measurement, &material, layer, rate))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(SpectrometerError::DataSource(format!(
+                "Pushgateway returned {status}: {body}"
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "pushgateway"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::service::state::create_shared_state;
+
+    fn measurement(seconds: i64, reading: f64) -> ProcessedMeasurement {
+        ProcessedMeasurement::new(
+            Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap(),
+            100.0,
+            1000.0,
+            550.0,
+            reading,
+        )
+    }
+
+    #[test]
+    fn test_render_gauges() {
+        let text = render(&measurement(0, 42.5), "H", 3, Some(-0.25));
+        assert!(
+            text.contains("spectrometer_calibrated_reading{material=\"H\",layer=\"3\"} 42.5\n")
+        );
+        assert!(text.contains("spectrometer_reading_rate{material=\"H\",layer=\"3\"} -0.25\n"));
+        assert!(text.contains("spectrometer_measurement_valid 1\n"));
+        assert!(text.contains("spectrometer_last_measurement_timestamp_seconds 1700000000\n"));
+
+        let invalid = measurement(0, 150.0).with_error("out of range".to_string());
+        let text = render(&invalid, "H", 3, None);
+        assert!(!text.contains("spectrometer_calibrated_reading{"));
+        assert!(text.contains("spectrometer_measurement_valid 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_write_puts_group_at_interval() {
+        use axum::extract::{Path, State};
+        use axum::{Router, routing::put};
+
+        type Received = Arc<Mutex<Vec<(String, String, String)>>>;
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let app = Router::new()
+            .route(
+                "/metrics/job/{job}/instance/{instance}",
+                put(
+                    |State(received): State<Received>,
+                     Path((job, instance)): Path<(String, String)>,
+                     body: String| async move {
+                        received.lock().unwrap().push((job, instance, body));
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let sink = PushgatewaySink::new(
+            create_shared_state(),
+            PushgatewaySettings {
+                url: format!("http://{addr}/"),
+                job: "spectrometer".to_string(),
+                instance: "chamber 2".to_string(),
+                interval: Duration::from_secs(60),
+            },
+        );
+        sink.write(&measurement(0, 40.0)).await.unwrap();
+        // Within the interval: not pushed, but folded into the rate
+        sink.write(&measurement(2, 44.0)).await.unwrap();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (job, instance, body) = &received[0];
+        assert_eq!(
+            (job.as_str(), instance.as_str()),
+            ("spectrometer", "chamber 2")
+        );
+        assert!(body.contains("spectrometer_calibrated_reading{material=\"H\",layer=\"0\"} 40\n"));
+        assert_eq!(sink.latest.lock().unwrap().rate, Some(2.0));
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::DataSink;
+use super::csv::{CSV_HEADER, csv_row};
+use crate::error::SpectrometerError;
+use crate::protocol::ProcessedMeasurement;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::state::SharedState;
+
+/// File format of the per-run logs
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RunLogFormat {
+    /// Same columns as the `csv` sink (default)
+    #[default]
+    Csv,
+    /// One JSON object per measurement, as the `stdout` sink writes
+    Jsonl,
+}
+
+impl RunLogFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RunLogFormat::Csv => "csv",
+            RunLogFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// File of the run being logged
+struct OpenRun {
+    run_id: String,
+    writer: BufWriter<File>,
+}
+
+/// Per-run files in one directory
+struct RunFiles {
+    dir: PathBuf,
+    format: RunLogFormat,
+    current: Option<OpenRun>,
+}
+
+impl RunFiles {
+    /// File of run `run_id`: `run-<run_id>.<csv|jsonl>`
+    fn path(&self, run_id: &str) -> PathBuf {
+        self.dir
+            .join(format!("run-{run_id}.{}", self.format.extension()))
+    }
+
+    /// Make `run_id` the open run, closing any other. A run resumed after a
+    /// pause appends to its file.
+    fn open(&mut self, run_id: &str) -> Result<&mut OpenRun, SpectrometerError> {
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|open| open.run_id != run_id)
+        {
+            self.close();
+        }
+        if self.current.is_none() {
+            let path = self.path(run_id);
+            self.current = Some(OpenRun {
+                run_id: run_id.to_string(),
+                writer: open_log(&path, self.format)?,
+            });
+            tracing::info!("Logging run {run_id} to {}", path.display());
+        }
+        Ok(self.current.as_mut().expect("opened above"))
+    }
+
+    fn close(&mut self) {
+        if let Some(mut open) = self.current.take() {
+            if let Err(e) = open.writer.flush() {
+                tracing::error!("Failed to flush log of run {}: {e}", open.run_id);
+            }
+            tracing::info!("Closed log of run {}", open.run_id);
+        }
+    }
+}
+
+fn open_log(path: &Path, format: RunLogFormat) -> Result<BufWriter<File>, SpectrometerError> {
+    let is_new = !path.exists() || path.metadata()?.len() == 0;
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    if is_new && format == RunLogFormat::Csv {
+        writeln!(writer, "{CSV_HEADER}")?;
+        writer.flush()?;
+    }
+    Ok(writer)
+}
+
+/// Writes the measurements of each deposition run to a file of its own,
+/// opened when the run starts and closed when it stops
+pub struct RunLogSink {
+    state: SharedState,
+    files: Arc<Mutex<RunFiles>>,
+}
+
+impl RunLogSink {
+    /// Log runs into `dir`, creating it if needed, and follow deposition
+    /// starts and stops on `events`
+    pub fn create(
+        dir: &Path,
+        format: RunLogFormat,
+        state: SharedState,
+        events: &EventBus,
+    ) -> Result<Self, SpectrometerError> {
+        std::fs::create_dir_all(dir)?;
+        let files = Arc::new(Mutex::new(RunFiles {
+            dir: dir.to_path_buf(),
+            format,
+            current: None,
+        }));
+        tokio::spawn(follow_runs(
+            events.subscribe(),
+            state.clone(),
+            files.clone(),
+        ));
+        tracing::info!("Logging each run to {}", dir.display());
+
+        Ok(Self { state, files })
+    }
+}
+
+/// Open the run's file when deposition starts and close it when it stops
+async fn follow_runs(
+    mut events: tokio::sync::broadcast::Receiver<ServiceEvent>,
+    state: SharedState,
+    files: Arc<Mutex<RunFiles>>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(ServiceEvent::DepositionStarted { .. }) => {
+                let run_id = state.read().await.run_id.clone();
+                if let Some(run_id) = run_id
+                    && let Err(e) = files.lock().unwrap().open(&run_id)
+                {
+                    tracing::error!("Failed to open log of run {run_id}: {e}");
+                }
+            }
+            Ok(ServiceEvent::DepositionStopped { .. }) => files.lock().unwrap().close(),
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+#[async_trait]
+impl DataSink for RunLogSink {
+    async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError> {
+        let Some(run_id) = self.state.read().await.run_id.clone() else {
+            return Ok(());
+        };
+        let mut files = self.files.lock().unwrap();
+        let format = files.format;
+        let writer = &mut files.open(&run_id)?.writer;
+
+        match format {
+            RunLogFormat::Csv => writeln!(writer, "{}", csv_row(measurement))?,
+            RunLogFormat::Jsonl => {
+                let line = serde_json::to_string(measurement)
+                    .map_err(|e| SpectrometerError::Validation(e.to_string()))?;
+                writeln!(writer, "{line}")?;
+            }
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "runs"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::service::state::create_shared_state;
+
+    /// Let the run follower handle published events
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    async fn start_run(state: &SharedState, events: &EventBus, run_id: &str) {
+        state.write().await.run_id = Some(run_id.to_string());
+        events.publish(ServiceEvent::DepositionStarted { at: Utc::now() });
+        settle().await;
+    }
+
+    #[tokio::test]
+    async fn test_file_per_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = create_shared_state();
+        let events = EventBus::default();
+        let sink =
+            RunLogSink::create(dir.path(), RunLogFormat::Csv, state.clone(), &events).unwrap();
+        let measurement = ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, 50.0);
+
+        start_run(&state, &events, "20250115T103000Z").await;
+        let first = dir.path().join("run-20250115T103000Z.csv");
+        assert!(first.exists());
+        sink.write(&measurement).await.unwrap();
+        sink.write(&measurement).await.unwrap();
+        events.publish(ServiceEvent::DepositionStopped { at: Utc::now() });
+        settle().await;
+        assert!(sink.files.lock().unwrap().current.is_none());
+
+        start_run(&state, &events, "20250115T110000Z").await;
+        sink.write(&measurement).await.unwrap();
+
+        let first = std::fs::read_to_string(first).unwrap();
+        assert_eq!(first.lines().count(), 3);
+        assert_eq!(first.lines().next(), Some(CSV_HEADER));
+        let second = std::fs::read_to_string(dir.path().join("run-20250115T110000Z.csv")).unwrap();
+        assert_eq!(second.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_jsonl_and_resumed_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = create_shared_state();
+        let events = EventBus::default();
+        let sink =
+            RunLogSink::create(dir.path(), RunLogFormat::Jsonl, state.clone(), &events).unwrap();
+        let measurement = ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, 50.0);
+
+        // Outside a run nothing is written
+        sink.write(&measurement).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        start_run(&state, &events, "20250115T103000Z").await;
+        sink.write(&measurement).await.unwrap();
+        // Resumed after a pause: same run, same file
+        events.publish(ServiceEvent::DepositionStarted { at: Utc::now() });
+        settle().await;
+        sink.write(&measurement).await.unwrap();
+
+        let content =
+            std::fs::read_to_string(dir.path().join("run-20250115T103000Z.jsonl")).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let parsed: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed["calibrated_reading"], 50.0);
+    }
+}
@@ -0,0 +1,28 @@
+use async_trait::async_trait;
+
+use super::DataSink;
+use crate::error::SpectrometerError;
+use crate::protocol::ProcessedMeasurement;
+
+/// Prints each measurement as a single JSON line on stdout
+pub struct StdoutJsonSink;
+
+impl StdoutJsonSink {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl DataSink for StdoutJsonSink {
+    async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError> {
+        let line = serde_json::to_string(measurement)
+            .map_err(|e| SpectrometerError::Validation(e.to_string()))?;
+        println!("{line}");
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "stdout"
+    }
+}
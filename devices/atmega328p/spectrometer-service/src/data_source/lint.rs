@@ -0,0 +1,284 @@
+use std::fmt;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use super::playback::PlaybackDataSource;
+use crate::error::ProtocolError;
+use crate::protocol::{CycleSettings, ParsedLine, ProtocolIssueKind, ProtocolKind};
+use crate::service::diagnostics::ProtocolCounts;
+
+/// Number of problem lines listed in the report
+pub const MAX_REPORTED_PROBLEMS: usize = 50;
+
+/// Line that keeps a log from playing back cleanly
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogProblem {
+    /// 1-based line number in the file
+    pub line_number: usize,
+    pub description: String,
+    pub line: String,
+}
+
+/// Result of checking a log offline with `lint_log`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogReport {
+    pub lines: usize,
+    pub timestamped: bool,
+    /// Lines playback ignores: lines without a timestamp in a timestamped log
+    pub skipped_lines: usize,
+    /// Cycles the log plays back as
+    pub cycles: usize,
+    /// End-of-cycle markers before every series arrived, plus series left
+    /// over at the end of the file
+    pub incomplete_cycles: usize,
+    /// Lines timestamped earlier than the line before them
+    pub timestamp_regressions: usize,
+    pub counts: ProtocolCounts,
+    /// Number of problems found; only the first `MAX_REPORTED_PROBLEMS` are
+    /// kept in `problems`
+    pub problem_count: usize,
+    pub problems: Vec<LogProblem>,
+}
+
+impl LogReport {
+    /// Whether the log plays back cleanly: at least one cycle and no
+    /// problems. `ERROR` and cycle-missing lines come from the firmware and
+    /// are counted without failing the log.
+    pub fn is_valid(&self) -> bool {
+        self.cycles > 0 && self.problem_count == 0
+    }
+
+    fn problem(&mut self, line_number: usize, description: impl Into<String>, line: &str) {
+        self.problem_count += 1;
+        if self.problems.len() < MAX_REPORTED_PROBLEMS {
+            self.problems.push(LogProblem {
+                line_number,
+                description: description.into(),
+                line: line.trim().to_string(),
+            });
+        }
+    }
+}
+
+impl fmt::Display for LogReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = if self.timestamped {
+            "timestamped"
+        } else {
+            "raw"
+        };
+        writeln!(
+            f,
+            "Format: {format}, {} lines ({} skipped)",
+            self.lines, self.skipped_lines
+        )?;
+        writeln!(
+            f,
+            "Cycles: {} complete, {} incomplete",
+            self.cycles, self.incomplete_cycles
+        )?;
+        writeln!(f, "Timestamp regressions: {}", self.timestamp_regressions)?;
+
+        let c = &self.counts;
+        writeln!(
+            f,
+            "Protocol: {} unknown, {} malformed, {} checksum errors, {} out of range, \
+             {} stale cycles, {} firmware errors, {} cycle missing",
+            c.unknown,
+            c.malformed,
+            c.checksum_errors,
+            c.out_of_range,
+            c.stale_cycles,
+            c.errors,
+            c.cycle_missing
+        )?;
+
+        if !self.problems.is_empty() {
+            writeln!(f, "Problems:")?;
+            for problem in &self.problems {
+                write!(f, "  line {}: {}", problem.line_number, problem.description)?;
+                if problem.line.is_empty() {
+                    writeln!(f)?;
+                } else {
+                    writeln!(f, ": {}", problem.line)?;
+                }
+            }
+            let more = self.problem_count - self.problems.len();
+            if more > 0 {
+                writeln!(f, "  ... and {more} more")?;
+            }
+        }
+
+        let result = if self.is_valid() { "valid" } else { "INVALID" };
+        write!(f, "Result: {result}")
+    }
+}
+
+/// Why a line with a protocol issue breaks playback, `None` for firmware
+/// messages that are only counted
+fn describe_issue(parsed: &ParsedLine, kind: ProtocolIssueKind) -> Option<String> {
+    match (parsed, kind) {
+        (ParsedLine::Malformed(ProtocolError::MalformedLine { keyword, .. }), _) => {
+            Some(format!("malformed {keyword} line"))
+        }
+        (ParsedLine::Malformed(error), _) => Some(error.to_string()),
+        (_, ProtocolIssueKind::Unknown) => Some("unknown line".to_string()),
+        (_, ProtocolIssueKind::Checksum) => Some("checksum mismatch".to_string()),
+        (_, ProtocolIssueKind::OutOfRange) => Some("value above ADC range".to_string()),
+        _ => None,
+    }
+}
+
+/// Parse a whole log the way playback would, without pacing, and report
+/// everything that would make its playback lossy or misleading. Malformed
+/// lines are reported as with `--strict-protocol`.
+pub async fn lint_log(
+    path: &Path,
+    protocol: ProtocolKind,
+    settings: CycleSettings,
+) -> std::io::Result<LogReport> {
+    let timestamped = PlaybackDataSource::detect_has_timestamps(path, protocol).await;
+    // Raw logs carry no time to apply the cycle timeout with
+    let timeout = settings.timeout.filter(|_| timestamped);
+    let mut protocol = protocol.create(CycleSettings {
+        timeout,
+        strict: true,
+        ..settings
+    });
+
+    let mut report = LogReport {
+        timestamped,
+        ..Default::default()
+    };
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let mut last_timestamp: Option<DateTime<Utc>> = None;
+    // Series received since the last end-of-cycle marker
+    let mut pending_series = false;
+
+    while let Some(line) = lines.next_line().await? {
+        report.lines += 1;
+        let line_number = report.lines;
+
+        let (content, timestamp) = if timestamped {
+            let Some(timestamped) = PlaybackDataSource::parse_timestamped_line(&line) else {
+                report.skipped_lines += 1;
+                continue;
+            };
+            if let Some(last) = last_timestamp
+                && timestamped.timestamp < last
+            {
+                report.timestamp_regressions += 1;
+                let back = (last - timestamped.timestamp).num_milliseconds();
+                report.problem(line_number, format!("timestamp {back}ms earlier"), &line);
+            }
+            last_timestamp = Some(timestamped.timestamp);
+
+            if let Some(stale) = protocol.expire(timestamped.timestamp) {
+                report.counts.add(ProtocolIssueKind::StaleCycle);
+                report.problem(line_number, stale.describe(), &line);
+                pending_series = false;
+            }
+            (timestamped.content, Some(timestamped.timestamp))
+        } else {
+            (line, None)
+        };
+
+        let Some(parsed) = protocol.parse(&content) else {
+            continue;
+        };
+        if let Some(kind) = parsed.issue_kind() {
+            report.counts.add(kind);
+            if let Some(description) = describe_issue(&parsed, kind) {
+                report.problem(line_number, description, &content);
+            }
+        }
+
+        let end_cycle = parsed == ParsedLine::EndCycle;
+        if let ParsedLine::Series { .. } = parsed {
+            pending_series = true;
+        }
+        if protocol.accumulate(parsed, timestamp).is_some() {
+            report.cycles += 1;
+            pending_series = false;
+        } else if end_cycle {
+            report.incomplete_cycles += 1;
+            report.problem(line_number, "cycle ended before every series", &content);
+            pending_series = false;
+        }
+    }
+
+    if pending_series {
+        report.incomplete_cycles += 1;
+        report.problem(
+            report.lines,
+            "series at end of file without end of cycle",
+            "",
+        );
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn lint(content: &str) -> LogReport {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.log");
+        std::fs::write(&path, content).unwrap();
+        lint_log(&path, ProtocolKind::Atmega, CycleSettings::default())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_clean_log_is_valid() {
+        let report = lint(
+            "PuTTY log\n\
+             2025-01-15T10:30:00.000Z SERIES1 = [100 101]\n\
+             2025-01-15T10:30:00.040Z SERIES2 = [1000 1001]\n\
+             2025-01-15T10:30:00.080Z SERIES3 = [500 501]\n\
+             2025-01-15T10:30:00.081Z END_CYCLE\n\
+             2025-01-15T10:30:00.090Z ERROR adc timeout\n",
+        )
+        .await;
+
+        assert!(report.timestamped);
+        assert_eq!(report.cycles, 1);
+        assert_eq!(report.skipped_lines, 1);
+        assert_eq!(report.counts.errors, 1);
+        assert!(report.is_valid(), "{report}");
+    }
+
+    #[tokio::test]
+    async fn test_reports_problems() {
+        let report = lint(
+            "2025-01-15T10:30:01.000Z SERIES1 = [100 101]\n\
+             2025-01-15T10:30:01.040Z SERIES2 = [1000 10\n\
+             2025-01-15T10:30:01.080Z END_CYCLE\n\
+             2025-01-15T10:30:00.500Z SERIES1 = [100 101]\n\
+             2025-01-15T10:30:00.540Z garbage\n",
+        )
+        .await;
+
+        assert_eq!(report.counts.malformed, 1);
+        assert_eq!(report.counts.unknown, 1);
+        assert_eq!(report.timestamp_regressions, 1);
+        // END_CYCLE without SERIES2/3, then series left at end of file
+        assert_eq!(report.incomplete_cycles, 2);
+        assert_eq!(report.problem_count, 5);
+        assert_eq!(report.problems[0].line_number, 2);
+        assert!(!report.is_valid());
+    }
+
+    #[tokio::test]
+    async fn test_empty_log_is_invalid() {
+        let report = lint("").await;
+        assert_eq!(report.cycles, 0);
+        assert!(!report.is_valid());
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader, Lines, SeekFrom};
+
+use super::playback::PlaybackDataSource;
+use crate::error::SpectrometerError;
+
+/// Lines searched for a file's first timestamp, past e.g. a PuTTY header
+const TIMESTAMP_SCAN_LINES: usize = 10;
+
+/// Files to play for `--file`: the file itself, every file in a directory,
+/// or the matches of a glob pattern. Several files are ordered by their first
+/// timestamp, then by name (raw logs have no timestamps).
+pub fn resolve_log_files(path: &Path) -> Result<Vec<PathBuf>, SpectrometerError> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let files: Vec<PathBuf> = if path.is_dir() {
+        std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.is_file() && !is_hidden(file))
+            .collect()
+    } else {
+        let pattern = path.to_string_lossy();
+        glob::glob(&pattern)
+            .map_err(|e| {
+                SpectrometerError::DataSource(format!("Invalid log file pattern {pattern:?}: {e}"))
+            })?
+            .filter_map(Result::ok)
+            .filter(|file| file.is_file())
+            .collect()
+    };
+
+    if files.is_empty() {
+        return Err(SpectrometerError::DataSource(format!(
+            "Log file not found: {path:?}"
+        )));
+    }
+
+    let mut keyed: Vec<_> = files
+        .into_iter()
+        .map(|file| (first_timestamp(&file), file))
+        .collect();
+    keyed.sort();
+    Ok(keyed.into_iter().map(|(_, file)| file).collect())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
+
+fn first_timestamp(path: &Path) -> Option<DateTime<Utc>> {
+    let file = std::fs::File::open(path).ok()?;
+    std::io::BufReader::new(file)
+        .lines()
+        .take(TIMESTAMP_SCAN_LINES)
+        .map_while(Result::ok)
+        .find_map(|line| PlaybackDataSource::parse_timestamped_line(&line))
+        .map(|line| line.timestamp)
+}
+
+/// Lines of several log files read back to back, as if they were one file
+pub struct LogLines {
+    files: VecDeque<PathBuf>,
+    current: Option<Lines<BufReader<File>>>,
+}
+
+impl LogLines {
+    pub fn new(files: &[PathBuf]) -> Self {
+        Self {
+            files: files.iter().cloned().collect(),
+            current: None,
+        }
+    }
+
+    /// Next line, moving on to the next file at the end of each file
+    pub async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        loop {
+            if let Some(lines) = &mut self.current
+                && let Some(line) = lines.next_line().await?
+            {
+                return Ok(Some(line));
+            }
+
+            let Some(path) = self.files.pop_front() else {
+                return Ok(None);
+            };
+            tracing::debug!("Reading log file {}", path.display());
+            self.current = Some(BufReader::new(File::open(&path).await?).lines());
+        }
+    }
+}
+
+/// Log file another process is appending to, read like `tail -f`
+pub struct FollowedLog {
+    path: PathBuf,
+    reader: BufReader<File>,
+    /// Bytes read so far
+    position: u64,
+    /// Inode of the open file, to notice a new file at the same path
+    identity: Option<u64>,
+    /// Start of a line whose newline has not been written yet
+    partial: String,
+}
+
+impl FollowedLog {
+    /// Open `path` positioned at its current end
+    pub async fn open_at_end(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path).await?;
+        let position = file.seek(SeekFrom::End(0)).await?;
+        Self::from_file(path, file, position).await
+    }
+
+    async fn open_at_start(path: &Path) -> std::io::Result<Self> {
+        let file = File::open(path).await?;
+        Self::from_file(path, file, 0).await
+    }
+
+    async fn from_file(path: &Path, file: File, position: u64) -> std::io::Result<Self> {
+        let identity = file_identity(&file.metadata().await?);
+        Ok(Self {
+            path: path.to_path_buf(),
+            reader: BufReader::new(file),
+            position,
+            identity,
+            partial: String::new(),
+        })
+    }
+
+    /// Next complete line, `None` while nothing new has been written. At the
+    /// end of a rotated file (replaced or truncated) the new file is opened
+    /// from its start.
+    pub async fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        let read = self.reader.read_line(&mut self.partial).await?;
+        self.position += read as u64;
+
+        if self.partial.ends_with('\n') {
+            let line = std::mem::take(&mut self.partial);
+            return Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()));
+        }
+        if read == 0 && self.rotated().await {
+            tracing::info!("{} was rotated, reopening", self.path.display());
+            *self = Self::open_at_start(&self.path).await?;
+        }
+        Ok(None)
+    }
+
+    async fn rotated(&self) -> bool {
+        // Missing in the middle of a rotation: keep waiting
+        let Ok(metadata) = tokio::fs::metadata(&self.path).await else {
+            return false;
+        };
+        let replaced = self.identity.is_some() && file_identity(&metadata) != self.identity;
+        replaced || metadata.len() < self.position
+    }
+}
+
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+/// Only truncation is detected where there are no inodes
+#[cfg(not(unix))]
+fn file_identity(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_orders_by_first_timestamp() {
+        let dir = tempfile::tempdir().unwrap();
+        // Names sort the other way round from the contents
+        std::fs::write(
+            dir.path().join("a.log"),
+            "PuTTY log\n2025-01-15T11:00:00.000Z END_CYCLE\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.log"),
+            "2025-01-15T10:00:00.000Z END_CYCLE\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join(".partial"), "").unwrap();
+
+        let names = |files: Vec<PathBuf>| -> Vec<String> {
+            files
+                .iter()
+                .map(|file| file.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(
+            names(resolve_log_files(dir.path()).unwrap()),
+            vec!["b.log", "a.log"]
+        );
+        assert_eq!(
+            names(resolve_log_files(&dir.path().join("*.log")).unwrap()),
+            vec!["b.log", "a.log"]
+        );
+        assert_eq!(
+            names(resolve_log_files(&dir.path().join("a.log")).unwrap()),
+            vec!["a.log"]
+        );
+        assert!(resolve_log_files(&dir.path().join("*.txt")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_log_lines_span_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("1.log");
+        let second = dir.path().join("2.log");
+        std::fs::write(&first, "one\ntwo\n").unwrap();
+        std::fs::write(&second, "three\n").unwrap();
+
+        let mut lines = LogLines::new(&[first, second]);
+        let mut read = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            read.push(line);
+        }
+        assert_eq!(read, vec!["one", "two", "three"]);
+    }
+
+    #[tokio::test]
+    async fn test_followed_log() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("live.log");
+        std::fs::write(&path, "old\n").unwrap();
+
+        let mut log = FollowedLog::open_at_end(&path).await.unwrap();
+        assert_eq!(log.next_line().await.unwrap(), None);
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        write!(file, "new li").unwrap();
+        assert_eq!(log.next_line().await.unwrap(), None);
+        writeln!(file, "ne\r").unwrap();
+        assert_eq!(log.next_line().await.unwrap().as_deref(), Some("new line"));
+
+        // Rotation: the file is moved away and a new one started
+        std::fs::rename(&path, dir.path().join("live.log.1")).unwrap();
+        std::fs::write(&path, "rotated\n").unwrap();
+        assert_eq!(log.next_line().await.unwrap(), None);
+        assert_eq!(log.next_line().await.unwrap().as_deref(), Some("rotated"));
+
+        // Truncation in place
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(log.next_line().await.unwrap(), None);
+        std::fs::write(&path, "after\n").unwrap();
+        assert_eq!(log.next_line().await.unwrap().as_deref(), Some("after"));
+    }
+}
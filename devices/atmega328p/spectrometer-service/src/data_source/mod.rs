@@ -1,13 +1,23 @@
+pub mod lint;
+pub mod log_files;
 pub mod playback;
 pub mod serial;
+pub mod socket;
 
 use std::path::PathBuf;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 use crate::error::SpectrometerError;
-use crate::protocol::MeasurementCycle;
+use crate::protocol::{
+    ConfirmedSettings, CycleSettings, DebugBlock, DeviceEvent, DeviceProtocol, FirmwareVersion,
+    MeasurementCycle, ParsedLine, ProtocolIssue, ProtocolIssueKind, ProtocolKind,
+};
+use crate::service::calibration::DeviceSettings;
 
 /// Trait for abstracting data sources (real hardware vs playback)
 #[allow(dead_code)]
@@ -16,7 +26,8 @@ pub trait DataSource: Send + Sync {
     /// Start the data source and return a channel receiver for measurement cycles
     async fn start(&mut self) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError>;
 
-    /// Stop the data source
+    /// Stop the data source. Returns the error the reader ended with (read
+    /// error or panic) when it had ended by itself.
     async fn stop(&mut self) -> Result<(), SpectrometerError>;
 
     /// Check if data source is active
@@ -30,6 +41,212 @@ pub trait DataSource: Send + Sync {
 
     /// Set a channel for forwarding raw serial/log lines to the UI
     fn set_log_channel(&mut self, _tx: mpsc::Sender<String>) {}
+
+    /// Set a channel for forwarding firmware debug blocks
+    fn set_debug_channel(&mut self, _tx: mpsc::Sender<DebugBlock>) {}
+
+    /// Set a channel for reporting unknown, error and cycle-missing lines
+    /// and dropped partial cycles
+    fn set_protocol_channel(&mut self, _tx: mpsc::Sender<ProtocolIssue>) {}
+
+    /// Set a channel for forwarding firmware error, cycle-missing and
+    /// ADC-ready lines
+    fn set_event_channel(&mut self, _tx: mpsc::Sender<DeviceEvent>) {}
+
+    /// Set the cycle timeout, series count and out-of-range policy used to
+    /// assemble cycles
+    fn set_cycle_settings(&mut self, _settings: CycleSettings) {}
+
+    /// Parse the input with this firmware dialect
+    fn set_protocol(&mut self, _protocol: ProtocolKind) {}
+
+    /// Firmware version reported at startup, when the source talks to a
+    /// device that answers `VERSION`
+    fn firmware_version(&self) -> Option<FirmwareVersion> {
+        None
+    }
+
+    /// ADC settings the device confirmed at startup
+    fn confirmed_settings(&self) -> Option<ConfirmedSettings> {
+        None
+    }
+}
+
+/// Reader task of a data source; ends with an error when reading failed
+type ReaderTask = JoinHandle<Result<(), SpectrometerError>>;
+
+/// Wait for a reader task, turning a panic into an error. A task aborted
+/// by `stop` counts as ended cleanly.
+async fn join_reader(task: ReaderTask) -> Result<(), SpectrometerError> {
+    match task.await {
+        Ok(outcome) => outcome,
+        Err(e) if e.is_cancelled() => Ok(()),
+        Err(e) => Err(SpectrometerError::DataSource(format!(
+            "Reader task panicked: {e}"
+        ))),
+    }
+}
+
+/// Channels a line-reading source task writes to
+struct SourceOutputs {
+    cycle_tx: mpsc::Sender<MeasurementCycle>,
+    log_tx: Option<mpsc::Sender<String>>,
+    debug_tx: Option<mpsc::Sender<DebugBlock>>,
+    protocol_tx: Option<mpsc::Sender<ProtocolIssue>>,
+    event_tx: Option<mpsc::Sender<DeviceEvent>>,
+    /// Source name reported with protocol issues and device events
+    source: String,
+}
+
+impl SourceOutputs {
+    /// Parse a line, forwarding debug blocks, protocol issues and device
+    /// events; returns
+    /// lines for cycle assembly
+    async fn parse(
+        &self,
+        protocol: &mut dyn DeviceProtocol,
+        line: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Option<ParsedLine> {
+        let parsed = protocol.parse(line)?;
+        if let (Some(tx), Some(kind)) = (&self.protocol_tx, parsed.issue_kind()) {
+            let _ = tx
+                .send(ProtocolIssue {
+                    timestamp,
+                    source: self.source.clone(),
+                    kind,
+                    line: line.trim().to_string(),
+                })
+                .await;
+        }
+        if let (Some(tx), Some(kind)) = (&self.event_tx, parsed.device_event_kind()) {
+            let _ = tx
+                .send(DeviceEvent {
+                    timestamp,
+                    source: self.source.clone(),
+                    kind,
+                    line: line.trim().to_string(),
+                })
+                .await;
+        }
+        let ParsedLine::DebugBlock { lines, truncated } = parsed else {
+            return Some(parsed);
+        };
+
+        if let Some(tx) = &self.debug_tx {
+            let _ = tx
+                .send(DebugBlock {
+                    timestamp,
+                    lines,
+                    truncated,
+                })
+                .await;
+        }
+        None
+    }
+
+    /// Drop a stale partial cycle and report it
+    async fn expire(&self, protocol: &mut dyn DeviceProtocol, now: DateTime<Utc>) {
+        let Some(stale) = protocol.expire(now) else {
+            return;
+        };
+
+        let detail = stale.describe();
+        tracing::warn!("{}: {detail}", self.source);
+        if let Some(tx) = &self.protocol_tx {
+            let _ = tx
+                .send(ProtocolIssue {
+                    timestamp: now,
+                    source: self.source.clone(),
+                    kind: ProtocolIssueKind::StaleCycle,
+                    line: detail,
+                })
+                .await;
+        }
+    }
+}
+
+/// Data source of a device listed in the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SourceSettings {
+    Serial {
+        port: String,
+        #[serde(default = "default_baud")]
+        baud: u32,
+        /// Dump raw serial output to this file
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        log_file: Option<PathBuf>,
+        #[serde(default)]
+        allow_firmware_mismatch: bool,
+        #[serde(flatten)]
+        line: serial::SerialLineSettings,
+    },
+    Playback {
+        file: PathBuf,
+        #[serde(default = "default_speed")]
+        speed: f64,
+        #[serde(default)]
+        loop_playback: bool,
+        #[serde(default = "default_cycle_interval")]
+        cycle_interval: u64,
+        #[serde(default)]
+        follow: bool,
+    },
+    Socket {
+        path: PathBuf,
+    },
+}
+
+fn default_baud() -> u32 {
+    38400
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+fn default_cycle_interval() -> u64 {
+    100
+}
+
+impl SourceSettings {
+    /// Data source configuration, acquiring with the device's saved settings
+    pub fn to_data_source_config(&self, saved: &DeviceSettings) -> DataSourceConfig {
+        match self {
+            SourceSettings::Serial {
+                port,
+                baud,
+                log_file,
+                allow_firmware_mismatch,
+                line,
+            } => DataSourceConfig::Serial {
+                port: port.clone(),
+                baud_rate: *baud,
+                gain: saved.gain,
+                fadc: saved.fadc,
+                count: saved.count,
+                log_file: log_file.clone(),
+                allow_firmware_mismatch: *allow_firmware_mismatch,
+                line: *line,
+            },
+            SourceSettings::Playback {
+                file,
+                speed,
+                loop_playback,
+                cycle_interval,
+                follow,
+            } => DataSourceConfig::Playback {
+                log_file: file.clone(),
+                speed_multiplier: *speed,
+                loop_playback: *loop_playback,
+                cycle_interval_ms: *cycle_interval,
+                time_range: playback::TimeRange::default(),
+                follow: *follow,
+            },
+            SourceSettings::Socket { path } => DataSourceConfig::Socket { path: path.clone() },
+        }
+    }
 }
 
 /// Configuration for creating data sources
@@ -43,6 +260,8 @@ pub enum DataSourceConfig {
         fadc: f32,
         count: u8,
         log_file: Option<PathBuf>,
+        allow_firmware_mismatch: bool,
+        line: serial::SerialLineSettings,
     },
     /// Log file playback (supports both timestamped and raw log formats)
     Playback {
@@ -51,10 +270,38 @@ pub enum DataSourceConfig {
         loop_playback: bool,
         /// Cycle interval in ms for raw logs without timestamps (default: 100)
         cycle_interval_ms: u64,
+        /// Part of a timestamped log to play
+        time_range: playback::TimeRange,
+        /// Tail the file for lines another process appends
+        follow: bool,
     },
+    /// Line protocol from a local bridge process over a Unix domain socket
+    /// or Windows named pipe
+    Socket { path: PathBuf },
 }
 
 impl DataSourceConfig {
+    /// Description of the source `name` created from this configuration
+    pub fn active_source(&self, name: &str) -> ActiveSource {
+        let (kind, baud_rate, adc) = match self {
+            DataSourceConfig::Serial {
+                baud_rate,
+                gain,
+                fadc,
+                count,
+                ..
+            } => ("serial", Some(*baud_rate), Some((*gain, *fadc, *count))),
+            DataSourceConfig::Playback { .. } => ("playback", None, None),
+            DataSourceConfig::Socket { .. } => ("socket", None, None),
+        };
+        ActiveSource {
+            kind,
+            name: name.to_string(),
+            baud_rate,
+            adc,
+        }
+    }
+
     /// Create a data source from this configuration
     pub fn create_source(&self) -> Box<dyn DataSource> {
         match self {
@@ -65,25 +312,78 @@ impl DataSourceConfig {
                 fadc,
                 count,
                 log_file,
-            } => Box::new(serial::SerialDataSource::new(
-                port.clone(),
-                *baud_rate,
-                *gain,
-                *fadc,
-                *count,
-                log_file.clone(),
-            )),
+                allow_firmware_mismatch,
+                line,
+            } => Box::new(
+                serial::SerialDataSource::new(
+                    port.clone(),
+                    *baud_rate,
+                    *gain,
+                    *fadc,
+                    *count,
+                    log_file.clone(),
+                )
+                .with_firmware_mismatch_allowed(*allow_firmware_mismatch)
+                .with_line_settings(*line),
+            ),
             DataSourceConfig::Playback {
                 log_file,
                 speed_multiplier,
                 loop_playback,
                 cycle_interval_ms,
-            } => Box::new(playback::PlaybackDataSource::new_raw(
-                log_file.clone(),
-                *speed_multiplier,
-                *loop_playback,
-                *cycle_interval_ms,
-            )),
+                time_range,
+                follow,
+            } => Box::new(
+                playback::PlaybackDataSource::new_raw(
+                    log_file.clone(),
+                    *speed_multiplier,
+                    *loop_playback,
+                    *cycle_interval_ms,
+                )
+                .with_time_range(*time_range)
+                .with_follow(*follow),
+            ),
+            DataSourceConfig::Socket { path } => {
+                Box::new(socket::SocketDataSource::new(path.clone()))
+            }
         }
     }
 }
+
+/// Data source a device is read from, for `GET /device/settings`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveSource {
+    /// `serial`, `playback` or `socket`, as in the config file
+    pub kind: &'static str,
+    pub name: String,
+    pub baud_rate: Option<u32>,
+    /// GAIN, FADC and COUNT last sent to a serial device; other sources
+    /// follow the saved settings
+    pub adc: Option<(u8, f32, u8)>,
+}
+
+/// Channels and settings every data source of a device is created with,
+/// kept by the supervisor to create a replacement source at runtime
+#[derive(Clone)]
+pub struct SourceSetup {
+    pub log_tx: mpsc::Sender<String>,
+    pub debug_tx: mpsc::Sender<DebugBlock>,
+    pub protocol_tx: mpsc::Sender<ProtocolIssue>,
+    pub event_tx: mpsc::Sender<DeviceEvent>,
+    pub cycle_settings: CycleSettings,
+    pub protocol: ProtocolKind,
+}
+
+impl SourceSetup {
+    /// Create a data source from `config`, wired to this device's channels
+    pub fn create_source(&self, config: &DataSourceConfig) -> Box<dyn DataSource> {
+        let mut source = config.create_source();
+        source.set_log_channel(self.log_tx.clone());
+        source.set_debug_channel(self.debug_tx.clone());
+        source.set_protocol_channel(self.protocol_tx.clone());
+        source.set_event_channel(self.event_tx.clone());
+        source.set_cycle_settings(self.cycle_settings);
+        source.set_protocol(self.protocol);
+        source
+    }
+}
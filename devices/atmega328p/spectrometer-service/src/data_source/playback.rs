@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -8,18 +8,51 @@ use regex::Regex;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::mpsc;
-use tokio::task::JoinHandle;
 use tokio::time::{Duration, sleep};
 
-use super::DataSource;
+use super::log_files::{FollowedLog, LogLines, resolve_log_files};
+use super::{DataSource, ReaderTask, SourceOutputs, join_reader};
 use crate::error::SpectrometerError;
-use crate::protocol::{CycleAccumulator, MeasurementCycle, ParsedLine, parse_line};
+use crate::protocol::{
+    CycleSettings, DebugBlock, DeviceEvent, DeviceProtocol, MeasurementCycle, ParsedLine,
+    ProtocolIssue, ProtocolKind,
+};
 
 /// A line from the log file with its timestamp
 #[derive(Debug, Clone)]
-struct TimestampedLine {
-    timestamp: DateTime<Utc>,
-    content: String,
+pub(super) struct TimestampedLine {
+    pub timestamp: DateTime<Utc>,
+    pub content: String,
+}
+
+/// How often a followed log is checked for new lines at its end
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Parse a log timestamp: RFC 3339, or without offset as UTC
+pub fn parse_log_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%.f")
+                .map(|ndt| ndt.and_utc())
+        })
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S").map(|ndt| ndt.and_utc())
+        })
+        .ok()
+}
+
+/// Window of log time to play back, from `--from` / `--to`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    pub fn is_set(&self) -> bool {
+        self.from.is_some() || self.to.is_some()
+    }
 }
 
 /// Data source for log file playback with timestamp-based timing
@@ -29,8 +62,20 @@ pub struct PlaybackDataSource {
     loop_playback: bool,
     cycle_interval_ms: u64,
     is_active: Arc<AtomicBool>,
-    reader_task: Option<JoinHandle<()>>,
+    reader_task: Option<ReaderTask>,
     log_tx: Option<mpsc::Sender<String>>,
+    debug_tx: Option<mpsc::Sender<DebugBlock>>,
+    protocol_tx: Option<mpsc::Sender<ProtocolIssue>>,
+    event_tx: Option<mpsc::Sender<DeviceEvent>>,
+    /// Cycle assembly settings. Partial cycles time out in log time; raw
+    /// logs carry no time, so only timestamped playback applies the timeout.
+    cycle_settings: CycleSettings,
+    /// Firmware dialect the log was recorded in
+    protocol: ProtocolKind,
+    /// Part of a timestamped log to play
+    time_range: TimeRange,
+    /// Keep reading new lines at the end of the file
+    follow: bool,
 }
 
 impl PlaybackDataSource {
@@ -44,6 +89,13 @@ impl PlaybackDataSource {
             is_active: Arc::new(AtomicBool::new(false)),
             reader_task: None,
             log_tx: None,
+            debug_tx: None,
+            protocol_tx: None,
+            event_tx: None,
+            cycle_settings: CycleSettings::default(),
+            protocol: ProtocolKind::default(),
+            time_range: TimeRange::default(),
+            follow: false,
         }
     }
 
@@ -63,33 +115,40 @@ impl PlaybackDataSource {
             is_active: Arc::new(AtomicBool::new(false)),
             reader_task: None,
             log_tx: None,
+            debug_tx: None,
+            protocol_tx: None,
+            event_tx: None,
+            cycle_settings: CycleSettings::default(),
+            protocol: ProtocolKind::default(),
+            time_range: TimeRange::default(),
+            follow: false,
         }
     }
 
+    /// Play only the lines of a timestamped log within `range`
+    pub fn with_time_range(mut self, range: TimeRange) -> Self {
+        self.time_range = range;
+        self
+    }
+
+    /// Follow the file as another process writes it instead of playing it
+    pub fn with_follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
     /// Parse a timestamped line from the log file
     /// Format: "2025-01-15T10:30:00.123 SERIES1 = [1234567 1234568 1234569]"
-    fn parse_timestamped_line(line: &str) -> Option<TimestampedLine> {
+    pub(super) fn parse_timestamped_line(line: &str) -> Option<TimestampedLine> {
         let re = Regex::new(
             r"^(\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:\d{2})?)\s+(.*)$",
         )
         .ok()?;
 
         let caps = re.captures(line.trim())?;
-        let timestamp_str = caps.get(1)?.as_str();
+        let timestamp = parse_log_timestamp(caps.get(1)?.as_str())?;
         let content = caps.get(2)?.as_str();
 
-        let timestamp = DateTime::parse_from_rfc3339(timestamp_str)
-            .map(|dt| dt.with_timezone(&Utc))
-            .or_else(|_| {
-                NaiveDateTime::parse_from_str(timestamp_str, "%Y-%m-%dT%H:%M:%S%.f")
-                    .map(|ndt| ndt.and_utc())
-            })
-            .or_else(|_| {
-                NaiveDateTime::parse_from_str(timestamp_str, "%Y-%m-%dT%H:%M:%S")
-                    .map(|ndt| ndt.and_utc())
-            })
-            .ok()?;
-
         Some(TimestampedLine {
             timestamp,
             content: content.to_string(),
@@ -97,7 +156,7 @@ impl PlaybackDataSource {
     }
 
     /// Detect whether the file has ISO8601 timestamps by checking first few data lines
-    async fn detect_has_timestamps(file_path: &PathBuf) -> bool {
+    pub(super) async fn detect_has_timestamps(file_path: &Path, protocol: ProtocolKind) -> bool {
         let file = match File::open(file_path).await {
             Ok(f) => f,
             Err(_) => return false,
@@ -106,6 +165,7 @@ impl PlaybackDataSource {
         let reader = BufReader::new(file);
         let mut lines = reader.lines();
         let mut checked = 0;
+        let mut protocol = protocol.create(CycleSettings::default());
 
         while checked < 10 {
             let line = match lines.next_line().await {
@@ -118,15 +178,19 @@ impl PlaybackDataSource {
                 continue;
             }
 
-            // Skip PuTTY header and non-data lines
-            let parsed = parse_line(trimmed);
-            if !matches!(parsed, ParsedLine::Series { .. } | ParsedLine::EndCycle) {
+            // Skip PuTTY header and non-data lines, looking past a timestamp
+            let timestamped = Self::parse_timestamped_line(trimmed);
+            let content = timestamped.as_ref().map_or(trimmed, |t| t.content.as_str());
+            if !matches!(
+                protocol.parse(content),
+                Some(ParsedLine::Series { .. } | ParsedLine::EndCycle)
+            ) {
                 continue;
             }
 
             checked += 1;
             // If any data line has a timestamp, assume timestamped format
-            if Self::parse_timestamped_line(trimmed).is_some() {
+            if timestamped.is_some() {
                 return true;
             }
         }
@@ -136,31 +200,26 @@ impl PlaybackDataSource {
 
     /// Run timestamped playback (original behavior)
     async fn run_timestamped(
-        log_file: PathBuf,
+        log_files: Vec<PathBuf>,
         speed_multiplier: f64,
         loop_playback: bool,
+        time_range: TimeRange,
+        new_protocol: impl Fn() -> Box<dyn DeviceProtocol>,
         is_active: Arc<AtomicBool>,
-        cycle_tx: mpsc::Sender<MeasurementCycle>,
-        log_tx: Option<mpsc::Sender<String>>,
-    ) {
+        outputs: SourceOutputs,
+    ) -> Result<(), SpectrometerError> {
         tracing::info!(
-            "Timestamped playback from {:?} at {}x speed",
-            log_file,
+            "Timestamped playback of {} file(s) from {:?} at {}x speed",
+            log_files.len(),
+            log_files[0],
             speed_multiplier
         );
 
         loop {
-            let file = match File::open(&log_file).await {
-                Ok(f) => f,
-                Err(e) => {
-                    tracing::error!("Failed to open log file: {}", e);
-                    break;
-                }
-            };
-
-            let reader = BufReader::new(file);
-            let mut lines = reader.lines();
-            let mut accumulator = CycleAccumulator::new();
+            // One line stream across all files, so a cycle split by file
+            // rotation is still assembled and timing continues
+            let mut lines = LogLines::new(&log_files);
+            let mut protocol = new_protocol();
             let mut last_timestamp: Option<DateTime<Utc>> = None;
             let playback_start = std::time::Instant::now();
             let mut log_start: Option<DateTime<Utc>> = None;
@@ -171,7 +230,7 @@ impl PlaybackDataSource {
                     Ok(None) => break,
                     Err(e) => {
                         tracing::error!("Error reading log file: {}", e);
-                        break;
+                        return Err(e.into());
                     }
                 };
 
@@ -179,6 +238,18 @@ impl PlaybackDataSource {
                     continue;
                 };
 
+                // Fast-forward to the window without pacing; pacing starts
+                // at its first line
+                if time_range
+                    .from
+                    .is_some_and(|from| timestamped.timestamp < from)
+                {
+                    continue;
+                }
+                if time_range.to.is_some_and(|to| timestamped.timestamp > to) {
+                    break;
+                }
+
                 if log_start.is_none() {
                     log_start = Some(timestamped.timestamp);
                 }
@@ -197,16 +268,27 @@ impl PlaybackDataSource {
 
                 last_timestamp = Some(timestamped.timestamp);
 
-                if let Some(tx) = &log_tx {
+                if let Some(tx) = &outputs.log_tx {
                     let _ = tx.send(timestamped.content.clone()).await;
                 }
-                let parsed = parse_line(&timestamped.content);
-                if let Some(cycle) =
-                    accumulator.process_line_with_timestamp(parsed, timestamped.timestamp)
-                    && cycle_tx.send(cycle).await.is_err()
+                outputs
+                    .expire(protocol.as_mut(), timestamped.timestamp)
+                    .await;
+                let Some(parsed) = outputs
+                    .parse(
+                        protocol.as_mut(),
+                        &timestamped.content,
+                        timestamped.timestamp,
+                    )
+                    .await
+                else {
+                    continue;
+                };
+                if let Some(cycle) = protocol.accumulate(parsed, Some(timestamped.timestamp))
+                    && outputs.cycle_tx.send(cycle).await.is_err()
                 {
                     tracing::warn!("Cycle receiver dropped, stopping playback");
-                    return;
+                    return Ok(());
                 }
             }
 
@@ -218,39 +300,98 @@ impl PlaybackDataSource {
         }
 
         tracing::info!("Timestamped playback finished");
+        Ok(())
+    }
+
+    /// Follow a log another process is appending to, like `tail -f`:
+    /// start at its end, pick up lines as they are written and reopen the
+    /// file when it is rotated. Lines are stamped on arrival like serial
+    /// input; a timestamp prefix is stripped.
+    async fn run_follow(
+        log_file: PathBuf,
+        new_protocol: impl Fn() -> Box<dyn DeviceProtocol>,
+        is_active: Arc<AtomicBool>,
+        outputs: SourceOutputs,
+    ) {
+        tracing::info!("Following {:?} for new lines", log_file);
+        let mut protocol = new_protocol();
+        let mut log: Option<FollowedLog> = None;
+
+        while is_active.load(Ordering::SeqCst) {
+            let followed = match &mut log {
+                Some(followed) => followed,
+                None => match FollowedLog::open_at_end(&log_file).await {
+                    Ok(followed) => log.insert(followed),
+                    Err(e) => {
+                        tracing::warn!("Cannot open {:?}: {}", log_file, e);
+                        sleep(FOLLOW_POLL_INTERVAL).await;
+                        continue;
+                    }
+                },
+            };
+
+            let line = match followed.next_line().await {
+                Ok(Some(line)) => line,
+                Ok(None) => {
+                    outputs.expire(protocol.as_mut(), Utc::now()).await;
+                    sleep(FOLLOW_POLL_INTERVAL).await;
+                    continue;
+                }
+                Err(e) => {
+                    tracing::error!("Error reading {:?}: {}", log_file, e);
+                    log = None;
+                    continue;
+                }
+            };
+
+            let now = Utc::now();
+            let content = match Self::parse_timestamped_line(&line) {
+                Some(timestamped) => timestamped.content,
+                None => line.trim().to_string(),
+            };
+            if let Some(tx) = &outputs.log_tx {
+                let _ = tx.send(content.clone()).await;
+            }
+            outputs.expire(protocol.as_mut(), now).await;
+            let Some(parsed) = outputs.parse(protocol.as_mut(), &content, now).await else {
+                continue;
+            };
+            if let Some(cycle) = protocol.accumulate(parsed, None)
+                && outputs.cycle_tx.send(cycle).await.is_err()
+            {
+                tracing::warn!("Cycle receiver dropped, stopping follow");
+                return;
+            }
+        }
+
+        tracing::info!("Stopped following {:?}", log_file);
     }
 
     /// Run raw playback for log files without timestamps.
     /// Generates synthetic timestamps and paces cycles at cycle_interval_ms.
     async fn run_raw(
-        log_file: PathBuf,
+        log_files: Vec<PathBuf>,
         speed_multiplier: f64,
         cycle_interval_ms: u64,
         loop_playback: bool,
+        new_protocol: impl Fn() -> Box<dyn DeviceProtocol>,
         is_active: Arc<AtomicBool>,
-        cycle_tx: mpsc::Sender<MeasurementCycle>,
-        log_tx: Option<mpsc::Sender<String>>,
-    ) {
+        outputs: SourceOutputs,
+    ) -> Result<(), SpectrometerError> {
         let effective_interval_ms = (cycle_interval_ms as f64 / speed_multiplier) as u64;
         tracing::info!(
-            "Raw playback from {:?} at {}x speed ({}ms between cycles)",
-            log_file,
+            "Raw playback of {} file(s) from {:?} at {}x speed ({}ms between cycles)",
+            log_files.len(),
+            log_files[0],
             speed_multiplier,
             effective_interval_ms
         );
 
         loop {
-            let file = match File::open(&log_file).await {
-                Ok(f) => f,
-                Err(e) => {
-                    tracing::error!("Failed to open log file: {}", e);
-                    break;
-                }
-            };
-
-            let reader = BufReader::new(file);
-            let mut lines = reader.lines();
-            let mut accumulator = CycleAccumulator::new();
+            // One line stream across all files, so a cycle split by file
+            // rotation is still assembled and timing continues
+            let mut lines = LogLines::new(&log_files);
+            let mut protocol = new_protocol();
             let mut cycle_count: u64 = 0;
             let base_timestamp = Utc::now();
 
@@ -260,21 +401,27 @@ impl PlaybackDataSource {
                     Ok(None) => break,
                     Err(e) => {
                         tracing::error!("Error reading log file: {}", e);
-                        break;
+                        return Err(e.into());
                     }
                 };
 
                 let trimmed = line.trim().to_string();
-                if let Some(tx) = &log_tx {
+                if let Some(tx) = &outputs.log_tx {
                     let _ = tx.send(trimmed.clone()).await;
                 }
-                let parsed = parse_line(&trimmed);
 
                 // Generate a synthetic timestamp for this cycle
                 let synthetic_ts = base_timestamp
                     + ChronoDuration::milliseconds((cycle_count * cycle_interval_ms) as i64);
 
-                if let Some(cycle) = accumulator.process_line_with_timestamp(parsed, synthetic_ts) {
+                let Some(parsed) = outputs
+                    .parse(protocol.as_mut(), &trimmed, synthetic_ts)
+                    .await
+                else {
+                    continue;
+                };
+
+                if let Some(cycle) = protocol.accumulate(parsed, Some(synthetic_ts)) {
                     cycle_count += 1;
 
                     // Pace the output
@@ -282,9 +429,9 @@ impl PlaybackDataSource {
                         sleep(Duration::from_millis(effective_interval_ms)).await;
                     }
 
-                    if cycle_tx.send(cycle).await.is_err() {
+                    if outputs.cycle_tx.send(cycle).await.is_err() {
                         tracing::warn!("Cycle receiver dropped, stopping playback");
-                        return;
+                        return Ok(());
                     }
                 }
             }
@@ -299,18 +446,14 @@ impl PlaybackDataSource {
         }
 
         tracing::info!("Raw playback finished");
+        Ok(())
     }
 }
 
 #[async_trait]
 impl DataSource for PlaybackDataSource {
     async fn start(&mut self) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError> {
-        if !self.log_file.exists() {
-            return Err(SpectrometerError::DataSource(format!(
-                "Log file not found: {:?}",
-                self.log_file
-            )));
-        }
+        let log_files = resolve_log_files(&self.log_file)?;
 
         let (cycle_tx, cycle_rx) = mpsc::channel(32);
 
@@ -318,40 +461,86 @@ impl DataSource for PlaybackDataSource {
         let is_active = self.is_active.clone();
         let speed_multiplier = self.speed_multiplier;
         let loop_playback = self.loop_playback;
-        let log_file = self.log_file.clone();
         let cycle_interval_ms = self.cycle_interval_ms;
-        let log_tx = self.log_tx.clone();
+        let protocol = self.protocol;
+        let cycle_settings = self.cycle_settings;
+        let time_range = self.time_range;
+        if let (Some(from), Some(to)) = (time_range.from, time_range.to)
+            && from > to
+        {
+            return Err(SpectrometerError::DataSource(format!(
+                "Playback range starts after it ends: {from} > {to}"
+            )));
+        }
+        let outputs = SourceOutputs {
+            cycle_tx,
+            log_tx: self.log_tx.clone(),
+            debug_tx: self.debug_tx.clone(),
+            protocol_tx: self.protocol_tx.clone(),
+            event_tx: self.event_tx.clone(),
+            source: self.name().to_string(),
+        };
+
+        if self.follow {
+            if !self.log_file.is_file() {
+                return Err(SpectrometerError::DataSource(format!(
+                    "--follow needs a single log file, not {:?}",
+                    self.log_file
+                )));
+            }
+            let log_file = self.log_file.clone();
+            self.reader_task = Some(tokio::spawn(async move {
+                Self::run_follow(
+                    log_file,
+                    move || protocol.create(cycle_settings),
+                    is_active,
+                    outputs,
+                )
+                .await;
+                Ok(())
+            }));
+            return Ok(cycle_rx);
+        }
 
-        // Auto-detect whether file has timestamps
-        let has_timestamps = Self::detect_has_timestamps(&log_file).await;
+        // Auto-detect whether the (first) file has timestamps
+        let has_timestamps = Self::detect_has_timestamps(&log_files[0], protocol).await;
 
         let reader_handle = if has_timestamps {
             tracing::info!("Detected timestamped log format");
             tokio::spawn(async move {
                 Self::run_timestamped(
-                    log_file,
+                    log_files,
                     speed_multiplier,
                     loop_playback,
+                    time_range,
+                    move || protocol.create(cycle_settings),
                     is_active,
-                    cycle_tx,
-                    log_tx,
+                    outputs,
                 )
-                .await;
+                .await
             })
         } else {
             tracing::info!("Detected raw log format (no timestamps)");
-            let log_tx2 = log_tx;
+            if time_range.is_set() {
+                tracing::warn!("Raw log has no timestamps, ignoring --from/--to");
+            }
             tokio::spawn(async move {
                 Self::run_raw(
-                    log_file,
+                    log_files,
                     speed_multiplier,
                     cycle_interval_ms,
                     loop_playback,
+                    // Raw logs carry no time to apply the cycle timeout with
+                    move || {
+                        protocol.create(CycleSettings {
+                            timeout: None,
+                            ..cycle_settings
+                        })
+                    },
                     is_active,
-                    cycle_tx,
-                    log_tx2,
+                    outputs,
                 )
-                .await;
+                .await
             })
         };
 
@@ -363,14 +552,17 @@ impl DataSource for PlaybackDataSource {
     async fn stop(&mut self) -> Result<(), SpectrometerError> {
         self.is_active.store(false, Ordering::SeqCst);
 
-        if let Some(handle) = self.reader_task.take() {
-            handle.abort();
-            let _ = handle.await;
-        }
+        let outcome = match self.reader_task.take() {
+            Some(task) => {
+                task.abort();
+                join_reader(task).await
+            }
+            None => Ok(()),
+        };
 
         tracing::info!("Playback data source stopped");
 
-        Ok(())
+        outcome
     }
 
     fn is_active(&self) -> bool {
@@ -390,6 +582,26 @@ impl DataSource for PlaybackDataSource {
     fn set_log_channel(&mut self, tx: mpsc::Sender<String>) {
         self.log_tx = Some(tx);
     }
+
+    fn set_debug_channel(&mut self, tx: mpsc::Sender<DebugBlock>) {
+        self.debug_tx = Some(tx);
+    }
+
+    fn set_protocol_channel(&mut self, tx: mpsc::Sender<ProtocolIssue>) {
+        self.protocol_tx = Some(tx);
+    }
+
+    fn set_event_channel(&mut self, tx: mpsc::Sender<DeviceEvent>) {
+        self.event_tx = Some(tx);
+    }
+
+    fn set_cycle_settings(&mut self, settings: CycleSettings) {
+        self.cycle_settings = settings;
+    }
+
+    fn set_protocol(&mut self, protocol: ProtocolKind) {
+        self.protocol = protocol;
+    }
 }
 
 #[cfg(test)]
@@ -397,6 +609,7 @@ mod tests {
     use chrono::Timelike;
 
     use super::*;
+    use crate::protocol::{DeviceEventKind, ProtocolIssueKind};
 
     #[test]
     fn test_parse_timestamped_line_with_millis() {
@@ -485,4 +698,272 @@ mod tests {
         let line = "2025-01-15T10:30:00.123+00:00 SERIES1 = [100]";
         assert!(PlaybackDataSource::parse_timestamped_line(line).is_some());
     }
+
+    #[tokio::test]
+    async fn test_detect_has_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let timestamped = dir.path().join("timestamped.log");
+        std::fs::write(
+            &timestamped,
+            "PuTTY log\n2025-01-15T10:30:00.000Z SERIES1 = [100 101 102]\n",
+        )
+        .unwrap();
+        assert!(
+            PlaybackDataSource::detect_has_timestamps(&timestamped, ProtocolKind::Atmega).await
+        );
+
+        let raw = dir.path().join("raw.log");
+        std::fs::write(&raw, "PuTTY log\nSERIES1 = 100 101 102\nEND_CYCLE\n").unwrap();
+        assert!(!PlaybackDataSource::detect_has_timestamps(&raw, ProtocolKind::Atmega).await);
+    }
+
+    #[tokio::test]
+    async fn test_raw_playback_forwards_debug_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("debug.log");
+        std::fs::write(
+            &path,
+            "DEBUG BEGIN\nreg0=0x1F\nreg1=0x00\nDEBUG END\n\
+             SERIES1 = 100 101 102\nSERIES2 = 1000 1001 1002\n\
+             SERIES3 = 500 501 502\nEND_CYCLE\n",
+        )
+        .unwrap();
+
+        let mut source = PlaybackDataSource::new_raw(path, 1.0, false, 0);
+        let (debug_tx, mut debug_rx) = mpsc::channel(4);
+        source.set_debug_channel(debug_tx);
+
+        let mut cycle_rx = source.start().await.unwrap();
+        let block = debug_rx.recv().await.unwrap();
+        assert_eq!(block.lines, vec!["reg0=0x1F", "reg1=0x00"]);
+        assert!(!block.truncated);
+
+        let cycle = cycle_rx.recv().await.unwrap();
+        assert_eq!(cycle.dark().values, vec![100, 101, 102]);
+
+        source.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_playback_of_rotated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        // The cycle is split by the rotation
+        std::fs::write(
+            dir.path().join("capture-11.log"),
+            "2025-01-15T11:00:00.040Z SERIES2 = [1000 1001 1002]\n\
+             2025-01-15T11:00:00.080Z SERIES3 = [500 501 502]\n\
+             2025-01-15T11:00:00.081Z END_CYCLE\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("capture-10.log"),
+            "2025-01-15T10:59:59.000Z SERIES1 = [1 1 1]\n\
+             2025-01-15T10:59:59.040Z SERIES2 = [2 2 2]\n\
+             2025-01-15T10:59:59.080Z SERIES3 = [3 3 3]\n\
+             2025-01-15T10:59:59.081Z END_CYCLE\n\
+             2025-01-15T11:00:00.000Z SERIES1 = [100 101 102]\n",
+        )
+        .unwrap();
+
+        let mut source = PlaybackDataSource::new(dir.path().join("capture-*.log"), 1000.0, false);
+        let mut cycle_rx = source.start().await.unwrap();
+
+        assert_eq!(cycle_rx.recv().await.unwrap().dark().values, vec![1, 1, 1]);
+        let cycle = cycle_rx.recv().await.unwrap();
+        assert_eq!(cycle.dark().values, vec![100, 101, 102]);
+        assert_eq!(cycle.sample().values, vec![500, 501, 502]);
+
+        source.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_playback_time_range() {
+        let time = |t| parse_log_timestamp(t).unwrap();
+        let mut source =
+            PlaybackDataSource::new(PathBuf::from("fixtures/sample_log.txt"), 1.0, false)
+                .with_time_range(TimeRange {
+                    from: Some(time("2025-01-15T10:30:00.400")),
+                    to: Some(time("2025-01-15T10:30:00.750")),
+                });
+
+        let started = std::time::Instant::now();
+        let mut cycle_rx = source.start().await.unwrap();
+        let mut darks = Vec::new();
+        while let Some(cycle) = cycle_rx.recv().await {
+            darks.push(cycle.dark().values[0]);
+        }
+
+        assert_eq!(darks, vec![1000020, 1000030]);
+        // Only the 300ms window is paced, not the 400ms before it
+        assert!(started.elapsed() < std::time::Duration::from_millis(650));
+        source.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_playback_rejects_inverted_range() {
+        let time = |t| parse_log_timestamp(t).unwrap();
+        let mut source =
+            PlaybackDataSource::new(PathBuf::from("fixtures/sample_log.txt"), 1.0, false)
+                .with_time_range(TimeRange {
+                    from: Some(time("2025-01-15T10:31:00Z")),
+                    to: Some(time("2025-01-15T10:30:00Z")),
+                });
+        assert!(source.start().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_playback_follow() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("live.log");
+        // Lines already in the file are not played
+        std::fs::write(&path, "SERIES1 = [1 2]\nEND_CYCLE\n").unwrap();
+
+        let mut source = PlaybackDataSource::new(path.clone(), 1.0, false).with_follow(true);
+        let mut cycle_rx = source.start().await.unwrap();
+        tokio::time::sleep(FOLLOW_POLL_INTERVAL).await;
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        write!(
+            file,
+            "SERIES1 = [100 101]\n\
+             2025-01-15T10:30:00.040Z SERIES2 = [1000 1001]\n\
+             SERIES3 = [500 501]\n\
+             END_CYCLE\n"
+        )
+        .unwrap();
+
+        let cycle = tokio::time::timeout(Duration::from_secs(2), cycle_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(cycle.dark().values, vec![100, 101]);
+        assert_eq!(cycle.full().values, vec![1000, 1001]);
+        source.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_csv_playback() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("csv.log");
+        std::fs::write(
+            &path,
+            "2025-01-15T10:30:00.000Z 1,100,101,102\n\
+             2025-01-15T10:30:00.040Z 2,1000,1001,1002\n\
+             2025-01-15T10:30:00.080Z 3,500,501,502\n\
+             2025-01-15T10:30:00.081Z END\n",
+        )
+        .unwrap();
+        assert!(PlaybackDataSource::detect_has_timestamps(&path, ProtocolKind::Csv).await);
+
+        let mut source = PlaybackDataSource::new(path, 1000.0, false);
+        source.set_protocol(ProtocolKind::Csv);
+
+        let mut cycle_rx = source.start().await.unwrap();
+        let cycle = cycle_rx.recv().await.unwrap();
+        assert_eq!(cycle.full().values, vec![1000, 1001, 1002]);
+
+        source.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_raw_playback_reports_protocol_issues() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("noisy.log");
+        std::fs::write(
+            &path,
+            "garbage
+
+ADC ready
+ERROR Invalid GAIN value
+Measurement cycle is missing
+             SERIES1 = 100 101 102
+SERIES2 = 1000 1001 1002
+             SERIES3 = 500 501 502
+END_CYCLE
+",
+        )
+        .unwrap();
+
+        let mut source = PlaybackDataSource::new_raw(path.clone(), 1.0, false, 0);
+        let (protocol_tx, mut protocol_rx) = mpsc::channel(8);
+        source.set_protocol_channel(protocol_tx);
+        let (event_tx, mut event_rx) = mpsc::channel(8);
+        source.set_event_channel(event_tx);
+
+        let mut cycle_rx = source.start().await.unwrap();
+        let mut issues = Vec::new();
+        for _ in 0..3 {
+            issues.push(protocol_rx.recv().await.unwrap());
+        }
+        let kinds: Vec<_> = issues.iter().map(|issue| issue.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ProtocolIssueKind::Unknown,
+                ProtocolIssueKind::Error,
+                ProtocolIssueKind::CycleMissing
+            ]
+        );
+        assert_eq!(issues[0].line, "garbage");
+        assert_eq!(issues[0].source, path.to_str().unwrap());
+
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            events.push(event_rx.recv().await.unwrap().kind);
+        }
+        assert_eq!(
+            events,
+            vec![
+                DeviceEventKind::AdcReady,
+                DeviceEventKind::Error,
+                DeviceEventKind::CycleMissing
+            ]
+        );
+
+        assert!(cycle_rx.recv().await.is_some());
+        source.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timestamped_playback_drops_stale_partial_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("brownout.log");
+        // SERIES1/2 before a brownout, then a full cycle a minute later
+        std::fs::write(
+            &path,
+            "2025-01-15T10:30:00.000Z SERIES1 = [900 900 900]
+             2025-01-15T10:30:00.100Z SERIES2 = [9000 9000 9000]
+             2025-01-15T10:31:00.000Z SERIES3 = [500 501 502]
+             2025-01-15T10:31:00.100Z SERIES1 = [100 101 102]
+             2025-01-15T10:31:00.200Z SERIES2 = [1000 1001 1002]
+             2025-01-15T10:31:00.300Z SERIES3 = [500 501 502]
+             2025-01-15T10:31:00.400Z END_CYCLE
+",
+        )
+        .unwrap();
+
+        let mut source = PlaybackDataSource::new(path, 1000.0, false);
+        let (protocol_tx, mut protocol_rx) = mpsc::channel(8);
+        source.set_protocol_channel(protocol_tx);
+        source.set_cycle_settings(CycleSettings {
+            timeout: Some(ChronoDuration::seconds(30)),
+            ..Default::default()
+        });
+
+        let mut cycle_rx = source.start().await.unwrap();
+        let issue = protocol_rx.recv().await.unwrap();
+        assert_eq!(issue.kind, ProtocolIssueKind::StaleCycle);
+        assert!(issue.line.contains("[1, 2]"));
+
+        let cycle = cycle_rx.recv().await.unwrap();
+        assert_eq!(cycle.dark().values, vec![100, 101, 102]);
+        assert_eq!(cycle.full().values, vec![1000, 1001, 1002]);
+
+        source.stop().await.unwrap();
+    }
 }
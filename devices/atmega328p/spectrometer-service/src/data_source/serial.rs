@@ -1,18 +1,191 @@
 use std::fs::OpenOptions;
-use std::io::{BufRead, BufReader, Write};
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
 
 use async_trait::async_trait;
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::sync::mpsc;
-use tokio::task::JoinHandle;
+use tokio::time::{Duration, interval, timeout};
+use tokio_serial::{SerialPort, SerialPortBuilderExt};
 
-use super::DataSource;
+use super::{DataSource, ReaderTask, SourceOutputs, join_reader};
 use crate::error::SpectrometerError;
-use crate::protocol::{CycleAccumulator, MeasurementCycle, parse_line};
+use crate::protocol::{
+    ConfirmedSettings, CycleSettings, DebugBlock, DeviceEvent, DeviceProtocol, FADC_TOLERANCE,
+    FirmwareVersion, MeasurementCycle, ParsedLine, ProtocolIssue, ProtocolKind,
+};
+
+/// Firmware major version this service speaks the protocol of
+pub const SUPPORTED_FIRMWARE_MAJOR: u32 = 1;
+
+/// How long to wait for the reply to `VERSION`
+const VERSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait for the device to confirm each setting sent at startup
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Times a setting is sent before startup fails for lack of a confirmation
+const CONFIG_ATTEMPTS: u32 = 3;
+
+/// How often a stale partial cycle is looked for while the port is silent
+const EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Parity bit of the serial line
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Parity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
+/// Flow control of the serial line
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowControl {
+    #[default]
+    None,
+    /// RTS/CTS
+    Hardware,
+    /// XON/XOFF
+    Software,
+}
+
+/// Framing and control lines of the serial port, 8N1 without flow control
+/// by default
+#[derive(clap::Args, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerialLineSettings {
+    /// Data bits per character (5-8)
+    #[arg(long, default_value = "8", value_parser = clap::value_parser!(u8).range(5..=8))]
+    pub data_bits: u8,
+
+    /// Parity bit
+    #[arg(long, value_enum, default_value = "none")]
+    pub parity: Parity,
+
+    /// Stop bits (1 or 2)
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u8).range(1..=2))]
+    pub stop_bits: u8,
+
+    /// Flow control
+    #[arg(long, value_enum, default_value = "none")]
+    pub flow_control: FlowControl,
+
+    /// Level DTR is set to right after opening the port; boards that reset
+    /// the AVR on DTR need `false`
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dtr: Option<bool>,
+
+    /// Level RTS is set to right after opening the port
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rts: Option<bool>,
+}
+
+impl Default for SerialLineSettings {
+    fn default() -> Self {
+        Self {
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+            dtr: None,
+            rts: None,
+        }
+    }
+}
+
+impl SerialLineSettings {
+    /// Open `port_name` with these settings and set the control lines
+    fn open(
+        &self,
+        port_name: &str,
+        baud_rate: u32,
+    ) -> Result<tokio_serial::SerialStream, SpectrometerError> {
+        let data_bits = match self.data_bits {
+            5 => tokio_serial::DataBits::Five,
+            6 => tokio_serial::DataBits::Six,
+            7 => tokio_serial::DataBits::Seven,
+            8 => tokio_serial::DataBits::Eight,
+            bits => {
+                return Err(SpectrometerError::Config(format!(
+                    "data_bits must be 5 to 8, got {bits}"
+                )));
+            }
+        };
+        let stop_bits = match self.stop_bits {
+            1 => tokio_serial::StopBits::One,
+            2 => tokio_serial::StopBits::Two,
+            bits => {
+                return Err(SpectrometerError::Config(format!(
+                    "stop_bits must be 1 or 2, got {bits}"
+                )));
+            }
+        };
+        let parity = match self.parity {
+            Parity::None => tokio_serial::Parity::None,
+            Parity::Odd => tokio_serial::Parity::Odd,
+            Parity::Even => tokio_serial::Parity::Even,
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => tokio_serial::FlowControl::None,
+            FlowControl::Hardware => tokio_serial::FlowControl::Hardware,
+            FlowControl::Software => tokio_serial::FlowControl::Software,
+        };
+
+        let mut port = tokio_serial::new(port_name, baud_rate)
+            .data_bits(data_bits)
+            .parity(parity)
+            .stop_bits(stop_bits)
+            .flow_control(flow_control)
+            .open_native_async()?;
+        if let Some(level) = self.dtr {
+            port.write_data_terminal_ready(level)?;
+        }
+        if let Some(level) = self.rts {
+            port.write_request_to_send(level)?;
+        }
+        Ok(port)
+    }
+}
+
+/// ADC setting sent at startup
+#[derive(Debug, Clone, Copy)]
+enum Setting {
+    Gain(u8),
+    Fadc(f32),
+    Count(u8),
+}
+
+impl Setting {
+    fn command(self) -> String {
+        match self {
+            Setting::Gain(gain) => format!("GAIN={gain}"),
+            Setting::Fadc(fadc) => format!("FADC={fadc}"),
+            Setting::Count(count) => format!("COUNT={count}"),
+        }
+    }
+
+    /// Whether `parsed` confirms this setting: `Some(false)` for a
+    /// confirmation of another value, `None` for unrelated lines
+    fn confirmed_by(self, parsed: &ParsedLine) -> Option<bool> {
+        match (self, parsed) {
+            (Setting::Gain(gain), ParsedLine::GainSet(confirmed)) => Some(gain == *confirmed),
+            (Setting::Fadc(fadc), ParsedLine::FadcSet(confirmed)) => {
+                Some((fadc - confirmed).abs() < FADC_TOLERANCE)
+            }
+            (Setting::Count(count), ParsedLine::CountSet(confirmed)) => Some(count == *confirmed),
+            _ => None,
+        }
+    }
+}
 
 /// Data source for real serial port connection to ATmega328P
 pub struct SerialDataSource {
@@ -22,11 +195,29 @@ pub struct SerialDataSource {
     fadc: f32,
     count: u8,
     log_file: Option<PathBuf>,
+    /// Framing, flow control and DTR/RTS levels
+    line: SerialLineSettings,
     is_active: Arc<AtomicBool>,
-    reader_task: Option<JoinHandle<()>>,
+    reader_task: Option<ReaderTask>,
     cmd_tx: Option<mpsc::Sender<String>>,
     /// Channel for forwarding raw serial lines to the UI
     log_tx: Option<mpsc::Sender<String>>,
+    /// Channel for forwarding firmware debug blocks
+    debug_tx: Option<mpsc::Sender<DebugBlock>>,
+    /// Channel for reporting protocol problems
+    protocol_tx: Option<mpsc::Sender<ProtocolIssue>>,
+    /// Channel for forwarding firmware status lines
+    event_tx: Option<mpsc::Sender<DeviceEvent>>,
+    /// Cycle timeout, series count and out-of-range policy
+    cycle_settings: CycleSettings,
+    /// Firmware dialect
+    protocol: ProtocolKind,
+    /// Start even if the firmware major version is not supported
+    allow_firmware_mismatch: bool,
+    /// Version reported by the firmware at startup
+    firmware_version: Option<FirmwareVersion>,
+    /// Settings the firmware confirmed at startup
+    confirmed_settings: Option<ConfirmedSettings>,
 }
 
 impl SerialDataSource {
@@ -45,142 +236,322 @@ impl SerialDataSource {
             fadc,
             count,
             log_file,
+            line: SerialLineSettings::default(),
             is_active: Arc::new(AtomicBool::new(false)),
             reader_task: None,
             cmd_tx: None,
             log_tx: None,
+            debug_tx: None,
+            protocol_tx: None,
+            event_tx: None,
+            cycle_settings: CycleSettings::default(),
+            protocol: ProtocolKind::default(),
+            allow_firmware_mismatch: false,
+            firmware_version: None,
+            confirmed_settings: None,
         }
     }
 
+    /// Start with a warning instead of failing when the firmware major
+    /// version is not supported
+    pub fn with_firmware_mismatch_allowed(mut self, allow: bool) -> Self {
+        self.allow_firmware_mismatch = allow;
+        self
+    }
+
+    pub fn with_line_settings(mut self, line: SerialLineSettings) -> Self {
+        self.line = line;
+        self
+    }
+
     /// List available serial ports (helper for CLI)
     pub fn list_available_ports() -> Result<Vec<serialport::SerialPortInfo>, SpectrometerError> {
         serialport::available_ports().map_err(SpectrometerError::SerialPort)
     }
 
-    /// Send initial configuration commands on the port
-    fn send_initial_config(
-        port: &mut dyn serialport::SerialPort,
+    /// Ask the firmware for its version. Firmware predating the `VERSION`
+    /// command does not answer, giving `None` after the timeout. Other lines
+    /// read meanwhile are dropped.
+    async fn query_version(
+        reader: &mut (impl AsyncBufRead + Unpin),
+        writer: &mut (impl AsyncWrite + Unpin),
+        protocol: &mut dyn DeviceProtocol,
+    ) -> Result<Option<FirmwareVersion>, SpectrometerError> {
+        writer.write_all(b"VERSION\n").await?;
+        writer.flush().await?;
+
+        let reply = timeout(VERSION_TIMEOUT, async {
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).await? == 0 {
+                    return Ok(None);
+                }
+                if let Some(ParsedLine::Version(version)) = protocol.parse(&line) {
+                    return Ok(Some(version));
+                }
+            }
+        })
+        .await;
+        reply.unwrap_or(Ok(None))
+    }
+
+    /// Check the reported firmware version against the supported major
+    /// version. Unknown versions are let through with a warning.
+    fn check_firmware(
+        version: Option<&FirmwareVersion>,
+        allow_mismatch: bool,
+    ) -> Result<(), SpectrometerError> {
+        let Some(version) = version else {
+            tracing::warn!("Firmware did not report a version; it may predate the VERSION command");
+            return Ok(());
+        };
+
+        if version.major == SUPPORTED_FIRMWARE_MAJOR {
+            tracing::info!("Firmware version {}", version.raw);
+            return Ok(());
+        }
+
+        let message = format!(
+            "Firmware version {} is not supported (expected major version {SUPPORTED_FIRMWARE_MAJOR})",
+            version.raw
+        );
+        if allow_mismatch {
+            tracing::warn!("{message}; continuing because --allow-firmware-mismatch is set");
+            Ok(())
+        } else {
+            Err(SpectrometerError::DataSource(message))
+        }
+    }
+
+    /// Send the initial configuration and wait for the device to confirm
+    /// each setting
+    async fn send_initial_config(
+        reader: &mut (impl AsyncBufRead + Unpin),
+        writer: &mut (impl AsyncWrite + Unpin),
+        protocol: &mut dyn DeviceProtocol,
         gain: u8,
         fadc: f32,
         count: u8,
-    ) -> Result<(), SpectrometerError> {
+    ) -> Result<ConfirmedSettings, SpectrometerError> {
         tracing::info!("Configuring device: GAIN={gain}, FADC={fadc}, COUNT={count}");
 
-        for cmd in [
-            format!("GAIN={gain}\n"),
-            format!("FADC={fadc}\n"),
-            format!("COUNT={count}\n"),
+        for setting in [
+            Setting::Gain(gain),
+            Setting::Fadc(fadc),
+            Setting::Count(count),
         ] {
-            port.write_all(cmd.as_bytes())?;
-            port.flush()?;
-            std::thread::sleep(Duration::from_millis(50));
+            Self::configure(reader, writer, protocol, setting).await?;
         }
 
-        tracing::info!("Device configuration sent");
-        Ok(())
+        tracing::info!("Device confirmed its configuration");
+        Ok(ConfirmedSettings {
+            gain,
+            fadc,
+            count,
+            confirmed_at: Utc::now(),
+        })
     }
-}
-
-#[async_trait]
-impl DataSource for SerialDataSource {
-    async fn start(&mut self) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError> {
-        let mut port = serialport::new(&self.port_name, self.baud_rate)
-            .timeout(Duration::from_millis(100))
-            .open()?;
-
-        // Send initial configuration
-        Self::send_initial_config(port.as_mut(), self.gain, self.fadc, self.count)?;
-
-        // Clone port for writing commands while reader owns the original
-        let mut write_port = port.try_clone()?;
 
-        let (cycle_tx, cycle_rx) = mpsc::channel(32);
-        let (cmd_tx, mut cmd_rx) = mpsc::channel::<String>(16);
-
-        self.is_active.store(true, Ordering::SeqCst);
-        self.cmd_tx = Some(cmd_tx);
-        let is_active = self.is_active.clone();
-        let port_name = self.port_name.clone();
-        let log_file = self.log_file.clone();
-        let log_tx = self.log_tx.clone();
-
-        // Spawn blocking reader + command writer task
-        let reader_handle = tokio::task::spawn_blocking(move || {
-            let mut reader = BufReader::new(port);
-            let mut accumulator = CycleAccumulator::new();
-            let mut line_buf = String::new();
-
-            let mut log_writer = log_file.and_then(|path| {
-                match OpenOptions::new().create(true).append(true).open(&path) {
-                    Ok(f) => {
-                        tracing::info!("Logging serial output to {:?}", path);
-                        Some(std::io::BufWriter::new(f))
+    /// Send one setting until the device confirms it, up to
+    /// `CONFIG_ATTEMPTS` times. An `ERROR` reply or a confirmation of another
+    /// value fails at once.
+    async fn configure(
+        reader: &mut (impl AsyncBufRead + Unpin),
+        writer: &mut (impl AsyncWrite + Unpin),
+        protocol: &mut dyn DeviceProtocol,
+        setting: Setting,
+    ) -> Result<(), SpectrometerError> {
+        let command = setting.command();
+        for attempt in 1..=CONFIG_ATTEMPTS {
+            writer.write_all(format!("{command}\n").as_bytes()).await?;
+            writer.flush().await?;
+
+            let reply = timeout(ACK_TIMEOUT, async {
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).await? == 0 {
+                        return Err(SpectrometerError::DataSource(format!(
+                            "Serial port closed while waiting for {command} to be confirmed"
+                        )));
                     }
-                    Err(e) => {
-                        tracing::error!("Failed to open log file {:?}: {e}", path);
-                        None
+                    let Some(parsed) = protocol.parse(&line) else {
+                        continue;
+                    };
+                    if let ParsedLine::Error(message) = parsed {
+                        return Err(SpectrometerError::DataSource(format!(
+                            "Device rejected {command}: {message}"
+                        )));
+                    }
+                    match setting.confirmed_by(&parsed) {
+                        Some(true) => return Ok(()),
+                        Some(false) => {
+                            return Err(SpectrometerError::DataSource(format!(
+                                "Device answered {command} with {}",
+                                line.trim()
+                            )));
+                        }
+                        None => {}
                     }
                 }
-            });
+            })
+            .await;
+
+            match reply {
+                Ok(result) => return result,
+                Err(_) => tracing::warn!(
+                    "No confirmation of {command} (attempt {attempt} of {CONFIG_ATTEMPTS})"
+                ),
+            }
+        }
 
-            tracing::info!("Serial reader started on {}", port_name);
+        Err(SpectrometerError::DataSource(format!(
+            "Device did not confirm {command} after {CONFIG_ATTEMPTS} attempts"
+        )))
+    }
 
-            let log_line = |w: &mut std::io::BufWriter<std::fs::File>, line: &str| {
-                let ts = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
-                let _ = writeln!(w, "{ts} {line}");
-                let _ = w.flush();
-            };
+    /// Read lines from the port and write commands to it until the port
+    /// fails or the cycle receiver is gone
+    async fn run(
+        reader: impl AsyncBufRead + Unpin,
+        mut writer: impl AsyncWrite + Unpin,
+        mut protocol: Box<dyn DeviceProtocol>,
+        mut cmd_rx: mpsc::Receiver<String>,
+        mut log_writer: Option<std::io::BufWriter<std::fs::File>>,
+        outputs: SourceOutputs,
+    ) -> Result<(), SpectrometerError> {
+        let mut lines = reader.lines();
+        let mut tick = interval(EXPIRE_INTERVAL);
+
+        loop {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let line = match line {
+                        Ok(Some(line)) => line,
+                        Ok(None) => {
+                            return Err(SpectrometerError::DataSource(format!(
+                                "Serial port {} closed",
+                                outputs.source
+                            )));
+                        }
+                        Err(e) => {
+                            tracing::error!("Serial read error: {e}");
+                            return Err(SpectrometerError::DataSource(format!(
+                                "Serial read error on {}: {e}",
+                                outputs.source
+                            )));
+                        }
+                    };
 
-            while is_active.load(Ordering::SeqCst) {
-                // Check for pending commands (non-blocking)
-                while let Ok(cmd) = cmd_rx.try_recv() {
+                    let trimmed = line.trim_end().to_string();
+                    if let Some(w) = &mut log_writer {
+                        log_line(w, &trimmed);
+                    }
+                    if let Some(tx) = &outputs.log_tx {
+                        let _ = tx.send(trimmed.clone()).await;
+                    }
+                    let Some(parsed) = outputs.parse(protocol.as_mut(), &trimmed, Utc::now()).await
+                    else {
+                        continue;
+                    };
+                    if let Some(cycle) = protocol.accumulate(parsed, None)
+                        && outputs.cycle_tx.send(cycle).await.is_err()
+                    {
+                        tracing::warn!("Cycle receiver dropped, stopping reader");
+                        return Ok(());
+                    }
+                }
+                Some(cmd) = cmd_rx.recv() => {
                     let cmd_line = format!("> {}", cmd.trim());
                     tracing::info!("Sending command: {}", cmd.trim());
                     if let Some(w) = &mut log_writer {
                         log_line(w, &cmd_line);
                     }
-                    if let Some(tx) = &log_tx {
-                        let _ = tx.blocking_send(cmd_line);
+                    if let Some(tx) = &outputs.log_tx {
+                        let _ = tx.send(cmd_line).await;
                     }
-                    if let Err(e) = write_port.write_all(cmd.as_bytes()) {
+                    if let Err(e) = writer.write_all(cmd.as_bytes()).await {
                         tracing::error!("Failed to send command: {e}");
                     }
-                    let _ = write_port.flush();
+                    let _ = writer.flush().await;
                 }
+                // Checked while the port stays silent too, so a cycle cut
+                // off by a brownout is dropped
+                _ = tick.tick() => outputs.expire(protocol.as_mut(), Utc::now()).await,
+            }
+        }
+    }
+}
 
-                line_buf.clear();
-                match reader.read_line(&mut line_buf) {
-                    Ok(0) => continue,
-                    Ok(_) => {
-                        let trimmed = line_buf.trim_end().to_string();
-                        if let Some(w) = &mut log_writer {
-                            log_line(w, &trimmed);
-                        }
-                        if let Some(tx) = &log_tx {
-                            let _ = tx.blocking_send(trimmed);
-                        }
-                        let parsed = parse_line(&line_buf);
-                        if let Some(cycle) = accumulator.process_line(parsed)
-                            && cycle_tx.blocking_send(cycle).is_err()
-                        {
-                            tracing::warn!("Cycle receiver dropped, stopping reader");
-                            break;
-                        }
-                    }
-                    Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                        continue;
-                    }
-                    Err(e) => {
-                        tracing::error!("Serial read error: {e}");
-                        break;
-                    }
+/// Append a line to the serial log file with the time it was read or sent
+fn log_line(w: &mut std::io::BufWriter<std::fs::File>, line: &str) {
+    let ts = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+    let _ = writeln!(w, "{ts} {line}");
+    let _ = w.flush();
+}
+
+#[async_trait]
+impl DataSource for SerialDataSource {
+    async fn start(&mut self) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError> {
+        let port = self.line.open(&self.port_name, self.baud_rate)?;
+        let (reader, mut writer) = tokio::io::split(port);
+        let mut reader = BufReader::new(reader);
+
+        let mut handshake = self.protocol.create(CycleSettings::default());
+        let version = Self::query_version(&mut reader, &mut writer, handshake.as_mut()).await?;
+        Self::check_firmware(version.as_ref(), self.allow_firmware_mismatch)?;
+        self.firmware_version = version;
+
+        // Send initial configuration
+        self.confirmed_settings = Some(
+            Self::send_initial_config(
+                &mut reader,
+                &mut writer,
+                handshake.as_mut(),
+                self.gain,
+                self.fadc,
+                self.count,
+            )
+            .await?,
+        );
+
+        let log_writer = self.log_file.as_ref().and_then(|path| {
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(f) => {
+                    tracing::info!("Logging serial output to {:?}", path);
+                    Some(std::io::BufWriter::new(f))
+                }
+                Err(e) => {
+                    tracing::error!("Failed to open log file {:?}: {e}", path);
+                    None
                 }
             }
-
-            tracing::info!("Serial reader stopped");
         });
 
-        self.reader_task = Some(reader_handle);
+        let (cycle_tx, cycle_rx) = mpsc::channel(32);
+        let (cmd_tx, cmd_rx) = mpsc::channel::<String>(16);
+
+        self.is_active.store(true, Ordering::SeqCst);
+        self.cmd_tx = Some(cmd_tx);
+        let protocol = self.protocol.create(self.cycle_settings);
+        tracing::info!(
+            "Serial reader started on {} ({} protocol)",
+            self.port_name,
+            protocol.name()
+        );
+        let outputs = SourceOutputs {
+            cycle_tx,
+            log_tx: self.log_tx.clone(),
+            debug_tx: self.debug_tx.clone(),
+            protocol_tx: self.protocol_tx.clone(),
+            event_tx: self.event_tx.clone(),
+            source: self.port_name.clone(),
+        };
+
+        self.reader_task = Some(tokio::spawn(Self::run(
+            reader, writer, protocol, cmd_rx, log_writer, outputs,
+        )));
         Ok(cycle_rx)
     }
 
@@ -188,12 +559,17 @@ impl DataSource for SerialDataSource {
         self.is_active.store(false, Ordering::SeqCst);
         self.cmd_tx = None;
 
-        if let Some(handle) = self.reader_task.take() {
-            let _ = handle.await;
-        }
+        // Nothing to wait for: the reader only ever waits on the port
+        let outcome = match self.reader_task.take() {
+            Some(task) => {
+                task.abort();
+                join_reader(task).await
+            }
+            None => Ok(()),
+        };
 
         tracing::info!("Serial data source stopped");
-        Ok(())
+        outcome
     }
 
     fn is_active(&self) -> bool {
@@ -204,6 +580,34 @@ impl DataSource for SerialDataSource {
         self.log_tx = Some(tx);
     }
 
+    fn set_debug_channel(&mut self, tx: mpsc::Sender<DebugBlock>) {
+        self.debug_tx = Some(tx);
+    }
+
+    fn set_protocol_channel(&mut self, tx: mpsc::Sender<ProtocolIssue>) {
+        self.protocol_tx = Some(tx);
+    }
+
+    fn set_event_channel(&mut self, tx: mpsc::Sender<DeviceEvent>) {
+        self.event_tx = Some(tx);
+    }
+
+    fn set_cycle_settings(&mut self, settings: CycleSettings) {
+        self.cycle_settings = settings;
+    }
+
+    fn set_protocol(&mut self, protocol: ProtocolKind) {
+        self.protocol = protocol;
+    }
+
+    fn firmware_version(&self) -> Option<FirmwareVersion> {
+        self.firmware_version.clone()
+    }
+
+    fn confirmed_settings(&self) -> Option<ConfirmedSettings> {
+        self.confirmed_settings
+    }
+
     async fn send_command(&mut self, command: &str) -> Result<(), SpectrometerError> {
         let Some(tx) = &self.cmd_tx else {
             return Err(SpectrometerError::DataSource(
@@ -229,6 +633,8 @@ impl DataSource for SerialDataSource {
 
 #[cfg(test)]
 mod tests {
+    use tokio::io::AsyncReadExt;
+
     use super::*;
 
     #[test]
@@ -253,6 +659,180 @@ mod tests {
         assert!(!source.is_active());
     }
 
+    #[test]
+    fn test_check_firmware() {
+        let version = |text| FirmwareVersion::parse(text).unwrap();
+
+        assert!(SerialDataSource::check_firmware(Some(&version("1.2.0")), false).is_ok());
+        assert!(SerialDataSource::check_firmware(None, false).is_ok());
+        assert!(SerialDataSource::check_firmware(Some(&version("2.0.0")), false).is_err());
+        assert!(SerialDataSource::check_firmware(Some(&version("2.0.0")), true).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_query_version() {
+        let (mut device, port) = tokio::io::duplex(256);
+        let (reader, mut writer) = tokio::io::split(port);
+        let mut reader = BufReader::new(reader);
+
+        device
+            .write_all(b"SERIES1 = [1 2]\nVERSION=1.2.0\n")
+            .await
+            .unwrap();
+        let mut protocol = ProtocolKind::Atmega.create(CycleSettings::default());
+        let version = SerialDataSource::query_version(&mut reader, &mut writer, protocol.as_mut())
+            .await
+            .unwrap();
+        assert_eq!(version.unwrap().major, 1);
+
+        let mut sent = [0u8; 8];
+        device.read_exact(&mut sent).await.unwrap();
+        assert_eq!(&sent, b"VERSION\n");
+    }
+
+    /// Configure a fake device that answers each command line with `reply`
+    async fn configure_fake_device(
+        reply: fn(&str) -> Option<String>,
+    ) -> Result<ConfirmedSettings, SpectrometerError> {
+        let (device, port) = tokio::io::duplex(256);
+        tokio::spawn(async move {
+            let (device_reader, mut device_writer) = tokio::io::split(device);
+            let mut commands = BufReader::new(device_reader).lines();
+            while let Ok(Some(command)) = commands.next_line().await {
+                if let Some(answer) = reply(&command) {
+                    let _ = device_writer.write_all(answer.as_bytes()).await;
+                }
+            }
+        });
+
+        let (reader, mut writer) = tokio::io::split(port);
+        let mut protocol = ProtocolKind::Atmega.create(CycleSettings::default());
+        SerialDataSource::send_initial_config(
+            &mut BufReader::new(reader),
+            &mut writer,
+            protocol.as_mut(),
+            4,
+            62.5,
+            3,
+        )
+        .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_initial_config_confirmed() {
+        // Cycles streaming meanwhile are skipped
+        let confirmed =
+            configure_fake_device(|command| Some(format!("SERIES1 = [1 2]\nOK {command}\n")))
+                .await
+                .unwrap();
+        assert_eq!(
+            (confirmed.gain, confirmed.fadc, confirmed.count),
+            (4, 62.5, 3)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_initial_config_rejected() {
+        let error = configure_fake_device(|command| {
+            Some(match command {
+                "COUNT=3" => "ERROR Invalid COUNT value\n".to_string(),
+                _ => format!("{command}\n"),
+            })
+        })
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("rejected COUNT=3"), "{error}");
+
+        let error = configure_fake_device(|_| Some("GAIN=2\n".to_string()))
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("answered GAIN=4 with GAIN=2"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_initial_config_retried_then_fails() {
+        // The first FADC command is lost
+        static FADC_SENT: AtomicBool = AtomicBool::new(false);
+        let confirmed = configure_fake_device(|command| {
+            (!command.starts_with("FADC") || FADC_SENT.swap(true, Ordering::SeqCst))
+                .then(|| format!("{command}\n"))
+        })
+        .await;
+        assert!(confirmed.is_ok());
+
+        let error = configure_fake_device(|_| None).await.unwrap_err();
+        assert!(error.to_string().contains("after 3 attempts"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_reader_forwards_cycles_and_commands() {
+        let (mut device, port) = tokio::io::duplex(256);
+        let (reader, writer) = tokio::io::split(port);
+        let (cycle_tx, mut cycle_rx) = mpsc::channel(1);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+        let outputs = SourceOutputs {
+            cycle_tx,
+            log_tx: None,
+            debug_tx: None,
+            protocol_tx: None,
+            event_tx: None,
+            source: "test".to_string(),
+        };
+        let reader = tokio::spawn(SerialDataSource::run(
+            BufReader::new(reader),
+            writer,
+            ProtocolKind::Atmega.create(CycleSettings::default()),
+            cmd_rx,
+            None,
+            outputs,
+        ));
+
+        device
+            .write_all(
+                b"SERIES1 = [100 101]\r\nSERIES2 = [1000 1001]\r\n\
+                  SERIES3 = [500 501]\r\nEND_CYCLE\r\n",
+            )
+            .await
+            .unwrap();
+        assert!(cycle_rx.recv().await.is_some());
+
+        cmd_tx.send("GAIN=4\n".to_string()).await.unwrap();
+        let mut sent = [0u8; 7];
+        device.read_exact(&mut sent).await.unwrap();
+        assert_eq!(&sent, b"GAIN=4\n");
+
+        // The device going away ends the reader with an error
+        drop(device);
+        assert!(reader.await.unwrap().is_err());
+    }
+
+    #[test]
+    fn test_line_settings_from_config() {
+        let line: SerialLineSettings =
+            toml::from_str("parity = \"even\"\nstop_bits = 2\ndtr = false").unwrap();
+        assert_eq!(line.data_bits, 8);
+        assert_eq!(line.parity, Parity::Even);
+        assert_eq!(line.stop_bits, 2);
+        assert_eq!(line.flow_control, FlowControl::None);
+        assert_eq!(line.dtr, Some(false));
+        assert_eq!(line.rts, None);
+    }
+
+    #[test]
+    fn test_invalid_line_settings_rejected_before_opening() {
+        let line = SerialLineSettings {
+            data_bits: 9,
+            ..Default::default()
+        };
+        assert!(matches!(
+            line.open("/dev/does-not-exist", 38400),
+            Err(SpectrometerError::Config(_))
+        ));
+    }
+
     #[test]
     fn test_list_ports_doesnt_panic() {
         let _ = SerialDataSource::list_available_ports();
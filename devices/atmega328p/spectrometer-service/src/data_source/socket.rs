@@ -0,0 +1,392 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time::{Duration, interval};
+
+use super::{DataSource, ReaderTask, SourceOutputs, join_reader};
+use crate::error::SpectrometerError;
+use crate::protocol::{
+    CycleSettings, DebugBlock, DeviceEvent, DeviceProtocol, MeasurementCycle, ProtocolIssue,
+    ProtocolKind,
+};
+
+/// How often the listener checks for stop and stale partial cycles while
+/// no line arrives
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Data source listening on a Unix domain socket (a named pipe on Windows)
+/// for the line protocol written by a local bridge process, e.g. a vendor
+/// daemon that cannot talk to a serial port itself. One bridge is served at
+/// a time; after it disconnects the next one is accepted.
+pub struct SocketDataSource {
+    path: PathBuf,
+    name: String,
+    is_active: Arc<AtomicBool>,
+    reader_task: Option<ReaderTask>,
+    cmd_tx: Option<mpsc::Sender<String>>,
+    /// Channel for forwarding received lines to the UI
+    log_tx: Option<mpsc::Sender<String>>,
+    /// Channel for forwarding firmware debug blocks
+    debug_tx: Option<mpsc::Sender<DebugBlock>>,
+    /// Channel for reporting protocol problems
+    protocol_tx: Option<mpsc::Sender<ProtocolIssue>>,
+    /// Channel for forwarding firmware status lines
+    event_tx: Option<mpsc::Sender<DeviceEvent>>,
+    /// Cycle timeout, series count and out-of-range policy
+    cycle_settings: CycleSettings,
+    /// Firmware dialect
+    protocol: ProtocolKind,
+}
+
+impl SocketDataSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            name: path.display().to_string(),
+            path,
+            is_active: Arc::new(AtomicBool::new(false)),
+            reader_task: None,
+            cmd_tx: None,
+            log_tx: None,
+            debug_tx: None,
+            protocol_tx: None,
+            event_tx: None,
+            cycle_settings: CycleSettings::default(),
+            protocol: ProtocolKind::default(),
+        }
+    }
+
+    /// Accept bridges one after another until stopped
+    async fn run(
+        mut listener: BridgeListener,
+        mut protocol: Box<dyn DeviceProtocol>,
+        mut cmd_rx: mpsc::Receiver<String>,
+        is_active: Arc<AtomicBool>,
+        outputs: SourceOutputs,
+    ) {
+        let mut tick = interval(POLL_INTERVAL);
+
+        while is_active.load(Ordering::SeqCst) {
+            let stream = tokio::select! {
+                accepted = listener.accept() => accepted,
+                Some(cmd) = cmd_rx.recv() => {
+                    tracing::warn!("No bridge connected, dropping command: {}", cmd.trim());
+                    continue;
+                }
+                _ = tick.tick() => {
+                    outputs.expire(protocol.as_mut(), Utc::now()).await;
+                    continue;
+                }
+            };
+
+            match stream {
+                Ok(stream) => {
+                    tracing::info!("Bridge connected to {}", outputs.source);
+                    let open =
+                        Self::serve(stream, protocol.as_mut(), &mut cmd_rx, &is_active, &outputs)
+                            .await;
+                    if !open {
+                        return;
+                    }
+                    tracing::info!("Bridge disconnected from {}", outputs.source);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to accept on {}: {e}", outputs.source);
+                    tick.tick().await;
+                }
+            }
+        }
+
+        tracing::info!("Socket listener on {} stopped", outputs.source);
+    }
+
+    /// Read lines from one bridge until it disconnects or the source is
+    /// stopped, writing commands back to it. Returns false when the cycle
+    /// receiver is gone.
+    async fn serve(
+        stream: impl AsyncRead + AsyncWrite,
+        protocol: &mut dyn DeviceProtocol,
+        cmd_rx: &mut mpsc::Receiver<String>,
+        is_active: &AtomicBool,
+        outputs: &SourceOutputs,
+    ) -> bool {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut lines = BufReader::new(reader).lines();
+        let mut tick = interval(POLL_INTERVAL);
+
+        while is_active.load(Ordering::SeqCst) {
+            tokio::select! {
+                line = lines.next_line() => {
+                    let line = match line {
+                        Ok(Some(line)) => line,
+                        Ok(None) => break,
+                        Err(e) => {
+                            tracing::error!("Socket read error on {}: {e}", outputs.source);
+                            break;
+                        }
+                    };
+
+                    let now = Utc::now();
+                    let trimmed = line.trim_end().to_string();
+                    if let Some(tx) = &outputs.log_tx {
+                        let _ = tx.send(trimmed.clone()).await;
+                    }
+                    let Some(parsed) = outputs.parse(protocol, &trimmed, now).await else {
+                        continue;
+                    };
+                    if let Some(cycle) = protocol.accumulate(parsed, None)
+                        && outputs.cycle_tx.send(cycle).await.is_err()
+                    {
+                        tracing::warn!("Cycle receiver dropped, stopping socket listener");
+                        return false;
+                    }
+                }
+                Some(cmd) = cmd_rx.recv() => {
+                    tracing::info!("Sending command: {}", cmd.trim());
+                    if let Some(tx) = &outputs.log_tx {
+                        let _ = tx.send(format!("> {}", cmd.trim())).await;
+                    }
+                    if let Err(e) = writer.write_all(cmd.as_bytes()).await {
+                        tracing::error!("Failed to send command: {e}");
+                    }
+                    let _ = writer.flush().await;
+                }
+                _ = tick.tick() => outputs.expire(protocol, Utc::now()).await,
+            }
+        }
+        true
+    }
+}
+
+#[async_trait]
+impl DataSource for SocketDataSource {
+    async fn start(&mut self) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError> {
+        let listener = BridgeListener::bind(&self.path).map_err(|e| {
+            SpectrometerError::DataSource(format!("Cannot listen on {}: {e}", self.name))
+        })?;
+        let protocol = self.protocol.create(self.cycle_settings);
+        tracing::info!(
+            "Listening for a bridge on {} ({} protocol)",
+            self.name,
+            protocol.name()
+        );
+
+        let (cycle_tx, cycle_rx) = mpsc::channel(32);
+        let (cmd_tx, cmd_rx) = mpsc::channel::<String>(16);
+
+        self.is_active.store(true, Ordering::SeqCst);
+        self.cmd_tx = Some(cmd_tx);
+        let outputs = SourceOutputs {
+            cycle_tx,
+            log_tx: self.log_tx.clone(),
+            debug_tx: self.debug_tx.clone(),
+            protocol_tx: self.protocol_tx.clone(),
+            event_tx: self.event_tx.clone(),
+            source: self.name.clone(),
+        };
+
+        let is_active = self.is_active.clone();
+        self.reader_task = Some(tokio::spawn(async move {
+            Self::run(listener, protocol, cmd_rx, is_active, outputs).await;
+            Ok(())
+        }));
+        Ok(cycle_rx)
+    }
+
+    async fn stop(&mut self) -> Result<(), SpectrometerError> {
+        self.is_active.store(false, Ordering::SeqCst);
+        self.cmd_tx = None;
+
+        let outcome = match self.reader_task.take() {
+            Some(task) => {
+                let outcome = join_reader(task).await;
+                BridgeListener::remove(&self.path);
+                outcome
+            }
+            None => Ok(()),
+        };
+
+        tracing::info!("Socket data source stopped");
+        outcome
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active.load(Ordering::SeqCst)
+    }
+
+    fn set_log_channel(&mut self, tx: mpsc::Sender<String>) {
+        self.log_tx = Some(tx);
+    }
+
+    fn set_debug_channel(&mut self, tx: mpsc::Sender<DebugBlock>) {
+        self.debug_tx = Some(tx);
+    }
+
+    fn set_protocol_channel(&mut self, tx: mpsc::Sender<ProtocolIssue>) {
+        self.protocol_tx = Some(tx);
+    }
+
+    fn set_event_channel(&mut self, tx: mpsc::Sender<DeviceEvent>) {
+        self.event_tx = Some(tx);
+    }
+
+    fn set_cycle_settings(&mut self, settings: CycleSettings) {
+        self.cycle_settings = settings;
+    }
+
+    fn set_protocol(&mut self, protocol: ProtocolKind) {
+        self.protocol = protocol;
+    }
+
+    /// Commands are written to the connected bridge, which is expected to
+    /// pass them on to the device; they are dropped while none is connected
+    async fn send_command(&mut self, command: &str) -> Result<(), SpectrometerError> {
+        let Some(tx) = &self.cmd_tx else {
+            return Err(SpectrometerError::DataSource(
+                "Data source not started".into(),
+            ));
+        };
+
+        let cmd = if command.ends_with('\n') {
+            command.to_string()
+        } else {
+            format!("{command}\n")
+        };
+
+        tx.send(cmd)
+            .await
+            .map_err(|_| SpectrometerError::DataSource("Command channel closed".into()))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Unix domain socket the bridge connects to
+#[cfg(unix)]
+struct BridgeListener(tokio::net::UnixListener);
+
+#[cfg(unix)]
+impl BridgeListener {
+    /// Listen on `path`, replacing a socket left behind by an earlier run
+    fn bind(path: &Path) -> std::io::Result<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if let Ok(metadata) = std::fs::symlink_metadata(path)
+            && metadata.file_type().is_socket()
+        {
+            std::fs::remove_file(path)?;
+        }
+        tokio::net::UnixListener::bind(path).map(Self)
+    }
+
+    async fn accept(&mut self) -> std::io::Result<tokio::net::UnixStream> {
+        self.0.accept().await.map(|(stream, _)| stream)
+    }
+
+    fn remove(path: &Path) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Named pipe the bridge connects to, e.g. `\\.\pipe\spectrometer`
+#[cfg(windows)]
+struct BridgeListener {
+    path: PathBuf,
+    server: tokio::net::windows::named_pipe::NamedPipeServer,
+}
+
+#[cfg(windows)]
+impl BridgeListener {
+    fn bind(path: &Path) -> std::io::Result<Self> {
+        let server = tokio::net::windows::named_pipe::ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            server,
+        })
+    }
+
+    /// Wait for a client, then open the next pipe instance for the one after
+    async fn accept(
+        &mut self,
+    ) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeServer> {
+        self.server.connect().await?;
+        let next = tokio::net::windows::named_pipe::ServerOptions::new().create(&self.path)?;
+        Ok(std::mem::replace(&mut self.server, next))
+    }
+
+    /// Pipes disappear with their last handle
+    fn remove(_path: &Path) {}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::UnixStream;
+
+    async fn recv(cycle_rx: &mut mpsc::Receiver<MeasurementCycle>) -> MeasurementCycle {
+        tokio::time::timeout(Duration::from_secs(2), cycle_rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_socket_source_reads_bridges() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spectrometer.sock");
+        let mut source = SocketDataSource::new(path.clone());
+        let mut cycle_rx = source.start().await.unwrap();
+
+        let mut bridge = UnixStream::connect(&path).await.unwrap();
+        bridge
+            .write_all(b"SERIES1 = [100 101]\nSERIES2 = [1000 1001]\nSERIES3 = [500 501]\n")
+            .await
+            .unwrap();
+        bridge.write_all(b"END_CYCLE\n").await.unwrap();
+        assert_eq!(recv(&mut cycle_rx).await.dark().values, vec![100, 101]);
+
+        source.send_command("GAIN=4").await.unwrap();
+        let mut command = [0u8; 7];
+        bridge.read_exact(&mut command).await.unwrap();
+        assert_eq!(&command, b"GAIN=4\n");
+        drop(bridge);
+
+        // The next bridge is accepted after the first disconnects
+        let mut bridge = UnixStream::connect(&path).await.unwrap();
+        bridge
+            .write_all(
+                b"SERIES1 = [200 201]\nSERIES2 = [1000 1001]\nSERIES3 = [500 501]\nEND_CYCLE\n",
+            )
+            .await
+            .unwrap();
+        assert_eq!(recv(&mut cycle_rx).await.dark().values, vec![200, 201]);
+
+        source.stop().await.unwrap();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_socket_source_replaces_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spectrometer.sock");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let mut source = SocketDataSource::new(path.clone());
+        source.start().await.unwrap();
+        source.stop().await.unwrap();
+
+        // Anything other than a socket is left alone
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(SocketDataSource::new(path).start().await.is_err());
+    }
+}
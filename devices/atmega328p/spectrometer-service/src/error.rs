@@ -16,6 +16,12 @@ pub enum SpectrometerError {
     #[error("HTTP client error: {0}")]
     HttpClient(#[from] reqwest::Error),
 
+    #[error("Monitoring API returned {0}")]
+    MonitoringStatus(reqwest::StatusCode),
+
+    #[error("Archive error: {0}")]
+    Storage(#[from] rusqlite::Error),
+
     #[error("Validation error: {0}")]
     Validation(String),
 
@@ -30,11 +36,14 @@ pub enum SpectrometerError {
 
     #[error("Device not registered with monitoring API")]
     NotRegistered,
+
+    #[error("Invalid chamber transition: {from} -> {to}")]
+    InvalidTransition { from: String, to: String },
 }
 
 /// Protocol-specific errors for ATmega328P communication
 #[allow(dead_code)]
-#[derive(Error, Debug, Clone)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum ProtocolError {
     #[error("Invalid GAIN value: {0}. Valid values: 1, 2, 4, 8, 16, 32, 64, 128")]
     InvalidGain(u8),
@@ -56,6 +65,9 @@ pub enum ProtocolError {
     #[error("Unexpected line: {0}")]
     UnexpectedLine(String),
 
+    #[error("Malformed {keyword} line: {line}")]
+    MalformedLine { keyword: &'static str, line: String },
+
     #[error("Invalid timestamp format: {0}")]
     InvalidTimestamp(String),
 }
@@ -0,0 +1,284 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::error::SpectrometerError;
+
+/// Format of log lines
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines (default)
+    #[default]
+    Text,
+    /// One JSON object per line, with the span fields, for log shippers
+    Json,
+}
+
+/// When the log file is rotated
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogRotation {
+    /// Never; the file grows without bound
+    Never,
+    /// Every hour
+    Hourly,
+    /// Every day (default)
+    #[default]
+    Daily,
+    /// Once the file exceeds `--log-max-size-mb`
+    Size,
+}
+
+/// Where and how the service logs
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogSettings {
+    pub format: LogFormat,
+    /// Log to this file instead of stderr
+    pub file: Option<PathBuf>,
+    pub rotation: LogRotation,
+    /// Size that triggers `LogRotation::Size`, in bytes
+    pub max_size: u64,
+    /// Rotated files kept next to the current one; 0 keeps all
+    pub max_files: usize,
+}
+
+/// Install the global subscriber. The returned guard flushes the log file
+/// when dropped, so it must live until the service exits.
+pub fn init(settings: &LogSettings) -> Result<Option<WorkerGuard>, SpectrometerError> {
+    let (writer, guard) = match &settings.file {
+        Some(path) => {
+            let (writer, guard) = tracing_appender::non_blocking(open_log_file(path, settings)?);
+            (Some(writer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let layer = match settings.format {
+        LogFormat::Text => fmt_layer(tracing_subscriber::fmt::layer().with_ansi(false), writer),
+        LogFormat::Json => fmt_layer(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false),
+            writer,
+        ),
+    };
+
+    tracing_subscriber::registry()
+        .with(layer)
+        .with(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "spectrometer_service=info".into()),
+        )
+        .init();
+
+    Ok(guard)
+}
+
+/// `layer` writing to the log file, or to stderr without one
+fn fmt_layer<S, N, E>(
+    layer: tracing_subscriber::fmt::Layer<S, N, E>,
+    writer: Option<NonBlocking>,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    N: for<'writer> tracing_subscriber::fmt::FormatFields<'writer> + Send + Sync + 'static,
+    E: tracing_subscriber::fmt::FormatEvent<S, N> + Send + Sync + 'static,
+{
+    match writer {
+        Some(writer) => layer.with_writer(writer).boxed(),
+        None => layer.with_writer(io::stderr).boxed(),
+    }
+}
+
+/// Writer for the log file at `path`, rotated as configured
+fn open_log_file(
+    path: &Path,
+    settings: &LogSettings,
+) -> Result<Box<dyn Write + Send>, SpectrometerError> {
+    let rotation = match settings.rotation {
+        LogRotation::Size => {
+            return Ok(Box::new(SizeRollingFile::open(
+                path,
+                settings.max_size,
+                settings.max_files,
+            )?));
+        }
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+    };
+
+    let (directory, file_name) = split_path(path)?;
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(file_name);
+    if settings.max_files > 0 {
+        // The current file counts towards the limit
+        builder = builder.max_log_files(settings.max_files + 1);
+    }
+    let appender = builder
+        .build(directory)
+        .map_err(|e| SpectrometerError::Config(format!("cannot open log file: {e}")))?;
+    Ok(Box::new(appender))
+}
+
+/// Directory and file name of a log file path
+fn split_path(path: &Path) -> Result<(PathBuf, String), SpectrometerError> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| SpectrometerError::Config(format!("invalid log file {path:?}")))?;
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    Ok((directory.to_path_buf(), file_name.to_string()))
+}
+
+/// Log file that is renamed to `<name>.<timestamp>` and started anew once
+/// it exceeds `max_size`, keeping the newest `max_files` rotated files
+struct SizeRollingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl SizeRollingFile {
+    fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size: max_size.max(1),
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(Utc::now().format(".%Y-%m-%d-%H-%M-%S%.6f").to_string());
+        fs::rename(&self.path, rotated)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.prune()
+    }
+
+    /// Delete the oldest rotated files beyond `max_files`
+    fn prune(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return Ok(());
+        }
+        let Ok((directory, file_name)) = split_path(&self.path) else {
+            return Ok(());
+        };
+        let prefix = format!("{file_name}.");
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&directory)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .collect();
+        // Timestamps sort chronologically
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for path in &rotated[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for SizeRollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotated_files(directory: &Path) -> usize {
+        fs::read_dir(directory)
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("service.log.")
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_size_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("service.log");
+        let mut file = SizeRollingFile::open(&path, 10, 2).unwrap();
+
+        file.write_all(b"12345678\n").unwrap();
+        assert_eq!(rotated_files(dir.path()), 0);
+
+        // Would pass 10 bytes: the current file is rotated first
+        file.write_all(b"abc\n").unwrap();
+        assert_eq!(rotated_files(dir.path()), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abc\n");
+
+        for _ in 0..3 {
+            file.write_all(b"0123456789\n").unwrap();
+        }
+        assert_eq!(rotated_files(dir.path()), 2);
+    }
+
+    #[test]
+    fn test_size_rotation_continues_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("service.log");
+        fs::write(&path, "0123456789").unwrap();
+
+        let mut file = SizeRollingFile::open(&path, 10, 0).unwrap();
+        file.write_all(b"next\n").unwrap();
+        assert_eq!(rotated_files(dir.path()), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "next\n");
+    }
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(
+            split_path(Path::new("service.log")).unwrap(),
+            (PathBuf::from("."), "service.log".to_string())
+        );
+        assert_eq!(
+            split_path(Path::new("/var/log/spectrometer/service.log")).unwrap(),
+            (
+                PathBuf::from("/var/log/spectrometer"),
+                "service.log".to_string()
+            )
+        );
+    }
+}
@@ -1,38 +1,74 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
-use tokio::sync::{broadcast, mpsc};
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
 
 mod api;
 mod config;
+mod daemon;
+mod data_sink;
 mod data_source;
 mod error;
+mod logging;
 mod monitoring;
 mod processing;
 mod protocol;
+mod server;
 mod service;
+mod storage;
 
-use config::Cli;
+use config::{Cli, Mode};
+use data_sink::DataSinkConfig;
+use data_sink::monitoring::MonitoringSinkSettings;
+use data_sink::pushgateway::PushgatewaySettings;
+use data_source::lint::lint_log;
 use data_source::serial::SerialDataSource;
-use service::calibration::create_shared_config;
+use data_source::{DataSourceConfig, SourceSetup};
+use monitoring::dead_letter::redeliver;
+use monitoring::mdns::{MdnsAdvertiser, MdnsService};
+use monitoring::{Announcement, MonitoringClient};
+use processing::outlier::create_shared_excluder;
+use processing::references::ReferenceTable;
+use protocol::{CycleSettings, DeviceEvent, DeviceEventKind, ProtocolIssue, ProtocolIssueKind};
+use service::alerts::run_alerts;
+use service::autogain::run_auto_gain;
+use service::calibration::{SharedConfig, create_shared_config, device_file, validate_devices};
 use service::data_loop::DataProcessingLoop;
+use service::events::{EventBus, ServiceEvent};
+use service::heartbeat::run_heartbeat;
+use service::history::create_shared_history;
+use service::metrics::create_shared_metrics;
+use service::registration::{run_announcer, run_registration_expiry};
+use service::reload::reload_config;
+use service::reprocess::{ReprocessOptions, reprocess_log};
 use service::state::{AppState, create_shared_state};
+use service::supervisor::{SourceState, SourceSupervisor};
+use service::termination::run_termination;
+use service::watchdog::run_watchdog;
+use storage::{MeasurementArchive, SharedArchive};
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_ansi(false))
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "spectrometer_service=info".into()),
-        )
-        .init();
+/// How often archived measurements are checked against the retention period
+const ARCHIVE_PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
 
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
+    // Detach before the log writer and the runtime start their threads
+    if cli.daemon {
+        daemon::daemonize(cli.pid_file.as_deref())?;
+    }
+
+    // Initialize tracing; the guard flushes the log file on exit
+    let _log_guard = logging::init(&cli.to_log_settings())?;
+
+    tokio::runtime::Runtime::new()?.block_on(run(cli))
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
     // Handle --list-ports
     if cli.list_ports {
         list_serial_ports();
@@ -46,104 +82,730 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         cfg.config.device_settings.clone()
     };
 
-    // Require a mode if not listing ports
-    let Some(data_source_config) = cli.to_data_source_config(&saved_settings) else {
-        eprintln!("Error: Please specify a mode (serial or playback)");
-        eprintln!("Use --help for usage information");
-        std::process::exit(1);
+    if let Some(Mode::ValidateLog(args)) = &cli.mode {
+        let settings = CycleSettings {
+            timeout: cli.cycle_timeout(),
+            series_count: saved_settings.series_count,
+            out_of_range: cli.out_of_range,
+            strict: true,
+        };
+        let report = lint_log(&args.file, cli.protocol, settings).await?;
+        println!("{}\n{report}", args.file.display());
+        std::process::exit(if report.is_valid() { 0 } else { 1 });
+    }
+
+    if let Some(Mode::Reprocess(args)) = &cli.mode {
+        let mut processing = device_config.read().await.config.processing.clone();
+        processing.linearity.load_file()?;
+        let options = ReprocessOptions {
+            protocol: cli.protocol,
+            cycle_settings: CycleSettings {
+                timeout: cli.cycle_timeout(),
+                series_count: saved_settings.series_count,
+                out_of_range: cli.out_of_range,
+                strict: cli.strict_protocol,
+            },
+            outlier: cli.to_outlier_method(processing.outlier.as_ref()),
+            smoother: cli.smoother,
+            timestamp_mode: cli.timestamp_mode,
+            cycle_interval_ms: args.cycle_interval,
+            wavelength: args.wavelength,
+            device: saved_settings,
+            processing,
+        };
+        let mut out: Box<dyn std::io::Write> = match &args.output {
+            Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+            None => Box::new(std::io::stdout().lock()),
+        };
+        let written = reprocess_log(args.file.clone(), &options, args.format, &mut out).await?;
+        tracing::info!("Reprocessed {written} measurements");
+        return Ok(());
+    }
+
+    if let Some(Mode::Redeliver(args)) = &cli.mode {
+        let settings = device_config.read().await.config.monitoring.clone();
+        let client = MonitoringClient::build(&settings.tls, &settings.http)?
+            .with_auth(settings.auth)
+            .with_compression(settings.compression);
+        let result = redeliver(&args.file, &client, args.api_url.as_deref()).await?;
+        println!(
+            "{}: {} delivered, {} remaining",
+            args.file.display(),
+            result.delivered,
+            result.remaining
+        );
+        std::process::exit(if result.remaining == 0 { 0 } else { 1 });
+    }
+
+    // Devices to run: the one given on the command line, else the ones
+    // listed in the config file
+    let devices = match cli.to_data_source_config(&saved_settings) {
+        Some(data_source_config) => vec![(None, device_config, data_source_config)],
+        None => {
+            let entries = device_config.read().await.config.devices.clone();
+            if entries.is_empty() {
+                eprintln!("Error: Please specify a mode (serial, playback or socket)");
+                eprintln!("or list [[devices]] in the config file");
+                eprintln!("Use --help for usage information");
+                std::process::exit(1);
+            }
+            validate_devices(&entries).map_err(error::SpectrometerError::Config)?;
+
+            let mut devices = Vec::new();
+            for entry in entries {
+                let config = create_shared_config(entry.config_path(&cli.calibration_config));
+                let saved = config.read().await.config.device_settings.clone();
+                let data_source_config = entry.source.to_data_source_config(&saved);
+                devices.push((Some(entry.name), config, data_source_config));
+            }
+            devices
+        }
+    };
+
+    let mut running = Vec::new();
+    for (name, config, data_source_config) in devices {
+        if let Some(name) = &name {
+            tracing::info!("Starting device {name}");
+        }
+        running.push(start_device(&cli, name, config, data_source_config).await?);
+    }
+
+    // Create and run HTTP server
+    let devices: Vec<(String, AppState)> = running
+        .iter()
+        .filter_map(|device| Some((device.name.clone()?, device.state.clone())))
+        .collect();
+    let router = if devices.is_empty() {
+        api::create_router(running[0].state.clone())
+    } else {
+        api::create_multi_device_router(devices)
     };
+    let mut listeners = server::Listeners::default();
+    for addr in cli.bind_addresses() {
+        listeners
+            .bind_tcp(addr)
+            .map_err(|e| format!("cannot listen on {addr}: {e}"))?;
+    }
+    if let Some(path) = &cli.unix_socket {
+        #[cfg(unix)]
+        listeners
+            .bind_unix(path)
+            .map_err(|e| format!("cannot listen on {}: {e}", path.display()))?;
+        #[cfg(not(unix))]
+        return Err(format!("--unix-socket {} is only supported on Unix", path.display()).into());
+    }
 
     tracing::info!(
-        "Starting spectrometer service on {}:{}",
-        cli.host,
-        cli.listen
+        "HTTP server listening on {}",
+        listeners.describe().join(", ")
     );
+    if !cli.bind_addresses().is_empty() {
+        for device in &running {
+            let path = device
+                .name
+                .as_ref()
+                .map(|name| format!("/devices/{name}"))
+                .unwrap_or_default();
+            tracing::info!(
+                "Open http://localhost:{}{path} for calibration UI",
+                cli.listen
+            );
+        }
+    }
+
+    // Announce each device to OptiMonitor now that call-backs can be served
+    let announce_handles: Vec<JoinHandle<()>> = match &cli.monitoring_url {
+        Some(monitoring_url) => {
+            let (host, port) = cli.advertised_address();
+            running
+                .iter()
+                .map(|device| {
+                    let path = device
+                        .name
+                        .as_ref()
+                        .map(|name| format!("/devices/{name}"))
+                        .unwrap_or_default();
+                    tokio::spawn(run_announcer(
+                        monitoring_url.clone(),
+                        Announcement::new(&host, port, &path),
+                        device.state.device.clone(),
+                        device.state.config.clone(),
+                    ))
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    // Advertise each device on the local network for auto-discovery
+    let mdns = if cli.mdns {
+        mdns_advertiser(&cli, &running)
+    } else {
+        None
+    };
+
+    // Run server with graceful shutdown
+    // Reload config on SIGHUP
+    #[cfg(unix)]
+    let reload_handle = {
+        let reload_states: Vec<AppState> =
+            running.iter().map(|device| device.state.clone()).collect();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{SignalKind, signal};
+
+            let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+                tracing::warn!("Failed to install SIGHUP handler");
+                return;
+            };
+            while hangup.recv().await.is_some() {
+                tracing::info!("Received SIGHUP, reloading config");
+                for state in &reload_states {
+                    if let Err(e) = reload_config(state).await {
+                        tracing::error!("Config reload failed: {e}");
+                    }
+                }
+            }
+        })
+    };
+
+    let systemd_watchdog_handle = daemon::systemd_watchdog_interval()
+        .map(|interval| tokio::spawn(daemon::run_systemd_watchdog(interval)));
+    daemon::notify_ready();
+
+    let server = server::serve(listeners, router, shutdown_signal());
+    let completions: Vec<_> = running
+        .iter_mut()
+        .filter_map(|device| Some((device.completion.take()?, device.processing.take()?)))
+        .collect();
+    let completed = if completions.is_empty() {
+        server.await?;
+        None
+    } else {
+        tokio::select! {
+            result = server => {
+                result?;
+                None
+            }
+            succeeded = wait_for_completion(completions) => Some(succeeded),
+        }
+    };
+
+    // Cleanup
+    tracing::info!("Shutting down...");
+    daemon::notify_stopping();
+    for handle in running
+        .iter()
+        .flat_map(|device| device.processing.iter().chain(&device.tasks))
+        .chain(&announce_handles)
+    {
+        handle.abort();
+    }
+    // Withdraws the advertised services
+    drop(mdns);
+    #[cfg(unix)]
+    reload_handle.abort();
+    if let Some(handle) = systemd_watchdog_handle {
+        handle.abort();
+    }
+    if let Some(pid_file) = &cli.pid_file {
+        daemon::remove_pid_file(pid_file);
+    }
+
+    if completed == Some(false) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Wait until every data source ended without restart and its processing
+/// loop wrote the remaining cycles. Returns whether every source finished
+/// rather than failed.
+async fn wait_for_completion(
+    completions: Vec<(oneshot::Receiver<SourceState>, JoinHandle<()>)>,
+) -> bool {
+    let mut succeeded = true;
+    for (completion, processing) in completions {
+        let state = completion.await.unwrap_or(SourceState::Failed);
+        let _ = processing.await;
+        succeeded &= state == SourceState::Finished;
+    }
+    if succeeded {
+        tracing::info!("All data sources completed");
+    } else {
+        tracing::error!("A data source failed");
+    }
+    succeeded
+}
+
+/// mDNS advertisement of every device on `--listen`, with only the
+/// addresses the server listens on: each `--host`, or the interfaces of its
+/// family when it is unspecified
+fn mdns_advertiser(cli: &Cli, running: &[RunningDevice]) -> Option<MdnsAdvertiser> {
+    let addresses: Vec<IpAddr> = cli.bind_addresses().iter().map(SocketAddr::ip).collect();
+    if addresses.is_empty() {
+        tracing::warn!("mDNS advertisement disabled: not listening on TCP");
+        return None;
+    }
+
+    let services = running
+        .iter()
+        .map(|device| match &device.name {
+            Some(name) => MdnsService::new(
+                &format!("{}-{name}", cli.mdns_name),
+                cli.listen,
+                &format!("/devices/{name}"),
+            ),
+            None => MdnsService::new(&cli.mdns_name, cli.listen, ""),
+        })
+        .collect::<Vec<_>>();
+    match MdnsAdvertiser::start(&cli.mdns_name, &addresses, &services) {
+        Ok(advertiser) => Some(advertiser),
+        Err(e) => {
+            tracing::error!("mDNS advertisement disabled: {e}");
+            None
+        }
+    }
+}
+
+/// Data source, processing loop and helper tasks of one spectrometer
+struct RunningDevice {
+    /// Set when several devices run in one process
+    name: Option<String>,
+    state: AppState,
+    processing: Option<JoinHandle<()>>,
+    /// How the data source ended, with `--exit-on-complete`
+    completion: Option<oneshot::Receiver<SourceState>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+/// Create the state of one device, start its data source and spawn its
+/// processing loop. With several devices, CSV sink and archive files get the
+/// device name appended.
+async fn start_device(
+    cli: &Cli,
+    name: Option<String>,
+    device_config: SharedConfig,
+    data_source_config: DataSourceConfig,
+) -> Result<RunningDevice, Box<dyn std::error::Error>> {
+    let saved_settings = device_config.read().await.config.device_settings.clone();
+    let per_device = |path: &Path| match &name {
+        Some(name) => device_file(path, name),
+        None => path.to_path_buf(),
+    };
 
     // Create shared state
     let device_state = create_shared_state();
+    device_state.write().await.health.stale_after =
+        chrono::Duration::seconds(cli.stale_after_secs as i64);
+    let history = create_shared_history(cli.history_size);
+    let metrics = create_shared_metrics();
 
-    // Create broadcast channel for WebSocket
-    let (broadcast_tx, _) = broadcast::channel(256);
+    // Create internal event bus (data loop + handlers -> subscribers)
+    let events = EventBus::default();
 
-    // Create device command channel (UI -> data source)
-    let (device_cmd_tx, mut device_cmd_rx) = mpsc::channel::<String>(16);
+    // Create device command channel (UI -> data source supervisor)
+    let (device_cmd_tx, device_cmd_rx) = mpsc::channel(16);
+
+    device_config
+        .write()
+        .await
+        .config
+        .processing
+        .linearity
+        .load_file()?;
+
+    // Create outlier excluder (swappable by config reload)
+    let (saved_processing, saved_monitoring, saved_alerts) = {
+        let cfg = device_config.read().await;
+        (
+            cfg.config.processing.clone(),
+            cfg.config.monitoring.clone(),
+            cfg.config.alerts.clone(),
+        )
+    };
+    let outlier_method = cli.to_outlier_method(saved_processing.outlier.as_ref());
+    let outlier_excluder = outlier_method.create()?;
+    tracing::info!("Using {} outlier exclusion", outlier_excluder.name());
+    let outlier_excluder = create_shared_excluder(outlier_excluder);
+
+    saved_processing
+        .validate()
+        .map_err(error::SpectrometerError::Config)?;
+    saved_settings
+        .series_mapping
+        .validate(
+            saved_settings.series_count,
+            saved_processing.references.stored(),
+        )
+        .map_err(error::SpectrometerError::Config)?;
+    saved_monitoring
+        .validate()
+        .map_err(error::SpectrometerError::Config)?;
+    MonitoringClient::build(&saved_monitoring.tls, &saved_monitoring.http)?;
+    saved_alerts
+        .validate()
+        .map_err(error::SpectrometerError::Config)?;
+    device_config
+        .read()
+        .await
+        .config
+        .control
+        .validate()
+        .map_err(error::SpectrometerError::Config)?;
+    device_config
+        .read()
+        .await
+        .config
+        .auto_gain
+        .validate()
+        .map_err(error::SpectrometerError::Config)?;
+    {
+        let cfg = device_config.read().await;
+        cfg.config
+            .termination
+            .validate(cfg.config.processing.thickness.enabled)
+            .map_err(error::SpectrometerError::Config)?;
+    }
+    if !saved_alerts.rules.is_empty() {
+        tracing::info!(
+            "Checking {} alert rules, notifying {} webhooks",
+            saved_alerts.rules.len(),
+            saved_alerts.webhooks.len()
+        );
+    }
+
+    tracing::info!(
+        "Using {:?} measurement validation",
+        saved_processing.validation.rule
+    );
+
+    let dark = saved_processing.dark_compensation;
+    if dark.enabled {
+        tracing::info!(
+            "Using rolling dark compensation (smoothing {}, blend {})",
+            dark.smoothing,
+            dark.blend
+        );
+    }
+    tracing::info!(
+        "Using {:?} smoothing",
+        saved_processing.smoother_config(cli.smoother).kind
+    );
+
+    if let Some(file) = &saved_processing.references.file {
+        let references = ReferenceTable::load(file)?;
+        tracing::info!(
+            "Loaded {} reference entries from {:?}{}",
+            references.entries.len(),
+            file,
+            if saved_processing.references.stored() {
+                ", calibrating against stored references"
+            } else {
+                ""
+            }
+        );
+        device_state.write().await.references = references;
+    }
+
+    if saved_monitoring.api_url.is_some() {
+        device_state.write().await.monitoring_api_url = saved_monitoring.api_url;
+    }
+
+    // Open the measurement archive
+    let archive = cli
+        .archive
+        .as_ref()
+        .map(|path| MeasurementArchive::open(&per_device(path), cli.archive_raw).map(Arc::new))
+        .transpose()?;
 
     // Composite app state
     let app_state = AppState {
         device: device_state.clone(),
         config: device_config.clone(),
-        broadcast_tx: broadcast_tx.clone(),
+        history: history.clone(),
+        events: events.clone(),
+        metrics: metrics.clone(),
+        outlier_excluder: outlier_excluder.clone(),
+        archive: archive.clone(),
         device_cmd_tx,
     };
 
-    // Create data source
-    let mut data_source = data_source_config.create_source();
+    // Prune archived measurements past the retention period
+    let prune_handle = match (&archive, cli.archive_retention_days) {
+        (Some(archive), Some(days)) => Some(tokio::spawn(prune_archive(
+            archive.clone(),
+            chrono::Duration::days(i64::from(days)),
+        ))),
+        _ => None,
+    };
 
-    // Create outlier excluder
-    let outlier_method = cli.to_outlier_method();
-    let outlier_excluder = outlier_method.create();
+    // Check alert rules and notify webhooks
+    let alerts_handle = tokio::spawn(run_alerts(
+        name.clone(),
+        device_state.clone(),
+        device_config.clone(),
+        events.clone(),
+    ));
 
-    tracing::info!("Using {} outlier exclusion", outlier_excluder.name());
+    // Clear the monitoring registration once its TTL passes
+    let registration_handle = tokio::spawn(run_registration_expiry(
+        device_state.clone(),
+        device_config.clone(),
+        events.clone(),
+    ));
 
-    // Set up log channel (serial lines -> WebSocket broadcast)
+    // Notice a processing loop that stopped receiving cycles
+    let watchdog_handle = tokio::spawn(run_watchdog(
+        device_state.clone(),
+        device_config.clone(),
+        events.clone(),
+        app_state.device_cmd_tx.clone(),
+    ));
+
+    // Recommend (or apply) a gain that keeps the span in range
+    let auto_gain_handle = tokio::spawn(run_auto_gain(
+        device_state.clone(),
+        device_config.clone(),
+        events.clone(),
+        app_state.device_cmd_tx.clone(),
+    ));
+
+    // Cut layers off locally at their configured targets
+    let termination_handle = tokio::spawn(run_termination(
+        device_state.clone(),
+        device_config.clone(),
+        events.clone(),
+    ));
+
+    // Tell the monitoring API the service is alive while registered
+    let heartbeat_handle = tokio::spawn(run_heartbeat(device_state.clone(), device_config.clone()));
+
+    // Create measurement outputs
+    let monitoring_endpoints = device_config
+        .read()
+        .await
+        .config
+        .monitoring
+        .endpoints
+        .clone();
+    let sinks = cli
+        .to_sink_configs()?
+        .into_iter()
+        .map(|sink| match sink {
+            DataSinkConfig::Csv { path } => DataSinkConfig::Csv {
+                path: per_device(&path),
+            },
+            DataSinkConfig::RunLog { dir, format } => DataSinkConfig::RunLog {
+                dir: per_device(&dir),
+                format,
+            },
+            DataSinkConfig::Monitoring(settings) => {
+                DataSinkConfig::Monitoring(MonitoringSinkSettings {
+                    dead_letter: settings.dead_letter.as_deref().map(per_device),
+                    endpoints: monitoring_endpoints.clone(),
+                    ..settings
+                })
+            }
+            DataSinkConfig::Pushgateway(settings) => {
+                DataSinkConfig::Pushgateway(PushgatewaySettings {
+                    instance: match &name {
+                        Some(name) => format!("{}-{name}", settings.instance),
+                        None => settings.instance,
+                    },
+                    ..settings
+                })
+            }
+            sink => sink,
+        })
+        .map(|sink| sink.create_sink(&device_state, &device_config, &events, &metrics))
+        .collect::<Result<Vec<_>, _>>()?;
+    let sink_names: Vec<&str> = sinks.iter().map(|sink| sink.name()).collect();
+    tracing::info!("Writing measurements to: {}", sink_names.join(", "));
+
+    // Set up log channel (serial lines -> service events)
     let (log_line_tx, mut log_line_rx) = mpsc::channel::<String>(256);
-    data_source.set_log_channel(log_line_tx);
 
-    let log_broadcast_tx = broadcast_tx.clone();
+    let log_events = events.clone();
     let log_handle = tokio::spawn(async move {
         while let Some(line) = log_line_rx.recv().await {
-            let _ = log_broadcast_tx.send(serde_json::json!({
-                "type": "log",
-                "line": line,
-            }));
+            log_events.publish(ServiceEvent::Log { line });
         }
     });
 
-    // Start data source and get cycle receiver
-    let cycle_rx = data_source.start().await?;
+    // Set up debug channel (firmware debug dumps -> device state)
+    let (debug_tx, mut debug_rx) = mpsc::channel(16);
 
-    // Spawn command forwarding task (forwards UI commands to data source)
-    let cmd_handle = tokio::spawn(async move {
-        while let Some(cmd) = device_cmd_rx.recv().await {
-            if let Err(e) = data_source.send_command(&cmd).await {
-                tracing::warn!("Device command '{cmd}' failed: {e}");
+    let debug_state = device_state.clone();
+    let debug_handle = tokio::spawn(async move {
+        while let Some(block) = debug_rx.recv().await {
+            debug_state.write().await.record_debug_block(block);
+        }
+    });
+
+    // Set up protocol channel (unknown/error lines -> diagnostics)
+    let (protocol_tx, mut protocol_rx) = mpsc::channel::<ProtocolIssue>(64);
+
+    let protocol_state = device_state.clone();
+    let protocol_events = events.clone();
+    let protocol_handle = tokio::spawn(async move {
+        while let Some(issue) = protocol_rx.recv().await {
+            match issue.kind {
+                ProtocolIssueKind::StaleCycle => {
+                    protocol_events.publish(ServiceEvent::PartialCycleDiscarded {
+                        at: issue.timestamp,
+                        source: issue.source.clone(),
+                        detail: issue.line.clone(),
+                    });
+                }
+                ProtocolIssueKind::OutOfRange => {
+                    tracing::warn!(
+                        "Value above ADC range from {}: {}",
+                        issue.source,
+                        issue.line
+                    );
+                }
+                ProtocolIssueKind::Malformed => {
+                    tracing::warn!("Malformed line from {}: {}", issue.source, issue.line);
+                }
+                _ => {}
             }
+            protocol_state.write().await.protocol.record(issue);
         }
-        // When cmd channel closes, stop the data source
-        let _ = data_source.stop().await;
     });
 
-    // Create and spawn data processing loop
-    let processing_loop =
-        DataProcessingLoop::new(device_state, device_config, broadcast_tx, outlier_excluder);
+    // Set up device event channel (firmware status lines -> event log)
+    let (event_tx, mut event_rx) = mpsc::channel::<DeviceEvent>(64);
 
-    let processing_handle = tokio::spawn(async move {
-        if let Err(e) = processing_loop.run(cycle_rx).await {
-            tracing::error!("Data processing loop error: {}", e);
+    let event_state = device_state.clone();
+    let device_events = events.clone();
+    let event_handle = tokio::spawn(async move {
+        while let Some(event) = event_rx.recv().await {
+            match event.kind {
+                DeviceEventKind::Error => {
+                    tracing::warn!("Firmware error from {}: {}", event.source, event.line)
+                }
+                DeviceEventKind::CycleMissing => {
+                    tracing::warn!("{} reports a missing measurement cycle", event.source)
+                }
+                DeviceEventKind::AdcReady => tracing::info!("ADC ready on {}", event.source),
+            }
+            event_state
+                .write()
+                .await
+                .device_events
+                .record(event.clone());
+            device_events.publish(ServiceEvent::DeviceEvent(event));
         }
     });
 
-    // Create and run HTTP server
-    let router = api::create_router(app_state);
-    let addr: SocketAddr = format!("{}:{}", cli.host, cli.listen).parse()?;
+    // Create data source, keeping its channels and settings for switching
+    // to another source at runtime
+    let setup = SourceSetup {
+        log_tx: log_line_tx,
+        debug_tx,
+        protocol_tx,
+        event_tx,
+        cycle_settings: CycleSettings {
+            timeout: cli.cycle_timeout(),
+            series_count: saved_settings.series_count,
+            out_of_range: cli.out_of_range,
+            strict: cli.strict_protocol,
+        },
+        protocol: cli.protocol,
+    };
+    let mut data_source = setup.create_source(&data_source_config);
 
-    tracing::info!("HTTP server listening on {}", addr);
-    tracing::info!("Open http://localhost:{} for calibration UI", cli.listen);
+    // Start data source and get cycle receiver
+    let source_rx = data_source.start().await?;
+    {
+        let mut device = device_state.write().await;
+        device.firmware_version = data_source.firmware_version();
+        device.confirmed_settings = data_source.confirmed_settings();
+        device.data_source = Some(data_source_config.active_source(data_source.name()));
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
+    // Supervise the data source: forward its cycles and UI commands, and
+    // restart it by the restart policy when its reader ends
+    let (cycle_tx, cycle_rx) = mpsc::channel(32);
+    let mut supervisor = SourceSupervisor::new(
+        data_source,
+        setup,
+        cli.restart_policy,
+        Duration::from_secs(cli.restart_max_backoff_secs),
+        device_state.clone(),
+        events.clone(),
+    );
+    let mut completion = None;
+    if cli.exit_on_complete {
+        let (completion_tx, completion_rx) = oneshot::channel();
+        supervisor = supervisor.with_completion(completion_tx);
+        completion = Some(completion_rx);
+    }
+    let supervisor_handle = tokio::spawn(supervisor.run(source_rx, cycle_tx, device_cmd_rx));
 
-    // Run server with graceful shutdown
-    axum::serve(listener, router)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // Create and spawn data processing loop
+    let processing_loop = DataProcessingLoop::new(
+        device_state,
+        device_config,
+        history,
+        events,
+        metrics,
+        outlier_excluder,
+    )
+    .with_sinks(sinks)
+    .with_timestamp_mode(cli.timestamp_mode)
+    .with_smoother(cli.smoother);
+    let processing_loop = match archive {
+        Some(archive) => processing_loop.with_archive(archive),
+        None => processing_loop,
+    };
 
-    // Cleanup
-    tracing::info!("Shutting down...");
-    processing_handle.abort();
-    cmd_handle.abort();
-    log_handle.abort();
+    let processing_handle = tokio::spawn(async move {
+        if let Err(e) = processing_loop.run(cycle_rx).await {
+            tracing::error!("Data processing loop error: {}", e);
+        }
+    });
 
-    Ok(())
+    Ok(RunningDevice {
+        name,
+        state: app_state,
+        processing: Some(processing_handle),
+        completion,
+        tasks: [
+            supervisor_handle,
+            log_handle,
+            debug_handle,
+            protocol_handle,
+            event_handle,
+            alerts_handle,
+            registration_handle,
+            heartbeat_handle,
+            watchdog_handle,
+            auto_gain_handle,
+            termination_handle,
+        ]
+        .into_iter()
+        .chain(prune_handle)
+        .collect(),
+    })
+}
+
+/// Delete archived measurements older than `retention`, now and then every
+/// `ARCHIVE_PRUNE_INTERVAL`
+async fn prune_archive(archive: SharedArchive, retention: chrono::Duration) {
+    let mut interval = tokio::time::interval(ARCHIVE_PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let cutoff = chrono::Utc::now() - retention;
+        match archive
+            .blocking(move |archive| archive.prune_before(cutoff))
+            .await
+        {
+            Ok(0) => {}
+            Ok(removed) => tracing::info!("Pruned {removed} archived measurements"),
+            Err(e) => tracing::error!("Failed to prune archive: {e}"),
+        }
+    }
 }
 
 /// List available serial ports
@@ -176,10 +838,29 @@ fn list_serial_ports() {
     }
 }
 
-/// Wait for shutdown signal (Ctrl+C)
+/// Wait for a shutdown signal: Ctrl+C, or SIGTERM as sent by systemd and
+/// `kill`
 async fn shutdown_signal() {
-    tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to install Ctrl+C handler");
-    tracing::info!("Received shutdown signal");
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        signal(SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => tracing::info!("Received Ctrl+C, shutting down"),
+        () = terminate => tracing::info!("Received SIGTERM, shutting down"),
+    }
 }
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+/// Device type reported to OptiMonitor
+pub const DEVICE_TYPE: &str = "spectrometer";
+
+/// Device name reported to OptiMonitor
+pub const DEVICE_NAME: &str = "ATmega328P Monochromatic Spectrometer";
+
+/// What OptiMonitor creates for this device: a spectrometer and a vacuum
+/// chamber
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceCapabilities {
+    pub has_spectrometer: bool,
+    pub has_vacuum_chamber: bool,
+    pub spectrometer_type: String,
+    pub is_monochromatic: bool,
+}
+
+impl Default for DeviceCapabilities {
+    fn default() -> Self {
+        Self {
+            has_spectrometer: true,
+            has_vacuum_chamber: true,
+            spectrometer_type: "two-component".to_string(),
+            is_monochromatic: true,
+        }
+    }
+}
+
+/// Sent to OptiMonitor's `POST /devices/connect` so it registers this
+/// service without being told about it first
+#[derive(Debug, Clone, Serialize)]
+pub struct Announcement {
+    /// Host OptiMonitor calls back on, e.g. the public side of a NAT
+    pub address: String,
+    pub port: u16,
+    /// Base URL of this device's API, including `/devices/{name}` when
+    /// several devices run in one process
+    pub callback_url: String,
+    #[serde(rename = "type")]
+    pub device_type: String,
+    pub name: String,
+    pub capabilities: DeviceCapabilities,
+}
+
+impl Announcement {
+    /// Announcement of the device served at `path` below `address:port`
+    pub fn new(address: &str, port: u16, path: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            port,
+            callback_url: format!("http://{address}:{port}{path}"),
+            device_type: DEVICE_TYPE.to_string(),
+            name: DEVICE_NAME.to_string(),
+            capabilities: DeviceCapabilities::default(),
+        }
+    }
+}
+
+/// IDs OptiMonitor assigned to the announced device
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnnounceResponse {
+    pub device_id: String,
+    pub spectrometer_id: Option<String>,
+    pub vacuum_chamber_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcement_serialization() {
+        let announcement = Announcement::new("lab-gw.example.com", 18100, "/devices/ch1");
+        let json = serde_json::to_value(&announcement).unwrap();
+
+        assert_eq!(json["address"], "lab-gw.example.com");
+        assert_eq!(json["port"], 18100);
+        assert_eq!(
+            json["callback_url"],
+            "http://lab-gw.example.com:18100/devices/ch1"
+        );
+        assert_eq!(json["type"], "spectrometer");
+        assert_eq!(json["capabilities"]["has_vacuum_chamber"], true);
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::VecDeque;
+
+use crate::protocol::ProcessedMeasurement;
+
+/// Default number of measurements held while the monitoring API is unreachable
+pub const DEFAULT_PUSH_BUFFER_CAPACITY: usize = 10_000;
+
+/// A measurement waiting to be delivered, with the wavelength it was taken at
+#[derive(Debug, Clone)]
+pub struct PendingPush {
+    pub measurement: ProcessedMeasurement,
+    pub wavelength: f64,
+}
+
+/// Bounded FIFO of measurements that failed to reach the monitoring API.
+/// When full, the oldest entry is dropped to make room.
+#[derive(Debug)]
+pub struct PushBuffer {
+    entries: VecDeque<PendingPush>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl PushBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+            dropped: 0,
+        }
+    }
+
+    /// Queue a push at the back, dropping the oldest entry when full.
+    /// Returns the dropped entry.
+    pub fn enqueue(&mut self, push: PendingPush) -> Option<PendingPush> {
+        let dropped = if self.entries.len() == self.capacity {
+            self.dropped += 1;
+            self.entries.pop_front()
+        } else {
+            None
+        };
+        self.entries.push_back(push);
+        dropped
+    }
+
+    /// Oldest pending push, if any
+    pub fn front(&self) -> Option<&PendingPush> {
+        self.entries.front()
+    }
+
+    /// Remove the oldest pending push after it was delivered
+    pub fn pop_front(&mut self) -> Option<PendingPush> {
+        self.entries.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of entries discarded because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl Default for PushBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_PUSH_BUFFER_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn pending(reading: f64) -> PendingPush {
+        PendingPush {
+            measurement: ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, reading),
+            wavelength: 550.0,
+        }
+    }
+
+    #[test]
+    fn test_fifo_order() {
+        let mut buffer = PushBuffer::new(4);
+        buffer.enqueue(pending(1.0));
+        buffer.enqueue(pending(2.0));
+
+        assert_eq!(buffer.front().unwrap().measurement.calibrated_reading, 1.0);
+        buffer.pop_front();
+        assert_eq!(buffer.front().unwrap().measurement.calibrated_reading, 2.0);
+    }
+
+    #[test]
+    fn test_drops_oldest_when_full() {
+        let mut buffer = PushBuffer::new(2);
+        buffer.enqueue(pending(1.0));
+        buffer.enqueue(pending(2.0));
+        let dropped = buffer.enqueue(pending(3.0)).unwrap();
+
+        assert_eq!(dropped.measurement.calibrated_reading, 1.0);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.dropped(), 1);
+        assert_eq!(buffer.front().unwrap().measurement.calibrated_reading, 2.0);
+    }
+
+    #[test]
+    fn test_empty_buffer() {
+        let mut buffer = PushBuffer::default();
+        assert!(buffer.is_empty());
+        assert!(buffer.pop_front().is_none());
+    }
+}
@@ -1,14 +1,243 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use chrono::{DateTime, Utc};
-use reqwest::Client;
-use serde::Serialize;
+use flate2::write::GzEncoder;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Identity, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
 
 use crate::error::SpectrometerError;
+use crate::monitoring::announce::{AnnounceResponse, Announcement};
+use crate::protocol::{FilteredSeries, MeasurementStatistics, ThicknessEstimate};
+
+/// Credentials and extra headers sent with every request to one monitoring
+/// API, e.g. for a reverse proxy in front of OptiMonitor
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MonitoringAuth {
+    /// Monitoring API URL the credentials are sent to, including requests
+    /// to paths below it
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bearer_token: Option<String>,
+    /// Basic auth user, sent with `password`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl MonitoringAuth {
+    pub fn validate(&self) -> Result<(), String> {
+        let url = &self.url;
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(format!("monitoring.auth: invalid URL {url:?}"));
+        }
+        if self.bearer_token.is_some() && self.username.is_some() {
+            return Err(format!(
+                "monitoring.auth: {url} has both a bearer token and a username"
+            ));
+        }
+        if self.password.is_some() && self.username.is_none() {
+            return Err(format!(
+                "monitoring.auth: {url} has a password but no username"
+            ));
+        }
+        for (name, value) in &self.headers {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("monitoring.auth: {url}: invalid header name {name:?}"))?;
+            HeaderValue::from_str(value)
+                .map_err(|_| format!("monitoring.auth: {url}: invalid value for {name}"))?;
+        }
+        Ok(())
+    }
+
+    /// Whether requests to `url` get these credentials
+    fn matches(&self, url: &str) -> bool {
+        let base = self.url.trim_end_matches('/');
+        url.strip_prefix(base)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+/// How an extra monitoring endpoint takes part in delivery
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointMode {
+    /// Receives a copy of every push, delivered and buffered on its own
+    Mirror,
+    /// Receives a push only when the registered API and the failover
+    /// endpoints before it failed to take it
+    Failover,
+}
+
+/// A monitoring API besides the registered one, e.g. a staging OptiMonitor
+/// mirroring production data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitoringEndpoint {
+    pub url: String,
+    pub mode: EndpointMode,
+    /// Spectrometer ID at this endpoint; the registered one when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spectrometer_id: Option<String>,
+}
+
+impl MonitoringEndpoint {
+    /// Check the URLs, which must differ from each other
+    pub fn validate_all(endpoints: &[MonitoringEndpoint]) -> Result<(), String> {
+        for (position, endpoint) in endpoints.iter().enumerate() {
+            let url = &endpoint.url;
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(format!("monitoring.endpoints: invalid URL {url:?}"));
+            }
+            if endpoint.spectrometer_id.as_deref() == Some("") {
+                return Err(format!(
+                    "monitoring.endpoints: {url} has an empty spectrometer_id"
+                ));
+            }
+            if endpoints[..position].iter().any(|other| other.url == *url) {
+                return Err(format!("monitoring.endpoints: {url} is listed twice"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// TLS options for monitoring servers using an internal PKI. Server
+/// certificates are always verified.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MonitoringTls {
+    /// PEM bundle of CA certificates trusted in addition to the system roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_bundle: Option<PathBuf>,
+    /// PEM client certificate presented to the server, with `client_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_cert: Option<PathBuf>,
+    /// PEM PKCS#8 private key of `client_cert`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_key: Option<PathBuf>,
+}
+
+/// Default whole-request timeout of the monitoring client
+pub const DEFAULT_HTTP_TIMEOUT_SECS: f64 = 5.0;
+
+/// Timeouts, connection pooling and proxy of the monitoring HTTP client,
+/// e.g. for pushes over a slow site VPN
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitoringHttp {
+    /// Time allowed for a whole request, in seconds
+    pub timeout_secs: f64,
+    /// Time allowed to connect, in seconds; only `timeout_secs` applies
+    /// when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<f64>,
+    /// Idle connections kept open per host (default: unlimited)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Seconds an idle connection is kept open (default: 90)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_idle_timeout_secs: Option<u64>,
+    /// Interval of TCP keep-alive probes in seconds (default: off)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Proxy for every monitoring request, e.g. `http://proxy:3128`, with
+    /// credentials in the URL if needed; by default the `HTTP_PROXY` and
+    /// `HTTPS_PROXY` environment variables apply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+impl Default for MonitoringHttp {
+    fn default() -> Self {
+        Self {
+            timeout_secs: DEFAULT_HTTP_TIMEOUT_SECS,
+            connect_timeout_secs: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_secs: None,
+            tcp_keepalive_secs: None,
+            proxy: None,
+        }
+    }
+}
+
+impl MonitoringHttp {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, secs) in [
+            ("timeout_secs", Some(self.timeout_secs)),
+            ("connect_timeout_secs", self.connect_timeout_secs),
+        ] {
+            if let Some(secs) = secs
+                && !(secs > 0.0 && Duration::try_from_secs_f64(secs).is_ok())
+            {
+                return Err(format!(
+                    "monitoring.http: {name} must be positive, got {secs}"
+                ));
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            reqwest::Proxy::all(proxy)
+                .map_err(|e| format!("monitoring.http: invalid proxy {proxy:?}: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// Content-Encoding of pushed spectral data, for raw series and batches
+/// over slow links
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitoringCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl MonitoringCompression {
+    /// `Content-Encoding` of compressed bodies
+    fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            MonitoringCompression::None => None,
+            MonitoringCompression::Gzip => Some("gzip"),
+            MonitoringCompression::Zstd => Some("zstd"),
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            MonitoringCompression::None => Ok(body.to_vec()),
+            MonitoringCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            MonitoringCompression::Zstd => zstd::encode_all(body, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, SpectrometerError> {
+    std::fs::read(path)
+        .map_err(|e| SpectrometerError::Config(format!("monitoring.tls: {}: {e}", path.display())))
+}
 
 /// HTTP client for communicating with OptiMonitor
 pub struct MonitoringClient {
     client: Client,
+    /// Credentials by monitoring URL
+    auth: Vec<MonitoringAuth>,
+    /// Encoding of spectral data bodies
+    compression: MonitoringCompression,
+    /// Monitoring URLs that answered 415 to a compressed body and get
+    /// uncompressed pushes from then on; shared with the clients made by
+    /// `with_auth`
+    uncompressed: Arc<Mutex<HashSet<String>>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -17,19 +246,161 @@ struct SpectralDataPayload {
     #[serde(skip_serializing_if = "Option::is_none")]
     wavelengths: Option<Vec<f64>>,
     timestamp: String,
+    /// Spread, sample counts and uncertainty per reading, so the monitoring
+    /// side can weight readings by quality
+    #[serde(skip_serializing_if = "Option::is_none")]
+    statistics: Option<Vec<MeasurementStatistics>>,
+    /// Filtered series values per reading, for offline re-analysis
+    #[serde(skip_serializing_if = "Option::is_none")]
+    series: Option<Vec<FilteredSeries>>,
+    /// On-device layer thickness estimate per reading, to cross-check the
+    /// monitoring side's own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thickness: Option<Vec<ThicknessEstimate>>,
+    /// Set when re-pushing stored measurements after an outage
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    backfill: bool,
+}
+
+/// Optional per-reading detail pushed with the calibrated readings
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReadingDetails<'a> {
+    /// Spread, sample counts and uncertainty
+    pub statistics: Option<&'a [MeasurementStatistics]>,
+    /// Filtered series values; multiplies the payload size
+    pub series: Option<&'a [FilteredSeries]>,
+    /// Layer thickness estimates
+    pub thickness: Option<&'a [ThicknessEstimate]>,
+}
+
+/// Periodic sign of life, so the monitoring side can tell an idle service
+/// from a dead one
+#[derive(Debug, Clone, Serialize)]
+pub struct Heartbeat {
+    pub timestamp: DateTime<Utc>,
+    /// Data source active and its data fresh, as in `/healthz`
+    pub healthy: bool,
+    /// Chamber state, e.g. `idle` or `depositing`
+    pub chamber_state: String,
+    /// Control wavelength in nm
+    pub control_wavelength: f64,
+    pub last_cycle_at: Option<DateTime<Utc>>,
+    /// Cycles that passed and failed validation since startup
+    pub valid_cycles: u64,
+    pub invalid_cycles: u64,
 }
 
 impl MonitoringClient {
     pub fn new() -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(5))
+        Self::build(&MonitoringTls::default(), &MonitoringHttp::default())
+            .expect("Failed to create HTTP client")
+    }
+
+    /// Client trusting the CAs and presenting the client certificate in
+    /// `tls`, with the timeouts, pooling and proxy in `http`. Fails when a
+    /// file is unreadable or not valid PEM, or the proxy URL is invalid.
+    pub fn build(tls: &MonitoringTls, http: &MonitoringHttp) -> Result<Self, SpectrometerError> {
+        let invalid = |what: &str, e: reqwest::Error| {
+            SpectrometerError::Config(format!("monitoring.tls: invalid {what}: {e}"))
+        };
+        http.validate().map_err(SpectrometerError::Config)?;
+        let mut builder = Client::builder().timeout(Duration::from_secs_f64(http.timeout_secs));
+        if let Some(secs) = http.connect_timeout_secs {
+            builder = builder.connect_timeout(Duration::from_secs_f64(secs));
+        }
+        if let Some(idle) = http.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(idle);
+        }
+        if let Some(secs) = http.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = http.tcp_keepalive_secs {
+            builder = builder.tcp_keepalive(Duration::from_secs(secs));
+        }
+        if let Some(proxy) = &http.proxy {
+            let proxy = reqwest::Proxy::all(proxy).map_err(|e| {
+                SpectrometerError::Config(format!("monitoring.http: invalid proxy: {e}"))
+            })?;
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = &tls.ca_bundle {
+            let certificates = Certificate::from_pem_bundle(&read_pem(path)?)
+                .map_err(|e| invalid("CA bundle", e))?;
+            if certificates.is_empty() {
+                return Err(SpectrometerError::Config(format!(
+                    "monitoring.tls: no certificates in {}",
+                    path.display()
+                )));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        match (&tls.client_cert, &tls.client_key) {
+            (Some(cert), Some(key)) => {
+                let identity = Identity::from_pkcs8_pem(&read_pem(cert)?, &read_pem(key)?)
+                    .map_err(|e| invalid("client certificate or key", e))?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => {
+                return Err(SpectrometerError::Config(
+                    "monitoring.tls: client_cert and client_key must be given together".to_string(),
+                ));
+            }
+        }
+
+        let client = builder
             .build()
-            .expect("Failed to create HTTP client");
+            .map_err(|e| invalid("TLS configuration", e))?;
+        Ok(Self {
+            client,
+            auth: Vec::new(),
+            compression: MonitoringCompression::None,
+            uncompressed: Arc::default(),
+        })
+    }
+
+    /// This client, sharing its connections, sending the credentials in
+    /// `auth` to matching URLs
+    pub fn with_auth(&self, auth: Vec<MonitoringAuth>) -> Self {
+        Self {
+            client: self.client.clone(),
+            auth,
+            compression: self.compression,
+            uncompressed: self.uncompressed.clone(),
+        }
+    }
 
-        Self { client }
+    /// Compress spectral data bodies with `compression`
+    pub fn with_compression(mut self, compression: MonitoringCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Add the credentials and headers configured for `url`
+    fn authorize(&self, request: RequestBuilder, url: &str) -> RequestBuilder {
+        let Some(auth) = self.auth.iter().find(|auth| auth.matches(url)) else {
+            return request;
+        };
+
+        let mut request = request;
+        if let Some(token) = &auth.bearer_token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(username) = &auth.username {
+            request = request.basic_auth(username, auth.password.as_ref());
+        }
+        for (name, value) in &auth.headers {
+            request = request.header(name, value);
+        }
+        request
     }
 
-    /// Post spectral data to the monitoring API
+    /// Post spectral data to the monitoring API, returning the status it
+    /// answered with
     ///
     /// For monochromatic spectrometer, calibrated_readings is a single-element array
     pub async fn post_spectral_data(
@@ -38,30 +409,163 @@ impl MonitoringClient {
         spectrometer_id: &str,
         calibrated_readings: &[f64],
         wavelengths: Option<&[f64]>,
+        details: ReadingDetails<'_>,
         timestamp: DateTime<Utc>,
-    ) -> Result<(), SpectrometerError> {
-        let url = format!("{}/spectrometers/{}/data", api_url, spectrometer_id);
+    ) -> Result<StatusCode, SpectrometerError> {
+        let payload = SpectralDataPayload {
+            calibrated_readings: calibrated_readings.to_vec(),
+            wavelengths: wavelengths.map(|w| w.to_vec()),
+            timestamp: timestamp.to_rfc3339(),
+            statistics: details.statistics.map(|s| s.to_vec()),
+            series: details.series.map(|s| s.to_vec()),
+            thickness: details.thickness.map(|t| t.to_vec()),
+            backfill: false,
+        };
 
+        self.post_payload(api_url, spectrometer_id, &payload).await
+    }
+
+    /// Re-post a stored measurement, tagged as backfill so the monitoring
+    /// side can tell it apart from live data
+    pub async fn post_backfill_data(
+        &self,
+        api_url: &str,
+        spectrometer_id: &str,
+        calibrated_readings: &[f64],
+        wavelengths: Option<&[f64]>,
+        details: ReadingDetails<'_>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<StatusCode, SpectrometerError> {
         let payload = SpectralDataPayload {
             calibrated_readings: calibrated_readings.to_vec(),
             wavelengths: wavelengths.map(|w| w.to_vec()),
             timestamp: timestamp.to_rfc3339(),
+            statistics: details.statistics.map(|s| s.to_vec()),
+            series: details.series.map(|s| s.to_vec()),
+            thickness: details.thickness.map(|t| t.to_vec()),
+            backfill: true,
         };
 
-        let response = self.client.post(&url).json(&payload).send().await?;
+        self.post_payload(api_url, spectrometer_id, &payload).await
+    }
+
+    /// Post a heartbeat for the spectrometer to the monitoring API
+    pub async fn post_heartbeat(
+        &self,
+        api_url: &str,
+        spectrometer_id: &str,
+        heartbeat: &Heartbeat,
+    ) -> Result<(), SpectrometerError> {
+        let url = format!("{}/spectrometers/{}/heartbeat", api_url, spectrometer_id);
+
+        let response = self
+            .authorize(self.client.post(&url), &url)
+            .json(heartbeat)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(SpectrometerError::DataSource(format!(
+                "Monitoring API returned {} for heartbeat",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that the monitoring API answers HTTP at all. Any response,
+    /// including an error status, counts as reachable.
+    pub async fn check_reachable(&self, api_url: &str) -> Result<(), SpectrometerError> {
+        self.authorize(self.client.get(api_url), api_url)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Announce this service to OptiMonitor at `monitoring_url` and return
+    /// the IDs it assigned
+    pub async fn announce(
+        &self,
+        monitoring_url: &str,
+        announcement: &Announcement,
+    ) -> Result<AnnounceResponse, SpectrometerError> {
+        let url = format!("{}/devices/connect", monitoring_url.trim_end_matches('/'));
+
+        let response = self
+            .authorize(self.client.post(&url), &url)
+            .json(announcement)
+            .send()
+            .await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
-            tracing::error!("Failed to post spectral data: {} - {}", status, body);
             return Err(SpectrometerError::DataSource(format!(
-                "Monitoring API returned {}",
-                status
+                "Monitoring API rejected announcement: {status} - {body}"
             )));
         }
 
+        Ok(response.json().await?)
+    }
+
+    async fn post_payload(
+        &self,
+        api_url: &str,
+        spectrometer_id: &str,
+        payload: &SpectralDataPayload,
+    ) -> Result<StatusCode, SpectrometerError> {
+        let url = format!("{}/spectrometers/{}/data", api_url, spectrometer_id);
+        let body = serde_json::to_vec(payload)
+            .map_err(|e| SpectrometerError::DataSource(format!("cannot serialize: {e}")))?;
+
+        let compression = if self.uncompressed.lock().unwrap().contains(api_url) {
+            MonitoringCompression::None
+        } else {
+            self.compression
+        };
+        let mut response = self.send_body(&url, &body, compression).await?;
+        // A server that cannot decode the body gets it again uncompressed,
+        // and so does every later push to it
+        if response.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE
+            && compression != MonitoringCompression::None
+        {
+            tracing::warn!(
+                "{api_url} does not accept {compression:?} bodies, sending uncompressed from now on"
+            );
+            self.uncompressed
+                .lock()
+                .unwrap()
+                .insert(api_url.to_string());
+            response = self
+                .send_body(&url, &body, MonitoringCompression::None)
+                .await?;
+        }
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            tracing::error!("Failed to post spectral data: {} - {}", status, body);
+            return Err(SpectrometerError::MonitoringStatus(status));
+        }
+
         tracing::debug!("Posted spectral data to {}", url);
-        Ok(())
+        Ok(response.status())
+    }
+
+    /// POST the JSON `body` to `url`, encoded with `compression`
+    async fn send_body(
+        &self,
+        url: &str,
+        body: &[u8],
+        compression: MonitoringCompression,
+    ) -> Result<reqwest::Response, SpectrometerError> {
+        let mut request = self
+            .authorize(self.client.post(url), url)
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(encoding) = compression.content_encoding() {
+            request = request.header(CONTENT_ENCODING, encoding);
+        }
+        Ok(request.body(compression.compress(body)?).send().await?)
     }
 }
 
@@ -86,11 +590,16 @@ mod tests {
             calibrated_readings: vec![45.5],
             wavelengths: Some(vec![550.0]),
             timestamp: "2025-01-15T10:30:00Z".to_string(),
+            statistics: None,
+            series: None,
+            thickness: None,
+            backfill: false,
         };
 
         let json = serde_json::to_string(&payload).unwrap();
         assert!(json.contains("45.5"));
         assert!(json.contains("550.0"));
+        assert!(!json.contains("backfill")); // Live data is not tagged
     }
 
     #[test]
@@ -99,10 +608,349 @@ mod tests {
             calibrated_readings: vec![45.5],
             wavelengths: None,
             timestamp: "2025-01-15T10:30:00Z".to_string(),
+            statistics: None,
+            series: None,
+            thickness: None,
+            backfill: false,
         };
 
         let json = serde_json::to_string(&payload).unwrap();
         assert!(json.contains("45.5"));
         assert!(!json.contains("wavelengths")); // Should be skipped
+        assert!(!json.contains("statistics"));
+    }
+
+    #[test]
+    fn test_payload_with_statistics() {
+        let series = crate::protocol::SeriesStatistics {
+            std_dev: 2.5,
+            count: 8,
+        };
+        let payload = SpectralDataPayload {
+            calibrated_readings: vec![45.5],
+            wavelengths: None,
+            timestamp: "2025-01-15T10:30:00Z".to_string(),
+            statistics: Some(vec![MeasurementStatistics {
+                dark: series,
+                full: series,
+                sample: series,
+                uncertainty: Some(0.12),
+                snr: Some(850.0),
+            }]),
+            series: None,
+            thickness: None,
+            backfill: false,
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["statistics"][0]["uncertainty"], 0.12);
+        assert_eq!(json["statistics"][0]["sample"]["count"], 8);
+    }
+
+    #[test]
+    fn test_payload_with_series() {
+        let payload = SpectralDataPayload {
+            calibrated_readings: vec![45.5],
+            wavelengths: None,
+            timestamp: "2025-01-15T10:30:00Z".to_string(),
+            statistics: None,
+            series: Some(vec![FilteredSeries {
+                dark: vec![100.0, 101.0],
+                full: vec![1000.0, 1002.0],
+                sample: vec![550.0, 551.0],
+            }]),
+            thickness: None,
+            backfill: false,
+        };
+
+        let json: serde_json::Value = serde_json::to_value(&payload).unwrap();
+        assert_eq!(
+            json["series"][0]["sample"],
+            serde_json::json!([550.0, 551.0])
+        );
+    }
+
+    #[test]
+    fn test_backfill_payload_tagged() {
+        let payload = SpectralDataPayload {
+            calibrated_readings: vec![45.5],
+            wavelengths: None,
+            timestamp: "2025-01-15T10:30:00Z".to_string(),
+            statistics: None,
+            series: None,
+            thickness: None,
+            backfill: true,
+        };
+
+        let json = serde_json::to_string(&payload).unwrap();
+        assert!(json.contains("\"backfill\":true"));
+    }
+
+    fn auth(url: &str) -> MonitoringAuth {
+        MonitoringAuth {
+            url: url.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_auth_validation_and_matching() {
+        let mut proxy = auth("https://proxy.example.com/optimonitor/");
+        proxy.bearer_token = Some("secret".to_string());
+        proxy
+            .headers
+            .insert("X-Tenant".to_string(), "lab1".to_string());
+        proxy.validate().unwrap();
+
+        assert!(proxy.matches("https://proxy.example.com/optimonitor"));
+        assert!(proxy.matches("https://proxy.example.com/optimonitor/spectrometers/1/data"));
+        assert!(!proxy.matches("https://proxy.example.com/optimonitor2"));
+        assert!(!proxy.matches("http://proxy.example.com/optimonitor"));
+
+        let mut both = proxy.clone();
+        both.username = Some("user".to_string());
+        assert!(both.validate().is_err());
+
+        let mut header = proxy;
+        header
+            .headers
+            .insert("bad header".to_string(), "x".to_string());
+        assert!(header.validate().is_err());
+
+        let mut password_only = auth("http://optimonitor:8200");
+        password_only.password = Some("pw".to_string());
+        assert!(password_only.validate().is_err());
+    }
+
+    #[test]
+    fn test_endpoint_validation() {
+        let endpoint = |url: &str, mode| MonitoringEndpoint {
+            url: url.to_string(),
+            mode,
+            spectrometer_id: None,
+        };
+        let staging = endpoint("http://staging:8200", EndpointMode::Mirror);
+        let standby = endpoint("https://standby:8200", EndpointMode::Failover);
+        assert!(MonitoringEndpoint::validate_all(&[staging.clone(), standby.clone()]).is_ok());
+
+        let err = MonitoringEndpoint::validate_all(&[staging.clone(), staging.clone()]);
+        assert!(err.unwrap_err().contains("listed twice"));
+        assert!(
+            MonitoringEndpoint::validate_all(&[endpoint("staging:8200", EndpointMode::Mirror)])
+                .is_err()
+        );
+        let unnamed = MonitoringEndpoint {
+            spectrometer_id: Some(String::new()),
+            ..standby
+        };
+        assert!(MonitoringEndpoint::validate_all(&[unnamed]).is_err());
+    }
+
+    #[test]
+    fn test_client_with_tls() {
+        let fixture = |name: &str| Some(PathBuf::from("fixtures/tls").join(name));
+        let tls = MonitoringTls {
+            ca_bundle: fixture("ca.pem"),
+            client_cert: fixture("client.pem"),
+            client_key: fixture("client.key"),
+        };
+        MonitoringClient::build(&tls, &MonitoringHttp::default()).unwrap();
+
+        let without_key = MonitoringTls {
+            client_key: None,
+            ..tls.clone()
+        };
+        assert!(MonitoringClient::build(&without_key, &MonitoringHttp::default()).is_err());
+
+        let missing = MonitoringTls {
+            ca_bundle: fixture("missing.pem"),
+            ..Default::default()
+        };
+        let Err(error) = MonitoringClient::build(&missing, &MonitoringHttp::default()) else {
+            panic!("missing CA bundle accepted");
+        };
+        assert!(error.to_string().contains("missing.pem"));
+
+        // A key is not a certificate
+        let not_a_ca = MonitoringTls {
+            ca_bundle: fixture("client.key"),
+            ..Default::default()
+        };
+        assert!(MonitoringClient::build(&not_a_ca, &MonitoringHttp::default()).is_err());
+    }
+
+    #[test]
+    fn test_http_settings() {
+        let http = MonitoringHttp {
+            timeout_secs: 30.0,
+            connect_timeout_secs: Some(10.0),
+            pool_max_idle_per_host: Some(2),
+            pool_idle_timeout_secs: Some(30),
+            tcp_keepalive_secs: Some(15),
+            proxy: Some("http://user:pw@proxy.site:3128".to_string()),
+        };
+        MonitoringClient::build(&MonitoringTls::default(), &http).unwrap();
+
+        for invalid in [
+            MonitoringHttp {
+                timeout_secs: 0.0,
+                ..MonitoringHttp::default()
+            },
+            MonitoringHttp {
+                connect_timeout_secs: Some(-1.0),
+                ..MonitoringHttp::default()
+            },
+            MonitoringHttp {
+                proxy: Some("not a url".to_string()),
+                ..MonitoringHttp::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+            assert!(MonitoringClient::build(&MonitoringTls::default(), &invalid).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_carry_credentials() {
+        use std::sync::{Arc, Mutex};
+
+        use axum::Router;
+        use axum::http::HeaderMap;
+        use axum::routing::post;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = seen.clone();
+        let app = Router::new().route(
+            "/spectrometers/{id}/data",
+            post(move |headers: HeaderMap| async move {
+                let header = |name: &str| {
+                    headers
+                        .get(name)
+                        .map(|value| value.to_str().unwrap().to_string())
+                };
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((header("authorization"), header("x-tenant")));
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut token = auth(&api_url);
+        token.bearer_token = Some("secret".to_string());
+        token
+            .headers
+            .insert("X-Tenant".to_string(), "lab1".to_string());
+        let mut basic = auth(&api_url);
+        basic.username = Some("user".to_string());
+        basic.password = Some("pw".to_string());
+
+        let base = MonitoringClient::new();
+        for client in [
+            base.with_auth(Vec::new()),
+            base.with_auth(vec![token]),
+            base.with_auth(vec![basic]),
+        ] {
+            client
+                .post_spectral_data(
+                    &api_url,
+                    "1",
+                    &[45.5],
+                    None,
+                    ReadingDetails::default(),
+                    Utc::now(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (None, None),
+                (Some("Bearer secret".to_string()), Some("lab1".to_string())),
+                (Some("Basic dXNlcjpwdw==".to_string()), None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_payloads_decode() {
+        use std::io::Read;
+        use std::sync::{Arc, Mutex};
+
+        use axum::Router;
+        use axum::body::Bytes;
+        use axum::http::{HeaderMap, StatusCode as AxumStatus};
+        use axum::routing::post;
+
+        type Received = Arc<Mutex<Vec<(Option<String>, Vec<u8>)>>>;
+        let received: Received = Arc::new(Mutex::new(Vec::new()));
+        let recorded = received.clone();
+        let app = Router::new().route(
+            "/spectrometers/{id}/data",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                let encoding = headers
+                    .get("content-encoding")
+                    .map(|value| value.to_str().unwrap().to_string());
+                // Like a server without zstd support
+                let status = if encoding.as_deref() == Some("zstd") {
+                    AxumStatus::UNSUPPORTED_MEDIA_TYPE
+                } else {
+                    AxumStatus::OK
+                };
+                recorded.lock().unwrap().push((encoding, body.to_vec()));
+                status
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let gzip_client = MonitoringClient::new().with_compression(MonitoringCompression::Gzip);
+        let zstd_client = MonitoringClient::new().with_compression(MonitoringCompression::Zstd);
+        // The second zstd push goes out uncompressed straight away, also
+        // from a client derived with `with_auth` like the data sink does
+        for client in [
+            gzip_client,
+            zstd_client.with_auth(Vec::new()),
+            zstd_client.with_auth(Vec::new()),
+        ] {
+            client
+                .post_spectral_data(
+                    &api_url,
+                    "1",
+                    &[45.5],
+                    None,
+                    ReadingDetails::default(),
+                    Utc::now(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let received = received.lock().unwrap();
+        let encodings: Vec<Option<&str>> = received
+            .iter()
+            .map(|(encoding, _)| encoding.as_deref())
+            .collect();
+        assert_eq!(encodings, [Some("gzip"), Some("zstd"), None, None]);
+
+        let mut gzip = String::new();
+        flate2::read::GzDecoder::new(received[0].1.as_slice())
+            .read_to_string(&mut gzip)
+            .unwrap();
+        let zstd = zstd::decode_all(received[1].1.as_slice()).unwrap();
+        for json in [
+            gzip.into_bytes(),
+            zstd,
+            received[2].1.clone(),
+            received[3].1.clone(),
+        ] {
+            let payload: serde_json::Value = serde_json::from_slice(&json).unwrap();
+            assert_eq!(payload["calibrated_readings"][0], 45.5);
+        }
     }
 }
@@ -0,0 +1,239 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpectrometerError;
+use crate::monitoring::{MonitoringClient, PendingPush, ReadingDetails};
+use crate::protocol::ProcessedMeasurement;
+
+/// A measurement the monitoring sink gave up on, with where it was meant to
+/// go and why it was not delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub failed_at: DateTime<Utc>,
+    pub reason: String,
+    pub api_url: String,
+    pub spectrometer_id: String,
+    /// Control wavelength (nm) the measurement was taken at
+    pub wavelength: f64,
+    pub measurement: ProcessedMeasurement,
+}
+
+impl DeadLetter {
+    pub fn new(push: PendingPush, api_url: &str, spectrometer_id: &str, reason: String) -> Self {
+        Self {
+            failed_at: Utc::now(),
+            reason,
+            api_url: api_url.to_string(),
+            spectrometer_id: spectrometer_id.to_string(),
+            wavelength: push.wavelength,
+            measurement: push.measurement,
+        }
+    }
+}
+
+/// JSONL file undeliverable pushes are appended to, one `DeadLetter` per
+/// line, for `redeliver` once the monitoring API is back
+pub struct DeadLetterFile {
+    path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl DeadLetterFile {
+    pub fn open(path: &Path) -> Result<Self, SpectrometerError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Append `letter` and flush, so it survives a crash right after
+    pub fn append(&self, letter: &DeadLetter) -> Result<(), SpectrometerError> {
+        let line = serde_json::to_string(letter)
+            .map_err(|e| SpectrometerError::DataSource(format!("cannot serialize: {e}")))?;
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{line}")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Read every dead letter in `path`
+pub fn read_dead_letters(path: &Path) -> Result<Vec<DeadLetter>, SpectrometerError> {
+    let mut letters = Vec::new();
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let letter = serde_json::from_str(&line).map_err(|e| {
+            SpectrometerError::Validation(format!("{}:{}: {e}", path.display(), index + 1))
+        })?;
+        letters.push(letter);
+    }
+    Ok(letters)
+}
+
+/// Outcome of a `redeliver` pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redelivery {
+    pub delivered: usize,
+    /// Left in the file: the first that failed and everything after it
+    pub remaining: usize,
+}
+
+/// Post the dead letters in `path` oldest first, as backfill, to their
+/// monitoring API or to `api_url` when given. Stops at the first failure so
+/// the order is kept, and rewrites the file with the letters not delivered.
+pub async fn redeliver(
+    path: &Path,
+    client: &MonitoringClient,
+    api_url: Option<&str>,
+) -> Result<Redelivery, SpectrometerError> {
+    let letters = read_dead_letters(path)?;
+    let mut delivered = 0;
+    for letter in &letters {
+        let measurement = &letter.measurement;
+        let result = client
+            .post_backfill_data(
+                api_url.unwrap_or(&letter.api_url),
+                &letter.spectrometer_id,
+                &[measurement.calibrated_reading],
+                Some(&[letter.wavelength]),
+                ReadingDetails {
+                    statistics: measurement.statistics.as_ref().map(std::slice::from_ref),
+                    series: measurement.series.as_deref().map(std::slice::from_ref),
+                    thickness: measurement.thickness.as_ref().map(std::slice::from_ref),
+                },
+                measurement.timestamp,
+            )
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Redelivery of {} failed: {e}", measurement.timestamp);
+            break;
+        }
+        delivered += 1;
+    }
+
+    // Replace the file only once the rest is written out
+    let remaining = &letters[delivered..];
+    let partial = path.with_extension("redeliver.tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&partial)?);
+        for letter in remaining {
+            let line = serde_json::to_string(letter)
+                .map_err(|e| SpectrometerError::DataSource(format!("cannot serialize: {e}")))?;
+            writeln!(writer, "{line}")?;
+        }
+        writer.flush()?;
+    }
+    std::fs::rename(&partial, path)?;
+
+    Ok(Redelivery {
+        delivered,
+        remaining: remaining.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::Json;
+    use axum::extract::State;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+
+    use super::*;
+
+    fn letter(api_url: &str, reading: f64) -> DeadLetter {
+        let push = PendingPush {
+            measurement: ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, reading),
+            wavelength: 632.8,
+        };
+        DeadLetter::new(push, api_url, "spec-1", "push buffer full".to_string())
+    }
+
+    /// Monitoring API accepting `accept` pushes, then failing
+    async fn spawn_monitoring_api(accept: usize) -> (String, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let app = axum::Router::new()
+            .route(
+                "/spectrometers/{id}/data",
+                post(
+                    move |State(received): State<Arc<Mutex<Vec<serde_json::Value>>>>,
+                          Json(body): Json<serde_json::Value>| async move {
+                        let mut received = received.lock().unwrap();
+                        if received.len() == accept {
+                            return StatusCode::SERVICE_UNAVAILABLE;
+                        }
+                        received.push(body);
+                        StatusCode::OK
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{addr}"), received)
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead.jsonl");
+        let file = DeadLetterFile::open(&path).unwrap();
+        file.append(&letter("http://a", 41.0)).unwrap();
+        file.append(&letter("http://a", 42.0)).unwrap();
+
+        let letters = read_dead_letters(&path).unwrap();
+        assert_eq!(letters.len(), 2);
+        assert_eq!(letters[1].measurement.calibrated_reading, 42.0);
+        assert_eq!(letters[0].reason, "push buffer full");
+        assert_eq!(letters[0].wavelength, 632.8);
+
+        std::fs::write(&path, "{\"not\": \"a letter\"}\n").unwrap();
+        assert!(read_dead_letters(&path).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redeliver_keeps_undelivered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dead.jsonl");
+        let (api_url, received) = spawn_monitoring_api(2).await;
+        let file = DeadLetterFile::open(&path).unwrap();
+        for reading in [1.0, 2.0, 3.0, 4.0] {
+            // Recorded against an address the API has since moved from
+            file.append(&letter("http://127.0.0.1:9", reading)).unwrap();
+        }
+
+        let client = MonitoringClient::new();
+        let result = redeliver(&path, &client, Some(&api_url)).await.unwrap();
+        assert_eq!(
+            result,
+            Redelivery {
+                delivered: 2,
+                remaining: 2
+            }
+        );
+        let received = received.lock().unwrap().clone();
+        assert_eq!(received[0]["calibrated_readings"][0], 1.0);
+        assert_eq!(received[1]["backfill"], true);
+        let left: Vec<f64> = read_dead_letters(&path)
+            .unwrap()
+            .iter()
+            .map(|letter| letter.measurement.calibrated_reading)
+            .collect();
+        assert_eq!(left, [3.0, 4.0]);
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use mdns_sd::{IfKind, ServiceDaemon, ServiceInfo};
+
+use crate::monitoring::announce::{DEVICE_NAME, DEVICE_TYPE};
+
+/// DNS-SD service type OptiMonitor browses for chamber devices
+pub const SERVICE_TYPE: &str = "_optimonitor-device._tcp.local.";
+
+/// How long shutdown waits for the goodbye of each service
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// One device advertised as an instance of `SERVICE_TYPE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdnsService {
+    /// Instance name shown when browsing; renamed by the daemon on conflict
+    pub instance: String,
+    pub port: u16,
+    /// Properties of the TXT record
+    pub txt: HashMap<String, String>,
+}
+
+impl MdnsService {
+    /// Service of the device served at `path` below port `port`
+    pub fn new(instance: &str, port: u16, path: &str) -> Self {
+        let txt = [
+            ("type", DEVICE_TYPE.to_string()),
+            ("name", DEVICE_NAME.to_string()),
+            ("port", port.to_string()),
+            ("path", if path.is_empty() { "/" } else { path }.to_string()),
+            ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+        Self {
+            instance: instance.to_string(),
+            port,
+            txt,
+        }
+    }
+
+    /// Service record for `<host>.local`, with the addresses of the
+    /// interfaces the daemon advertises on
+    fn info(&self, host: &str) -> mdns_sd::Result<ServiceInfo> {
+        Ok(ServiceInfo::new(
+            SERVICE_TYPE,
+            &self.instance,
+            &format!("{host}.local."),
+            (),
+            self.port,
+            self.txt.clone(),
+        )?
+        .enable_addr_auto())
+    }
+}
+
+/// Interfaces to advertise on so the A and AAAA records only carry
+/// addresses the server listens on: the address itself, or every interface
+/// of its family for an unspecified address
+pub fn interfaces(addresses: &[IpAddr]) -> Vec<IfKind> {
+    addresses
+        .iter()
+        .map(|address| match address {
+            IpAddr::V4(ip) if ip.is_unspecified() => IfKind::IPv4,
+            IpAddr::V6(ip) if ip.is_unspecified() => IfKind::IPv6,
+            ip => IfKind::Addr(*ip),
+        })
+        .collect()
+}
+
+/// Advertises services over mDNS until dropped. Probing, renaming on
+/// conflicts, IPv4 and IPv6 answers and the goodbye on shutdown are left to
+/// the `mdns-sd` daemon thread.
+pub struct MdnsAdvertiser {
+    daemon: ServiceDaemon,
+    fullnames: Vec<String>,
+}
+
+impl MdnsAdvertiser {
+    /// Advertise `services` as `<host>.local` on the interfaces of
+    /// `addresses`. `host` is reduced to letters, digits and `-`.
+    pub fn start(
+        host: &str,
+        addresses: &[IpAddr],
+        services: &[MdnsService],
+    ) -> mdns_sd::Result<Self> {
+        let host: String = host
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let daemon = ServiceDaemon::new()?;
+        daemon.disable_interface(IfKind::All)?;
+        daemon.enable_interface(interfaces(addresses))?;
+
+        let mut fullnames = Vec::new();
+        for service in services {
+            let info = service.info(&host)?;
+            fullnames.push(info.get_fullname().to_string());
+            daemon.register(info)?;
+        }
+        Ok(Self { daemon, fullnames })
+    }
+}
+
+impl Drop for MdnsAdvertiser {
+    fn drop(&mut self) {
+        for fullname in &self.fullnames {
+            match self.daemon.unregister(fullname) {
+                Ok(status) => {
+                    let _ = status.recv_timeout(GOODBYE_TIMEOUT);
+                }
+                Err(e) => tracing::warn!("Failed to withdraw mDNS service {fullname}: {e}"),
+            }
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_carries_device_details() {
+        let service = MdnsService::new("coater-2-ch1", 8080, "/devices/ch1");
+        let info = service.info("coater 2").unwrap();
+
+        assert_eq!(
+            info.get_fullname(),
+            "coater-2-ch1._optimonitor-device._tcp.local."
+        );
+        assert_eq!(info.get_hostname(), "coater 2.local.");
+        assert_eq!(info.get_port(), 8080);
+        assert!(info.is_addr_auto());
+        assert!(info.get_addresses().is_empty());
+        for (key, value) in [
+            ("type", DEVICE_TYPE),
+            ("name", DEVICE_NAME),
+            ("port", "8080"),
+            ("path", "/devices/ch1"),
+            ("version", env!("CARGO_PKG_VERSION")),
+        ] {
+            assert_eq!(info.get_property_val_str(key), Some(value));
+        }
+    }
+
+    #[test]
+    fn test_root_path_for_single_device() {
+        let service = MdnsService::new("spectrometer", 8080, "");
+        assert_eq!(service.txt["path"], "/");
+    }
+
+    #[test]
+    fn test_interfaces_follow_bind_addresses() {
+        let kinds = interfaces(&["::1".parse().unwrap()]);
+        assert!(matches!(kinds[..], [IfKind::Addr(IpAddr::V6(ip))] if ip.is_loopback()));
+
+        let kinds = interfaces(&["::".parse().unwrap(), "192.168.1.20".parse().unwrap()]);
+        assert!(matches!(
+            kinds[..],
+            [IfKind::IPv6, IfKind::Addr(IpAddr::V4(ip))] if ip.octets() == [192, 168, 1, 20]
+        ));
+
+        let kinds = interfaces(&["0.0.0.0".parse().unwrap()]);
+        assert!(matches!(kinds[..], [IfKind::IPv4]));
+    }
+}
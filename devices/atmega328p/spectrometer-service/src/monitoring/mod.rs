@@ -1,3 +1,15 @@
+pub mod announce;
+pub mod buffer;
 pub mod client;
+pub mod dead_letter;
+pub mod mdns;
+pub mod queue;
 
-pub use client::MonitoringClient;
+pub use announce::{Announcement, DeviceCapabilities};
+pub use buffer::{PendingPush, PushBuffer};
+pub use client::{
+    EndpointMode, Heartbeat, MonitoringAuth, MonitoringClient, MonitoringCompression,
+    MonitoringEndpoint, MonitoringHttp, MonitoringTls, ReadingDetails,
+};
+pub use dead_letter::{DeadLetter, DeadLetterFile};
+pub use queue::{OverflowPolicy, PushQueue};
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::sync::Notify;
+
+use crate::monitoring::PendingPush;
+
+/// What a full push queue does with another measurement
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued measurement (default)
+    #[default]
+    DropOldest,
+    /// Discard the new measurement
+    DropNewest,
+    /// Wait for the sender, stalling the processing loop
+    Block,
+}
+
+/// Bounded queue between the processing loop and the monitoring sender,
+/// so a slow monitoring API does not delay the next cycle
+#[derive(Debug)]
+pub struct PushQueue {
+    entries: Mutex<VecDeque<PendingPush>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: AtomicU64,
+    /// Signalled when a push is queued
+    pushed: Notify,
+    /// Signalled when a push is taken
+    taken: Notify,
+}
+
+impl PushQueue {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            policy,
+            dropped: AtomicU64::new(0),
+            pushed: Notify::new(),
+            taken: Notify::new(),
+        }
+    }
+
+    /// Queue a push, applying the overflow policy when full; with `Block`,
+    /// wait until the sender takes one
+    pub async fn enqueue(&self, push: PendingPush) {
+        loop {
+            {
+                let mut entries = self.entries.lock().unwrap();
+                if entries.len() < self.capacity {
+                    entries.push_back(push);
+                    self.pushed.notify_one();
+                    return;
+                }
+                match self.policy {
+                    OverflowPolicy::DropOldest => {
+                        entries.pop_front();
+                        entries.push_back(push);
+                        self.record_drop();
+                        return;
+                    }
+                    OverflowPolicy::DropNewest => {
+                        self.record_drop();
+                        return;
+                    }
+                    OverflowPolicy::Block => {}
+                }
+            }
+            self.taken.notified().await;
+        }
+    }
+
+    /// Oldest queued push, waiting for one when empty
+    pub async fn next(&self) -> PendingPush {
+        loop {
+            if let Some(push) = self.entries.lock().unwrap().pop_front() {
+                self.taken.notify_one();
+                return push;
+            }
+            self.pushed.notified().await;
+        }
+    }
+
+    /// Number of pushes discarded because the queue was full
+    #[cfg(test)]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn record_drop(&self) {
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::warn!(
+            "Monitoring push queue full ({:?}), dropped a measurement ({dropped} dropped so far)",
+            self.policy
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::protocol::ProcessedMeasurement;
+
+    fn push(reading: f64) -> PendingPush {
+        PendingPush {
+            measurement: ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, reading),
+            wavelength: 550.0,
+        }
+    }
+
+    async fn drain(queue: &PushQueue) -> Vec<f64> {
+        let mut readings = Vec::new();
+        while !queue.entries.lock().unwrap().is_empty() {
+            readings.push(queue.next().await.measurement.calibrated_reading);
+        }
+        readings
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let queue = PushQueue::new(2, OverflowPolicy::DropOldest);
+        for reading in [1.0, 2.0, 3.0] {
+            queue.enqueue(push(reading)).await;
+        }
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(drain(&queue).await, vec![2.0, 3.0]);
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest() {
+        let queue = PushQueue::new(2, OverflowPolicy::DropNewest);
+        for reading in [1.0, 2.0, 3.0] {
+            queue.enqueue(push(reading)).await;
+        }
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(drain(&queue).await, vec![1.0, 2.0]);
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_room() {
+        let queue = Arc::new(PushQueue::new(1, OverflowPolicy::Block));
+        queue.enqueue(push(1.0)).await;
+
+        let producer = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.enqueue(push(2.0)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(queue.next().await.measurement.calibrated_reading, 1.0);
+        tokio::time::timeout(Duration::from_secs(1), producer)
+            .await
+            .expect("enqueue still blocked")
+            .unwrap();
+        assert_eq!(queue.next().await.measurement.calibrated_reading, 2.0);
+        assert_eq!(queue.dropped(), 0);
+    }
+}
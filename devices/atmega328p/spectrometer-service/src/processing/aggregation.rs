@@ -0,0 +1,265 @@
+use serde::{Deserialize, Serialize};
+
+use crate::processing::calibration::mean;
+
+/// Default share of values trimmed or winsorized at each end
+pub const DEFAULT_TRIM_FRACTION: f64 = 0.1;
+
+/// Trait for pluggable reduction of a series to a single level
+pub trait Aggregator: Send + Sync {
+    /// Level of `values`; 0 for an empty series
+    fn aggregate(&self, values: &[f64]) -> f64;
+}
+
+/// How a series is reduced to the level used for calibration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregationMethod {
+    /// Arithmetic mean (default)
+    #[default]
+    Mean,
+    /// Middle value, or the mean of the two middle values
+    Median,
+    /// Mean after dropping `trim_fraction` of the values at each end
+    TrimmedMean,
+    /// Mean after clamping `trim_fraction` of the values at each end to the
+    /// nearest value kept
+    WinsorizedMean,
+}
+
+/// `[processing.aggregation]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AggregationSettings {
+    /// Method for every series without its own
+    #[serde(default)]
+    pub method: AggregationMethod,
+    /// Share of the values at each end, in [0, 0.5), that the trimmed and
+    /// winsorized means drop or clamp
+    #[serde(default = "default_trim_fraction")]
+    pub trim_fraction: f64,
+    /// Method for the dark series, overriding `method`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dark: Option<AggregationMethod>,
+    /// Method for the full series, overriding `method`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full: Option<AggregationMethod>,
+    /// Method for the sample series, overriding `method`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<AggregationMethod>,
+}
+
+fn default_trim_fraction() -> f64 {
+    DEFAULT_TRIM_FRACTION
+}
+
+impl Default for AggregationSettings {
+    fn default() -> Self {
+        Self {
+            method: AggregationMethod::Mean,
+            trim_fraction: DEFAULT_TRIM_FRACTION,
+            dark: None,
+            full: None,
+            sample: None,
+        }
+    }
+}
+
+impl AggregationSettings {
+    /// Check the trim fraction is usable
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..0.5).contains(&self.trim_fraction) {
+            return Err(format!(
+                "trim_fraction must be in [0, 0.5), got {}",
+                self.trim_fraction
+            ));
+        }
+        Ok(())
+    }
+
+    /// Aggregator for `method` with the configured trim fraction
+    pub fn aggregator(&self, method: AggregationMethod) -> Box<dyn Aggregator> {
+        match method {
+            AggregationMethod::Mean => Box::new(Mean),
+            AggregationMethod::Median => Box::new(Median),
+            AggregationMethod::TrimmedMean => Box::new(TrimmedMean {
+                fraction: self.trim_fraction,
+            }),
+            AggregationMethod::WinsorizedMean => Box::new(WinsorizedMean {
+                fraction: self.trim_fraction,
+            }),
+        }
+    }
+
+    /// Aggregators for the dark, full and sample series
+    pub fn series_aggregators(&self) -> [Box<dyn Aggregator>; 3] {
+        [self.dark, self.full, self.sample]
+            .map(|method| self.aggregator(method.unwrap_or(self.method)))
+    }
+}
+
+/// Arithmetic mean
+pub struct Mean;
+
+impl Aggregator for Mean {
+    fn aggregate(&self, values: &[f64]) -> f64 {
+        mean(values)
+    }
+}
+
+/// Median
+pub struct Median;
+
+impl Aggregator for Median {
+    fn aggregate(&self, values: &[f64]) -> f64 {
+        if values.is_empty() {
+            return 0.0;
+        }
+        let sorted = sorted(values);
+        let middle = sorted.len() / 2;
+        if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        }
+    }
+}
+
+/// Mean of the values left after dropping `fraction` at each end
+pub struct TrimmedMean {
+    fraction: f64,
+}
+
+impl Aggregator for TrimmedMean {
+    fn aggregate(&self, values: &[f64]) -> f64 {
+        let sorted = sorted(values);
+        let cut = trimmed_count(sorted.len(), self.fraction);
+        mean(&sorted[cut..sorted.len() - cut])
+    }
+}
+
+/// Mean after clamping `fraction` of the values at each end to the
+/// nearest value kept
+pub struct WinsorizedMean {
+    fraction: f64,
+}
+
+impl Aggregator for WinsorizedMean {
+    fn aggregate(&self, values: &[f64]) -> f64 {
+        let mut sorted = sorted(values);
+        let n = sorted.len();
+        let cut = trimmed_count(n, self.fraction);
+        if cut > 0 {
+            let (low, high) = (sorted[cut], sorted[n - cut - 1]);
+            sorted[..cut].fill(low);
+            sorted[n - cut..].fill(high);
+        }
+        mean(&sorted)
+    }
+}
+
+fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted
+}
+
+/// Values cut at each end of `n`, leaving at least one
+fn trimmed_count(n: usize, fraction: f64) -> usize {
+    ((n as f64 * fraction).floor() as usize).min(n.saturating_sub(1) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// COUNT=10 series with two high values
+    const VALUES: [f64; 10] = [5.0, 1.0, 4.0, 2.0, 3.0, 100.0, 6.0, 8.0, 7.0, 20.0];
+
+    fn aggregate(method: AggregationMethod, trim_fraction: f64, values: &[f64]) -> f64 {
+        AggregationSettings {
+            method,
+            trim_fraction,
+            ..Default::default()
+        }
+        .aggregator(method)
+        .aggregate(values)
+    }
+
+    #[test]
+    fn test_mean_and_median() {
+        assert_eq!(aggregate(AggregationMethod::Mean, 0.1, &VALUES), 15.6);
+        assert_eq!(aggregate(AggregationMethod::Median, 0.1, &VALUES), 5.5);
+        assert_eq!(
+            aggregate(AggregationMethod::Median, 0.1, &[3.0, 1.0, 2.0]),
+            2.0
+        );
+    }
+
+    #[test]
+    fn test_trimmed_mean() {
+        // Drops 1 and 100
+        assert_eq!(
+            aggregate(AggregationMethod::TrimmedMean, 0.1, &VALUES),
+            6.875
+        );
+        // Drops 1, 2, 20 and 100
+        assert_eq!(aggregate(AggregationMethod::TrimmedMean, 0.2, &VALUES), 5.5);
+        // No trim is the plain mean
+        assert_eq!(
+            aggregate(AggregationMethod::TrimmedMean, 0.0, &VALUES),
+            15.6
+        );
+    }
+
+    #[test]
+    fn test_winsorized_mean() {
+        // 1 becomes 2 and 100 becomes 20
+        assert_eq!(
+            aggregate(AggregationMethod::WinsorizedMean, 0.1, &VALUES),
+            7.7
+        );
+    }
+
+    #[test]
+    fn test_small_and_empty_series() {
+        for method in [
+            AggregationMethod::Mean,
+            AggregationMethod::Median,
+            AggregationMethod::TrimmedMean,
+            AggregationMethod::WinsorizedMean,
+        ] {
+            assert_eq!(aggregate(method, 0.4, &[]), 0.0);
+            assert_eq!(aggregate(method, 0.4, &[7.0]), 7.0);
+            assert_eq!(aggregate(method, 0.4, &[1.0, 3.0]), 2.0);
+        }
+    }
+
+    #[test]
+    fn test_per_series_override() {
+        let settings: AggregationSettings = toml::from_str(
+            r#"
+method = "trimmed_mean"
+trim_fraction = 0.2
+dark = "median"
+"#,
+        )
+        .unwrap();
+        settings.validate().unwrap();
+        let [dark, full, sample] = settings.series_aggregators();
+        let values = [1.0, 2.0, 3.0, 10.0, 100.0];
+        assert_eq!(dark.aggregate(&values), 3.0);
+        assert_eq!(full.aggregate(&values), 5.0);
+        assert_eq!(sample.aggregate(&values), 5.0);
+    }
+
+    #[test]
+    fn test_validate_rejects_trim_fraction() {
+        for trim_fraction in [-0.1, 0.5, f64::NAN] {
+            let settings = AggregationSettings {
+                trim_fraction,
+                ..Default::default()
+            };
+            assert!(settings.validate().is_err(), "{trim_fraction}");
+        }
+    }
+}
@@ -1,11 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpectrometerError;
+
+/// `[processing.linearity]` config section: a polynomial correcting the
+/// calibrated percentage for detector and ADC nonlinearity
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinearitySettings {
+    /// Coefficients in ascending powers of the reading, `c0 + c1·T + c2·T²
+    /// + …`; empty for no correction
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coefficients: Vec<f64>,
+    /// File with a `coefficients` array from bench calibration, read at
+    /// startup and on reload instead of `coefficients`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// Coefficients read from `file`
+    #[serde(skip)]
+    loaded: Vec<f64>,
+}
+
+/// Contents of a linearity file
+#[derive(Deserialize)]
+struct LinearityFile {
+    coefficients: Vec<f64>,
+}
+
+impl LinearitySettings {
+    /// Check the coefficients are usable and come from one place
+    pub fn validate(&self) -> Result<(), String> {
+        if self.file.is_some() && !self.coefficients.is_empty() {
+            return Err("set either coefficients or file, not both".to_string());
+        }
+        if let Some(c) = self.correction().iter().find(|c| !c.is_finite()) {
+            return Err(format!("coefficients must be finite, got {c}"));
+        }
+        Ok(())
+    }
+
+    /// Read the coefficients from `file`, if set
+    pub fn load_file(&mut self) -> Result<(), SpectrometerError> {
+        self.loaded = match &self.file {
+            Some(path) => read_coefficients(path)?,
+            None => Vec::new(),
+        };
+        Ok(())
+    }
+
+    /// Coefficients in effect
+    pub fn correction(&self) -> &[f64] {
+        if self.file.is_some() {
+            &self.loaded
+        } else {
+            &self.coefficients
+        }
+    }
+}
+
+fn read_coefficients(path: &Path) -> Result<Vec<f64>, SpectrometerError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        SpectrometerError::Config(format!("Failed to read linearity file {path:?}: {e}"))
+    })?;
+    let file: LinearityFile = toml::from_str(&contents).map_err(|e| {
+        SpectrometerError::Config(format!("Failed to parse linearity file {path:?}: {e}"))
+    })?;
+    Ok(file.coefficients)
+}
+
 /// Calibration processor for converting raw ADC values to percentage
 ///
-/// Formula: (sample - dark) / (full - dark) * 100
-pub struct CalibrationProcessor;
+/// Formula: (sample - dark) / (full - dark) * 100, optionally followed by a
+/// nonlinearity correction polynomial
+#[derive(Default)]
+pub struct CalibrationProcessor {
+    /// Correction coefficients in ascending powers; empty for none
+    correction: Vec<f64>,
+}
 
 impl CalibrationProcessor {
-    pub fn new() -> Self {
-        Self
+    /// Processor applying the correction polynomial with `coefficients`
+    pub fn with_correction(coefficients: &[f64]) -> Self {
+        Self {
+            correction: coefficients.to_vec(),
+        }
     }
 
     /// Calculate calibrated reading as percentage
@@ -19,13 +97,69 @@ impl CalibrationProcessor {
             return 0.0;
         }
 
-        ((sample_mean - dark_mean) / denominator) * 100.0
+        self.correct(((sample_mean - dark_mean) / denominator) * 100.0)
     }
-}
 
-impl Default for CalibrationProcessor {
-    fn default() -> Self {
-        Self::new()
+    /// Apply the correction polynomial to a reading
+    fn correct(&self, reading: f64) -> f64 {
+        if self.correction.is_empty() {
+            return reading;
+        }
+        self.correction
+            .iter()
+            .rev()
+            .fold(0.0, |acc, c| acc * reading + c)
+    }
+
+    /// Slope of the correction polynomial at `reading`
+    fn correction_slope(&self, reading: f64) -> f64 {
+        if self.correction.is_empty() {
+            return 1.0;
+        }
+        self.correction
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .fold(0.0, |acc, (power, c)| acc * reading + power as f64 * c)
+    }
+
+    /// Standard uncertainty of the calibrated reading in percentage points
+    ///
+    /// Propagates the standard error of each series mean through the
+    /// calibration formula (first order, series assumed uncorrelated), and
+    /// through the correction polynomial. Returns None if full == dark.
+    pub fn uncertainty(&self, dark: &[f64], full: &[f64], sample: &[f64]) -> Option<f64> {
+        let (dark_mean, full_mean, sample_mean) = (mean(dark), mean(full), mean(sample));
+        let denominator = full_mean - dark_mean;
+
+        if denominator.abs() < f64::EPSILON {
+            return None;
+        }
+
+        // Partial derivatives of 100 * (S - D) / (F - D)
+        let d_sample = 100.0 / denominator;
+        let d_full = -100.0 * (sample_mean - dark_mean) / denominator.powi(2);
+        let d_dark = 100.0 * (sample_mean - full_mean) / denominator.powi(2);
+
+        let variance = (d_dark * standard_error(dark)).powi(2)
+            + (d_full * standard_error(full)).powi(2)
+            + (d_sample * standard_error(sample)).powi(2);
+
+        let reading = 100.0 * (sample_mean - dark_mean) / denominator;
+        Some(variance.sqrt() * self.correction_slope(reading).abs())
+    }
+
+    /// Signal-to-noise ratio of a cycle: the full-dark span over the noise
+    /// of a single full-minus-dark difference, `sqrt(σ_full² + σ_dark²)`.
+    /// A falling SNR at a steady span points at the lamp or detector rather
+    /// than the coating. Returns None without spread to measure.
+    pub fn signal_to_noise(&self, dark: &[f64], full: &[f64]) -> Option<f64> {
+        let noise = (std_dev(dark).powi(2) + std_dev(full).powi(2)).sqrt();
+        if noise < f64::EPSILON {
+            return None;
+        }
+        Some((mean(full) - mean(dark)).abs() / noise)
     }
 }
 
@@ -37,6 +171,24 @@ pub fn mean(values: &[f64]) -> f64 {
     values.iter().sum::<f64>() / values.len() as f64
 }
 
+/// Calculate sample standard deviation of values (0 for fewer than two)
+pub fn std_dev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(values);
+    let sum_sq = values.iter().map(|v| (v - m).powi(2)).sum::<f64>();
+    (sum_sq / (values.len() - 1) as f64).sqrt()
+}
+
+/// Standard error of the mean of values
+fn standard_error(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    std_dev(values) / (values.len() as f64).sqrt()
+}
+
 #[cfg(test)]
 mod tests {
     use approx::assert_relative_eq;
@@ -45,16 +197,41 @@ mod tests {
 
     #[test]
     fn test_calibration_basic() {
-        let processor = CalibrationProcessor::new();
+        let processor = CalibrationProcessor::default();
 
         // dark=100, full=1000, sample=550 -> (550-100)/(1000-100)*100 = 50%
         let result = processor.calculate(100.0, 1000.0, 550.0);
         assert_relative_eq!(result, 50.0, epsilon = 0.01);
     }
 
+    #[test]
+    fn test_signal_to_noise() {
+        let processor = CalibrationProcessor::default();
+
+        // Span 900, σ_dark = σ_full = 1 -> 900 / √2
+        let snr = processor
+            .signal_to_noise(&[99.0, 100.0, 101.0], &[999.0, 1000.0, 1001.0])
+            .unwrap();
+        assert_relative_eq!(snr, 900.0 / 2f64.sqrt(), epsilon = 1e-9);
+
+        // Inverted detector
+        let inverted = processor
+            .signal_to_noise(&[1001.0, 1000.0, 999.0], &[101.0, 100.0, 99.0])
+            .unwrap();
+        assert_relative_eq!(inverted, snr, epsilon = 1e-9);
+
+        // No spread: no noise estimate
+        assert!(processor.signal_to_noise(&[100.0], &[1000.0]).is_none());
+        assert!(
+            processor
+                .signal_to_noise(&[100.0; 3], &[1000.0; 3])
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_calibration_zero_percent() {
-        let processor = CalibrationProcessor::new();
+        let processor = CalibrationProcessor::default();
 
         // sample == dark -> 0%
         let result = processor.calculate(100.0, 1000.0, 100.0);
@@ -63,7 +240,7 @@ mod tests {
 
     #[test]
     fn test_calibration_hundred_percent() {
-        let processor = CalibrationProcessor::new();
+        let processor = CalibrationProcessor::default();
 
         // sample == full -> 100%
         let result = processor.calculate(100.0, 1000.0, 1000.0);
@@ -72,7 +249,7 @@ mod tests {
 
     #[test]
     fn test_calibration_exceeds_hundred() {
-        let processor = CalibrationProcessor::new();
+        let processor = CalibrationProcessor::default();
 
         // sample > full -> > 100%
         let result = processor.calculate(100.0, 1000.0, 1100.0);
@@ -81,7 +258,7 @@ mod tests {
 
     #[test]
     fn test_calibration_negative() {
-        let processor = CalibrationProcessor::new();
+        let processor = CalibrationProcessor::default();
 
         // sample < dark -> negative %
         let result = processor.calculate(100.0, 1000.0, 50.0);
@@ -90,13 +267,83 @@ mod tests {
 
     #[test]
     fn test_calibration_division_by_zero() {
-        let processor = CalibrationProcessor::new();
+        let processor = CalibrationProcessor::default();
 
         // full == dark -> division by zero, returns 0
         let result = processor.calculate(100.0, 100.0, 50.0);
         assert_relative_eq!(result, 0.0, epsilon = 0.01);
     }
 
+    #[test]
+    fn test_correction_polynomial() {
+        // 0.5 + 0.98·T + 0.0002·T²
+        let processor = CalibrationProcessor::with_correction(&[0.5, 0.98, 0.0002]);
+        let result = processor.calculate(100.0, 1000.0, 550.0);
+        assert_relative_eq!(result, 0.5 + 0.98 * 50.0 + 0.0002 * 2500.0, epsilon = 1e-9);
+
+        // Identity coefficients change nothing
+        let identity = CalibrationProcessor::with_correction(&[0.0, 1.0]);
+        assert_relative_eq!(identity.calculate(100.0, 1000.0, 550.0), 50.0);
+        // Division by zero stays uncorrected
+        assert_relative_eq!(processor.calculate(100.0, 100.0, 50.0), 0.0);
+    }
+
+    #[test]
+    fn test_uncertainty_scaled_by_correction_slope() {
+        let sample = [549.0, 551.0, 549.0, 551.0];
+        let plain = CalibrationProcessor::default()
+            .uncertainty(&[100.0; 4], &[1000.0; 4], &sample)
+            .unwrap();
+        // Slope at T=50: 0.98 + 2·0.0002·50 = 1.0
+        let corrected = CalibrationProcessor::with_correction(&[0.5, 0.98, 0.0002])
+            .uncertainty(&[100.0; 4], &[1000.0; 4], &sample)
+            .unwrap();
+        assert_relative_eq!(corrected, plain, epsilon = 1e-9);
+
+        let doubled = CalibrationProcessor::with_correction(&[0.0, 2.0])
+            .uncertainty(&[100.0; 4], &[1000.0; 4], &sample)
+            .unwrap();
+        assert_relative_eq!(doubled, 2.0 * plain, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_linearity_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("linearity.toml");
+        std::fs::write(&path, "coefficients = [0.1, 0.99]\n").unwrap();
+
+        let mut settings = LinearitySettings {
+            file: Some(path.clone()),
+            ..Default::default()
+        };
+        assert!(settings.correction().is_empty());
+        settings.load_file().unwrap();
+        assert_eq!(settings.correction(), &[0.1, 0.99]);
+        assert!(settings.validate().is_ok());
+
+        std::fs::write(&path, "coefficients = \"none\"\n").unwrap();
+        assert!(matches!(
+            settings.load_file(),
+            Err(SpectrometerError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_linearity_validate() {
+        assert!(LinearitySettings::default().validate().is_ok());
+        let both = LinearitySettings {
+            coefficients: vec![0.0, 1.0],
+            file: Some("linearity.toml".into()),
+            ..Default::default()
+        };
+        assert!(both.validate().is_err());
+        let infinite = LinearitySettings {
+            coefficients: vec![f64::INFINITY],
+            ..Default::default()
+        };
+        assert!(infinite.validate().is_err());
+    }
+
     #[test]
     fn test_mean_basic() {
         let values = vec![10.0, 20.0, 30.0];
@@ -114,4 +361,57 @@ mod tests {
         let values = vec![42.0];
         assert_relative_eq!(mean(&values), 42.0, epsilon = 0.01);
     }
+
+    #[test]
+    fn test_std_dev() {
+        let values = vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_relative_eq!(std_dev(&values), 2.138, epsilon = 0.001);
+        assert_relative_eq!(std_dev(&[42.0]), 0.0);
+        assert_relative_eq!(std_dev(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_uncertainty_sample_noise_only() {
+        let processor = CalibrationProcessor::default();
+
+        // Sample mean 550 with standard error 1 -> 100 * 1 / 900
+        let result = processor
+            .uncertainty(&[100.0; 4], &[1000.0; 4], &[549.0, 551.0, 549.0, 551.0])
+            .unwrap();
+        let expected = 100.0 * std_dev(&[549.0, 551.0, 549.0, 551.0]) / 2.0 / 900.0;
+        assert_relative_eq!(result, expected, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_uncertainty_grows_with_reference_noise() {
+        let processor = CalibrationProcessor::default();
+        let sample = [549.0, 551.0, 549.0, 551.0];
+
+        let quiet = processor
+            .uncertainty(&[100.0; 4], &[1000.0; 4], &sample)
+            .unwrap();
+        let noisy = processor
+            .uncertainty(
+                &[99.0, 101.0, 99.0, 101.0],
+                &[990.0, 1010.0, 990.0, 1010.0],
+                &sample,
+            )
+            .unwrap();
+        assert!(noisy > quiet);
+    }
+
+    #[test]
+    fn test_uncertainty_exact_series() {
+        let processor = CalibrationProcessor::default();
+        let result = processor
+            .uncertainty(&[100.0], &[1000.0], &[550.0])
+            .unwrap();
+        assert_relative_eq!(result, 0.0);
+    }
+
+    #[test]
+    fn test_uncertainty_division_by_zero() {
+        let processor = CalibrationProcessor::default();
+        assert!(processor.uncertainty(&[100.0], &[100.0], &[50.0]).is_none());
+    }
 }
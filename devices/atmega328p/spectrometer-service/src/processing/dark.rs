@@ -0,0 +1,222 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::processing::aggregation::Aggregator;
+use crate::processing::outlier::OutlierExcluder;
+use crate::processing::validation::MeasurementValidator;
+use crate::protocol::MeasurementCycle;
+
+/// Default weight of the newest cycle in the rolling dark estimate
+pub const DEFAULT_DARK_SMOOTHING: f64 = 0.1;
+
+/// Rolling dark-current compensation, from the
+/// `[processing.dark_compensation]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DarkCompensationSettings {
+    /// Calibrate against the rolling estimate instead of (or blended with)
+    /// the per-cycle dark mean
+    pub enabled: bool,
+    /// Smoothing constant in (0, 1]: weight of the newest cycle in the
+    /// estimate. Smaller values average over more cycles.
+    pub smoothing: f64,
+    /// Weight of the rolling estimate in the dark level used for
+    /// calibration, in [0, 1]. 1 uses the estimate alone.
+    pub blend: f64,
+}
+
+impl Default for DarkCompensationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            smoothing: DEFAULT_DARK_SMOOTHING,
+            blend: 1.0,
+        }
+    }
+}
+
+impl DarkCompensationSettings {
+    /// Check the constants are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.smoothing > 0.0 && self.smoothing <= 1.0) {
+            return Err(format!(
+                "smoothing must be in (0, 1], got {}",
+                self.smoothing
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.blend) {
+            return Err(format!("blend must be in [0, 1], got {}", self.blend));
+        }
+        Ok(())
+    }
+
+    /// Dark level to calibrate with, given this cycle's dark mean and the
+    /// rolling estimate
+    pub fn apply(&self, cycle_dark: f64, estimate: f64) -> f64 {
+        self.blend * estimate + (1.0 - self.blend) * cycle_dark
+    }
+}
+
+/// Exponentially weighted rolling estimate of the dark mean across cycles
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RollingDark {
+    estimate: Option<f64>,
+    cycles: u64,
+}
+
+impl RollingDark {
+    /// Fold a cycle's dark mean into the estimate and return the new value.
+    /// The first cycle after a reset seeds the estimate.
+    pub fn update(&mut self, dark_mean: f64, smoothing: f64) -> f64 {
+        let estimate = match self.estimate {
+            Some(previous) => previous + smoothing * (dark_mean - previous),
+            None => dark_mean,
+        };
+        self.estimate = Some(estimate);
+        self.cycles += 1;
+        estimate
+    }
+
+    /// Current estimate, `None` until the first cycle
+    pub fn estimate(&self) -> Option<f64> {
+        self.estimate
+    }
+
+    /// Cycles folded in since the last reset
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Forget the estimate, e.g. when the acquisition settings change the
+    /// dark level
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Dark estimate shared between the pipeline and the data loop
+pub type SharedRollingDark = Arc<Mutex<RollingDark>>;
+
+/// Dark level of a cycle taken behind a closed shutter, where every series
+/// reads dark: the values of all series pooled, outliers excluded, and
+/// reduced with `aggregator`. None for a cycle without values or outside
+/// the ADC range, which would drag the estimate off.
+pub fn closed_shutter_level(
+    cycle: &MeasurementCycle,
+    excluder: &dyn OutlierExcluder,
+    aggregator: &dyn Aggregator,
+    validator: &MeasurementValidator,
+) -> Option<f64> {
+    validator.check_range(cycle).ok()?;
+    let values: Vec<f64> = cycle
+        .series
+        .values()
+        .flat_map(|series| series.to_f64())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    Some(aggregator.aggregate(&excluder.filter(&values)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use approx::assert_relative_eq;
+    use chrono::Utc;
+
+    use super::*;
+    use crate::processing::aggregation::Mean;
+    use crate::processing::outlier::OutlierMethod;
+    use crate::protocol::{MAX_ADC_VALUE, SeriesData};
+
+    #[test]
+    fn test_first_cycle_seeds_estimate() {
+        let mut dark = RollingDark::default();
+        assert_eq!(dark.estimate(), None);
+
+        assert_relative_eq!(dark.update(1000.0, 0.1), 1000.0);
+        assert_eq!(dark.cycles(), 1);
+    }
+
+    #[test]
+    fn test_estimate_is_exponentially_weighted() {
+        let mut dark = RollingDark::default();
+        dark.update(1000.0, 0.25);
+        assert_relative_eq!(dark.update(1100.0, 0.25), 1025.0);
+        assert_relative_eq!(dark.update(1100.0, 0.25), 1043.75);
+    }
+
+    #[test]
+    fn test_smoothing_one_follows_last_cycle() {
+        let mut dark = RollingDark::default();
+        dark.update(1000.0, 1.0);
+        assert_relative_eq!(dark.update(1200.0, 1.0), 1200.0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut dark = RollingDark::default();
+        dark.update(1000.0, 0.1);
+        dark.reset();
+        assert_eq!(dark, RollingDark::default());
+    }
+
+    #[test]
+    fn test_blend() {
+        let settings = DarkCompensationSettings {
+            blend: 0.75,
+            ..Default::default()
+        };
+        assert_relative_eq!(settings.apply(1000.0, 1100.0), 1075.0);
+
+        let estimate_only = DarkCompensationSettings::default();
+        assert_relative_eq!(estimate_only.apply(1000.0, 1100.0), 1100.0);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(DarkCompensationSettings::default().validate().is_ok());
+
+        for smoothing in [0.0, -0.1, 1.5, f64::NAN] {
+            let settings = DarkCompensationSettings {
+                smoothing,
+                ..Default::default()
+            };
+            assert!(settings.validate().is_err(), "smoothing {smoothing}");
+        }
+
+        let settings = DarkCompensationSettings {
+            blend: 1.1,
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn test_closed_shutter_level() {
+        let cycle = MeasurementCycle::from_series(
+            Utc::now(),
+            BTreeMap::from([
+                (1, SeriesData::new(vec![1000, 1002])),
+                (2, SeriesData::new(vec![1001, 1003, 9000])),
+            ]),
+        );
+        let excluder = OutlierMethod::Grubbs { alpha: 0.05 }.create().unwrap();
+        let validator = MeasurementValidator::new();
+        let level = closed_shutter_level(&cycle, excluder.as_ref(), &Mean, &validator).unwrap();
+        // 9000 is dropped as an outlier
+        assert_relative_eq!(level, 1001.5);
+
+        let empty = MeasurementCycle::from_series(Utc::now(), BTreeMap::new());
+        assert!(closed_shutter_level(&empty, excluder.as_ref(), &Mean, &validator).is_none());
+
+        let saturated = MeasurementCycle::from_series(
+            Utc::now(),
+            BTreeMap::from([(1, SeriesData::new(vec![MAX_ADC_VALUE]))]),
+        );
+        assert!(closed_shutter_level(&saturated, excluder.as_ref(), &Mean, &validator).is_none());
+    }
+}
@@ -0,0 +1,212 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::protocol::MeasurementCycle;
+
+/// Intervals needed before the timing heuristic reports gaps
+const MIN_TIMING_INTERVALS: u32 = 3;
+
+/// An interval longer than this many typical intervals counts as a gap
+const GAP_FACTOR: f64 = 1.5;
+
+/// Weight of the newest interval in the typical-interval estimate
+const INTERVAL_SMOOTHING: f64 = 0.2;
+
+/// How missed cycles were detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapDetection {
+    /// Jump in the device's `CYCLE=<n>` counter
+    Sequence,
+    /// Interval well above the typical cycle interval, for firmware
+    /// without a cycle counter
+    Timing,
+}
+
+impl GapDetection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GapDetection::Sequence => "sequence",
+            GapDetection::Timing => "timing",
+        }
+    }
+}
+
+/// Cycles missed between two received cycles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleGap {
+    pub missed: u64,
+    pub detection: GapDetection,
+}
+
+/// Detects cycles lost between the device and the service. Uses the
+/// device's cycle counter when present and falls back to comparing the
+/// interval against the typical cycle interval.
+#[derive(Debug, Clone, Default)]
+pub struct GapDetector {
+    last_sequence: Option<u32>,
+    last_timestamp: Option<DateTime<Utc>>,
+    /// Typical cycle interval in seconds
+    interval: Option<f64>,
+    intervals: u32,
+}
+
+impl GapDetector {
+    /// Check a received cycle against the previous one
+    pub fn check(&mut self, cycle: &MeasurementCycle) -> Option<CycleGap> {
+        let by_sequence = self.check_sequence(cycle.sequence);
+        let by_timing = self.check_timing(cycle.timestamp);
+
+        match cycle.sequence {
+            Some(_) => by_sequence,
+            None => by_timing,
+        }
+    }
+
+    fn check_sequence(&mut self, sequence: Option<u32>) -> Option<CycleGap> {
+        let previous = std::mem::replace(&mut self.last_sequence, sequence)?;
+        let current = sequence?;
+
+        // A counter that does not move forward means the device restarted
+        let missed = current.checked_sub(previous)?.checked_sub(1)?;
+        (missed > 0).then_some(CycleGap {
+            missed: u64::from(missed),
+            detection: GapDetection::Sequence,
+        })
+    }
+
+    fn check_timing(&mut self, timestamp: DateTime<Utc>) -> Option<CycleGap> {
+        let previous = self.last_timestamp.replace(timestamp)?;
+        let elapsed = (timestamp - previous).num_microseconds()? as f64 / 1e6;
+        if elapsed <= 0.0 {
+            return None;
+        }
+
+        if let Some(interval) = self.interval
+            && self.intervals >= MIN_TIMING_INTERVALS
+            && elapsed > GAP_FACTOR * interval
+        {
+            // Gap intervals are left out of the estimate
+            let missed = ((elapsed / interval).round() as u64)
+                .saturating_sub(1)
+                .max(1);
+            return Some(CycleGap {
+                missed,
+                detection: GapDetection::Timing,
+            });
+        }
+
+        self.interval = Some(match self.interval {
+            Some(interval) => interval + INTERVAL_SMOOTHING * (elapsed - interval),
+            None => elapsed,
+        });
+        self.intervals += 1;
+        None
+    }
+
+    /// Forget the history, e.g. when the acquisition settings change the
+    /// cycle interval
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::protocol::SeriesData;
+
+    fn cycle(timestamp: DateTime<Utc>, sequence: Option<u32>) -> MeasurementCycle {
+        MeasurementCycle {
+            sequence,
+            ..MeasurementCycle::with_timestamp(
+                timestamp,
+                SeriesData::new(vec![1]),
+                SeriesData::new(vec![1]),
+                SeriesData::new(vec![1]),
+            )
+        }
+    }
+
+    #[test]
+    fn test_sequence_gap() {
+        let mut detector = GapDetector::default();
+        let t0 = Utc::now();
+        assert_eq!(detector.check(&cycle(t0, Some(10))), None);
+        assert_eq!(detector.check(&cycle(t0, Some(11))), None);
+        assert_eq!(
+            detector.check(&cycle(t0, Some(14))),
+            Some(CycleGap {
+                missed: 2,
+                detection: GapDetection::Sequence
+            })
+        );
+    }
+
+    #[test]
+    fn test_sequence_restart_is_not_a_gap() {
+        let mut detector = GapDetector::default();
+        let t0 = Utc::now();
+        detector.check(&cycle(t0, Some(500)));
+        assert_eq!(detector.check(&cycle(t0, Some(0))), None);
+        assert_eq!(detector.check(&cycle(t0, Some(1))), None);
+    }
+
+    #[test]
+    fn test_timing_gap_without_sequence() {
+        let mut detector = GapDetector::default();
+        let t0 = Utc::now();
+        let at = |ms| t0 + Duration::milliseconds(ms);
+
+        for i in 0..=4 {
+            assert_eq!(detector.check(&cycle(at(i * 1000), None)), None);
+        }
+        // Next cycle 3 s late: two missed
+        assert_eq!(
+            detector.check(&cycle(at(7000), None)),
+            Some(CycleGap {
+                missed: 2,
+                detection: GapDetection::Timing
+            })
+        );
+        // Jitter stays below the threshold
+        assert_eq!(detector.check(&cycle(at(8200), None)), None);
+    }
+
+    #[test]
+    fn test_timing_needs_warm_up() {
+        let mut detector = GapDetector::default();
+        let t0 = Utc::now();
+        detector.check(&cycle(t0, None));
+        detector.check(&cycle(t0 + Duration::seconds(1), None));
+        assert_eq!(
+            detector.check(&cycle(t0 + Duration::seconds(5), None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sequence_takes_precedence_over_timing() {
+        let mut detector = GapDetector::default();
+        let t0 = Utc::now();
+        for i in 0..5 {
+            detector.check(&cycle(t0 + Duration::seconds(i), Some(i as u32)));
+        }
+        // Late but consecutive: the device paused, nothing was lost
+        assert_eq!(
+            detector.check(&cycle(t0 + Duration::seconds(20), Some(5))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut detector = GapDetector::default();
+        let t0 = Utc::now();
+        detector.check(&cycle(t0, Some(1)));
+        detector.reset();
+        assert_eq!(detector.check(&cycle(t0, Some(9))), None);
+    }
+}
@@ -1,3 +1,12 @@
+pub mod aggregation;
 pub mod calibration;
+pub mod dark;
+pub mod gaps;
 pub mod outlier;
+pub mod pipeline;
+pub mod references;
+pub mod smoothing;
+pub mod thickness;
+pub mod timing;
+pub mod units;
 pub mod validation;
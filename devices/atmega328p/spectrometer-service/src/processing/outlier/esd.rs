@@ -0,0 +1,212 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use statrs::distribution::{ContinuousCDF, StudentsT};
+
+use super::OutlierExcluder;
+use crate::error::SpectrometerError;
+
+/// Default significance level of the generalized ESD test
+pub const DEFAULT_ESD_ALPHA: f64 = 0.05;
+
+/// Default upper bound on the number of outliers per series
+pub const DEFAULT_ESD_MAX_OUTLIERS: usize = 3;
+
+/// Generalized Extreme Studentized Deviate test (Rosner, 1983)
+///
+/// Removes the most extreme value `max_outliers` times, recording its
+/// statistic each time, and then declares as outliers the values removed
+/// up to the last step whose statistic exceeded its critical value. Unlike
+/// repeated Grubbs tests, which stop at the first step that fails, a later
+/// step can still flag the earlier ones, so a second outlier that inflates
+/// the standard deviation no longer masks the first. Keeps at least two
+/// values.
+pub struct EsdExcluder {
+    alpha: f64,
+    max_outliers: usize,
+    /// Set once a critical value could not be computed, to warn only once
+    fallback_warned: AtomicBool,
+}
+
+impl EsdExcluder {
+    /// Create a generalized ESD excluder. `alpha` must lie strictly between
+    /// 0 and 1 and `max_outliers` be at least 1.
+    pub fn new(alpha: f64, max_outliers: usize) -> Result<Self, SpectrometerError> {
+        if !(alpha > 0.0 && alpha < 1.0) {
+            return Err(SpectrometerError::Config(format!(
+                "ESD alpha must be between 0 and 1 (exclusive), got {alpha}"
+            )));
+        }
+        if max_outliers == 0 {
+            return Err(SpectrometerError::Config(
+                "ESD max_outliers must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            alpha,
+            max_outliers,
+            fallback_warned: AtomicBool::new(false),
+        })
+    }
+
+    /// Critical value for step `i` (1-based) of a test on `n` values.
+    /// Returns None if the t-distribution cannot be evaluated.
+    fn critical_value(&self, n: usize, i: usize) -> Option<f64> {
+        let remaining = (n - i) as f64;
+        let df = remaining - 1.0;
+
+        let t_dist = StudentsT::new(0.0, 1.0, df).ok()?;
+        let p = 1.0 - self.alpha / (2.0 * (remaining + 1.0));
+        let t_crit = t_dist.inverse_cdf(p);
+
+        let critical = remaining * t_crit / ((df + t_crit.powi(2)) * (remaining + 1.0)).sqrt();
+        critical.is_finite().then_some(critical)
+    }
+
+    fn warn_fallback(&self, n: usize) {
+        if self.fallback_warned.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        tracing::warn!(
+            "ESD critical value unavailable (alpha={}, n={}), skipping outlier exclusion",
+            self.alpha,
+            n
+        );
+    }
+}
+
+impl OutlierExcluder for EsdExcluder {
+    fn find_outliers(&self, values: &[f64]) -> Vec<usize> {
+        let n = values.len();
+        if n < 3 {
+            return Vec::new();
+        }
+
+        let mut remaining: Vec<(usize, f64)> =
+            values.iter().enumerate().map(|(i, &v)| (i, v)).collect();
+        let mut removed = Vec::new();
+        let mut outlier_count = 0;
+
+        // Each step needs a defined spread after removal: at least two left
+        for i in 1..=self.max_outliers.min(n - 2) {
+            let count = remaining.len() as f64;
+            let mean = remaining.iter().map(|(_, v)| v).sum::<f64>() / count;
+            let variance = remaining
+                .iter()
+                .map(|(_, v)| (v - mean).powi(2))
+                .sum::<f64>()
+                / (count - 1.0);
+            let std_dev = variance.sqrt();
+            if std_dev == 0.0 {
+                break;
+            }
+
+            // Most extreme remaining value
+            let mut position = 0;
+            let mut deviation = 0.0;
+            for (i, (_, v)) in remaining.iter().enumerate() {
+                if (v - mean).abs() > deviation {
+                    deviation = (v - mean).abs();
+                    position = i;
+                }
+            }
+            let Some(critical) = self.critical_value(n, i) else {
+                self.warn_fallback(n);
+                return Vec::new();
+            };
+
+            let (original_idx, _) = remaining.remove(position);
+            removed.push(original_idx);
+            if deviation / std_dev > critical {
+                outlier_count = i;
+            }
+        }
+
+        removed.truncate(outlier_count);
+        removed
+    }
+
+    fn name(&self) -> &'static str {
+        "Generalized ESD"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::outlier::grubbs::GrubbsExcluder;
+
+    /// COUNT=12 series with two similar outliers
+    fn two_outliers() -> Vec<f64> {
+        vec![
+            10.0, 10.1, 10.2, 10.3, 10.4, 10.5, 10.6, 10.7, 10.8, 10.9, 14.0, 14.1,
+        ]
+    }
+
+    #[test]
+    fn test_esd_no_outliers() {
+        let excluder = EsdExcluder::new(0.05, 3).unwrap();
+        let values = vec![10.0, 11.0, 10.5, 10.2, 10.8];
+
+        assert!(excluder.find_outliers(&values).is_empty());
+    }
+
+    #[test]
+    fn test_esd_single_outlier() {
+        let excluder = EsdExcluder::new(0.05, 3).unwrap();
+        let values = vec![10.0, 10.1, 10.2, 10.3, 10.4, 10.5, 10.6, 10.7, 500.0];
+
+        assert_eq!(excluder.find_outliers(&values), vec![8]);
+    }
+
+    #[test]
+    fn test_esd_finds_outliers_masked_for_grubbs() {
+        let values = two_outliers();
+
+        let grubbs = GrubbsExcluder::new(0.05).unwrap();
+        assert!(grubbs.find_outliers(&values).is_empty());
+
+        let esd = EsdExcluder::new(0.05, 3).unwrap();
+        let mut outliers = esd.find_outliers(&values);
+        outliers.sort_unstable();
+        assert_eq!(outliers, vec![10, 11]);
+
+        // Testing for a single outlier leaves the pair masked
+        let single = EsdExcluder::new(0.05, 1).unwrap();
+        assert!(single.find_outliers(&values).is_empty());
+    }
+
+    #[test]
+    fn test_esd_small_and_identical() {
+        let excluder = EsdExcluder::new(0.05, 3).unwrap();
+        assert!(excluder.find_outliers(&[10.0, 100.0]).is_empty());
+        assert!(excluder.find_outliers(&[10.0; 5]).is_empty());
+    }
+
+    #[test]
+    fn test_esd_keeps_two_values() {
+        let excluder = EsdExcluder::new(0.5, 10).unwrap();
+        let values = vec![1.0, 1.0, 1.0, 50.0, 100.0];
+
+        let outliers = excluder.find_outliers(&values);
+        assert!(values.len() - outliers.len() >= 2);
+        assert_eq!(
+            excluder.filter(&values).len(),
+            values.len() - outliers.len()
+        );
+    }
+
+    #[test]
+    fn test_esd_rejects_invalid_parameters() {
+        for alpha in [0.0, 1.0, -0.1, f64::NAN] {
+            assert!(
+                matches!(
+                    EsdExcluder::new(alpha, 3),
+                    Err(SpectrometerError::Config(_))
+                ),
+                "alpha {alpha} should be rejected"
+            );
+        }
+        assert!(EsdExcluder::new(0.05, 0).is_err());
+    }
+}
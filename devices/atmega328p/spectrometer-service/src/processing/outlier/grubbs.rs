@@ -1,6 +1,9 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use statrs::distribution::{ContinuousCDF, StudentsT};
 
 use super::OutlierExcluder;
+use crate::error::SpectrometerError;
 
 /// Grubbs' test for outlier detection
 ///
@@ -8,11 +11,23 @@ use super::OutlierExcluder;
 /// Requires at least 3 values to perform the test.
 pub struct GrubbsExcluder {
     alpha: f64,
+    /// Set once the critical value could not be computed, to warn only once
+    fallback_warned: AtomicBool,
 }
 
 impl GrubbsExcluder {
-    pub fn new(alpha: f64) -> Self {
-        Self { alpha }
+    /// Create a Grubbs excluder. `alpha` must lie strictly between 0 and 1.
+    pub fn new(alpha: f64) -> Result<Self, SpectrometerError> {
+        if !(alpha > 0.0 && alpha < 1.0) {
+            return Err(SpectrometerError::Config(format!(
+                "Grubbs alpha must be between 0 and 1 (exclusive), got {alpha}"
+            )));
+        }
+
+        Ok(Self {
+            alpha,
+            fallback_warned: AtomicBool::new(false),
+        })
     }
 
     /// Calculate Grubbs' test statistic for a specific value
@@ -29,26 +44,45 @@ impl GrubbsExcluder {
         (values[index] - mean).abs() / std_dev
     }
 
-    /// Calculate critical value for Grubbs' test
-    fn critical_value(&self, n: usize) -> f64 {
+    /// Calculate critical value for Grubbs' test.
+    /// Returns None if the t-distribution cannot be evaluated.
+    fn critical_value(&self, n: usize) -> Option<f64> {
         let n = n as f64;
         let df = n - 2.0;
 
         if df <= 0.0 {
-            return f64::INFINITY;
+            return Some(f64::INFINITY);
         }
 
         // Two-tailed t critical value
-        let t_dist = StudentsT::new(0.0, 1.0, df).unwrap();
+        let t_dist = StudentsT::new(0.0, 1.0, df).ok()?;
         let t_crit = t_dist.inverse_cdf(1.0 - self.alpha / (2.0 * n));
 
         // Grubbs critical value formula
-        ((n - 1.0) / n.sqrt()) * (t_crit.powi(2) / (n - 2.0 + t_crit.powi(2))).sqrt()
+        let critical =
+            ((n - 1.0) / n.sqrt()) * (t_crit.powi(2) / (n - 2.0 + t_crit.powi(2))).sqrt();
+
+        critical.is_finite().then_some(critical)
     }
-}
 
-impl OutlierExcluder for GrubbsExcluder {
-    fn find_outliers(&self, values: &[f64]) -> Vec<usize> {
+    fn warn_fallback(&self, n: usize) {
+        if self.fallback_warned.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        tracing::warn!(
+            "Grubbs critical value unavailable (alpha={}, n={}), stopping outlier exclusion",
+            self.alpha,
+            n
+        );
+    }
+
+    /// Repeated Grubbs tests with `critical_value` giving the threshold for
+    /// n values. When it is unavailable the outliers found so far are kept.
+    fn find_outliers_with(
+        &self,
+        values: &[f64],
+        critical_value: impl Fn(usize) -> Option<f64>,
+    ) -> Vec<usize> {
         if values.len() < 3 {
             return Vec::new();
         }
@@ -63,7 +97,10 @@ impl OutlierExcluder for GrubbsExcluder {
             }
 
             let current_values: Vec<f64> = remaining.iter().map(|(_, v)| *v).collect();
-            let critical = self.critical_value(current_values.len());
+            let Some(critical) = critical_value(current_values.len()) else {
+                self.warn_fallback(current_values.len());
+                break;
+            };
 
             // Find value with maximum Grubbs statistic
             let mut max_idx = 0;
@@ -87,6 +124,12 @@ impl OutlierExcluder for GrubbsExcluder {
 
         outliers
     }
+}
+
+impl OutlierExcluder for GrubbsExcluder {
+    fn find_outliers(&self, values: &[f64]) -> Vec<usize> {
+        self.find_outliers_with(values, |n| self.critical_value(n))
+    }
 
     fn name(&self) -> &'static str {
         "Grubbs"
@@ -100,7 +143,7 @@ mod tests {
 
     #[test]
     fn test_grubbs_no_outliers() {
-        let excluder = GrubbsExcluder::new(0.05);
+        let excluder = GrubbsExcluder::new(0.05).unwrap();
         let values = vec![10.0, 11.0, 10.5, 10.2, 10.8];
         let outliers = excluder.find_outliers(&values);
 
@@ -109,7 +152,7 @@ mod tests {
 
     #[test]
     fn test_grubbs_single_outlier() {
-        let excluder = GrubbsExcluder::new(0.05);
+        let excluder = GrubbsExcluder::new(0.05).unwrap();
         let values = vec![10.0, 11.0, 10.5, 100.0, 10.2]; // 100.0 is obvious outlier
         let outliers = excluder.find_outliers(&values);
 
@@ -119,7 +162,7 @@ mod tests {
 
     #[test]
     fn test_grubbs_multiple_outliers() {
-        let excluder = GrubbsExcluder::new(0.05);
+        let excluder = GrubbsExcluder::new(0.05).unwrap();
         // More values needed for reliable outlier detection
         let values = vec![
             10.0, 10.1, 10.2, 10.3, 10.4, 10.5, 10.6, 10.7,  // Normal values
@@ -133,7 +176,7 @@ mod tests {
 
     #[test]
     fn test_grubbs_small_dataset() {
-        let excluder = GrubbsExcluder::new(0.05);
+        let excluder = GrubbsExcluder::new(0.05).unwrap();
 
         // Less than 3 values - no outlier detection possible
         let values = vec![10.0, 100.0];
@@ -147,7 +190,7 @@ mod tests {
 
     #[test]
     fn test_grubbs_identical_values() {
-        let excluder = GrubbsExcluder::new(0.05);
+        let excluder = GrubbsExcluder::new(0.05).unwrap();
         let values = vec![10.0, 10.0, 10.0, 10.0, 10.0];
         let outliers = excluder.find_outliers(&values);
 
@@ -156,7 +199,7 @@ mod tests {
 
     #[test]
     fn test_grubbs_filter() {
-        let excluder = GrubbsExcluder::new(0.05);
+        let excluder = GrubbsExcluder::new(0.05).unwrap();
         let values = vec![10.0, 11.0, 10.5, 100.0, 10.2];
         let filtered = excluder.filter(&values);
 
@@ -169,8 +212,8 @@ mod tests {
     #[test]
     fn test_grubbs_different_alpha_levels() {
         // More strict (lower alpha) should find fewer outliers
-        let strict = GrubbsExcluder::new(0.01);
-        let lenient = GrubbsExcluder::new(0.10);
+        let strict = GrubbsExcluder::new(0.01).unwrap();
+        let lenient = GrubbsExcluder::new(0.10).unwrap();
 
         // Borderline outlier case
         let values = vec![10.0, 11.0, 10.5, 15.0, 10.2];
@@ -190,4 +233,89 @@ mod tests {
         // The outlier should have a high G statistic
         assert!(g > 1.0);
     }
+
+    #[test]
+    fn test_grubbs_rejects_invalid_alpha() {
+        for alpha in [0.0, 1.0, -0.1, 1.5, f64::NAN, f64::INFINITY] {
+            let result = GrubbsExcluder::new(alpha);
+            assert!(
+                matches!(result, Err(SpectrometerError::Config(_))),
+                "alpha {alpha} should be rejected"
+            );
+        }
+    }
+
+    /// Deterministic pseudo-random values in [0, 1) for sweep tests
+    fn pseudo_random(seed: u64, n: usize) -> Vec<f64> {
+        let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (0..n)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 11) as f64 / (1u64 << 53) as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_grubbs_properties_across_alpha_and_n() {
+        let alphas = [1e-12, 1e-6, 0.001, 0.01, 0.05, 0.1, 0.5, 0.9, 0.999_999];
+
+        for &alpha in &alphas {
+            let excluder = GrubbsExcluder::new(alpha).unwrap();
+
+            for n in 0..=40 {
+                let mut values: Vec<f64> = pseudo_random(n as u64 + 7, n)
+                    .into_iter()
+                    .map(|v| 1_000_000.0 + v * 1000.0)
+                    .collect();
+                if n > 3 {
+                    values[n / 2] = 16_777_215.0;
+                }
+
+                let outliers = excluder.find_outliers(&values);
+
+                // Never removes so much that fewer than 2 values remain
+                assert!(outliers.len() <= n.saturating_sub(2));
+                // Indices are in range and unique
+                let mut sorted = outliers.clone();
+                sorted.sort_unstable();
+                sorted.dedup();
+                assert_eq!(sorted.len(), outliers.len());
+                assert!(outliers.iter().all(|&i| i < n));
+                // Filter is consistent with find_outliers
+                assert_eq!(excluder.filter(&values).len(), n - outliers.len());
+            }
+        }
+    }
+
+    #[test]
+    fn test_grubbs_keeps_outliers_found_before_critical_value_fails() {
+        let excluder = GrubbsExcluder::new(0.05).unwrap();
+        let values = vec![10.0, 11.0, 10.5, 100.0, 10.2];
+
+        // Available for the first test only
+        let outliers = excluder.find_outliers_with(&values, |n| {
+            (n == values.len())
+                .then(|| excluder.critical_value(n))
+                .flatten()
+        });
+
+        assert_eq!(outliers, vec![3]);
+    }
+
+    #[test]
+    fn test_grubbs_critical_value_finite_for_valid_inputs() {
+        for alpha in [1e-9, 0.01, 0.05, 0.5, 0.99] {
+            let excluder = GrubbsExcluder::new(alpha).unwrap();
+            for n in 3..=1000 {
+                let critical = excluder.critical_value(n);
+                assert!(
+                    critical.is_some_and(|c| c.is_finite() && c > 0.0),
+                    "alpha={alpha}, n={n}"
+                );
+            }
+        }
+    }
 }
@@ -1,7 +1,15 @@
+pub mod esd;
 pub mod grubbs;
 pub mod none;
+pub mod sigma_clip;
+pub mod stats;
 
 use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpectrometerError;
 
 /// Trait for pluggable outlier exclusion algorithms
 pub trait OutlierExcluder: Send + Sync {
@@ -25,12 +33,45 @@ pub trait OutlierExcluder: Send + Sync {
 }
 
 /// Configuration for outlier exclusion method
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
 pub enum OutlierMethod {
     /// No outlier exclusion
     None,
     /// Grubbs' test with given significance level (alpha)
     Grubbs { alpha: f64 },
+    /// Iterated sigma clipping: drop values more than `k` standard
+    /// deviations from the mean, up to `max_iterations` passes
+    SigmaClip {
+        #[serde(default = "default_sigma_k")]
+        k: f64,
+        #[serde(default = "default_sigma_max_iterations")]
+        max_iterations: u32,
+    },
+    /// Generalized ESD test for up to `max_outliers` outliers at
+    /// significance level `alpha`
+    Esd {
+        #[serde(default = "default_esd_alpha")]
+        alpha: f64,
+        #[serde(default = "default_esd_max_outliers")]
+        max_outliers: usize,
+    },
+}
+
+fn default_sigma_k() -> f64 {
+    sigma_clip::DEFAULT_SIGMA_K
+}
+
+fn default_sigma_max_iterations() -> u32 {
+    sigma_clip::DEFAULT_SIGMA_MAX_ITERATIONS
+}
+
+fn default_esd_alpha() -> f64 {
+    esd::DEFAULT_ESD_ALPHA
+}
+
+fn default_esd_max_outliers() -> usize {
+    esd::DEFAULT_ESD_MAX_OUTLIERS
 }
 
 impl Default for OutlierMethod {
@@ -41,15 +82,29 @@ impl Default for OutlierMethod {
 }
 
 impl OutlierMethod {
-    /// Create an outlier excluder instance
-    pub fn create(&self) -> Box<dyn OutlierExcluder> {
+    /// Create an outlier excluder instance, validating its parameters
+    pub fn create(&self) -> Result<Box<dyn OutlierExcluder>, SpectrometerError> {
         match self {
-            OutlierMethod::None => Box::new(none::NoOutlierExcluder),
-            OutlierMethod::Grubbs { alpha } => Box::new(grubbs::GrubbsExcluder::new(*alpha)),
+            OutlierMethod::None => Ok(Box::new(none::NoOutlierExcluder)),
+            OutlierMethod::Grubbs { alpha } => Ok(Box::new(grubbs::GrubbsExcluder::new(*alpha)?)),
+            OutlierMethod::SigmaClip { k, max_iterations } => Ok(Box::new(
+                sigma_clip::SigmaClipExcluder::new(*k, *max_iterations)?,
+            )),
+            OutlierMethod::Esd {
+                alpha,
+                max_outliers,
+            } => Ok(Box::new(esd::EsdExcluder::new(*alpha, *max_outliers)?)),
         }
     }
 }
 
+/// Outlier excluder shared between the data loop and config reload
+pub type SharedExcluder = Arc<RwLock<Arc<dyn OutlierExcluder>>>;
+
+pub fn create_shared_excluder(excluder: Box<dyn OutlierExcluder>) -> SharedExcluder {
+    Arc::new(RwLock::new(Arc::from(excluder)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -73,6 +128,55 @@ mod tests {
         assert_eq!(filtered, vec![1.0, 2.0, 3.0]);
     }
 
+    #[test]
+    fn test_create_rejects_invalid_alpha() {
+        assert!(OutlierMethod::Grubbs { alpha: 0.0 }.create().is_err());
+        assert!(OutlierMethod::Grubbs { alpha: 0.05 }.create().is_ok());
+        assert!(OutlierMethod::None.create().is_ok());
+        assert!(
+            OutlierMethod::SigmaClip {
+                k: 0.0,
+                max_iterations: 5
+            }
+            .create()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_sigma_clip_config_defaults() {
+        let method: OutlierMethod = toml::from_str(r#"method = "sigma_clip""#).unwrap();
+        assert_eq!(
+            method,
+            OutlierMethod::SigmaClip {
+                k: 3.0,
+                max_iterations: 5
+            }
+        );
+        assert_eq!(method.create().unwrap().name(), "Sigma-clip");
+    }
+
+    #[test]
+    fn test_esd_config_defaults() {
+        let method: OutlierMethod = toml::from_str(r#"method = "esd""#).unwrap();
+        assert_eq!(
+            method,
+            OutlierMethod::Esd {
+                alpha: 0.05,
+                max_outliers: 3
+            }
+        );
+        assert_eq!(method.create().unwrap().name(), "Generalized ESD");
+        assert!(
+            OutlierMethod::Esd {
+                alpha: 0.05,
+                max_outliers: 0
+            }
+            .create()
+            .is_err()
+        );
+    }
+
     #[test]
     fn test_outlier_method_default_is_grubbs() {
         let method = OutlierMethod::default();
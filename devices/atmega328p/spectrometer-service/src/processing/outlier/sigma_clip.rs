@@ -0,0 +1,156 @@
+use super::OutlierExcluder;
+use crate::error::SpectrometerError;
+
+/// Default clipping threshold in standard deviations
+pub const DEFAULT_SIGMA_K: f64 = 3.0;
+
+/// Default number of clipping passes
+pub const DEFAULT_SIGMA_MAX_ITERATIONS: u32 = 5;
+
+/// Iterated sigma clipping
+///
+/// Each pass drops every value with `|x - mean| > k * sigma`, then
+/// recomputes the mean and standard deviation of what is left. Stops when a
+/// pass drops nothing or after `max_iterations` passes. Cheaper than Grubbs
+/// for large COUNT settings since each pass is a single scan.
+pub struct SigmaClipExcluder {
+    k: f64,
+    max_iterations: u32,
+}
+
+impl SigmaClipExcluder {
+    /// Create a sigma-clipping excluder. `k` must be positive and
+    /// `max_iterations` at least 1.
+    pub fn new(k: f64, max_iterations: u32) -> Result<Self, SpectrometerError> {
+        if !(k > 0.0 && k.is_finite()) {
+            return Err(SpectrometerError::Config(format!(
+                "sigma-clip k must be a positive number, got {k}"
+            )));
+        }
+        if max_iterations == 0 {
+            return Err(SpectrometerError::Config(
+                "sigma-clip max_iterations must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(Self { k, max_iterations })
+    }
+}
+
+impl OutlierExcluder for SigmaClipExcluder {
+    fn find_outliers(&self, values: &[f64]) -> Vec<usize> {
+        if values.len() < 3 {
+            return Vec::new();
+        }
+
+        let mut outliers = Vec::new();
+        let mut remaining: Vec<(usize, f64)> =
+            values.iter().enumerate().map(|(i, &v)| (i, v)).collect();
+
+        for _ in 0..self.max_iterations {
+            let n = remaining.len() as f64;
+            let mean = remaining.iter().map(|(_, v)| v).sum::<f64>() / n;
+            let variance = remaining
+                .iter()
+                .map(|(_, v)| (v - mean).powi(2))
+                .sum::<f64>()
+                / (n - 1.0);
+            let limit = self.k * variance.sqrt();
+
+            let (clipped, kept): (Vec<_>, Vec<_>) = remaining
+                .iter()
+                .partition(|(_, v)| (v - mean).abs() > limit);
+
+            // Keep at least two values so the spread stays defined
+            if clipped.is_empty() || kept.len() < 2 {
+                break;
+            }
+
+            outliers.extend(clipped.iter().map(|(i, _)| *i));
+            remaining = kept;
+            if remaining.len() < 3 {
+                break;
+            }
+        }
+
+        outliers
+    }
+
+    fn name(&self) -> &'static str {
+        "Sigma-clip"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sigma_clip_no_outliers() {
+        let excluder = SigmaClipExcluder::new(3.0, 5).unwrap();
+        let values = vec![10.0, 11.0, 10.5, 10.2, 10.8];
+
+        assert!(excluder.find_outliers(&values).is_empty());
+    }
+
+    #[test]
+    fn test_sigma_clip_single_outlier() {
+        let excluder = SigmaClipExcluder::new(2.0, 5).unwrap();
+        let values = vec![10.0, 10.1, 10.2, 10.3, 10.4, 10.5, 10.6, 10.7, 500.0];
+
+        assert_eq!(excluder.find_outliers(&values), vec![8]);
+    }
+
+    #[test]
+    fn test_sigma_clip_iterates() {
+        // 60.0 only stands out once 1000.0 no longer inflates sigma
+        let mut values = vec![10.0; 20];
+        for (i, v) in values.iter_mut().enumerate() {
+            *v += (i % 5) as f64 * 0.1;
+        }
+        values.push(60.0);
+        values.push(1000.0);
+
+        let once = SigmaClipExcluder::new(3.0, 1).unwrap();
+        assert_eq!(once.find_outliers(&values), vec![21]);
+
+        let iterated = SigmaClipExcluder::new(3.0, 5).unwrap();
+        let mut outliers = iterated.find_outliers(&values);
+        outliers.sort_unstable();
+        assert_eq!(outliers, vec![20, 21]);
+    }
+
+    #[test]
+    fn test_sigma_clip_small_and_identical() {
+        let excluder = SigmaClipExcluder::new(3.0, 5).unwrap();
+        assert!(excluder.find_outliers(&[10.0, 100.0]).is_empty());
+        assert!(excluder.find_outliers(&[10.0; 5]).is_empty());
+    }
+
+    #[test]
+    fn test_sigma_clip_keeps_two_values() {
+        let excluder = SigmaClipExcluder::new(0.1, 10).unwrap();
+        let values = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+
+        let outliers = excluder.find_outliers(&values);
+        assert!(values.len() - outliers.len() >= 2);
+        assert_eq!(
+            excluder.filter(&values).len(),
+            values.len() - outliers.len()
+        );
+    }
+
+    #[test]
+    fn test_sigma_clip_rejects_invalid_parameters() {
+        for k in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                matches!(
+                    SigmaClipExcluder::new(k, 5),
+                    Err(SpectrometerError::Config(_))
+                ),
+                "k {k} should be rejected"
+            );
+        }
+        assert!(SigmaClipExcluder::new(3.0, 0).is_err());
+    }
+}
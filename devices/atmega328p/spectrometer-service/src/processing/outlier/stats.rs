@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use crate::protocol::{OutlierExclusion, SeriesExclusion};
+
+/// Number of recent cycles the short-term exclusion rate is taken over
+pub const RECENT_EXCLUSION_CYCLES: usize = 100;
+
+/// Running totals for one series
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeriesTotals {
+    /// Raw values seen
+    pub values: u64,
+    /// Values dropped as outliers
+    pub excluded: u64,
+}
+
+impl SeriesTotals {
+    fn add(&mut self, series: SeriesExclusion) {
+        self.values += series.total as u64;
+        self.excluded += series.excluded as u64;
+    }
+
+    /// Fraction of values excluded, `None` before any value
+    pub fn rate(&self) -> Option<f64> {
+        (self.values > 0).then(|| self.excluded as f64 / self.values as f64)
+    }
+}
+
+/// Outlier exclusion counts since startup, plus a rate over the last
+/// [`RECENT_EXCLUSION_CYCLES`] cycles. A jump in the recent rate is an early
+/// sign of a failing lamp or loose fiber.
+#[derive(Debug, Clone, Default)]
+pub struct ExclusionStats {
+    cycles: u64,
+    cycles_with_exclusions: u64,
+    dark: SeriesTotals,
+    full: SeriesTotals,
+    sample: SeriesTotals,
+    /// (values, excluded) of the most recent cycles, oldest first
+    recent: VecDeque<(usize, usize)>,
+}
+
+impl ExclusionStats {
+    /// Add a cycle's exclusion result
+    pub fn record(&mut self, exclusion: &OutlierExclusion) {
+        self.cycles += 1;
+        if exclusion.excluded() > 0 {
+            self.cycles_with_exclusions += 1;
+        }
+        self.dark.add(exclusion.dark);
+        self.full.add(exclusion.full);
+        self.sample.add(exclusion.sample);
+
+        let values = exclusion.series().iter().map(|(_, s)| s.total).sum();
+        self.recent.push_back((values, exclusion.excluded()));
+        if self.recent.len() > RECENT_EXCLUSION_CYCLES {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Cycles recorded
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Cycles in which at least one value was excluded
+    pub fn cycles_with_exclusions(&self) -> u64 {
+        self.cycles_with_exclusions
+    }
+
+    pub fn dark(&self) -> SeriesTotals {
+        self.dark
+    }
+
+    pub fn full(&self) -> SeriesTotals {
+        self.full
+    }
+
+    pub fn sample(&self) -> SeriesTotals {
+        self.sample
+    }
+
+    /// Cycles the recent rate is taken over
+    pub fn recent_cycles(&self) -> usize {
+        self.recent.len()
+    }
+
+    /// Fraction of values excluded over the recent cycles
+    pub fn recent_rate(&self) -> Option<f64> {
+        let (values, excluded) = self
+            .recent
+            .iter()
+            .fold((0, 0), |(v, e), (values, excluded)| {
+                (v + values, e + excluded)
+            });
+        (values > 0).then(|| excluded as f64 / values as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclusion(excluded: usize) -> OutlierExclusion {
+        let series = |excluded| SeriesExclusion {
+            total: 10,
+            excluded,
+        };
+        OutlierExclusion {
+            dark: series(excluded),
+            full: series(0),
+            sample: series(0),
+        }
+    }
+
+    #[test]
+    fn test_totals() {
+        let mut stats = ExclusionStats::default();
+        assert_eq!(stats.recent_rate(), None);
+        assert_eq!(stats.dark().rate(), None);
+
+        stats.record(&exclusion(0));
+        stats.record(&exclusion(2));
+
+        assert_eq!(stats.cycles(), 2);
+        assert_eq!(stats.cycles_with_exclusions(), 1);
+        assert_eq!(
+            stats.dark(),
+            SeriesTotals {
+                values: 20,
+                excluded: 2
+            }
+        );
+        assert_eq!(stats.dark().rate(), Some(0.1));
+        assert_eq!(stats.full().rate(), Some(0.0));
+        assert!((stats.recent_rate().unwrap() - 2.0 / 60.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_recent_window_follows_jump() {
+        let mut stats = ExclusionStats::default();
+        for _ in 0..RECENT_EXCLUSION_CYCLES {
+            stats.record(&exclusion(0));
+        }
+        for _ in 0..RECENT_EXCLUSION_CYCLES {
+            stats.record(&exclusion(3));
+        }
+
+        assert_eq!(stats.recent_cycles(), RECENT_EXCLUSION_CYCLES);
+        assert!((stats.recent_rate().unwrap() - 0.1).abs() < 1e-12);
+        // Totals keep the whole history
+        assert_eq!(stats.dark().rate(), Some(0.15));
+    }
+}
@@ -0,0 +1,296 @@
+pub mod stages;
+
+use serde::{Deserialize, Serialize};
+
+use crate::processing::dark::SharedRollingDark;
+use crate::processing::outlier::SharedExcluder;
+use crate::processing::references::StoredReference;
+use crate::processing::smoothing::SmootherKind;
+use crate::protocol::{MeasurementCycle, ProcessedMeasurement};
+use crate::service::calibration::ProcessingSettings;
+
+/// One cycle on its way through the pipeline
+pub struct CycleContext<'a> {
+    pub cycle: &'a MeasurementCycle,
+    /// Series values, reduced by outlier exclusion
+    pub dark: Vec<f64>,
+    pub full: Vec<f64>,
+    pub sample: Vec<f64>,
+    /// Levels to calibrate against with `processing.references.mode =
+    /// "stored"`; `None` when none is stored for the current gain
+    pub reference: Option<StoredReference>,
+    /// Result so far; each stage fills in its part
+    pub measurement: ProcessedMeasurement,
+}
+
+impl<'a> CycleContext<'a> {
+    pub fn new(cycle: &'a MeasurementCycle) -> Self {
+        Self {
+            cycle,
+            dark: cycle.dark().to_f64(),
+            full: cycle.full().to_f64(),
+            sample: cycle.sample().to_f64(),
+            reference: None,
+            measurement: ProcessedMeasurement {
+                cycle_id: cycle.id,
+                ..ProcessedMeasurement::new(cycle.timestamp, 0.0, 0.0, 0.0, 0.0)
+            },
+        }
+    }
+}
+
+/// Trait for one step of cycle processing
+pub trait ProcessingStage: Send + Sync {
+    /// Apply the stage with the current runtime settings
+    fn process(&self, ctx: &mut CycleContext, settings: &ProcessingSettings);
+
+    /// Forget state carried across cycles, e.g. when the acquisition
+    /// settings change
+    fn reset(&self) {}
+
+    /// Name of the stage for logging/debugging
+    fn name(&self) -> &'static str;
+}
+
+/// Stages that can be listed in `processing.pipeline`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageKind {
+    /// Drop outliers from each series
+    Outlier,
+    /// Mean of each series
+    Aggregation,
+    /// Rolling dark estimate, applied when enabled
+    DarkCompensation,
+    /// Calibrated reading from the means
+    Calibration,
+    /// Range and relationship checks
+    Validation,
+    /// Smoothed reading and derivative
+    Smoothing,
+    /// Per-series spread and reading uncertainty
+    Statistics,
+}
+
+/// outlier → aggregation → dark compensation → calibration → validation →
+/// smoothing → statistics
+pub const DEFAULT_PIPELINE: &[StageKind] = &[
+    StageKind::Outlier,
+    StageKind::Aggregation,
+    StageKind::DarkCompensation,
+    StageKind::Calibration,
+    StageKind::Validation,
+    StageKind::Smoothing,
+    StageKind::Statistics,
+];
+
+impl StageKind {
+    /// Stages that must run earlier in the chain
+    fn requires(self) -> &'static [StageKind] {
+        match self {
+            StageKind::Outlier | StageKind::Aggregation => &[],
+            StageKind::DarkCompensation
+            | StageKind::Calibration
+            | StageKind::Validation
+            | StageKind::Statistics => &[StageKind::Aggregation],
+            StageKind::Smoothing => &[StageKind::Calibration],
+        }
+    }
+}
+
+/// Check a stage chain: aggregation and calibration present, no stage
+/// twice, dependencies first, and outlier exclusion before aggregation
+pub fn validate_pipeline(stages: &[StageKind]) -> Result<(), String> {
+    for required in [StageKind::Aggregation, StageKind::Calibration] {
+        if !stages.contains(&required) {
+            return Err(format!("{required:?} stage is required"));
+        }
+    }
+
+    for (position, stage) in stages.iter().enumerate() {
+        let earlier = &stages[..position];
+        if earlier.contains(stage) {
+            return Err(format!("{stage:?} stage is listed twice"));
+        }
+        if let Some(missing) = stage.requires().iter().find(|r| !earlier.contains(r)) {
+            return Err(format!("{stage:?} stage must come after {missing:?}"));
+        }
+        if *stage == StageKind::Outlier && earlier.contains(&StageKind::Aggregation) {
+            return Err("Outlier stage must come before Aggregation".to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Shared state the stages are built with
+#[derive(Clone)]
+pub struct StageResources {
+    pub outlier_excluder: SharedExcluder,
+    pub rolling_dark: SharedRollingDark,
+    /// `--smoother`, overriding the configured smoother
+    pub smoother_override: Option<SmootherKind>,
+}
+
+/// Ordered chain of processing stages
+pub struct Pipeline {
+    kinds: Vec<StageKind>,
+    stages: Vec<Box<dyn ProcessingStage>>,
+}
+
+impl Pipeline {
+    /// Assemble the chain. `kinds` is expected to have passed
+    /// `validate_pipeline`.
+    pub fn build(kinds: &[StageKind], resources: &StageResources) -> Self {
+        let stages = kinds
+            .iter()
+            .map(|kind| stages::create(*kind, resources))
+            .collect();
+
+        Self {
+            kinds: kinds.to_vec(),
+            stages,
+        }
+    }
+
+    /// Stages this pipeline was built from
+    pub fn kinds(&self) -> &[StageKind] {
+        &self.kinds
+    }
+
+    /// Run a cycle through every stage, with the stored reference for the
+    /// current gain and wavelength
+    pub fn run(
+        &self,
+        cycle: &MeasurementCycle,
+        settings: &ProcessingSettings,
+        reference: Option<StoredReference>,
+    ) -> ProcessedMeasurement {
+        let mut ctx = CycleContext::new(cycle);
+        ctx.reference = reference;
+        for stage in &self.stages {
+            stage.process(&mut ctx, settings);
+        }
+        ctx.measurement
+    }
+
+    /// Reset every stage
+    pub fn reset(&self) {
+        for stage in &self.stages {
+            stage.reset();
+        }
+    }
+
+    /// Stage names in order, for logging
+    pub fn describe(&self) -> String {
+        self.stages
+            .iter()
+            .map(|stage| stage.name())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::processing::dark::RollingDark;
+    use crate::processing::outlier::{OutlierMethod, create_shared_excluder};
+    use crate::protocol::SeriesData;
+
+    fn resources() -> StageResources {
+        StageResources {
+            outlier_excluder: create_shared_excluder(OutlierMethod::default().create().unwrap()),
+            rolling_dark: Arc::new(Mutex::new(RollingDark::default())),
+            smoother_override: None,
+        }
+    }
+
+    fn cycle() -> MeasurementCycle {
+        MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 100, 100, 100, 100, 5000]),
+            SeriesData::new(vec![1000; 6]),
+            SeriesData::new(vec![540, 550, 560, 540, 550, 560]),
+        )
+    }
+
+    #[test]
+    fn test_default_pipeline_is_valid() {
+        assert!(validate_pipeline(DEFAULT_PIPELINE).is_ok());
+    }
+
+    #[test]
+    fn test_validate_pipeline_rejects() {
+        use StageKind::*;
+
+        assert!(validate_pipeline(&[Aggregation]).is_err());
+        assert!(validate_pipeline(&[Calibration, Aggregation]).is_err());
+        assert!(validate_pipeline(&[Aggregation, Outlier, Calibration]).is_err());
+        assert!(validate_pipeline(&[Aggregation, Calibration, Calibration]).is_err());
+        assert!(validate_pipeline(&[Aggregation, Smoothing, Calibration]).is_err());
+        assert!(validate_pipeline(&[Aggregation, Calibration]).is_ok());
+    }
+
+    #[test]
+    fn test_default_pipeline_run() {
+        let pipeline = Pipeline::build(DEFAULT_PIPELINE, &resources());
+        let cycle = cycle();
+        let measurement = pipeline.run(&cycle, &ProcessingSettings::default(), None);
+
+        assert_eq!(measurement.cycle_id, cycle.id);
+        // Grubbs drops the 5000 dark outlier
+        assert_eq!(measurement.dark_mean, 100.0);
+        assert!((measurement.calibrated_reading - 50.0).abs() < 1e-9);
+        assert!(measurement.is_valid);
+        assert_eq!(measurement.statistics.unwrap().dark.count, 5);
+        let outliers = measurement.outliers.unwrap();
+        assert_eq!(outliers.dark.total, 6);
+        assert_eq!(outliers.dark.excluded, 1);
+        assert_eq!(outliers.excluded(), 1);
+        // The series carry what was averaged
+        let series = measurement.series.unwrap();
+        assert_eq!(series.dark, vec![100.0; 5]);
+        assert_eq!(series.sample.len(), 6);
+    }
+
+    #[test]
+    fn test_minimal_pipeline_run() {
+        let pipeline = Pipeline::build(
+            &[StageKind::Aggregation, StageKind::Calibration],
+            &resources(),
+        );
+        let measurement = pipeline.run(&cycle(), &ProcessingSettings::default(), None);
+
+        // No outlier exclusion, validation or statistics
+        assert!(measurement.dark_mean > 100.0);
+        assert!(measurement.statistics.is_none());
+        assert!(measurement.outliers.is_none());
+        assert!(measurement.is_valid);
+        assert_eq!(pipeline.describe(), "aggregation -> calibration");
+    }
+
+    #[test]
+    fn test_reference_mean() {
+        let pipeline = Pipeline::build(DEFAULT_PIPELINE, &resources());
+        let settings = ProcessingSettings::default();
+        assert!(
+            pipeline
+                .run(&cycle(), &settings, None)
+                .reference_mean
+                .is_none()
+        );
+
+        let mut cycle = cycle();
+        cycle.series.insert(4, SeriesData::new(vec![900, 910]));
+        cycle.roles.reference = Some(4);
+        assert_eq!(
+            pipeline.run(&cycle, &settings, None).reference_mean,
+            Some(905.0)
+        );
+    }
+}
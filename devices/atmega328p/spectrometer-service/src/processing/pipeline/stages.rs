@@ -0,0 +1,565 @@
+use std::sync::Mutex;
+
+use super::{CycleContext, ProcessingStage, StageKind, StageResources};
+use crate::processing::calibration::{CalibrationProcessor, std_dev};
+use crate::processing::dark::SharedRollingDark;
+use crate::processing::outlier::SharedExcluder;
+use crate::processing::smoothing::{Smoother, SmootherConfig, SmootherKind};
+use crate::processing::validation::MeasurementValidator;
+use crate::protocol::{
+    FilteredSeries, MeasurementStatistics, OutlierExclusion, SeriesExclusion, SeriesStatistics,
+    ValidationCategory,
+};
+use crate::service::calibration::ProcessingSettings;
+
+/// Create the stage for `kind`
+pub fn create(kind: StageKind, resources: &StageResources) -> Box<dyn ProcessingStage> {
+    match kind {
+        StageKind::Outlier => Box::new(OutlierStage {
+            excluder: resources.outlier_excluder.clone(),
+        }),
+        StageKind::Aggregation => Box::new(AggregationStage),
+        StageKind::DarkCompensation => Box::new(DarkCompensationStage {
+            rolling_dark: resources.rolling_dark.clone(),
+        }),
+        StageKind::Calibration => Box::new(CalibrationStage),
+        StageKind::Validation => Box::new(ValidationStage),
+        StageKind::Smoothing => Box::new(SmoothingStage::new(resources.smoother_override)),
+        StageKind::Statistics => Box::new(StatisticsStage),
+    }
+}
+
+/// Drops outliers from each series with the active excluder
+pub struct OutlierStage {
+    excluder: SharedExcluder,
+}
+
+impl ProcessingStage for OutlierStage {
+    fn process(&self, ctx: &mut CycleContext, _settings: &ProcessingSettings) {
+        let excluder = self.excluder.read().unwrap().clone();
+        let filter = |values: &mut Vec<f64>| {
+            let total = values.len();
+            *values = excluder.filter(values);
+            SeriesExclusion {
+                total,
+                excluded: total - values.len(),
+            }
+        };
+
+        ctx.measurement.outliers = Some(OutlierExclusion {
+            dark: filter(&mut ctx.dark),
+            full: filter(&mut ctx.full),
+            sample: filter(&mut ctx.sample),
+        });
+    }
+
+    fn name(&self) -> &'static str {
+        "outlier"
+    }
+}
+
+/// Reduces each series to a level with the configured aggregators
+pub struct AggregationStage;
+
+impl ProcessingStage for AggregationStage {
+    fn process(&self, ctx: &mut CycleContext, settings: &ProcessingSettings) {
+        let aggregation = &settings.aggregation;
+        let [dark, full, sample] = aggregation.series_aggregators();
+        ctx.measurement.dark_mean = dark.aggregate(&ctx.dark);
+        ctx.measurement.full_mean = full.aggregate(&ctx.full);
+        ctx.measurement.sample_mean = sample.aggregate(&ctx.sample);
+        ctx.measurement.reference_mean = ctx
+            .cycle
+            .reference()
+            .filter(|reference| !reference.is_empty())
+            .map(|reference| {
+                aggregation
+                    .aggregator(aggregation.method)
+                    .aggregate(&reference.to_f64())
+            });
+        ctx.measurement.series = Some(Box::new(FilteredSeries {
+            dark: ctx.dark.clone(),
+            full: ctx.full.clone(),
+            sample: ctx.sample.clone(),
+        }));
+    }
+
+    fn name(&self) -> &'static str {
+        "aggregation"
+    }
+}
+
+/// Tracks the rolling dark estimate and, when enabled, replaces the dark
+/// mean with it
+pub struct DarkCompensationStage {
+    rolling_dark: SharedRollingDark,
+}
+
+impl ProcessingStage for DarkCompensationStage {
+    fn process(&self, ctx: &mut CycleContext, settings: &ProcessingSettings) {
+        // Out-of-range cycles would drag the estimate off; the estimate is
+        // tracked even when not applied so it is warm once enabled
+        let validator = MeasurementValidator::with_settings(settings.validation);
+        if validator.check_range(ctx.cycle).is_err() {
+            return;
+        }
+
+        let compensation = settings.dark_compensation;
+        let cycle_dark = ctx.measurement.dark_mean;
+        let estimate = self
+            .rolling_dark
+            .lock()
+            .unwrap()
+            .update(cycle_dark, compensation.smoothing);
+        if compensation.enabled {
+            ctx.measurement.dark_mean = compensation.apply(cycle_dark, estimate);
+        }
+    }
+
+    fn reset(&self) {
+        self.rolling_dark.lock().unwrap().reset();
+    }
+
+    fn name(&self) -> &'static str {
+        "dark_compensation"
+    }
+}
+
+/// Converts the means to a calibrated reading, against the stored dark and
+/// full levels when `processing.references.mode` is `stored`, and applies
+/// the nonlinearity correction
+pub struct CalibrationStage;
+
+impl ProcessingStage for CalibrationStage {
+    fn process(&self, ctx: &mut CycleContext, settings: &ProcessingSettings) {
+        if settings.references.stored() {
+            let Some(reference) = ctx.reference else {
+                ctx.measurement = ctx.measurement.clone().with_categorized_error(
+                    ValidationCategory::NoReference,
+                    "No stored reference for the current gain".to_string(),
+                );
+                return;
+            };
+            ctx.measurement.dark_mean = reference.dark;
+            ctx.measurement.full_mean = reference.full;
+        }
+
+        let m = &mut ctx.measurement;
+        m.calibrated_reading = CalibrationProcessor::with_correction(
+            settings.linearity.correction(),
+        )
+        .calculate(m.dark_mean, m.full_mean, m.sample_mean);
+    }
+
+    fn name(&self) -> &'static str {
+        "calibration"
+    }
+}
+
+/// Checks raw values against the ADC range and the number of values left
+/// after outlier exclusion, then the relationship between the means
+pub struct ValidationStage;
+
+impl ProcessingStage for ValidationStage {
+    fn process(&self, ctx: &mut CycleContext, settings: &ProcessingSettings) {
+        // Already failed, e.g. for lack of a stored reference
+        if !ctx.measurement.is_valid {
+            return;
+        }
+        let validator = MeasurementValidator::with_settings(settings.validation);
+        let m = &ctx.measurement;
+
+        let result = match validator.check_range(ctx.cycle).and_then(|()| {
+            validator.check_sample_count([
+                ("dark", ctx.dark.len(), ctx.cycle.dark().len()),
+                ("full", ctx.full.len(), ctx.cycle.full().len()),
+                ("sample", ctx.sample.len(), ctx.cycle.sample().len()),
+            ])
+        }) {
+            Err((category, e)) => Some(m.clone().with_categorized_error(category, e)),
+            Ok(()) => validator
+                .check(m.dark_mean, m.full_mean, m.sample_mean)
+                .err()
+                .map(|e| m.clone().with_error(e)),
+        };
+        if let Some(invalid) = result {
+            ctx.measurement = invalid;
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "validation"
+    }
+}
+
+/// Smoother in use and the config it was built from
+struct ActiveSmoother {
+    config: Option<SmootherConfig>,
+    smoother: Option<Box<dyn Smoother>>,
+}
+
+/// Adds the smoothed reading and derivative. Only valid readings reach the
+/// smoother, so a rejected cycle does not distort the curve.
+pub struct SmoothingStage {
+    /// `--smoother`, overriding the configured smoother
+    smoother_override: Option<SmootherKind>,
+    /// Rebuilt when the smoother config changes
+    active: Mutex<ActiveSmoother>,
+}
+
+impl SmoothingStage {
+    pub fn new(smoother_override: Option<SmootherKind>) -> Self {
+        Self {
+            smoother_override,
+            active: Mutex::new(ActiveSmoother {
+                config: None,
+                smoother: None,
+            }),
+        }
+    }
+}
+
+impl ProcessingStage for SmoothingStage {
+    fn process(&self, ctx: &mut CycleContext, settings: &ProcessingSettings) {
+        let config = settings.smoother_config(self.smoother_override);
+        let mut active = self.active.lock().unwrap();
+        if active.config != Some(config) {
+            match config.create() {
+                Ok(smoother) => {
+                    tracing::info!("Smoothing with {}", smoother.name());
+                    active.smoother = Some(smoother);
+                }
+                Err(e) => tracing::warn!("Keeping previous smoother: {e}"),
+            }
+            active.config = Some(config);
+        }
+
+        let m = &mut ctx.measurement;
+        if m.is_valid
+            && let Some(smoother) = &mut active.smoother
+        {
+            m.smoothed = smoother.push(m.timestamp, m.calibrated_reading);
+        }
+    }
+
+    fn reset(&self) {
+        if let Some(smoother) = &mut self.active.lock().unwrap().smoother {
+            smoother.reset();
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "smoothing"
+    }
+}
+
+/// Adds per-series spread, the propagated reading uncertainty and the
+/// signal-to-noise ratio, flagging readings below `validation.min_snr`
+pub struct StatisticsStage;
+
+impl ProcessingStage for StatisticsStage {
+    fn process(&self, ctx: &mut CycleContext, settings: &ProcessingSettings) {
+        let processor = CalibrationProcessor::with_correction(settings.linearity.correction());
+        let snr = processor.signal_to_noise(&ctx.dark, &ctx.full);
+        let statistics = MeasurementStatistics {
+            dark: series_statistics(&ctx.dark),
+            full: series_statistics(&ctx.full),
+            sample: series_statistics(&ctx.sample),
+            uncertainty: processor.uncertainty(&ctx.dark, &ctx.full, &ctx.sample),
+            snr,
+        };
+        ctx.measurement.statistics = Some(statistics);
+        ctx.measurement.low_snr = settings
+            .validation
+            .min_snr
+            .zip(snr)
+            .is_some_and(|(min_snr, snr)| snr < min_snr);
+    }
+
+    fn name(&self) -> &'static str {
+        "statistics"
+    }
+}
+
+/// Spread and size of a series after outlier removal
+fn series_statistics(values: &[f64]) -> SeriesStatistics {
+    SeriesStatistics {
+        std_dev: std_dev(values),
+        count: values.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::processing::aggregation::{AggregationMethod, AggregationSettings};
+    use crate::processing::dark::{DarkCompensationSettings, RollingDark};
+    use crate::processing::references::{ReferenceMode, ReferenceSettings, StoredReference};
+    use crate::processing::smoothing::SavitzkyGolaySettings;
+    use crate::processing::validation::ValidationSettings;
+    use crate::protocol::{MeasurementCycle, ProcessedMeasurement, SeriesData, ValidationCategory};
+
+    fn cycle(dark: u32, sample: u32) -> MeasurementCycle {
+        MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![dark; 3]),
+            SeriesData::new(vec![1100; 3]),
+            SeriesData::new(vec![sample; 3]),
+        )
+    }
+
+    fn aggregated(cycle: &MeasurementCycle) -> CycleContext<'_> {
+        let mut ctx = CycleContext::new(cycle);
+        AggregationStage.process(&mut ctx, &ProcessingSettings::default());
+        ctx
+    }
+
+    #[test]
+    fn test_dark_compensation_stage() {
+        let rolling_dark: SharedRollingDark = Arc::new(Mutex::new(RollingDark::default()));
+        let stage = DarkCompensationStage {
+            rolling_dark: rolling_dark.clone(),
+        };
+        let settings = ProcessingSettings {
+            dark_compensation: DarkCompensationSettings {
+                enabled: true,
+                smoothing: 0.5,
+                blend: 1.0,
+            },
+            ..Default::default()
+        };
+
+        let first = cycle(100, 600);
+        let mut ctx = aggregated(&first);
+        stage.process(&mut ctx, &settings);
+        assert_eq!(ctx.measurement.dark_mean, 100.0);
+
+        // Estimate moves halfway towards the new dark level
+        let second = cycle(200, 600);
+        let mut ctx = aggregated(&second);
+        stage.process(&mut ctx, &settings);
+        assert_eq!(ctx.measurement.dark_mean, 150.0);
+
+        // Disabled: per-cycle dark is kept, estimate keeps tracking
+        let mut ctx = aggregated(&second);
+        stage.process(&mut ctx, &ProcessingSettings::default());
+        assert_eq!(ctx.measurement.dark_mean, 200.0);
+        assert_eq!(rolling_dark.lock().unwrap().cycles(), 3);
+
+        stage.reset();
+        assert_eq!(rolling_dark.lock().unwrap().estimate(), None);
+    }
+
+    #[test]
+    fn test_aggregation_stage_uses_configured_methods() {
+        let cycle = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 100, 400]),
+            SeriesData::new(vec![1100, 1100, 1400]),
+            SeriesData::new(vec![600, 600, 900]),
+        );
+        let settings = ProcessingSettings {
+            aggregation: AggregationSettings {
+                method: AggregationMethod::Median,
+                sample: Some(AggregationMethod::Mean),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut ctx = CycleContext::new(&cycle);
+        AggregationStage.process(&mut ctx, &settings);
+        assert_eq!(ctx.measurement.dark_mean, 100.0);
+        assert_eq!(ctx.measurement.full_mean, 1100.0);
+        assert_eq!(ctx.measurement.sample_mean, 700.0);
+    }
+
+    #[test]
+    fn test_dark_compensation_skips_out_of_range_cycles() {
+        let rolling_dark: SharedRollingDark = Arc::new(Mutex::new(RollingDark::default()));
+        let stage = DarkCompensationStage {
+            rolling_dark: rolling_dark.clone(),
+        };
+        let saturated = cycle(16_777_215, 600);
+        let mut ctx = aggregated(&saturated);
+        stage.process(&mut ctx, &ProcessingSettings::default());
+        assert_eq!(rolling_dark.lock().unwrap().estimate(), None);
+    }
+
+    #[test]
+    fn test_calibration_stage_with_stored_reference() {
+        let settings = ProcessingSettings {
+            references: ReferenceSettings {
+                mode: ReferenceMode::Stored,
+                file: Some("references.toml".into()),
+            },
+            ..Default::default()
+        };
+        // Sample-only cycle
+        let source = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(Vec::new()),
+            SeriesData::new(Vec::new()),
+            SeriesData::new(vec![550, 550, 550]),
+        );
+
+        let mut ctx = aggregated(&source);
+        ctx.reference = Some(StoredReference {
+            dark: 100.0,
+            full: 1000.0,
+        });
+        CalibrationStage.process(&mut ctx, &settings);
+        assert_eq!(ctx.measurement.dark_mean, 100.0);
+        assert_eq!(ctx.measurement.calibrated_reading, 50.0);
+        ValidationStage.process(&mut ctx, &settings);
+        assert!(ctx.measurement.is_valid);
+
+        let mut ctx = aggregated(&source);
+        CalibrationStage.process(&mut ctx, &settings);
+        ValidationStage.process(&mut ctx, &settings);
+        assert_eq!(
+            ctx.measurement.validation_category,
+            Some(ValidationCategory::NoReference)
+        );
+    }
+
+    #[test]
+    fn test_validation_stage() {
+        let bright = cycle(100, 1500);
+        let mut ctx = aggregated(&bright);
+        ValidationStage.process(&mut ctx, &ProcessingSettings::default());
+        assert!(!ctx.measurement.is_valid);
+
+        // Only one of three sample values left after outlier exclusion
+        let source = cycle(100, 600);
+        let mut ctx = aggregated(&source);
+        ctx.sample.truncate(1);
+        ValidationStage.process(&mut ctx, &ProcessingSettings::default());
+        assert!(!ctx.measurement.is_valid);
+        assert_eq!(
+            ctx.measurement.validation_category,
+            Some(ValidationCategory::InsufficientSamples)
+        );
+    }
+
+    #[test]
+    fn test_smoothing_valid_readings_only() {
+        let stage = SmoothingStage::new(None);
+        let settings = ProcessingSettings {
+            savitzky_golay: SavitzkyGolaySettings {
+                window: 3,
+                order: 1,
+            },
+            ..Default::default()
+        };
+        let t0 = Utc::now();
+        let source = cycle(100, 600);
+        let smooth = |measurement: ProcessedMeasurement| {
+            let mut ctx = CycleContext::new(&source);
+            ctx.measurement = measurement;
+            stage.process(&mut ctx, &settings);
+            ctx.measurement.smoothed
+        };
+        let reading = |i: i64, value: f64| {
+            ProcessedMeasurement::new(t0 + Duration::seconds(i), 100.0, 1000.0, 550.0, value)
+        };
+
+        assert!(smooth(reading(0, 10.0)).is_none());
+        assert!(smooth(reading(1, 11.0)).is_none());
+        assert!(smooth(reading(1, 90.0).with_error("bad".to_string())).is_none());
+
+        // 1 %/s line through the valid readings
+        let smoothed = smooth(reading(2, 12.0)).unwrap();
+        assert!((smoothed.value - 12.0).abs() < 1e-9);
+        assert!((smoothed.derivative - 1.0).abs() < 1e-9);
+
+        stage.reset();
+        assert!(smooth(reading(3, 13.0)).is_none());
+    }
+
+    #[test]
+    fn test_smoothing_override_and_switch() {
+        let stage = SmoothingStage::new(Some(SmootherKind::Kalman));
+        let settings = ProcessingSettings {
+            smoother: Some(SmootherKind::Ema),
+            ..Default::default()
+        };
+        let source = cycle(100, 600);
+        let t0 = Utc::now();
+        let smooth = |stage: &SmoothingStage, i: i64| {
+            let mut ctx = CycleContext::new(&source);
+            ctx.measurement =
+                ProcessedMeasurement::new(t0 + Duration::seconds(i), 100.0, 1000.0, 550.0, 50.0);
+            stage.process(&mut ctx, &settings);
+            ctx.measurement.smoothed
+        };
+
+        assert!(smooth(&stage, 0).is_none());
+        assert!(smooth(&stage, 1).is_some());
+        let active = stage.active.lock().unwrap();
+        assert_eq!(active.smoother.as_ref().unwrap().name(), "Kalman");
+        drop(active);
+
+        // Without the override the configured EMA is used
+        let stage = SmoothingStage::new(None);
+        smooth(&stage, 0);
+        let active = stage.active.lock().unwrap();
+        assert_eq!(active.smoother.as_ref().unwrap().name(), "EMA");
+    }
+
+    #[test]
+    fn test_statistics_stage() {
+        let source = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 100, 100]),
+            SeriesData::new(vec![1000, 1000, 1000]),
+            SeriesData::new(vec![540, 550, 560]),
+        );
+        let mut ctx = aggregated(&source);
+        StatisticsStage.process(&mut ctx, &ProcessingSettings::default());
+
+        let statistics = ctx.measurement.statistics.unwrap();
+        assert_eq!(statistics.sample.count, 3);
+        assert!((statistics.sample.std_dev - 10.0).abs() < 1e-9);
+        assert_eq!(statistics.dark.std_dev, 0.0);
+        // 100 * (10 / sqrt(3)) / 900
+        let uncertainty = statistics.uncertainty.unwrap();
+        assert!((uncertainty - 100.0 * 10.0 / 3f64.sqrt() / 900.0).abs() < 1e-9);
+        // Dark and full without spread: no noise estimate
+        assert_eq!(statistics.snr, None);
+        assert!(!ctx.measurement.low_snr);
+    }
+
+    #[test]
+    fn test_statistics_stage_flags_low_snr() {
+        // Span 900, σ_dark = σ_full = 10 -> SNR 900 / √200 ≈ 63.6
+        let source = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![90, 100, 110]),
+            SeriesData::new(vec![990, 1000, 1010]),
+            SeriesData::new(vec![550, 550, 550]),
+        );
+        let settings = |min_snr| ProcessingSettings {
+            validation: ValidationSettings {
+                min_snr: Some(min_snr),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut ctx = aggregated(&source);
+        StatisticsStage.process(&mut ctx, &settings(100.0));
+        let snr = ctx.measurement.statistics.unwrap().snr.unwrap();
+        assert!((snr - 900.0 / 200f64.sqrt()).abs() < 1e-9);
+        assert!(ctx.measurement.low_snr);
+        assert!(ctx.measurement.is_valid);
+
+        let mut ctx = aggregated(&source);
+        StatisticsStage.process(&mut ctx, &settings(50.0));
+        assert!(!ctx.measurement.low_snr);
+    }
+}
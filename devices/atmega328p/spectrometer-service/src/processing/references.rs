@@ -0,0 +1,281 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpectrometerError;
+
+/// Where calibration takes the dark and full levels from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReferenceMode {
+    /// Each cycle's own dark and full series (default)
+    #[default]
+    Cycle,
+    /// Levels stored in the reference file, for the current gain and
+    /// control wavelength; cycles need only a sample series
+    Stored,
+}
+
+/// `[processing.references]` config section
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceSettings {
+    #[serde(default)]
+    pub mode: ReferenceMode,
+    /// Reference file, read at startup and on reload and written by
+    /// `POST /processing/references/capture`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+}
+
+impl ReferenceSettings {
+    /// Check stored references have a file to come from
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mode == ReferenceMode::Stored && self.file.is_none() {
+            return Err("mode \"stored\" requires file".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether calibration uses stored levels
+    pub fn stored(&self) -> bool {
+        self.mode == ReferenceMode::Stored
+    }
+}
+
+/// Dark and full levels calibration uses instead of the cycle's own
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StoredReference {
+    pub dark: f64,
+    pub full: f64,
+}
+
+/// Levels measured at one gain and wavelength. An entry without `gain`
+/// applies at any gain without an entry of its own, one without
+/// `wavelength` at any wavelength.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gain: Option<u8>,
+    /// Control wavelength in nm
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wavelength: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dark: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full: Option<f64>,
+    /// When the levels were last captured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub captured_at: Option<DateTime<Utc>>,
+}
+
+impl ReferenceEntry {
+    fn levels(&self) -> Option<StoredReference> {
+        Some(StoredReference {
+            dark: self.dark?,
+            full: self.full?,
+        })
+    }
+}
+
+/// Contents of the reference file: `[[reference]]` tables
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceTable {
+    #[serde(default, rename = "reference")]
+    pub entries: Vec<ReferenceEntry>,
+}
+
+impl ReferenceTable {
+    /// Read the table from `path`; a missing file is an empty table
+    pub fn load(path: &Path) -> Result<Self, SpectrometerError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| {
+            SpectrometerError::Config(format!("Failed to parse reference file {path:?}: {e}"))
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SpectrometerError> {
+        let contents = toml::to_string_pretty(self)
+            .map_err(|e| SpectrometerError::Config(format!("Serialize error: {e}")))?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Levels for `gain` at `wavelength`: from the entries of that gain, or
+    /// else those without one. Between entries with wavelengths the levels
+    /// are interpolated linearly, beyond them the nearest entry applies, and
+    /// entries without a wavelength are used when none has one. Entries
+    /// lacking the dark or full level are skipped.
+    pub fn lookup(&self, gain: u8, wavelength: f64) -> Option<StoredReference> {
+        let complete = |entry: &&ReferenceEntry| entry.levels().is_some();
+        let for_gain: Vec<&ReferenceEntry> = self
+            .entries
+            .iter()
+            .filter(complete)
+            .filter(|entry| entry.gain == Some(gain))
+            .collect();
+        let candidates = if for_gain.is_empty() {
+            self.entries
+                .iter()
+                .filter(complete)
+                .filter(|entry| entry.gain.is_none())
+                .collect()
+        } else {
+            for_gain
+        };
+
+        let mut curve: Vec<(f64, StoredReference)> = candidates
+            .iter()
+            .filter_map(|entry| Some((entry.wavelength?, entry.levels()?)))
+            .collect();
+        if curve.is_empty() {
+            return candidates.first().and_then(|entry| entry.levels());
+        }
+        curve.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let upper = curve.partition_point(|(at, _)| *at < wavelength);
+        if upper == 0 {
+            return Some(curve[0].1);
+        }
+        if upper == curve.len() {
+            return Some(curve[upper - 1].1);
+        }
+        let ((w0, r0), (w1, r1)) = (curve[upper - 1], curve[upper]);
+        let t = (wavelength - w0) / (w1 - w0);
+        Some(StoredReference {
+            dark: r0.dark + t * (r1.dark - r0.dark),
+            full: r0.full + t * (r1.full - r0.full),
+        })
+    }
+
+    /// Store captured levels for `gain` and `wavelength`, replacing only the
+    /// levels given in the entry for exactly those, or adding one
+    pub fn record(
+        &mut self,
+        gain: u8,
+        wavelength: Option<f64>,
+        dark: Option<f64>,
+        full: Option<f64>,
+        at: DateTime<Utc>,
+    ) -> &ReferenceEntry {
+        let position = self
+            .entries
+            .iter()
+            .position(|entry| entry.gain == Some(gain) && entry.wavelength == wavelength)
+            .unwrap_or_else(|| {
+                self.entries.push(ReferenceEntry {
+                    gain: Some(gain),
+                    wavelength,
+                    dark: None,
+                    full: None,
+                    captured_at: None,
+                });
+                self.entries.len() - 1
+            });
+
+        let entry = &mut self.entries[position];
+        entry.dark = dark.or(entry.dark);
+        entry.full = full.or(entry.full);
+        entry.captured_at = Some(at);
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(gain: Option<u8>, wavelength: Option<f64>, dark: f64, full: f64) -> ReferenceEntry {
+        ReferenceEntry {
+            gain,
+            wavelength,
+            dark: Some(dark),
+            full: Some(full),
+            captured_at: None,
+        }
+    }
+
+    fn levels(dark: f64, full: f64) -> Option<StoredReference> {
+        Some(StoredReference { dark, full })
+    }
+
+    #[test]
+    fn test_lookup_by_gain() {
+        let table = ReferenceTable {
+            entries: vec![
+                entry(Some(2), None, 14e6, 300.0),
+                entry(Some(4), None, 12e6, 200.0),
+                entry(None, None, 10e6, 100.0),
+            ],
+        };
+        assert_eq!(table.lookup(4, 550.0), levels(12e6, 200.0));
+        // No entry of its own: the gain-less one applies
+        assert_eq!(table.lookup(8, 550.0), levels(10e6, 100.0));
+        assert_eq!(ReferenceTable::default().lookup(2, 550.0), None);
+    }
+
+    #[test]
+    fn test_lookup_interpolates_wavelength() {
+        let table = ReferenceTable {
+            entries: vec![
+                entry(Some(2), Some(600.0), 14e6, 500.0),
+                entry(Some(2), Some(500.0), 14e6, 300.0),
+            ],
+        };
+        assert_eq!(table.lookup(2, 550.0), levels(14e6, 400.0));
+        assert_eq!(table.lookup(2, 500.0), levels(14e6, 300.0));
+        // Nearest entry beyond the curve
+        assert_eq!(table.lookup(2, 400.0), levels(14e6, 300.0));
+        assert_eq!(table.lookup(2, 700.0), levels(14e6, 500.0));
+    }
+
+    #[test]
+    fn test_lookup_skips_incomplete_entries() {
+        let mut table = ReferenceTable::default();
+        table.record(2, None, Some(14e6), None, Utc::now());
+        assert_eq!(table.lookup(2, 550.0), None);
+
+        table.record(2, None, None, Some(300.0), Utc::now());
+        assert_eq!(table.entries.len(), 1);
+        assert_eq!(table.lookup(2, 550.0), levels(14e6, 300.0));
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("references.toml");
+        assert_eq!(
+            ReferenceTable::load(&path).unwrap(),
+            ReferenceTable::default()
+        );
+
+        let mut table = ReferenceTable::default();
+        table.record(2, Some(550.0), Some(14e6), Some(300.0), Utc::now());
+        table.save(&path).unwrap();
+        assert_eq!(ReferenceTable::load(&path).unwrap(), table);
+
+        let table: ReferenceTable = toml::from_str(
+            r#"
+[[reference]]
+gain = 4
+dark = 12000000.0
+full = 250.0
+"#,
+        )
+        .unwrap();
+        assert_eq!(table.lookup(4, 550.0), levels(12e6, 250.0));
+    }
+
+    #[test]
+    fn test_stored_mode_requires_file() {
+        let settings = ReferenceSettings {
+            mode: ReferenceMode::Stored,
+            file: None,
+        };
+        assert!(settings.validate().is_err());
+        assert!(ReferenceSettings::default().validate().is_ok());
+    }
+}
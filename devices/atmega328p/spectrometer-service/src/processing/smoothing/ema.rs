@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Smoother, seconds_between};
+use crate::protocol::SmoothedReading;
+
+/// Default weight of the newest reading
+pub const DEFAULT_EMA_ALPHA: f64 = 0.2;
+
+/// EMA parameters, from the `[processing.ema]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmaSettings {
+    /// Weight of the newest reading in (0, 1]
+    pub alpha: f64,
+}
+
+impl Default for EmaSettings {
+    fn default() -> Self {
+        Self {
+            alpha: DEFAULT_EMA_ALPHA,
+        }
+    }
+}
+
+impl EmaSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.alpha > 0.0 && self.alpha <= 1.0) {
+            return Err(format!("alpha must be in (0, 1], got {}", self.alpha));
+        }
+        Ok(())
+    }
+}
+
+/// Exponential moving average of the reading. The derivative is the change
+/// of the average between consecutive readings.
+#[derive(Debug, Clone)]
+pub struct Ema {
+    alpha: f64,
+    last: Option<(DateTime<Utc>, f64)>,
+}
+
+impl Ema {
+    pub fn new(settings: EmaSettings) -> Result<Self, String> {
+        settings.validate()?;
+        Ok(Self {
+            alpha: settings.alpha,
+            last: None,
+        })
+    }
+}
+
+impl Smoother for Ema {
+    fn push(&mut self, timestamp: DateTime<Utc>, reading: f64) -> Option<SmoothedReading> {
+        let Some((previous_time, previous)) = self.last else {
+            self.last = Some((timestamp, reading));
+            return None;
+        };
+
+        let value = previous + self.alpha * (reading - previous);
+        self.last = Some((timestamp, value));
+
+        let dt = seconds_between(previous_time, timestamp);
+        (dt > 0.0).then(|| SmoothedReading {
+            value,
+            derivative: (value - previous) / dt,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+
+    fn name(&self) -> &'static str {
+        "EMA"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_ema() {
+        let mut ema = Ema::new(EmaSettings { alpha: 0.5 }).unwrap();
+        let t0 = Utc::now();
+
+        assert!(ema.push(t0, 10.0).is_none());
+        let smoothed = ema.push(t0 + Duration::seconds(2), 20.0).unwrap();
+        assert_relative_eq!(smoothed.value, 15.0);
+        assert_relative_eq!(smoothed.derivative, 2.5);
+
+        ema.reset();
+        assert!(ema.push(t0 + Duration::seconds(3), 20.0).is_none());
+    }
+
+    #[test]
+    fn test_rejects_invalid_alpha() {
+        assert!(Ema::new(EmaSettings { alpha: 0.0 }).is_err());
+        assert!(Ema::new(EmaSettings { alpha: 1.5 }).is_err());
+        assert!(Ema::new(EmaSettings { alpha: 1.0 }).is_ok());
+    }
+}
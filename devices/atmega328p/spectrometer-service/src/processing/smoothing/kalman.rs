@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Smoother, seconds_between};
+use crate::protocol::SmoothedReading;
+
+/// Default process noise density, (%/s)² per second
+pub const DEFAULT_PROCESS_NOISE: f64 = 0.01;
+
+/// Default measurement noise variance, %²
+pub const DEFAULT_MEASUREMENT_NOISE: f64 = 0.25;
+
+/// Variance assumed for the unknown initial rate, (%/s)²
+const INITIAL_RATE_VARIANCE: f64 = 1e4;
+
+/// Kalman filter parameters, from the `[processing.kalman]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KalmanSettings {
+    /// How quickly the deposition rate may drift, as the spectral density of
+    /// the rate's random walk in (%/s)² per second. Larger values track rate
+    /// changes faster but smooth less.
+    pub process_noise: f64,
+    /// Variance of a single calibrated reading in %²
+    pub measurement_noise: f64,
+}
+
+impl Default for KalmanSettings {
+    fn default() -> Self {
+        Self {
+            process_noise: DEFAULT_PROCESS_NOISE,
+            measurement_noise: DEFAULT_MEASUREMENT_NOISE,
+        }
+    }
+}
+
+impl KalmanSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("process_noise", self.process_noise),
+            ("measurement_noise", self.measurement_noise),
+        ] {
+            if !(value > 0.0 && value.is_finite()) {
+                return Err(format!("{name} must be a positive number, got {value}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Filter state: reading, its rate, and their covariance
+#[derive(Debug, Clone, Copy)]
+struct Estimate {
+    timestamp: DateTime<Utc>,
+    value: f64,
+    rate: f64,
+    /// Covariance [[p00, p01], [p01, p11]]
+    p00: f64,
+    p01: f64,
+    p11: f64,
+}
+
+/// 1D Kalman filter with a constant-rate model: the reading changes
+/// linearly between cycles and the rate follows a random walk.
+#[derive(Debug, Clone)]
+pub struct Kalman {
+    settings: KalmanSettings,
+    estimate: Option<Estimate>,
+}
+
+impl Kalman {
+    pub fn new(settings: KalmanSettings) -> Result<Self, String> {
+        settings.validate()?;
+        Ok(Self {
+            settings,
+            estimate: None,
+        })
+    }
+}
+
+impl Smoother for Kalman {
+    fn push(&mut self, timestamp: DateTime<Utc>, reading: f64) -> Option<SmoothedReading> {
+        let q = self.settings.process_noise;
+        let r = self.settings.measurement_noise;
+
+        let Some(mut e) = self.estimate else {
+            self.estimate = Some(Estimate {
+                timestamp,
+                value: reading,
+                rate: 0.0,
+                p00: r,
+                p01: 0.0,
+                p11: INITIAL_RATE_VARIANCE,
+            });
+            return None;
+        };
+
+        // Predict
+        let dt = seconds_between(e.timestamp, timestamp).max(0.0);
+        e.value += e.rate * dt;
+        e.p00 += 2.0 * dt * e.p01 + dt * dt * e.p11 + q * dt.powi(3) / 3.0;
+        e.p01 += dt * e.p11 + q * dt * dt / 2.0;
+        e.p11 += q * dt;
+
+        // Update with the new reading
+        let innovation = reading - e.value;
+        let s = e.p00 + r;
+        let (k0, k1) = (e.p00 / s, e.p01 / s);
+        e.value += k0 * innovation;
+        e.rate += k1 * innovation;
+        e.p11 -= k1 * e.p01;
+        e.p01 *= 1.0 - k0;
+        e.p00 *= 1.0 - k0;
+        e.timestamp = timestamp;
+
+        self.estimate = Some(e);
+        Some(SmoothedReading {
+            value: e.value,
+            derivative: e.rate,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.estimate = None;
+    }
+
+    fn name(&self) -> &'static str {
+        "Kalman"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_tracks_ramp() {
+        let mut kalman = Kalman::new(KalmanSettings::default()).unwrap();
+        let t0 = Utc::now();
+
+        let mut last = None;
+        for i in 0..200 {
+            let noise = if i % 2 == 0 { 0.3 } else { -0.3 };
+            let reading = 20.0 + 0.5 * i as f64 * 0.1 + noise;
+            last = kalman.push(t0 + Duration::milliseconds(i * 100), reading);
+        }
+
+        let smoothed = last.unwrap();
+        assert!((smoothed.value - (20.0 + 0.5 * 19.9)).abs() < 0.3);
+        assert!((smoothed.derivative - 0.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_first_reading_gives_nothing() {
+        let mut kalman = Kalman::new(KalmanSettings::default()).unwrap();
+        let t0 = Utc::now();
+        assert!(kalman.push(t0, 50.0).is_none());
+        assert!(kalman.push(t0 + Duration::seconds(1), 50.0).is_some());
+
+        kalman.reset();
+        assert!(kalman.push(t0 + Duration::seconds(2), 50.0).is_none());
+    }
+
+    #[test]
+    fn test_rejects_invalid_noise() {
+        let settings = KalmanSettings {
+            measurement_noise: 0.0,
+            ..Default::default()
+        };
+        assert!(Kalman::new(settings).is_err());
+
+        let settings = KalmanSettings {
+            process_noise: f64::INFINITY,
+            ..Default::default()
+        };
+        assert!(Kalman::new(settings).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+pub mod ema;
+pub mod kalman;
+pub mod savgol;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::SmoothedReading;
+
+pub use ema::EmaSettings;
+pub use kalman::KalmanSettings;
+pub use savgol::SavitzkyGolaySettings;
+
+/// Trait for pluggable smoothing of the calibrated reading
+pub trait Smoother: Send {
+    /// Add a valid reading; returns the smoothed value and derivative
+    /// (per second) once the smoother has enough history
+    fn push(&mut self, timestamp: DateTime<Utc>, reading: f64) -> Option<SmoothedReading>;
+
+    /// Forget the history, e.g. when the acquisition settings change
+    fn reset(&mut self);
+
+    /// Name of the algorithm for logging/debugging
+    fn name(&self) -> &'static str;
+}
+
+/// Which smoother produces `ProcessedMeasurement::smoothed`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmootherKind {
+    /// Exponential moving average
+    Ema,
+    /// Savitzky-Golay polynomial fit over a window (default)
+    #[default]
+    SavitzkyGolay,
+    /// Constant-rate Kalman filter
+    Kalman,
+}
+
+/// Selected smoother and the parameters of every method
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmootherConfig {
+    pub kind: SmootherKind,
+    pub ema: EmaSettings,
+    pub savitzky_golay: SavitzkyGolaySettings,
+    pub kalman: KalmanSettings,
+}
+
+impl SmootherConfig {
+    /// Create the selected smoother, validating its parameters
+    pub fn create(&self) -> Result<Box<dyn Smoother>, String> {
+        match self.kind {
+            SmootherKind::Ema => Ok(Box::new(ema::Ema::new(self.ema)?)),
+            SmootherKind::SavitzkyGolay => {
+                Ok(Box::new(savgol::SavitzkyGolay::new(self.savitzky_golay)?))
+            }
+            SmootherKind::Kalman => Ok(Box::new(kalman::Kalman::new(self.kalman)?)),
+        }
+    }
+}
+
+/// Seconds between two readings
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_microseconds().unwrap_or(0) as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(kind: SmootherKind) -> SmootherConfig {
+        SmootherConfig {
+            kind,
+            ema: EmaSettings::default(),
+            savitzky_golay: SavitzkyGolaySettings::default(),
+            kalman: KalmanSettings::default(),
+        }
+    }
+
+    #[test]
+    fn test_create_each_kind() {
+        assert_eq!(config(SmootherKind::Ema).create().unwrap().name(), "EMA");
+        assert_eq!(
+            config(SmootherKind::SavitzkyGolay).create().unwrap().name(),
+            "Savitzky-Golay"
+        );
+        assert_eq!(
+            config(SmootherKind::Kalman).create().unwrap().name(),
+            "Kalman"
+        );
+    }
+
+    #[test]
+    fn test_create_validates_selected_only() {
+        let mut cfg = config(SmootherKind::Kalman);
+        cfg.ema.alpha = 0.0;
+        assert!(cfg.create().is_ok());
+
+        cfg.kind = SmootherKind::Ema;
+        assert!(cfg.create().is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Smoother, seconds_between};
+use crate::protocol::SmoothedReading;
+
+/// Default number of readings the polynomial is fitted over
+pub const DEFAULT_SAVGOL_WINDOW: usize = 11;
+
+/// Default polynomial order
+pub const DEFAULT_SAVGOL_ORDER: usize = 2;
+
+/// Largest accepted window
+pub const MAX_SAVGOL_WINDOW: usize = 101;
+
+/// Largest accepted polynomial order
+pub const MAX_SAVGOL_ORDER: usize = 5;
+
+/// Savitzky-Golay window and order, from the `[processing.savitzky_golay]`
+/// config section
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavitzkyGolaySettings {
+    /// Number of recent valid readings the polynomial is fitted over
+    pub window: usize,
+    /// Polynomial order, at least 1 so the derivative is defined
+    pub order: usize,
+}
+
+impl Default for SavitzkyGolaySettings {
+    fn default() -> Self {
+        Self {
+            window: DEFAULT_SAVGOL_WINDOW,
+            order: DEFAULT_SAVGOL_ORDER,
+        }
+    }
+}
+
+impl SavitzkyGolaySettings {
+    /// Check the window can be fitted with the requested order
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_SAVGOL_ORDER).contains(&self.order) {
+            return Err(format!(
+                "order must be between 1 and {MAX_SAVGOL_ORDER}, got {}",
+                self.order
+            ));
+        }
+        if self.window < self.order + 2 || self.window > MAX_SAVGOL_WINDOW {
+            return Err(format!(
+                "window must be between order + 2 ({}) and {MAX_SAVGOL_WINDOW}, got {}",
+                self.order + 2,
+                self.window
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Savitzky-Golay smoothing and differentiation over the most recent
+/// calibrated readings.
+///
+/// A least-squares polynomial is fitted over the window and evaluated at the
+/// newest reading, so the output is available in real time instead of half
+/// a window late. Readings are assumed evenly spaced; the derivative is
+/// scaled by the mean spacing of the window.
+#[derive(Debug, Clone)]
+pub struct SavitzkyGolay {
+    settings: SavitzkyGolaySettings,
+    value_weights: Vec<f64>,
+    derivative_weights: Vec<f64>,
+    readings: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl SavitzkyGolay {
+    pub fn new(settings: SavitzkyGolaySettings) -> Result<Self, String> {
+        settings.validate()?;
+        let (value_weights, derivative_weights) = weights(settings.window, settings.order);
+
+        Ok(Self {
+            settings,
+            value_weights,
+            derivative_weights,
+            readings: VecDeque::with_capacity(settings.window),
+        })
+    }
+}
+
+impl Smoother for SavitzkyGolay {
+    /// Returns nothing until the window is full
+    fn push(&mut self, timestamp: DateTime<Utc>, reading: f64) -> Option<SmoothedReading> {
+        if self.readings.len() == self.settings.window {
+            self.readings.pop_front();
+        }
+        self.readings.push_back((timestamp, reading));
+
+        if self.readings.len() < self.settings.window {
+            return None;
+        }
+
+        let (first, _) = self.readings.front()?;
+        let (last, _) = self.readings.back()?;
+        let span = seconds_between(*first, *last);
+        let spacing = span / (self.settings.window - 1) as f64;
+        if spacing <= 0.0 {
+            return None;
+        }
+
+        let dot = |weights: &[f64]| {
+            weights
+                .iter()
+                .zip(&self.readings)
+                .map(|(w, (_, value))| w * value)
+                .sum::<f64>()
+        };
+
+        Some(SmoothedReading {
+            value: dot(&self.value_weights),
+            derivative: dot(&self.derivative_weights) / spacing,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.readings.clear();
+    }
+
+    fn name(&self) -> &'static str {
+        "Savitzky-Golay"
+    }
+}
+
+/// Convolution weights giving the fitted value and first derivative (per
+/// sample step) at the last point of a `window`-point fit of `order`
+fn weights(window: usize, order: usize) -> (Vec<f64>, Vec<f64>) {
+    let terms = order + 1;
+    // Sample positions relative to the newest reading: -(window-1) ..= 0
+    let positions: Vec<f64> = (0..window)
+        .map(|i| i as f64 - (window - 1) as f64)
+        .collect();
+
+    // Normal equations matrix AᵀA, where A[i][j] = x_i^j
+    let mut normal = vec![vec![0.0; terms]; terms];
+    for (j, row) in normal.iter_mut().enumerate() {
+        for (k, cell) in row.iter_mut().enumerate() {
+            *cell = positions.iter().map(|x| x.powi((j + k) as i32)).sum();
+        }
+    }
+    let inverse = invert(normal);
+
+    // Coefficient j of the fit is row j of (AᵀA)⁻¹Aᵀ applied to the readings;
+    // at x = 0 the value is coefficient 0 and the slope coefficient 1
+    let row = |j: usize| -> Vec<f64> {
+        positions
+            .iter()
+            .map(|x| (0..terms).map(|k| inverse[j][k] * x.powi(k as i32)).sum())
+            .collect()
+    };
+
+    (row(0), row(1))
+}
+
+/// Gauss-Jordan inversion with partial pivoting. The normal matrix of
+/// distinct sample positions is positive definite, so a pivot always exists.
+fn invert(mut matrix: Vec<Vec<f64>>) -> Vec<Vec<f64>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&a, &b| matrix[a][col].abs().total_cmp(&matrix[b][col].abs()))
+            .unwrap_or(col);
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+
+        let scale = matrix[col][col];
+        for k in 0..n {
+            matrix[col][k] /= scale;
+            inverse[col][k] /= scale;
+        }
+
+        for row in 0..n {
+            if row == col {
+                continue;
+            }
+            let factor = matrix[row][col];
+            for k in 0..n {
+                matrix[row][k] -= factor * matrix[col][k];
+                inverse[row][k] -= factor * inverse[col][k];
+            }
+        }
+    }
+
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use chrono::Duration;
+
+    use super::*;
+
+    fn filter(window: usize, order: usize) -> SavitzkyGolay {
+        SavitzkyGolay::new(SavitzkyGolaySettings { window, order }).unwrap()
+    }
+
+    #[test]
+    fn test_weights_sum() {
+        let (value, derivative) = weights(7, 2);
+        // A constant is reproduced exactly and has zero slope
+        assert_relative_eq!(value.iter().sum::<f64>(), 1.0, epsilon = 1e-9);
+        assert_relative_eq!(derivative.iter().sum::<f64>(), 0.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_linear_fit_end_point_weights() {
+        // Straight line over 3 points, evaluated at the last: (-1, 2, 5) / 6
+        let (value, _) = weights(3, 1);
+        assert_relative_eq!(value[0], -1.0 / 6.0, epsilon = 1e-9);
+        assert_relative_eq!(value[1], 2.0 / 6.0, epsilon = 1e-9);
+        assert_relative_eq!(value[2], 5.0 / 6.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_needs_full_window() {
+        let mut sg = filter(5, 2);
+        let t0 = Utc::now();
+        for i in 0..4 {
+            assert!(sg.push(t0 + Duration::seconds(i), 1.0).is_none());
+        }
+        assert!(sg.push(t0 + Duration::seconds(4), 1.0).is_some());
+    }
+
+    #[test]
+    fn test_quadratic_reproduced_exactly() {
+        let mut sg = filter(9, 2);
+        let t0 = Utc::now();
+        let curve = |t: f64| 10.0 + 2.0 * t - 0.1 * t * t;
+
+        let mut last = None;
+        for i in 0..20 {
+            let t = i as f64 * 0.5;
+            last = sg.push(t0 + Duration::milliseconds(i * 500), curve(t));
+        }
+
+        let smoothed = last.unwrap();
+        let t = 19.0 * 0.5;
+        assert_relative_eq!(smoothed.value, curve(t), epsilon = 1e-6);
+        assert_relative_eq!(smoothed.derivative, 2.0 - 0.2 * t, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_noise_is_reduced() {
+        let mut sg = filter(11, 2);
+        let t0 = Utc::now();
+        let mut last = None;
+        for i in 0..11 {
+            let noise = if i % 2 == 0 { 1.0 } else { -1.0 };
+            last = sg.push(t0 + Duration::seconds(i), 50.0 + noise);
+        }
+        assert!((last.unwrap().value - 50.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_identical_timestamps_give_nothing() {
+        let mut sg = filter(3, 1);
+        let t0 = Utc::now();
+        for _ in 0..3 {
+            assert!(sg.push(t0, 1.0).is_none());
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut sg = filter(3, 1);
+        let t0 = Utc::now();
+        sg.push(t0, 1.0);
+        sg.push(t0 + Duration::seconds(1), 1.0);
+        sg.reset();
+        assert!(sg.push(t0 + Duration::seconds(2), 1.0).is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(SavitzkyGolaySettings::default().validate().is_ok());
+        for (window, order) in [(5, 0), (3, 2), (11, 6), (201, 2)] {
+            let settings = SavitzkyGolaySettings { window, order };
+            assert!(
+                settings.validate().is_err(),
+                "window {window}, order {order}"
+            );
+        }
+    }
+}
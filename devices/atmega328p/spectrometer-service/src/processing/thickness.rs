@@ -0,0 +1,217 @@
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::ThicknessEstimate;
+
+/// Default hysteresis of turning-point detection, in % of the reading
+pub const DEFAULT_MIN_SWING: f64 = 1.0;
+
+/// Thickness estimation, from the `[processing.thickness]` config section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThicknessSettings {
+    /// Estimate the thickness of the layer being deposited
+    pub enabled: bool,
+    /// How far the reading must fall back from a maximum (or rise from a
+    /// minimum), in %, before it counts as a turning point; keeps noise from
+    /// adding turning points
+    pub min_swing: f64,
+}
+
+impl Default for ThicknessSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_swing: DEFAULT_MIN_SWING,
+        }
+    }
+}
+
+impl ThicknessSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.min_swing > 0.0 && self.min_swing.is_finite()) {
+            return Err(format!(
+                "min_swing must be positive, got {}",
+                self.min_swing
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Direction the reading curve is moving in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trend {
+    Rising,
+    Falling,
+}
+
+/// Rough thickness of the layer being deposited, from its reading curve.
+///
+/// Each turning point (extremum) of the reading adds a quarter wave of
+/// optical thickness at the control wavelength. Between turning points the
+/// phase is interpolated from how far the reading has moved across the
+/// previous swing, as on a sinusoid; before the second turning point there
+/// is no swing to go by and the estimate counts whole quarter waves only.
+#[derive(Debug, Clone, Default)]
+pub struct ThicknessEstimator {
+    /// Layer the curve belongs to, by number and start; another layer,
+    /// including layer 1 of the next run, starts over
+    layer: u32,
+    started_at: Option<DateTime<Utc>>,
+    /// Last turning point, or the first reading of the layer
+    anchor: Option<f64>,
+    /// Most extreme reading since the anchor, in the direction of `trend`
+    extreme: f64,
+    trend: Option<Trend>,
+    /// Amplitude between the last two turning points
+    swing: Option<f64>,
+    turning_points: u32,
+    last: f64,
+}
+
+impl ThicknessEstimator {
+    /// Fold a valid reading (%) of layer `layer`, started at `started_at`,
+    /// into the curve
+    pub fn record(&mut self, layer: u32, started_at: DateTime<Utc>, reading: f64, min_swing: f64) {
+        if (layer, Some(started_at)) != (self.layer, self.started_at) {
+            *self = Self {
+                layer,
+                started_at: Some(started_at),
+                ..Self::default()
+            };
+        }
+        self.last = reading;
+        let Some(anchor) = self.anchor else {
+            self.anchor = Some(reading);
+            self.extreme = reading;
+            return;
+        };
+
+        match self.trend {
+            None if reading - anchor >= min_swing => self.trend = Some(Trend::Rising),
+            None if anchor - reading >= min_swing => self.trend = Some(Trend::Falling),
+            None => return,
+            Some(Trend::Rising) if reading > self.extreme => {}
+            Some(Trend::Falling) if reading < self.extreme => {}
+            Some(trend) => {
+                if (self.extreme - reading).abs() < min_swing {
+                    return;
+                }
+                // Turned at the extreme
+                if self.turning_points > 0 {
+                    self.swing = Some((self.extreme - anchor).abs());
+                }
+                self.turning_points += 1;
+                self.anchor = Some(self.extreme);
+                self.trend = Some(match trend {
+                    Trend::Rising => Trend::Falling,
+                    Trend::Falling => Trend::Rising,
+                });
+            }
+        }
+        self.extreme = reading;
+    }
+
+    /// Thickness at the last reading for the control `wavelength` (nm), in
+    /// physical terms too when the material's `refractive_index` is known.
+    /// None before the first reading.
+    pub fn estimate(
+        &self,
+        wavelength: f64,
+        refractive_index: Option<f64>,
+    ) -> Option<ThicknessEstimate> {
+        let anchor = self.anchor?;
+        let fraction = match self.swing {
+            Some(swing) if swing > 0.0 => {
+                let moved = ((self.last - anchor).abs() / swing).min(1.0);
+                (1.0 - 2.0 * moved).acos() / PI
+            }
+            _ => 0.0,
+        };
+        let optical = (self.turning_points as f64 + fraction) * wavelength / 4.0;
+
+        Some(ThicknessEstimate {
+            layer: self.layer,
+            turning_points: self.turning_points,
+            optical_nm: optical,
+            physical_nm: refractive_index.map(|n| optical / n),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    /// Reading of a layer `optical` nm thick at 600 nm, swinging between
+    /// 40 % and 80 %
+    fn reading(optical: f64) -> f64 {
+        60.0 + 20.0 * (4.0 * PI * optical / 600.0).cos()
+    }
+
+    #[test]
+    fn test_counts_quarter_waves() {
+        let start = Utc::now();
+        let mut estimator = ThicknessEstimator::default();
+        assert!(estimator.estimate(600.0, None).is_none());
+
+        // Up to 400 nm: turning points at 150, 300 nm
+        for step in 0..=400 {
+            estimator.record(1, start, reading(step as f64), DEFAULT_MIN_SWING);
+        }
+        let estimate = estimator.estimate(600.0, Some(2.0)).unwrap();
+        assert_eq!(estimate.turning_points, 2);
+        assert!((estimate.optical_nm - 400.0).abs() < 10.0, "{estimate:?}");
+        assert!((estimate.physical_nm.unwrap() - 200.0).abs() < 5.0);
+    }
+
+    #[test]
+    fn test_noise_below_min_swing_ignored() {
+        let start = Utc::now();
+        let mut estimator = ThicknessEstimator::default();
+        for step in 0..100 {
+            let jitter = if step % 2 == 0 { 0.3 } else { -0.3 };
+            estimator.record(1, start, 50.0 + jitter, DEFAULT_MIN_SWING);
+        }
+        let estimate = estimator.estimate(600.0, None).unwrap();
+        assert_eq!(estimate.turning_points, 0);
+        assert_eq!(estimate.optical_nm, 0.0);
+        assert_eq!(estimate.physical_nm, None);
+    }
+
+    #[test]
+    fn test_new_layer_starts_over() {
+        let start = Utc::now();
+        let mut estimator = ThicknessEstimator::default();
+        for step in 0..=200 {
+            estimator.record(1, start, reading(step as f64), DEFAULT_MIN_SWING);
+        }
+        assert_eq!(estimator.estimate(600.0, None).unwrap().turning_points, 1);
+
+        estimator.record(2, start, 55.0, DEFAULT_MIN_SWING);
+        let estimate = estimator.estimate(600.0, None).unwrap();
+        assert_eq!((estimate.layer, estimate.turning_points), (2, 0));
+
+        // Layer 1 of the next run
+        for step in 0..=200 {
+            estimator.record(1, start, reading(step as f64), DEFAULT_MIN_SWING);
+        }
+        estimator.record(1, start + Duration::hours(1), 55.0, DEFAULT_MIN_SWING);
+        assert_eq!(estimator.estimate(600.0, None).unwrap().turning_points, 0);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ThicknessSettings::default().validate().is_ok());
+        let settings = ThicknessSettings {
+            min_swing: 0.0,
+            ..ThicknessSettings::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// Which instant a measurement timestamp refers to.
+///
+/// The firmware acquires its series back to back, `COUNT` samples each
+/// at `FADC`, and prints each series once it has been acquired. The SERIES1
+/// line therefore arrives at the end of the first series' acquisition.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampMode {
+    /// When the SERIES1 line arrived
+    Arrival,
+    /// Midpoint of the whole acquisition window of all series (default)
+    #[default]
+    CycleMidpoint,
+    /// Midpoint of the series mapped as sample
+    SampleMidpoint,
+}
+
+/// Time needed to acquire one series
+pub fn series_duration(fadc: f32, count: u8) -> Duration {
+    if fadc <= 0.0 {
+        return Duration::zero();
+    }
+    Duration::microseconds((f64::from(count) / f64::from(fadc) * 1_000_000.0).round() as i64)
+}
+
+impl TimestampMode {
+    /// Derive the measurement timestamp from the SERIES1 arrival time.
+    /// `series_count` series make up the cycle; `sample_series` is the
+    /// 1-based position of the sample series.
+    pub fn timestamp(
+        self,
+        series1_arrival: DateTime<Utc>,
+        fadc: f32,
+        count: u8,
+        series_count: u8,
+        sample_series: u8,
+    ) -> DateTime<Utc> {
+        let series = series_duration(fadc, count);
+        // Acquisition of SERIES1 started one series duration before it arrived
+        let window_start = series1_arrival - series;
+
+        match self {
+            TimestampMode::Arrival => series1_arrival,
+            TimestampMode::CycleMidpoint => window_start + series * i32::from(series_count) / 2,
+            TimestampMode::SampleMidpoint => {
+                let position = i32::from(sample_series.clamp(1, series_count.max(1))) - 1;
+                window_start + series * position + series / 2
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_duration() {
+        assert_eq!(series_duration(250.0, 4), Duration::milliseconds(16));
+        assert_eq!(series_duration(10.0, 12), Duration::milliseconds(1200));
+        assert_eq!(series_duration(0.0, 4), Duration::zero());
+    }
+
+    #[test]
+    fn test_arrival_unchanged() {
+        let t = Utc::now();
+        assert_eq!(TimestampMode::Arrival.timestamp(t, 250.0, 4, 3, 3), t);
+    }
+
+    #[test]
+    fn test_cycle_midpoint() {
+        let t = Utc::now();
+        // 100 ms per series: window is [t - 100ms, t + 200ms], midpoint t + 50ms
+        let ts = TimestampMode::CycleMidpoint.timestamp(t, 10.0, 1, 3, 3);
+        assert_eq!(ts, t + Duration::milliseconds(50));
+
+        // Four series: window is [t - 100ms, t + 300ms]
+        let ts = TimestampMode::CycleMidpoint.timestamp(t, 10.0, 1, 4, 3);
+        assert_eq!(ts, t + Duration::milliseconds(100));
+    }
+
+    #[test]
+    fn test_sample_midpoint_follows_series_order() {
+        let t = Utc::now();
+        let first = TimestampMode::SampleMidpoint.timestamp(t, 10.0, 1, 3, 1);
+        let third = TimestampMode::SampleMidpoint.timestamp(t, 10.0, 1, 3, 3);
+
+        assert_eq!(first, t - Duration::milliseconds(50));
+        assert_eq!(third, t + Duration::milliseconds(150));
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{PhysicalLevels, ProcessedMeasurement};
+
+/// Codes spanned by the 24-bit ADC
+const FULL_SCALE_CODES: f64 = 16_777_216.0;
+
+/// Output coding of the AD7793, set by the U/B bit of its configuration
+/// register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdcCoding {
+    /// Offset binary: 0 is -VREF/GAIN, 0x800000 is 0 V (power-on default)
+    #[default]
+    Bipolar,
+    /// Straight binary: 0 is 0 V, 0xFFFFFF is VREF/GAIN
+    Unipolar,
+}
+
+/// `[processing.units]` config section
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct UnitsSettings {
+    /// Voltage across REFIN(+) and REFIN(-); when unset, levels are
+    /// reported in ADC counts only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_voltage: Option<f64>,
+    #[serde(default)]
+    pub coding: AdcCoding,
+    /// Feedback resistor of the photodiode's transimpedance amplifier; when
+    /// set, photocurrents are reported as well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transimpedance_ohms: Option<f64>,
+}
+
+impl UnitsSettings {
+    /// Check the reference and resistor are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(reference) = self.reference_voltage
+            && !(reference > 0.0 && reference.is_finite())
+        {
+            return Err(format!(
+                "reference_voltage must be positive, got {reference}"
+            ));
+        }
+        if let Some(ohms) = self.transimpedance_ohms {
+            if !(ohms > 0.0 && ohms.is_finite()) {
+                return Err(format!("transimpedance_ohms must be positive, got {ohms}"));
+            }
+            if self.reference_voltage.is_none() {
+                return Err("transimpedance_ohms requires reference_voltage".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Input voltage for an ADC `code` read at `gain`, or None without a
+    /// reference voltage
+    pub fn voltage(&self, code: f64, gain: u8) -> Option<f64> {
+        let span = self.reference_voltage? / f64::from(gain.max(1));
+        Some(match self.coding {
+            AdcCoding::Bipolar => (2.0 * code / FULL_SCALE_CODES - 1.0) * span,
+            AdcCoding::Unipolar => code / FULL_SCALE_CODES * span,
+        })
+    }
+
+    /// The measurement's dark, full and sample levels at the ADC input and,
+    /// with a transimpedance, at the photodiode. `gain` should be the one the
+    /// cycle was acquired with.
+    pub fn convert(&self, measurement: &ProcessedMeasurement, gain: u8) -> Option<PhysicalLevels> {
+        let voltage = |code| self.voltage(code, gain);
+        let current = |volts: f64| self.transimpedance_ohms.map(|ohms| volts / ohms);
+
+        let dark_voltage = voltage(measurement.dark_mean)?;
+        let full_voltage = voltage(measurement.full_mean)?;
+        let sample_voltage = voltage(measurement.sample_mean)?;
+        Some(PhysicalLevels {
+            gain,
+            dark_voltage,
+            full_voltage,
+            sample_voltage,
+            dark_current: current(dark_voltage),
+            full_current: current(full_voltage),
+            sample_current: current(sample_voltage),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn settings(coding: AdcCoding) -> UnitsSettings {
+        UnitsSettings {
+            reference_voltage: Some(2.5),
+            coding,
+            transimpedance_ohms: None,
+        }
+    }
+
+    #[test]
+    fn test_bipolar_voltage() {
+        let units = settings(AdcCoding::Bipolar);
+        assert_eq!(units.voltage(8_388_608.0, 1), Some(0.0));
+        assert_eq!(units.voltage(0.0, 1), Some(-2.5));
+        assert_eq!(units.voltage(12_582_912.0, 1), Some(1.25));
+        // Gain divides the input range
+        assert_eq!(units.voltage(12_582_912.0, 8), Some(0.15625));
+    }
+
+    #[test]
+    fn test_unipolar_voltage() {
+        let units = settings(AdcCoding::Unipolar);
+        assert_eq!(units.voltage(0.0, 1), Some(0.0));
+        assert_eq!(units.voltage(8_388_608.0, 1), Some(1.25));
+        assert_eq!(units.voltage(8_388_608.0, 2), Some(0.625));
+    }
+
+    #[test]
+    fn test_no_reference_no_conversion() {
+        let units = UnitsSettings::default();
+        let measurement = ProcessedMeasurement::new(Utc::now(), 14e6, 300.0, 7e6, 50.0);
+        assert_eq!(units.voltage(1000.0, 1), None);
+        assert!(units.convert(&measurement, 1).is_none());
+    }
+
+    #[test]
+    fn test_convert_with_current() {
+        let units = UnitsSettings {
+            transimpedance_ohms: Some(1e6),
+            ..settings(AdcCoding::Unipolar)
+        };
+        let measurement =
+            ProcessedMeasurement::new(Utc::now(), 8_388_608.0, 0.0, 4_194_304.0, 50.0);
+        let levels = units.convert(&measurement, 2).unwrap();
+        assert_eq!(levels.gain, 2);
+        assert_eq!(levels.dark_voltage, 0.625);
+        assert_eq!(levels.full_voltage, 0.0);
+        assert_eq!(levels.sample_voltage, 0.3125);
+        assert_eq!(levels.dark_current, Some(0.625e-6));
+        assert_eq!(levels.sample_current, Some(0.3125e-6));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(UnitsSettings::default().validate().is_ok());
+        assert!(settings(AdcCoding::Bipolar).validate().is_ok());
+        for reference_voltage in [0.0, -1.0, f64::NAN] {
+            let units = UnitsSettings {
+                reference_voltage: Some(reference_voltage),
+                ..Default::default()
+            };
+            assert!(units.validate().is_err(), "{reference_voltage}");
+        }
+        let units = UnitsSettings {
+            transimpedance_ohms: Some(1e6),
+            ..Default::default()
+        };
+        assert!(units.validate().unwrap_err().contains("reference_voltage"));
+    }
+}
@@ -1,43 +1,284 @@
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::MAX_ADC_VALUE;
+use crate::protocol::{MeasurementCycle, SeriesData, ValidationCategory};
+
+/// Default distance below full scale treated as saturated (about 0.1%)
+pub const DEFAULT_SATURATION_MARGIN: u32 = 16_777;
+
+/// Default raw value at or below which a reading is treated as under-range
+pub const DEFAULT_UNDER_RANGE_LIMIT: u32 = 10;
+
+/// Default number of values per series that must survive outlier exclusion
+pub const DEFAULT_MIN_SAMPLES: usize = 2;
+
+/// Which relationship between the means a measurement must satisfy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationRule {
+    /// full > sample > dark
+    Strict,
+    /// Sample between dark and full, for detectors of either polarity
+    #[default]
+    AnyPolarity,
+    /// Accept every measurement (e.g. when replaying historical logs)
+    Off,
+}
+
+/// Validation rule and tolerances, from the `[processing.validation]`
+/// config section
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValidationSettings {
+    pub rule: ValidationRule,
+    /// How far sample may go past full, in percent of the dark-full span.
+    /// Anti-reflection coatings legitimately transmit more than the reference.
+    pub overshoot_percent: f64,
+    /// Differences up to this many ADC counts count as equal, so a sample
+    /// sitting on the dark or full level is accepted
+    pub epsilon: f64,
+    /// Raw values within this many counts of `MAX_ADC_VALUE` are saturated
+    pub saturation_margin: u32,
+    /// Raw values at or below this are under-range
+    pub under_range_limit: u32,
+    /// Values per series that must survive outlier exclusion, or all of
+    /// them when the series is shorter
+    pub min_samples: usize,
+    /// Signal-to-noise ratio below which a measurement is flagged
+    /// `low_snr`; it stays valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_snr: Option<f64>,
+    /// Cycles after a material change, shutter move or deposition start
+    /// that are flagged `transitional` and held back from the sinks and
+    /// alerts; 0 disables blanking
+    pub blanking_cycles: u32,
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        Self {
+            rule: ValidationRule::default(),
+            overshoot_percent: 0.0,
+            epsilon: 0.0,
+            saturation_margin: DEFAULT_SATURATION_MARGIN,
+            under_range_limit: DEFAULT_UNDER_RANGE_LIMIT,
+            min_samples: DEFAULT_MIN_SAMPLES,
+            min_snr: None,
+            blanking_cycles: 0,
+        }
+    }
+}
+
+impl ValidationSettings {
+    /// Check the tolerances are usable
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.overshoot_percent >= 0.0 && self.overshoot_percent.is_finite()) {
+            return Err(format!(
+                "overshoot_percent must be a non-negative number, got {}",
+                self.overshoot_percent
+            ));
+        }
+        if !(self.epsilon >= 0.0 && self.epsilon.is_finite()) {
+            return Err(format!(
+                "epsilon must be a non-negative number, got {}",
+                self.epsilon
+            ));
+        }
+        if let Some(min_snr) = self.min_snr
+            && !(min_snr > 0.0 && min_snr.is_finite())
+        {
+            return Err(format!("min_snr must be a positive number, got {min_snr}"));
+        }
+        Ok(())
+    }
+}
+
 /// Measurement validator
 ///
-/// Validates that measurements follow expected relationship: full > sample > dark
+/// Validates that measurements follow expected relationship: full > sample > dark,
+/// within the configured tolerances
 #[allow(dead_code)]
-pub struct MeasurementValidator;
+#[derive(Debug, Clone, Copy)]
+pub struct MeasurementValidator {
+    settings: ValidationSettings,
+}
 
 #[allow(dead_code)]
 impl MeasurementValidator {
     pub fn new() -> Self {
-        Self
+        Self::with_settings(ValidationSettings::default())
+    }
+
+    pub fn with_settings(settings: ValidationSettings) -> Self {
+        Self { settings }
+    }
+
+    /// Check raw values against the ADC range. Saturated full scans give
+    /// plausible-looking but wrong readings, so this runs before the
+    /// relationship check and regardless of the configured rule.
+    pub fn check_range(
+        &self,
+        cycle: &MeasurementCycle,
+    ) -> Result<(), (ValidationCategory, String)> {
+        let series: [(&str, &SeriesData); 3] = [
+            ("dark", cycle.dark()),
+            ("full", cycle.full()),
+            ("sample", cycle.sample()),
+        ];
+        let saturation_level = MAX_ADC_VALUE.saturating_sub(self.settings.saturation_margin);
+
+        for (name, data) in series {
+            let saturated = data
+                .values
+                .iter()
+                .filter(|&&v| v >= saturation_level)
+                .count();
+            if saturated > 0 {
+                return Err((
+                    ValidationCategory::Saturation,
+                    format!(
+                        "{name} saturated: {saturated} of {} values at or above {saturation_level}",
+                        data.len()
+                    ),
+                ));
+            }
+        }
+
+        for (name, data) in series {
+            let low = data
+                .values
+                .iter()
+                .filter(|&&v| v <= self.settings.under_range_limit)
+                .count();
+            if low > 0 {
+                return Err((
+                    ValidationCategory::UnderRange,
+                    format!(
+                        "{name} under range: {low} of {} values at or below {}",
+                        data.len(),
+                        self.settings.under_range_limit
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check enough values of each series survived outlier exclusion to
+    /// average. `series` holds the name, surviving and original count.
+    pub fn check_sample_count(
+        &self,
+        series: [(&str, usize, usize); 3],
+    ) -> Result<(), (ValidationCategory, String)> {
+        for (name, kept, total) in series {
+            let required = self.settings.min_samples.min(total);
+            if kept < required {
+                return Err((
+                    ValidationCategory::InsufficientSamples,
+                    format!(
+                        "{name} has {kept} of {total} values left after outlier exclusion, {required} required"
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate using the configured rule
+    pub fn check(&self, dark_mean: f64, full_mean: f64, sample_mean: f64) -> Result<(), String> {
+        match self.settings.rule {
+            ValidationRule::Strict => self.validate(dark_mean, full_mean, sample_mean),
+            ValidationRule::AnyPolarity => {
+                self.validate_any_polarity(dark_mean, full_mean, sample_mean)
+            }
+            ValidationRule::Off => Ok(()),
+        }
     }
 
     /// Validate measurement relationship: full > sample > dark
     ///
     /// Returns Ok(()) if valid, Err with description if invalid
     pub fn validate(&self, dark_mean: f64, full_mean: f64, sample_mean: f64) -> Result<(), String> {
-        if full_mean <= dark_mean {
+        let epsilon = self.settings.epsilon;
+
+        if full_mean - dark_mean <= epsilon {
             return Err(format!(
                 "full ({:.2}) must be greater than dark ({:.2})",
                 full_mean, dark_mean
             ));
         }
 
-        if sample_mean <= dark_mean {
+        if sample_mean - dark_mean <= -epsilon {
             return Err(format!(
                 "sample ({:.2}) must be greater than dark ({:.2})",
                 sample_mean, dark_mean
             ));
         }
 
-        if sample_mean >= full_mean {
+        if sample_mean >= self.upper_limit(dark_mean, full_mean) {
             return Err(format!(
-                "sample ({:.2}) must be less than full ({:.2})",
-                sample_mean, full_mean
+                "sample ({:.2}) must be less than full ({:.2}){}",
+                sample_mean,
+                full_mean,
+                self.overshoot_note()
             ));
         }
 
         Ok(())
     }
 
+    /// Validate that sample lies strictly between dark and full, for detectors
+    /// of either polarity (the AD7793 reads higher values for less light)
+    pub fn validate_any_polarity(
+        &self,
+        dark_mean: f64,
+        full_mean: f64,
+        sample_mean: f64,
+    ) -> Result<(), String> {
+        let span = (full_mean - dark_mean).abs();
+        if span < f64::EPSILON || span <= self.settings.epsilon {
+            return Err(format!(
+                "full ({:.2}) must differ from dark ({:.2})",
+                full_mean, dark_mean
+            ));
+        }
+
+        // Distance of sample from dark, measured towards full
+        let direction = (full_mean - dark_mean).signum();
+        let offset = (sample_mean - dark_mean) * direction;
+        let limit = (self.upper_limit(dark_mean, full_mean) - dark_mean) * direction;
+
+        if offset <= -self.settings.epsilon || offset >= limit {
+            return Err(format!(
+                "sample ({:.2}) must lie between dark ({:.2}) and full ({:.2}){}",
+                sample_mean,
+                dark_mean,
+                full_mean,
+                self.overshoot_note()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Sample value that is no longer accepted on the full side, including
+    /// the overshoot allowance and epsilon
+    fn upper_limit(&self, dark_mean: f64, full_mean: f64) -> f64 {
+        let span = full_mean - dark_mean;
+        let allowance =
+            span.abs() * self.settings.overshoot_percent / 100.0 + self.settings.epsilon;
+        full_mean + allowance * span.signum()
+    }
+
+    fn overshoot_note(&self) -> String {
+        if self.settings.overshoot_percent > 0.0 {
+            format!(" + {}%", self.settings.overshoot_percent)
+        } else {
+            String::new()
+        }
+    }
+
     /// Validate with warnings instead of errors for edge cases
     ///
     /// Returns (is_valid, optional_warning)
@@ -134,6 +375,186 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_any_polarity_accepts_inverted_detector() {
+        let validator = MeasurementValidator::new();
+
+        // AD7793: dark ~14M, full ~300, sample in between
+        assert!(
+            validator
+                .validate_any_polarity(14_000_000.0, 300.0, 13_000_000.0)
+                .is_ok()
+        );
+        assert!(
+            validator
+                .validate_any_polarity(100.0, 1000.0, 500.0)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_any_polarity_rejects_out_of_range_sample() {
+        let validator = MeasurementValidator::new();
+
+        let result = validator.validate_any_polarity(14_000_000.0, 300.0, 15_000_000.0);
+        assert!(result.unwrap_err().contains("between"));
+
+        let result = validator.validate_any_polarity(100.0, 1000.0, 1100.0);
+        assert!(result.is_err());
+
+        let result = validator.validate_any_polarity(500.0, 500.0, 500.0);
+        assert!(result.unwrap_err().contains("differ"));
+    }
+
+    fn validator(
+        rule: ValidationRule,
+        overshoot_percent: f64,
+        epsilon: f64,
+    ) -> MeasurementValidator {
+        MeasurementValidator::with_settings(ValidationSettings {
+            rule,
+            overshoot_percent,
+            epsilon,
+            ..ValidationSettings::default()
+        })
+    }
+
+    fn cycle(dark: Vec<u32>, full: Vec<u32>, sample: Vec<u32>) -> MeasurementCycle {
+        MeasurementCycle::with_timestamp(
+            chrono::Utc::now(),
+            SeriesData::new(dark),
+            SeriesData::new(full),
+            SeriesData::new(sample),
+        )
+    }
+
+    #[test]
+    fn test_range_check_flags_saturation() {
+        let v = MeasurementValidator::new();
+        let near_max = MAX_ADC_VALUE - 100;
+
+        let (category, error) = v
+            .check_range(&cycle(vec![100, 101], vec![near_max, 1000], vec![500, 501]))
+            .unwrap_err();
+        assert_eq!(category, ValidationCategory::Saturation);
+        assert!(error.starts_with("full saturated: 1 of 2"));
+    }
+
+    #[test]
+    fn test_range_check_flags_under_range() {
+        let v = MeasurementValidator::new();
+
+        let (category, error) = v
+            .check_range(&cycle(vec![0, 3], vec![1000, 1001], vec![500, 501]))
+            .unwrap_err();
+        assert_eq!(category, ValidationCategory::UnderRange);
+        assert!(error.starts_with("dark under range: 2 of 2"));
+    }
+
+    #[test]
+    fn test_sample_count_check() {
+        let v = MeasurementValidator::new();
+        assert!(
+            v.check_sample_count([("dark", 3, 3), ("full", 2, 3), ("sample", 3, 3)])
+                .is_ok()
+        );
+
+        // 2 of 3 removed
+        let (category, error) = v
+            .check_sample_count([("dark", 3, 3), ("full", 3, 3), ("sample", 1, 3)])
+            .unwrap_err();
+        assert_eq!(category, ValidationCategory::InsufficientSamples);
+        assert_eq!(
+            error,
+            "sample has 1 of 3 values left after outlier exclusion, 2 required"
+        );
+
+        // A single-value series (COUNT=1) is accepted as is
+        assert!(
+            v.check_sample_count([("dark", 1, 1), ("full", 1, 1), ("sample", 1, 1)])
+                .is_ok()
+        );
+
+        let strict = MeasurementValidator::with_settings(ValidationSettings {
+            min_samples: 10,
+            ..Default::default()
+        });
+        assert!(
+            strict
+                .check_sample_count([("dark", 12, 12), ("full", 9, 12), ("sample", 12, 12)])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_range_check_accepts_inverted_detector() {
+        let v = MeasurementValidator::new();
+
+        // AD7793: dark ~14M, full ~300
+        assert!(
+            v.check_range(&cycle(
+                vec![14_000_000, 14_000_100],
+                vec![300, 310],
+                vec![13_000_000, 13_000_050],
+            ))
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_overshoot_allows_sample_above_full() {
+        let v = validator(ValidationRule::Strict, 5.0, 0.0);
+
+        // Span 900, so sample may reach just under 1045
+        assert!(v.check(100.0, 1000.0, 1040.0).is_ok());
+        let err = v.check(100.0, 1000.0, 1050.0).unwrap_err();
+        assert!(err.contains("+ 5%"));
+
+        // Inverted detector: overshoot goes below full
+        let v = validator(ValidationRule::AnyPolarity, 5.0, 0.0);
+        assert!(v.check(1000.0, 100.0, 60.0).is_ok());
+        assert!(v.check(1000.0, 100.0, 50.0).is_err());
+    }
+
+    #[test]
+    fn test_epsilon_accepts_near_equality() {
+        let v = validator(ValidationRule::Strict, 0.0, 2.0);
+
+        assert!(v.check(100.0, 1000.0, 100.0).is_ok());
+        assert!(v.check(100.0, 1000.0, 1001.0).is_ok());
+        assert!(v.check(100.0, 1000.0, 97.0).is_err());
+        // full within epsilon of dark is still unusable
+        assert!(v.check(100.0, 101.0, 100.5).is_err());
+    }
+
+    #[test]
+    fn test_rule_selection() {
+        let strict = validator(ValidationRule::Strict, 0.0, 0.0);
+        let any = validator(ValidationRule::AnyPolarity, 0.0, 0.0);
+        let off = validator(ValidationRule::Off, 0.0, 0.0);
+
+        assert!(strict.check(14_000_000.0, 300.0, 13_000_000.0).is_err());
+        assert!(any.check(14_000_000.0, 300.0, 13_000_000.0).is_ok());
+        assert!(off.check(100.0, 100.0, 5000.0).is_ok());
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(ValidationSettings::default().validate().is_ok());
+
+        let settings = ValidationSettings {
+            overshoot_percent: -1.0,
+            ..ValidationSettings::default()
+        };
+        assert!(settings.validate().is_err());
+
+        let settings = ValidationSettings {
+            epsilon: f64::NAN,
+            ..ValidationSettings::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
     #[test]
     fn test_validate_with_warnings() {
         let validator = MeasurementValidator::new();
@@ -0,0 +1,103 @@
+//! Optional trailing `*<hex>` checksum on protocol lines.
+//!
+//! Two hex digits carry a CRC-8 (polynomial 0x07, init 0x00, as avr-libc's
+//! `_crc8_ccitt_update`), four carry a CRC-16/XMODEM (polynomial 0x1021,
+//! init 0x0000, as `_crc_xmodem_update`). Both cover the line text before
+//! the `*`.
+
+/// Checksum that did not match the line content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub expected: u16,
+    pub actual: u16,
+}
+
+/// CRC-8, polynomial 0x07, init 0x00
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16/XMODEM, polynomial 0x1021, init 0x0000
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Verify and strip a trailing checksum. Lines without one are returned
+/// unchanged, so firmware without the option keeps working.
+pub fn strip_checksum(line: &str) -> Result<&str, ChecksumMismatch> {
+    let Some((content, digits)) = line.rsplit_once('*') else {
+        return Ok(line);
+    };
+    if !matches!(digits.len(), 2 | 4) || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Ok(line);
+    }
+    let Ok(expected) = u16::from_str_radix(digits, 16) else {
+        return Ok(line);
+    };
+
+    let actual = match digits.len() {
+        2 => u16::from(crc8(content.as_bytes())),
+        _ => crc16(content.as_bytes()),
+    };
+    if actual == expected {
+        Ok(content.trim_end())
+    } else {
+        Err(ChecksumMismatch { expected, actual })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc_check_values() {
+        // Standard check values over "123456789"
+        assert_eq!(crc8(b"123456789"), 0xF4);
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn test_strip_valid_checksums() {
+        let line = "SERIES1 = [100 101 102]";
+        let with_crc8 = format!("{line}*{:02X}", crc8(line.as_bytes()));
+        let with_crc16 = format!("{line}*{:04x}", crc16(line.as_bytes()));
+
+        assert_eq!(strip_checksum(&with_crc8), Ok(line));
+        assert_eq!(strip_checksum(&with_crc16), Ok(line));
+    }
+
+    #[test]
+    fn test_line_without_checksum_unchanged() {
+        assert_eq!(strip_checksum("END_CYCLE"), Ok("END_CYCLE"));
+        assert_eq!(strip_checksum("ERROR a*b"), Ok("ERROR a*b"));
+        assert_eq!(strip_checksum("x*123"), Ok("x*123"));
+    }
+
+    #[test]
+    fn test_corrupt_digit_detected() {
+        let line = "SERIES1 = [100 101 102]";
+        let crc = crc8(line.as_bytes());
+        let corrupt = format!("SERIES1 = [100 181 102]*{crc:02X}");
+
+        let err = strip_checksum(&corrupt).unwrap_err();
+        assert_eq!(err.expected, u16::from(crc));
+        assert_ne!(err.actual, err.expected);
+    }
+}
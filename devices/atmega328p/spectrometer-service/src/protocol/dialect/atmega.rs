@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+
+use super::{CycleSettings, DeviceProtocol};
+use crate::protocol::parser::{CycleAccumulator, LineParser, ParsedLine, StalePartialCycle};
+use crate::protocol::types::MeasurementCycle;
+
+/// Text protocol of the ATmega328P firmware, see `parse_line`
+#[derive(Debug)]
+pub struct AtmegaProtocol {
+    parser: LineParser,
+    accumulator: CycleAccumulator,
+}
+
+impl AtmegaProtocol {
+    pub fn new(settings: CycleSettings) -> Self {
+        Self {
+            parser: LineParser::new().with_strict(settings.strict),
+            accumulator: CycleAccumulator::with_timeout(settings.timeout)
+                .with_series_count(settings.series_count)
+                .with_out_of_range(settings.out_of_range),
+        }
+    }
+}
+
+impl DeviceProtocol for AtmegaProtocol {
+    fn parse(&mut self, line: &str) -> Option<ParsedLine> {
+        self.parser.parse(line)
+    }
+
+    fn accumulate(
+        &mut self,
+        line: ParsedLine,
+        at: Option<DateTime<Utc>>,
+    ) -> Option<MeasurementCycle> {
+        match at {
+            Some(at) => self.accumulator.process_line_with_timestamp(line, at),
+            None => self.accumulator.process_line(line),
+        }
+    }
+
+    fn expire(&mut self, now: DateTime<Utc>) -> Option<StalePartialCycle> {
+        self.accumulator.expire(now)
+    }
+
+    fn name(&self) -> &'static str {
+        "atmega"
+    }
+}
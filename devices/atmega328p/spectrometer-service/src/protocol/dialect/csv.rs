@@ -0,0 +1,187 @@
+use chrono::{DateTime, Utc};
+
+use super::{CycleSettings, DeviceProtocol};
+use crate::error::ProtocolError;
+use crate::protocol::parser::{CycleAccumulator, ParsedLine, StalePartialCycle};
+use crate::protocol::types::{MeasurementCycle, RawAdcValue, ShutterState};
+
+/// Comma-separated dialect:
+///
+/// ```text
+/// CYCLE,42          optional device cycle counter
+/// SHUTTER,CLOSED    optional shutter state, until the next report
+/// 1,1000,1001,1002  series number, then its values
+/// 2,8000,8001,8002
+/// 3,4000,4001,4002
+/// END               closes the cycle
+/// ERR,<message>     error from the firmware
+/// ```
+///
+/// Blank lines and `#` comments are ignored.
+#[derive(Debug)]
+pub struct CsvProtocol {
+    accumulator: CycleAccumulator,
+    strict: bool,
+}
+
+impl CsvProtocol {
+    pub fn new(settings: CycleSettings) -> Self {
+        Self {
+            accumulator: CycleAccumulator::with_timeout(settings.timeout)
+                .with_series_count(settings.series_count)
+                .with_out_of_range(settings.out_of_range),
+            strict: settings.strict,
+        }
+    }
+}
+
+/// Parse a single CSV line
+pub fn parse_csv_line(input: &str) -> ParsedLine {
+    let trimmed = input.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return ParsedLine::Unknown(String::new());
+    }
+    if trimmed.eq_ignore_ascii_case("END") {
+        return ParsedLine::EndCycle;
+    }
+
+    let mut fields = trimmed.split(',').map(str::trim);
+    let first = fields.next().unwrap_or_default();
+
+    if first.eq_ignore_ascii_case("ERR") {
+        return ParsedLine::Error(fields.collect::<Vec<_>>().join(","));
+    }
+    if first.eq_ignore_ascii_case("CYCLE")
+        && let Some(Ok(sequence)) = fields.next().map(str::parse::<u32>)
+    {
+        return ParsedLine::CycleNumber(sequence);
+    }
+    if first.eq_ignore_ascii_case("SHUTTER")
+        && let Some(state) = fields.next().and_then(ShutterState::parse)
+    {
+        return ParsedLine::Shutter(state);
+    }
+    if let Ok(number) = first.parse::<u8>() {
+        let values: Result<Vec<RawAdcValue>, _> = fields.map(str::parse).collect();
+        if let Ok(values) = values {
+            return ParsedLine::Series { number, values };
+        }
+    }
+
+    ParsedLine::Unknown(trimmed.to_string())
+}
+
+/// Parse a CSV line, reporting `CYCLE`, `SHUTTER` and series lines with a bad field as
+/// errors instead of `ParsedLine::Unknown`
+pub fn parse_csv_line_strict(input: &str) -> Result<ParsedLine, ProtocolError> {
+    let parsed = parse_csv_line(input);
+    let ParsedLine::Unknown(line) = &parsed else {
+        return Ok(parsed);
+    };
+
+    let first = line.split(',').next().unwrap_or_default().trim();
+    let keyword = if first.eq_ignore_ascii_case("CYCLE") {
+        "CYCLE"
+    } else if first.eq_ignore_ascii_case("SHUTTER") {
+        "SHUTTER"
+    } else if first.parse::<u8>().is_ok() {
+        "series"
+    } else {
+        return Ok(parsed);
+    };
+    Err(ProtocolError::MalformedLine {
+        keyword,
+        line: line.clone(),
+    })
+}
+
+impl DeviceProtocol for CsvProtocol {
+    fn parse(&mut self, line: &str) -> Option<ParsedLine> {
+        if self.strict {
+            return Some(parse_csv_line_strict(line).unwrap_or_else(ParsedLine::Malformed));
+        }
+        Some(parse_csv_line(line))
+    }
+
+    fn accumulate(
+        &mut self,
+        line: ParsedLine,
+        at: Option<DateTime<Utc>>,
+    ) -> Option<MeasurementCycle> {
+        match at {
+            Some(at) => self.accumulator.process_line_with_timestamp(line, at),
+            None => self.accumulator.process_line(line),
+        }
+    }
+
+    fn expire(&mut self, now: DateTime<Utc>) -> Option<StalePartialCycle> {
+        self.accumulator.expire(now)
+    }
+
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_series() {
+        assert_eq!(
+            parse_csv_line("2, 8000, 8001,8002"),
+            ParsedLine::Series {
+                number: 2,
+                values: vec![8000, 8001, 8002]
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_markers() {
+        assert_eq!(parse_csv_line("END"), ParsedLine::EndCycle);
+        assert_eq!(parse_csv_line("CYCLE,42"), ParsedLine::CycleNumber(42));
+        assert_eq!(
+            parse_csv_line("SHUTTER,CLOSED"),
+            ParsedLine::Shutter(ShutterState::Closed)
+        );
+        assert_eq!(
+            parse_csv_line("ERR,adc timeout"),
+            ParsedLine::Error("adc timeout".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_ignores_comments_and_flags_garbage() {
+        assert_eq!(
+            parse_csv_line("# board rev B"),
+            ParsedLine::Unknown(String::new())
+        );
+        assert_eq!(
+            parse_csv_line("1,100,abc"),
+            ParsedLine::Unknown("1,100,abc".to_string())
+        );
+        assert_eq!(
+            parse_csv_line("SERIES1 = [100]"),
+            ParsedLine::Unknown("SERIES1 = [100]".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_strict() {
+        assert!(matches!(
+            parse_csv_line_strict("1,100,abc"),
+            Err(ProtocolError::MalformedLine {
+                keyword: "series",
+                ..
+            })
+        ));
+        assert!(parse_csv_line_strict("CYCLE,x").is_err());
+        assert_eq!(
+            parse_csv_line_strict("hello"),
+            Ok(ParsedLine::Unknown("hello".to_string()))
+        );
+        assert_eq!(parse_csv_line_strict("END"), Ok(ParsedLine::EndCycle));
+    }
+}
@@ -0,0 +1,111 @@
+pub mod atmega;
+pub mod csv;
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::parser::{ParsedLine, StalePartialCycle};
+use super::types::{DEFAULT_SERIES_COUNT, MeasurementCycle, OutOfRangePolicy};
+
+/// Trait for a firmware dialect: how received lines are parsed and
+/// assembled into measurement cycles
+pub trait DeviceProtocol: Send {
+    /// Parse a received line. Returns `None` for lines the dialect folds
+    /// into a later result, e.g. inside a multi-line block.
+    fn parse(&mut self, line: &str) -> Option<ParsedLine>;
+
+    /// Feed a parsed line; returns the cycle it completes. `at` is the
+    /// line's log time during playback, `None` for live input.
+    fn accumulate(
+        &mut self,
+        line: ParsedLine,
+        at: Option<DateTime<Utc>>,
+    ) -> Option<MeasurementCycle>;
+
+    /// Drop the partial cycle if it is older than the cycle timeout at `now`
+    fn expire(&mut self, now: DateTime<Utc>) -> Option<StalePartialCycle>;
+
+    /// Name of the dialect for logging/debugging
+    fn name(&self) -> &'static str;
+}
+
+/// Parsing and cycle assembly settings every dialect is created with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleSettings {
+    /// Partial cycles older than this are dropped
+    pub timeout: Option<Duration>,
+    /// SERIES lines per cycle
+    pub series_count: u8,
+    /// Handling of raw values above `MAX_ADC_VALUE`
+    pub out_of_range: OutOfRangePolicy,
+    /// Report malformed lines as `ParsedLine::Malformed` instead of
+    /// passing them on as unknown
+    pub strict: bool,
+}
+
+impl Default for CycleSettings {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            series_count: DEFAULT_SERIES_COUNT,
+            out_of_range: OutOfRangePolicy::default(),
+            strict: false,
+        }
+    }
+}
+
+/// Firmware dialect spoken by the device, selected with `--protocol`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolKind {
+    /// `SERIESn = [...]` / `END_CYCLE` text protocol of the ATmega328P
+    /// firmware (default)
+    #[default]
+    Atmega,
+    /// Comma-separated `<series>,<value>,...` lines closed by `END`
+    Csv,
+}
+
+impl ProtocolKind {
+    pub fn create(self, settings: CycleSettings) -> Box<dyn DeviceProtocol> {
+        match self {
+            ProtocolKind::Atmega => Box::new(atmega::AtmegaProtocol::new(settings)),
+            ProtocolKind::Csv => Box::new(csv::CsvProtocol::new(settings)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_each_kind() {
+        let settings = CycleSettings::default();
+        assert_eq!(ProtocolKind::Atmega.create(settings).name(), "atmega");
+        assert_eq!(ProtocolKind::Csv.create(settings).name(), "csv");
+    }
+
+    #[test]
+    fn test_dialects_agree_on_cycles() {
+        let atmega = [
+            "SERIES1 = [100 101]",
+            "SERIES2 = [8000 8001]",
+            "SERIES3 = [4000 4001]",
+            "END_CYCLE",
+        ];
+        let csv = ["1,100,101", "2,8000,8001", "3,4000,4001", "END"];
+
+        for (kind, lines) in [(ProtocolKind::Atmega, atmega), (ProtocolKind::Csv, csv)] {
+            let mut protocol = kind.create(CycleSettings::default());
+            let mut cycle = None;
+            for line in lines {
+                if let Some(parsed) = protocol.parse(line) {
+                    cycle = cycle.or(protocol.accumulate(parsed, None));
+                }
+            }
+            let cycle = cycle.unwrap();
+
+            assert_eq!(cycle.dark().values, vec![100, 101], "{kind:?}");
+            assert_eq!(cycle.sample().values, vec![4000, 4001], "{kind:?}");
+        }
+    }
+}
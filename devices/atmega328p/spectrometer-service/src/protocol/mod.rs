@@ -1,9 +1,17 @@
+pub mod checksum;
+pub mod dialect;
 #[allow(dead_code)]
 pub mod parser;
 #[allow(dead_code)]
 pub mod types;
 
-pub use parser::{CycleAccumulator, ParsedLine, parse_line};
-#[cfg(test)]
-pub use types::SeriesData;
-pub use types::{MeasurementCycle, ProcessedMeasurement};
+pub use dialect::{CycleSettings, DeviceProtocol, ProtocolKind};
+pub use parser::{ParsedLine, parse_line};
+pub use types::{
+    AdcFrequency, ConfirmedSettings, DEFAULT_SERIES_COUNT, DebugBlock, DeviceEvent,
+    DeviceEventKind, FADC_TOLERANCE, FilteredSeries, FirmwareVersion, Gain, MAX_ADC_VALUE,
+    Material, MeasurementCount, MeasurementCycle, MeasurementStatistics, OutOfRangePolicy,
+    OutlierExclusion, PhysicalLevels, ProcessedMeasurement, ProtocolIssue, ProtocolIssueKind,
+    RawAdcValue, SeriesData, SeriesExclusion, SeriesMapping, SeriesStatistics, ShutterState,
+    SmoothedReading, ThicknessEstimate, ValidationCategory,
+};
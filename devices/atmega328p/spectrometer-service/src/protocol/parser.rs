@@ -1,14 +1,20 @@
+use std::collections::BTreeMap;
 use std::sync::LazyLock;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use regex::Regex;
 
-use super::types::{MeasurementCycle, RawAdcValue, SeriesData};
+use super::checksum::strip_checksum;
+use super::types::{
+    DEFAULT_SERIES_COUNT, DeviceEventKind, FirmwareVersion, MAX_ADC_VALUE, MeasurementCycle,
+    OutOfRangePolicy, ProtocolIssueKind, RawAdcValue, SeriesData, ShutterState,
+};
+use crate::error::ProtocolError;
 
 // Pre-compiled regex patterns for efficiency
 // Accepts both bracketed [val val val] and bare "val val val" formats
 static SERIES_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^SERIES(\d)\s*=\s*(?:\[([^\]]+)\]|([0-9][0-9 ]*))").unwrap());
+    LazyLock::new(|| Regex::new(r"^SERIES(\d+)\s*=\s*(?:\[([^\]]+)\]|([0-9][0-9 ]*))").unwrap());
 
 static GAIN_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^GAIN=(\d+)").unwrap());
 
@@ -17,19 +23,31 @@ static FADC_REGEX: LazyLock<Regex> =
 
 static COUNT_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^COUNT=(\d+)").unwrap());
 
+static CYCLE_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^CYCLE=(\d+)$").unwrap());
+
+static VERSION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^VERSION\s*=\s*(\S+)$").unwrap());
+
 static MEASUREMENTS_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^MEASUREMENTS\s*=\s*\[([^\]]+)\]").unwrap());
 
 /// Parsed line variants from ATmega328P serial output
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParsedLine {
-    /// Series data: SERIES1/2/3 = [values]
+    /// Series data: SERIES<n> = [values]
     Series {
         number: u8,
         values: Vec<RawAdcValue>,
     },
     /// End of measurement cycle marker
     EndCycle,
+    /// Device cycle counter: CYCLE=<n> (newer firmware only)
+    CycleNumber(u32),
+    /// Shutter position: SHUTTER=OPEN or SHUTTER=CLOSED, sent by firmware
+    /// that interleaves dark-only cycles
+    Shutter(ShutterState),
+    /// Reply to the `VERSION` command
+    Version(FirmwareVersion),
     /// GAIN setting confirmation
     GainSet(u8),
     /// FADC setting confirmation
@@ -44,10 +62,45 @@ pub enum ParsedLine {
     Error(String),
     /// Measurement cycle missing warning
     MeasurementCycleMissing,
+    /// Firmware debug dump collected between DEBUG BEGIN and DEBUG END
+    DebugBlock { lines: Vec<String>, truncated: bool },
+    /// Line whose trailing checksum did not match; holds the raw line
+    ChecksumError(String),
+    /// Line that starts like a known message but does not fit its format
+    /// (strict parsing only)
+    Malformed(ProtocolError),
     /// Unrecognized line
     Unknown(String),
 }
 
+impl ParsedLine {
+    /// Device event log kind for firmware status lines
+    pub fn device_event_kind(&self) -> Option<DeviceEventKind> {
+        match self {
+            ParsedLine::Error(_) => Some(DeviceEventKind::Error),
+            ParsedLine::MeasurementCycleMissing => Some(DeviceEventKind::CycleMissing),
+            ParsedLine::AdcReady => Some(DeviceEventKind::AdcReady),
+            _ => None,
+        }
+    }
+
+    /// Diagnostics kind for lines that point at a protocol problem.
+    /// Blank lines are not counted.
+    pub fn issue_kind(&self) -> Option<ProtocolIssueKind> {
+        match self {
+            ParsedLine::Unknown(line) if !line.is_empty() => Some(ProtocolIssueKind::Unknown),
+            ParsedLine::Error(_) => Some(ProtocolIssueKind::Error),
+            ParsedLine::MeasurementCycleMissing => Some(ProtocolIssueKind::CycleMissing),
+            ParsedLine::ChecksumError(_) => Some(ProtocolIssueKind::Checksum),
+            ParsedLine::Malformed(_) => Some(ProtocolIssueKind::Malformed),
+            ParsedLine::Series { values, .. } if values.iter().any(|&v| v > MAX_ADC_VALUE) => {
+                Some(ProtocolIssueKind::OutOfRange)
+            }
+            _ => None,
+        }
+    }
+}
+
 /// Parse space-separated values into a Vec<u32>
 fn parse_values(values_str: &str) -> Vec<RawAdcValue> {
     values_str
@@ -64,7 +117,12 @@ pub fn parse_line(input: &str) -> ParsedLine {
         return ParsedLine::Unknown(String::new());
     }
 
-    // SERIES1/2/3 = [values] or SERIES1/2/3 = values
+    // Optional *<CRC8/CRC16> suffix; a corrupt line must not reach the cycle
+    let Ok(trimmed) = strip_checksum(trimmed) else {
+        return ParsedLine::ChecksumError(trimmed.to_string());
+    };
+
+    // SERIESn = [values] or SERIESn = values
     if let Some(caps) = SERIES_REGEX.captures(trimmed) {
         let number: u8 = caps[1].parse().unwrap_or(0);
         let values_str = caps
@@ -81,6 +139,28 @@ pub fn parse_line(input: &str) -> ParsedLine {
         return ParsedLine::EndCycle;
     }
 
+    // CYCLE=<n>
+    if let Some(caps) = CYCLE_REGEX.captures(trimmed)
+        && let Ok(sequence) = caps[1].parse::<u32>()
+    {
+        return ParsedLine::CycleNumber(sequence);
+    }
+
+    // SHUTTER=OPEN or SHUTTER=CLOSED
+    if let Some(state) = trimmed
+        .strip_prefix("SHUTTER=")
+        .and_then(ShutterState::parse)
+    {
+        return ParsedLine::Shutter(state);
+    }
+
+    // VERSION=<major>.<minor>[.<patch>]
+    if let Some(caps) = VERSION_REGEX.captures(trimmed)
+        && let Some(version) = FirmwareVersion::parse(&caps[1])
+    {
+        return ParsedLine::Version(version);
+    }
+
     // GAIN=<value> or OK GAIN=<value>
     let trimmed = trimmed.strip_prefix("OK ").unwrap_or(trimmed);
     if let Some(caps) = GAIN_REGEX.captures(trimmed)
@@ -127,13 +207,206 @@ pub fn parse_line(input: &str) -> ParsedLine {
     ParsedLine::Unknown(trimmed.to_string())
 }
 
-/// State machine for accumulating a complete measurement cycle
+/// Parse a line, reporting lines that start like a known message but do not
+/// fit its format (`GAIN=abc`, `SERIES1 = [100 20`) as errors instead of
+/// `ParsedLine::Unknown` or silently dropping the bad values
+pub fn parse_line_strict(input: &str) -> Result<ParsedLine, ProtocolError> {
+    let parsed = parse_line(input);
+    if let ParsedLine::ChecksumError(_) = parsed {
+        return Ok(parsed);
+    }
+
+    let trimmed = input.trim();
+    let content = strip_checksum(trimmed).unwrap_or(trimmed);
+    let content = content.strip_prefix("OK ").unwrap_or(content);
+    check_format(content)?;
+    Ok(parsed)
+}
+
+/// Check the line against the format of the message its keyword names
+fn check_format(content: &str) -> Result<(), ProtocolError> {
+    let (keyword, well_formed) = if let Some(rest) = content.strip_prefix("SERIES") {
+        let well_formed = rest.split_once('=').is_some_and(|(number, values)| {
+            number.trim().parse::<u8>().is_ok_and(|n| n > 0) && values_well_formed(values)
+        });
+        ("SERIES", well_formed)
+    } else if let Some(rest) = content.strip_prefix("MEASUREMENTS") {
+        let well_formed = rest.trim_start().strip_prefix('=').is_some_and(|values| {
+            values.trim_start().starts_with('[') && values_well_formed(values)
+        });
+        ("MEASUREMENTS", well_formed)
+    } else if let Some(value) = content.strip_prefix("GAIN=") {
+        ("GAIN", value.parse::<u8>().is_ok())
+    } else if let Some(value) = content.strip_prefix("FADC=") {
+        ("FADC", value.parse::<f32>().is_ok())
+    } else if let Some(value) = content.strip_prefix("COUNT=") {
+        ("COUNT", value.parse::<u8>().is_ok())
+    } else if let Some(value) = content.strip_prefix("CYCLE=") {
+        ("CYCLE", value.parse::<u32>().is_ok())
+    } else if let Some(value) = content.strip_prefix("SHUTTER=") {
+        ("SHUTTER", ShutterState::parse(value).is_some())
+    } else if let Some(rest) = content.strip_prefix("VERSION") {
+        let well_formed = rest
+            .trim_start()
+            .strip_prefix('=')
+            .is_some_and(|version| FirmwareVersion::parse(version.trim()).is_some());
+        ("VERSION", well_formed)
+    } else {
+        return Ok(());
+    };
+
+    if well_formed {
+        Ok(())
+    } else {
+        Err(ProtocolError::MalformedLine {
+            keyword,
+            line: content.to_string(),
+        })
+    }
+}
+
+/// Whether `[v v v]` or bare `v v v` holds at least one value and nothing
+/// but values
+fn values_well_formed(values: &str) -> bool {
+    let values = values.trim();
+    let values = match values.strip_prefix('[') {
+        Some(inner) => match inner.strip_suffix(']') {
+            Some(inner) => inner,
+            None => return false,
+        },
+        None => values,
+    };
+
+    let mut tokens = values.split_whitespace().peekable();
+    tokens.peek().is_some() && tokens.all(|token| token.parse::<RawAdcValue>().is_ok())
+}
+
+/// Marker lines framing a firmware debug dump
+const DEBUG_BEGIN: &str = "DEBUG BEGIN";
+const DEBUG_END: &str = "DEBUG END";
+
+/// Upper bound on lines in one debug block, so a missing DEBUG END
+/// cannot swallow the measurement stream
+pub const MAX_DEBUG_BLOCK_LINES: usize = 256;
+
+/// Stateful line parser that folds multi-line firmware debug dumps
+/// into a single `ParsedLine::DebugBlock`
 #[derive(Debug, Default)]
+pub struct LineParser {
+    debug_lines: Option<Vec<String>>,
+    /// Report malformed lines as `ParsedLine::Malformed`
+    strict: bool,
+}
+
+impl LineParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse with `parse_line_strict` when `strict` is set
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Parse a line, returning None while inside a debug block
+    pub fn parse(&mut self, input: &str) -> Option<ParsedLine> {
+        let trimmed = input.trim();
+        let content = strip_checksum(trimmed).unwrap_or(trimmed);
+
+        let Some(lines) = &mut self.debug_lines else {
+            if content == DEBUG_BEGIN {
+                self.debug_lines = Some(Vec::new());
+                return None;
+            }
+            if self.strict {
+                return Some(parse_line_strict(trimmed).unwrap_or_else(ParsedLine::Malformed));
+            }
+            return Some(parse_line(trimmed));
+        };
+
+        if content == DEBUG_END {
+            let lines = self.debug_lines.take().unwrap_or_default();
+            return Some(ParsedLine::DebugBlock {
+                lines,
+                truncated: false,
+            });
+        }
+
+        lines.push(content.to_string());
+        if lines.len() < MAX_DEBUG_BLOCK_LINES {
+            return None;
+        }
+
+        let lines = self.debug_lines.take().unwrap_or_default();
+        Some(ParsedLine::DebugBlock {
+            lines,
+            truncated: true,
+        })
+    }
+
+    /// Whether the parser is currently inside a debug block
+    pub fn in_debug_block(&self) -> bool {
+        self.debug_lines.is_some()
+    }
+}
+
+/// Partial cycle dropped because `END_CYCLE` did not arrive in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct StalePartialCycle {
+    /// Series numbers that had been received
+    pub received: Vec<u8>,
+    /// Time since the first series of the partial cycle
+    pub age: Duration,
+}
+
+impl StalePartialCycle {
+    pub fn describe(&self) -> String {
+        format!(
+            "partial cycle discarded after {:.1}s without END_CYCLE (received series {:?})",
+            self.age.num_milliseconds() as f64 / 1000.0,
+            self.received
+        )
+    }
+}
+
+/// State machine for accumulating a complete measurement cycle
+#[derive(Debug)]
 pub struct CycleAccumulator {
-    series1: Option<Vec<RawAdcValue>>,
-    series2: Option<Vec<RawAdcValue>>,
-    series3: Option<Vec<RawAdcValue>>,
+    /// Series received in the current cycle, by SERIES number
+    series: BTreeMap<u8, Vec<RawAdcValue>>,
+    /// SERIES1..=series_count must all arrive before `END_CYCLE` completes
+    /// the cycle
+    series_count: u8,
     timestamp: Option<DateTime<Utc>>,
+    /// `CYCLE=<n>` seen during the current cycle
+    sequence: Option<u32>,
+    /// Last `SHUTTER=` state, kept across cycles until the next report
+    shutter: Option<ShutterState>,
+    /// When the first series of the current partial cycle arrived
+    started_at: Option<DateTime<Utc>>,
+    /// Partial cycles older than this are dropped by `expire`
+    timeout: Option<Duration>,
+    /// Handling of values above `MAX_ADC_VALUE`
+    out_of_range: OutOfRangePolicy,
+    /// The current cycle had an out-of-range value under `RejectCycle`
+    rejected: bool,
+}
+
+impl Default for CycleAccumulator {
+    fn default() -> Self {
+        Self {
+            series: BTreeMap::new(),
+            series_count: DEFAULT_SERIES_COUNT,
+            timestamp: None,
+            sequence: None,
+            shutter: None,
+            started_at: None,
+            timeout: None,
+            out_of_range: OutOfRangePolicy::default(),
+            rejected: false,
+        }
+    }
 }
 
 impl CycleAccumulator {
@@ -141,27 +414,50 @@ impl CycleAccumulator {
         Self::default()
     }
 
+    /// Accumulator that drops partial cycles older than `timeout`
+    pub fn with_timeout(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            ..Self::default()
+        }
+    }
+
+    /// Expect SERIES1..=`count` in every cycle
+    pub fn with_series_count(mut self, count: u8) -> Self {
+        self.series_count = count;
+        self
+    }
+
+    /// Handle values above `MAX_ADC_VALUE` with `policy`
+    pub fn with_out_of_range(mut self, policy: OutOfRangePolicy) -> Self {
+        self.out_of_range = policy;
+        self
+    }
+
+    /// Drop the partial cycle if it is older than the timeout at `now`, so
+    /// series from before e.g. a device brownout do not end up in the next
+    /// cycle. Call before each line and while the source is idle.
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Option<StalePartialCycle> {
+        let (timeout, started_at) = (self.timeout?, self.started_at?);
+        let age = now - started_at;
+        if age <= timeout {
+            return None;
+        }
+
+        let received = self.series.keys().copied().collect();
+        self.reset();
+        Some(StalePartialCycle { received, age })
+    }
+
     /// Process a parsed line and return a complete cycle if ready
     pub fn process_line(&mut self, line: ParsedLine) -> Option<MeasurementCycle> {
-        match line {
-            ParsedLine::Series { number: 1, values } => {
-                if self.series1.is_none() {
-                    self.timestamp = Some(Utc::now());
-                }
-                self.series1 = Some(values);
-                None
-            }
-            ParsedLine::Series { number: 2, values } => {
-                self.series2 = Some(values);
-                None
-            }
-            ParsedLine::Series { number: 3, values } => {
-                self.series3 = Some(values);
-                None
-            }
-            ParsedLine::EndCycle => self.try_complete(),
-            _ => None,
-        }
+        let now = Utc::now();
+        // A repeated SERIES1 keeps the first arrival time
+        let series1_at = self
+            .timestamp
+            .filter(|_| self.series.contains_key(&1))
+            .unwrap_or(now);
+        self.accept(line, series1_at, now)
     }
 
     /// Process a parsed line with an external timestamp (for log playback)
@@ -169,19 +465,44 @@ impl CycleAccumulator {
         &mut self,
         line: ParsedLine,
         timestamp: DateTime<Utc>,
+    ) -> Option<MeasurementCycle> {
+        self.accept(line, timestamp, timestamp)
+    }
+
+    /// `series1_at` stamps the cycle when the line is SERIES1; `received_at`
+    /// starts the timeout clock
+    fn accept(
+        &mut self,
+        line: ParsedLine,
+        series1_at: DateTime<Utc>,
+        received_at: DateTime<Utc>,
     ) -> Option<MeasurementCycle> {
         match line {
-            ParsedLine::Series { number: 1, values } => {
-                self.timestamp = Some(timestamp);
-                self.series1 = Some(values);
+            ParsedLine::Series { number, mut values } => {
+                if !self.has_partial_data() {
+                    self.started_at = Some(received_at);
+                }
+                if number == 1 {
+                    self.timestamp = Some(series1_at);
+                }
+                if values.iter().any(|&v| v > MAX_ADC_VALUE) {
+                    match self.out_of_range {
+                        OutOfRangePolicy::RejectValue => values.retain(|&v| v <= MAX_ADC_VALUE),
+                        OutOfRangePolicy::RejectCycle => self.rejected = true,
+                        OutOfRangePolicy::Clamp => {
+                            values.iter_mut().for_each(|v| *v = (*v).min(MAX_ADC_VALUE))
+                        }
+                    }
+                }
+                self.series.insert(number, values);
                 None
             }
-            ParsedLine::Series { number: 2, values } => {
-                self.series2 = Some(values);
+            ParsedLine::CycleNumber(sequence) => {
+                self.sequence = Some(sequence);
                 None
             }
-            ParsedLine::Series { number: 3, values } => {
-                self.series3 = Some(values);
+            ParsedLine::Shutter(state) => {
+                self.shutter = Some(state);
                 None
             }
             ParsedLine::EndCycle => self.try_complete(),
@@ -190,47 +511,51 @@ impl CycleAccumulator {
     }
 
     fn try_complete(&mut self) -> Option<MeasurementCycle> {
-        // Only take values if all series are present
-        if self.series1.is_none() || self.series2.is_none() || self.series3.is_none() {
+        // Only take values if all series are present; a dark-only cycle
+        // behind a closed shutter may carry fewer
+        let complete = if self.shutter == Some(ShutterState::Closed) {
+            self.has_partial_data()
+        } else {
+            self.missing_series().is_empty()
+        };
+        if !complete {
+            return None;
+        }
+        if self.rejected {
+            self.reset();
             return None;
         }
 
-        let s1 = self.series1.take().unwrap();
-        let s2 = self.series2.take().unwrap();
-        let s3 = self.series3.take().unwrap();
+        let series = std::mem::take(&mut self.series)
+            .into_iter()
+            .map(|(number, values)| (number, SeriesData::new(values)))
+            .collect();
         let timestamp = self.timestamp.take().unwrap_or_else(Utc::now);
+        self.started_at = None;
 
-        Some(MeasurementCycle::with_timestamp(
-            timestamp,
-            SeriesData::new(s1),
-            SeriesData::new(s2),
-            SeriesData::new(s3),
-        ))
+        Some(MeasurementCycle {
+            sequence: self.sequence.take(),
+            shutter: self.shutter,
+            ..MeasurementCycle::from_series(timestamp, series)
+        })
     }
 
     pub fn reset(&mut self) {
-        self.series1 = None;
-        self.series2 = None;
-        self.series3 = None;
+        self.series.clear();
         self.timestamp = None;
+        self.sequence = None;
+        self.started_at = None;
+        self.rejected = false;
     }
 
     pub fn has_partial_data(&self) -> bool {
-        self.series1.is_some() || self.series2.is_some() || self.series3.is_some()
+        !self.series.is_empty()
     }
 
     pub fn missing_series(&self) -> Vec<u8> {
-        let mut missing = Vec::new();
-        if self.series1.is_none() {
-            missing.push(1);
-        }
-        if self.series2.is_none() {
-            missing.push(2);
-        }
-        if self.series3.is_none() {
-            missing.push(3);
-        }
-        missing
+        (1..=self.series_count)
+            .filter(|number| !self.series.contains_key(number))
+            .collect()
     }
 }
 
@@ -366,6 +691,24 @@ mod tests {
         assert_eq!(parse_line("   "), ParsedLine::Unknown(String::new()));
     }
 
+    #[test]
+    fn test_issue_kind() {
+        assert_eq!(
+            parse_line("some random text").issue_kind(),
+            Some(ProtocolIssueKind::Unknown)
+        );
+        assert_eq!(
+            parse_line("ERROR Unknown command").issue_kind(),
+            Some(ProtocolIssueKind::Error)
+        );
+        assert_eq!(
+            parse_line("Measurement cycle is missing").issue_kind(),
+            Some(ProtocolIssueKind::CycleMissing)
+        );
+        assert_eq!(parse_line("").issue_kind(), None);
+        assert_eq!(parse_line("END_CYCLE").issue_kind(), None);
+    }
+
     #[test]
     fn test_parse_measurements() {
         assert_eq!(
@@ -374,6 +717,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_line_parser_collects_debug_block() {
+        let mut parser = LineParser::new();
+
+        assert!(parser.parse("DEBUG BEGIN").is_none());
+        assert!(parser.in_debug_block());
+        assert!(parser.parse("reg0=0x1F").is_none());
+        assert!(parser.parse("SERIES1 = [1 2 3]").is_none());
+
+        assert_eq!(
+            parser.parse("DEBUG END"),
+            Some(ParsedLine::DebugBlock {
+                lines: vec!["reg0=0x1F".to_string(), "SERIES1 = [1 2 3]".to_string()],
+                truncated: false,
+            })
+        );
+        assert!(!parser.in_debug_block());
+        assert_eq!(parser.parse("END_CYCLE"), Some(ParsedLine::EndCycle));
+    }
+
+    #[test]
+    fn test_line_parser_truncates_unterminated_block() {
+        let mut parser = LineParser::new();
+        parser.parse("DEBUG BEGIN");
+
+        for i in 0..MAX_DEBUG_BLOCK_LINES - 1 {
+            assert!(parser.parse(&format!("line {i}")).is_none());
+        }
+
+        let Some(ParsedLine::DebugBlock { lines, truncated }) = parser.parse("last") else {
+            panic!("Expected truncated debug block");
+        };
+        assert!(truncated);
+        assert_eq!(lines.len(), MAX_DEBUG_BLOCK_LINES);
+        assert!(!parser.in_debug_block());
+    }
+
+    #[test]
+    fn test_line_parser_passes_through_normal_lines() {
+        let mut parser = LineParser::new();
+        assert_eq!(parser.parse("GAIN=4"), Some(ParsedLine::GainSet(4)));
+    }
+
     #[test]
     fn test_cycle_accumulator_complete_cycle() {
         let mut acc = CycleAccumulator::new();
@@ -403,9 +789,9 @@ mod tests {
         );
 
         let cycle = acc.process_line(ParsedLine::EndCycle).unwrap();
-        assert_eq!(cycle.dark.values, vec![100, 101, 102]);
-        assert_eq!(cycle.full.values, vec![8000, 8001, 8002]);
-        assert_eq!(cycle.sample.values, vec![4000, 4001, 4002]);
+        assert_eq!(cycle.dark().values, vec![100, 101, 102]);
+        assert_eq!(cycle.full().values, vec![8000, 8001, 8002]);
+        assert_eq!(cycle.sample().values, vec![4000, 4001, 4002]);
     }
 
     #[test]
@@ -441,6 +827,37 @@ mod tests {
         assert_eq!(acc.missing_series(), vec![1, 2, 3]);
     }
 
+    #[test]
+    fn test_cycle_accumulator_four_series() {
+        let mut acc = CycleAccumulator::new().with_series_count(4);
+        for number in 1..=3 {
+            acc.process_line(ParsedLine::Series {
+                number,
+                values: vec![u32::from(number) * 1000],
+            });
+        }
+
+        assert!(acc.process_line(ParsedLine::EndCycle).is_none());
+        assert_eq!(acc.missing_series(), vec![4]);
+
+        acc.process_line(parse_line("SERIES4 = [7000]"));
+        let cycle = acc.process_line(ParsedLine::EndCycle).unwrap();
+        assert_eq!(cycle.series.len(), 4);
+        assert_eq!(cycle.series(4).unwrap().values, vec![7000]);
+        assert_eq!(cycle.sample().values, vec![3000]);
+    }
+
+    #[test]
+    fn test_parse_multi_digit_series_number() {
+        assert_eq!(
+            parse_line("SERIES12 = [1 2]"),
+            ParsedLine::Series {
+                number: 12,
+                values: vec![1, 2]
+            }
+        );
+    }
+
     #[test]
     fn test_cycle_accumulator_ignores_non_series_lines() {
         let mut acc = CycleAccumulator::new();
@@ -449,4 +866,278 @@ mod tests {
         assert!(acc.process_line(ParsedLine::AdcReady).is_none());
         assert!(!acc.has_partial_data());
     }
+
+    #[test]
+    fn test_parse_line_with_checksum() {
+        use crate::protocol::checksum::crc8;
+
+        let line = "SERIES1 = [100 101 102]";
+        let valid = format!("{line}*{:02X}", crc8(line.as_bytes()));
+        assert_eq!(
+            parse_line(&valid),
+            ParsedLine::Series {
+                number: 1,
+                values: vec![100, 101, 102]
+            }
+        );
+
+        let corrupt = valid.replace("101", "181");
+        assert_eq!(
+            parse_line(&corrupt),
+            ParsedLine::ChecksumError(corrupt.clone())
+        );
+        assert_eq!(
+            parse_line(&corrupt).issue_kind(),
+            Some(ProtocolIssueKind::Checksum)
+        );
+
+        let mut parser = LineParser::new();
+        let begin = format!("DEBUG BEGIN*{:02X}", crc8(b"DEBUG BEGIN"));
+        let end = format!("DEBUG END*{:02X}", crc8(b"DEBUG END"));
+        assert_eq!(parser.parse(&begin), None);
+        assert_eq!(parser.parse("reg0=0x1F"), None);
+        assert!(matches!(
+            parser.parse(&end),
+            Some(ParsedLine::DebugBlock { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let ParsedLine::Version(version) = parse_line("VERSION=1.3.0") else {
+            panic!("expected a version line");
+        };
+        assert_eq!((version.major, version.minor), (1, 3));
+
+        assert!(matches!(parse_line("VERSION=dev"), ParsedLine::Unknown(_)));
+    }
+
+    #[test]
+    fn test_parse_cycle_number() {
+        assert_eq!(parse_line("CYCLE=42"), ParsedLine::CycleNumber(42));
+        assert_eq!(
+            parse_line("CYCLE=abc"),
+            ParsedLine::Unknown("CYCLE=abc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cycle_accumulator_sequence() {
+        let mut acc = CycleAccumulator::new();
+        acc.process_line(ParsedLine::CycleNumber(7));
+        for number in 1..=3 {
+            acc.process_line(ParsedLine::Series {
+                number,
+                values: vec![1],
+            });
+        }
+        let cycle = acc.process_line(ParsedLine::EndCycle).unwrap();
+        assert_eq!(cycle.sequence, Some(7));
+
+        // Firmware without CYCLE=<n>
+        for number in 1..=3 {
+            acc.process_line(ParsedLine::Series {
+                number,
+                values: vec![1],
+            });
+        }
+        let cycle = acc.process_line(ParsedLine::EndCycle).unwrap();
+        assert_eq!(cycle.sequence, None);
+    }
+
+    #[test]
+    fn test_parse_shutter() {
+        assert_eq!(
+            parse_line("SHUTTER=CLOSED"),
+            ParsedLine::Shutter(ShutterState::Closed)
+        );
+        assert_eq!(
+            parse_line("SHUTTER=open"),
+            ParsedLine::Shutter(ShutterState::Open)
+        );
+        assert!(matches!(parse_line("SHUTTER=ajar"), ParsedLine::Unknown(_)));
+        assert!(parse_line_strict("SHUTTER=ajar").is_err());
+    }
+
+    #[test]
+    fn test_cycle_accumulator_shutter_state() {
+        let mut acc = CycleAccumulator::new();
+        let series = |number| ParsedLine::Series {
+            number,
+            values: vec![1],
+        };
+
+        // Dark-only cycle: one series is enough behind a closed shutter
+        acc.process_line(ParsedLine::Shutter(ShutterState::Closed));
+        acc.process_line(series(1));
+        let cycle = acc.process_line(ParsedLine::EndCycle).unwrap();
+        assert_eq!(cycle.shutter, Some(ShutterState::Closed));
+        assert_eq!(cycle.series.len(), 1);
+
+        // The state holds until the next report
+        acc.process_line(series(1));
+        let cycle = acc.process_line(ParsedLine::EndCycle).unwrap();
+        assert_eq!(cycle.shutter, Some(ShutterState::Closed));
+
+        acc.process_line(ParsedLine::Shutter(ShutterState::Open));
+        acc.process_line(series(1));
+        assert!(acc.process_line(ParsedLine::EndCycle).is_none());
+        for number in 1..=3 {
+            acc.process_line(series(number));
+        }
+        let cycle = acc.process_line(ParsedLine::EndCycle).unwrap();
+        assert_eq!(cycle.shutter, Some(ShutterState::Open));
+    }
+
+    #[test]
+    fn test_cycle_accumulator_expires_stale_partial_cycle() {
+        let mut acc = CycleAccumulator::with_timeout(Some(Duration::seconds(5)));
+        let t0 = Utc::now();
+        let series = |number| ParsedLine::Series {
+            number,
+            values: vec![number as u32; 3],
+        };
+
+        // SERIES1 and SERIES2 arrive, then the device browns out
+        acc.process_line_with_timestamp(series(1), t0);
+        acc.process_line_with_timestamp(series(2), t0 + Duration::seconds(1));
+        assert!(acc.expire(t0 + Duration::seconds(5)).is_none());
+
+        let stale = acc.expire(t0 + Duration::seconds(6)).unwrap();
+        assert_eq!(stale.received, vec![1, 2]);
+        assert_eq!(stale.age, Duration::seconds(6));
+        assert!(!acc.has_partial_data());
+
+        // The next cycle starts clean
+        let t1 = t0 + Duration::seconds(20);
+        acc.process_line_with_timestamp(series(3), t1);
+        assert!(
+            acc.process_line_with_timestamp(ParsedLine::EndCycle, t1)
+                .is_none()
+        );
+        assert_eq!(acc.missing_series(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_cycle_accumulator_timeout_restarts_per_cycle() {
+        let mut acc = CycleAccumulator::with_timeout(Some(Duration::seconds(5)));
+        let t0 = Utc::now();
+        for number in 1..=3 {
+            acc.process_line_with_timestamp(
+                ParsedLine::Series {
+                    number,
+                    values: vec![1],
+                },
+                t0,
+            );
+        }
+        assert!(
+            acc.process_line_with_timestamp(ParsedLine::EndCycle, t0)
+                .is_some()
+        );
+
+        // Long after a completed cycle there is nothing to expire
+        assert!(acc.expire(t0 + Duration::seconds(60)).is_none());
+        // Without a timeout partial cycles are kept
+        let mut acc = CycleAccumulator::new();
+        acc.process_line_with_timestamp(
+            ParsedLine::Series {
+                number: 1,
+                values: vec![1],
+            },
+            t0,
+        );
+        assert!(acc.expire(t0 + Duration::days(1)).is_none());
+    }
+
+    fn out_of_range_cycle(policy: OutOfRangePolicy) -> Option<MeasurementCycle> {
+        let mut acc = CycleAccumulator::new().with_out_of_range(policy);
+        acc.process_line(parse_line("SERIES1 = [100 16777216 100]"));
+        acc.process_line(parse_line("SERIES2 = [1000]"));
+        acc.process_line(parse_line("SERIES3 = [500]"));
+        let cycle = acc.process_line(ParsedLine::EndCycle);
+        assert!(!acc.has_partial_data());
+        cycle
+    }
+
+    #[test]
+    fn test_out_of_range_policies() {
+        let cycle = out_of_range_cycle(OutOfRangePolicy::RejectValue).unwrap();
+        assert_eq!(cycle.dark().values, vec![100, 100]);
+
+        let cycle = out_of_range_cycle(OutOfRangePolicy::Clamp).unwrap();
+        assert_eq!(cycle.dark().values, vec![100, MAX_ADC_VALUE, 100]);
+
+        assert!(out_of_range_cycle(OutOfRangePolicy::RejectCycle).is_none());
+    }
+
+    #[test]
+    fn test_out_of_range_issue_kind() {
+        assert_eq!(
+            parse_line("SERIES2 = [16777216]").issue_kind(),
+            Some(ProtocolIssueKind::OutOfRange)
+        );
+        assert_eq!(parse_line("SERIES2 = [16777215]").issue_kind(), None);
+    }
+
+    #[test]
+    fn test_parse_line_strict_rejects_malformed() {
+        for line in [
+            "GAIN=abc",
+            "OK GAIN=300",
+            "FADC=fast",
+            "COUNT=",
+            "CYCLE=-1",
+            "VERSION=beta",
+            "SERIES1 = [100 200",
+            "SERIES1 = [100 2x0 300]",
+            "SERIES1 = []",
+            "SERIESx = [1 2]",
+            "SERIES0 = [1 2]",
+            "MEASUREMENTS = [1 2",
+        ] {
+            let keyword = line.trim_start_matches("OK ")[..4].to_string();
+            match parse_line_strict(line) {
+                Err(ProtocolError::MalformedLine { keyword: k, .. }) => {
+                    assert!(k.starts_with(&keyword), "{line}: {k}")
+                }
+                other => panic!("{line}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_line_strict_accepts_valid() {
+        for line in [
+            "SERIES1 = [100 200 300]",
+            "SERIES2 = 100 200",
+            "OK GAIN=4",
+            "FADC=62.5",
+            "CYCLE=7",
+            "VERSION=1.2.3",
+            "END_CYCLE",
+            "ERROR adc timeout",
+            "",
+        ] {
+            assert_eq!(parse_line_strict(line), Ok(parse_line(line)), "{line}");
+        }
+        // Lines that match no message stay unknown
+        assert_eq!(
+            parse_line_strict("hello"),
+            Ok(ParsedLine::Unknown("hello".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_line_parser_strict() {
+        let mut parser = LineParser::new().with_strict(true);
+        let parsed = parser.parse("GAIN=abc").unwrap();
+        assert_eq!(parsed.issue_kind(), Some(ProtocolIssueKind::Malformed));
+
+        let mut parser = LineParser::new();
+        assert_eq!(
+            parser.parse("GAIN=abc"),
+            Some(ParsedLine::Unknown("GAIN=abc".to_string()))
+        );
+    }
 }
@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +9,22 @@ use crate::error::ProtocolError;
 /// Raw ADC values from ATmega328P (24-bit, 0-16777215)
 pub type RawAdcValue = u32;
 
+/// Maximum raw ADC value (24-bit) — indicates saturation/clipping
+pub const MAX_ADC_VALUE: RawAdcValue = 16_777_215;
+
+/// What to do with raw values above `MAX_ADC_VALUE`, which only a garbled
+/// line can produce
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutOfRangePolicy {
+    /// Drop the value, keep the rest of the series (default)
+    #[default]
+    RejectValue,
+    /// Drop the whole cycle
+    RejectCycle,
+    /// Replace the value with `MAX_ADC_VALUE`
+    Clamp,
+}
+
 /// Validated GAIN values for AD7793 ADC
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum Gain {
@@ -156,8 +175,11 @@ impl TryFrom<u8> for MeasurementCount {
     }
 }
 
-/// A single series of measurements (dark, full, or sample)
-#[derive(Debug, Clone, PartialEq)]
+/// Number of SERIES lines per cycle sent by the original firmware
+pub const DEFAULT_SERIES_COUNT: u8 = 3;
+
+/// A single series of measurements (dark, full, sample, or reference)
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct SeriesData {
     pub values: Vec<RawAdcValue>,
 }
@@ -181,42 +203,584 @@ impl SeriesData {
     }
 }
 
+/// Which SERIES number maps to each measurement role
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeriesMapping {
+    /// SERIES number for dark measurement (default: 1)
+    pub dark: u8,
+    /// SERIES number for full/reference measurement (default: 2)
+    pub full: u8,
+    /// SERIES number for sample measurement (default: 3)
+    pub sample: u8,
+    /// SERIES number of the reference detector, on firmware that has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<u8>,
+}
+
+impl Default for SeriesMapping {
+    fn default() -> Self {
+        Self {
+            dark: 1,
+            full: 2,
+            sample: 3,
+            reference: None,
+        }
+    }
+}
+
+impl SeriesMapping {
+    /// Whether `role` is one of the levels that stored references replace
+    pub fn is_reference_level(role: &str) -> bool {
+        matches!(role, "dark" | "full")
+    }
+
+    /// Roles with their SERIES numbers
+    pub fn roles(&self) -> impl Iterator<Item = (&'static str, u8)> {
+        [
+            ("dark", Some(self.dark)),
+            ("full", Some(self.full)),
+            ("sample", Some(self.sample)),
+            ("reference", self.reference),
+        ]
+        .into_iter()
+        .filter_map(|(role, number)| Some((role, number?)))
+    }
+
+    /// Check every role points at a distinct series the firmware sends.
+    /// With `sample_only`, for stored dark and full levels, the dark and
+    /// full roles are not checked.
+    pub fn validate(&self, series_count: u8, sample_only: bool) -> Result<(), String> {
+        let mut seen = Vec::new();
+        for (role, number) in self
+            .roles()
+            .filter(|(role, _)| !(sample_only && Self::is_reference_level(role)))
+        {
+            if !(1..=series_count).contains(&number) {
+                return Err(format!(
+                    "{role} maps to SERIES{number}, but the firmware sends SERIES1-{series_count}"
+                ));
+            }
+            if seen.contains(&number) {
+                return Err(format!("SERIES{number} is mapped to more than one role"));
+            }
+            seen.push(number);
+        }
+        Ok(())
+    }
+}
+
+/// Empty series returned for a role whose series is absent
+static NO_SERIES: SeriesData = SeriesData { values: Vec::new() };
+
+/// Next `MeasurementCycle::id`, shared by every data source of the process
+static NEXT_CYCLE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Shutter position reported by `SHUTTER=OPEN` / `SHUTTER=CLOSED`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutterState {
+    Open,
+    /// Light blocked: every series of the cycle reads dark
+    Closed,
+}
+
+impl ShutterState {
+    /// Parse `OPEN` or `CLOSED`, in any case
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.eq_ignore_ascii_case("OPEN") {
+            Some(ShutterState::Open)
+        } else if s.eq_ignore_ascii_case("CLOSED") {
+            Some(ShutterState::Closed)
+        } else {
+            None
+        }
+    }
+}
+
 /// Complete measurement cycle from ATmega328P
 #[derive(Debug, Clone)]
 pub struct MeasurementCycle {
+    /// Assigned when the cycle is complete, increasing across data sources
+    /// and restarts, so the logs of one cycle can be correlated
+    pub id: u64,
     pub timestamp: DateTime<Utc>,
-    pub dark: SeriesData,   // SERIES1
-    pub full: SeriesData,   // SERIES2
-    pub sample: SeriesData, // SERIES3
+    /// Every series of the cycle, by SERIES number
+    pub series: BTreeMap<u8, SeriesData>,
+    /// Role of each series; SERIES1/2/3 = dark/full/sample until the data
+    /// loop applies the configured mapping
+    pub roles: SeriesMapping,
+    /// Device cycle counter from `CYCLE=<n>`, when the firmware sends it
+    pub sequence: Option<u32>,
+    /// Shutter state last reported before the cycle ended, when the
+    /// firmware reports it
+    pub shutter: Option<ShutterState>,
 }
 
 impl MeasurementCycle {
+    /// Cycle from the series as received, with the default roles
+    pub fn from_series(timestamp: DateTime<Utc>, series: BTreeMap<u8, SeriesData>) -> Self {
+        Self {
+            id: NEXT_CYCLE_ID.fetch_add(1, Ordering::Relaxed),
+            timestamp,
+            series,
+            roles: SeriesMapping::default(),
+            sequence: None,
+            shutter: None,
+        }
+    }
+
+    /// Three-series cycle: SERIES1 dark, SERIES2 full, SERIES3 sample
     pub fn with_timestamp(
         timestamp: DateTime<Utc>,
         dark: SeriesData,
         full: SeriesData,
         sample: SeriesData,
     ) -> Self {
-        Self {
+        Self::from_series(
             timestamp,
-            dark,
-            full,
-            sample,
+            BTreeMap::from([(1, dark), (2, full), (3, sample)]),
+        )
+    }
+
+    /// Series by SERIES number
+    pub fn series(&self, number: u8) -> Option<&SeriesData> {
+        self.series.get(&number)
+    }
+
+    fn role(&self, number: u8) -> &SeriesData {
+        self.series(number).unwrap_or(&NO_SERIES)
+    }
+
+    pub fn dark(&self) -> &SeriesData {
+        self.role(self.roles.dark)
+    }
+
+    pub fn full(&self) -> &SeriesData {
+        self.role(self.roles.full)
+    }
+
+    pub fn sample(&self) -> &SeriesData {
+        self.role(self.roles.sample)
+    }
+
+    /// Reference detector series, when mapped
+    pub fn reference(&self) -> Option<&SeriesData> {
+        self.series(self.roles.reference?)
+    }
+
+    /// Roles whose series is not in this cycle
+    pub fn missing_roles(&self) -> Vec<&'static str> {
+        self.roles
+            .roles()
+            .filter(|(_, number)| !self.series.contains_key(number))
+            .map(|(role, _)| role)
+            .collect()
+    }
+}
+
+/// Firmware version from the `VERSION=<major>.<minor>[.<patch>]` reply
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareVersion {
+    /// Version text as sent by the firmware
+    pub raw: String,
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl FirmwareVersion {
+    /// Parse `1.4`, `1.4.2` or `v2.0.1-rc1`; suffixes after the digits of a
+    /// component are ignored
+    pub fn parse(text: &str) -> Option<Self> {
+        let raw = text.trim();
+        let numbers = raw.strip_prefix(['v', 'V']).unwrap_or(raw);
+        let mut components = numbers.split('.').map(|component| {
+            let digits = component
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(component, |end| &component[..end]);
+            digits.parse::<u32>().ok()
+        });
+
+        let major = components.next()??;
+        let minor = components.next().unwrap_or(Some(0))?;
+        let patch = components.next().unwrap_or(Some(0))?;
+        Some(Self {
+            raw: raw.to_string(),
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+/// ADC settings the firmware confirmed with `GAIN=`, `FADC=` and `COUNT=`
+/// lines after they were sent at startup
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ConfirmedSettings {
+    pub gain: u8,
+    pub fadc: f32,
+    pub count: u8,
+    pub confirmed_at: DateTime<Utc>,
+}
+
+/// Difference up to which a confirmed FADC matches the value sent, as the
+/// firmware rounds it
+pub const FADC_TOLERANCE: f32 = 0.1;
+
+impl ConfirmedSettings {
+    /// Whether these are the settings `gain`, `fadc` and `count`
+    pub fn matches(&self, gain: u8, fadc: f32, count: u8) -> bool {
+        self.gain == gain && (self.fadc - fadc).abs() < FADC_TOLERANCE && self.count == count
+    }
+}
+
+/// Multi-line firmware debug dump (`DEBUG BEGIN` ... `DEBUG END`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DebugBlock {
+    pub timestamp: DateTime<Utc>,
+    pub lines: Vec<String>,
+    /// Set when the block hit the line limit before `DEBUG END`
+    pub truncated: bool,
+}
+
+/// Kind of firmware status line kept in the device event log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceEventKind {
+    /// `ERROR <message>`
+    Error,
+    /// `Measurement cycle is missing`
+    CycleMissing,
+    /// `ADC ready`, sent when the firmware (re)initialises the ADC
+    AdcReady,
+}
+
+/// Firmware status line from a data source, for `GET /diagnostics/events`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceEvent {
+    pub timestamp: DateTime<Utc>,
+    /// Data source the line came from (port name or log file)
+    pub source: String,
+    pub kind: DeviceEventKind,
+    pub line: String,
+}
+
+/// Kind of line counted by the protocol diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolIssueKind {
+    /// Line matching no known message
+    Unknown,
+    /// `ERROR <message>` from the firmware
+    Error,
+    /// `Measurement cycle is missing` from the firmware
+    CycleMissing,
+    /// Partial cycle dropped after the cycle timeout without `END_CYCLE`
+    StaleCycle,
+    /// Line rejected because its trailing checksum did not match
+    Checksum,
+    /// Series line with a value above the 24-bit ADC range
+    OutOfRange,
+    /// Line starting like a known message but not fitting its format
+    /// (strict parsing only)
+    Malformed,
+}
+
+/// Line from a data source that did not fit the expected protocol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolIssue {
+    pub timestamp: DateTime<Utc>,
+    /// Data source the line came from (port name or log file)
+    pub source: String,
+    pub kind: ProtocolIssueKind,
+    pub line: String,
+}
+
+/// Why a measurement failed validation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationCategory {
+    /// Means violate the configured dark/sample/full relationship
+    Relationship,
+    /// A raw value is at or near the top of the ADC range
+    Saturation,
+    /// A raw value is at or near zero
+    UnderRange,
+    /// Too few values of a series survived outlier exclusion
+    InsufficientSamples,
+    /// No stored reference for the current gain, with
+    /// `processing.references.mode = "stored"`
+    NoReference,
+}
+
+impl ValidationCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidationCategory::Relationship => "relationship",
+            ValidationCategory::Saturation => "saturation",
+            ValidationCategory::UnderRange => "under_range",
+            ValidationCategory::InsufficientSamples => "insufficient_samples",
+            ValidationCategory::NoReference => "no_reference",
+        }
+    }
+
+    /// Parse the `as_str` form
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "relationship" => Some(ValidationCategory::Relationship),
+            "saturation" => Some(ValidationCategory::Saturation),
+            "under_range" => Some(ValidationCategory::UnderRange),
+            "insufficient_samples" => Some(ValidationCategory::InsufficientSamples),
+            "no_reference" => Some(ValidationCategory::NoReference),
+            _ => None,
+        }
+    }
+}
+
+/// Spread of one series after outlier removal
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SeriesStatistics {
+    /// Sample standard deviation (0 for a single value)
+    pub std_dev: f64,
+    /// Number of values left after outlier removal
+    pub count: usize,
+}
+
+/// Quality information for a processed measurement
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MeasurementStatistics {
+    pub dark: SeriesStatistics,
+    pub full: SeriesStatistics,
+    pub sample: SeriesStatistics,
+    /// Standard uncertainty of the calibrated reading in percentage points,
+    /// propagated from the standard errors of the three means.
+    /// `None` when full and dark coincide.
+    pub uncertainty: Option<f64>,
+    /// Full-dark span over the noise of one full-minus-dark difference;
+    /// `None` when the series have no spread
+    #[serde(default)]
+    pub snr: Option<f64>,
+}
+
+/// Catalogue entry of a coating material, from `control.materials`. Given
+/// in the config as a table or, for the symbol alone, as a string.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "MaterialEntry")]
+pub struct Material {
+    /// Name the chamber endpoints use, e.g. `H`
+    pub symbol: String,
+    /// Descriptive name, e.g. `Ta2O5`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Expected refractive index at the control wavelength
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refractive_index: Option<f64>,
+    /// Target deposition rate in nm/s
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_rate: Option<f64>,
+    /// Control wavelength in nm switched to when the material is selected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control_wavelength: Option<f64>,
+}
+
+impl Material {
+    /// Entry with a symbol and no metadata
+    pub fn symbol(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            name: None,
+            refractive_index: None,
+            target_rate: None,
+            control_wavelength: None,
         }
     }
 }
 
+/// Either config form of a `Material`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaterialEntry {
+    Symbol(String),
+    Table {
+        symbol: String,
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        refractive_index: Option<f64>,
+        #[serde(default)]
+        target_rate: Option<f64>,
+        #[serde(default)]
+        control_wavelength: Option<f64>,
+    },
+}
+
+impl From<MaterialEntry> for Material {
+    fn from(entry: MaterialEntry) -> Self {
+        match entry {
+            MaterialEntry::Symbol(symbol) => Material::symbol(&symbol),
+            MaterialEntry::Table {
+                symbol,
+                name,
+                refractive_index,
+                target_rate,
+                control_wavelength,
+            } => Material {
+                symbol,
+                name,
+                refractive_index,
+                target_rate,
+                control_wavelength,
+            },
+        }
+    }
+}
+
+/// Series levels converted from ADC counts with `processing.units`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PhysicalLevels {
+    /// Gain the cycle was acquired with
+    pub gain: u8,
+    /// Input voltages, in volts
+    pub dark_voltage: f64,
+    pub full_voltage: f64,
+    pub sample_voltage: f64,
+    /// Photodiode currents, in amperes, when a transimpedance is configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dark_current: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_current: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_current: Option<f64>,
+}
+
+/// Series values left after outlier exclusion, as averaged into the means
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilteredSeries {
+    pub dark: Vec<f64>,
+    pub full: Vec<f64>,
+    pub sample: Vec<f64>,
+}
+
+/// Values dropped from one series by outlier exclusion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeriesExclusion {
+    /// Values in the raw series
+    pub total: usize,
+    /// Values dropped as outliers
+    pub excluded: usize,
+}
+
+/// Outlier exclusion result for one cycle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutlierExclusion {
+    pub dark: SeriesExclusion,
+    pub full: SeriesExclusion,
+    pub sample: SeriesExclusion,
+}
+
+impl OutlierExclusion {
+    /// Series with their metric label, in dark/full/sample order
+    pub fn series(&self) -> [(&'static str, SeriesExclusion); 3] {
+        [
+            ("dark", self.dark),
+            ("full", self.full),
+            ("sample", self.sample),
+        ]
+    }
+
+    /// Values dropped across all series
+    pub fn excluded(&self) -> usize {
+        self.dark.excluded + self.full.excluded + self.sample.excluded
+    }
+}
+
+/// Smoothed calibrated reading at this measurement, from the configured smoother
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SmoothedReading {
+    /// Smoothed calibrated reading (%)
+    pub value: f64,
+    /// Rate of change of the calibrated reading (% per second)
+    pub derivative: f64,
+}
+
+/// Rough thickness of the layer being deposited, counted from the turning
+/// points of its reading curve
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThicknessEstimate {
+    /// Layer the estimate is for, as in `LayerRecord::index`
+    pub layer: u32,
+    /// Extrema of the reading passed so far, a quarter wave each
+    pub turning_points: u32,
+    /// Optical thickness n·d in nm at the control wavelength
+    pub optical_nm: f64,
+    /// Physical thickness in nm, when the material's refractive index is known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical_nm: Option<f64>,
+}
+
 /// Processed measurement result after outlier exclusion and calibration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcessedMeasurement {
+    /// `MeasurementCycle::id` of the cycle; 0 when unknown, e.g. archived
+    /// before cycle IDs were recorded
+    #[serde(default)]
+    pub cycle_id: u64,
     pub timestamp: DateTime<Utc>,
+    /// Control wavelength in nm the reading was taken at; absent for
+    /// measurements archived before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wavelength: Option<f64>,
     pub dark_mean: f64,
     pub full_mean: f64,
     pub sample_mean: f64,
+    /// Mean of the reference detector series, when one is mapped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_mean: Option<f64>,
+    /// The means in volts (and amperes), when a reference voltage is
+    /// configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub physical: Option<PhysicalLevels>,
     /// Calibrated reading as percentage: (sample-dark)/(full-dark) * 100
     pub calibrated_reading: f64,
     pub is_valid: bool,
     pub validation_error: Option<String>,
+    /// Kind of validation failure, set together with `validation_error`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation_category: Option<ValidationCategory>,
+    /// Per-series spread and reading uncertainty, when computed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub statistics: Option<MeasurementStatistics>,
+    /// Signal-to-noise ratio below `validation.min_snr`; the measurement
+    /// stays valid, but its reading is less trustworthy
+    #[serde(default)]
+    pub low_snr: bool,
+    /// Taken within `validation.blanking_cycles` of a material change,
+    /// shutter move or deposition start; not pushed, alerted on or counted
+    /// in the validity totals
+    #[serde(default)]
+    pub transitional: bool,
+    /// A raw value of the cycle is at the top of the ADC range; not kept in
+    /// the archive
+    #[serde(default)]
+    pub clipped: bool,
+    /// Values dropped per series, when outlier exclusion ran
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outliers: Option<OutlierExclusion>,
+    /// Smoothed reading and derivative, once enough valid readings are buffered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smoothed: Option<SmoothedReading>,
+    /// Catalogue entry of the material in the chamber, when it has one;
+    /// not kept in the archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material_info: Option<Material>,
+    /// Layer thickness so far, when `processing.thickness` is enabled;
+    /// not kept in the archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thickness: Option<ThicknessEstimate>,
+    /// Filtered series values, kept for `monitoring.push_series`; not part
+    /// of events, history or the archive
+    #[serde(skip)]
+    pub series: Option<Box<FilteredSeries>>,
 }
 
 impl ProcessedMeasurement {
@@ -228,19 +792,44 @@ impl ProcessedMeasurement {
         calibrated_reading: f64,
     ) -> Self {
         Self {
+            cycle_id: 0,
             timestamp,
+            wavelength: None,
             dark_mean,
             full_mean,
             sample_mean,
+            reference_mean: None,
+            physical: None,
             calibrated_reading,
             is_valid: true,
             validation_error: None,
+            validation_category: None,
+            statistics: None,
+            low_snr: false,
+            transitional: false,
+            clipped: false,
+            outliers: None,
+            smoothed: None,
+            material_info: None,
+            thickness: None,
+            series: None,
         }
     }
 
-    pub fn with_error(mut self, error: String) -> Self {
+    pub fn with_statistics(mut self, statistics: MeasurementStatistics) -> Self {
+        self.statistics = Some(statistics);
+        self
+    }
+
+    /// Mark as failing the dark/sample/full relationship check
+    pub fn with_error(self, error: String) -> Self {
+        self.with_categorized_error(ValidationCategory::Relationship, error)
+    }
+
+    pub fn with_categorized_error(mut self, category: ValidationCategory, error: String) -> Self {
         self.is_valid = false;
         self.validation_error = Some(error);
+        self.validation_category = Some(category);
         self
     }
 }
@@ -306,9 +895,78 @@ mod tests {
             sample.clone(),
         );
 
-        assert_eq!(cycle.dark.values, dark.values);
-        assert_eq!(cycle.full.values, full.values);
-        assert_eq!(cycle.sample.values, sample.values);
+        assert_eq!(cycle.dark().values, dark.values);
+        assert_eq!(cycle.full().values, full.values);
+        assert_eq!(cycle.sample().values, sample.values);
+        assert!(cycle.reference().is_none());
+
+        // IDs increase with every cycle, whichever source made it
+        let next = MeasurementCycle::from_series(Utc::now(), BTreeMap::new());
+        assert!(next.id > cycle.id);
+    }
+
+    #[test]
+    fn test_measurement_cycle_roles() {
+        let series = |value| SeriesData::new(vec![value]);
+        let mut cycle = MeasurementCycle::from_series(
+            Utc::now(),
+            BTreeMap::from([
+                (1, series(100)),
+                (2, series(8000)),
+                (3, series(4000)),
+                (4, series(7000)),
+            ]),
+        );
+        cycle.roles = SeriesMapping {
+            dark: 1,
+            full: 4,
+            sample: 3,
+            reference: Some(2),
+        };
+
+        assert_eq!(cycle.full().values, vec![7000]);
+        assert_eq!(cycle.reference().unwrap().values, vec![8000]);
+        assert!(cycle.missing_roles().is_empty());
+
+        cycle.series.remove(&4);
+        assert_eq!(cycle.missing_roles(), vec!["full"]);
+        assert!(cycle.full().is_empty());
+    }
+
+    #[test]
+    fn test_series_mapping_validate() {
+        assert!(SeriesMapping::default().validate(3, false).is_ok());
+
+        let with_reference = SeriesMapping {
+            reference: Some(4),
+            ..Default::default()
+        };
+        assert!(with_reference.validate(4, false).is_ok());
+        assert!(with_reference.validate(3, false).is_err());
+
+        let duplicate = SeriesMapping {
+            sample: 1,
+            ..Default::default()
+        };
+        assert!(duplicate.validate(3, false).is_err());
+
+        // Firmware sending only the sample series, with stored references
+        assert!(duplicate.validate(1, true).is_ok());
+        assert!(duplicate.validate(1, false).is_err());
+    }
+
+    #[test]
+    fn test_firmware_version_parse() {
+        let version = FirmwareVersion::parse("1.4.2").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (1, 4, 2));
+        assert_eq!(version.raw, "1.4.2");
+
+        let version = FirmwareVersion::parse("v2.0-rc1").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (2, 0, 0));
+
+        assert!(FirmwareVersion::parse("").is_none());
+        assert!(FirmwareVersion::parse("dev").is_none());
+        assert!(FirmwareVersion::parse("1.x").is_none());
     }
 
     #[test]
@@ -321,5 +979,9 @@ mod tests {
         let measurement = measurement.with_error("sample > full".to_string());
         assert!(!measurement.is_valid);
         assert!(measurement.validation_error.is_some());
+        assert_eq!(
+            measurement.validation_category,
+            Some(ValidationCategory::Relationship)
+        );
     }
 }
@@ -0,0 +1,182 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use axum::Router;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+/// Connections queued before the server accepts them
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Sockets the HTTP server accepts connections on
+#[derive(Default)]
+pub struct Listeners {
+    tcp: Vec<TcpListener>,
+    /// Listener and the path it created, removed again on shutdown
+    #[cfg(unix)]
+    unix: Option<(tokio::net::UnixListener, PathBuf)>,
+}
+
+impl Listeners {
+    /// Listen on `addr`. The IPv6 wildcard `::` also accepts IPv4
+    /// connections, whatever the system default for IPv6 sockets.
+    pub fn bind_tcp(&mut self, addr: SocketAddr) -> io::Result<()> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if addr.is_ipv6() && addr.ip().is_unspecified() {
+            socket.set_only_v6(false)?;
+        }
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(LISTEN_BACKLOG)?;
+        socket.set_nonblocking(true)?;
+        self.tcp.push(TcpListener::from_std(socket.into())?);
+        Ok(())
+    }
+
+    /// Listen on the Unix domain socket `path`, replacing a socket file left
+    /// by an earlier run. Fails if another process still serves on it.
+    #[cfg(unix)]
+    pub fn bind_unix(&mut self, path: &Path) -> io::Result<()> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another process", path.display()),
+            ));
+        }
+        if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = tokio::net::UnixListener::bind(path)?;
+        self.unix = Some((listener, path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Where connections are accepted, for the startup log
+    pub fn describe(&self) -> Vec<String> {
+        let tcp = self
+            .tcp
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .map(|addr| addr.to_string());
+        #[cfg(unix)]
+        let unix = self
+            .unix
+            .iter()
+            .map(|(_, path)| format!("unix:{}", path.display()));
+        #[cfg(not(unix))]
+        let unix = std::iter::empty();
+        tcp.chain(unix).collect()
+    }
+}
+
+/// Serve `router` on every listener until `shutdown` completes, then let
+/// in-flight requests finish. Fails as soon as one listener fails.
+pub async fn serve(
+    listeners: Listeners,
+    router: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let (stop_tx, stop_rx) = watch::channel(());
+    let stopped = move || {
+        let mut stop_rx = stop_rx.clone();
+        async move {
+            let _ = stop_rx.changed().await;
+        }
+    };
+
+    let mut servers = JoinSet::new();
+    for listener in listeners.tcp {
+        let server = axum::serve(listener, router.clone()).with_graceful_shutdown(stopped());
+        servers.spawn(server.into_future());
+    }
+    #[cfg(unix)]
+    let socket_path = listeners.unix.map(|(listener, path)| {
+        let server = axum::serve(listener, router.clone()).with_graceful_shutdown(stopped());
+        servers.spawn(server.into_future());
+        path
+    });
+
+    let signal = tokio::spawn(async move {
+        shutdown.await;
+        let _ = stop_tx.send(());
+    });
+    let mut result = Ok(());
+    while let Some(joined) = servers.join_next().await {
+        result = joined.map_err(io::Error::other).and_then(|served| served);
+        if result.is_err() {
+            break;
+        }
+    }
+    signal.abort();
+
+    #[cfg(unix)]
+    if let Some(path) = socket_path
+        && let Err(e) = std::fs::remove_file(&path)
+    {
+        tracing::warn!("Failed to remove {}: {e}", path.display());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_every_listener_until_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("api.sock");
+        let mut listeners = Listeners::default();
+        listeners.bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+        #[cfg(unix)]
+        listeners.bind_unix(&socket_path).unwrap();
+        let tcp_addr = listeners.tcp[0].local_addr().unwrap();
+        assert_eq!(listeners.describe()[0], tcp_addr.to_string());
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let router = Router::new().route("/healthz", get(|| async { "ok" }));
+        let server = tokio::spawn(serve(listeners, router, async {
+            let _ = shutdown_rx.await;
+        }));
+
+        let body = reqwest::get(format!("http://{tcp_addr}/healthz"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert_eq!(body, "ok");
+
+        #[cfg(unix)]
+        {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+            let mut stream = tokio::net::UnixStream::connect(&socket_path).await.unwrap();
+            stream
+                .write_all(b"GET /healthz HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+            assert!(response.ends_with("ok"));
+
+            // A second service must not take over the socket
+            assert!(Listeners::default().bind_unix(&socket_path).is_err());
+        }
+
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!socket_path.exists());
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::protocol::ProcessedMeasurement;
+use crate::service::calibration::SharedConfig;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::health::HealthState;
+use crate::service::state::SharedState;
+
+/// How often staleness and push failures are checked between measurements
+const ALERT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time allowed for one webhook request
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Condition an alert rule watches
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// Calibrated reading of the latest valid measurement below `min` or
+    /// above `max` (either bound may be left out)
+    ReadingOutOfRange { min: Option<f64>, max: Option<f64> },
+    /// `cycles` invalid measurements in a row
+    InvalidStreak { cycles: u32 },
+    /// No cycle for `after_secs`
+    StaleData { after_secs: u64 },
+    /// `count` monitoring pushes failed in a row
+    PushFailures { count: u32 },
+    /// The watchdog found the processing loop stalled, until the next
+    /// measurement
+    ProcessingStalled,
+}
+
+/// Named alert rule from the `[[alerts.rules]]` config section
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+}
+
+/// Body posted to a webhook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// JSON object with the alert name, status, device and detail
+    #[default]
+    Generic,
+    /// `{"text": ...}` as accepted by Slack incoming webhooks
+    Slack,
+}
+
+/// Endpoint told when an alert fires and when it clears
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookSettings {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+/// Alert rules and the webhooks they notify; re-read on every check, so
+/// config reload applies changes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlertSettings {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<AlertRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookSettings>,
+}
+
+impl AlertSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        for (position, rule) in self.rules.iter().enumerate() {
+            let name = &rule.name;
+            if name.is_empty() {
+                return Err("alerts: rule name must not be empty".to_string());
+            }
+            if self.rules[..position]
+                .iter()
+                .any(|other| other.name == *name)
+            {
+                return Err(format!("alerts: rule {name:?} is listed twice"));
+            }
+            match rule.condition {
+                AlertCondition::ReadingOutOfRange {
+                    min: None,
+                    max: None,
+                } => {
+                    return Err(format!("alerts: rule {name:?} needs min or max"));
+                }
+                AlertCondition::ReadingOutOfRange {
+                    min: Some(min),
+                    max: Some(max),
+                } if min >= max => {
+                    return Err(format!("alerts: rule {name:?} has min >= max"));
+                }
+                AlertCondition::InvalidStreak { cycles: 0 }
+                | AlertCondition::StaleData { after_secs: 0 }
+                | AlertCondition::PushFailures { count: 0 } => {
+                    return Err(format!("alerts: rule {name:?} must be greater than 0"));
+                }
+                _ => {}
+            }
+        }
+        if let Some(webhook) = self.webhooks.iter().find(|webhook| {
+            !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://")
+        }) {
+            return Err(format!("alerts: invalid webhook URL {:?}", webhook.url));
+        }
+        Ok(())
+    }
+}
+
+/// An alert firing or clearing
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlertTransition {
+    pub rule: String,
+    pub firing: bool,
+    pub detail: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Which alerts are firing, and the measurement history the rules need
+#[derive(Debug, Default)]
+pub struct AlertTracker {
+    firing: BTreeSet<String>,
+    /// Calibrated reading of the latest valid measurement
+    latest_reading: Option<f64>,
+    /// Invalid measurements since the last valid one
+    invalid_streak: u32,
+    /// Stall reported by the watchdog, cleared by the next measurement
+    stalled: Option<String>,
+}
+
+impl AlertTracker {
+    pub fn record_measurement(&mut self, measurement: &ProcessedMeasurement) {
+        self.stalled = None;
+        if measurement.transitional {
+            return;
+        }
+        if measurement.is_valid {
+            self.latest_reading = Some(measurement.calibrated_reading);
+            self.invalid_streak = 0;
+        } else {
+            self.invalid_streak += 1;
+        }
+    }
+
+    pub fn record_stall(&mut self, source: &str, stalled_secs: u64) {
+        self.stalled = Some(format!("no cycle from {source} for {stalled_secs}s"));
+    }
+
+    /// Check every rule, returning the alerts that started or stopped
+    /// firing. Alerts of rules no longer configured are dropped silently.
+    pub fn evaluate(
+        &mut self,
+        rules: &[AlertRule],
+        health: &HealthState,
+        now: DateTime<Utc>,
+    ) -> Vec<AlertTransition> {
+        self.firing
+            .retain(|name| rules.iter().any(|rule| rule.name == *name));
+
+        let mut transitions = Vec::new();
+        for rule in rules {
+            let problem = self.problem(&rule.condition, health, now);
+            let was_firing = self.firing.contains(&rule.name);
+            let detail = match (problem, was_firing) {
+                (Some(problem), false) => {
+                    self.firing.insert(rule.name.clone());
+                    problem
+                }
+                (None, true) => {
+                    self.firing.remove(&rule.name);
+                    "back to normal".to_string()
+                }
+                _ => continue,
+            };
+            transitions.push(AlertTransition {
+                rule: rule.name.clone(),
+                firing: !was_firing,
+                detail,
+                at: now,
+            });
+        }
+        transitions
+    }
+
+    /// Why `condition` is met, `None` while it is not
+    fn problem(
+        &self,
+        condition: &AlertCondition,
+        health: &HealthState,
+        now: DateTime<Utc>,
+    ) -> Option<String> {
+        match *condition {
+            AlertCondition::ReadingOutOfRange { min, max } => {
+                let reading = self.latest_reading?;
+                if let Some(min) = min.filter(|&min| reading < min) {
+                    Some(format!("reading {reading:.2}% below {min}%"))
+                } else {
+                    max.filter(|&max| reading > max)
+                        .map(|max| format!("reading {reading:.2}% above {max}%"))
+                }
+            }
+            AlertCondition::InvalidStreak { cycles } => (self.invalid_streak >= cycles)
+                .then(|| format!("{} invalid measurements in a row", self.invalid_streak)),
+            AlertCondition::StaleData { after_secs } => {
+                let age = now - health.last_cycle_at.unwrap_or(health.started_at);
+                (age.num_seconds() >= after_secs as i64)
+                    .then(|| format!("no cycle for {}s", age.num_seconds()))
+            }
+            AlertCondition::PushFailures { count } => (health.push_failures >= count)
+                .then(|| format!("{} monitoring pushes failed in a row", health.push_failures)),
+            AlertCondition::ProcessingStalled => self.stalled.clone(),
+        }
+    }
+}
+
+/// Webhook body for `transition` of `device` (`None` for a single device)
+pub fn webhook_payload(
+    format: WebhookFormat,
+    device: Option<&str>,
+    transition: &AlertTransition,
+) -> serde_json::Value {
+    let status = if transition.firing {
+        "firing"
+    } else {
+        "resolved"
+    };
+    match format {
+        WebhookFormat::Generic => serde_json::json!({
+            "alert": transition.rule,
+            "status": status,
+            "device": device,
+            "detail": transition.detail,
+            "at": transition.at.to_rfc3339(),
+        }),
+        WebhookFormat::Slack => {
+            let device = device
+                .map(|device| format!(" on {device}"))
+                .unwrap_or_default();
+            serde_json::json!({
+                "text": format!(
+                    "Spectrometer alert {} {status}{device}: {}",
+                    transition.rule, transition.detail
+                ),
+            })
+        }
+    }
+}
+
+/// Check the alert rules of one device after every measurement and once a
+/// second, posting each transition to the configured webhooks
+pub async fn run_alerts(
+    device: Option<String>,
+    state: SharedState,
+    config: SharedConfig,
+    events: EventBus,
+) {
+    let client = Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .expect("Failed to create HTTP client");
+    let mut tracker = AlertTracker::default();
+    let mut events = events.subscribe();
+    let mut interval = tokio::time::interval(ALERT_CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(ServiceEvent::Measurement(measurement)) => {
+                    tracker.record_measurement(&measurement);
+                }
+                Ok(ServiceEvent::ProcessingStalled { source, stalled_secs, .. }) => {
+                    tracker.record_stall(&source, stalled_secs);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            _ = interval.tick() => {}
+        }
+
+        let alerts = config.read().await.config.alerts.clone();
+        let transitions = {
+            let device = state.read().await;
+            tracker.evaluate(&alerts.rules, &device.health, Utc::now())
+        };
+        for transition in transitions {
+            if transition.firing {
+                tracing::warn!("Alert {} firing: {}", transition.rule, transition.detail);
+            } else {
+                tracing::info!("Alert {} resolved", transition.rule);
+            }
+            for webhook in &alerts.webhooks {
+                let payload = webhook_payload(webhook.format, device.as_deref(), &transition);
+                let result = client
+                    .post(&webhook.url)
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = result {
+                    tracing::warn!("Alert webhook {} failed: {e}", webhook.url);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, condition: AlertCondition) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            condition,
+        }
+    }
+
+    fn measurement(reading: f64, is_valid: bool) -> ProcessedMeasurement {
+        let mut measurement = ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 500.0, reading);
+        measurement.is_valid = is_valid;
+        measurement
+    }
+
+    #[test]
+    fn test_parse_and_validate_settings() {
+        let settings: AlertSettings = toml::from_str(
+            r#"
+            [[rules]]
+            name = "reading"
+            kind = "reading_out_of_range"
+            max = 95.0
+
+            [[rules]]
+            name = "stale"
+            kind = "stale_data"
+            after_secs = 30
+
+            [[webhooks]]
+            url = "https://hooks.slack.com/services/T0/B0/x"
+            format = "slack"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.rules[0].condition,
+            AlertCondition::ReadingOutOfRange {
+                min: None,
+                max: Some(95.0)
+            }
+        );
+        assert_eq!(settings.webhooks[0].format, WebhookFormat::Slack);
+        settings.validate().unwrap();
+
+        let mut invalid = settings.clone();
+        invalid.rules[1].name = "reading".to_string();
+        assert!(invalid.validate().unwrap_err().contains("twice"));
+
+        let mut invalid = settings.clone();
+        invalid.rules[0].condition = AlertCondition::ReadingOutOfRange {
+            min: Some(50.0),
+            max: Some(10.0),
+        };
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = settings;
+        invalid.webhooks[0].url = "hooks.example.com".to_string();
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_reading_and_invalid_streak_alerts() {
+        let rules = [
+            rule(
+                "reading",
+                AlertCondition::ReadingOutOfRange {
+                    min: Some(10.0),
+                    max: Some(90.0),
+                },
+            ),
+            rule("invalid", AlertCondition::InvalidStreak { cycles: 2 }),
+        ];
+        let health = HealthState::default();
+        let now = health.started_at;
+        let mut tracker = AlertTracker::default();
+
+        tracker.record_measurement(&measurement(95.0, true));
+        let transitions = tracker.evaluate(&rules, &health, now);
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].rule, "reading");
+        assert!(transitions[0].firing);
+        assert!(transitions[0].detail.contains("above 90%"));
+
+        // Still firing: no new transition
+        tracker.record_measurement(&measurement(0.0, false));
+        assert!(tracker.evaluate(&rules, &health, now).is_empty());
+
+        tracker.record_measurement(&measurement(0.0, false));
+        let transitions = tracker.evaluate(&rules, &health, now);
+        assert_eq!(transitions[0].rule, "invalid");
+        assert!(transitions[0].firing);
+
+        tracker.record_measurement(&measurement(50.0, true));
+        let transitions = tracker.evaluate(&rules, &health, now);
+        assert_eq!(transitions.len(), 2);
+        assert!(transitions.iter().all(|transition| !transition.firing));
+    }
+
+    #[test]
+    fn test_stale_data_and_push_failure_alerts() {
+        let rules = [
+            rule("stale", AlertCondition::StaleData { after_secs: 30 }),
+            rule("push", AlertCondition::PushFailures { count: 2 }),
+        ];
+        let mut health = HealthState::default();
+        let start = health.started_at;
+        let mut tracker = AlertTracker::default();
+
+        assert!(tracker.evaluate(&rules, &health, start).is_empty());
+        let transitions = tracker.evaluate(&rules, &health, start + chrono::Duration::seconds(31));
+        assert_eq!(transitions[0].rule, "stale");
+        assert!(transitions[0].firing);
+
+        health.record_cycle(start + chrono::Duration::seconds(31));
+        health.record_push(Err("timeout".to_string()));
+        health.record_push(Err("timeout".to_string()));
+        let transitions = tracker.evaluate(&rules, &health, start + chrono::Duration::seconds(32));
+        let summary: Vec<_> = transitions
+            .iter()
+            .map(|transition| (transition.rule.as_str(), transition.firing))
+            .collect();
+        assert_eq!(summary, vec![("stale", false), ("push", true)]);
+
+        // A removed rule's alert is forgotten rather than resolved
+        assert!(tracker.evaluate(&[], &health, start).is_empty());
+    }
+
+    #[test]
+    fn test_processing_stalled_alert() {
+        let settings: AlertSettings = toml::from_str(
+            r#"
+            [[rules]]
+            name = "stalled"
+            kind = "processing_stalled"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            settings.rules[0].condition,
+            AlertCondition::ProcessingStalled
+        );
+        let health = HealthState::default();
+        let now = health.started_at;
+        let mut tracker = AlertTracker::default();
+
+        tracker.record_stall("/dev/ttyUSB0", 30);
+        let transitions = tracker.evaluate(&settings.rules, &health, now);
+        assert!(transitions[0].firing);
+        assert_eq!(transitions[0].detail, "no cycle from /dev/ttyUSB0 for 30s");
+
+        tracker.record_measurement(&measurement(50.0, true));
+        let transitions = tracker.evaluate(&settings.rules, &health, now);
+        assert!(!transitions[0].firing);
+    }
+
+    #[test]
+    fn test_webhook_payloads() {
+        let transition = AlertTransition {
+            rule: "stale".to_string(),
+            firing: true,
+            detail: "no cycle for 31s".to_string(),
+            at: Utc::now(),
+        };
+
+        let generic = webhook_payload(WebhookFormat::Generic, Some("ch1"), &transition);
+        assert_eq!(generic["alert"], "stale");
+        assert_eq!(generic["status"], "firing");
+        assert_eq!(generic["device"], "ch1");
+
+        let slack = webhook_payload(WebhookFormat::Slack, None, &transition);
+        assert_eq!(
+            slack["text"],
+            "Spectrometer alert stale firing: no cycle for 31s"
+        );
+    }
+}
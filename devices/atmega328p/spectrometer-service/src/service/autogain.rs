@@ -0,0 +1,421 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+
+use crate::protocol::{MAX_ADC_VALUE, ProcessedMeasurement, ValidationCategory};
+use crate::service::calibration::SharedConfig;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::state::SharedState;
+use crate::service::supervisor::SourceCommand;
+
+/// Lowest and highest AD7793 gain
+const MIN_GAIN: u8 = 1;
+const MAX_GAIN: u8 = 128;
+
+/// What the service does when the full-dark span calls for another gain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoGainMode {
+    /// Do not watch the span
+    Off,
+    /// Log and publish the recommended gain (default)
+    #[default]
+    Recommend,
+    /// Also send the recommended gain to the device
+    Apply,
+}
+
+/// Gain recommendation from the `[auto_gain]` config section; re-read on
+/// every measurement, so config reload applies changes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AutoGainSettings {
+    #[serde(default)]
+    pub mode: AutoGainMode,
+    /// Full-dark span, as a share of the ADC range, below which the next
+    /// higher gain is recommended
+    #[serde(default = "default_low_fraction")]
+    pub low_fraction: f64,
+    /// Span above which the next lower gain is recommended
+    #[serde(default = "default_high_fraction")]
+    pub high_fraction: f64,
+    /// Measurements in a row that must call for the same gain before it is
+    /// recommended
+    #[serde(default = "default_hold_cycles")]
+    pub hold_cycles: u32,
+}
+
+fn default_low_fraction() -> f64 {
+    0.2
+}
+
+fn default_high_fraction() -> f64 {
+    0.9
+}
+
+fn default_hold_cycles() -> u32 {
+    5
+}
+
+impl Default for AutoGainSettings {
+    fn default() -> Self {
+        Self {
+            mode: AutoGainMode::default(),
+            low_fraction: default_low_fraction(),
+            high_fraction: default_high_fraction(),
+            hold_cycles: default_hold_cycles(),
+        }
+    }
+}
+
+impl AutoGainSettings {
+    /// Check the thresholds leave room for a gain step in between, so that
+    /// doubling the gain never takes the span straight above
+    /// `high_fraction`
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.high_fraction > 0.0 && self.high_fraction <= 1.0) {
+            return Err(format!(
+                "auto_gain: high_fraction must be in (0, 1], got {}",
+                self.high_fraction
+            ));
+        }
+        if !(self.low_fraction >= 0.0 && 2.0 * self.low_fraction < self.high_fraction) {
+            return Err(format!(
+                "auto_gain: low_fraction must be at least 0 and below half of high_fraction, got {}",
+                self.low_fraction
+            ));
+        }
+        if self.hold_cycles == 0 {
+            return Err("auto_gain: hold_cycles must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Why another gain is recommended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GainReason {
+    /// Raw values at either end of the ADC range
+    Saturated,
+    /// Span above `high_fraction`
+    HighSpan,
+    /// Span below `low_fraction`
+    LowSpan,
+}
+
+impl GainReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GainReason::Saturated => "saturated",
+            GainReason::HighSpan => "high_span",
+            GainReason::LowSpan => "low_span",
+        }
+    }
+}
+
+/// Gain one step from `gain` for `measurement`, if it needs another one
+pub fn recommend_gain(
+    measurement: &ProcessedMeasurement,
+    gain: u8,
+    settings: &AutoGainSettings,
+) -> Option<(u8, GainReason)> {
+    let lower = (gain > MIN_GAIN).then_some(gain / 2);
+    let higher = (gain < MAX_GAIN).then_some(gain.saturating_mul(2));
+
+    if matches!(
+        measurement.validation_category,
+        Some(ValidationCategory::Saturation | ValidationCategory::UnderRange)
+    ) {
+        return lower.map(|gain| (gain, GainReason::Saturated));
+    }
+
+    let span = (measurement.full_mean - measurement.dark_mean).abs() / f64::from(MAX_ADC_VALUE);
+    if span > settings.high_fraction {
+        return lower.map(|gain| (gain, GainReason::HighSpan));
+    }
+    // An invalid measurement may be a blocked beam or an empty holder
+    if measurement.is_valid && span < settings.low_fraction {
+        return higher.map(|gain| (gain, GainReason::LowSpan));
+    }
+    None
+}
+
+/// Recommendation that held for `hold_cycles` measurements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GainRecommendation {
+    pub gain: u8,
+    pub recommended: u8,
+    pub reason: GainReason,
+}
+
+/// Debounces recommendations across measurements
+#[derive(Debug, Default)]
+pub struct GainTracker {
+    /// Recommendation of the current streak and its length
+    streak: Option<((u8, GainReason), u32)>,
+    /// Recommendation already reported, not repeated while it holds
+    reported: Option<(u8, GainReason)>,
+}
+
+impl GainTracker {
+    /// Record a measurement taken at `gain`, returning a recommendation
+    /// once it held for `hold_cycles` measurements in a row
+    pub fn record(
+        &mut self,
+        measurement: &ProcessedMeasurement,
+        gain: u8,
+        settings: &AutoGainSettings,
+    ) -> Option<GainRecommendation> {
+        let Some(current) = recommend_gain(measurement, gain, settings) else {
+            self.streak = None;
+            self.reported = None;
+            return None;
+        };
+
+        let length = match self.streak {
+            Some((previous, length)) if previous == current => length + 1,
+            _ => 1,
+        };
+        self.streak = Some((current, length));
+        if length < settings.hold_cycles || self.reported == Some(current) {
+            return None;
+        }
+
+        self.reported = Some(current);
+        let (recommended, reason) = current;
+        Some(GainRecommendation {
+            gain,
+            recommended,
+            reason,
+        })
+    }
+
+    /// Forget the streak, e.g. after the gain was changed
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Watch the full-dark span of every measurement and recommend a gain that
+/// keeps it within `auto_gain.low_fraction` and `high_fraction`. With
+/// `auto_gain.mode = "apply"` the gain is also sent to the device and
+/// replaces the running one, without saving it to the config file.
+pub async fn run_auto_gain(
+    state: SharedState,
+    config: SharedConfig,
+    events: EventBus,
+    cmd_tx: mpsc::Sender<SourceCommand>,
+) {
+    let mut tracker = GainTracker::default();
+    let mut rx = events.subscribe();
+
+    loop {
+        let measurement = match rx.recv().await {
+            Ok(ServiceEvent::Measurement(measurement)) => measurement,
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        let (settings, device_settings) = {
+            let cfg = config.read().await;
+            (cfg.config.auto_gain, cfg.config.device_settings.clone())
+        };
+        if settings.mode == AutoGainMode::Off {
+            tracker.reset();
+            continue;
+        }
+        let Some(recommendation) = tracker.record(&measurement, device_settings.gain, &settings)
+        else {
+            continue;
+        };
+
+        let applied = settings.mode == AutoGainMode::Apply;
+        let GainRecommendation {
+            gain,
+            recommended,
+            reason,
+        } = recommendation;
+        if applied {
+            tracing::info!(
+                "Changing GAIN {gain} -> {recommended} ({})",
+                reason.as_str()
+            );
+            if cmd_tx
+                .send(SourceCommand::Device(format!("GAIN={recommended}")))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            let (fadc, count) = (device_settings.fadc, device_settings.count);
+            if let Some(source) = &mut state.write().await.data_source
+                && source.adc.is_some()
+            {
+                source.adc = Some((recommended, fadc, count));
+            }
+            config
+                .write()
+                .await
+                .update_settings(recommended, fadc, count);
+            tracker.reset();
+        } else {
+            tracing::warn!(
+                "Recommended GAIN {recommended} instead of {gain} ({})",
+                reason.as_str()
+            );
+        }
+        events.publish(ServiceEvent::GainRecommended {
+            at: Utc::now(),
+            gain,
+            recommended,
+            reason,
+            applied,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::calibration::create_shared_config;
+    use crate::service::state::create_shared_state;
+
+    /// Measurement with a full-dark span of `fraction` of the ADC range
+    fn span(fraction: f64) -> ProcessedMeasurement {
+        let dark = 14_000_000.0;
+        let full = dark - fraction * f64::from(MAX_ADC_VALUE);
+        ProcessedMeasurement::new(Utc::now(), dark, full, (dark + full) / 2.0, 50.0)
+    }
+
+    fn saturated() -> ProcessedMeasurement {
+        span(0.5).with_categorized_error(ValidationCategory::Saturation, "clipped".to_string())
+    }
+
+    #[test]
+    fn test_recommend_gain() {
+        let settings = AutoGainSettings::default();
+        assert_eq!(recommend_gain(&span(0.5), 4, &settings), None);
+        assert_eq!(
+            recommend_gain(&span(0.1), 4, &settings),
+            Some((8, GainReason::LowSpan))
+        );
+        assert_eq!(
+            recommend_gain(&span(0.95), 4, &settings),
+            Some((2, GainReason::HighSpan))
+        );
+        assert_eq!(
+            recommend_gain(&saturated(), 4, &settings),
+            Some((2, GainReason::Saturated))
+        );
+        // No step beyond the ends of the gain range
+        assert_eq!(recommend_gain(&span(0.1), 128, &settings), None);
+        assert_eq!(recommend_gain(&saturated(), 1, &settings), None);
+    }
+
+    #[test]
+    fn test_low_span_of_invalid_measurement_ignored() {
+        let measurement = span(0.01).with_error("sample outside".to_string());
+        assert_eq!(
+            recommend_gain(&measurement, 4, &AutoGainSettings::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tracker_holds_and_reports_once() {
+        let settings = AutoGainSettings {
+            hold_cycles: 3,
+            ..Default::default()
+        };
+        let mut tracker = GainTracker::default();
+        assert!(tracker.record(&span(0.1), 4, &settings).is_none());
+        assert!(tracker.record(&span(0.1), 4, &settings).is_none());
+        assert_eq!(
+            tracker.record(&span(0.1), 4, &settings),
+            Some(GainRecommendation {
+                gain: 4,
+                recommended: 8,
+                reason: GainReason::LowSpan,
+            })
+        );
+        // Not repeated while the condition holds
+        assert!(tracker.record(&span(0.1), 4, &settings).is_none());
+
+        // A good measurement breaks the streak
+        tracker.record(&span(0.5), 4, &settings);
+        tracker.record(&span(0.1), 4, &settings);
+        tracker.record(&span(0.1), 4, &settings);
+        assert!(tracker.record(&span(0.1), 4, &settings).is_some());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(AutoGainSettings::default().validate().is_ok());
+        for (low_fraction, high_fraction) in [(0.5, 0.9), (0.1, 1.5), (-0.1, 0.9), (0.1, 0.0)] {
+            let settings = AutoGainSettings {
+                low_fraction,
+                high_fraction,
+                ..Default::default()
+            };
+            assert!(
+                settings.validate().is_err(),
+                "{low_fraction} {high_fraction}"
+            );
+        }
+        let settings = AutoGainSettings {
+            hold_cycles: 0,
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_sends_gain_and_updates_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = create_shared_config(dir.path().join("cfg.toml"));
+        {
+            let mut cfg = config.write().await;
+            cfg.config.device_settings.gain = 4;
+            cfg.config.auto_gain = AutoGainSettings {
+                mode: AutoGainMode::Apply,
+                hold_cycles: 2,
+                ..Default::default()
+            };
+        }
+        let events = EventBus::default();
+        let mut rx = events.subscribe();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(4);
+        let task = tokio::spawn(run_auto_gain(
+            create_shared_state(),
+            config.clone(),
+            events.clone(),
+            cmd_tx,
+        ));
+        tokio::task::yield_now().await;
+
+        for _ in 0..2 {
+            events.publish(ServiceEvent::Measurement(Box::new(saturated())));
+        }
+        match cmd_rx.recv().await {
+            Some(SourceCommand::Device(cmd)) => assert_eq!(cmd, "GAIN=2"),
+            other => panic!("Unexpected command: {other:?}"),
+        }
+        loop {
+            if let ServiceEvent::GainRecommended {
+                gain,
+                recommended,
+                reason,
+                applied,
+                ..
+            } = rx.recv().await.unwrap()
+            {
+                assert_eq!((gain, recommended), (4, 2));
+                assert_eq!(reason, GainReason::Saturated);
+                assert!(applied);
+                break;
+            }
+        }
+        assert_eq!(config.read().await.config.device_settings.gain, 2);
+        task.abort();
+    }
+}
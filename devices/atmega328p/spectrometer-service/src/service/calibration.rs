@@ -1,38 +1,419 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
-/// Maximum raw ADC value (24-bit) — indicates saturation/clipping
-pub const MAX_ADC_VALUE: u32 = 16_777_215;
+use crate::data_source::SourceSettings;
+use crate::monitoring::{
+    MonitoringAuth, MonitoringCompression, MonitoringEndpoint, MonitoringHttp, MonitoringTls,
+};
+use crate::processing::aggregation::AggregationSettings;
+use crate::processing::calibration::LinearitySettings;
+use crate::processing::dark::DarkCompensationSettings;
+use crate::processing::outlier::OutlierMethod;
+use crate::processing::pipeline::{DEFAULT_PIPELINE, StageKind, validate_pipeline};
+use crate::processing::references::ReferenceSettings;
+use crate::processing::smoothing::{
+    EmaSettings, KalmanSettings, SavitzkyGolaySettings, SmootherConfig, SmootherKind,
+};
+use crate::processing::thickness::ThicknessSettings;
+use crate::processing::units::UnitsSettings;
+use crate::processing::validation::ValidationSettings;
+pub use crate::protocol::SeriesMapping;
+use crate::protocol::{DEFAULT_SERIES_COUNT, Material};
+use crate::service::alerts::AlertSettings;
+use crate::service::autogain::AutoGainSettings;
+use crate::service::termination::TerminationSettings;
+use crate::service::watchdog::WatchdogSettings;
 
 /// Persisted device configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceConfig {
     pub device_settings: DeviceSettings,
     pub last_updated: DateTime<Utc>,
+    #[serde(default)]
+    pub processing: ProcessingSettings,
+    #[serde(default)]
+    pub monitoring: MonitoringSettings,
+    /// Alert rules and webhooks
+    #[serde(default)]
+    pub alerts: AlertSettings,
+    /// Accepted control wavelengths and materials
+    #[serde(default)]
+    pub control: ControlSettings,
+    /// Processing stall detection
+    #[serde(default)]
+    pub watchdog: WatchdogSettings,
+    /// Gain recommendation from the full-dark span
+    #[serde(default)]
+    pub auto_gain: AutoGainSettings,
+    /// Local layer cut-off at a target reading or turning point
+    #[serde(default)]
+    pub termination: TerminationSettings,
+    /// Spectrometers run by this process when no mode is given on the
+    /// command line, each served under `/devices/{name}/`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceEntry>,
 }
 
-/// Which SERIES number (1-3) maps to each measurement channel
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SeriesMapping {
-    /// SERIES number for dark measurement (default: 1)
-    pub dark: u8,
-    /// SERIES number for full/reference measurement (default: 2)
-    pub full: u8,
-    /// SERIES number for sample measurement (default: 3)
-    pub sample: u8,
+/// One of several spectrometers run in the same process
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceEntry {
+    /// Name in the API path, letters, digits, `-` and `_`
+    pub name: String,
+    /// Calibration config of this device; by default the main config file
+    /// name with `-<name>` appended, e.g. `calibration-ch1.toml`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    pub source: SourceSettings,
 }
 
-impl Default for SeriesMapping {
+impl DeviceEntry {
+    /// Calibration config file of this device
+    pub fn config_path(&self, main_config: &Path) -> PathBuf {
+        self.config
+            .clone()
+            .unwrap_or_else(|| device_file(main_config, &self.name))
+    }
+}
+
+/// `path` with `-<device>` appended to the file stem, giving each device its
+/// own file: `run.csv` becomes `run-ch1.csv`
+pub fn device_file(path: &Path, device: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{stem}-{device}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{device}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Check that device names are usable in URLs and unique
+pub fn validate_devices(devices: &[DeviceEntry]) -> Result<(), String> {
+    for (position, device) in devices.iter().enumerate() {
+        let name = &device.name;
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(format!(
+                "devices: invalid name {name:?}, use letters, digits, '-' and '_'"
+            ));
+        }
+        if devices[..position].iter().any(|other| other.name == *name) {
+            return Err(format!("devices: {name:?} is listed twice"));
+        }
+    }
+    Ok(())
+}
+
+/// Processing options that can be changed at runtime via config reload
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessingSettings {
+    /// Outlier exclusion; when unset the CLI flags (or defaults) apply
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outlier: Option<OutlierMethod>,
+    /// Measurement validation rule and tolerances
+    #[serde(default)]
+    pub validation: ValidationSettings,
+    /// How each series is reduced to a level
+    #[serde(default)]
+    pub aggregation: AggregationSettings,
+    /// Rolling dark-current estimate and how it is used
+    #[serde(default)]
+    pub dark_compensation: DarkCompensationSettings,
+    /// Smoother for the calibrated reading; `--smoother` takes precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smoother: Option<SmootherKind>,
+    /// Exponential moving average parameters
+    #[serde(default)]
+    pub ema: EmaSettings,
+    /// Savitzky-Golay window and order
+    #[serde(default)]
+    pub savitzky_golay: SavitzkyGolaySettings,
+    /// Kalman filter noise parameters
+    #[serde(default)]
+    pub kalman: KalmanSettings,
+    /// Conversion of the means to volts and amperes
+    #[serde(default)]
+    pub units: UnitsSettings,
+    /// Per-cycle or stored dark and full levels
+    #[serde(default)]
+    pub references: ReferenceSettings,
+    /// Nonlinearity correction of the calibrated reading
+    #[serde(default)]
+    pub linearity: LinearitySettings,
+    /// Layer thickness estimation from the reading curve
+    #[serde(default)]
+    pub thickness: ThicknessSettings,
+    /// Processing stages in order; when unset `DEFAULT_PIPELINE` applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<Vec<StageKind>>,
+}
+
+impl ProcessingSettings {
+    /// Check every section, naming the offending one in the error
+    pub fn validate(&self) -> Result<(), String> {
+        let sections: [(&str, Result<(), String>); 11] = [
+            ("validation", self.validation.validate()),
+            ("aggregation", self.aggregation.validate()),
+            ("dark_compensation", self.dark_compensation.validate()),
+            ("ema", self.ema.validate()),
+            ("savitzky_golay", self.savitzky_golay.validate()),
+            ("kalman", self.kalman.validate()),
+            ("units", self.units.validate()),
+            ("references", self.references.validate()),
+            ("linearity", self.linearity.validate()),
+            ("thickness", self.thickness.validate()),
+            ("pipeline", validate_pipeline(self.pipeline())),
+        ];
+        for (name, result) in sections {
+            result.map_err(|e| format!("processing.{name}: {e}"))?;
+        }
+        Ok(())
+    }
+
+    /// Stage chain to run
+    pub fn pipeline(&self) -> &[StageKind] {
+        self.pipeline.as_deref().unwrap_or(DEFAULT_PIPELINE)
+    }
+
+    /// Smoother to use: `cli_kind` if given, else the configured one, else
+    /// the default
+    pub fn smoother_config(&self, cli_kind: Option<SmootherKind>) -> SmootherConfig {
+        SmootherConfig {
+            kind: cli_kind.or(self.smoother).unwrap_or_default(),
+            ema: self.ema,
+            savitzky_golay: self.savitzky_golay,
+            kalman: self.kalman,
+        }
+    }
+}
+
+/// Default number of buffered pushes replayed per measurement
+pub const DEFAULT_REPLAY_BATCH_SIZE: usize = 50;
+
+/// Default seconds between heartbeats to the monitoring API
+pub const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 30;
+
+/// Monitoring push options that can be changed at runtime via config reload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonitoringSettings {
+    /// Monitoring API URL; overrides the one given at registration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Buffered pushes replayed per measurement after an outage
+    #[serde(default = "default_replay_batch_size")]
+    pub replay_batch_size: usize,
+    /// Credentials and headers per monitoring URL
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth: Vec<MonitoringAuth>,
+    /// Custom CA bundle and client certificate
+    #[serde(default, skip_serializing_if = "is_default_tls")]
+    pub tls: MonitoringTls,
+    /// Timeouts, connection pooling and proxy
+    #[serde(default, skip_serializing_if = "is_default_http")]
+    pub http: MonitoringHttp,
+    /// Mirror and failover endpoints besides the registered API; read at
+    /// startup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<MonitoringEndpoint>,
+    /// Clear the registration when OptiMonitor has not re-registered for
+    /// this long; kept until `/unregister` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_ttl_secs: Option<u64>,
+    /// Seconds between heartbeats while registered; 0 disables them
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+    /// Push the filtered series values with each reading, for offline
+    /// re-analysis; multiplies the payload size
+    #[serde(default)]
+    pub push_series: bool,
+    /// Content-Encoding of spectral data pushes
+    #[serde(default)]
+    pub compression: MonitoringCompression,
+}
+
+fn is_default_tls(tls: &MonitoringTls) -> bool {
+    *tls == MonitoringTls::default()
+}
+
+fn is_default_http(http: &MonitoringHttp) -> bool {
+    *http == MonitoringHttp::default()
+}
+
+fn default_replay_batch_size() -> usize {
+    DEFAULT_REPLAY_BATCH_SIZE
+}
+
+fn default_heartbeat_interval_secs() -> u64 {
+    DEFAULT_HEARTBEAT_INTERVAL_SECS
+}
+
+impl Default for MonitoringSettings {
     fn default() -> Self {
         Self {
-            dark: 1,
-            full: 2,
-            sample: 3,
+            api_url: None,
+            replay_batch_size: DEFAULT_REPLAY_BATCH_SIZE,
+            auth: Vec::new(),
+            tls: MonitoringTls::default(),
+            http: MonitoringHttp::default(),
+            endpoints: Vec::new(),
+            registration_ttl_secs: None,
+            heartbeat_interval_secs: DEFAULT_HEARTBEAT_INTERVAL_SECS,
+            push_series: false,
+            compression: MonitoringCompression::None,
+        }
+    }
+}
+
+impl MonitoringSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.registration_ttl_secs == Some(0) {
+            return Err("monitoring: registration_ttl_secs must be positive".to_string());
         }
+        self.auth.iter().try_for_each(MonitoringAuth::validate)?;
+        MonitoringEndpoint::validate_all(&self.endpoints)?;
+        self.http.validate()
+    }
+}
+
+/// Range of control wavelengths and catalogue of materials the chamber
+/// endpoints accept
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlSettings {
+    /// Shortest accepted control wavelength in nm
+    #[serde(default = "default_wavelength_min")]
+    pub wavelength_min: f64,
+    /// Longest accepted control wavelength in nm
+    #[serde(default = "default_wavelength_max")]
+    pub wavelength_max: f64,
+    /// Known materials, matched exactly by symbol: `"H"` or
+    /// `{ symbol = "H", name = "Ta2O5", refractive_index = 2.1, target_rate = 0.3 }`
+    #[serde(default = "default_materials")]
+    pub materials: Vec<Material>,
+    /// Changes accepted per second across the control endpoints (chamber,
+    /// material, wavelength, shutter, settings); 0 disables the limit
+    #[serde(default = "default_max_requests_per_sec")]
+    pub max_requests_per_sec: u32,
+}
+
+fn default_wavelength_min() -> f64 {
+    190.0
+}
+
+fn default_wavelength_max() -> f64 {
+    1100.0
+}
+
+fn default_max_requests_per_sec() -> u32 {
+    5
+}
+
+fn default_materials() -> Vec<Material> {
+    ["H", "L", "M"].map(Material::symbol).to_vec()
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            wavelength_min: default_wavelength_min(),
+            wavelength_max: default_wavelength_max(),
+            materials: default_materials(),
+            max_requests_per_sec: default_max_requests_per_sec(),
+        }
+    }
+}
+
+impl ControlSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.wavelength_min > 0.0
+            && self.wavelength_min < self.wavelength_max
+            && self.wavelength_max.is_finite())
+        {
+            return Err(format!(
+                "control: wavelength range {}..{} nm must be positive and not empty",
+                self.wavelength_min, self.wavelength_max
+            ));
+        }
+        if self.materials.is_empty() {
+            return Err("control: materials must not be empty".to_string());
+        }
+        for (i, material) in self.materials.iter().enumerate() {
+            if material.symbol.is_empty() {
+                return Err("control: material symbols must not be empty".to_string());
+            }
+            if self.materials[..i]
+                .iter()
+                .any(|other| other.symbol == material.symbol)
+            {
+                return Err(format!(
+                    "control: material {:?} listed twice",
+                    material.symbol
+                ));
+            }
+            for (field, value) in [
+                ("refractive_index", material.refractive_index),
+                ("target_rate", material.target_rate),
+            ] {
+                if let Some(value) = value
+                    && !(value > 0.0 && value.is_finite())
+                {
+                    return Err(format!(
+                        "control: {field} of material {:?} must be positive, got {value}",
+                        material.symbol
+                    ));
+                }
+            }
+            if let Some(wavelength) = material.control_wavelength {
+                self.check_wavelength(wavelength).map_err(|e| {
+                    format!(
+                        "control: control_wavelength of material {:?}: {e}",
+                        material.symbol
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Catalogue entry of `symbol`
+    pub fn material(&self, symbol: &str) -> Option<&Material> {
+        self.materials.iter().find(|known| known.symbol == symbol)
+    }
+
+    /// Symbols of the known materials, comma-separated
+    pub fn material_symbols(&self) -> String {
+        self.materials
+            .iter()
+            .map(|material| material.symbol.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Check a requested control wavelength against the configured range
+    pub fn check_wavelength(&self, wavelength: f64) -> Result<(), String> {
+        if !(self.wavelength_min..=self.wavelength_max).contains(&wavelength) {
+            return Err(format!(
+                "Wavelength {wavelength} nm outside {}..{} nm",
+                self.wavelength_min, self.wavelength_max
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check a requested material against the known materials
+    pub fn check_material(&self, material: &str) -> Result<(), String> {
+        if self.material(material).is_none() {
+            return Err(format!(
+                "Unknown material {material:?}, expected one of {}",
+                self.material_symbols()
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -41,16 +422,24 @@ pub struct DeviceSettings {
     pub gain: u8,
     pub fadc: f32,
     pub count: u8,
+    /// SERIES lines the firmware sends per cycle
+    #[serde(default = "default_series_count")]
+    pub series_count: u8,
     #[serde(default)]
     pub series_mapping: SeriesMapping,
 }
 
+fn default_series_count() -> u8 {
+    DEFAULT_SERIES_COUNT
+}
+
 impl Default for DeviceSettings {
     fn default() -> Self {
         Self {
             gain: 2,
             fadc: 250.0,
             count: 4,
+            series_count: DEFAULT_SERIES_COUNT,
             series_mapping: SeriesMapping::default(),
         }
     }
@@ -61,6 +450,14 @@ impl Default for DeviceConfig {
         Self {
             device_settings: DeviceSettings::default(),
             last_updated: Utc::now(),
+            processing: ProcessingSettings::default(),
+            monitoring: MonitoringSettings::default(),
+            alerts: AlertSettings::default(),
+            control: ControlSettings::default(),
+            watchdog: WatchdogSettings::default(),
+            auto_gain: AutoGainSettings::default(),
+            termination: TerminationSettings::default(),
+            devices: Vec::new(),
         }
     }
 }
@@ -101,6 +498,14 @@ impl ConfigRuntime {
         }
     }
 
+    /// Read and parse the config file without applying it
+    pub fn read_from_disk(&self) -> Result<DeviceConfig, String> {
+        let contents = std::fs::read_to_string(&self.config_path)
+            .map_err(|e| format!("Failed to read {:?}: {e}", self.config_path))?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Failed to parse {:?}: {e}", self.config_path))
+    }
+
     pub fn save(&self) -> Result<(), String> {
         let toml_str =
             toml::to_string_pretty(&self.config).map_err(|e| format!("Serialize error: {e}"))?;
@@ -110,12 +515,11 @@ impl ConfigRuntime {
     }
 
     pub fn update_settings(&mut self, gain: u8, fadc: f32, count: u8) {
-        let mapping = self.config.device_settings.series_mapping.clone();
         self.config.device_settings = DeviceSettings {
             gain,
             fadc,
             count,
-            series_mapping: mapping,
+            ..self.config.device_settings.clone()
         };
         self.config.last_updated = Utc::now();
     }
@@ -130,6 +534,8 @@ pub fn create_shared_config(path: PathBuf) -> SharedConfig {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data_source::serial::Parity;
+    use crate::processing::validation::ValidationRule;
 
     #[test]
     fn test_device_config_default() {
@@ -153,4 +559,206 @@ mod tests {
         assert_eq!(runtime2.config.device_settings.fadc, 500.0);
         assert_eq!(runtime2.config.device_settings.count, 3);
     }
+
+    #[test]
+    fn test_runtime_sections_parse() {
+        let config: DeviceConfig = toml::from_str(
+            r#"
+            last_updated = "2025-01-15T10:30:00Z"
+
+            [device_settings]
+            gain = 2
+            fadc = 250.0
+            count = 4
+
+            [processing.outlier]
+            method = "grubbs"
+            alpha = 0.01
+
+            [processing.validation]
+            rule = "strict"
+            overshoot_percent = 3.0
+
+            [monitoring]
+            api_url = "http://monitor:8200"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.processing.validation.rule, ValidationRule::Strict);
+        assert_eq!(config.processing.validation.overshoot_percent, 3.0);
+        assert_eq!(config.processing.validation.epsilon, 0.0);
+
+        assert_eq!(
+            config.processing.outlier,
+            Some(OutlierMethod::Grubbs { alpha: 0.01 })
+        );
+        assert_eq!(
+            config.monitoring.api_url.as_deref(),
+            Some("http://monitor:8200")
+        );
+        assert_eq!(
+            config.monitoring.replay_batch_size,
+            DEFAULT_REPLAY_BATCH_SIZE
+        );
+    }
+
+    #[test]
+    fn test_legacy_config_without_sections() {
+        let config: DeviceConfig = toml::from_str(
+            r#"
+            last_updated = "2025-01-15T10:30:00Z"
+
+            [device_settings]
+            gain = 2
+            fadc = 250.0
+            count = 4
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.processing, ProcessingSettings::default());
+        assert_eq!(config.monitoring, MonitoringSettings::default());
+        assert_eq!(config.device_settings.series_count, DEFAULT_SERIES_COUNT);
+    }
+
+    #[test]
+    fn test_devices_parse() {
+        let config: DeviceConfig = toml::from_str(
+            r#"
+            last_updated = "2025-01-15T10:30:00Z"
+
+            [device_settings]
+            gain = 2
+            fadc = 250.0
+            count = 4
+
+            [[devices]]
+            name = "ch1"
+            source = { type = "serial", port = "/dev/ttyUSB0", parity = "even", dtr = false }
+
+            [[devices]]
+            name = "ch2"
+            config = "/etc/spectrometer/ch2.toml"
+            source = { type = "playback", file = "ch2.log", speed = 2.0 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.devices.len(), 2);
+        assert!(validate_devices(&config.devices).is_ok());
+        let main = Path::new("/etc/spectrometer/calibration.toml");
+        assert_eq!(
+            config.devices[0].config_path(main),
+            PathBuf::from("/etc/spectrometer/calibration-ch1.toml")
+        );
+        assert_eq!(
+            config.devices[1].config_path(main),
+            PathBuf::from("/etc/spectrometer/ch2.toml")
+        );
+        let SourceSettings::Serial { baud, line, .. } = &config.devices[0].source else {
+            panic!("Expected serial source");
+        };
+        assert_eq!(*baud, 38400);
+        assert_eq!(line.parity, Parity::Even);
+        assert_eq!(line.dtr, Some(false));
+    }
+
+    #[test]
+    fn test_validate_devices() {
+        let device = |name: &str| DeviceEntry {
+            name: name.to_string(),
+            config: None,
+            source: SourceSettings::Socket {
+                path: PathBuf::from("ch.sock"),
+            },
+        };
+
+        assert!(validate_devices(&[device("ch-1"), device("ch_2")]).is_ok());
+        assert!(validate_devices(&[device("ch1"), device("ch1")]).is_err());
+        assert!(validate_devices(&[device("")]).is_err());
+        assert!(validate_devices(&[device("ch/1")]).is_err());
+    }
+
+    #[test]
+    fn test_device_file() {
+        assert_eq!(
+            device_file(Path::new("out/run.csv"), "ch1"),
+            PathBuf::from("out/run-ch1.csv")
+        );
+        assert_eq!(
+            device_file(Path::new("archive"), "ch1"),
+            PathBuf::from("archive-ch1")
+        );
+    }
+
+    #[test]
+    fn test_control_settings() {
+        let control = ControlSettings::default();
+        control.validate().unwrap();
+        assert!(control.check_wavelength(550.0).is_ok());
+        for wavelength in [f64::NAN, -550.0, 0.0, 1500.0] {
+            assert!(
+                control.check_wavelength(wavelength).is_err(),
+                "{wavelength}"
+            );
+        }
+        assert!(control.check_material("L").is_ok());
+        assert!(control.check_material("SiO2").is_err());
+
+        let inverted = ControlSettings {
+            wavelength_min: 900.0,
+            wavelength_max: 400.0,
+            ..ControlSettings::default()
+        };
+        assert!(inverted.validate().is_err());
+        let no_materials = ControlSettings {
+            materials: Vec::new(),
+            ..ControlSettings::default()
+        };
+        assert!(no_materials.validate().is_err());
+    }
+
+    #[test]
+    fn test_material_catalogue() {
+        let control: ControlSettings = toml::from_str(
+            r#"
+materials = [
+    "M",
+    { symbol = "H", name = "Ta2O5", refractive_index = 2.1, target_rate = 0.3, control_wavelength = 632.8 },
+]
+"#,
+        )
+        .unwrap();
+        control.validate().unwrap();
+        assert_eq!(control.material("M"), Some(&Material::symbol("M")));
+        let high = control.material("H").unwrap();
+        assert_eq!(high.name.as_deref(), Some("Ta2O5"));
+        assert_eq!(high.refractive_index, Some(2.1));
+        assert_eq!(high.control_wavelength, Some(632.8));
+        assert!(control.check_material("L").unwrap_err().contains("M, H"));
+
+        // Saved and read back
+        let saved = toml::to_string(&control).unwrap();
+        assert_eq!(toml::from_str::<ControlSettings>(&saved).unwrap(), control);
+
+        for materials in [
+            vec![Material::symbol("H"), Material::symbol("H")],
+            vec![Material::symbol("")],
+            vec![Material {
+                target_rate: Some(-1.0),
+                ..Material::symbol("H")
+            }],
+            vec![Material {
+                control_wavelength: Some(2000.0),
+                ..Material::symbol("H")
+            }],
+        ] {
+            let control = ControlSettings {
+                materials,
+                ..ControlSettings::default()
+            };
+            assert!(control.validate().is_err());
+        }
+    }
 }
@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::error::SpectrometerError;
+use crate::protocol::ShutterState;
+
+/// Maximum number of transitions kept in the log
+const MAX_TRANSITIONS: usize = 100;
+
+/// Vacuum chamber deposition state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChamberState {
+    Idle,
+    /// Getting ready for deposition, entered through
+    /// `/vacuum_chamber/prepare`
+    Preconditioning,
+    Depositing,
+    Paused,
+    Stopped,
+    /// A run was aborted by a fault, e.g. a failed data source; left by
+    /// stopping
+    Error,
+}
+
+impl ChamberState {
+    /// Whether moving from `self` to `to` is an allowed transition
+    pub fn can_transition_to(self, to: ChamberState) -> bool {
+        use ChamberState::*;
+
+        matches!(
+            (self, to),
+            (Idle, Preconditioning)
+                | (Idle, Depositing)
+                | (Preconditioning, Depositing)
+                | (Preconditioning, Stopped)
+                | (Depositing, Paused)
+                | (Depositing, Stopped)
+                | (Paused, Depositing)
+                | (Paused, Stopped)
+                | (Stopped, Idle)
+                | (Stopped, Preconditioning)
+                | (Stopped, Depositing)
+                | (Preconditioning | Depositing | Paused, Error)
+                | (Error, Stopped)
+                | (Error, Idle)
+        )
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChamberState::Idle => "idle",
+            ChamberState::Preconditioning => "preconditioning",
+            ChamberState::Depositing => "depositing",
+            ChamberState::Paused => "paused",
+            ChamberState::Stopped => "stopped",
+            ChamberState::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for ChamberState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A single recorded state change
+#[derive(Debug, Clone, Serialize)]
+pub struct ChamberTransition {
+    pub from: ChamberState,
+    pub to: ChamberState,
+    pub at: DateTime<Utc>,
+    /// Why the chamber entered `error`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Explicit chamber state machine with validated transitions
+#[derive(Debug, Clone)]
+pub struct ChamberStateMachine {
+    state: ChamberState,
+    /// When each state was most recently entered
+    entered_at: BTreeMap<ChamberState, DateTime<Utc>>,
+    transitions: VecDeque<ChamberTransition>,
+}
+
+impl ChamberStateMachine {
+    pub fn new() -> Self {
+        let mut entered_at = BTreeMap::new();
+        entered_at.insert(ChamberState::Idle, Utc::now());
+
+        Self {
+            state: ChamberState::Idle,
+            entered_at,
+            transitions: VecDeque::new(),
+        }
+    }
+
+    pub fn state(&self) -> ChamberState {
+        self.state
+    }
+
+    /// When the current state was entered
+    pub fn state_since(&self) -> DateTime<Utc> {
+        self.entered_at[&self.state]
+    }
+
+    pub fn entered_at(&self) -> &BTreeMap<ChamberState, DateTime<Utc>> {
+        &self.entered_at
+    }
+
+    /// Transition log, oldest first
+    pub fn transitions(&self) -> impl Iterator<Item = &ChamberTransition> {
+        self.transitions.iter()
+    }
+
+    /// Transitions since `since` (all when None), oldest first
+    pub fn transitions_since(&self, since: Option<DateTime<Utc>>) -> Vec<ChamberTransition> {
+        self.transitions
+            .iter()
+            .filter(|t| since.is_none_or(|since| t.at >= since))
+            .cloned()
+            .collect()
+    }
+
+    /// Why the chamber is in `error`, None in any other state
+    pub fn fault(&self) -> Option<&str> {
+        if self.state != ChamberState::Error {
+            return None;
+        }
+        self.transitions.back().and_then(|t| t.reason.as_deref())
+    }
+
+    /// Move to `to`, rejecting transitions the chamber cannot make.
+    /// Transitioning to the current state is a no-op.
+    pub fn transition_to(&mut self, to: ChamberState) -> Result<(), SpectrometerError> {
+        self.record(to, None)
+    }
+
+    /// Abort the current run into `error` for `reason`
+    pub fn fail(&mut self, reason: impl Into<String>) -> Result<(), SpectrometerError> {
+        self.record(ChamberState::Error, Some(reason.into()))
+    }
+
+    fn record(
+        &mut self,
+        to: ChamberState,
+        reason: Option<String>,
+    ) -> Result<(), SpectrometerError> {
+        if self.state == to {
+            return Ok(());
+        }
+
+        if !self.state.can_transition_to(to) {
+            return Err(SpectrometerError::InvalidTransition {
+                from: self.state.to_string(),
+                to: to.to_string(),
+            });
+        }
+
+        let now = Utc::now();
+        if self.transitions.len() == MAX_TRANSITIONS {
+            self.transitions.pop_front();
+        }
+        self.transitions.push_back(ChamberTransition {
+            from: self.state,
+            to,
+            at: now,
+            reason,
+        });
+        self.entered_at.insert(to, now);
+        self.state = to;
+
+        Ok(())
+    }
+
+    pub fn is_depositing(&self) -> bool {
+        self.state == ChamberState::Depositing
+    }
+}
+
+impl Default for ChamberStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where the known shutter position came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutterSource {
+    /// Sent through `POST /vacuum_chamber/shutter`
+    Command,
+    /// Reported by the firmware with `SHUTTER=`
+    Device,
+}
+
+/// Measurements taken in one shutter position
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ValidityCounts {
+    pub measurements: u64,
+    pub invalid: u64,
+}
+
+/// Last known shutter position and the validity of the measurements taken
+/// in each position
+#[derive(Debug, Clone, Default)]
+pub struct ShutterTracker {
+    state: Option<ShutterState>,
+    since: Option<DateTime<Utc>>,
+    source: Option<ShutterSource>,
+    open: ValidityCounts,
+    closed: ValidityCounts,
+}
+
+impl ShutterTracker {
+    pub fn state(&self) -> Option<ShutterState> {
+        self.state
+    }
+
+    /// When the shutter moved to its current position
+    pub fn since(&self) -> Option<DateTime<Utc>> {
+        self.since
+    }
+
+    pub fn source(&self) -> Option<ShutterSource> {
+        self.source
+    }
+
+    /// Measurements while open and while closed
+    pub fn counts(&self) -> (ValidityCounts, ValidityCounts) {
+        (self.open, self.closed)
+    }
+
+    /// Note the shutter at `state`; returns whether it moved
+    pub fn set(&mut self, state: ShutterState, source: ShutterSource, at: DateTime<Utc>) -> bool {
+        self.source = Some(source);
+        if self.state == Some(state) {
+            return false;
+        }
+        self.state = Some(state);
+        self.since = Some(at);
+        true
+    }
+
+    /// Count a measurement against the current position, if known
+    pub fn record(&mut self, is_valid: bool) {
+        let counts = match self.state {
+            Some(ShutterState::Open) => &mut self.open,
+            Some(ShutterState::Closed) => &mut self.closed,
+            None => return,
+        };
+        counts.measurements += 1;
+        if !is_valid {
+            counts.invalid += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initial_state_is_idle() {
+        let machine = ChamberStateMachine::new();
+        assert_eq!(machine.state(), ChamberState::Idle);
+        assert!(!machine.is_depositing());
+        assert_eq!(machine.transitions().count(), 0);
+    }
+
+    #[test]
+    fn test_full_run_transitions() {
+        let mut machine = ChamberStateMachine::new();
+
+        machine
+            .transition_to(ChamberState::Preconditioning)
+            .unwrap();
+        machine.transition_to(ChamberState::Depositing).unwrap();
+        machine.transition_to(ChamberState::Paused).unwrap();
+        machine.transition_to(ChamberState::Depositing).unwrap();
+        machine.transition_to(ChamberState::Stopped).unwrap();
+
+        assert_eq!(machine.state(), ChamberState::Stopped);
+        assert_eq!(machine.transitions().count(), 5);
+        assert!(machine.entered_at().contains_key(&ChamberState::Paused));
+    }
+
+    #[test]
+    fn test_invalid_transition_rejected() {
+        let mut machine = ChamberStateMachine::new();
+
+        let err = machine.transition_to(ChamberState::Paused).unwrap_err();
+        assert!(err.to_string().contains("idle -> paused"));
+        assert_eq!(machine.state(), ChamberState::Idle);
+        assert_eq!(machine.transitions().count(), 0);
+    }
+
+    #[test]
+    fn test_fault_aborts_run() {
+        let mut machine = ChamberStateMachine::new();
+        assert!(machine.fail("source failed").is_err());
+
+        machine.transition_to(ChamberState::Depositing).unwrap();
+        let started = machine.state_since();
+        machine.fail("source failed").unwrap();
+        assert_eq!(machine.state(), ChamberState::Error);
+        assert_eq!(machine.fault(), Some("source failed"));
+        assert!(!machine.is_depositing());
+        // Only stopping (or resetting) leaves the error
+        assert!(machine.transition_to(ChamberState::Depositing).is_err());
+        machine.transition_to(ChamberState::Stopped).unwrap();
+        assert_eq!(machine.fault(), None);
+
+        assert_eq!(machine.transitions_since(None).len(), 3);
+        assert_eq!(machine.transitions_since(Some(started)).len(), 3);
+        assert!(
+            machine
+                .transitions_since(Some(Utc::now() + chrono::Duration::seconds(1)))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_shutter_tracker() {
+        let mut shutter = ShutterTracker::default();
+        // Unknown position: nothing to correlate with
+        shutter.record(true);
+        assert_eq!(shutter.counts(), Default::default());
+
+        let t0 = Utc::now();
+        assert!(shutter.set(ShutterState::Closed, ShutterSource::Command, t0));
+        shutter.record(false);
+        shutter.record(false);
+        // Confirmed by the device: same position, same start
+        assert!(!shutter.set(
+            ShutterState::Closed,
+            ShutterSource::Device,
+            t0 + chrono::Duration::seconds(1)
+        ));
+        assert_eq!(shutter.since(), Some(t0));
+        assert_eq!(shutter.source(), Some(ShutterSource::Device));
+
+        assert!(shutter.set(ShutterState::Open, ShutterSource::Command, Utc::now()));
+        shutter.record(true);
+        let (open, closed) = shutter.counts();
+        assert_eq!(
+            open,
+            ValidityCounts {
+                measurements: 1,
+                invalid: 0
+            }
+        );
+        assert_eq!(
+            closed,
+            ValidityCounts {
+                measurements: 2,
+                invalid: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_same_state_is_noop() {
+        let mut machine = ChamberStateMachine::new();
+        machine.transition_to(ChamberState::Depositing).unwrap();
+        machine.transition_to(ChamberState::Depositing).unwrap();
+
+        assert_eq!(machine.transitions().count(), 1);
+    }
+
+    #[test]
+    fn test_transition_log_bounded() {
+        let mut machine = ChamberStateMachine::new();
+        machine.transition_to(ChamberState::Depositing).unwrap();
+        for _ in 0..MAX_TRANSITIONS {
+            machine.transition_to(ChamberState::Paused).unwrap();
+            machine.transition_to(ChamberState::Depositing).unwrap();
+        }
+
+        assert_eq!(machine.transitions().count(), MAX_TRANSITIONS);
+    }
+}
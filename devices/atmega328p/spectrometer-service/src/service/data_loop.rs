@@ -1,57 +1,242 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use tokio::sync::{broadcast, mpsc};
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc;
+use tracing::Instrument;
 
+use crate::data_sink::DataSink;
 use crate::error::SpectrometerError;
-use crate::monitoring::MonitoringClient;
-use crate::processing::calibration::{CalibrationProcessor, mean};
-use crate::processing::outlier::OutlierExcluder;
-use crate::protocol::{MeasurementCycle, ProcessedMeasurement};
-use crate::service::calibration::{MAX_ADC_VALUE, SeriesMapping, SharedConfig};
-use crate::service::state::SharedState;
+use crate::processing::dark::{RollingDark, SharedRollingDark, closed_shutter_level};
+use crate::processing::gaps::GapDetector;
+use crate::processing::outlier::SharedExcluder;
+use crate::processing::pipeline::{Pipeline, StageResources};
+use crate::processing::references::StoredReference;
+use crate::processing::smoothing::SmootherKind;
+use crate::processing::timing::TimestampMode;
+use crate::processing::validation::MeasurementValidator;
+use crate::protocol::{
+    MAX_ADC_VALUE, MeasurementCycle, ProcessedMeasurement, ShutterState, ThicknessEstimate,
+};
+use crate::service::calibration::{
+    DeviceSettings, ProcessingSettings, SeriesMapping, SharedConfig,
+};
+use crate::service::chamber::ShutterSource;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::history::SharedHistory;
+use crate::service::metrics::SharedMetrics;
+use crate::service::state::{DeviceState, SharedState};
+use crate::storage::{RunTags, SharedArchive};
+
+/// Assign the configured roles to the cycle's series.
+/// The parser defaults to SERIES1→dark, SERIES2→full, SERIES3→sample,
+/// but the physical order may differ. Cycles lacking a mapped series
+/// are dropped; with `sample_only`, for stored references, only the dark
+/// and full series may be absent.
+pub fn assign_roles(
+    mut cycle: MeasurementCycle,
+    mapping: &SeriesMapping,
+    sample_only: bool,
+) -> Option<MeasurementCycle> {
+    cycle.roles = mapping.clone();
+    let mut missing = cycle.missing_roles();
+    if sample_only {
+        missing.retain(|role| !SeriesMapping::is_reference_level(role));
+    }
+    if !missing.is_empty() {
+        tracing::warn!(
+            "Dropping cycle at {}: no series for {}",
+            cycle.timestamp,
+            missing.join(", ")
+        );
+        return None;
+    }
+    Some(cycle)
+}
 
 /// Background data processing loop
 pub struct DataProcessingLoop {
     state: SharedState,
     config: SharedConfig,
-    broadcast_tx: broadcast::Sender<serde_json::Value>,
-    outlier_excluder: Arc<dyn OutlierExcluder>,
-    monitoring_client: MonitoringClient,
-    calibrator: CalibrationProcessor,
+    history: SharedHistory,
+    events: EventBus,
+    metrics: SharedMetrics,
+    outlier_excluder: SharedExcluder,
+    /// Outputs receiving measurements while deposition is active
+    sinks: Vec<Box<dyn DataSink>>,
+    /// Archive receiving every processed measurement, deposition or not
+    archive: Option<SharedArchive>,
+    timestamp_mode: TimestampMode,
+    /// Dark estimate across cycles, reset when the acquisition settings change
+    rolling_dark: SharedRollingDark,
+    /// Acquisition settings the dark estimate was built under
+    acquisition: Mutex<Option<AcquisitionKey>>,
+    /// Missed-cycle detection, reset with the acquisition settings
+    gaps: Mutex<GapDetector>,
+    /// `--smoother`, overriding the configured smoother
+    smoother_override: Option<SmootherKind>,
+    /// Stage chain, built on the first cycle and rebuilt when
+    /// `processing.pipeline` changes
+    pipeline: Mutex<Option<Pipeline>>,
+    /// Cycles still flagged `transitional` after the last transition
+    blanking: Mutex<Blanking>,
+}
+
+/// Settings that shift the dark level: gain, rate, count and dark channel
+type AcquisitionKey = (u8, f32, u8, u8);
+
+/// What the first cycles after a change of violate the validation rule:
+/// the material, when the shutter last moved and when deposition started
+type TransitionKey = (String, Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Blanking window after a material change, shutter move or deposition
+/// start, from `validation.blanking_cycles`
+#[derive(Debug, Default)]
+struct Blanking {
+    /// Transition state of the previous cycle
+    key: Option<TransitionKey>,
+    remaining: u32,
+}
+
+impl Blanking {
+    /// Whether a cycle taken in `key` falls in the window, restarting it
+    /// when `key` differs from the previous cycle's. The first cycle only
+    /// sets the baseline.
+    fn check(&mut self, key: TransitionKey, cycles: u32) -> bool {
+        if let Some(previous) = self.key.replace(key)
+            && self.key.as_ref() != Some(&previous)
+        {
+            self.remaining = cycles;
+        }
+        // A shortened window applies at once
+        self.remaining = self.remaining.min(cycles);
+        if self.remaining == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+}
+
+/// Transition state of `state`, for `Blanking`
+fn transition_key(state: &DeviceState) -> TransitionKey {
+    let deposition_start = state
+        .chamber
+        .is_depositing()
+        .then(|| state.chamber.state_since());
+    (
+        state.current_material.clone(),
+        state.shutter.since(),
+        deposition_start,
+    )
 }
 
 impl DataProcessingLoop {
     pub fn new(
         state: SharedState,
         config: SharedConfig,
-        broadcast_tx: broadcast::Sender<serde_json::Value>,
-        outlier_excluder: Box<dyn OutlierExcluder>,
+        history: SharedHistory,
+        events: EventBus,
+        metrics: SharedMetrics,
+        outlier_excluder: SharedExcluder,
     ) -> Self {
         Self {
             state,
             config,
-            broadcast_tx,
-            outlier_excluder: Arc::from(outlier_excluder),
-            monitoring_client: MonitoringClient::new(),
-            calibrator: CalibrationProcessor::new(),
+            history,
+            events,
+            metrics,
+            outlier_excluder,
+            sinks: Vec::new(),
+            archive: None,
+            timestamp_mode: TimestampMode::default(),
+            rolling_dark: Arc::new(Mutex::new(RollingDark::default())),
+            acquisition: Mutex::new(None),
+            gaps: Mutex::new(GapDetector::default()),
+            smoother_override: None,
+            pipeline: Mutex::new(None),
+            blanking: Mutex::new(Blanking::default()),
         }
     }
 
-    /// Remap series based on configured mapping.
-    /// The parser always puts SERIES1→dark, SERIES2→full, SERIES3→sample,
-    /// but the physical order may differ.
-    fn remap_cycle(&self, cycle: &MeasurementCycle, mapping: &SeriesMapping) -> MeasurementCycle {
-        // Raw series from parser: index 1=dark, 2=full, 3=sample
-        let series = [&cycle.dark, &cycle.full, &cycle.sample];
+    /// Set the outputs each processed measurement is fanned out to
+    pub fn with_sinks(mut self, sinks: Vec<Box<dyn DataSink>>) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
+    /// Archive every processed measurement to a local database
+    pub fn with_archive(mut self, archive: SharedArchive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Use this smoother regardless of the config file
+    pub fn with_smoother(mut self, kind: Option<SmootherKind>) -> Self {
+        self.smoother_override = kind;
+        self
+    }
+
+    /// Set which instant of the acquisition window measurements are stamped with
+    pub fn with_timestamp_mode(mut self, mode: TimestampMode) -> Self {
+        self.timestamp_mode = mode;
+        self
+    }
 
-        let get = |n: u8| series[(n - 1).min(2) as usize].clone();
+    /// Reset the pipeline's cross-cycle state (dark estimate, smoothing
+    /// history) and gap detection when the acquisition settings differ from
+    /// the previous cycle's. The dark level and the cycle interval depend on
+    /// them.
+    fn track_acquisition(&self, settings: &DeviceSettings) {
+        let current = (
+            settings.gain,
+            settings.fadc,
+            settings.count,
+            settings.series_mapping.dark,
+        );
+        let previous = self.acquisition.lock().unwrap().replace(current);
+        if previous.is_some_and(|previous| previous != current) {
+            tracing::info!("Acquisition settings changed, resetting dark estimate and smoothing");
+            if let Some(pipeline) = self.pipeline.lock().unwrap().as_ref() {
+                pipeline.reset();
+            }
+            self.gaps.lock().unwrap().reset();
+        }
+    }
 
-        MeasurementCycle::with_timestamp(
+    /// Gain the device confirmed, or the configured one when it confirmed
+    /// none (simulation, playback, or a firmware without confirmations) or
+    /// the settings were changed since, from the UI or by auto-gain
+    async fn acquisition_gain(
+        &self,
+        settings: &DeviceSettings,
+        settings_changed_at: DateTime<Utc>,
+    ) -> u8 {
+        self.state
+            .read()
+            .await
+            .confirmed_settings
+            .filter(|confirmed| confirmed.confirmed_at >= settings_changed_at)
+            .map_or(settings.gain, |confirmed| confirmed.gain)
+    }
+
+    /// Report cycles lost before this one
+    fn check_gap(&self, cycle: &MeasurementCycle) {
+        let Some(gap) = self.gaps.lock().unwrap().check(cycle) else {
+            return;
+        };
+
+        tracing::warn!(
+            "Missed {} cycle(s) before {} (detected by {})",
+            gap.missed,
             cycle.timestamp,
-            get(mapping.dark),
-            get(mapping.full),
-            get(mapping.sample),
-        )
+            gap.detection.as_str()
+        );
+        self.metrics.record_gap(&gap);
+        self.events.publish(ServiceEvent::CyclesMissed {
+            at: cycle.timestamp,
+            missed: gap.missed,
+            detection: gap.detection,
+        });
     }
 
     /// Run the processing loop, receiving cycles from the channel
@@ -60,148 +245,314 @@ impl DataProcessingLoop {
         mut cycle_rx: mpsc::Receiver<MeasurementCycle>,
     ) -> Result<(), SpectrometerError> {
         tracing::info!("Data processing loop started");
+        self.state.write().await.health.source_active = true;
 
         while let Some(cycle) = cycle_rx.recv().await {
-            // Remap series based on config
-            let mapping = {
-                let cfg = self.config.read().await;
-                cfg.config.device_settings.series_mapping.clone()
-            };
-            let cycle = self.remap_cycle(&cycle, &mapping);
-
-            let processed = self.process_cycle(&cycle);
-            let is_clipped = self.check_clipping(&cycle);
-
-            // Broadcast to WebSocket clients
-            let _ = self.broadcast_tx.send(serde_json::json!({
-                "type": "cycle",
-                "timestamp": processed.timestamp.to_rfc3339(),
-                "dark_mean": processed.dark_mean,
-                "full_mean": processed.full_mean,
-                "sample_mean": processed.sample_mean,
-                "calibrated_reading": processed.calibrated_reading,
-                "is_clipped": is_clipped,
-            }));
-
-            // Update device state
-            {
-                let mut state = self.state.write().await;
-                state.latest_reading = Some(processed.clone());
-            }
-
-            // Push to monitoring API if registered
-            let should_push = {
-                let state = self.state.read().await;
-                state.should_process_data()
-            };
-
-            if should_push {
-                self.push_to_monitoring(&processed).await;
-            }
+            let span = tracing::info_span!("cycle", id = cycle.id);
+            self.handle_cycle(cycle).instrument(span).await;
         }
 
+        self.state.write().await.health.source_active = false;
         tracing::info!("Data processing loop finished");
         Ok(())
     }
 
-    /// Check if any raw value in the cycle is at max (clipped/saturated)
-    fn check_clipping(&self, cycle: &MeasurementCycle) -> bool {
-        cycle.dark.values.contains(&MAX_ADC_VALUE)
-            || cycle.full.values.contains(&MAX_ADC_VALUE)
-            || cycle.sample.values.contains(&MAX_ADC_VALUE)
-    }
+    /// Process one cycle and hand the result to the state, history,
+    /// archive, event bus and sinks
+    async fn handle_cycle(&self, cycle: MeasurementCycle) {
+        // Remap series based on config
+        let (settings, processing, control, settings_changed_at) = {
+            let cfg = self.config.read().await;
+            (
+                cfg.config.device_settings.clone(),
+                cfg.config.processing.clone(),
+                cfg.config.control.clone(),
+                cfg.config.last_updated,
+            )
+        };
+        self.track_acquisition(&settings);
+        if let Some(shutter) = cycle.shutter {
+            self.track_shutter(shutter, cycle.timestamp).await;
+        }
+        if cycle.shutter == Some(ShutterState::Closed) {
+            self.check_gap(&cycle);
+            self.handle_dark_cycle(&cycle, &processing).await;
+            return;
+        }
+        let Some(mut cycle) = assign_roles(
+            cycle,
+            &settings.series_mapping,
+            processing.references.stored(),
+        ) else {
+            return;
+        };
+        self.check_gap(&cycle);
+        cycle.timestamp = self.timestamp_mode.timestamp(
+            cycle.timestamp,
+            settings.fadc,
+            settings.count,
+            settings.series_count,
+            settings.series_mapping.sample,
+        );
 
-    /// Process a single measurement cycle — per-cycle calibration
-    fn process_cycle(&self, cycle: &MeasurementCycle) -> ProcessedMeasurement {
-        let dark_values = cycle.dark.to_f64();
-        let full_values = cycle.full.to_f64();
-        let sample_values = cycle.sample.to_f64();
+        let gain = self.acquisition_gain(&settings, settings_changed_at).await;
+        let reference = if processing.references.stored() {
+            let state = self.state.read().await;
+            state.references.lookup(gain, state.control_wavelength)
+        } else {
+            None
+        };
+        let mut processed = self.process_cycle(&cycle, &processing, reference);
+        processed.physical = processing.units.convert(&processed, gain);
+        processed.clipped = self.check_clipping(&cycle);
+
+        // Update device state
+        let tags = {
+            let mut state = self.state.write().await;
+            processed.transitional = self.blanking.lock().unwrap().check(
+                transition_key(&state),
+                processing.validation.blanking_cycles,
+            );
+            processed.wavelength = Some(state.control_wavelength);
+            processed.material_info = control.material(&state.current_material).cloned();
+            if processing.thickness.enabled {
+                processed.thickness = estimate_thickness(&mut state, &processed, &processing);
+            }
+            state.latest_reading = Some(processed.clone());
+            state.dark_estimate = *self.rolling_dark.lock().unwrap();
+            if let Some(outliers) = &processed.outliers {
+                state.outlier_stats.record(outliers);
+            }
+            state.health.record_cycle(Utc::now());
+            if !processed.transitional {
+                state.health.record_validity(processed.is_valid);
+                state.shutter.record(processed.is_valid);
+            }
+            if state.should_process_data() {
+                state.layers.record_measurement(&processed);
+            }
+            RunTags {
+                run_id: state.run_id.clone(),
+                material: state.current_material.clone(),
+                layer: state.layer(),
+            }
+        };
+        self.metrics
+            .record_measurement(&processed, &tags.material, tags.layer);
+        self.history.write().await.push(processed.clone());
+
+        if let Some(archive) = &self.archive {
+            let measurement = processed.clone();
+            let appended = archive
+                .blocking(move |archive| archive.append(&measurement, &tags, &cycle))
+                .await;
+            if let Err(e) = appended {
+                tracing::error!("Failed to archive measurement: {e}");
+            }
+        }
 
-        let dark_filtered = self.outlier_excluder.filter(&dark_values);
-        let full_filtered = self.outlier_excluder.filter(&full_values);
-        let sample_filtered = self.outlier_excluder.filter(&sample_values);
+        if let Some(error) = &processed.validation_error
+            && !processed.transitional
+        {
+            self.events.publish(ServiceEvent::ValidationFailed {
+                timestamp: processed.timestamp,
+                error: error.clone(),
+            });
+        }
+        self.events
+            .publish(ServiceEvent::Measurement(Box::new(processed.clone())));
 
-        let dark_mean = mean(&dark_filtered);
-        let full_mean = mean(&full_filtered);
-        let sample_mean = mean(&sample_filtered);
+        // Fan out to sinks (monitoring API, files, ...) during deposition,
+        // once past the blanking window
+        let should_push = !processed.transitional && {
+            let state = self.state.read().await;
+            state.should_process_data()
+        };
 
-        let calibrated = self.calibrator.calculate(dark_mean, full_mean, sample_mean);
+        if should_push {
+            self.write_to_sinks(&processed).await;
+        }
+    }
 
-        let measurement = ProcessedMeasurement::new(
-            cycle.timestamp,
-            dark_mean,
-            full_mean,
-            sample_mean,
-            calibrated,
+    /// Note the shutter position the device reported with a cycle
+    async fn track_shutter(&self, shutter: ShutterState, at: DateTime<Utc>) {
+        let moved = self
+            .state
+            .write()
+            .await
+            .shutter
+            .set(shutter, ShutterSource::Device, at);
+        if moved {
+            self.events.publish(ServiceEvent::ShutterChanged {
+                at,
+                state: shutter,
+                source: ShutterSource::Device,
+            });
+        }
+    }
+
+    /// Fold a closed-shutter cycle into the rolling dark estimate instead of
+    /// producing a reading from it
+    async fn handle_dark_cycle(&self, cycle: &MeasurementCycle, processing: &ProcessingSettings) {
+        let excluder = self.outlier_excluder.read().unwrap().clone();
+        let [aggregator, _, _] = processing.aggregation.series_aggregators();
+        let validator = MeasurementValidator::with_settings(processing.validation);
+        let Some(level) =
+            closed_shutter_level(cycle, excluder.as_ref(), aggregator.as_ref(), &validator)
+        else {
+            tracing::debug!("Skipping closed-shutter cycle without usable values");
+            return;
+        };
+
+        let rolling_dark = {
+            let mut rolling_dark = self.rolling_dark.lock().unwrap();
+            rolling_dark.update(level, processing.dark_compensation.smoothing);
+            *rolling_dark
+        };
+        tracing::debug!(
+            "Closed-shutter cycle: dark={:.0}, estimate={:.0}",
+            level,
+            rolling_dark.estimate().unwrap_or(level)
         );
+        {
+            let mut state = self.state.write().await;
+            state.dark_estimate = rolling_dark;
+            state.health.record_cycle(Utc::now());
+        }
+        self.metrics.record_dark_cycle();
+
+        self.events.publish(ServiceEvent::DarkCycle {
+            cycle_id: cycle.id,
+            timestamp: cycle.timestamp,
+            dark_level: level,
+            dark_estimate: rolling_dark.estimate(),
+        });
+    }
+
+    /// Check if any raw value in the cycle is at max (clipped/saturated)
+    fn check_clipping(&self, cycle: &MeasurementCycle) -> bool {
+        cycle
+            .series
+            .values()
+            .any(|series| series.values.contains(&MAX_ADC_VALUE))
+    }
+
+    /// Process a single measurement cycle through the configured stages
+    fn process_cycle(
+        &self,
+        cycle: &MeasurementCycle,
+        processing: &ProcessingSettings,
+        reference: Option<StoredReference>,
+    ) -> ProcessedMeasurement {
+        let mut pipeline = self.pipeline.lock().unwrap();
+        let stages = processing.pipeline();
+        if pipeline.as_ref().is_none_or(|p| p.kinds() != stages) {
+            let built = Pipeline::build(
+                stages,
+                &StageResources {
+                    outlier_excluder: self.outlier_excluder.clone(),
+                    rolling_dark: self.rolling_dark.clone(),
+                    smoother_override: self.smoother_override,
+                },
+            );
+            tracing::info!("Processing pipeline: {}", built.describe());
+            *pipeline = Some(built);
+        }
+        let measurement = pipeline
+            .as_ref()
+            .expect("pipeline built above")
+            .run(cycle, processing, reference);
 
         tracing::debug!(
             "Processed: dark={:.0}, full={:.0}, sample={:.0}, T={:.2}%, clipped={}",
-            dark_mean,
-            full_mean,
-            sample_mean,
-            calibrated,
+            measurement.dark_mean,
+            measurement.full_mean,
+            measurement.sample_mean,
+            measurement.calibrated_reading,
             self.check_clipping(cycle),
         );
 
         measurement
     }
 
-    /// Push processed measurement to the monitoring API
-    async fn push_to_monitoring(&self, measurement: &ProcessedMeasurement) {
-        let (api_url, spectrometer_id, control_wavelength) = {
-            let state = self.state.read().await;
-            (
-                state.monitoring_api_url.clone(),
-                state.spectrometer_id.clone(),
-                state.control_wavelength,
-            )
-        };
-
-        let Some(api_url) = api_url else {
-            return;
-        };
-
-        let Some(spec_id) = spectrometer_id else {
-            return;
-        };
-
-        let result = self
-            .monitoring_client
-            .post_spectral_data(
-                &api_url,
-                &spec_id,
-                &[measurement.calibrated_reading],
-                Some(&[control_wavelength]),
-                measurement.timestamp,
-            )
-            .await;
-
-        if let Err(e) = result {
-            tracing::error!("Failed to push data to monitoring: {e}");
+    /// Deliver a measurement to every sink. A failing sink does not
+    /// prevent delivery to the others.
+    async fn write_to_sinks(&self, measurement: &ProcessedMeasurement) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.write(measurement).await {
+                tracing::error!("Failed to write to {} sink: {e}", sink.name());
+                self.events.publish(ServiceEvent::PushFailed {
+                    at: Utc::now(),
+                    sink: sink.name().to_string(),
+                    timestamp: measurement.timestamp,
+                    error: e.to_string(),
+                });
+            }
         }
     }
 }
 
+/// Fold a valid measurement of the open layer into the thickness estimate
+/// and return the estimate; None outside deposition
+fn estimate_thickness(
+    state: &mut DeviceState,
+    measurement: &ProcessedMeasurement,
+    processing: &ProcessingSettings,
+) -> Option<ThicknessEstimate> {
+    if !state.should_process_data() {
+        return None;
+    }
+    let layer = state.layers.records().last()?;
+    let (index, started_at) = (layer.index, layer.started_at);
+    if measurement.is_valid {
+        state.thickness.record(
+            index,
+            started_at,
+            measurement.calibrated_reading,
+            processing.thickness.min_swing,
+        );
+    }
+    let refractive_index = measurement
+        .material_info
+        .as_ref()
+        .and_then(|material| material.refractive_index);
+    state
+        .thickness
+        .estimate(state.control_wavelength, refractive_index)
+}
+
 #[cfg(test)]
 mod tests {
-
-    use chrono::Utc;
-    use tokio::sync::broadcast;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
 
     use super::*;
+    use crate::processing::outlier::create_shared_excluder;
     use crate::processing::outlier::grubbs::GrubbsExcluder;
+    use crate::processing::pipeline::StageKind;
     use crate::protocol::SeriesData;
-    use crate::service::calibration::create_shared_config;
+    use crate::service::calibration::{ProcessingSettings, create_shared_config};
+    use crate::service::history::create_shared_history;
+    use crate::service::metrics::create_shared_metrics;
     use crate::service::state::create_shared_state;
 
     fn test_loop() -> (DataProcessingLoop, tempfile::TempDir) {
         let dir = tempfile::tempdir().unwrap();
         let state = create_shared_state();
         let config = create_shared_config(dir.path().join("cfg.toml"));
-        let (tx, _) = broadcast::channel(16);
-        let excluder = Box::new(GrubbsExcluder::new(0.05));
-        (DataProcessingLoop::new(state, config, tx, excluder), dir)
+        let excluder = create_shared_excluder(Box::new(GrubbsExcluder::new(0.05).unwrap()));
+        let history = create_shared_history(16);
+        (
+            DataProcessingLoop::new(
+                state,
+                config,
+                history,
+                EventBus::default(),
+                create_shared_metrics(),
+                excluder,
+            ),
+            dir,
+        )
     }
 
     #[test]
@@ -213,8 +564,221 @@ mod tests {
             SeriesData::new(vec![1000, 1001, 1002]),
             SeriesData::new(vec![500, 501, 502]),
         );
-        let processed = lp.process_cycle(&cycle);
+        let processed = lp.process_cycle(&cycle, &ProcessingSettings::default(), None);
         assert!(processed.calibrated_reading > 40.0 && processed.calibrated_reading < 50.0);
+        assert!(processed.is_valid);
+    }
+
+    #[test]
+    fn test_process_cycle_flags_invalid() {
+        let (lp, _dir) = test_loop();
+        // Sample brighter than the full reference
+        let cycle = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![1000, 1001, 1002]),
+            SeriesData::new(vec![1500, 1501, 1502]),
+        );
+        let processed = lp.process_cycle(&cycle, &ProcessingSettings::default(), None);
+        assert!(!processed.is_valid);
+        assert!(processed.validation_error.is_some());
+    }
+
+    #[test]
+    fn test_process_cycle_flags_saturated_full() {
+        let (lp, _dir) = test_loop();
+        // Clipped full scan still yields a plausible reading
+        let cycle = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![MAX_ADC_VALUE, MAX_ADC_VALUE, MAX_ADC_VALUE]),
+            SeriesData::new(vec![5_000_000, 5_000_001, 5_000_002]),
+        );
+        let processed = lp.process_cycle(&cycle, &ProcessingSettings::default(), None);
+        assert!(!processed.is_valid);
+        assert_eq!(
+            processed.validation_category,
+            Some(crate::protocol::ValidationCategory::Saturation)
+        );
+    }
+
+    #[test]
+    fn test_sequence_gap_publishes_event() {
+        let (lp, _dir) = test_loop();
+        let mut events = lp.events.subscribe();
+        let cycle = |sequence| MeasurementCycle {
+            sequence: Some(sequence),
+            ..MeasurementCycle::with_timestamp(
+                Utc::now(),
+                SeriesData::new(vec![100]),
+                SeriesData::new(vec![1000]),
+                SeriesData::new(vec![500]),
+            )
+        };
+
+        lp.check_gap(&cycle(1));
+        lp.check_gap(&cycle(2));
+        assert!(events.try_recv().is_err());
+
+        lp.check_gap(&cycle(5));
+        match events.try_recv().unwrap() {
+            ServiceEvent::CyclesMissed { missed, .. } => assert_eq!(missed, 2),
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_dark_reset_on_acquisition_change() {
+        let (lp, _dir) = test_loop();
+        let mut settings = DeviceSettings::default();
+        let cycle = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![1000, 1001, 1002]),
+            SeriesData::new(vec![500, 501, 502]),
+        );
+        lp.track_acquisition(&settings);
+        lp.process_cycle(&cycle, &ProcessingSettings::default(), None);
+
+        // Unchanged settings keep the estimate
+        lp.track_acquisition(&settings);
+        assert_eq!(lp.rolling_dark.lock().unwrap().cycles(), 1);
+
+        settings.gain = 8;
+        lp.track_acquisition(&settings);
+        assert_eq!(lp.rolling_dark.lock().unwrap().estimate(), None);
+    }
+
+    #[tokio::test]
+    async fn test_acquisition_gain_prefers_confirmed() {
+        let (lp, _dir) = test_loop();
+        let settings = DeviceSettings {
+            gain: 2,
+            ..Default::default()
+        };
+        let loaded_at = Utc::now() - chrono::Duration::minutes(1);
+        assert_eq!(lp.acquisition_gain(&settings, loaded_at).await, 2);
+
+        lp.state.write().await.confirmed_settings = Some(crate::protocol::ConfirmedSettings {
+            gain: 16,
+            fadc: 250.0,
+            count: 4,
+            confirmed_at: Utc::now(),
+        });
+        assert_eq!(lp.acquisition_gain(&settings, loaded_at).await, 16);
+
+        // Changed at runtime after the device confirmed
+        let changed_at = Utc::now() + chrono::Duration::seconds(1);
+        assert_eq!(lp.acquisition_gain(&settings, changed_at).await, 2);
+    }
+
+    #[test]
+    fn test_process_cycle_follows_configured_pipeline() {
+        let (lp, _dir) = test_loop();
+        let cycle = MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![1000, 1001, 1002]),
+            SeriesData::new(vec![1500, 1501, 1502]),
+        );
+
+        let full = lp.process_cycle(&cycle, &ProcessingSettings::default(), None);
+        assert!(full.statistics.is_some());
+        assert!(!full.is_valid);
+
+        let minimal = ProcessingSettings {
+            pipeline: Some(vec![StageKind::Aggregation, StageKind::Calibration]),
+            ..Default::default()
+        };
+        let processed = lp.process_cycle(&cycle, &minimal, None);
+        assert!(processed.statistics.is_none());
+        assert!(processed.is_valid);
+    }
+
+    #[tokio::test]
+    async fn test_run_publishes_events() {
+        let (lp, _dir) = test_loop();
+        let mut events = lp.events.subscribe();
+        let (tx, rx) = mpsc::channel(4);
+
+        tx.send(MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![1000, 1001, 1002]),
+            SeriesData::new(vec![1500, 1501, 1502]),
+        ))
+        .await
+        .unwrap();
+        drop(tx);
+        lp.run(rx).await.unwrap();
+
+        let first = events.recv().await.unwrap();
+        assert!(matches!(first, ServiceEvent::ValidationFailed { .. }));
+        let second = events.recv().await.unwrap();
+        assert!(matches!(second, ServiceEvent::Measurement(_)));
+    }
+
+    #[tokio::test]
+    async fn test_measurement_event_flags_clipping() {
+        let (lp, _dir) = test_loop();
+        let mut events = lp.events.subscribe();
+        let (tx, rx) = mpsc::channel(4);
+
+        tx.send(MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![1000, MAX_ADC_VALUE, 1002]),
+            SeriesData::new(vec![500, 501, 502]),
+        ))
+        .await
+        .unwrap();
+        drop(tx);
+        lp.run(rx).await.unwrap();
+
+        while let Ok(event) = events.try_recv() {
+            if let ServiceEvent::Measurement(measurement) = event {
+                assert!(measurement.clipped);
+                return;
+            }
+        }
+        panic!("no measurement event");
+    }
+
+    #[tokio::test]
+    async fn test_closed_shutter_cycle_feeds_dark_estimate() {
+        let (lp, _dir) = test_loop();
+        let mut events = lp.events.subscribe();
+        let (tx, rx) = mpsc::channel(4);
+
+        // Dark-only cycle with a single series
+        let mut dark = MeasurementCycle::from_series(
+            Utc::now(),
+            BTreeMap::from([(1, SeriesData::new(vec![14_000_000, 14_000_002]))]),
+        );
+        dark.shutter = Some(ShutterState::Closed);
+        tx.send(dark).await.unwrap();
+        drop(tx);
+        lp.run(rx).await.unwrap();
+
+        let state = lp.state.read().await;
+        assert_eq!(state.dark_estimate.estimate(), Some(14_000_001.0));
+        assert!(state.latest_reading.is_none());
+        assert!(lp.history.read().await.is_empty());
+        assert_eq!(state.shutter.state(), Some(ShutterState::Closed));
+        // The reported position and the dark level are published, but no
+        // measurement
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            ServiceEvent::ShutterChanged {
+                source: ShutterSource::Device,
+                ..
+            }
+        ));
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            ServiceEvent::DarkCycle { dark_level, .. } if dark_level == 14_000_001.0
+        ));
+        assert!(events.try_recv().is_err());
     }
 
     #[test]
@@ -226,8 +790,9 @@ mod tests {
             SeriesData::new(vec![300, 310, 305]),
             SeriesData::new(vec![13_000_000, 13_000_100, 13_000_050]),
         );
-        let processed = lp.process_cycle(&cycle);
+        let processed = lp.process_cycle(&cycle, &ProcessingSettings::default(), None);
         assert!(processed.calibrated_reading > 0.0);
+        assert!(processed.is_valid);
     }
 
     #[test]
@@ -250,4 +815,179 @@ mod tests {
         );
         assert!(!lp.check_clipping(&good));
     }
+
+    /// Sink recording delivered readings, optionally failing every write
+    struct RecordingSink {
+        received: Arc<std::sync::Mutex<Vec<f64>>>,
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl DataSink for RecordingSink {
+        async fn write(&self, measurement: &ProcessedMeasurement) -> Result<(), SpectrometerError> {
+            self.received
+                .lock()
+                .unwrap()
+                .push(measurement.calibrated_reading);
+            if self.fail {
+                return Err(SpectrometerError::DataSource("sink down".to_string()));
+            }
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "recording"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_fans_out_to_all_sinks() {
+        let failing = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let healthy = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (lp, _dir) = test_loop();
+        let lp = lp.with_sinks(vec![
+            Box::new(RecordingSink {
+                received: failing.clone(),
+                fail: true,
+            }),
+            Box::new(RecordingSink {
+                received: healthy.clone(),
+                fail: false,
+            }),
+        ]);
+        lp.state
+            .write()
+            .await
+            .chamber
+            .transition_to(crate::service::chamber::ChamberState::Depositing)
+            .unwrap();
+
+        let mut events = lp.events.subscribe();
+
+        let (tx, rx) = mpsc::channel(4);
+        tx.send(MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![1000, 1001, 1002]),
+            SeriesData::new(vec![500, 501, 502]),
+        ))
+        .await
+        .unwrap();
+        drop(tx);
+        lp.run(rx).await.unwrap();
+
+        assert_eq!(failing.lock().unwrap().len(), 1);
+        assert_eq!(healthy.lock().unwrap().len(), 1);
+        let failures: Vec<String> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|event| match event {
+                ServiceEvent::PushFailed { sink, error, .. } => Some(format!("{sink}: {error}")),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(failures, ["recording: Data source error: sink down"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_archives_every_cycle() {
+        use crate::storage::{MeasurementArchive, MeasurementFilter};
+
+        let (lp, dir) = test_loop();
+        let archive =
+            Arc::new(MeasurementArchive::open(&dir.path().join("archive.db"), false).unwrap());
+        let lp = lp.with_archive(archive.clone());
+        {
+            let mut state = lp.state.write().await;
+            state.run_id = Some("run-1".to_string());
+            state.layers.start_run("H", Utc::now());
+            state.layers.next_layer("L", Utc::now());
+        }
+
+        // Not depositing: sinks are skipped but the archive still records
+        let (tx, rx) = mpsc::channel(4);
+        tx.send(MeasurementCycle::with_timestamp(
+            Utc::now(),
+            SeriesData::new(vec![100, 101, 102]),
+            SeriesData::new(vec![1000, 1001, 1002]),
+            SeriesData::new(vec![500, 501, 502]),
+        ))
+        .await
+        .unwrap();
+        drop(tx);
+        lp.run(rx).await.unwrap();
+
+        let stored = archive.query(&MeasurementFilter::default()).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].run_id.as_deref(), Some("run-1"));
+        assert_eq!(stored[0].layer, 2);
+    }
+
+    #[test]
+    fn test_blanking_restarts_on_transition() {
+        let key = |material: &str| (material.to_string(), None, None);
+        let mut blanking = Blanking::default();
+        assert!(!blanking.check(key("H"), 2));
+        assert!(!blanking.check(key("H"), 2));
+
+        let flagged: Vec<bool> = (0..3).map(|_| blanking.check(key("L"), 2)).collect();
+        assert_eq!(flagged, [true, true, false]);
+
+        // Disabling ends a window already running
+        assert!(blanking.check(key("H"), 2));
+        assert!(!blanking.check(key("H"), 0));
+    }
+
+    #[tokio::test]
+    async fn test_transitional_cycles_skip_sinks_and_warnings() {
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (lp, _dir) = test_loop();
+        let lp = lp.with_sinks(vec![Box::new(RecordingSink {
+            received: received.clone(),
+            fail: false,
+        })]);
+        lp.config
+            .write()
+            .await
+            .config
+            .processing
+            .validation
+            .blanking_cycles = 2;
+        lp.state
+            .write()
+            .await
+            .chamber
+            .transition_to(crate::service::chamber::ChamberState::Depositing)
+            .unwrap();
+        let mut events = lp.events.subscribe();
+        // Sample above full: invalid under any rule
+        let cycle = || {
+            MeasurementCycle::with_timestamp(
+                Utc::now(),
+                SeriesData::new(vec![100, 101, 102]),
+                SeriesData::new(vec![1000, 1001, 1002]),
+                SeriesData::new(vec![5000, 5001, 5002]),
+            )
+        };
+
+        lp.handle_cycle(cycle()).await;
+        lp.state.write().await.current_material = "L".to_string();
+        for _ in 0..3 {
+            lp.handle_cycle(cycle()).await;
+        }
+
+        let flags: Vec<bool> = lp
+            .history
+            .read()
+            .await
+            .latest(4)
+            .iter()
+            .map(|m| m.transitional)
+            .collect();
+        assert_eq!(flags, [false, true, true, false]);
+        assert_eq!(received.lock().unwrap().len(), 2);
+        let warnings = std::iter::from_fn(|| events.try_recv().ok())
+            .filter(|event| matches!(event, ServiceEvent::ValidationFailed { .. }))
+            .count();
+        assert_eq!(warnings, 2);
+        assert_eq!(lp.state.read().await.health.invalid_cycles, 2);
+    }
 }
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::protocol::{DeviceEvent, ProtocolIssue, ProtocolIssueKind};
+
+/// Number of offending lines kept for `GET /diagnostics/protocol`
+pub const MAX_PROTOCOL_ISSUES: usize = 50;
+
+/// Number of firmware status lines kept for `GET /diagnostics/events`
+pub const MAX_DEVICE_EVENTS: usize = 200;
+
+/// Number of monitoring push attempts kept for `GET /diagnostics/monitoring`
+pub const MAX_PUSH_ATTEMPTS: usize = 100;
+
+/// Protocol problem counts for one data source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ProtocolCounts {
+    /// Lines matching no known message
+    pub unknown: u64,
+    /// `ERROR` lines from the firmware
+    pub errors: u64,
+    /// `Measurement cycle is missing` lines
+    pub cycle_missing: u64,
+    /// Partial cycles dropped after the cycle timeout
+    pub stale_cycles: u64,
+    /// Lines rejected for a checksum mismatch
+    pub checksum_errors: u64,
+    /// Series lines with values above the ADC range
+    pub out_of_range: u64,
+    /// Malformed lines reported by strict parsing
+    pub malformed: u64,
+}
+
+impl ProtocolCounts {
+    pub fn add(&mut self, kind: ProtocolIssueKind) {
+        match kind {
+            ProtocolIssueKind::Unknown => self.unknown += 1,
+            ProtocolIssueKind::Error => self.errors += 1,
+            ProtocolIssueKind::CycleMissing => self.cycle_missing += 1,
+            ProtocolIssueKind::StaleCycle => self.stale_cycles += 1,
+            ProtocolIssueKind::Checksum => self.checksum_errors += 1,
+            ProtocolIssueKind::OutOfRange => self.out_of_range += 1,
+            ProtocolIssueKind::Malformed => self.malformed += 1,
+        }
+    }
+}
+
+/// Unknown, error, cycle-missing, corrupt and out-of-range lines and
+/// dropped partial cycles seen since startup. Without these, unknown lines
+/// vanish silently and protocol drift goes unnoticed.
+#[derive(Debug, Clone, Default)]
+pub struct ProtocolDiagnostics {
+    /// Counts by data source name
+    sources: BTreeMap<String, ProtocolCounts>,
+    /// Most recent offending lines, oldest first
+    recent: VecDeque<ProtocolIssue>,
+}
+
+impl ProtocolDiagnostics {
+    /// Count an issue and keep its line, dropping the oldest beyond
+    /// `MAX_PROTOCOL_ISSUES`
+    pub fn record(&mut self, issue: ProtocolIssue) {
+        self.sources
+            .entry(issue.source.clone())
+            .or_default()
+            .add(issue.kind);
+
+        if self.recent.len() == MAX_PROTOCOL_ISSUES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(issue);
+    }
+
+    pub fn sources(&self) -> &BTreeMap<String, ProtocolCounts> {
+        &self.sources
+    }
+
+    pub fn recent(&self) -> impl Iterator<Item = &ProtocolIssue> {
+        self.recent.iter()
+    }
+}
+
+/// Firmware errors, cycle-missing warnings and ADC (re)initialisations,
+/// most recent last, so a flaky device can be diagnosed after the fact
+#[derive(Debug, Clone, Default)]
+pub struct DeviceEventLog {
+    events: VecDeque<DeviceEvent>,
+}
+
+impl DeviceEventLog {
+    /// Keep an event, dropping the oldest beyond `MAX_DEVICE_EVENTS`
+    pub fn record(&mut self, event: DeviceEvent) {
+        if self.events.len() == MAX_DEVICE_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = &DeviceEvent> {
+        self.events.iter()
+    }
+}
+
+/// One attempt to push a measurement to a monitoring endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PushAttempt {
+    pub at: DateTime<Utc>,
+    /// Monitoring API URL
+    pub endpoint: String,
+    /// Timestamp of the measurement pushed
+    pub timestamp: DateTime<Utc>,
+    pub latency_ms: f64,
+    /// HTTP status of the response; unset when no response arrived
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Push outcomes of one monitoring endpoint since startup
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EndpointDelivery {
+    pub delivered: u64,
+    pub failed: u64,
+    /// Failed attempts since the last delivery
+    pub consecutive_failures: u64,
+    pub last_delivered_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Monitoring push attempts, most recent last, with totals per endpoint,
+/// so operators can see whether data is reaching OptiMonitor
+#[derive(Debug, Clone, Default)]
+pub struct PushLog {
+    /// Totals by endpoint URL
+    endpoints: BTreeMap<String, EndpointDelivery>,
+    recent: VecDeque<PushAttempt>,
+}
+
+impl PushLog {
+    /// Count an attempt and keep it, dropping the oldest beyond
+    /// `MAX_PUSH_ATTEMPTS`
+    pub fn record(&mut self, attempt: PushAttempt) {
+        let delivery = self.endpoints.entry(attempt.endpoint.clone()).or_default();
+        match &attempt.error {
+            None => {
+                delivery.delivered += 1;
+                delivery.consecutive_failures = 0;
+                delivery.last_delivered_at = Some(attempt.at);
+            }
+            Some(error) => {
+                delivery.failed += 1;
+                delivery.consecutive_failures += 1;
+                delivery.last_error = Some(error.clone());
+            }
+        }
+
+        if self.recent.len() == MAX_PUSH_ATTEMPTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(attempt);
+    }
+
+    pub fn endpoints(&self) -> &BTreeMap<String, EndpointDelivery> {
+        &self.endpoints
+    }
+
+    pub fn recent(&self) -> impl Iterator<Item = &PushAttempt> {
+        self.recent.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::protocol::DeviceEventKind;
+
+    fn issue(source: &str, kind: ProtocolIssueKind, line: &str) -> ProtocolIssue {
+        ProtocolIssue {
+            timestamp: Utc::now(),
+            source: source.to_string(),
+            kind,
+            line: line.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_counts_per_source() {
+        let mut diagnostics = ProtocolDiagnostics::default();
+        diagnostics.record(issue("/dev/ttyUSB0", ProtocolIssueKind::Unknown, "x"));
+        diagnostics.record(issue("/dev/ttyUSB0", ProtocolIssueKind::Error, "ERROR y"));
+        diagnostics.record(issue("run.log", ProtocolIssueKind::CycleMissing, "z"));
+
+        assert_eq!(
+            diagnostics.sources()["/dev/ttyUSB0"],
+            ProtocolCounts {
+                unknown: 1,
+                errors: 1,
+                cycle_missing: 0,
+                stale_cycles: 0,
+                checksum_errors: 0,
+                out_of_range: 0,
+                malformed: 0
+            }
+        );
+        assert_eq!(diagnostics.sources()["run.log"].cycle_missing, 1);
+    }
+
+    #[test]
+    fn test_device_event_log_bounded() {
+        let mut log = DeviceEventLog::default();
+        for i in 0..MAX_DEVICE_EVENTS + 3 {
+            log.record(DeviceEvent {
+                timestamp: Utc::now(),
+                source: "port".to_string(),
+                kind: DeviceEventKind::Error,
+                line: format!("ERROR {i}"),
+            });
+        }
+
+        let events: Vec<_> = log.events().collect();
+        assert_eq!(events.len(), MAX_DEVICE_EVENTS);
+        assert_eq!(events[0].line, "ERROR 3");
+    }
+
+    #[test]
+    fn test_recent_lines_bounded() {
+        let mut diagnostics = ProtocolDiagnostics::default();
+        for i in 0..MAX_PROTOCOL_ISSUES + 5 {
+            diagnostics.record(issue("port", ProtocolIssueKind::Unknown, &i.to_string()));
+        }
+
+        let recent: Vec<_> = diagnostics.recent().collect();
+        assert_eq!(recent.len(), MAX_PROTOCOL_ISSUES);
+        assert_eq!(recent[0].line, "5");
+        assert_eq!(
+            diagnostics.sources()["port"].unknown,
+            (MAX_PROTOCOL_ISSUES + 5) as u64
+        );
+    }
+
+    #[test]
+    fn test_push_log_totals_per_endpoint() {
+        let attempt = |endpoint: &str, error: Option<&str>| PushAttempt {
+            at: Utc::now(),
+            endpoint: endpoint.to_string(),
+            timestamp: Utc::now(),
+            latency_ms: 12.0,
+            status: Some(if error.is_some() { 503 } else { 200 }),
+            error: error.map(str::to_string),
+        };
+        let mut log = PushLog::default();
+        log.record(attempt("http://prod", None));
+        log.record(attempt("http://prod", Some("Monitoring API returned 503")));
+        log.record(attempt("http://prod", Some("Monitoring API returned 503")));
+        log.record(attempt("http://staging", None));
+        for _ in 0..MAX_PUSH_ATTEMPTS {
+            log.record(attempt("http://staging", None));
+        }
+
+        let prod = &log.endpoints()["http://prod"];
+        assert_eq!(
+            (prod.delivered, prod.failed, prod.consecutive_failures),
+            (1, 2, 2)
+        );
+        assert_eq!(
+            prod.last_error.as_deref(),
+            Some("Monitoring API returned 503")
+        );
+        assert_eq!(log.endpoints()["http://staging"].delivered, 101);
+        assert_eq!(log.recent().count(), MAX_PUSH_ATTEMPTS);
+        assert!(
+            log.recent()
+                .all(|attempt| attempt.endpoint == "http://staging")
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::processing::gaps::GapDetection;
+use crate::protocol::{DeviceEvent, ProcessedMeasurement, SeriesMapping, ShutterState};
+use crate::service::autogain::GainReason;
+use crate::service::chamber::ShutterSource;
+use crate::service::registration::RegistrationLoss;
+use crate::service::supervisor::SourceState;
+use crate::service::termination::TerminationTarget;
+
+/// Default number of events buffered per subscriber before it starts lagging;
+/// room for the serial log lines of a few dozen cycles
+pub const DEFAULT_EVENT_CAPACITY: usize = 1024;
+
+/// Events published by the data loop and API handlers
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServiceEvent {
+    /// A cycle was processed into a measurement
+    Measurement(Box<ProcessedMeasurement>),
+    /// A closed-shutter cycle was folded into the rolling dark estimate
+    DarkCycle {
+        cycle_id: u64,
+        timestamp: DateTime<Utc>,
+        dark_level: f64,
+        dark_estimate: Option<f64>,
+    },
+    /// Line read from the data source, or `> ` and a command sent to it
+    Log {
+        line: String,
+    },
+    /// Gain, FADC, COUNT and series mapping were changed from the UI
+    SettingsUpdated {
+        gain: u8,
+        fadc: f32,
+        count: u8,
+        series_mapping: SeriesMapping,
+    },
+    /// A processed measurement failed validation
+    ValidationFailed {
+        timestamp: DateTime<Utc>,
+        error: String,
+    },
+    /// The chamber material was changed
+    MaterialChanged {
+        previous: String,
+        material: String,
+        at: DateTime<Utc>,
+    },
+    /// The control wavelength was changed, by `POST /control_wavelength`
+    /// or by the preset of the selected `material`
+    ControlWavelengthChanged {
+        at: DateTime<Utc>,
+        previous: f64,
+        wavelength: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        material: Option<String>,
+    },
+    DepositionStarted {
+        at: DateTime<Utc>,
+    },
+    DepositionStopped {
+        at: DateTime<Utc>,
+    },
+    /// Deposition was paused, e.g. for a shutter interruption
+    DepositionPaused {
+        at: DateTime<Utc>,
+    },
+    /// A paused deposition continued in the same run
+    DepositionResumed {
+        at: DateTime<Utc>,
+    },
+    /// The shutter was commanded or reported in a new position
+    ShutterChanged {
+        at: DateTime<Utc>,
+        state: ShutterState,
+        source: ShutterSource,
+    },
+    /// The open layer reached its `[termination]` target
+    TargetReached {
+        at: DateTime<Utc>,
+        layer: u32,
+        target: TerminationTarget,
+        /// Calibrated reading of the measurement that reached it
+        reading: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        turning_points: Option<u32>,
+        /// Whether deposition was stopped
+        stopping: bool,
+    },
+    /// The run was aborted into the chamber's `error` state
+    ChamberFault {
+        at: DateTime<Utc>,
+        reason: String,
+    },
+    /// Processing was paused for maintenance; measurements are not pushed
+    /// until it resumes
+    ProcessingPaused {
+        at: DateTime<Utc>,
+    },
+    ProcessingResumed {
+        at: DateTime<Utc>,
+    },
+    /// Cycles were lost between the device and the service
+    CyclesMissed {
+        at: DateTime<Utc>,
+        missed: u64,
+        detection: GapDetection,
+    },
+    /// A partial cycle was dropped because `END_CYCLE` never arrived
+    PartialCycleDiscarded {
+        at: DateTime<Utc>,
+        source: String,
+        detail: String,
+    },
+    /// The firmware reported an error, a missing cycle or a ready ADC
+    DeviceEvent(DeviceEvent),
+    /// The data source started, ended or is waiting to be restarted
+    SourceStateChanged {
+        at: DateTime<Utc>,
+        source: String,
+        state: SourceState,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+    },
+    /// A measurement could not be written to a sink, or the monitoring sink
+    /// failed to deliver it and buffered it for replay
+    PushFailed {
+        at: DateTime<Utc>,
+        sink: String,
+        /// Time of the measurement
+        timestamp: DateTime<Utc>,
+        error: String,
+    },
+    /// The monitoring registration was cleared; pushes stop until the next
+    /// `/register`
+    RegistrationLost {
+        at: DateTime<Utc>,
+        monitoring_api_url: String,
+        reason: RegistrationLoss,
+    },
+    /// No cycle was processed for `stalled_secs` while the data source
+    /// claimed to be running
+    ProcessingStalled {
+        at: DateTime<Utc>,
+        source: String,
+        stalled_secs: u64,
+        /// Whether the watchdog restarts the data source
+        restarting: bool,
+    },
+    /// The full-dark span called for another gain for `auto_gain.hold_cycles`
+    /// measurements
+    GainRecommended {
+        at: DateTime<Utc>,
+        gain: u8,
+        recommended: u8,
+        reason: GainReason,
+        /// Whether the gain was sent to the device
+        applied: bool,
+    },
+    /// Playback reached the end of its input and is not restarted
+    PlaybackFinished {
+        at: DateTime<Utc>,
+        source: String,
+        /// Cycles delivered since the source was last started
+        cycles: u64,
+    },
+}
+
+/// Internal publish/subscribe bus for service events
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<ServiceEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    /// Publish an event. Events with no subscribers are dropped.
+    pub fn publish(&self, event: ServiceEvent) {
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ServiceEvent> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_subscribe() {
+        let bus = EventBus::new(8);
+        let mut rx = bus.subscribe();
+
+        bus.publish(ServiceEvent::DepositionStarted { at: Utc::now() });
+
+        let event = rx.recv().await.unwrap();
+        assert!(matches!(event, ServiceEvent::DepositionStarted { .. }));
+    }
+
+    #[test]
+    fn test_publish_without_subscribers() {
+        let bus = EventBus::new(8);
+        bus.publish(ServiceEvent::DepositionStopped { at: Utc::now() });
+    }
+
+    #[test]
+    fn test_event_serialization_tagged() {
+        let event = ServiceEvent::MaterialChanged {
+            previous: "H".to_string(),
+            material: "L".to_string(),
+            at: Utc::now(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "material_changed");
+        assert_eq!(json["material"], "L");
+
+        let measurement = ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 550.0, 50.0);
+        let json = serde_json::to_value(ServiceEvent::Measurement(Box::new(measurement))).unwrap();
+        assert_eq!(json["type"], "measurement");
+        assert_eq!(json["calibrated_reading"], 50.0);
+
+        // Frames the web UI reads
+        let json = serde_json::to_value(ServiceEvent::Log {
+            line: "GAIN=2".to_string(),
+        })
+        .unwrap();
+        assert_eq!(json, serde_json::json!({"type": "log", "line": "GAIN=2"}));
+        let json = serde_json::to_value(ServiceEvent::SettingsUpdated {
+            gain: 2,
+            fadc: 250.0,
+            count: 4,
+            series_mapping: SeriesMapping::default(),
+        })
+        .unwrap();
+        assert_eq!(json["type"], "settings_updated");
+        assert_eq!(json["series_mapping"]["sample"], 3);
+    }
+}
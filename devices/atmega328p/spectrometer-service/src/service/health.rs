@@ -0,0 +1,216 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+/// Default age of the last cycle after which data is considered stale
+pub const DEFAULT_STALE_AFTER_SECS: u64 = 10;
+
+/// Outcome of the most recent push to the monitoring API
+#[derive(Debug, Clone, Serialize)]
+pub struct PushOutcome {
+    pub at: DateTime<Utc>,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Liveness inputs updated by the data loop and monitoring sink
+#[derive(Debug, Clone)]
+pub struct HealthState {
+    pub started_at: DateTime<Utc>,
+    /// Whether the data source is still delivering cycles
+    pub source_active: bool,
+    /// When the data source last started running
+    pub source_started_at: Option<DateTime<Utc>>,
+    /// Set when playback reached the end of its input and was not restarted
+    pub playback_finished_at: Option<DateTime<Utc>>,
+    pub last_cycle_at: Option<DateTime<Utc>>,
+    pub last_push: Option<PushOutcome>,
+    /// Monitoring pushes failed since the last successful one
+    pub push_failures: u32,
+    /// Cycles that passed and failed validation since startup
+    pub valid_cycles: u64,
+    pub invalid_cycles: u64,
+    pub stale_after: Duration,
+}
+
+impl Default for HealthState {
+    fn default() -> Self {
+        Self {
+            started_at: Utc::now(),
+            source_active: false,
+            source_started_at: None,
+            playback_finished_at: None,
+            last_cycle_at: None,
+            last_push: None,
+            push_failures: 0,
+            valid_cycles: 0,
+            invalid_cycles: 0,
+            stale_after: Duration::seconds(DEFAULT_STALE_AFTER_SECS as i64),
+        }
+    }
+}
+
+/// Evaluated health at a point in time
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    /// Data source active and data fresh
+    pub healthy: bool,
+    /// Healthy and the last monitoring push (if any) succeeded
+    pub ready: bool,
+    pub source_active: bool,
+    /// When playback reached the end of its input, once it has
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playback_finished_at: Option<DateTime<Utc>>,
+    pub last_cycle_at: Option<DateTime<Utc>>,
+    /// Age of the last cycle, or time since startup if none arrived yet
+    pub data_age_secs: f64,
+    pub stale_after_secs: f64,
+    pub last_push: Option<PushOutcome>,
+    /// Human-readable reasons for not being healthy/ready
+    pub problems: Vec<String>,
+}
+
+impl HealthState {
+    pub fn record_cycle(&mut self, at: DateTime<Utc>) {
+        self.source_active = true;
+        self.last_cycle_at = Some(at);
+    }
+
+    pub fn record_validity(&mut self, valid: bool) {
+        if valid {
+            self.valid_cycles += 1;
+        } else {
+            self.invalid_cycles += 1;
+        }
+    }
+
+    pub fn record_push(&mut self, result: Result<(), String>) {
+        self.push_failures = if result.is_ok() {
+            0
+        } else {
+            self.push_failures + 1
+        };
+        self.last_push = Some(PushOutcome {
+            at: Utc::now(),
+            success: result.is_ok(),
+            error: result.err(),
+        });
+    }
+
+    pub fn evaluate(&self, now: DateTime<Utc>) -> HealthReport {
+        let reference = self.last_cycle_at.unwrap_or(self.started_at);
+        let data_age = now - reference;
+        let mut problems = Vec::new();
+
+        if let Some(at) = self.playback_finished_at {
+            problems.push(format!("playback finished at {}", at.to_rfc3339()));
+        } else if !self.source_active {
+            problems.push("data source is not active".to_string());
+        }
+        if data_age > self.stale_after {
+            problems.push(match self.last_cycle_at {
+                Some(_) => format!(
+                    "last cycle is {}s old (limit {}s)",
+                    data_age.num_seconds(),
+                    self.stale_after.num_seconds()
+                ),
+                None => "no cycle received since startup".to_string(),
+            });
+        }
+        let healthy = problems.is_empty();
+
+        let push_ok = self.last_push.as_ref().is_none_or(|p| p.success);
+        if !push_ok {
+            problems.push("last push to monitoring failed".to_string());
+        }
+
+        HealthReport {
+            healthy,
+            ready: healthy && push_ok,
+            source_active: self.source_active,
+            playback_finished_at: self.playback_finished_at,
+            last_cycle_at: self.last_cycle_at,
+            data_age_secs: data_age.num_milliseconds() as f64 / 1000.0,
+            stale_after_secs: self.stale_after.num_milliseconds() as f64 / 1000.0,
+            last_push: self.last_push.clone(),
+            problems,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresh_cycle_is_healthy() {
+        let mut health = HealthState::default();
+        let now = Utc::now();
+        health.record_cycle(now);
+
+        let report = health.evaluate(now + Duration::seconds(1));
+        assert!(report.healthy);
+        assert!(report.ready);
+        assert!(report.problems.is_empty());
+    }
+
+    #[test]
+    fn test_stale_data_unhealthy() {
+        let mut health = HealthState::default();
+        let now = Utc::now();
+        health.record_cycle(now);
+
+        let report = health.evaluate(now + Duration::seconds(30));
+        assert!(!report.healthy);
+        assert!(!report.ready);
+        assert!(report.problems[0].contains("30s old"));
+    }
+
+    #[test]
+    fn test_no_cycle_since_startup() {
+        let health = HealthState::default();
+
+        let report = health.evaluate(health.started_at + Duration::seconds(1));
+        assert!(!report.healthy);
+        assert_eq!(report.problems, vec!["data source is not active"]);
+
+        let report = health.evaluate(health.started_at + Duration::seconds(60));
+        assert!(
+            report
+                .problems
+                .contains(&"no cycle received since startup".to_string())
+        );
+    }
+
+    #[test]
+    fn test_finished_playback_reported() {
+        let mut health = HealthState::default();
+        let now = Utc::now();
+        health.record_cycle(now);
+        health.source_active = false;
+        health.playback_finished_at = Some(now);
+
+        let report = health.evaluate(now);
+        assert!(!report.healthy);
+        assert_eq!(report.playback_finished_at, Some(now));
+        assert!(report.problems[0].starts_with("playback finished at"));
+    }
+
+    #[test]
+    fn test_failed_push_not_ready() {
+        let mut health = HealthState::default();
+        let now = Utc::now();
+        health.record_cycle(now);
+        health.record_push(Err("connection refused".to_string()));
+
+        let report = health.evaluate(now);
+        assert!(report.healthy);
+        assert!(!report.ready);
+        health.record_push(Err("connection refused".to_string()));
+        assert_eq!(health.push_failures, 2);
+
+        health.record_push(Ok(()));
+        assert!(health.evaluate(now).ready);
+        assert_eq!(health.push_failures, 0);
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::monitoring::{Heartbeat, MonitoringClient};
+use crate::service::calibration::SharedConfig;
+use crate::service::state::{DeviceState, SharedState};
+
+/// How often a disabled heartbeat checks whether it was enabled
+const DISABLED_RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Heartbeat describing the device as of now
+pub fn heartbeat(device: &DeviceState) -> Heartbeat {
+    let now = Utc::now();
+    Heartbeat {
+        timestamp: now,
+        healthy: device.health.evaluate(now).healthy,
+        chamber_state: device.chamber.state().to_string(),
+        control_wavelength: device.control_wavelength,
+        last_cycle_at: device.health.last_cycle_at,
+        valid_cycles: device.health.valid_cycles,
+        invalid_cycles: device.health.invalid_cycles,
+    }
+}
+
+/// Post a heartbeat every `monitoring.heartbeat_interval_secs` while the
+/// device is registered. A failing endpoint is logged once per outage.
+pub async fn run_heartbeat(state: SharedState, config: SharedConfig) {
+    let mut failing = false;
+    loop {
+        let settings = config.read().await.config.monitoring.clone();
+        if settings.heartbeat_interval_secs == 0 {
+            tokio::time::sleep(DISABLED_RECHECK_INTERVAL).await;
+            continue;
+        }
+        tokio::time::sleep(Duration::from_secs(settings.heartbeat_interval_secs)).await;
+
+        let (target, heartbeat) = {
+            let device = state.read().await;
+            let target = device
+                .monitoring_api_url
+                .clone()
+                .zip(device.spectrometer_id.clone());
+            (target, heartbeat(&device))
+        };
+        let Some((api_url, spectrometer_id)) = target else {
+            continue;
+        };
+
+        let result = match MonitoringClient::build(&settings.tls, &settings.http) {
+            Ok(client) => {
+                client
+                    .with_auth(settings.auth)
+                    .post_heartbeat(&api_url, &spectrometer_id, &heartbeat)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) if failing => {
+                tracing::info!("Heartbeats to {api_url} delivered again");
+                failing = false;
+            }
+            Ok(()) => tracing::debug!("Posted heartbeat to {api_url}"),
+            Err(e) if !failing => {
+                tracing::warn!("Heartbeat to {api_url} failed: {e}");
+                failing = true;
+            }
+            Err(e) => tracing::debug!("Heartbeat to {api_url} failed: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::Router;
+    use axum::routing::post;
+
+    use super::*;
+    use crate::service::calibration::create_shared_config;
+    use crate::service::chamber::ChamberState;
+    use crate::service::state::create_shared_state;
+
+    #[test]
+    fn test_heartbeat_reports_status() {
+        let mut device = DeviceState::default();
+        let beat = heartbeat(&device);
+        assert!(!beat.healthy);
+        assert_eq!(beat.chamber_state, "idle");
+        assert_eq!(beat.control_wavelength, 550.0);
+        assert!(beat.last_cycle_at.is_none());
+
+        device.health.record_cycle(Utc::now());
+        device.health.record_validity(true);
+        device.health.record_validity(false);
+        device.health.record_validity(true);
+        device
+            .chamber
+            .transition_to(ChamberState::Preconditioning)
+            .unwrap();
+        let beat = heartbeat(&device);
+        assert!(beat.healthy);
+        assert_eq!(beat.chamber_state, "preconditioning");
+        assert_eq!((beat.valid_cycles, beat.invalid_cycles), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_posted_when_registered() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorded = received.clone();
+        let app = Router::new().route(
+            "/spectrometers/{id}/heartbeat",
+            post(move |body: String| async move {
+                recorded.lock().unwrap().push(body);
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = create_shared_config(dir.path().join("cfg.toml"));
+        config
+            .write()
+            .await
+            .config
+            .monitoring
+            .heartbeat_interval_secs = 1;
+        let state = create_shared_state();
+        {
+            let mut device = state.write().await;
+            device.monitoring_api_url = Some(api_url);
+            device.spectrometer_id = Some("spec-1".to_string());
+        }
+        let task = tokio::spawn(run_heartbeat(state, config));
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while received.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("no heartbeat posted");
+        task.abort();
+
+        let body: serde_json::Value = serde_json::from_str(&received.lock().unwrap()[0]).unwrap();
+        assert_eq!(body["chamber_state"], "idle");
+        assert_eq!(body["valid_cycles"], 0);
+    }
+}
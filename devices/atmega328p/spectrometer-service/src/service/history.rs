@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use crate::processing::calibration::{mean, std_dev};
+use crate::protocol::ProcessedMeasurement;
+
+/// Default number of processed measurements kept in memory
+pub const DEFAULT_HISTORY_CAPACITY: usize = 36_000;
+
+/// Bounded in-memory buffer of recent processed measurements (oldest first)
+#[derive(Debug)]
+pub struct MeasurementHistory {
+    entries: VecDeque<ProcessedMeasurement>,
+    capacity: usize,
+}
+
+impl MeasurementHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity.min(1024)),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Append a measurement, evicting the oldest one when full. Filtered
+    /// series values are dropped to keep the buffer small.
+    pub fn push(&mut self, mut measurement: ProcessedMeasurement) {
+        measurement.series = None;
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(measurement);
+    }
+
+    /// Measurements with `from <= timestamp <= to`, in chronological order
+    pub fn range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<ProcessedMeasurement> {
+        self.entries
+            .iter()
+            .filter(|m| m.timestamp >= from && m.timestamp <= to)
+            .cloned()
+            .collect()
+    }
+
+    /// The `n` most recent measurements, in chronological order
+    pub fn latest(&self, n: usize) -> Vec<ProcessedMeasurement> {
+        let skip = self.entries.len().saturating_sub(n);
+        self.entries.iter().skip(skip).cloned().collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for MeasurementHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+pub type SharedHistory = Arc<RwLock<MeasurementHistory>>;
+
+pub fn create_shared_history(capacity: usize) -> SharedHistory {
+    Arc::new(RwLock::new(MeasurementHistory::new(capacity)))
+}
+
+/// Summary of the calibrated readings of a set of measurements. The
+/// statistics cover valid measurements only and are absent without any.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReadingStats {
+    pub count: usize,
+    pub valid_count: usize,
+    /// Share of the measurements that failed validation; 0 without any
+    pub invalid_fraction: f64,
+    pub mean: Option<f64>,
+    /// Sample standard deviation (0 for a single reading)
+    pub std: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Least-squares trend in percent per second; needs readings at two
+    /// different instants
+    pub slope: Option<f64>,
+}
+
+impl ReadingStats {
+    pub fn of(measurements: &[ProcessedMeasurement]) -> Self {
+        let valid: Vec<&ProcessedMeasurement> =
+            measurements.iter().filter(|m| m.is_valid).collect();
+        let readings: Vec<f64> = valid.iter().map(|m| m.calibrated_reading).collect();
+        let present = !readings.is_empty();
+
+        Self {
+            count: measurements.len(),
+            valid_count: valid.len(),
+            invalid_fraction: if measurements.is_empty() {
+                0.0
+            } else {
+                (measurements.len() - valid.len()) as f64 / measurements.len() as f64
+            },
+            mean: present.then(|| mean(&readings)),
+            std: present.then(|| std_dev(&readings)),
+            min: readings.iter().copied().reduce(f64::min),
+            max: readings.iter().copied().reduce(f64::max),
+            slope: slope(&valid),
+        }
+    }
+}
+
+/// Least-squares slope of reading over time in seconds
+fn slope(measurements: &[&ProcessedMeasurement]) -> Option<f64> {
+    let t0 = measurements.first()?.timestamp;
+    let points: Vec<(f64, f64)> = measurements
+        .iter()
+        .map(|m| {
+            let elapsed = (m.timestamp - t0).num_microseconds().unwrap_or(i64::MAX);
+            (elapsed as f64 / 1e6, m.calibrated_reading)
+        })
+        .collect();
+    let n = points.len() as f64;
+    let mean_t = points.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_r = points.iter().map(|(_, r)| r).sum::<f64>() / n;
+    let covariance: f64 = points
+        .iter()
+        .map(|(t, r)| (t - mean_t) * (r - mean_r))
+        .sum();
+    let variance: f64 = points.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn measurement_at(timestamp: DateTime<Utc>) -> ProcessedMeasurement {
+        ProcessedMeasurement::new(timestamp, 100.0, 1000.0, 550.0, 50.0)
+    }
+
+    #[test]
+    fn test_history_evicts_oldest() {
+        let mut history = MeasurementHistory::new(2);
+        let t0 = Utc::now();
+        history.push(measurement_at(t0));
+        history.push(measurement_at(t0 + Duration::seconds(1)));
+        history.push(measurement_at(t0 + Duration::seconds(2)));
+
+        assert_eq!(history.len(), 2);
+        let all = history.range(t0, t0 + Duration::seconds(10));
+        assert_eq!(all[0].timestamp, t0 + Duration::seconds(1));
+    }
+
+    #[test]
+    fn test_history_range_inclusive() {
+        let mut history = MeasurementHistory::new(10);
+        let t0 = Utc::now();
+        for i in 0..5 {
+            history.push(measurement_at(t0 + Duration::seconds(i)));
+        }
+
+        let selected = history.range(t0 + Duration::seconds(1), t0 + Duration::seconds(3));
+        assert_eq!(selected.len(), 3);
+        assert_eq!(selected[0].timestamp, t0 + Duration::seconds(1));
+        assert_eq!(selected[2].timestamp, t0 + Duration::seconds(3));
+    }
+
+    #[test]
+    fn test_history_latest() {
+        let mut history = MeasurementHistory::new(10);
+        let t0 = Utc::now();
+        for i in 0..5 {
+            history.push(measurement_at(t0 + Duration::seconds(i)));
+        }
+
+        let latest = history.latest(2);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].timestamp, t0 + Duration::seconds(3));
+        assert_eq!(history.latest(10).len(), 5);
+    }
+
+    #[test]
+    fn test_reading_stats() {
+        let t0 = Utc::now();
+        let mut measurements: Vec<ProcessedMeasurement> = [10.0, 12.0, 14.0, 16.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, reading)| {
+                ProcessedMeasurement::new(
+                    t0 + Duration::seconds(2 * i as i64),
+                    100.0,
+                    1000.0,
+                    550.0,
+                    reading,
+                )
+            })
+            .collect();
+        let mut invalid = measurement_at(t0 + Duration::seconds(1));
+        invalid.is_valid = false;
+        measurements.push(invalid);
+
+        let stats = ReadingStats::of(&measurements);
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.valid_count, 4);
+        assert_eq!(stats.invalid_fraction, 0.2);
+        assert_eq!(stats.mean, Some(13.0));
+        assert_eq!(stats.min, Some(10.0));
+        assert_eq!(stats.max, Some(16.0));
+        assert_eq!(stats.slope, Some(1.0));
+        assert!((stats.std.unwrap() - 2.581_988_897).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_reading_stats_empty_and_single() {
+        let stats = ReadingStats::of(&[]);
+        assert_eq!((stats.count, stats.invalid_fraction), (0, 0.0));
+        assert_eq!(stats.mean, None);
+
+        let stats = ReadingStats::of(&[measurement_at(Utc::now())]);
+        assert_eq!(stats.mean, Some(50.0));
+        assert_eq!(stats.std, Some(0.0));
+        assert_eq!(stats.slope, None);
+    }
+
+    #[test]
+    fn test_history_zero_capacity_clamped() {
+        let mut history = MeasurementHistory::new(0);
+        history.push(measurement_at(Utc::now()));
+        assert_eq!(history.len(), 1);
+    }
+}
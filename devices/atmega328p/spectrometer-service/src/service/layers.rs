@@ -0,0 +1,137 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::protocol::ProcessedMeasurement;
+
+/// One deposited layer of the current run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LayerRecord {
+    /// 1-based layer number within the run
+    pub index: u32,
+    pub material: String,
+    pub started_at: DateTime<Utc>,
+    /// `None` while the layer is still being deposited
+    pub ended_at: Option<DateTime<Utc>>,
+    pub measurements: u64,
+    pub invalid_measurements: u64,
+}
+
+/// Layer boundaries of the current (or last) deposition run
+#[derive(Debug, Clone, Default)]
+pub struct LayerLog {
+    records: Vec<LayerRecord>,
+}
+
+impl LayerLog {
+    /// Forget the previous run and open layer 1
+    pub fn start_run(&mut self, material: &str, at: DateTime<Utc>) {
+        self.records.clear();
+        self.open(material, at);
+    }
+
+    /// Close the current layer and open the next one with `material`
+    pub fn next_layer(&mut self, material: &str, at: DateTime<Utc>) {
+        self.close(at);
+        self.open(material, at);
+    }
+
+    /// Close the current layer, if open
+    pub fn close(&mut self, at: DateTime<Utc>) {
+        if let Some(layer) = self.records.last_mut()
+            && layer.ended_at.is_none()
+        {
+            layer.ended_at = Some(at);
+        }
+    }
+
+    /// Count a measurement towards the open layer
+    pub fn record_measurement(&mut self, measurement: &ProcessedMeasurement) {
+        if let Some(layer) = self.records.last_mut()
+            && layer.ended_at.is_none()
+        {
+            layer.measurements += 1;
+            if !measurement.is_valid {
+                layer.invalid_measurements += 1;
+            }
+        }
+    }
+
+    /// Number of the current layer; 0 before the first run
+    pub fn current_index(&self) -> u32 {
+        self.records.last().map_or(0, |layer| layer.index)
+    }
+
+    /// All layers of the run, oldest first
+    pub fn records(&self) -> &[LayerRecord] {
+        &self.records
+    }
+
+    fn open(&mut self, material: &str, at: DateTime<Utc>) {
+        self.records.push(LayerRecord {
+            index: self.current_index() + 1,
+            material: material.to_string(),
+            started_at: at,
+            ended_at: None,
+            measurements: 0,
+            invalid_measurements: 0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_layer_boundaries() {
+        let t0 = Utc::now();
+        let mut log = LayerLog::default();
+        assert_eq!(log.current_index(), 0);
+
+        log.start_run("H", t0);
+        log.next_layer("L", t0 + Duration::seconds(10));
+        log.close(t0 + Duration::seconds(25));
+
+        let records = log.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].material, "H");
+        assert_eq!(records[0].ended_at, Some(t0 + Duration::seconds(10)));
+        assert_eq!(records[1].index, 2);
+        assert_eq!(records[1].started_at, t0 + Duration::seconds(10));
+        assert_eq!(records[1].ended_at, Some(t0 + Duration::seconds(25)));
+        assert_eq!(log.current_index(), 2);
+    }
+
+    #[test]
+    fn test_measurements_counted_on_open_layer() {
+        let t0 = Utc::now();
+        let measurement = ProcessedMeasurement::new(t0, 100.0, 1000.0, 550.0, 50.0);
+        let mut log = LayerLog::default();
+
+        // No layer open yet
+        log.record_measurement(&measurement);
+
+        log.start_run("H", t0);
+        log.record_measurement(&measurement);
+        log.record_measurement(&measurement.clone().with_error("bad".to_string()));
+        log.close(t0);
+        log.record_measurement(&measurement);
+
+        assert_eq!(log.records()[0].measurements, 2);
+        assert_eq!(log.records()[0].invalid_measurements, 1);
+    }
+
+    #[test]
+    fn test_start_run_resets() {
+        let t0 = Utc::now();
+        let mut log = LayerLog::default();
+        log.start_run("H", t0);
+        log.next_layer("L", t0);
+
+        log.start_run("L", t0);
+        assert_eq!(log.records().len(), 1);
+        assert_eq!(log.current_index(), 1);
+    }
+}
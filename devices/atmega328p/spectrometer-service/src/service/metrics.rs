@@ -0,0 +1,756 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::processing::gaps::CycleGap;
+use crate::protocol::ProcessedMeasurement;
+
+/// Number of (material, layer) label sets kept on per-layer counters.
+/// Older layers are dropped so a long run does not grow the series count.
+pub const MAX_LAYER_SERIES: usize = 8;
+
+pub const MEASUREMENTS_TOTAL: &str = "spectrometer_measurements_total";
+pub const INVALID_MEASUREMENTS_TOTAL: &str = "spectrometer_invalid_measurements_total";
+pub const LAYER: &str = "spectrometer_layer";
+pub const READING: &str = "spectrometer_calibrated_reading";
+pub const READING_RATE: &str = "spectrometer_reading_rate";
+pub const LAYER_MEASUREMENTS_TOTAL: &str = "spectrometer_layer_measurements_total";
+pub const LAYER_INVALID_MEASUREMENTS_TOTAL: &str = "spectrometer_layer_invalid_measurements_total";
+pub const VALIDATION_ERRORS_TOTAL: &str = "spectrometer_validation_errors_total";
+pub const MISSED_CYCLES_TOTAL: &str = "spectrometer_missed_cycles_total";
+pub const OUTLIER_VALUES_TOTAL: &str = "spectrometer_outlier_check_values_total";
+pub const OUTLIERS_EXCLUDED_TOTAL: &str = "spectrometer_outliers_excluded_total";
+pub const SNR: &str = "spectrometer_snr";
+pub const LOW_SNR_TOTAL: &str = "spectrometer_low_snr_total";
+pub const DARK_CYCLES_TOTAL: &str = "spectrometer_dark_cycles_total";
+pub const PROCESSING_PAUSED: &str = "spectrometer_processing_paused";
+pub const HTTP_REQUESTS_TOTAL: &str = "spectrometer_http_requests_total";
+pub const HTTP_REQUEST_DURATION: &str = "spectrometer_http_request_duration_seconds";
+pub const MONITORING_PUSHES_TOTAL: &str = "spectrometer_monitoring_pushes_total";
+
+/// Upper bounds (s) of the HTTP latency histogram buckets
+pub const HTTP_LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+/// Label pairs in the order they are rendered
+type Labels = Vec<(String, String)>;
+
+/// Observations of one histogram series
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations at or below each bucket bound (not cumulative)
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Debug)]
+struct Family {
+    kind: MetricKind,
+    help: &'static str,
+    series: BTreeMap<Labels, f64>,
+    /// Bucket bounds, for histograms
+    bounds: &'static [f64],
+    histograms: BTreeMap<Labels, Histogram>,
+}
+
+/// Minimal registry rendering the Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    families: Mutex<BTreeMap<&'static str, Family>>,
+}
+
+fn to_labels(labels: &[(&str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write one sample line, with `extra` (e.g. `le`) after the series labels
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &Labels,
+    extra: Option<(&str, &str)>,
+    value: f64,
+) {
+    let labels = labels
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .chain(extra)
+        .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+    }
+}
+
+impl MetricsRegistry {
+    /// Register a metric family. Series can only be recorded for described families.
+    pub fn describe(&self, name: &'static str, kind: MetricKind, help: &'static str) {
+        self.families.lock().unwrap().entry(name).or_insert(Family {
+            kind,
+            help,
+            series: BTreeMap::new(),
+            bounds: &[],
+            histograms: BTreeMap::new(),
+        });
+    }
+
+    /// Register a histogram family with the given ascending bucket bounds
+    pub fn describe_histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        bounds: &'static [f64],
+    ) {
+        self.families.lock().unwrap().entry(name).or_insert(Family {
+            kind: MetricKind::Histogram,
+            help,
+            series: BTreeMap::new(),
+            bounds,
+            histograms: BTreeMap::new(),
+        });
+    }
+
+    /// Add an observation to a histogram series
+    pub fn observe(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut families = self.families.lock().unwrap();
+        let Some(family) = families.get_mut(name) else {
+            return;
+        };
+        let bounds = family.bounds;
+        let histogram = family
+            .histograms
+            .entry(to_labels(labels))
+            .or_insert_with(|| Histogram {
+                buckets: vec![0; bounds.len()],
+                ..Histogram::default()
+            });
+        if let Some(bucket) = bounds.iter().position(|&bound| value <= bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += value;
+    }
+
+    /// Add `by` to a counter series
+    pub fn inc(&self, name: &str, labels: &[(&str, &str)], by: f64) {
+        let mut families = self.families.lock().unwrap();
+        if let Some(family) = families.get_mut(name) {
+            *family.series.entry(to_labels(labels)).or_insert(0.0) += by;
+        }
+    }
+
+    /// Set a gauge series
+    pub fn set(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut families = self.families.lock().unwrap();
+        if let Some(family) = families.get_mut(name) {
+            family.series.insert(to_labels(labels), value);
+        }
+    }
+
+    /// Drop a single series
+    pub fn remove(&self, name: &str, labels: &[(&str, &str)]) {
+        let mut families = self.families.lock().unwrap();
+        if let Some(family) = families.get_mut(name) {
+            family.series.remove(&to_labels(labels));
+        }
+    }
+
+    /// Current value of a series, if recorded
+    #[allow(dead_code)]
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let families = self.families.lock().unwrap();
+        families.get(name)?.series.get(&to_labels(labels)).copied()
+    }
+
+    /// Render all families in the Prometheus text format
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# HELP {} {}", name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", name, family.kind.as_str());
+
+            for (labels, value) in &family.series {
+                write_sample(&mut out, name, labels, None, *value);
+            }
+            for (labels, histogram) in &family.histograms {
+                let bucket_name = format!("{name}_bucket");
+                let mut cumulative = 0;
+                for (bound, count) in family.bounds.iter().zip(&histogram.buckets) {
+                    cumulative += count;
+                    let le = bound.to_string();
+                    write_sample(
+                        &mut out,
+                        &bucket_name,
+                        labels,
+                        Some(("le", &le)),
+                        cumulative as f64,
+                    );
+                }
+                write_sample(
+                    &mut out,
+                    &bucket_name,
+                    labels,
+                    Some(("le", "+Inf")),
+                    histogram.count as f64,
+                );
+                write_sample(
+                    &mut out,
+                    &format!("{name}_sum"),
+                    labels,
+                    None,
+                    histogram.sum,
+                );
+                write_sample(
+                    &mut out,
+                    &format!("{name}_count"),
+                    labels,
+                    None,
+                    histogram.count as f64,
+                );
+            }
+        }
+
+        out
+    }
+}
+
+/// Tracks which (material, layer) label sets are live
+#[derive(Debug, Default)]
+struct LayerLabels {
+    /// Label sets with per-layer counters, oldest first; the last one is current
+    recent: VecDeque<(String, String)>,
+    /// Previous valid reading in the current layer, for the rate gauge
+    last_reading: Option<(DateTime<Utc>, f64)>,
+}
+
+/// Service metrics exported on `GET /metrics`
+#[derive(Debug)]
+pub struct Metrics {
+    registry: MetricsRegistry,
+    layers: Mutex<LayerLabels>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = MetricsRegistry::default();
+        registry.describe(
+            MEASUREMENTS_TOTAL,
+            MetricKind::Counter,
+            "Processed measurement cycles",
+        );
+        registry.describe(
+            INVALID_MEASUREMENTS_TOTAL,
+            MetricKind::Counter,
+            "Processed measurement cycles that failed validation",
+        );
+        registry.describe(LAYER, MetricKind::Gauge, "Current deposition layer number");
+        registry.describe(
+            READING,
+            MetricKind::Gauge,
+            "Latest calibrated reading (%) for the current material and layer",
+        );
+        registry.describe(
+            READING_RATE,
+            MetricKind::Gauge,
+            "Change of the calibrated reading in %/s within the current layer",
+        );
+        registry.describe(
+            LAYER_MEASUREMENTS_TOTAL,
+            MetricKind::Counter,
+            "Processed measurement cycles per material and layer (recent layers only)",
+        );
+        registry.describe(
+            LAYER_INVALID_MEASUREMENTS_TOTAL,
+            MetricKind::Counter,
+            "Invalid measurement cycles per material and layer (recent layers only)",
+        );
+        registry.describe(
+            VALIDATION_ERRORS_TOTAL,
+            MetricKind::Counter,
+            "Invalid measurement cycles by category (relationship, saturation, under_range, insufficient_samples, no_reference)",
+        );
+        registry.describe(
+            MISSED_CYCLES_TOTAL,
+            MetricKind::Counter,
+            "Cycles lost between device and service, by detection (sequence, timing)",
+        );
+        registry.describe(
+            OUTLIER_VALUES_TOTAL,
+            MetricKind::Counter,
+            "Raw values checked for outliers, by series",
+        );
+        registry.describe(
+            OUTLIERS_EXCLUDED_TOTAL,
+            MetricKind::Counter,
+            "Raw values excluded as outliers, by series",
+        );
+        registry.describe(
+            SNR,
+            MetricKind::Gauge,
+            "Signal-to-noise ratio of the latest cycle: full-dark span over its noise",
+        );
+        registry.describe(
+            LOW_SNR_TOTAL,
+            MetricKind::Counter,
+            "Measurement cycles flagged for a signal-to-noise ratio below validation.min_snr",
+        );
+        registry.describe(
+            DARK_CYCLES_TOTAL,
+            MetricKind::Counter,
+            "Closed-shutter cycles folded into the rolling dark estimate",
+        );
+        registry.describe(
+            PROCESSING_PAUSED,
+            MetricKind::Gauge,
+            "1 while processing is paused through POST /processing/pause, else 0",
+        );
+        registry.set(PROCESSING_PAUSED, &[], 0.0);
+        registry.describe(
+            HTTP_REQUESTS_TOTAL,
+            MetricKind::Counter,
+            "HTTP requests handled, by method, route and status",
+        );
+        registry.describe_histogram(
+            HTTP_REQUEST_DURATION,
+            "Time to handle an HTTP request in seconds, by method and route",
+            HTTP_LATENCY_BUCKETS,
+        );
+        registry.describe(
+            MONITORING_PUSHES_TOTAL,
+            MetricKind::Counter,
+            "Monitoring push attempts, by endpoint URL and result (delivered, failed)",
+        );
+
+        Self {
+            registry,
+            layers: Mutex::new(LayerLabels::default()),
+        }
+    }
+
+    /// Underlying registry, for recording metrics outside the measurement path
+    #[allow(dead_code)]
+    pub fn registry(&self) -> &MetricsRegistry {
+        &self.registry
+    }
+
+    /// Record a processed measurement taken for `material` during `layer`
+    pub fn record_measurement(
+        &self,
+        measurement: &ProcessedMeasurement,
+        material: &str,
+        layer: u32,
+    ) {
+        let layer_str = layer.to_string();
+        let labels = [("material", material), ("layer", layer_str.as_str())];
+        let mut layers = self.layers.lock().unwrap();
+
+        let key = (material.to_string(), layer_str.clone());
+        if layers.recent.back() != Some(&key) {
+            self.switch_layer(&mut layers, key);
+        }
+
+        self.registry.inc(MEASUREMENTS_TOTAL, &[], 1.0);
+        self.registry.inc(LAYER_MEASUREMENTS_TOTAL, &labels, 1.0);
+        self.registry.set(LAYER, &[], f64::from(layer));
+
+        if let Some(outliers) = &measurement.outliers {
+            for (series, counts) in outliers.series() {
+                let labels = [("series", series)];
+                self.registry
+                    .inc(OUTLIER_VALUES_TOTAL, &labels, counts.total as f64);
+                self.registry
+                    .inc(OUTLIERS_EXCLUDED_TOTAL, &labels, counts.excluded as f64);
+            }
+        }
+
+        if let Some(snr) = measurement.statistics.and_then(|s| s.snr) {
+            self.registry.set(SNR, &[], snr);
+        }
+        if measurement.low_snr {
+            self.registry.inc(LOW_SNR_TOTAL, &[], 1.0);
+        }
+
+        if !measurement.is_valid {
+            self.registry.inc(INVALID_MEASUREMENTS_TOTAL, &[], 1.0);
+            self.registry
+                .inc(LAYER_INVALID_MEASUREMENTS_TOTAL, &labels, 1.0);
+            if let Some(category) = measurement.validation_category {
+                self.registry.inc(
+                    VALIDATION_ERRORS_TOTAL,
+                    &[("category", category.as_str())],
+                    1.0,
+                );
+            }
+            return;
+        }
+
+        let reading = measurement.calibrated_reading;
+        self.registry.set(READING, &labels, reading);
+
+        if let Some((prev_at, prev_reading)) = layers.last_reading {
+            let dt = (measurement.timestamp - prev_at)
+                .num_microseconds()
+                .unwrap_or(0) as f64
+                / 1e6;
+            if dt > 0.0 {
+                self.registry
+                    .set(READING_RATE, &labels, (reading - prev_reading) / dt);
+            }
+        }
+        layers.last_reading = Some((measurement.timestamp, reading));
+    }
+
+    /// Record cycles lost before the latest one
+    pub fn record_gap(&self, gap: &CycleGap) {
+        self.registry.inc(
+            MISSED_CYCLES_TOTAL,
+            &[("detection", gap.detection.as_str())],
+            gap.missed as f64,
+        );
+    }
+
+    /// Record a closed-shutter cycle taken for the dark estimate
+    pub fn record_dark_cycle(&self) {
+        self.registry.inc(DARK_CYCLES_TOTAL, &[], 1.0);
+    }
+
+    /// Record processing being paused or resumed
+    pub fn set_processing_paused(&self, paused: bool) {
+        self.registry
+            .set(PROCESSING_PAUSED, &[], if paused { 1.0 } else { 0.0 });
+    }
+
+    /// Record an HTTP request to the route `path` answered with `status`
+    /// after `latency`
+    pub fn record_request(&self, method: &str, path: &str, status: u16, latency: Duration) {
+        let status = status.to_string();
+        self.registry.inc(
+            HTTP_REQUESTS_TOTAL,
+            &[("method", method), ("path", path), ("status", &status)],
+            1.0,
+        );
+        self.registry.observe(
+            HTTP_REQUEST_DURATION,
+            &[("method", method), ("path", path)],
+            latency.as_secs_f64(),
+        );
+    }
+
+    /// Record a push attempt to the monitoring API at `endpoint`
+    pub fn record_push(&self, endpoint: &str, delivered: bool) {
+        let result = if delivered { "delivered" } else { "failed" };
+        self.registry.inc(
+            MONITORING_PUSHES_TOTAL,
+            &[("endpoint", endpoint), ("result", result)],
+            1.0,
+        );
+    }
+
+    /// Move gauges to a new label set and prune old per-layer counters
+    fn switch_layer(&self, layers: &mut LayerLabels, key: (String, String)) {
+        if let Some((material, layer)) = layers.recent.back() {
+            let old = [("material", material.as_str()), ("layer", layer.as_str())];
+            self.registry.remove(READING, &old);
+            self.registry.remove(READING_RATE, &old);
+        }
+        layers.last_reading = None;
+
+        // A label set can come back (e.g. material toggled outside a run)
+        layers.recent.retain(|k| k != &key);
+        layers.recent.push_back(key);
+
+        while layers.recent.len() > MAX_LAYER_SERIES {
+            let Some((material, layer)) = layers.recent.pop_front() else {
+                break;
+            };
+            let old = [("material", material.as_str()), ("layer", layer.as_str())];
+            self.registry.remove(LAYER_MEASUREMENTS_TOTAL, &old);
+            self.registry.remove(LAYER_INVALID_MEASUREMENTS_TOTAL, &old);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        self.registry.render()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub type SharedMetrics = Arc<Metrics>;
+
+pub fn create_shared_metrics() -> SharedMetrics {
+    Arc::new(Metrics::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::processing::gaps::GapDetection;
+    use crate::protocol::{
+        MeasurementStatistics, OutlierExclusion, SeriesExclusion, SeriesStatistics,
+        ValidationCategory,
+    };
+
+    fn measurement(at: DateTime<Utc>, reading: f64) -> ProcessedMeasurement {
+        ProcessedMeasurement::new(at, 100.0, 1000.0, 550.0, reading)
+    }
+
+    #[test]
+    fn test_render_format() {
+        let registry = MetricsRegistry::default();
+        registry.describe("test_total", MetricKind::Counter, "A test counter");
+        registry.inc("test_total", &[("material", "H")], 2.0);
+        registry.inc("unknown_total", &[], 1.0);
+
+        let text = registry.render();
+        assert!(text.contains("# HELP test_total A test counter\n"));
+        assert!(text.contains("# TYPE test_total counter\n"));
+        assert!(text.contains("test_total{material=\"H\"} 2\n"));
+        assert!(!text.contains("unknown_total"));
+    }
+
+    #[test]
+    fn test_histogram_render() {
+        let registry = MetricsRegistry::default();
+        registry.describe_histogram("latency_seconds", "A test histogram", &[0.1, 1.0]);
+        for value in [0.05, 0.5, 0.5, 3.0] {
+            registry.observe("latency_seconds", &[("path", "/x")], value);
+        }
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE latency_seconds histogram\n"));
+        assert!(text.contains("latency_seconds_bucket{path=\"/x\",le=\"0.1\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{path=\"/x\",le=\"1\"} 3\n"));
+        assert!(text.contains("latency_seconds_bucket{path=\"/x\",le=\"+Inf\"} 4\n"));
+        assert!(text.contains("latency_seconds_sum{path=\"/x\"} 4.05\n"));
+        assert!(text.contains("latency_seconds_count{path=\"/x\"} 4\n"));
+    }
+
+    #[test]
+    fn test_label_value_escaped() {
+        let registry = MetricsRegistry::default();
+        registry.describe("test", MetricKind::Gauge, "help");
+        registry.set("test", &[("material", "a\"b")], 1.0);
+
+        assert!(registry.render().contains("test{material=\"a\\\"b\"} 1"));
+    }
+
+    #[test]
+    fn test_record_measurement_labels() {
+        let metrics = Metrics::new();
+        let t0 = Utc::now();
+        metrics.record_measurement(&measurement(t0, 40.0), "H", 1);
+        metrics.record_measurement(&measurement(t0 + Duration::seconds(2), 44.0), "H", 1);
+
+        let labels = [("material", "H"), ("layer", "1")];
+        let registry = metrics.registry();
+        assert_eq!(registry.get(READING, &labels), Some(44.0));
+        assert_eq!(registry.get(READING_RATE, &labels), Some(2.0));
+        assert_eq!(registry.get(LAYER_MEASUREMENTS_TOTAL, &labels), Some(2.0));
+        assert_eq!(registry.get(MEASUREMENTS_TOTAL, &[]), Some(2.0));
+    }
+
+    #[test]
+    fn test_invalid_measurement_counted() {
+        let metrics = Metrics::new();
+        let invalid = measurement(Utc::now(), 150.0).with_error("out of range".to_string());
+        metrics.record_measurement(&invalid, "L", 2);
+
+        let labels = [("material", "L"), ("layer", "2")];
+        let registry = metrics.registry();
+        assert_eq!(registry.get(INVALID_MEASUREMENTS_TOTAL, &[]), Some(1.0));
+        assert_eq!(
+            registry.get(LAYER_INVALID_MEASUREMENTS_TOTAL, &labels),
+            Some(1.0)
+        );
+        assert_eq!(registry.get(READING, &labels), None);
+        assert_eq!(
+            registry.get(VALIDATION_ERRORS_TOTAL, &[("category", "relationship")]),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_validation_errors_by_category() {
+        let metrics = Metrics::new();
+        let saturated = measurement(Utc::now(), 50.0)
+            .with_categorized_error(ValidationCategory::Saturation, "full saturated".to_string());
+        metrics.record_measurement(&saturated, "H", 1);
+        metrics.record_measurement(&saturated, "H", 1);
+
+        let registry = metrics.registry();
+        assert_eq!(
+            registry.get(VALIDATION_ERRORS_TOTAL, &[("category", "saturation")]),
+            Some(2.0)
+        );
+        assert_eq!(
+            registry.get(VALIDATION_ERRORS_TOTAL, &[("category", "under_range")]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_snr_recorded() {
+        let metrics = Metrics::new();
+        let series = SeriesStatistics {
+            std_dev: 2.0,
+            count: 12,
+        };
+        let statistics = MeasurementStatistics {
+            dark: series,
+            full: series,
+            sample: series,
+            uncertainty: None,
+            snr: Some(12.5),
+        };
+        let flagged = ProcessedMeasurement {
+            low_snr: true,
+            ..measurement(Utc::now(), 50.0).with_statistics(statistics)
+        };
+        metrics.record_measurement(&flagged, "H", 1);
+        metrics.record_measurement(&measurement(Utc::now(), 50.0), "H", 1);
+
+        let registry = metrics.registry();
+        assert_eq!(registry.get(SNR, &[]), Some(12.5));
+        assert_eq!(registry.get(LOW_SNR_TOTAL, &[]), Some(1.0));
+    }
+
+    #[test]
+    fn test_outlier_counters_by_series() {
+        let metrics = Metrics::new();
+        let mut m = measurement(Utc::now(), 50.0);
+        m.outliers = Some(OutlierExclusion {
+            dark: SeriesExclusion {
+                total: 4,
+                excluded: 1,
+            },
+            ..Default::default()
+        });
+        metrics.record_measurement(&m, "H", 1);
+        metrics.record_measurement(&m, "H", 1);
+
+        let registry = metrics.registry();
+        assert_eq!(
+            registry.get(OUTLIER_VALUES_TOTAL, &[("series", "dark")]),
+            Some(8.0)
+        );
+        assert_eq!(
+            registry.get(OUTLIERS_EXCLUDED_TOTAL, &[("series", "dark")]),
+            Some(2.0)
+        );
+        assert_eq!(
+            registry.get(OUTLIERS_EXCLUDED_TOTAL, &[("series", "sample")]),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn test_record_gap() {
+        let metrics = Metrics::new();
+        metrics.record_gap(&CycleGap {
+            missed: 3,
+            detection: GapDetection::Sequence,
+        });
+
+        assert_eq!(
+            metrics
+                .registry()
+                .get(MISSED_CYCLES_TOTAL, &[("detection", "sequence")]),
+            Some(3.0)
+        );
+    }
+
+    #[test]
+    fn test_layer_change_moves_gauges() {
+        let metrics = Metrics::new();
+        metrics.record_measurement(&measurement(Utc::now(), 40.0), "H", 1);
+        metrics.record_measurement(&measurement(Utc::now(), 60.0), "L", 2);
+
+        let registry = metrics.registry();
+        assert_eq!(
+            registry.get(READING, &[("material", "H"), ("layer", "1")]),
+            None
+        );
+        assert_eq!(
+            registry.get(READING, &[("material", "L"), ("layer", "2")]),
+            Some(60.0)
+        );
+        // Counters for the previous layer are kept
+        assert_eq!(
+            registry.get(
+                LAYER_MEASUREMENTS_TOTAL,
+                &[("material", "H"), ("layer", "1")]
+            ),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn test_old_layer_counters_pruned() {
+        let metrics = Metrics::new();
+        for layer in 1..=(MAX_LAYER_SERIES as u32 + 2) {
+            metrics.record_measurement(&measurement(Utc::now(), 50.0), "H", layer);
+        }
+
+        let registry = metrics.registry();
+        assert_eq!(
+            registry.get(
+                LAYER_MEASUREMENTS_TOTAL,
+                &[("material", "H"), ("layer", "1")]
+            ),
+            None
+        );
+        assert_eq!(
+            registry.get(
+                LAYER_MEASUREMENTS_TOTAL,
+                &[("material", "H"), ("layer", "10")]
+            ),
+            Some(1.0)
+        );
+        let series = registry.render().matches(LAYER_MEASUREMENTS_TOTAL).count();
+        // HELP and TYPE lines plus one line per kept label set
+        assert_eq!(series, 2 + MAX_LAYER_SERIES);
+    }
+}
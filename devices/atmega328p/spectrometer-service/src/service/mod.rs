@@ -1,3 +1,20 @@
+pub mod alerts;
+pub mod autogain;
 pub mod calibration;
+pub mod chamber;
 pub mod data_loop;
+pub mod diagnostics;
+pub mod events;
+pub mod health;
+pub mod heartbeat;
+pub mod history;
+pub mod layers;
+pub mod metrics;
+pub mod registration;
+pub mod reload;
+pub mod reprocess;
+pub mod selftest;
 pub mod state;
+pub mod supervisor;
+pub mod termination;
+pub mod watchdog;
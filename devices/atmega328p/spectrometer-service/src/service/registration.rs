@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::monitoring::{Announcement, MonitoringClient};
+use crate::service::calibration::SharedConfig;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::state::{DeviceState, SharedState};
+
+/// How often the registration age is checked against the TTL
+const REGISTRATION_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often an unregistered device announces itself again
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Why the monitoring registration was cleared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationLoss {
+    /// `POST /unregister` was called
+    Unregistered,
+    /// OptiMonitor did not re-register within the TTL
+    Expired,
+}
+
+/// Clear the registration of `device` and announce it. Returns false when
+/// the device was not registered.
+pub fn clear_registration(
+    device: &mut DeviceState,
+    events: &EventBus,
+    reason: RegistrationLoss,
+) -> bool {
+    let Some(monitoring_api_url) = device.monitoring_api_url.take() else {
+        return false;
+    };
+    device.spectrometer_id = None;
+    device.vacuum_chamber_id = None;
+    device.registered_at = None;
+
+    tracing::warn!("Monitoring registration with {monitoring_api_url} lost: {reason:?}");
+    events.publish(ServiceEvent::RegistrationLost {
+        at: Utc::now(),
+        monitoring_api_url,
+        reason,
+    });
+    true
+}
+
+/// Whether a registration made at `registered_at` is older than `ttl_secs`.
+/// A TTL too large for chrono never expires.
+pub fn registration_expired(
+    registered_at: DateTime<Utc>,
+    ttl_secs: u64,
+    now: DateTime<Utc>,
+) -> bool {
+    i64::try_from(ttl_secs)
+        .ok()
+        .and_then(chrono::Duration::try_seconds)
+        .is_some_and(|ttl| now - registered_at > ttl)
+}
+
+/// Clear the registration once it is older than the configured
+/// `monitoring.registration_ttl_secs`
+pub async fn run_registration_expiry(state: SharedState, config: SharedConfig, events: EventBus) {
+    let mut interval = tokio::time::interval(REGISTRATION_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Some(ttl_secs) = config.read().await.config.monitoring.registration_ttl_secs else {
+            continue;
+        };
+        let mut device = state.write().await;
+        if let Some(registered_at) = device.registered_at
+            && registration_expired(registered_at, ttl_secs, Utc::now())
+        {
+            clear_registration(&mut device, &events, RegistrationLoss::Expired);
+        }
+    }
+}
+
+/// Announce the device to OptiMonitor at `monitoring_url` whenever it is
+/// not registered: now, and again every `ANNOUNCE_INTERVAL` while the
+/// announcement is rejected or the registration was lost
+pub async fn run_announcer(
+    monitoring_url: String,
+    announcement: Announcement,
+    state: SharedState,
+    config: SharedConfig,
+) {
+    let mut interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if state.read().await.is_registered() {
+            continue;
+        }
+
+        let settings = config.read().await.config.monitoring.clone();
+        let client = match MonitoringClient::build(&settings.tls, &settings.http) {
+            Ok(client) => client.with_auth(settings.auth),
+            Err(e) => {
+                tracing::warn!("Cannot announce to {monitoring_url}: {e}");
+                continue;
+            }
+        };
+        match client.announce(&monitoring_url, &announcement).await {
+            Ok(response) => {
+                tracing::info!(
+                    "Announced to {monitoring_url} as device {}, spectrometer_id: {:?}, vacuum_chamber_id: {:?}",
+                    response.device_id,
+                    response.spectrometer_id,
+                    response.vacuum_chamber_id
+                );
+                let mut device = state.write().await;
+                device.monitoring_api_url = Some(monitoring_url.clone());
+                device.spectrometer_id = response.spectrometer_id;
+                device.vacuum_chamber_id = response.vacuum_chamber_id;
+                device.registered_at = Some(Utc::now());
+            }
+            Err(e) => tracing::warn!(
+                "Announcement to {monitoring_url} failed, retrying in {}s: {e}",
+                ANNOUNCE_INTERVAL.as_secs()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registered() -> DeviceState {
+        DeviceState {
+            monitoring_api_url: Some("http://optimonitor:8200".to_string()),
+            spectrometer_id: Some("spec-1".to_string()),
+            vacuum_chamber_id: Some("vc-1".to_string()),
+            registered_at: Some(Utc::now()),
+            ..DeviceState::default()
+        }
+    }
+
+    #[test]
+    fn test_registration_expired() {
+        let now = Utc::now();
+        assert!(!registration_expired(
+            now - chrono::Duration::seconds(30),
+            60,
+            now
+        ));
+        assert!(registration_expired(
+            now - chrono::Duration::seconds(61),
+            60,
+            now
+        ));
+        assert!(!registration_expired(now, u64::MAX, now));
+    }
+
+    #[tokio::test]
+    async fn test_clear_registration_publishes_event() {
+        let events = EventBus::default();
+        let mut rx = events.subscribe();
+        let mut device = registered();
+
+        assert!(clear_registration(
+            &mut device,
+            &events,
+            RegistrationLoss::Expired
+        ));
+        assert!(!device.is_registered());
+        assert!(device.registered_at.is_none());
+        match rx.recv().await.unwrap() {
+            ServiceEvent::RegistrationLost {
+                monitoring_api_url,
+                reason,
+                ..
+            } => {
+                assert_eq!(monitoring_api_url, "http://optimonitor:8200");
+                assert_eq!(reason, RegistrationLoss::Expired);
+            }
+            other => panic!("Unexpected event: {other:?}"),
+        }
+
+        // Nothing to clear the second time
+        assert!(!clear_registration(
+            &mut device,
+            &events,
+            RegistrationLoss::Unregistered
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_announcer_registers_with_assigned_ids() {
+        use axum::routing::post;
+        use axum::{Json, Router};
+
+        use crate::service::calibration::create_shared_config;
+        use crate::service::state::create_shared_state;
+
+        let app = Router::new().route(
+            "/devices/connect",
+            post(|Json(body): Json<serde_json::Value>| async move {
+                assert_eq!(body["address"], "lab-gw.example.com");
+                assert_eq!(body["type"], "spectrometer");
+                Json(serde_json::json!({
+                    "device_id": "dev-1",
+                    "device_name": "ATmega328P Monochromatic Spectrometer",
+                    "spectrometer_id": "spec-1",
+                    "vacuum_chamber_id": "vc-1",
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let monitoring_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let state = create_shared_state();
+        let announcer = tokio::spawn(run_announcer(
+            monitoring_url.clone(),
+            Announcement::new("lab-gw.example.com", 18100, ""),
+            state.clone(),
+            create_shared_config(dir.path().join("cfg.toml")),
+        ));
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !state.read().await.is_registered() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("announcer did not register");
+        announcer.abort();
+
+        let device = state.read().await;
+        assert_eq!(
+            device.monitoring_api_url.as_deref(),
+            Some(monitoring_url.as_str())
+        );
+        assert_eq!(device.spectrometer_id.as_deref(), Some("spec-1"));
+        assert_eq!(device.vacuum_chamber_id.as_deref(), Some("vc-1"));
+        assert!(device.registered_at.is_some());
+    }
+}
@@ -0,0 +1,534 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::error::SpectrometerError;
+use crate::monitoring::MonitoringClient;
+use crate::processing::references::ReferenceTable;
+use crate::service::state::AppState;
+
+/// What a config reload changed
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    /// Changes applied immediately
+    pub applied: Vec<String>,
+    /// Changes that need a data source restart and were not applied
+    pub deferred: Vec<String>,
+}
+
+/// Re-read the config file and apply the settings that are safe to change
+/// while running. Nothing is applied if the file is unreadable or invalid.
+pub async fn reload_config(state: &AppState) -> Result<ReloadReport, SpectrometerError> {
+    let mut cfg = state.config.write().await;
+    let current = cfg.config.clone();
+    let mut new = cfg.read_from_disk().map_err(SpectrometerError::Config)?;
+    let mut report = ReloadReport::default();
+
+    // Build the excluder first so an invalid alpha aborts the whole reload
+    let new_excluder = match &new.processing.outlier {
+        Some(method) if new.processing.outlier != current.processing.outlier => {
+            Some((method.clone(), method.create()?))
+        }
+        _ => None,
+    };
+
+    new.processing.linearity.load_file()?;
+    new.processing
+        .validate()
+        .map_err(SpectrometerError::Config)?;
+    // Re-read the reference file even if the settings are unchanged, as
+    // it may have been edited
+    let new_references = new
+        .processing
+        .references
+        .file
+        .as_deref()
+        .map(ReferenceTable::load)
+        .transpose()?
+        .unwrap_or_default();
+    new.monitoring
+        .validate()
+        .map_err(SpectrometerError::Config)?;
+    if (&new.monitoring.tls, &new.monitoring.http)
+        != (&current.monitoring.tls, &current.monitoring.http)
+    {
+        MonitoringClient::build(&new.monitoring.tls, &new.monitoring.http)?;
+    }
+    new.alerts.validate().map_err(SpectrometerError::Config)?;
+    new.control.validate().map_err(SpectrometerError::Config)?;
+    new.auto_gain
+        .validate()
+        .map_err(SpectrometerError::Config)?;
+    new.termination
+        .validate(new.processing.thickness.enabled)
+        .map_err(SpectrometerError::Config)?;
+
+    // Mirror and failover senders are set up when the monitoring sink is
+    // created; keep the running endpoints
+    if new.monitoring.endpoints != current.monitoring.endpoints {
+        report.deferred.push("monitoring.endpoints".to_string());
+        new.monitoring.endpoints = current.monitoring.endpoints.clone();
+    }
+
+    // Acquisition settings are sent to the device when the data source
+    // starts; keep the running values and report the difference
+    let running = &current.device_settings;
+    let wanted = &new.device_settings;
+    if wanted.gain != running.gain {
+        report
+            .deferred
+            .push(format!("gain: {} -> {}", running.gain, wanted.gain));
+    }
+    if wanted.fadc != running.fadc {
+        report
+            .deferred
+            .push(format!("fadc: {} -> {}", running.fadc, wanted.fadc));
+    }
+    if wanted.count != running.count {
+        report
+            .deferred
+            .push(format!("count: {} -> {}", running.count, wanted.count));
+    }
+    if wanted.series_count != running.series_count {
+        report.deferred.push(format!(
+            "series_count: {} -> {}",
+            running.series_count, wanted.series_count
+        ));
+    }
+    new.device_settings.gain = running.gain;
+    new.device_settings.fadc = running.fadc;
+    new.device_settings.count = running.count;
+    new.device_settings.series_count = running.series_count;
+    new.device_settings
+        .series_mapping
+        .validate(running.series_count, new.processing.references.stored())
+        .map_err(SpectrometerError::Config)?;
+
+    if new.device_settings.series_mapping != running.series_mapping {
+        report.applied.push("series_mapping".to_string());
+    }
+
+    if let Some((method, excluder)) = new_excluder {
+        *state.outlier_excluder.write().unwrap() = Arc::from(excluder);
+        report.applied.push(format!("outlier: {method:?}"));
+    }
+
+    if new.processing.validation != current.processing.validation {
+        report
+            .applied
+            .push(format!("validation: {:?}", new.processing.validation));
+    }
+
+    if new.processing.aggregation != current.processing.aggregation {
+        report
+            .applied
+            .push(format!("aggregation: {:?}", new.processing.aggregation));
+    }
+
+    if new.processing.units != current.processing.units {
+        report
+            .applied
+            .push(format!("units: {:?}", new.processing.units));
+    }
+
+    {
+        let mut device = state.device.write().await;
+        if new.processing.references != current.processing.references
+            || new_references != device.references
+        {
+            report.applied.push(format!(
+                "references: {:?}, {} entries",
+                new.processing.references.mode,
+                new_references.entries.len()
+            ));
+            device.references = new_references;
+        }
+    }
+
+    if new.processing.linearity != current.processing.linearity {
+        report.applied.push(format!(
+            "linearity: {:?}",
+            new.processing.linearity.correction()
+        ));
+    }
+
+    if new.processing.thickness != current.processing.thickness {
+        report
+            .applied
+            .push(format!("thickness: {:?}", new.processing.thickness));
+    }
+
+    if new.processing.dark_compensation != current.processing.dark_compensation {
+        report.applied.push(format!(
+            "dark_compensation: {:?}",
+            new.processing.dark_compensation
+        ));
+    }
+
+    if new.processing.smoother_config(None) != current.processing.smoother_config(None) {
+        report.applied.push(format!(
+            "smoother: {:?}",
+            new.processing.smoother_config(None)
+        ));
+    }
+
+    if new.processing.pipeline() != current.processing.pipeline() {
+        report
+            .applied
+            .push(format!("pipeline: {:?}", new.processing.pipeline()));
+    }
+
+    if new.monitoring.api_url != current.monitoring.api_url {
+        let mut device = state.device.write().await;
+        match &new.monitoring.api_url {
+            Some(url) => {
+                device.monitoring_api_url = Some(url.clone());
+                report.applied.push(format!("monitoring.api_url: {url}"));
+            }
+            // Stop pushing to the removed URL, unless a registration has
+            // moved the device to another one since
+            None => {
+                if device.monitoring_api_url == current.monitoring.api_url {
+                    device.monitoring_api_url = None;
+                }
+                report
+                    .applied
+                    .push("monitoring.api_url: removed".to_string());
+            }
+        }
+    }
+
+    if new.monitoring.replay_batch_size != current.monitoring.replay_batch_size {
+        report.applied.push(format!(
+            "monitoring.replay_batch_size: {}",
+            new.monitoring.replay_batch_size
+        ));
+    }
+
+    if new.monitoring.auth != current.monitoring.auth {
+        report.applied.push("monitoring.auth".to_string());
+    }
+
+    if new.monitoring.tls != current.monitoring.tls {
+        report.applied.push("monitoring.tls".to_string());
+    }
+
+    if new.monitoring.http != current.monitoring.http {
+        report.applied.push("monitoring.http".to_string());
+    }
+
+    if new.monitoring.heartbeat_interval_secs != current.monitoring.heartbeat_interval_secs {
+        report.applied.push(format!(
+            "monitoring.heartbeat_interval_secs: {}",
+            new.monitoring.heartbeat_interval_secs
+        ));
+    }
+
+    if new.monitoring.compression != current.monitoring.compression {
+        report.applied.push(format!(
+            "monitoring.compression: {:?}",
+            new.monitoring.compression
+        ));
+    }
+
+    if new.monitoring.push_series != current.monitoring.push_series {
+        report.applied.push(format!(
+            "monitoring.push_series: {}",
+            new.monitoring.push_series
+        ));
+    }
+
+    if new.monitoring.registration_ttl_secs != current.monitoring.registration_ttl_secs {
+        report.applied.push(format!(
+            "monitoring.registration_ttl_secs: {:?}",
+            new.monitoring.registration_ttl_secs
+        ));
+    }
+
+    if new.alerts != current.alerts {
+        report.applied.push(format!(
+            "alerts: {} rules, {} webhooks",
+            new.alerts.rules.len(),
+            new.alerts.webhooks.len()
+        ));
+    }
+
+    if new.control != current.control {
+        report.applied.push(format!(
+            "control: {}..{} nm, materials {}",
+            new.control.wavelength_min,
+            new.control.wavelength_max,
+            new.control.material_symbols()
+        ));
+    }
+
+    if new.watchdog != current.watchdog {
+        report.applied.push(format!(
+            "watchdog: stall_after_secs {}, restart_source {}",
+            new.watchdog.stall_after_secs, new.watchdog.restart_source
+        ));
+    }
+
+    if new.termination != current.termination {
+        report.applied.push(format!(
+            "termination: {} targets, auto_stop {}",
+            new.termination.targets.len(),
+            new.termination.auto_stop
+        ));
+    }
+
+    if new.auto_gain != current.auto_gain {
+        report
+            .applied
+            .push(format!("auto_gain: {:?}", new.auto_gain));
+    }
+
+    cfg.config = new;
+
+    tracing::info!(
+        "Config reloaded: applied [{}], deferred [{}]",
+        report.applied.join(", "),
+        report.deferred.join(", ")
+    );
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(dir: &tempfile::TempDir, extra: &str) {
+        let contents = format!(
+            r#"
+last_updated = "2025-01-15T10:30:00Z"
+
+[device_settings]
+gain = 8
+fadc = 250.0
+count = 4
+
+[device_settings.series_mapping]
+dark = 3
+full = 2
+sample = 1
+{extra}"#
+        );
+        std::fs::write(dir.path().join("cfg.toml"), contents).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_applies_runtime_settings() {
+        let (state, dir) = AppState::for_test();
+        write_config(
+            &dir,
+            r#"
+[processing.outlier]
+method = "none"
+
+[processing.validation]
+rule = "off"
+
+[processing.dark_compensation]
+enabled = true
+smoothing = 0.05
+
+[processing.kalman]
+process_noise = 0.05
+
+[monitoring]
+api_url = "http://monitor:8200"
+replay_batch_size = 10
+"#,
+        );
+
+        let report = reload_config(&state).await.unwrap();
+
+        assert!(report.applied.contains(&"series_mapping".to_string()));
+        assert!(report.applied.iter().any(|a| a.starts_with("outlier")));
+        assert_eq!(state.outlier_excluder.read().unwrap().name(), "None");
+        assert!(report.applied.iter().any(|a| a.starts_with("validation")));
+        assert!(
+            report
+                .applied
+                .iter()
+                .any(|a| a.starts_with("dark_compensation"))
+        );
+        assert!(report.applied.iter().any(|a| a.starts_with("smoother")));
+        assert_eq!(
+            state.device.read().await.monitoring_api_url.as_deref(),
+            Some("http://monitor:8200")
+        );
+        let cfg = state.config.read().await;
+        assert_eq!(cfg.config.monitoring.replay_batch_size, 10);
+        assert_eq!(cfg.config.device_settings.series_mapping.dark, 3);
+    }
+
+    #[tokio::test]
+    async fn test_reload_clears_removed_api_url() {
+        let (state, dir) = AppState::for_test();
+        {
+            let mut cfg = state.config.write().await;
+            cfg.config.monitoring.api_url = Some("http://monitor:8200".to_string());
+        }
+        state.device.write().await.monitoring_api_url = Some("http://monitor:8200".to_string());
+        write_config(&dir, "");
+
+        let report = reload_config(&state).await.unwrap();
+
+        assert!(
+            report
+                .applied
+                .contains(&"monitoring.api_url: removed".to_string())
+        );
+        assert!(state.device.read().await.monitoring_api_url.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reload_defers_acquisition_settings() {
+        let (state, dir) = AppState::for_test();
+        write_config(
+            &dir,
+            r#"
+[[monitoring.endpoints]]
+url = "http://staging:8200"
+mode = "mirror"
+"#,
+        );
+
+        let report = reload_config(&state).await.unwrap();
+
+        assert_eq!(
+            report.deferred,
+            vec!["monitoring.endpoints", "gain: 2 -> 8"]
+        );
+        let cfg = state.config.read().await;
+        assert_eq!(cfg.config.device_settings.gain, 2);
+        assert!(cfg.config.monitoring.endpoints.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config_applies_nothing() {
+        let (state, dir) = AppState::for_test();
+        write_config(
+            &dir,
+            r#"
+[processing.outlier]
+method = "grubbs"
+alpha = 2.0
+"#,
+        );
+
+        assert!(reload_config(&state).await.is_err());
+        let cfg = state.config.read().await;
+        assert_eq!(cfg.config.device_settings.series_mapping.dark, 1);
+        assert!(cfg.config.processing.outlier.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mapping_beyond_series_count_applies_nothing() {
+        let (state, dir) = AppState::for_test();
+        write_config(&dir, "reference = 4\n");
+
+        let err = reload_config(&state).await.unwrap_err();
+        assert!(err.to_string().contains("SERIES4"));
+        let cfg = state.config.read().await;
+        assert_eq!(cfg.config.device_settings.series_mapping.dark, 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_validation_tolerance_applies_nothing() {
+        let (state, dir) = AppState::for_test();
+        write_config(
+            &dir,
+            r#"
+[processing.validation]
+overshoot_percent = -5.0
+"#,
+        );
+
+        let err = reload_config(&state).await.unwrap_err();
+        assert!(err.to_string().contains("overshoot_percent"));
+        let cfg = state.config.read().await;
+        assert_eq!(cfg.config.processing.validation.overshoot_percent, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_dark_smoothing_applies_nothing() {
+        let (state, dir) = AppState::for_test();
+        write_config(
+            &dir,
+            r#"
+[processing.dark_compensation]
+enabled = true
+smoothing = 0.0
+"#,
+        );
+
+        let err = reload_config(&state).await.unwrap_err();
+        assert!(err.to_string().contains("processing.dark_compensation"));
+        let cfg = state.config.read().await;
+        assert!(!cfg.config.processing.dark_compensation.enabled);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_change_is_applied() {
+        let (state, dir) = AppState::for_test();
+        write_config(
+            &dir,
+            r#"
+[processing]
+pipeline = ["aggregation", "calibration", "validation"]
+"#,
+        );
+
+        let report = reload_config(&state).await.unwrap();
+        assert!(report.applied.iter().any(|a| a.starts_with("pipeline")));
+
+        write_config(
+            &dir,
+            r#"
+[processing]
+pipeline = ["aggregation", "validation"]
+"#,
+        );
+        let err = reload_config(&state).await.unwrap_err();
+        assert!(err.to_string().contains("processing.pipeline"));
+        let cfg = state.config.read().await;
+        assert_eq!(cfg.config.processing.pipeline().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_reference_file_is_loaded() {
+        let (state, dir) = AppState::for_test();
+        let file = dir.path().join("references.toml");
+        std::fs::write(
+            &file,
+            "[[reference]]\ngain = 8\ndark = 14000000.0\nfull = 300.0\n",
+        )
+        .unwrap();
+        write_config(
+            &dir,
+            &format!(
+                "\n[processing.references]\nmode = \"stored\"\nfile = {:?}\n",
+                file
+            ),
+        );
+
+        let report = reload_config(&state).await.unwrap();
+        assert!(report.applied.iter().any(|a| a.starts_with("references")));
+        assert_eq!(state.device.read().await.references.entries.len(), 1);
+
+        // An unparseable file applies nothing
+        std::fs::write(&file, "[[reference]]\ngain = \"high\"\n").unwrap();
+        assert!(reload_config(&state).await.is_err());
+        assert_eq!(state.device.read().await.references.entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_is_error() {
+        let (state, _dir) = AppState::for_test();
+        let err = reload_config(&state).await.unwrap_err();
+        assert!(err.to_string().contains("Failed to read"));
+    }
+}
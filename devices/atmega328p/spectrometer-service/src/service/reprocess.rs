@@ -0,0 +1,230 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::data_sink::csv::{CSV_HEADER, csv_row};
+use crate::data_source::DataSource;
+use crate::data_source::playback::PlaybackDataSource;
+use crate::error::SpectrometerError;
+use crate::processing::dark::{RollingDark, closed_shutter_level};
+use crate::processing::outlier::{OutlierMethod, create_shared_excluder};
+use crate::processing::pipeline::{Pipeline, StageResources};
+use crate::processing::references::ReferenceTable;
+use crate::processing::smoothing::SmootherKind;
+use crate::processing::timing::TimestampMode;
+use crate::processing::validation::MeasurementValidator;
+use crate::protocol::{CycleSettings, ProtocolKind, ShutterState};
+use crate::service::calibration::{DeviceSettings, ProcessingSettings};
+use crate::service::data_loop::assign_roles;
+
+/// Output of the `reprocess` subcommand
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReprocessFormat {
+    /// One JSON object per measurement, as the `stdout` sink writes (default)
+    #[default]
+    Jsonl,
+    /// Same columns as the `csv` sink
+    Csv,
+}
+
+/// How a log is read and processed by `reprocess_log`
+#[derive(Debug, Clone)]
+pub struct ReprocessOptions {
+    pub protocol: ProtocolKind,
+    pub cycle_settings: CycleSettings,
+    /// Series mapping and acquisition settings the log was recorded with
+    pub device: DeviceSettings,
+    pub processing: ProcessingSettings,
+    pub outlier: OutlierMethod,
+    /// `--smoother`, overriding the configured smoother
+    pub smoother: Option<SmootherKind>,
+    pub timestamp_mode: TimestampMode,
+    /// Spacing of the synthetic timestamps of raw logs
+    pub cycle_interval_ms: u64,
+    /// Control wavelength the log was recorded at, for stored references
+    pub wavelength: f64,
+}
+
+/// Play a log back without delay through the processing pipeline and write
+/// every measurement to `out`. Returns the number of measurements written.
+///
+/// Unlike the data loop, every cycle is written regardless of the chamber
+/// state, so runs can be compared against different settings in full.
+pub async fn reprocess_log(
+    log_file: PathBuf,
+    options: &ReprocessOptions,
+    format: ReprocessFormat,
+    out: &mut dyn Write,
+) -> Result<usize, SpectrometerError> {
+    options
+        .processing
+        .validate()
+        .map_err(SpectrometerError::Config)?;
+
+    let outlier_excluder = create_shared_excluder(options.outlier.create()?);
+    let excluder = outlier_excluder.read().unwrap().clone();
+    let rolling_dark = Arc::new(Mutex::new(RollingDark::default()));
+    let pipeline = Pipeline::build(
+        options.processing.pipeline(),
+        &StageResources {
+            outlier_excluder,
+            rolling_dark: rolling_dark.clone(),
+            smoother_override: options.smoother,
+        },
+    );
+    let [dark_aggregator, _, _] = options.processing.aggregation.series_aggregators();
+    let validator = MeasurementValidator::with_settings(options.processing.validation);
+    tracing::info!("Reprocessing {:?} with {}", log_file, pipeline.describe());
+
+    let references = &options.processing.references;
+    let reference = match (&references.file, references.stored()) {
+        (Some(file), true) => {
+            let reference =
+                ReferenceTable::load(file)?.lookup(options.device.gain, options.wavelength);
+            if reference.is_none() {
+                tracing::warn!(
+                    "No stored reference for gain {} at {} nm in {:?}",
+                    options.device.gain,
+                    options.wavelength,
+                    file
+                );
+            }
+            reference
+        }
+        _ => None,
+    };
+
+    let mut source =
+        PlaybackDataSource::new_raw(log_file, f64::INFINITY, false, options.cycle_interval_ms);
+    source.set_protocol(options.protocol);
+    source.set_cycle_settings(options.cycle_settings);
+    let mut cycle_rx = source.start().await?;
+
+    if format == ReprocessFormat::Csv {
+        writeln!(out, "{CSV_HEADER}")?;
+    }
+
+    let device = &options.device;
+    let mut written = 0;
+    while let Some(cycle) = cycle_rx.recv().await {
+        // Closed-shutter cycles only feed the dark estimate, as live
+        if cycle.shutter == Some(ShutterState::Closed) {
+            if let Some(level) = closed_shutter_level(
+                &cycle,
+                excluder.as_ref(),
+                dark_aggregator.as_ref(),
+                &validator,
+            ) {
+                let smoothing = options.processing.dark_compensation.smoothing;
+                rolling_dark.lock().unwrap().update(level, smoothing);
+            }
+            continue;
+        }
+        let Some(mut cycle) = assign_roles(cycle, &device.series_mapping, references.stored())
+        else {
+            continue;
+        };
+        cycle.timestamp = options.timestamp_mode.timestamp(
+            cycle.timestamp,
+            device.fadc,
+            device.count,
+            device.series_count,
+            device.series_mapping.sample,
+        );
+
+        let mut measurement = pipeline.run(&cycle, &options.processing, reference);
+        measurement.wavelength = Some(options.wavelength);
+        match format {
+            ReprocessFormat::Jsonl => {
+                let line = serde_json::to_string(&measurement)
+                    .map_err(|e| SpectrometerError::Validation(e.to_string()))?;
+                writeln!(out, "{line}")?;
+            }
+            ReprocessFormat::Csv => writeln!(out, "{}", csv_row(&measurement))?,
+        }
+        written += 1;
+    }
+
+    source.stop().await?;
+    out.flush()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> ReprocessOptions {
+        ReprocessOptions {
+            protocol: ProtocolKind::Atmega,
+            cycle_settings: CycleSettings::default(),
+            device: DeviceSettings::default(),
+            processing: ProcessingSettings::default(),
+            outlier: OutlierMethod::default(),
+            smoother: None,
+            timestamp_mode: TimestampMode::default(),
+            cycle_interval_ms: 100,
+            wavelength: 550.0,
+        }
+    }
+
+    async fn reprocess(options: &ReprocessOptions, format: ReprocessFormat) -> Vec<String> {
+        let mut out = Vec::new();
+        let written = reprocess_log(
+            PathBuf::from("fixtures/sample_log.txt"),
+            options,
+            format,
+            &mut out,
+        )
+        .await
+        .unwrap();
+
+        let lines: Vec<String> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let header = usize::from(format == ReprocessFormat::Csv);
+        assert_eq!(lines.len(), written + header);
+        lines
+    }
+
+    #[tokio::test]
+    async fn test_reprocess_sample_log() {
+        let lines = reprocess(&options(), ReprocessFormat::Jsonl).await;
+        assert_eq!(lines.len(), 5);
+
+        let first: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert!(first["calibrated_reading"].as_f64().unwrap() > 0.0);
+
+        let lines = reprocess(&options(), ReprocessFormat::Csv).await;
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines.len(), 6);
+    }
+
+    #[tokio::test]
+    async fn test_reprocess_with_other_settings() {
+        let mut options = options();
+        options.smoother = Some(SmootherKind::Ema);
+        options.outlier = OutlierMethod::None;
+        let lines = reprocess(&options, ReprocessFormat::Jsonl).await;
+
+        // EMA smooths from the second reading on
+        let last: serde_json::Value = serde_json::from_str(&lines[4]).unwrap();
+        assert!(last["smoothed"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_reprocess_rejects_invalid_settings() {
+        let mut options = options();
+        options.processing.ema.alpha = 0.0;
+        let result = reprocess_log(
+            PathBuf::from("fixtures/sample_log.txt"),
+            &options,
+            ReprocessFormat::Jsonl,
+            &mut Vec::new(),
+        )
+        .await;
+        assert!(matches!(result, Err(SpectrometerError::Config(_))));
+    }
+}
@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::time::{Instant, timeout_at};
+
+use crate::monitoring::MonitoringClient;
+use crate::protocol::{MAX_ADC_VALUE, ParsedLine, ProcessedMeasurement, parse_line};
+use crate::service::events::ServiceEvent;
+use crate::service::state::AppState;
+
+/// Minimum |full - dark| separation for the baselines to be considered usable
+pub const MIN_BASELINE_SPAN: f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// The check could not run (e.g. not registered, no cycle to inspect)
+    Skip,
+}
+
+/// Result of a single self-test check
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+impl SelfTestCheck {
+    fn new(name: &'static str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Scripted operator check: device responds, cycles arrive, baselines are
+/// sane, monitoring API is reachable
+pub struct SelfTest {
+    /// How long to wait for the device to acknowledge a command
+    pub device_timeout: Duration,
+    /// How long to wait for a complete measurement cycle
+    pub cycle_timeout: Duration,
+}
+
+impl Default for SelfTest {
+    fn default() -> Self {
+        Self {
+            device_timeout: Duration::from_secs(2),
+            cycle_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl SelfTest {
+    pub async fn run(&self, state: &AppState) -> Vec<SelfTestCheck> {
+        let mut checks = vec![self.check_device_response(state).await];
+
+        let measurement = self.wait_for_cycle(state).await;
+        checks.push(match &measurement {
+            Some(m) => SelfTestCheck::new(
+                "cycle_received",
+                CheckStatus::Pass,
+                format!("Cycle at {}", m.timestamp.to_rfc3339()),
+            ),
+            None => SelfTestCheck::new(
+                "cycle_received",
+                CheckStatus::Fail,
+                format!("No cycle within {:?}", self.cycle_timeout),
+            ),
+        });
+        checks.push(check_baselines(measurement.as_ref()));
+        checks.push(check_monitoring(state).await);
+
+        checks
+    }
+
+    /// Re-send the current COUNT and wait for the firmware to echo it.
+    /// The firmware has no version query; COUNT is the cheapest round trip.
+    async fn check_device_response(&self, state: &AppState) -> SelfTestCheck {
+        const NAME: &str = "device_response";
+
+        let count = state.config.read().await.config.device_settings.count;
+        let mut events = state.events.subscribe();
+
+        if let Err(e) = state.send_device_command(&format!("COUNT={count}")).await {
+            return SelfTestCheck::new(NAME, CheckStatus::Fail, e);
+        }
+
+        let deadline = Instant::now() + self.device_timeout;
+        loop {
+            match timeout_at(deadline, events.recv()).await {
+                Ok(Ok(ServiceEvent::Log { line }))
+                    if parse_line(&line) == ParsedLine::CountSet(count) =>
+                {
+                    return SelfTestCheck::new(
+                        NAME,
+                        CheckStatus::Pass,
+                        format!("Device acknowledged COUNT={count}"),
+                    );
+                }
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => {
+                    return SelfTestCheck::new(
+                        NAME,
+                        CheckStatus::Fail,
+                        format!(
+                            "No acknowledgement of COUNT={count} within {:?}",
+                            self.device_timeout
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    async fn wait_for_cycle(&self, state: &AppState) -> Option<ProcessedMeasurement> {
+        let mut events = state.events.subscribe();
+        let deadline = Instant::now() + self.cycle_timeout;
+
+        loop {
+            match timeout_at(deadline, events.recv()).await {
+                Ok(Ok(ServiceEvent::Measurement(m))) => return Some(*m),
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => continue,
+                Ok(Err(broadcast::error::RecvError::Closed)) | Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Dark and full references must be unclipped and well separated
+fn check_baselines(measurement: Option<&ProcessedMeasurement>) -> SelfTestCheck {
+    const NAME: &str = "calibration_baselines";
+
+    let Some(m) = measurement else {
+        return SelfTestCheck::new(NAME, CheckStatus::Skip, "No cycle to inspect");
+    };
+
+    let max = f64::from(MAX_ADC_VALUE);
+    let span = (m.full_mean - m.dark_mean).abs();
+
+    let problem = if m.dark_mean >= max || m.full_mean >= max {
+        Some("reference is saturated".to_string())
+    } else if m.dark_mean <= 0.0 || m.full_mean <= 0.0 {
+        Some("reference reads zero".to_string())
+    } else if span < MIN_BASELINE_SPAN {
+        Some(format!(
+            "full/dark separation {span:.0} is below {MIN_BASELINE_SPAN:.0}"
+        ))
+    } else {
+        None
+    };
+
+    let detail = format!("dark={:.0}, full={:.0}", m.dark_mean, m.full_mean);
+    match problem {
+        Some(problem) => {
+            SelfTestCheck::new(NAME, CheckStatus::Fail, format!("{detail}: {problem}"))
+        }
+        None => SelfTestCheck::new(NAME, CheckStatus::Pass, detail),
+    }
+}
+
+async fn check_monitoring(state: &AppState) -> SelfTestCheck {
+    const NAME: &str = "monitoring_reachable";
+
+    let Some(api_url) = state.device.read().await.monitoring_api_url.clone() else {
+        return SelfTestCheck::new(NAME, CheckStatus::Skip, "Not registered");
+    };
+
+    let settings = state.config.read().await.config.monitoring.clone();
+    let client = match MonitoringClient::build(&settings.tls, &settings.http) {
+        Ok(client) => client.with_auth(settings.auth),
+        Err(e) => return SelfTestCheck::new(NAME, CheckStatus::Fail, e.to_string()),
+    };
+    match client.check_reachable(&api_url).await {
+        Ok(()) => SelfTestCheck::new(NAME, CheckStatus::Pass, format!("{api_url} reachable")),
+        Err(e) => SelfTestCheck::new(NAME, CheckStatus::Fail, format!("{api_url}: {e}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::service::supervisor::SourceCommand;
+
+    fn test_state() -> (AppState, mpsc::Receiver<SourceCommand>, tempfile::TempDir) {
+        let (mut state, dir) = AppState::for_test();
+        let (cmd_tx, cmd_rx) = mpsc::channel(16);
+        state.device_cmd_tx = cmd_tx;
+        (state, cmd_rx, dir)
+    }
+
+    fn quick() -> SelfTest {
+        SelfTest {
+            device_timeout: Duration::from_millis(200),
+            cycle_timeout: Duration::from_millis(200),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_all_checks_pass_with_responsive_device() {
+        let (state, mut cmd_rx, _dir) = test_state();
+
+        // Fake device: echo commands and produce a cycle
+        let device = state.clone();
+        tokio::spawn(async move {
+            while let Some(SourceCommand::Device(cmd)) = cmd_rx.recv().await {
+                device.events.publish(ServiceEvent::Log { line: cmd });
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                device.events.publish(ServiceEvent::Measurement(Box::new(
+                    ProcessedMeasurement::new(Utc::now(), 14_000_000.0, 300.0, 7_000_000.0, 50.0),
+                )));
+            }
+        });
+
+        let checks = quick().run(&state).await;
+        let statuses: Vec<_> = checks.iter().map(|c| (c.name, c.status)).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("device_response", CheckStatus::Pass),
+                ("cycle_received", CheckStatus::Pass),
+                ("calibration_baselines", CheckStatus::Pass),
+                ("monitoring_reachable", CheckStatus::Skip),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_silent_device_fails() {
+        let (state, _cmd_rx, _dir) = test_state();
+
+        let checks = quick().run(&state).await;
+        assert_eq!(checks[0].status, CheckStatus::Fail);
+        assert_eq!(checks[1].status, CheckStatus::Fail);
+        assert_eq!(checks[2].status, CheckStatus::Skip);
+    }
+
+    #[test]
+    fn test_baselines_rejects_close_references() {
+        let m = ProcessedMeasurement::new(Utc::now(), 1000.0, 1500.0, 1200.0, 40.0);
+        assert_eq!(check_baselines(Some(&m)).status, CheckStatus::Fail);
+
+        let m =
+            ProcessedMeasurement::new(Utc::now(), f64::from(MAX_ADC_VALUE), 300.0, 1000.0, 40.0);
+        assert_eq!(check_baselines(Some(&m)).status, CheckStatus::Fail);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_monitoring_fails() {
+        let (state, _cmd_rx, _dir) = test_state();
+        state.device.write().await.monitoring_api_url = Some("http://127.0.0.1:1".to_string());
+
+        assert_eq!(check_monitoring(&state).await.status, CheckStatus::Fail);
+    }
+}
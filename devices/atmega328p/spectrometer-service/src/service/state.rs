@@ -1,9 +1,30 @@
+use std::collections::VecDeque;
 use std::sync::Arc;
 
-use tokio::sync::{RwLock, broadcast, mpsc};
+use chrono::{DateTime, Utc};
+use tokio::sync::{RwLock, mpsc, oneshot};
 
-use crate::protocol::ProcessedMeasurement;
+use crate::data_source::{ActiveSource, DataSourceConfig};
+use crate::error::SpectrometerError;
+use crate::processing::dark::RollingDark;
+use crate::processing::outlier::SharedExcluder;
+use crate::processing::outlier::stats::ExclusionStats;
+use crate::processing::references::ReferenceTable;
+use crate::processing::thickness::ThicknessEstimator;
+use crate::protocol::{ConfirmedSettings, DebugBlock, FirmwareVersion, ProcessedMeasurement};
 use crate::service::calibration::SharedConfig;
+use crate::service::chamber::{ChamberState, ChamberStateMachine, ShutterTracker};
+use crate::service::diagnostics::{DeviceEventLog, ProtocolDiagnostics, PushLog};
+use crate::service::events::EventBus;
+use crate::service::health::HealthState;
+use crate::service::history::SharedHistory;
+use crate::service::layers::LayerLog;
+use crate::service::metrics::SharedMetrics;
+use crate::service::supervisor::SourceCommand;
+use crate::storage::SharedArchive;
+
+/// Number of firmware debug blocks kept for `GET /debug/device`
+pub const MAX_DEBUG_BLOCKS: usize = 32;
 
 /// Application state for the spectrometer service
 #[derive(Debug, Clone)]
@@ -11,11 +32,48 @@ pub struct DeviceState {
     pub monitoring_api_url: Option<String>,
     pub spectrometer_id: Option<String>,
     pub vacuum_chamber_id: Option<String>,
+    /// When OptiMonitor last called `/register`; the registration TTL
+    /// counts from here
+    pub registered_at: Option<DateTime<Utc>>,
     pub control_wavelength: f64,
-    pub is_running: bool,
+    pub chamber: ChamberStateMachine,
     pub current_material: String,
-    pub is_depositing: bool,
+    /// Layers of the current run: layer 1 opens when a run starts, the next
+    /// one on each material change during deposition
+    pub layers: LayerLog,
+    /// Identifier of the current deposition run, set when a run starts and
+    /// cleared when it stops
+    pub run_id: Option<String>,
     pub latest_reading: Option<ProcessedMeasurement>,
+    /// Rolling dark estimate as of the latest cycle
+    pub dark_estimate: RollingDark,
+    /// Outlier exclusion counts for `GET /processing/stats`
+    pub outlier_stats: ExclusionStats,
+    /// Most recent firmware debug dumps, oldest first
+    pub debug_blocks: VecDeque<DebugBlock>,
+    /// Unknown, error and cycle-missing lines from the data source
+    pub protocol: ProtocolDiagnostics,
+    /// Firmware error, cycle-missing and ADC-ready lines
+    pub device_events: DeviceEventLog,
+    /// Data freshness and push outcome for `/healthz` and `/readyz`
+    pub health: HealthState,
+    /// Recent monitoring push attempts for `GET /diagnostics/monitoring`
+    pub push_log: PushLog,
+    /// Firmware version reported by the device at startup
+    pub firmware_version: Option<FirmwareVersion>,
+    /// ADC settings the device confirmed at startup
+    pub confirmed_settings: Option<ConfirmedSettings>,
+    /// Source the device is read from, replaced on a switch
+    pub data_source: Option<ActiveSource>,
+    /// Stored dark and full levels, from `processing.references.file`
+    pub references: ReferenceTable,
+    /// Shutter position, commanded or reported by the device
+    pub shutter: ShutterTracker,
+    /// When `POST /processing/pause` paused processing, until
+    /// `POST /processing/resume`; independent of the chamber state
+    pub processing_paused: Option<DateTime<Utc>>,
+    /// Thickness of the open layer, when `processing.thickness` is enabled
+    pub thickness: ThicknessEstimator,
 }
 
 impl Default for DeviceState {
@@ -24,11 +82,27 @@ impl Default for DeviceState {
             monitoring_api_url: None,
             spectrometer_id: None,
             vacuum_chamber_id: None,
+            registered_at: None,
             control_wavelength: 550.0,
-            is_running: false,
+            chamber: ChamberStateMachine::new(),
             current_material: "H".to_string(),
-            is_depositing: false,
+            layers: LayerLog::default(),
+            run_id: None,
             latest_reading: None,
+            dark_estimate: RollingDark::default(),
+            outlier_stats: ExclusionStats::default(),
+            debug_blocks: VecDeque::new(),
+            protocol: ProtocolDiagnostics::default(),
+            device_events: DeviceEventLog::default(),
+            health: HealthState::default(),
+            push_log: PushLog::default(),
+            firmware_version: None,
+            confirmed_settings: None,
+            data_source: None,
+            references: ReferenceTable::default(),
+            shutter: ShutterTracker::default(),
+            processing_paused: None,
+            thickness: ThicknessEstimator::default(),
         }
     }
 }
@@ -39,8 +113,32 @@ impl DeviceState {
         self.monitoring_api_url.is_some() && self.spectrometer_id.is_some()
     }
 
+    /// Current deposition layer number, 0 before the first run
+    pub fn layer(&self) -> u32 {
+        self.layers.current_index()
+    }
+
+    /// End the run: stop the chamber, close the open layer and clear the
+    /// run ID
+    pub fn stop_run(&mut self, at: DateTime<Utc>) -> Result<(), SpectrometerError> {
+        self.chamber.transition_to(ChamberState::Stopped)?;
+        self.layers.close(at);
+        self.run_id = None;
+        Ok(())
+    }
+
+    /// Whether measurements count towards the run and go to the sinks:
+    /// during deposition, unless processing is paused
     pub fn should_process_data(&self) -> bool {
-        self.is_running || self.is_depositing
+        self.chamber.is_depositing() && self.processing_paused.is_none()
+    }
+
+    /// Store a debug block, dropping the oldest beyond `MAX_DEBUG_BLOCKS`
+    pub fn record_debug_block(&mut self, block: DebugBlock) {
+        if self.debug_blocks.len() == MAX_DEBUG_BLOCKS {
+            self.debug_blocks.pop_front();
+        }
+        self.debug_blocks.push_back(block);
     }
 }
 
@@ -55,31 +153,95 @@ pub fn create_shared_state() -> SharedState {
 pub struct AppState {
     pub device: SharedState,
     pub config: SharedConfig,
-    pub broadcast_tx: broadcast::Sender<serde_json::Value>,
-    /// Channel for sending commands to the device (GAIN=, FADC=, COUNT=)
-    pub device_cmd_tx: mpsc::Sender<String>,
+    /// Recent processed measurements, used for backfill
+    pub history: SharedHistory,
+    /// Typed service events (measurements, validation, chamber changes)
+    pub events: EventBus,
+    /// Prometheus metrics served on `GET /metrics`
+    pub metrics: SharedMetrics,
+    /// Active outlier excluder, swapped by config reload
+    pub outlier_excluder: SharedExcluder,
+    /// SQLite measurement archive, when enabled with `--archive`
+    pub archive: Option<SharedArchive>,
+    /// Channel to the data source supervisor: device commands (GAIN=,
+    /// FADC=, COUNT=) and data source switches
+    pub device_cmd_tx: mpsc::Sender<SourceCommand>,
 }
 
 impl AppState {
     /// Send a device command (e.g., "GAIN=4")
     pub async fn send_device_command(&self, cmd: &str) -> Result<(), String> {
         self.device_cmd_tx
-            .send(cmd.to_string())
+            .send(SourceCommand::Device(cmd.to_string()))
             .await
             .map_err(|_| "Device command channel closed".to_string())
     }
+
+    /// Send a raw command to the data source, failing when the source does
+    /// not accept commands
+    pub async fn send_raw_command(&self, command: &str) -> Result<(), String> {
+        let (reply, result) = oneshot::channel();
+        self.device_cmd_tx
+            .send(SourceCommand::Passthrough {
+                command: command.to_string(),
+                reply,
+            })
+            .await
+            .map_err(|_| "Device command channel closed".to_string())?;
+        result
+            .await
+            .map_err(|_| "Data source supervisor stopped".to_string())?
+    }
+
+    /// Stop the data source and start one from `config` in its place.
+    /// Returns the new source's name.
+    pub async fn switch_source(&self, config: DataSourceConfig) -> Result<String, String> {
+        let (reply, result) = oneshot::channel();
+        self.device_cmd_tx
+            .send(SourceCommand::Switch { config, reply })
+            .await
+            .map_err(|_| "Device command channel closed".to_string())?;
+        result
+            .await
+            .map_err(|_| "Data source supervisor stopped".to_string())?
+    }
+
+    /// Default state for handler tests, with its config file in the
+    /// returned directory, no archive and a command channel nobody reads
+    #[cfg(test)]
+    pub(crate) fn for_test() -> (Self, tempfile::TempDir) {
+        use crate::processing::outlier::{OutlierMethod, create_shared_excluder};
+        use crate::service::calibration::create_shared_config;
+        use crate::service::history::create_shared_history;
+        use crate::service::metrics::create_shared_metrics;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (device_cmd_tx, _) = mpsc::channel(16);
+        let state = Self {
+            device: create_shared_state(),
+            config: create_shared_config(dir.path().join("cfg.toml")),
+            history: create_shared_history(16),
+            events: EventBus::default(),
+            metrics: create_shared_metrics(),
+            outlier_excluder: create_shared_excluder(OutlierMethod::default().create().unwrap()),
+            archive: None,
+            device_cmd_tx,
+        };
+        (state, dir)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::service::chamber::ChamberState;
 
     #[test]
     fn test_device_state_default() {
         let state = DeviceState::default();
         assert!(state.monitoring_api_url.is_none());
         assert_eq!(state.control_wavelength, 550.0);
-        assert!(!state.is_running);
+        assert!(!state.chamber.is_depositing());
         assert_eq!(state.current_material, "H");
     }
 
@@ -92,11 +254,36 @@ mod tests {
         assert!(state.is_registered());
     }
 
+    #[test]
+    fn test_debug_blocks_bounded() {
+        let mut state = DeviceState::default();
+        for i in 0..MAX_DEBUG_BLOCKS + 3 {
+            state.record_debug_block(DebugBlock {
+                timestamp: chrono::Utc::now(),
+                lines: vec![format!("block {i}")],
+                truncated: false,
+            });
+        }
+
+        assert_eq!(state.debug_blocks.len(), MAX_DEBUG_BLOCKS);
+        assert_eq!(state.debug_blocks[0].lines[0], "block 3");
+    }
+
     #[test]
     fn test_should_process_data() {
         let mut state = DeviceState::default();
         assert!(!state.should_process_data());
-        state.is_running = true;
+        state
+            .chamber
+            .transition_to(ChamberState::Preconditioning)
+            .unwrap();
+        assert!(!state.should_process_data());
+        state
+            .chamber
+            .transition_to(ChamberState::Depositing)
+            .unwrap();
         assert!(state.should_process_data());
+        state.processing_paused = Some(Utc::now());
+        assert!(!state.should_process_data());
     }
 }
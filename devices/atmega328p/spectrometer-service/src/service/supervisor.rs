@@ -0,0 +1,775 @@
+use chrono::Utc;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Duration, Instant, sleep_until};
+
+use crate::data_source::{DataSource, DataSourceConfig, SourceSetup};
+use crate::error::SpectrometerError;
+use crate::protocol::MeasurementCycle;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::state::SharedState;
+
+/// Delay before the first restart; doubled after each restart that
+/// delivered no cycle
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Whether a data source that ended is started again
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestartPolicy {
+    /// After any end, including playback reaching the end of its file
+    Always,
+    /// After a read error or a panic of the reader (default)
+    #[default]
+    OnFailure,
+    /// Never; the service keeps answering without data
+    Never,
+}
+
+impl RestartPolicy {
+    fn restarts(self, failed: bool) -> bool {
+        match self {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => failed,
+            RestartPolicy::Never => false,
+        }
+    }
+}
+
+/// Data source state published as `ServiceEvent::SourceStateChanged`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceState {
+    /// Started and delivering cycles
+    Running,
+    /// Ended by itself without an error, e.g. playback at the end of its file
+    Finished,
+    /// Ended with a read error or panic, or failed to restart
+    Failed,
+    /// Waiting out the backoff before the next start
+    Restarting,
+}
+
+/// Command to the supervised data source, sent through
+/// `AppState::device_cmd_tx`
+#[derive(Debug)]
+pub enum SourceCommand {
+    /// Line for the device, e.g. `GAIN=4`
+    Device(String),
+    /// Line for the device, answered with whether the source accepted it
+    Passthrough {
+        command: String,
+        reply: oneshot::Sender<Result<(), String>>,
+    },
+    /// Replace the data source; answered with the new source's name
+    Switch {
+        config: DataSourceConfig,
+        reply: oneshot::Sender<Result<String, String>>,
+    },
+    /// Stop and start the running source again, e.g. after a stall
+    Restart { reason: String },
+}
+
+/// Owns a started data source: forwards its cycles to the processing loop
+/// and device commands to it, and restarts it by `RestartPolicy` when its
+/// reader ends, so a failed reader does not leave a service without data.
+/// The source can be replaced at runtime with `SourceCommand::Switch`.
+pub struct SourceSupervisor {
+    source: Box<dyn DataSource>,
+    setup: SourceSetup,
+    policy: RestartPolicy,
+    max_backoff: Duration,
+    state: SharedState,
+    events: EventBus,
+    /// Told how the source ended once it is not restarted, for
+    /// `--exit-on-complete`
+    completion: Option<oneshot::Sender<SourceState>>,
+}
+
+impl SourceSupervisor {
+    pub fn new(
+        source: Box<dyn DataSource>,
+        setup: SourceSetup,
+        policy: RestartPolicy,
+        max_backoff: Duration,
+        state: SharedState,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            source,
+            setup,
+            policy,
+            max_backoff,
+            state,
+            events,
+            completion: None,
+        }
+    }
+
+    /// Stop supervising once the source ended and is not restarted, sending
+    /// `SourceState::Finished` or `SourceState::Failed` to `completion`
+    pub fn with_completion(mut self, completion: oneshot::Sender<SourceState>) -> Self {
+        self.completion = Some(completion);
+        self
+    }
+
+    /// Supervise the source started with `cycle_rx` until `cmd_rx` closes or
+    /// the processing loop is gone. A source that ended and is not restarted
+    /// leaves the supervisor idle, still able to switch to another source,
+    /// unless a completion was requested with `with_completion`.
+    pub async fn run(
+        mut self,
+        cycle_rx: mpsc::Receiver<MeasurementCycle>,
+        cycle_tx: mpsc::Sender<MeasurementCycle>,
+        mut cmd_rx: mpsc::Receiver<SourceCommand>,
+    ) {
+        let mut cycle_rx = Some(cycle_rx);
+        let mut restart_at: Option<Instant> = None;
+        let mut backoff = INITIAL_BACKOFF;
+        // Cycles delivered since the source was last started
+        let mut cycles = 0;
+        self.transition(SourceState::Running, None).await;
+
+        loop {
+            tokio::select! {
+                cycle = next_cycle(&mut cycle_rx) => {
+                    if let Some(cycle) = cycle {
+                        cycles += 1;
+                        if cycle_tx.send(cycle).await.is_err() {
+                            let _ = self.source.stop().await;
+                            return;
+                        }
+                        continue;
+                    }
+
+                    cycle_rx = None;
+                    let failed = self.source_ended().await;
+                    if !self.policy.restarts(failed) {
+                        tracing::warn!(
+                            "{} is not restarted ({:?} restart policy)",
+                            self.source.name(),
+                            self.policy
+                        );
+                        if !failed {
+                            self.playback_finished(cycles).await;
+                        }
+                        if let Some(completion) = self.completion.take() {
+                            let state = if failed {
+                                SourceState::Failed
+                            } else {
+                                SourceState::Finished
+                            };
+                            let _ = completion.send(state);
+                            return;
+                        }
+                        continue;
+                    }
+                    if cycles > 0 {
+                        backoff = INITIAL_BACKOFF;
+                    }
+                    restart_at = Some(self.schedule_restart(&mut backoff).await);
+                }
+                _ = wait_until(restart_at) => {
+                    restart_at = None;
+                    // Every start failure counts as a failure, so both
+                    // restarting policies keep trying
+                    match self.source.start().await {
+                        Ok(rx) => {
+                            cycle_rx = Some(rx);
+                            cycles = 0;
+                            self.started(None).await;
+                        }
+                        Err(e) => {
+                            self.transition(SourceState::Failed, Some(e.to_string())).await;
+                            restart_at = Some(self.schedule_restart(&mut backoff).await);
+                        }
+                    }
+                }
+                cmd = cmd_rx.recv() => match cmd {
+                    None => {
+                        // Shutting down
+                        let _ = self.source.stop().await;
+                        return;
+                    }
+                    Some(SourceCommand::Device(cmd)) => {
+                        if let Err(e) = self.source.send_command(&cmd).await {
+                            tracing::warn!("Device command '{cmd}' failed: {e}");
+                        }
+                    }
+                    Some(SourceCommand::Passthrough { command, reply }) => {
+                        let result = self.source.send_command(&command).await;
+                        let _ = reply.send(result.map_err(|e| e.to_string()));
+                    }
+                    Some(SourceCommand::Switch { config, reply }) => {
+                        let was_running = cycle_rx.is_some() || restart_at.is_some();
+                        let result = match self.switch(&config).await {
+                            Ok(rx) => {
+                                cycle_rx = Some(rx);
+                                Ok(self.source.name().to_string())
+                            }
+                            Err(e) => {
+                                // Back to the previous source, if it was
+                                // running or about to restart
+                                cycle_rx = None;
+                                if was_running {
+                                    match self.source.start().await {
+                                        Ok(rx) => {
+                                            cycle_rx = Some(rx);
+                                            self.started(None).await;
+                                        }
+                                        Err(e) => {
+                                            self.transition(
+                                                SourceState::Failed,
+                                                Some(e.to_string()),
+                                            )
+                                            .await;
+                                        }
+                                    }
+                                }
+                                Err(e.to_string())
+                            }
+                        };
+                        if cycle_rx.is_some() {
+                            restart_at = None;
+                        }
+                        backoff = INITIAL_BACKOFF;
+                        cycles = 0;
+                        let _ = reply.send(result);
+                    }
+                    Some(SourceCommand::Restart { reason }) => {
+                        // An ended source restarts by its policy, if at all
+                        if cycle_rx.take().is_none() {
+                            continue;
+                        }
+                        let name = self.source.name().to_string();
+                        tracing::warn!("Restarting {name}: {reason}");
+                        if let Err(e) = self.source.stop().await {
+                            tracing::warn!("{name} stopped with an error: {e}");
+                        }
+                        match self.source.start().await {
+                            Ok(rx) => {
+                                cycle_rx = Some(rx);
+                                cycles = 0;
+                                self.started(Some(format!("restarted: {reason}"))).await;
+                            }
+                            Err(e) => {
+                                self.transition(SourceState::Failed, Some(e.to_string())).await;
+                                restart_at = Some(self.schedule_restart(&mut backoff).await);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stop the source whose reader ended and publish how it ended. Returns
+    /// whether it failed.
+    async fn source_ended(&mut self) -> bool {
+        match self.source.stop().await {
+            Ok(()) => {
+                self.transition(SourceState::Finished, None).await;
+                false
+            }
+            Err(e) => {
+                self.transition(SourceState::Failed, Some(e.to_string()))
+                    .await;
+                true
+            }
+        }
+    }
+
+    /// Record and publish that the source reached the end of its input
+    async fn playback_finished(&self, cycles: u64) {
+        let at = Utc::now();
+        self.state.write().await.health.playback_finished_at = Some(at);
+        self.events.publish(ServiceEvent::PlaybackFinished {
+            at,
+            source: self.source.name().to_string(),
+            cycles,
+        });
+    }
+
+    /// Publish the coming restart and return when it is due, doubling the
+    /// backoff for the one after
+    async fn schedule_restart(&self, backoff: &mut Duration) -> Instant {
+        self.transition(
+            SourceState::Restarting,
+            Some(format!("in {:.0}s", backoff.as_secs_f64())),
+        )
+        .await;
+        let at = Instant::now() + *backoff;
+        *backoff = (*backoff * 2).min(self.max_backoff);
+        at
+    }
+
+    /// Stop the current source and start one from `config` in its place. The
+    /// old source is stopped first, as both may need the same port.
+    async fn switch(
+        &mut self,
+        config: &DataSourceConfig,
+    ) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError> {
+        let previous = self.source.name().to_string();
+        if let Err(e) = self.source.stop().await {
+            tracing::warn!("{previous} stopped with an error: {e}");
+        }
+
+        let mut source = self.setup.create_source(config);
+        match source.start().await {
+            Ok(rx) => {
+                self.source = source;
+                self.state.write().await.data_source =
+                    Some(config.active_source(self.source.name()));
+                self.started(Some(format!("switched from {previous}")))
+                    .await;
+                Ok(rx)
+            }
+            Err(e) => {
+                tracing::error!("Switching to {} failed: {e}", source.name());
+                Err(e)
+            }
+        }
+    }
+
+    async fn started(&self, detail: Option<String>) {
+        {
+            let mut device = self.state.write().await;
+            device.firmware_version = self.source.firmware_version();
+            device.confirmed_settings = self.source.confirmed_settings();
+        }
+        self.transition(SourceState::Running, detail).await;
+    }
+
+    /// Log and publish a state change, keeping the health state in step
+    async fn transition(&self, state: SourceState, detail: Option<String>) {
+        let source = self.source.name().to_string();
+        match (&detail, state) {
+            (Some(detail), SourceState::Failed) => {
+                tracing::error!("Data source {source} failed: {detail}")
+            }
+            (Some(detail), _) => tracing::info!("Data source {source} {state:?}: {detail}"),
+            (None, _) => tracing::info!("Data source {source} {state:?}"),
+        }
+
+        {
+            let mut device = self.state.write().await;
+            device.health.source_active = state == SourceState::Running;
+            if state == SourceState::Running {
+                device.health.playback_finished_at = None;
+                device.health.source_started_at = Some(Utc::now());
+            }
+        }
+        self.events.publish(ServiceEvent::SourceStateChanged {
+            at: Utc::now(),
+            source,
+            state,
+            detail,
+        });
+    }
+}
+
+/// Next cycle of the running source; never ready while none runs
+async fn next_cycle(
+    cycle_rx: &mut Option<mpsc::Receiver<MeasurementCycle>>,
+) -> Option<MeasurementCycle> {
+    match cycle_rx {
+        Some(cycle_rx) => cycle_rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep until `at`; never ready without a restart scheduled
+async fn wait_until(at: Option<Instant>) {
+    match at {
+        Some(at) => sleep_until(at).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+    use tokio::sync::broadcast;
+    use tokio::task::JoinHandle;
+
+    use super::*;
+    use crate::protocol::{CycleSettings, ProtocolKind, SeriesData};
+    use crate::service::state::create_shared_state;
+
+    /// Source delivering one cycle per start, its reader then ending with
+    /// `fail`
+    struct OneShotSource {
+        starts: Arc<AtomicUsize>,
+        fail: bool,
+        reader: Option<JoinHandle<()>>,
+    }
+
+    #[async_trait]
+    impl DataSource for OneShotSource {
+        async fn start(&mut self) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError> {
+            self.starts.fetch_add(1, Ordering::SeqCst);
+            let (tx, rx) = mpsc::channel(1);
+            self.reader = Some(tokio::spawn(async move {
+                let series = SeriesData::new(vec![1, 2]);
+                let cycle = MeasurementCycle::with_timestamp(
+                    Utc::now(),
+                    series.clone(),
+                    series.clone(),
+                    series,
+                );
+                let _ = tx.send(cycle).await;
+            }));
+            Ok(rx)
+        }
+
+        async fn stop(&mut self) -> Result<(), SpectrometerError> {
+            if let Some(reader) = self.reader.take() {
+                let _ = reader.await;
+                if self.fail {
+                    return Err(SpectrometerError::DataSource("read error".into()));
+                }
+            }
+            Ok(())
+        }
+
+        fn is_active(&self) -> bool {
+            self.reader.is_some()
+        }
+
+        async fn send_command(&mut self, _command: &str) -> Result<(), SpectrometerError> {
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "one-shot"
+        }
+    }
+
+    struct Supervised {
+        starts: Arc<AtomicUsize>,
+        state: SharedState,
+        cycle_rx: mpsc::Receiver<MeasurementCycle>,
+        cmd_tx: mpsc::Sender<SourceCommand>,
+        event_rx: broadcast::Receiver<ServiceEvent>,
+    }
+
+    impl Supervised {
+        /// Next forwarded cycle, `None` once none came for a while
+        async fn next_cycle(&mut self) -> Option<MeasurementCycle> {
+            tokio::time::timeout(Duration::from_secs(10), self.cycle_rx.recv())
+                .await
+                .ok()
+                .flatten()
+        }
+    }
+
+    /// Source that delivers nothing until stopped, like a device that
+    /// went quiet
+    struct StalledSource {
+        starts: Arc<AtomicUsize>,
+        tx: Option<mpsc::Sender<MeasurementCycle>>,
+    }
+
+    #[async_trait]
+    impl DataSource for StalledSource {
+        async fn start(&mut self) -> Result<mpsc::Receiver<MeasurementCycle>, SpectrometerError> {
+            self.starts.fetch_add(1, Ordering::SeqCst);
+            let (tx, rx) = mpsc::channel(1);
+            self.tx = Some(tx);
+            Ok(rx)
+        }
+
+        async fn stop(&mut self) -> Result<(), SpectrometerError> {
+            self.tx = None;
+            Ok(())
+        }
+
+        fn is_active(&self) -> bool {
+            self.tx.is_some()
+        }
+
+        async fn send_command(&mut self, _command: &str) -> Result<(), SpectrometerError> {
+            Ok(())
+        }
+
+        fn name(&self) -> &str {
+            "stalled"
+        }
+    }
+
+    fn source_setup() -> SourceSetup {
+        let (log_tx, _) = mpsc::channel(1);
+        let (debug_tx, _) = mpsc::channel(1);
+        let (protocol_tx, _) = mpsc::channel(1);
+        let (event_tx, _) = mpsc::channel(1);
+        SourceSetup {
+            log_tx,
+            debug_tx,
+            protocol_tx,
+            event_tx,
+            cycle_settings: CycleSettings::default(),
+            protocol: ProtocolKind::Atmega,
+        }
+    }
+
+    /// Spawn a supervisor of a `OneShotSource`
+    async fn spawn_supervisor(policy: RestartPolicy, fail: bool) -> Supervised {
+        spawn_supervisor_with(policy, fail, None).await
+    }
+
+    async fn spawn_supervisor_with(
+        policy: RestartPolicy,
+        fail: bool,
+        completion: Option<oneshot::Sender<SourceState>>,
+    ) -> Supervised {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let mut source = OneShotSource {
+            starts: starts.clone(),
+            fail,
+            reader: None,
+        };
+        let first_rx = source.start().await.unwrap();
+        let setup = source_setup();
+
+        let events = EventBus::default();
+        let event_rx = events.subscribe();
+        let state = create_shared_state();
+        let mut supervisor = SourceSupervisor::new(
+            Box::new(source),
+            setup,
+            policy,
+            Duration::from_millis(20),
+            state.clone(),
+            events,
+        );
+        if let Some(completion) = completion {
+            supervisor = supervisor.with_completion(completion);
+        }
+        let (cycle_tx, cycle_rx) = mpsc::channel(8);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+        tokio::spawn(supervisor.run(first_rx, cycle_tx, cmd_rx));
+
+        Supervised {
+            starts,
+            state,
+            cycle_rx,
+            cmd_tx,
+            event_rx,
+        }
+    }
+
+    /// Run a supervisor and collect the states it publishes until it gives
+    /// up or `limit` cycles were forwarded
+    async fn supervise(
+        policy: RestartPolicy,
+        fail: bool,
+        limit: usize,
+    ) -> (usize, Vec<SourceState>) {
+        let mut supervised = spawn_supervisor(policy, fail).await;
+
+        let mut cycles = 0;
+        while cycles < limit && supervised.next_cycle().await.is_some() {
+            cycles += 1;
+        }
+        let Supervised {
+            starts,
+            cmd_tx,
+            mut event_rx,
+            ..
+        } = supervised;
+        drop(cmd_tx);
+
+        let mut states = Vec::new();
+        loop {
+            match event_rx.try_recv() {
+                Ok(ServiceEvent::SourceStateChanged { state, .. }) => states.push(state),
+                Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => {}
+                Err(_) => break,
+            }
+        }
+        assert_eq!(starts.load(Ordering::SeqCst), cycles);
+        (cycles, states)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_restarts_after_failure() {
+        let (cycles, states) = supervise(RestartPolicy::OnFailure, true, 3).await;
+        assert_eq!(cycles, 3);
+        assert_eq!(
+            states[..4],
+            [
+                SourceState::Running,
+                SourceState::Failed,
+                SourceState::Restarting,
+                SourceState::Running
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_finished_source_not_restarted_on_failure_policy() {
+        let (cycles, states) = supervise(RestartPolicy::OnFailure, false, 3).await;
+        assert_eq!(cycles, 1);
+        assert_eq!(states, [SourceState::Running, SourceState::Finished]);
+
+        let (cycles, _) = supervise(RestartPolicy::Always, false, 3).await;
+        assert_eq!(cycles, 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_never_policy() {
+        let (cycles, states) = supervise(RestartPolicy::Never, true, 3).await;
+        assert_eq!(cycles, 1);
+        assert_eq!(states, [SourceState::Running, SourceState::Failed]);
+    }
+
+    async fn switch(supervised: &Supervised, config: DataSourceConfig) -> Result<String, String> {
+        let (reply, result) = oneshot::channel();
+        supervised
+            .cmd_tx
+            .send(SourceCommand::Switch { config, reply })
+            .await
+            .unwrap();
+        result.await.unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_switch_source() {
+        // Finished and not restarted, the supervisor still switches
+        let mut supervised = spawn_supervisor(RestartPolicy::Never, false).await;
+        assert!(supervised.next_cycle().await.is_some());
+
+        let playback = DataSourceConfig::Playback {
+            log_file: "fixtures/sample_log.txt".into(),
+            speed_multiplier: 100.0,
+            loop_playback: false,
+            cycle_interval_ms: 100,
+            time_range: Default::default(),
+            follow: false,
+        };
+        let source = switch(&supervised, playback).await.unwrap();
+        assert_eq!(source, "fixtures/sample_log.txt");
+
+        let mut cycles = 0;
+        while supervised.next_cycle().await.is_some() {
+            cycles += 1;
+        }
+        assert_eq!(cycles, 5);
+        assert_eq!(supervised.starts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_switch_keeps_previous_source() {
+        let mut supervised = spawn_supervisor(RestartPolicy::Always, false).await;
+        assert!(supervised.next_cycle().await.is_some());
+
+        let serial = DataSourceConfig::Serial {
+            port: "/dev/does-not-exist".to_string(),
+            baud_rate: 38400,
+            gain: 1,
+            fadc: 1000.0,
+            count: 1,
+            log_file: None,
+            allow_firmware_mismatch: false,
+            line: Default::default(),
+        };
+        assert!(switch(&supervised, serial).await.is_err());
+
+        // The one-shot source is back and restarting
+        let before = supervised.starts.load(Ordering::SeqCst);
+        assert!(supervised.next_cycle().await.is_some());
+        assert!(supervised.next_cycle().await.is_some());
+        assert!(supervised.starts.load(Ordering::SeqCst) > before);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_restart_command() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let mut source = StalledSource {
+            starts: starts.clone(),
+            tx: None,
+        };
+        let first_rx = source.start().await.unwrap();
+        let events = EventBus::default();
+        let mut event_rx = events.subscribe();
+        let supervisor = SourceSupervisor::new(
+            Box::new(source),
+            source_setup(),
+            RestartPolicy::Never,
+            Duration::from_millis(20),
+            create_shared_state(),
+            events,
+        );
+        let (cycle_tx, _cycle_rx) = mpsc::channel(1);
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+        tokio::spawn(supervisor.run(first_rx, cycle_tx, cmd_rx));
+
+        cmd_tx
+            .send(SourceCommand::Restart {
+                reason: "no cycle for 30s".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let mut details = Vec::new();
+        while details.len() < 2 {
+            if let ServiceEvent::SourceStateChanged {
+                state: SourceState::Running,
+                detail,
+                ..
+            } = event_rx.recv().await.unwrap()
+            {
+                details.push(detail);
+            }
+        }
+        assert_eq!(
+            details,
+            [None, Some("restarted: no cycle for 30s".to_string())]
+        );
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_completion_after_playback_finished() {
+        let (completion_tx, completion_rx) = oneshot::channel();
+        let mut supervised =
+            spawn_supervisor_with(RestartPolicy::OnFailure, false, Some(completion_tx)).await;
+
+        assert!(supervised.next_cycle().await.is_some());
+        assert_eq!(completion_rx.await.unwrap(), SourceState::Finished);
+        // The supervisor is gone, ending the processing loop's input
+        assert!(supervised.cycle_rx.recv().await.is_none());
+
+        let health = supervised.state.read().await.health.clone();
+        assert!(!health.source_active);
+        assert!(health.playback_finished_at.is_some());
+
+        let finished = std::iter::from_fn(|| supervised.event_rx.try_recv().ok()).find_map(
+            |event| match event {
+                ServiceEvent::PlaybackFinished { cycles, .. } => Some(cycles),
+                _ => None,
+            },
+        );
+        assert_eq!(finished, Some(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_completion_after_failure() {
+        let (completion_tx, completion_rx) = oneshot::channel();
+        let supervised =
+            spawn_supervisor_with(RestartPolicy::Never, true, Some(completion_tx)).await;
+
+        assert_eq!(completion_rx.await.unwrap(), SourceState::Failed);
+        assert!(
+            supervised
+                .state
+                .read()
+                .await
+                .health
+                .playback_finished_at
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::protocol::ProcessedMeasurement;
+use crate::service::calibration::SharedConfig;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::state::{DeviceState, SharedState};
+
+/// Time allowed for the webhook request
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// When a layer is complete: the calibrated reading crossing `reading`, or
+/// the thickness estimate passing `turning_points`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TerminationTarget {
+    /// 1-based layer number within the run
+    pub layer: u32,
+    /// Calibrated reading (%) that ends the layer, reached from either side
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reading: Option<f64>,
+    /// Turning points that end the layer; needs `processing.thickness`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub turning_points: Option<u32>,
+}
+
+/// Local layer cut-off from the `[termination]` config section; re-read on
+/// every measurement, so config reload applies changes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TerminationSettings {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TerminationTarget>,
+    /// Stop deposition when a target is reached
+    #[serde(default)]
+    pub auto_stop: bool,
+    /// Endpoint the `target_reached` event is posted to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+}
+
+impl TerminationSettings {
+    /// Check the targets and webhook; turning-point targets need the
+    /// thickness estimate to be enabled
+    pub fn validate(&self, thickness_enabled: bool) -> Result<(), String> {
+        for (position, target) in self.targets.iter().enumerate() {
+            let layer = target.layer;
+            if layer == 0 {
+                return Err("termination: layer numbers start at 1".to_string());
+            }
+            if self.targets[..position]
+                .iter()
+                .any(|other| other.layer == layer)
+            {
+                return Err(format!("termination: layer {layer} has two targets"));
+            }
+            match (target.reading, target.turning_points) {
+                (Some(reading), None) if reading.is_finite() => {}
+                (None, Some(turning_points)) if turning_points > 0 => {
+                    if !thickness_enabled {
+                        return Err(format!(
+                            "termination: layer {layer} counts turning points, \
+                             which needs processing.thickness enabled"
+                        ));
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "termination: layer {layer} needs either a reading or \
+                         a positive turning_points"
+                    ));
+                }
+            }
+        }
+        if let Some(url) = &self.webhook
+            && !url.starts_with("http://")
+            && !url.starts_with("https://")
+        {
+            return Err(format!("termination: invalid webhook URL {url:?}"));
+        }
+        Ok(())
+    }
+
+    /// Target of layer `layer`
+    pub fn target(&self, layer: u32) -> Option<&TerminationTarget> {
+        self.targets.iter().find(|target| target.layer == layer)
+    }
+}
+
+/// Progress of the open layer towards its target
+#[derive(Debug, Default)]
+pub struct TerminationTracker {
+    /// Layer watched, by number and start; another layer starts over
+    layer: Option<(u32, DateTime<Utc>)>,
+    /// Previous valid reading of the layer
+    previous: Option<f64>,
+    reached: bool,
+}
+
+impl TerminationTracker {
+    /// Check a valid measurement of the layer numbered `layer` and started
+    /// at `started_at` against `target`. True once, for the measurement
+    /// that reaches it.
+    pub fn check(
+        &mut self,
+        layer: u32,
+        started_at: DateTime<Utc>,
+        target: &TerminationTarget,
+        measurement: &ProcessedMeasurement,
+    ) -> bool {
+        if self.layer != Some((layer, started_at)) {
+            *self = Self {
+                layer: Some((layer, started_at)),
+                ..Self::default()
+            };
+        }
+        let reading = measurement.calibrated_reading;
+        let previous = self.previous.replace(reading);
+        if self.reached {
+            return false;
+        }
+
+        self.reached = match (target.reading, target.turning_points) {
+            (Some(target), _) => {
+                reading == target
+                    || previous.is_some_and(|p| (p - target) * (reading - target) < 0.0)
+            }
+            (None, Some(turning_points)) => measurement
+                .thickness
+                .is_some_and(|thickness| thickness.turning_points >= turning_points),
+            (None, None) => false,
+        };
+        self.reached
+    }
+}
+
+/// Check a measurement against the open layer's target. Returns the
+/// `TargetReached` event, after stopping deposition when configured, or
+/// `None` while the target is not reached.
+fn check_target(
+    tracker: &mut TerminationTracker,
+    settings: &TerminationSettings,
+    device: &mut DeviceState,
+    measurement: &ProcessedMeasurement,
+) -> Option<ServiceEvent> {
+    if !measurement.is_valid || !device.should_process_data() {
+        return None;
+    }
+    let layer = device.layers.records().last()?;
+    let (index, started_at) = (layer.index, layer.started_at);
+    let target = settings.target(index)?;
+    if !tracker.check(index, started_at, target, measurement) {
+        return None;
+    }
+
+    let at = Utc::now();
+    let stopping = settings.auto_stop
+        && device
+            .stop_run(at)
+            .inspect_err(|e| tracing::warn!("Failed to stop deposition at target: {e}"))
+            .is_ok();
+    tracing::info!(
+        layer = index,
+        reading = measurement.calibrated_reading,
+        stopping,
+        "Layer target reached"
+    );
+    Some(ServiceEvent::TargetReached {
+        at,
+        layer: index,
+        target: *target,
+        reading: measurement.calibrated_reading,
+        turning_points: measurement
+            .thickness
+            .map(|thickness| thickness.turning_points),
+        stopping,
+    })
+}
+
+/// Watch measurements for the open layer reaching its target, so the layer
+/// can be cut off locally while the monitoring server is unreachable.
+/// Publishes `TargetReached` (and `DepositionStopped` when stopping) and
+/// posts the event to the configured webhook.
+pub async fn run_termination(state: SharedState, config: SharedConfig, events: EventBus) {
+    let client = Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .expect("Failed to create HTTP client");
+    let mut tracker = TerminationTracker::default();
+    let mut receiver = events.subscribe();
+
+    loop {
+        let measurement = match receiver.recv().await {
+            Ok(ServiceEvent::Measurement(measurement)) => measurement,
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        let settings = config.read().await.config.termination.clone();
+        if settings.targets.is_empty() {
+            continue;
+        }
+        let Some(event) = check_target(
+            &mut tracker,
+            &settings,
+            &mut *state.write().await,
+            &measurement,
+        ) else {
+            continue;
+        };
+
+        let stopping = matches!(event, ServiceEvent::TargetReached { stopping: true, .. });
+        events.publish(event.clone());
+        if stopping {
+            events.publish(ServiceEvent::DepositionStopped { at: Utc::now() });
+        }
+        if let Some(url) = &settings.webhook {
+            let result = client
+                .post(url)
+                .json(&event)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                tracing::warn!("Termination webhook {url} failed: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ThicknessEstimate;
+    use crate::service::chamber::ChamberState;
+
+    fn target(reading: Option<f64>, turning_points: Option<u32>) -> TerminationTarget {
+        TerminationTarget {
+            layer: 1,
+            reading,
+            turning_points,
+        }
+    }
+
+    fn measurement(reading: f64) -> ProcessedMeasurement {
+        ProcessedMeasurement::new(Utc::now(), 100.0, 1000.0, 500.0, reading)
+    }
+
+    fn depositing() -> DeviceState {
+        let mut device = DeviceState::default();
+        device
+            .chamber
+            .transition_to(ChamberState::Depositing)
+            .unwrap();
+        device.layers.start_run("H", Utc::now());
+        device.run_id = Some("run-1".to_string());
+        device
+    }
+
+    #[test]
+    fn test_reading_crossed_from_either_side() {
+        let start = Utc::now();
+        let falling = target(Some(45.0), None);
+        let mut tracker = TerminationTracker::default();
+        let reached: Vec<bool> = [60.0, 50.0, 44.0, 40.0]
+            .into_iter()
+            .map(|reading| tracker.check(1, start, &falling, &measurement(reading)))
+            .collect();
+        assert_eq!(reached, [false, false, true, false]);
+
+        // Next layer, rising
+        let rising = TerminationTarget {
+            layer: 2,
+            ..target(Some(70.0), None)
+        };
+        assert!(!tracker.check(2, start, &rising, &measurement(65.0)));
+        assert!(tracker.check(2, start, &rising, &measurement(71.0)));
+    }
+
+    #[test]
+    fn test_turning_points_target() {
+        let start = Utc::now();
+        let goal = target(None, Some(2));
+        let mut tracker = TerminationTracker::default();
+        let mut with_turning_points = |turning_points| {
+            let mut measurement = measurement(50.0);
+            measurement.thickness = Some(ThicknessEstimate {
+                layer: 1,
+                turning_points,
+                optical_nm: 0.0,
+                physical_nm: None,
+            });
+            tracker.check(1, start, &goal, &measurement)
+        };
+        assert!(!with_turning_points(1));
+        assert!(with_turning_points(2));
+        assert!(!with_turning_points(3));
+    }
+
+    #[test]
+    fn test_auto_stop() {
+        let settings = TerminationSettings {
+            targets: vec![target(Some(45.0), None)],
+            auto_stop: true,
+            webhook: None,
+        };
+        let mut device = depositing();
+        let mut tracker = TerminationTracker::default();
+
+        assert!(check_target(&mut tracker, &settings, &mut device, &measurement(50.0)).is_none());
+        let event = check_target(&mut tracker, &settings, &mut device, &measurement(40.0));
+        assert!(matches!(
+            event,
+            Some(ServiceEvent::TargetReached {
+                layer: 1,
+                stopping: true,
+                ..
+            })
+        ));
+        assert_eq!(device.chamber.state(), ChamberState::Stopped);
+        assert!(device.run_id.is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let settings = |targets| TerminationSettings {
+            targets,
+            ..TerminationSettings::default()
+        };
+        assert!(
+            settings(vec![target(Some(45.0), None)])
+                .validate(false)
+                .is_ok()
+        );
+        assert!(settings(vec![target(None, Some(3))]).validate(true).is_ok());
+        // Turning points without the thickness estimate
+        assert!(
+            settings(vec![target(None, Some(3))])
+                .validate(false)
+                .is_err()
+        );
+        for targets in [
+            vec![target(None, None)],
+            vec![target(Some(45.0), Some(3))],
+            vec![target(Some(45.0), None), target(Some(50.0), None)],
+            vec![TerminationTarget {
+                layer: 0,
+                ..target(Some(45.0), None)
+            }],
+        ] {
+            assert!(settings(targets).validate(true).is_err());
+        }
+        let bad_webhook = TerminationSettings {
+            webhook: Some("ftp://x".to_string()),
+            ..TerminationSettings::default()
+        };
+        assert!(bad_webhook.validate(false).is_err());
+    }
+}
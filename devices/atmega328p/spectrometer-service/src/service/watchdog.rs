@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::service::calibration::SharedConfig;
+use crate::service::events::{EventBus, ServiceEvent};
+use crate::service::health::HealthState;
+use crate::service::state::SharedState;
+use crate::service::supervisor::SourceCommand;
+
+/// How often the watchdog looks for a stall
+const WATCHDOG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default time without a processed cycle before the loop counts as stalled
+pub const DEFAULT_STALL_AFTER_SECS: u64 = 30;
+
+/// Stall detection from the `[watchdog]` config section; re-read on every
+/// check, so config reload applies changes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchdogSettings {
+    /// Seconds without a processed cycle, while the data source is active,
+    /// before the loop counts as stalled; 0 disables the watchdog
+    #[serde(default = "default_stall_after_secs")]
+    pub stall_after_secs: u64,
+    /// Restart the data source on a stall
+    #[serde(default)]
+    pub restart_source: bool,
+}
+
+fn default_stall_after_secs() -> u64 {
+    DEFAULT_STALL_AFTER_SECS
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            stall_after_secs: DEFAULT_STALL_AFTER_SECS,
+            restart_source: false,
+        }
+    }
+}
+
+/// Time since the last processed cycle, or since the data source started
+/// if none arrived since; `None` while the source is not active
+pub fn stalled_for(health: &HealthState, now: DateTime<Utc>) -> Option<chrono::Duration> {
+    if !health.source_active {
+        return None;
+    }
+    let since = health
+        .last_cycle_at
+        .max(health.source_started_at)
+        .unwrap_or(health.started_at);
+    Some(now - since)
+}
+
+/// Watch for a processing loop that stopped receiving cycles while its data
+/// source claims to be running. A stall is logged and published once, and
+/// the source restarted when `watchdog.restart_source` is set.
+pub async fn run_watchdog(
+    state: SharedState,
+    config: SharedConfig,
+    events: EventBus,
+    cmd_tx: mpsc::Sender<SourceCommand>,
+) {
+    let mut interval = tokio::time::interval(WATCHDOG_CHECK_INTERVAL);
+    // Last cycle seen when the current stall was detected
+    let mut stalled_at_cycle: Option<Option<DateTime<Utc>>> = None;
+
+    loop {
+        interval.tick().await;
+        let settings = config.read().await.config.watchdog.clone();
+        let now = Utc::now();
+        let (stalled, last_cycle_at, source) = {
+            let device = state.read().await;
+            (
+                stalled_for(&device.health, now),
+                device.health.last_cycle_at,
+                device
+                    .data_source
+                    .as_ref()
+                    .map(|source| source.name.clone())
+                    .unwrap_or_default(),
+            )
+        };
+
+        let stalled = stalled.filter(|stalled| {
+            settings.stall_after_secs > 0
+                && stalled.num_seconds() >= settings.stall_after_secs as i64
+        });
+        match (stalled, stalled_at_cycle) {
+            (Some(stalled), None) => {
+                stalled_at_cycle = Some(last_cycle_at);
+                let stalled_secs = stalled.num_seconds().unsigned_abs();
+                tracing::warn!(
+                    source = %source,
+                    stalled_secs,
+                    last_cycle_at = ?last_cycle_at,
+                    restarting = settings.restart_source,
+                    "Processing loop stalled: no cycle while the data source is active"
+                );
+                events.publish(ServiceEvent::ProcessingStalled {
+                    at: now,
+                    source,
+                    stalled_secs,
+                    restarting: settings.restart_source,
+                });
+                if settings.restart_source
+                    && cmd_tx
+                        .send(SourceCommand::Restart {
+                            reason: format!("no cycle for {stalled_secs}s"),
+                        })
+                        .await
+                        .is_err()
+                {
+                    return;
+                }
+            }
+            (None, Some(previous)) => {
+                stalled_at_cycle = None;
+                if last_cycle_at != previous {
+                    tracing::info!(source = %source, "Processing loop resumed");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::calibration::create_shared_config;
+    use crate::service::state::create_shared_state;
+
+    #[test]
+    fn test_stalled_for() {
+        let now = Utc::now();
+        let mut health = HealthState::default();
+        assert!(stalled_for(&health, now).is_none());
+
+        health.source_active = true;
+        health.source_started_at = Some(now - chrono::Duration::seconds(40));
+        assert_eq!(stalled_for(&health, now).unwrap().num_seconds(), 40);
+
+        health.record_cycle(now - chrono::Duration::seconds(5));
+        assert_eq!(stalled_for(&health, now).unwrap().num_seconds(), 5);
+
+        // A restart counts as activity even before its first cycle
+        health.source_started_at = Some(now - chrono::Duration::seconds(2));
+        assert_eq!(stalled_for(&health, now).unwrap().num_seconds(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stall_published_and_source_restarted() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = create_shared_config(dir.path().join("cfg.toml"));
+        config.write().await.config.watchdog = WatchdogSettings {
+            stall_after_secs: 1,
+            restart_source: true,
+        };
+        let state = create_shared_state();
+        {
+            let mut device = state.write().await;
+            device.health.source_active = true;
+            device.health.source_started_at = Some(Utc::now() - chrono::Duration::seconds(10));
+        }
+        let events = EventBus::default();
+        let mut rx = events.subscribe();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(4);
+        let watchdog = tokio::spawn(run_watchdog(state, config, events, cmd_tx));
+
+        match rx.recv().await.unwrap() {
+            ServiceEvent::ProcessingStalled {
+                stalled_secs,
+                restarting,
+                ..
+            } => {
+                assert!(stalled_secs >= 10);
+                assert!(restarting);
+            }
+            other => panic!("Unexpected event: {other:?}"),
+        }
+        assert!(matches!(
+            cmd_rx.recv().await,
+            Some(SourceCommand::Restart { .. })
+        ));
+        watchdog.abort();
+    }
+}
@@ -0,0 +1,807 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags, Row, params, params_from_iter};
+use serde::Serialize;
+
+use crate::error::SpectrometerError;
+use crate::protocol::{MeasurementCycle, ProcessedMeasurement, RawAdcValue, ValidationCategory};
+
+/// Rows returned by a measurement query when no limit is given
+pub const DEFAULT_QUERY_LIMIT: usize = 10_000;
+
+/// Upper bound on rows returned by a single measurement query
+pub const MAX_QUERY_LIMIT: usize = 100_000;
+
+/// Rows deleted per statement when pruning, so appends wait for one batch
+/// rather than the whole prune
+const PRUNE_BATCH: usize = 10_000;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS measurements (
+    id INTEGER PRIMARY KEY,
+    run_id TEXT,
+    timestamp_us INTEGER NOT NULL,
+    material TEXT NOT NULL,
+    layer INTEGER NOT NULL,
+    dark_mean REAL NOT NULL,
+    full_mean REAL NOT NULL,
+    sample_mean REAL NOT NULL,
+    calibrated_reading REAL NOT NULL,
+    is_valid INTEGER NOT NULL,
+    validation_error TEXT,
+    dark_raw TEXT,
+    full_raw TEXT,
+    sample_raw TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_measurements_timestamp ON measurements (timestamp_us);
+CREATE INDEX IF NOT EXISTS idx_measurements_run ON measurements (run_id, timestamp_us);
+";
+
+/// Schema changes applied after `SCHEMA`, tracked in `PRAGMA user_version`.
+/// Append only; entry `n` upgrades a database from version `n` to `n + 1`.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE measurements ADD COLUMN validation_category TEXT;",
+    "ALTER TABLE measurements ADD COLUMN statistics TEXT;",
+    "ALTER TABLE measurements ADD COLUMN cycle_id INTEGER;",
+    "ALTER TABLE measurements ADD COLUMN low_snr INTEGER;",
+    "ALTER TABLE measurements ADD COLUMN physical TEXT;",
+    "ALTER TABLE measurements ADD COLUMN transitional INTEGER;",
+    "ALTER TABLE measurements ADD COLUMN wavelength REAL;",
+];
+
+const SELECT_COLUMNS: &str = "run_id, timestamp_us, material, layer, dark_mean, full_mean, \
+     sample_mean, calibrated_reading, is_valid, validation_error, dark_raw, full_raw, sample_raw, \
+     validation_category, statistics, cycle_id, low_snr, physical, transitional, wavelength";
+
+/// Number of columns in `SELECT_COLUMNS`
+const SELECT_COLUMN_COUNT: usize = column_count(SELECT_COLUMNS);
+
+/// Number of entries in a comma-separated column list
+const fn column_count(columns: &str) -> usize {
+    let bytes = columns.as_bytes();
+    let mut count = 1;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b',' {
+            count += 1;
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Chamber context a measurement was taken in
+#[derive(Debug, Clone, Default)]
+pub struct RunTags {
+    /// Deposition run, `None` outside a run
+    pub run_id: Option<String>,
+    pub material: String,
+    pub layer: u32,
+}
+
+/// Raw ADC series of an archived cycle, after series remapping
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RawSeries {
+    pub dark: Vec<RawAdcValue>,
+    pub full: Vec<RawAdcValue>,
+    pub sample: Vec<RawAdcValue>,
+}
+
+/// A measurement read back from the archive
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchivedMeasurement {
+    pub run_id: Option<String>,
+    pub material: String,
+    pub layer: u32,
+    #[serde(flatten)]
+    pub measurement: ProcessedMeasurement,
+    /// Present when the archive was written with raw series enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<RawSeries>,
+}
+
+/// Selection for a measurement query. Bounds are inclusive.
+#[derive(Debug, Clone, Default)]
+pub struct MeasurementFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub run_id: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Aggregate of one archived deposition run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub run_id: String,
+    pub first_measurement: DateTime<Utc>,
+    pub last_measurement: DateTime<Utc>,
+    pub measurements: u64,
+    pub invalid_measurements: u64,
+    pub layers: u32,
+}
+
+/// Boundaries of one layer of an archived run, from its tagged measurements
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchivedLayer {
+    pub index: u32,
+    pub material: String,
+    pub first_measurement: DateTime<Utc>,
+    pub last_measurement: DateTime<Utc>,
+    pub measurements: u64,
+    pub invalid_measurements: u64,
+}
+
+/// Where a `page` of measurements ended, to continue after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageCursor {
+    timestamp_us: i64,
+    id: i64,
+}
+
+/// Append-only SQLite archive of processed measurements. The database is
+/// in WAL mode and queried through its own connection, so a long query does
+/// not hold up appends.
+pub struct MeasurementArchive {
+    writer: Mutex<Connection>,
+    reader: Mutex<Connection>,
+    store_raw: bool,
+}
+
+pub type SharedArchive = Arc<MeasurementArchive>;
+
+impl MeasurementArchive {
+    /// Open or create the archive database at `path`.
+    /// With `store_raw`, the raw ADC series are kept next to each measurement.
+    pub fn open(path: &Path, store_raw: bool) -> Result<Self, SpectrometerError> {
+        let writer = Connection::open(path)?;
+        writer.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+        writer.execute_batch(SCHEMA)?;
+        migrate(&writer)?;
+        let reader = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+
+        tracing::info!("Archiving measurements to {}", path.display());
+
+        Ok(Self {
+            writer: Mutex::new(writer),
+            reader: Mutex::new(reader),
+            store_raw,
+        })
+    }
+
+    /// Run `op` on the blocking thread pool, so SQLite I/O does not hold up
+    /// a runtime worker
+    pub async fn blocking<T, F>(self: &Arc<Self>, op: F) -> Result<T, SpectrometerError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Self) -> Result<T, SpectrometerError> + Send + 'static,
+    {
+        let archive = Arc::clone(self);
+        tokio::task::spawn_blocking(move || op(&archive))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    /// Append a measurement with its run context and source cycle
+    pub fn append(
+        &self,
+        measurement: &ProcessedMeasurement,
+        tags: &RunTags,
+        cycle: &MeasurementCycle,
+    ) -> Result<(), SpectrometerError> {
+        let raw = |values: &[RawAdcValue]| {
+            self.store_raw
+                .then(|| serde_json::to_string(values).unwrap_or_default())
+        };
+
+        self.writer.lock().unwrap().execute(
+            "INSERT INTO measurements (run_id, timestamp_us, material, layer, dark_mean, \
+             full_mean, sample_mean, calibrated_reading, is_valid, validation_error, \
+             dark_raw, full_raw, sample_raw, validation_category, statistics, cycle_id, \
+             low_snr, physical, transitional, wavelength) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, \
+             ?18, ?19, ?20)",
+            params![
+                tags.run_id,
+                measurement.timestamp.timestamp_micros(),
+                tags.material,
+                tags.layer,
+                measurement.dark_mean,
+                measurement.full_mean,
+                measurement.sample_mean,
+                measurement.calibrated_reading,
+                measurement.is_valid,
+                measurement.validation_error,
+                raw(&cycle.dark().values),
+                raw(&cycle.full().values),
+                raw(&cycle.sample().values),
+                measurement.validation_category.map(|c| c.as_str()),
+                measurement
+                    .statistics
+                    .map(|s| serde_json::to_string(&s).unwrap_or_default()),
+                measurement.cycle_id as i64,
+                measurement.low_snr,
+                measurement
+                    .physical
+                    .map(|p| serde_json::to_string(&p).unwrap_or_default()),
+                measurement.transitional,
+                measurement.wavelength,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Measurements matching `filter`, in chronological order
+    pub fn query(
+        &self,
+        filter: &MeasurementFilter,
+    ) -> Result<Vec<ArchivedMeasurement>, SpectrometerError> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(from) = filter.from {
+            conditions.push("timestamp_us >= ?");
+            values.push(Value::Integer(from.timestamp_micros()));
+        }
+        if let Some(to) = filter.to {
+            conditions.push("timestamp_us <= ?");
+            values.push(Value::Integer(to.timestamp_micros()));
+        }
+        if let Some(run_id) = &filter.run_id {
+            conditions.push("run_id = ?");
+            values.push(Value::Text(run_id.clone()));
+        }
+
+        let limit = filter
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .min(MAX_QUERY_LIMIT);
+        values.push(Value::Integer(limit as i64));
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let sql = format!(
+            "SELECT {SELECT_COLUMNS} FROM measurements {where_clause} \
+             ORDER BY timestamp_us, id LIMIT ?"
+        );
+
+        let conn = self.reader.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), read_measurement)?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Up to `limit` measurements with `from <= timestamp <= to` following
+    /// `after` (from the start of the range when None), in chronological
+    /// order, with the cursor to continue after the last of them
+    pub fn page(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        after: Option<PageCursor>,
+        limit: usize,
+    ) -> Result<(Vec<ArchivedMeasurement>, Option<PageCursor>), SpectrometerError> {
+        let after = after.unwrap_or(PageCursor {
+            timestamp_us: from.timestamp_micros(),
+            id: i64::MIN,
+        });
+        let sql = format!(
+            "SELECT {SELECT_COLUMNS}, id FROM measurements \
+             WHERE timestamp_us <= ?1 AND timestamp_us >= ?2 \
+             AND (timestamp_us > ?3 OR (timestamp_us = ?3 AND id > ?4)) \
+             ORDER BY timestamp_us, id LIMIT ?5"
+        );
+
+        let conn = self.reader.lock().unwrap();
+        let mut stmt = conn.prepare(&sql)?;
+        let mut cursor = None;
+        let rows = stmt.query_map(
+            params![
+                to.timestamp_micros(),
+                from.timestamp_micros(),
+                after.timestamp_us,
+                after.id,
+                limit as i64
+            ],
+            |row| {
+                let position = PageCursor {
+                    timestamp_us: row.get(1)?,
+                    id: row.get(SELECT_COLUMN_COUNT)?,
+                };
+                Ok((read_measurement(row)?, position))
+            },
+        )?;
+        let mut measurements = Vec::new();
+        for row in rows {
+            let (measurement, position) = row?;
+            measurements.push(measurement);
+            cursor = Some(position);
+        }
+
+        Ok((measurements, cursor))
+    }
+
+    /// Summaries of all archived runs, oldest first
+    pub fn runs(&self) -> Result<Vec<RunSummary>, SpectrometerError> {
+        let conn = self.reader.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT run_id, MIN(timestamp_us), MAX(timestamp_us), COUNT(*), \
+             SUM(is_valid = 0), MAX(layer) \
+             FROM measurements WHERE run_id IS NOT NULL \
+             GROUP BY run_id ORDER BY MIN(timestamp_us)",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(RunSummary {
+                run_id: row.get(0)?,
+                first_measurement: from_micros(row.get(1)?),
+                last_measurement: from_micros(row.get(2)?),
+                measurements: row.get(3)?,
+                invalid_measurements: row.get(4)?,
+                layers: row.get(5)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Layers of an archived run, in deposition order
+    pub fn layers(&self, run_id: &str) -> Result<Vec<ArchivedLayer>, SpectrometerError> {
+        let conn = self.reader.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT layer, material, MIN(timestamp_us), MAX(timestamp_us), COUNT(*), \
+             SUM(is_valid = 0) \
+             FROM measurements WHERE run_id = ?1 \
+             GROUP BY layer, material ORDER BY layer, MIN(timestamp_us)",
+        )?;
+        let rows = stmt.query_map(params![run_id], |row| {
+            Ok(ArchivedLayer {
+                index: row.get(0)?,
+                material: row.get(1)?,
+                first_measurement: from_micros(row.get(2)?),
+                last_measurement: from_micros(row.get(3)?),
+                measurements: row.get(4)?,
+                invalid_measurements: row.get(5)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Timestamp of the oldest archived measurement
+    #[allow(dead_code)]
+    pub fn oldest(&self) -> Result<Option<DateTime<Utc>>, SpectrometerError> {
+        let conn = self.reader.lock().unwrap();
+        let oldest: Option<i64> =
+            conn.query_row("SELECT MIN(timestamp_us) FROM measurements", [], |row| {
+                row.get(0)
+            })?;
+
+        Ok(oldest.map(from_micros))
+    }
+
+    /// Delete measurements taken before `cutoff`, `PRUNE_BATCH` rows at a
+    /// time. Returns the number removed.
+    pub fn prune_before(&self, cutoff: DateTime<Utc>) -> Result<usize, SpectrometerError> {
+        let mut removed = 0;
+        loop {
+            let batch = self.writer.lock().unwrap().execute(
+                "DELETE FROM measurements WHERE id IN \
+                 (SELECT id FROM measurements WHERE timestamp_us < ?1 LIMIT ?2)",
+                params![cutoff.timestamp_micros(), PRUNE_BATCH as i64],
+            )?;
+            removed += batch;
+            if batch < PRUNE_BATCH {
+                return Ok(removed);
+            }
+        }
+    }
+}
+
+/// Bring an archive created by an older version up to date
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(migration)?;
+        conn.pragma_update(None, "user_version", index + 1)?;
+    }
+
+    Ok(())
+}
+
+fn from_micros(micros: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_micros(micros).unwrap_or_default()
+}
+
+fn read_measurement(row: &Row) -> rusqlite::Result<ArchivedMeasurement> {
+    let raw_series = |index: usize| -> rusqlite::Result<Option<Vec<RawAdcValue>>> {
+        let text: Option<String> = row.get(index)?;
+        Ok(text.and_then(|text| serde_json::from_str(&text).ok()))
+    };
+
+    let raw = match (raw_series(10)?, raw_series(11)?, raw_series(12)?) {
+        (Some(dark), Some(full), Some(sample)) => Some(RawSeries { dark, full, sample }),
+        _ => None,
+    };
+
+    Ok(ArchivedMeasurement {
+        run_id: row.get(0)?,
+        material: row.get(2)?,
+        layer: row.get(3)?,
+        measurement: ProcessedMeasurement {
+            cycle_id: row.get::<_, Option<i64>>(15)?.unwrap_or(0) as u64,
+            timestamp: from_micros(row.get(1)?),
+            wavelength: row.get(19)?,
+            dark_mean: row.get(4)?,
+            full_mean: row.get(5)?,
+            sample_mean: row.get(6)?,
+            reference_mean: None,
+            physical: row
+                .get::<_, Option<String>>(17)?
+                .and_then(|text| serde_json::from_str(&text).ok()),
+            calibrated_reading: row.get(7)?,
+            is_valid: row.get(8)?,
+            validation_error: row.get(9)?,
+            validation_category: row
+                .get::<_, Option<String>>(13)?
+                .as_deref()
+                .and_then(ValidationCategory::parse),
+            statistics: row
+                .get::<_, Option<String>>(14)?
+                .and_then(|text| serde_json::from_str(&text).ok()),
+            low_snr: row.get::<_, Option<bool>>(16)?.unwrap_or(false),
+            transitional: row.get::<_, Option<bool>>(18)?.unwrap_or(false),
+            clipped: false,
+            outliers: None,
+            smoothed: None,
+            material_info: None,
+            thickness: None,
+            series: None,
+        },
+        raw,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::protocol::{MeasurementStatistics, PhysicalLevels, SeriesData, SeriesStatistics};
+
+    fn open(store_raw: bool) -> (MeasurementArchive, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = MeasurementArchive::open(&dir.path().join("archive.db"), store_raw).unwrap();
+        (archive, dir)
+    }
+
+    fn cycle_at(timestamp: DateTime<Utc>) -> MeasurementCycle {
+        MeasurementCycle::with_timestamp(
+            timestamp,
+            SeriesData::new(vec![100, 101]),
+            SeriesData::new(vec![1000, 1001]),
+            SeriesData::new(vec![550, 551]),
+        )
+    }
+
+    fn append_at(archive: &MeasurementArchive, timestamp: DateTime<Utc>, run_id: Option<&str>) {
+        let tags = RunTags {
+            run_id: run_id.map(str::to_string),
+            material: "H".to_string(),
+            layer: 1,
+        };
+        append_tagged(archive, timestamp, &tags);
+    }
+
+    fn append_tagged(archive: &MeasurementArchive, timestamp: DateTime<Utc>, tags: &RunTags) {
+        let measurement = ProcessedMeasurement::new(timestamp, 100.0, 1000.0, 550.0, 50.0);
+        archive
+            .append(&measurement, tags, &cycle_at(timestamp))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_query_time_range_inclusive() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        for i in 0..5 {
+            append_at(&archive, t0 + Duration::seconds(i), None);
+        }
+
+        let selected = archive
+            .query(&MeasurementFilter {
+                from: Some(t0 + Duration::seconds(1)),
+                to: Some(t0 + Duration::seconds(3)),
+                ..MeasurementFilter::default()
+            })
+            .unwrap();
+
+        assert_eq!(selected.len(), 3);
+        assert_eq!(
+            selected[0].measurement.timestamp.timestamp_micros(),
+            (t0 + Duration::seconds(1)).timestamp_micros()
+        );
+        assert!(selected[0].raw.is_none());
+    }
+
+    #[test]
+    fn test_query_by_run_and_limit() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        append_at(&archive, t0, Some("run-a"));
+        append_at(&archive, t0 + Duration::seconds(1), Some("run-b"));
+        append_at(&archive, t0 + Duration::seconds(2), Some("run-b"));
+
+        let run_b = archive
+            .query(&MeasurementFilter {
+                run_id: Some("run-b".to_string()),
+                ..MeasurementFilter::default()
+            })
+            .unwrap();
+        assert_eq!(run_b.len(), 2);
+        assert!(run_b.iter().all(|m| m.run_id.as_deref() == Some("run-b")));
+
+        let limited = archive
+            .query(&MeasurementFilter {
+                limit: Some(1),
+                ..MeasurementFilter::default()
+            })
+            .unwrap();
+        assert_eq!(limited.len(), 1);
+        assert_eq!(limited[0].run_id.as_deref(), Some("run-a"));
+    }
+
+    #[test]
+    fn test_raw_series_round_trip() {
+        let (archive, _dir) = open(true);
+        append_at(&archive, Utc::now(), None);
+
+        let stored = archive.query(&MeasurementFilter::default()).unwrap();
+        assert_eq!(
+            stored[0].raw,
+            Some(RawSeries {
+                dark: vec![100, 101],
+                full: vec![1000, 1001],
+                sample: vec![550, 551],
+            })
+        );
+    }
+
+    #[test]
+    fn test_run_summaries() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        append_at(&archive, t0, None);
+        append_at(&archive, t0 + Duration::seconds(1), Some("run-a"));
+        append_at(&archive, t0 + Duration::seconds(2), Some("run-a"));
+
+        let runs = archive.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].run_id, "run-a");
+        assert_eq!(runs[0].measurements, 2);
+        assert_eq!(runs[0].invalid_measurements, 0);
+    }
+
+    #[test]
+    fn test_layers_of_run() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        for (i, (layer, material)) in [(1, "H"), (1, "H"), (2, "L")].into_iter().enumerate() {
+            let tags = RunTags {
+                run_id: Some("run-a".to_string()),
+                material: material.to_string(),
+                layer,
+            };
+            append_tagged(&archive, t0 + Duration::seconds(i as i64), &tags);
+        }
+
+        let layers = archive.layers("run-a").unwrap();
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].measurements, 2);
+        assert_eq!(layers[1].index, 2);
+        assert_eq!(layers[1].material, "L");
+        assert!(archive.layers("run-b").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_prune_before() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        append_at(&archive, t0 - Duration::days(10), None);
+        append_at(&archive, t0, None);
+
+        assert_eq!(archive.prune_before(t0 - Duration::days(1)).unwrap(), 1);
+        let oldest = archive.oldest().unwrap().unwrap();
+        assert_eq!(oldest.timestamp_micros(), t0.timestamp_micros());
+    }
+
+    #[test]
+    fn test_page_continues_after_cursor() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        // Two measurements share a timestamp across the page boundary
+        for seconds in [0, 1, 1, 2, 3] {
+            append_at(&archive, t0 + Duration::seconds(seconds), None);
+        }
+
+        let mut cursor = None;
+        let mut pages = Vec::new();
+        loop {
+            let (page, next) = archive
+                .page(t0, t0 + Duration::seconds(2), cursor, 2)
+                .unwrap();
+            pages.push(page.len());
+            if page.len() < 2 {
+                break;
+            }
+            cursor = next;
+        }
+        assert_eq!(pages, [2, 2, 0]);
+    }
+
+    #[test]
+    fn test_select_column_count_matches_query() {
+        let (archive, _dir) = open(false);
+        let reader = archive.reader.lock().unwrap();
+        let stmt = reader
+            .prepare(&format!("SELECT {SELECT_COLUMNS} FROM measurements"))
+            .unwrap();
+
+        assert_eq!(stmt.column_count(), SELECT_COLUMN_COUNT);
+    }
+
+    #[test]
+    fn test_append_during_open_read() {
+        let (archive, _dir) = open(false);
+        append_at(&archive, Utc::now(), None);
+        let count = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM measurements", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        let reader = archive.reader.lock().unwrap();
+        reader.execute_batch("BEGIN").unwrap();
+        assert_eq!(count(&reader), 1);
+        // The read snapshot does not lock the writer out
+        append_at(&archive, Utc::now(), None);
+        assert_eq!(count(&reader), 1);
+        reader.execute_batch("COMMIT").unwrap();
+        assert_eq!(count(&reader), 2);
+    }
+
+    #[tokio::test]
+    async fn test_blocking_runs_operation() {
+        let (archive, _dir) = open(false);
+        let archive = Arc::new(archive);
+        let t0 = Utc::now();
+        append_at(&archive, t0 - Duration::days(10), None);
+
+        let removed = archive
+            .blocking(move |archive| archive.prune_before(t0))
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+    }
+
+    #[test]
+    fn test_validation_category_round_trip() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        let measurement = ProcessedMeasurement::new(t0, 100.0, 1000.0, 550.0, 50.0)
+            .with_categorized_error(ValidationCategory::UnderRange, "dark under range".into());
+        archive
+            .append(&measurement, &RunTags::default(), &cycle_at(t0))
+            .unwrap();
+
+        let stored = archive.query(&MeasurementFilter::default()).unwrap();
+        assert_eq!(
+            stored[0].measurement.validation_category,
+            Some(ValidationCategory::UnderRange)
+        );
+    }
+
+    #[test]
+    fn test_statistics_round_trip() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        let series = SeriesStatistics {
+            std_dev: 3.5,
+            count: 12,
+        };
+        let statistics = MeasurementStatistics {
+            dark: series,
+            full: series,
+            sample: series,
+            uncertainty: Some(0.25),
+            snr: Some(42.0),
+        };
+        let physical = PhysicalLevels {
+            gain: 2,
+            dark_voltage: -1.2,
+            full_voltage: 0.4,
+            sample_voltage: -0.3,
+            dark_current: None,
+            full_current: None,
+            sample_current: None,
+        };
+        let measurement = ProcessedMeasurement {
+            low_snr: true,
+            transitional: true,
+            physical: Some(physical),
+            wavelength: Some(632.8),
+            ..ProcessedMeasurement::new(t0, 100.0, 1000.0, 550.0, 50.0).with_statistics(statistics)
+        };
+        archive
+            .append(&measurement, &RunTags::default(), &cycle_at(t0))
+            .unwrap();
+
+        let stored = archive.query(&MeasurementFilter::default()).unwrap();
+        assert_eq!(stored[0].measurement.statistics, Some(statistics));
+        assert!(stored[0].measurement.low_snr);
+        assert!(stored[0].measurement.transitional);
+        assert_eq!(stored[0].measurement.physical, Some(physical));
+        assert_eq!(stored[0].measurement.wavelength, Some(632.8));
+    }
+
+    #[test]
+    fn test_cycle_id_round_trip() {
+        let (archive, _dir) = open(false);
+        let t0 = Utc::now();
+        let cycle = cycle_at(t0);
+        let measurement = ProcessedMeasurement {
+            cycle_id: cycle.id,
+            ..ProcessedMeasurement::new(t0, 100.0, 1000.0, 550.0, 50.0)
+        };
+        archive
+            .append(&measurement, &RunTags::default(), &cycle)
+            .unwrap();
+
+        let stored = archive.query(&MeasurementFilter::default()).unwrap();
+        assert_eq!(stored[0].measurement.cycle_id, cycle.id);
+    }
+
+    #[test]
+    fn test_migrates_version_zero_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(SCHEMA)
+            .unwrap();
+
+        let archive = MeasurementArchive::open(&path, false).unwrap();
+        append_at(&archive, Utc::now(), None);
+
+        let conn = archive.writer.lock().unwrap();
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_reopen_keeps_measurements() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.db");
+        {
+            let archive = MeasurementArchive::open(&path, false).unwrap();
+            append_at(&archive, Utc::now(), None);
+        }
+
+        let archive = MeasurementArchive::open(&path, false).unwrap();
+        assert_eq!(
+            archive.query(&MeasurementFilter::default()).unwrap().len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::ProcessedMeasurement;
+
+/// How a series is reduced to a number of points for charting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownsampleMethod {
+    /// Largest-Triangle-Three-Buckets: keeps the points that preserve the
+    /// visual shape of the curve (default)
+    #[default]
+    Lttb,
+    /// Lowest and highest reading of each of `points / 2` equal buckets,
+    /// so no spike is lost
+    MinMax,
+}
+
+/// At most `points` of `measurements` (chronological), chosen by `method`
+/// from their calibrated readings. Series that already fit are returned
+/// unchanged.
+pub fn downsample(
+    measurements: Vec<ProcessedMeasurement>,
+    points: usize,
+    method: DownsampleMethod,
+) -> Vec<ProcessedMeasurement> {
+    if measurements.len() <= points {
+        return measurements;
+    }
+    let keep = match method {
+        DownsampleMethod::Lttb => lttb(&measurements, points),
+        DownsampleMethod::MinMax => min_max(&measurements, points),
+    };
+
+    let mut keep = keep.into_iter().peekable();
+    measurements
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| keep.next_if_eq(index).is_some())
+        .map(|(_, measurement)| measurement)
+        .collect()
+}
+
+/// Chart coordinates: milliseconds since the first measurement, reading
+fn point(measurements: &[ProcessedMeasurement], index: usize) -> (f64, f64) {
+    let elapsed = measurements[index].timestamp - measurements[0].timestamp;
+    (
+        elapsed.num_milliseconds() as f64,
+        measurements[index].calibrated_reading,
+    )
+}
+
+/// Indices LTTB keeps out of more than `points` measurements: the first and
+/// last, and from each of `points - 2` buckets in between the one forming
+/// the largest triangle with the previous pick and the next bucket's mean
+fn lttb(measurements: &[ProcessedMeasurement], points: usize) -> Vec<usize> {
+    let n = measurements.len();
+    if points < 3 {
+        return [0, n - 1].into_iter().take(points).collect();
+    }
+    let bucket_size = (n - 2) as f64 / (points - 2) as f64;
+    let bucket_start = |bucket: usize| ((bucket as f64 * bucket_size) as usize + 1).min(n - 1);
+
+    let mut keep = Vec::with_capacity(points);
+    keep.push(0);
+    let mut previous = 0;
+    for bucket in 0..points - 2 {
+        let (start, end) = (bucket_start(bucket), bucket_start(bucket + 1));
+        let next_end = if bucket == points - 3 {
+            n
+        } else {
+            bucket_start(bucket + 2)
+        };
+        let next = end..next_end.max(end + 1);
+        let (next_x, next_y) = next
+            .clone()
+            .map(|index| point(measurements, index))
+            .fold((0.0, 0.0), |(x, y), (px, py)| (x + px, y + py));
+        let (next_x, next_y) = (next_x / next.len() as f64, next_y / next.len() as f64);
+
+        let (ax, ay) = point(measurements, previous);
+        let area = |index: usize| {
+            let (bx, by) = point(measurements, index);
+            ((ax - next_x) * (by - ay) - (ax - bx) * (next_y - ay)).abs()
+        };
+        previous = (start..end.max(start + 1))
+            .max_by(|&a, &b| area(a).total_cmp(&area(b)))
+            .unwrap_or(start);
+        keep.push(previous);
+    }
+    keep.push(n - 1);
+    keep
+}
+
+/// Indices of the lowest and highest reading of each of `points / 2`
+/// buckets of equal size, in order
+fn min_max(measurements: &[ProcessedMeasurement], points: usize) -> Vec<usize> {
+    let n = measurements.len();
+    let buckets = (points / 2).max(1);
+    let reading = |index: usize| measurements[index].calibrated_reading;
+
+    let mut keep = Vec::with_capacity(points);
+    for bucket in 0..buckets {
+        let range = bucket * n / buckets..(bucket + 1) * n / buckets;
+        let low = range
+            .clone()
+            .min_by(|&a, &b| reading(a).total_cmp(&reading(b)));
+        let high = range.max_by(|&a, &b| reading(a).total_cmp(&reading(b)));
+        if let (Some(low), Some(high)) = (low, high) {
+            keep.push(low.min(high));
+            if low != high {
+                keep.push(low.max(high));
+            }
+        }
+    }
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+
+    fn series(readings: &[f64]) -> Vec<ProcessedMeasurement> {
+        let t0 = Utc::now();
+        readings
+            .iter()
+            .enumerate()
+            .map(|(i, &reading)| {
+                ProcessedMeasurement::new(
+                    t0 + Duration::seconds(i as i64),
+                    100.0,
+                    1000.0,
+                    550.0,
+                    reading,
+                )
+            })
+            .collect()
+    }
+
+    fn readings(measurements: &[ProcessedMeasurement]) -> Vec<f64> {
+        measurements.iter().map(|m| m.calibrated_reading).collect()
+    }
+
+    #[test]
+    fn test_short_series_unchanged() {
+        let measurements = series(&[1.0, 2.0, 3.0]);
+        for method in [DownsampleMethod::Lttb, DownsampleMethod::MinMax] {
+            assert_eq!(
+                readings(&downsample(measurements.clone(), 3, method)),
+                [1.0, 2.0, 3.0]
+            );
+        }
+    }
+
+    #[test]
+    fn test_lttb_keeps_ends_and_spike() {
+        let mut values = vec![50.0; 1000];
+        values[0] = 10.0;
+        values[999] = 90.0;
+        values[421] = 99.0;
+        let measurements = series(&values);
+
+        let reduced = downsample(measurements, 20, DownsampleMethod::Lttb);
+        assert_eq!(reduced.len(), 20);
+        assert_eq!(reduced[0].calibrated_reading, 10.0);
+        assert_eq!(reduced[19].calibrated_reading, 90.0);
+        assert!(readings(&reduced).contains(&99.0));
+        assert!(reduced.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+    }
+
+    #[test]
+    fn test_min_max_keeps_extremes() {
+        let values: Vec<f64> = (0..100).map(|i| (i % 10) as f64).collect();
+        let measurements = series(&values);
+
+        let reduced = downsample(measurements, 10, DownsampleMethod::MinMax);
+        assert_eq!(reduced.len(), 10);
+        // Each bucket of 20 contributes its 0 and its 9
+        assert_eq!(
+            readings(&reduced),
+            [0.0, 9.0, 0.0, 9.0, 0.0, 9.0, 0.0, 9.0, 0.0, 9.0]
+        );
+    }
+}
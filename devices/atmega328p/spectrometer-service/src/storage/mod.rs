@@ -0,0 +1,8 @@
+pub mod archive;
+pub mod downsample;
+
+pub use archive::{
+    ArchivedLayer, ArchivedMeasurement, MeasurementArchive, MeasurementFilter, PageCursor,
+    RunSummary, RunTags, SharedArchive,
+};
+pub use downsample::{DownsampleMethod, downsample};